@@ -0,0 +1,879 @@
+//! Management of the cache of downloaded archives. The archives downloaded by espup are
+//! stored by their SHA-256 hash, when enabled with `--cache-archives` or by `espup cache
+//! fetch`, so that an archive referenced by several versions or components is stored once,
+//! and an index records the URLs they were downloaded from and the directories they were
+//! extracted to.
+
+use crate::{
+    context::Context,
+    emoji,
+    error::Error,
+    host_triple::HostTriple,
+    list::format_size,
+    lock::InstallLock,
+    plan::Plan,
+    progress,
+    store::HashingReader,
+    toolchain::{
+        espidf::{get_dist_path, get_tool_path},
+        get_dir_size, get_github_json,
+        rust::get_xtensa_rust_path,
+    },
+};
+use log::{debug, info, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::{
+        create_dir_all, read_dir, read_to_string, remove_dir, remove_dir_all, remove_file, write,
+        File,
+    },
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+use strum::IntoEnumIterator;
+use tempfile::NamedTempFile;
+
+/// Name of the index of the archives, in their directory.
+const ARCHIVE_INDEX_FILE_NAME: &str = "index.json";
+/// Extension of the digests recorded next to the files downloaded as is.
+const DIGEST_EXTENSION: &str = "digest";
+
+/// Prefix of the URLs of the GitHub release assets.
+const GITHUB_RELEASE_PREFIX: &str = "https://github.com/";
+
+/// Serializes the updates of the index, as archives can be downloaded concurrently.
+static ARCHIVE_INDEX_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+/// SHA-256 hashes GitHub publishes for the assets of the releases queried, by release API URL
+/// and asset name.
+static PUBLISHED_HASHES: Lazy<Mutex<HashMap<String, BTreeMap<String, String>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// An archive of the cache, stored under its hash.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CachedArchive {
+    /// File name of the archive, e.g. `xtensa-esp32-elf.tar.xz`.
+    pub file_name: String,
+    /// URLs the archive was downloaded from.
+    pub urls: BTreeSet<String>,
+    /// Directories the archive was extracted to.
+    pub directories: BTreeSet<PathBuf>,
+}
+
+impl CachedArchive {
+    /// Whether one of the directories extracted from the archive is still installed.
+    pub fn is_installed(&self) -> bool {
+        self.directories.iter().any(|directory| directory.exists())
+    }
+}
+
+/// Archives of the cache, by their hash.
+type ArchiveIndex = BTreeMap<String, CachedArchive>;
+
+/// An entry of the cache: a downloaded archive or the extracted contents of one.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    /// Path of the entry.
+    pub path: PathBuf,
+    /// Disk usage of the entry, in bytes.
+    pub size: u64,
+    /// Last modification time of the entry.
+    pub modified: SystemTime,
+    /// Archive stored in the entry, when downloaded by espup.
+    pub archive: Option<CachedArchive>,
+}
+
+/// Entry of the cache, as printed by `cache list --message-format json`.
+#[derive(Debug, Serialize)]
+pub struct CacheEntryLine<'a> {
+    /// Path of the entry.
+    pub path: &'a Path,
+    /// Disk usage of the entry, in bytes.
+    pub size: u64,
+    /// Age of the entry, in days.
+    pub age_days: u64,
+    /// Archive stored in the entry, when downloaded by espup.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive: Option<&'a CachedArchive>,
+}
+
+impl CacheEntry {
+    /// Age of the entry.
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.modified)
+            .unwrap_or_default()
+    }
+
+    /// Age of the entry, in days.
+    pub fn age_days(&self) -> u64 {
+        self.age().as_secs() / (24 * 60 * 60)
+    }
+
+    /// Gets the JSON line of the entry.
+    pub fn to_line(&self) -> CacheEntryLine<'_> {
+        CacheEntryLine {
+            path: &self.path,
+            size: self.size,
+            age_days: self.age_days(),
+            archive: self.archive.as_ref(),
+        }
+    }
+
+    /// Removes the entry.
+    fn remove(&self) -> Result<(), Error> {
+        if self.path.is_dir() {
+            remove_dir_all(&self.path)
+                .map_err(|_| Error::FailedToRemoveDirectory(self.path.display().to_string()))
+        } else {
+            remove_digested(&self.path)
+                .map_err(|_| Error::FailedToRemoveFile(self.path.display().to_string()))
+        }
+    }
+}
+
+/// Gets the cache directories: the dist folder of espup and the one of ESP-IDF's tools,
+/// which differ when the XDG base directories are used.
+pub fn get_cache_dirs(context: &Context) -> Vec<PathBuf> {
+    let mut cache_dirs = vec![PathBuf::from(get_dist_path(context, ""))];
+    let esp_idf_dist_path = context.tools_path.join("dist");
+    if !cache_dirs.contains(&esp_idf_dist_path) {
+        cache_dirs.push(esp_idf_dist_path);
+    }
+    cache_dirs
+}
+
+/// Creates the entry of a path, if its metadata can be read.
+fn get_entry(path: PathBuf) -> Option<CacheEntry> {
+    let modified = path.symlink_metadata().ok()?.modified().ok()?;
+    Some(CacheEntry {
+        size: get_dir_size(&path),
+        modified,
+        path,
+        archive: None,
+    })
+}
+
+/// Gets the entries of a cache directory. The archives downloaded by ESP-IDF are stored
+/// directly in it, while espup stores them in a subdirectory per tool.
+fn get_dir_entries(cache_dir: &Path) -> Vec<CacheEntry> {
+    let mut entries = Vec::new();
+    for path in read_dir(cache_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+    {
+        if path.is_dir() {
+            let index = read_archive_index(&path);
+            entries.extend(
+                read_dir(&path)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| !path.ends_with(ARCHIVE_INDEX_FILE_NAME) && !is_digest(path))
+                    .filter_map(|path| {
+                        let archive = path
+                            .file_name()
+                            .and_then(|hash| index.get(hash.to_str()?))
+                            .cloned();
+                        get_entry(path).map(|entry| CacheEntry { archive, ..entry })
+                    }),
+            );
+        } else if !is_digest(&path) {
+            entries.extend(get_entry(path));
+        }
+    }
+    entries
+}
+
+/// Gets the entries of every cache directory, sorted by path.
+pub fn get_cache_entries(context: &Context) -> Vec<CacheEntry> {
+    let mut entries: Vec<CacheEntry> = get_cache_dirs(context)
+        .iter()
+        .flat_map(|cache_dir| get_dir_entries(cache_dir))
+        .collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries
+}
+
+/// Gets the cache entries older than `older_than` or, if not set, every entry.
+pub fn get_stale_entries(context: &Context, older_than: Option<Duration>) -> Vec<CacheEntry> {
+    get_cache_entries(context)
+        .into_iter()
+        .filter(|entry| older_than.map_or(true, |older_than| entry.age() > older_than))
+        .collect()
+}
+
+/// Gets the versions of the tools installed in the tools directory. The directories of
+/// LLVM are suffixed with their host triple, e.g. `esp-15.0.0-20221201-x86_64-apple-darwin`,
+/// which is not part of the names of its archives.
+fn get_installed_versions(context: &Context) -> Vec<String> {
+    let get_subdirectories = |path: &Path| -> Vec<PathBuf> {
+        read_dir(path)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_dir())
+            .collect()
+    };
+    get_subdirectories(Path::new(&get_tool_path(context, "")))
+        .iter()
+        .flat_map(|tool_path| get_subdirectories(tool_path))
+        .filter_map(|version_path| {
+            version_path
+                .file_name()
+                .map(|version| strip_host_triple(&version.to_string_lossy()).to_string())
+        })
+        .collect()
+}
+
+/// Strips the host triple suffixing a directory name, if any.
+fn strip_host_triple(directory_name: &str) -> &str {
+    HostTriple::iter()
+        .find_map(|host_triple| directory_name.strip_suffix(&format!("-{host_triple}")))
+        .unwrap_or(directory_name)
+}
+
+/// Gets the names of the entries of the dist folder kept to reinstall the Xtensa Rust
+/// toolchain, when one is installed: its archives and the installers extracted from them.
+fn get_installed_rust_dist_names(context: &Context) -> Vec<String> {
+    let installed = read_dir(get_xtensa_rust_path(context))
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .any(|entry| entry.path().is_dir());
+    if !installed {
+        return Vec::new();
+    }
+    let mut names: Vec<String> = [
+        "rust.tar.xz",
+        "rust.zip",
+        "rust-src.tar.xz",
+        "rust-src-nightly",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect();
+    names.extend(HostTriple::iter().map(|host_triple| format!("rust-nightly-{host_triple}")));
+    names
+}
+
+/// Whether an archive name refers to a version: every dash separated part of the version
+/// is in the name, either with dots or underscores.
+fn matches_version(file_name: &str, version: &str) -> bool {
+    version
+        .trim_start_matches('v')
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .all(|part| file_name.contains(part) || file_name.contains(&part.replace('.', "_")))
+}
+
+/// Gets the cache entries that do not belong to any installed tool version, which are not
+/// needed to reinstall the current versions.
+pub fn get_obsolete_entries(context: &Context) -> Vec<CacheEntry> {
+    let versions = get_installed_versions(context);
+    let rust_dist_names = get_installed_rust_dist_names(context);
+    get_cache_entries(context)
+        .into_iter()
+        .filter(|entry| {
+            if let Some(archive) = &entry.archive {
+                return !archive.is_installed();
+            }
+            let file_name = entry
+                .path
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            is_obsolete(&file_name, &versions, &rust_dist_names)
+        })
+        .collect()
+}
+
+/// Whether a cache entry belongs to none of the installed tool versions nor to the
+/// installed Xtensa Rust toolchain.
+fn is_obsolete(file_name: &str, versions: &[String], rust_dist_names: &[String]) -> bool {
+    !rust_dist_names.iter().any(|name| name == file_name)
+        && !versions
+            .iter()
+            .any(|version| matches_version(file_name, version))
+}
+
+/// Removes cache entries, along with the tool subdirectories left empty.
+pub fn remove_entries(context: &Context, entries: &[CacheEntry]) -> Result<(), Error> {
+    let cache_dirs = get_cache_dirs(context);
+    for entry in entries {
+        info!("{} Removing '{}'", emoji::WRENCH, entry.path.display());
+        entry.remove()?;
+        if let Some(parent) = entry.path.parent() {
+            if !cache_dirs.iter().any(|cache_dir| cache_dir == parent) {
+                // Fails, as expected, while the subdirectory is not empty.
+                let _ = remove_dir(parent);
+            }
+        }
+    }
+    let archives_dir = get_archives_dir(context);
+    let _lock = ARCHIVE_INDEX_LOCK.lock().unwrap();
+    let mut index = read_archive_index(&archives_dir);
+    let count = index.len();
+    index.retain(|hash, _| archives_dir.join(hash).exists());
+    if index.len() != count {
+        write_archive_index(&archives_dir, &index);
+    }
+    Ok(())
+}
+
+/// Removes the cached archives older than `older_than`, or every one when `None`. With
+/// `dry_run`, only prints them.
+pub fn clean(
+    context: &mut Context,
+    older_than: Option<Duration>,
+    dry_run: bool,
+) -> Result<(), Error> {
+    context.load_config().ok();
+    let context = &*context;
+    let entries = get_stale_entries(context, older_than);
+    let size: u64 = entries.iter().map(|entry| entry.size).sum();
+    if dry_run {
+        let mut plan = Plan::default();
+        for entry in &entries {
+            plan.remove(&entry.path);
+        }
+        plan.print(context);
+        info!("{} {} would be freed", emoji::INFO, format_size(size));
+        return Ok(());
+    }
+
+    let _lock = InstallLock::acquire(context, false)?;
+    remove_entries(context, &entries)?;
+    info!(
+        "{} Removed {} cached archives, freeing {}",
+        emoji::CHECK,
+        entries.len(),
+        format_size(size)
+    );
+    Ok(())
+}
+
+/// Prints the cached archives, and the directories they were extracted to, or a JSON line
+/// per archive with `json`.
+pub fn print_entries(context: &mut Context, json: bool) {
+    context.load_config().ok();
+    let entries = get_cache_entries(context);
+    if entries.is_empty() {
+        info!("{} The cache is empty", emoji::INFO);
+        return;
+    }
+    for entry in entries {
+        if json {
+            progress::print(&entry.to_line());
+            continue;
+        }
+        println!(
+            "{:>10}  {:>5} days  {}",
+            format_size(entry.size),
+            entry.age_days(),
+            entry.path.display()
+        );
+        // Archives downloaded by espup are stored by their hash.
+        if let Some(archive) = entry.archive {
+            println!("{:>24}{}", "", archive.file_name);
+            for directory in &archive.directories {
+                println!(
+                    "{:>24}  extracted to {}{}",
+                    "",
+                    directory.display(),
+                    if directory.exists() { "" } else { " (removed)" }
+                );
+            }
+        }
+    }
+}
+
+/// Prints the total size of the cache.
+pub fn print_size(context: &mut Context) {
+    context.load_config().ok();
+    let entries = get_cache_entries(context);
+    let size: u64 = entries.iter().map(|entry| entry.size).sum();
+    println!("{} in {} cached archives", format_size(size), entries.len());
+    for cache_dir in get_cache_dirs(context) {
+        debug!(
+            "{} Cache directory: '{}'",
+            emoji::DEBUG,
+            cache_dir.display()
+        );
+    }
+}
+
+/// Whether the archives extracted to `directory` are cached: the ones of the tools and of
+/// the dist folder of espup, not the ones extracted to other directories, e.g. by tests.
+pub fn is_cached(context: &Context, directory: &Path) -> bool {
+    directory.starts_with(&context.tools_path) || directory.starts_with(get_dist_path(context, ""))
+}
+
+/// Gets the directory of the archives downloaded by espup.
+pub fn get_archives_dir(context: &Context) -> PathBuf {
+    PathBuf::from(get_dist_path(context, "archives"))
+}
+
+/// Reads the index of the archives, empty if it does not exist.
+fn read_archive_index(archives_dir: &Path) -> ArchiveIndex {
+    read_to_string(archives_dir.join(ARCHIVE_INDEX_FILE_NAME))
+        .ok()
+        .and_then(|index| serde_json::from_str(&index).ok())
+        .unwrap_or_default()
+}
+
+/// Writes the index of the archives.
+fn write_archive_index(archives_dir: &Path, index: &ArchiveIndex) {
+    let index_path = archives_dir.join(ARCHIVE_INDEX_FILE_NAME);
+    let written = serde_json::to_string_pretty(index)
+        .ok()
+        .map_or(false, |contents| write(&index_path, contents).is_ok());
+    if !written {
+        debug!(
+            "{} Failed to update the archive index '{}'",
+            emoji::DEBUG,
+            index_path.display()
+        );
+    }
+}
+
+/// Gets the URL of the GitHub API release of a release asset URL, along with the name of the
+/// asset, e.g. `https://api.github.com/repos/esp-rs/rust-build/releases/tags/v1.64.0.0` and
+/// `rust-src-1.64.0.0.tar.xz`.
+fn get_release_api_url(url: &str) -> Option<(String, String)> {
+    let path = url.strip_prefix(GITHUB_RELEASE_PREFIX)?;
+    match path.split('/').collect::<Vec<&str>>().as_slice() {
+        [owner, repository, "releases", "download", tag, asset] => Some((
+            format!("https://api.github.com/repos/{owner}/{repository}/releases/tags/{tag}"),
+            asset.to_string(),
+        )),
+        _ => None,
+    }
+}
+
+/// Gets the SHA-256 hash GitHub publishes for a release asset, if `url` is one. The assets of
+/// a release are queried once.
+fn get_published_hash(context: &Context, url: &str) -> Option<String> {
+    let (api_url, asset_name) = get_release_api_url(url)?;
+    let mut published_hashes = PUBLISHED_HASHES.lock().unwrap();
+    if !published_hashes.contains_key(&api_url) {
+        let hashes = get_github_json(context, &api_url)
+            .map(|release| {
+                release["assets"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|asset| {
+                        let digest = asset["digest"].as_str()?.strip_prefix("sha256:")?;
+                        Some((asset["name"].as_str()?.to_string(), digest.to_lowercase()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        published_hashes.insert(api_url.clone(), hashes);
+    }
+    published_hashes[&api_url].get(&asset_name).cloned()
+}
+
+/// Opens the archive downloaded from `url`, if it is cached, returning it along with its
+/// hash. GitHub release assets are looked up by the hash GitHub publishes for them, so that
+/// an archive is found whatever URL or mirror it was downloaded from, the other archives by
+/// the URLs recorded in the index. The archive is hashed again, and removed when it does not
+/// match its hash anymore.
+pub fn open_archive(context: &Context, url: &str) -> Option<(String, File)> {
+    let archives_dir = get_archives_dir(context);
+    // Nothing is queried while no archive is cached.
+    let has_archives = read_dir(&archives_dir)
+        .ok()?
+        .any(|entry| entry.map_or(false, |entry| entry.file_name() != ARCHIVE_INDEX_FILE_NAME));
+    if !has_archives {
+        return None;
+    }
+    open_archive_in(
+        &archives_dir,
+        url,
+        get_published_hash(context, url).as_deref(),
+    )
+}
+
+/// Opens the archive of [`open_archive`] in `archives_dir`, by its published hash, if any,
+/// otherwise by the URL it was downloaded from.
+fn open_archive_in(
+    archives_dir: &Path,
+    url: &str,
+    published_hash: Option<&str>,
+) -> Option<(String, File)> {
+    let hash = match published_hash {
+        Some(hash) => hash.to_string(),
+        None => {
+            read_archive_index(archives_dir)
+                .into_iter()
+                .find(|(_, archive)| archive.urls.contains(url))?
+                .0
+        }
+    };
+    let path = archives_dir.join(&hash);
+    let mut file = File::open(&path).ok()?;
+    if HashingReader::new(&mut file).finish().ok()? != hash {
+        warn!(
+            "{} Removing the cached archive '{}', it does not match its hash",
+            emoji::WARN,
+            path.display()
+        );
+        let _ = remove_file(&path);
+        return None;
+    }
+    file.seek(SeekFrom::Start(0)).ok()?;
+    Some((hash, file))
+}
+
+/// Gets the archive `directory` was extracted from, if it was downloaded by espup, along with
+/// its hash.
+pub fn get_extracted_archive(
+    context: &Context,
+    directory: &Path,
+) -> Option<(String, CachedArchive)> {
+    get_extracted_archive_in(&get_archives_dir(context), directory)
+}
+
+/// Gets the archive of [`get_extracted_archive`] from the index of `archives_dir`.
+fn get_extracted_archive_in(
+    archives_dir: &Path,
+    directory: &Path,
+) -> Option<(String, CachedArchive)> {
+    read_archive_index(archives_dir)
+        .into_iter()
+        .find(|(_, archive)| archive.directories.contains(directory))
+}
+
+/// Records that the archive with `hash`, downloaded from `url`, was extracted to `directory`.
+pub fn record_archive(context: &Context, hash: &str, url: &str, file_name: &str, directory: &Path) {
+    record_archive_in(&get_archives_dir(context), hash, url, file_name, directory)
+}
+
+/// Records the archive of [`record_archive`] in the index of `archives_dir`.
+fn record_archive_in(
+    archives_dir: &Path,
+    hash: &str,
+    url: &str,
+    file_name: &str,
+    directory: &Path,
+) {
+    update_archive_index(archives_dir, hash, url, file_name, Some(directory))
+}
+
+/// Records that the archive with `hash` was downloaded from `url` without being extracted,
+/// e.g. by `espup cache fetch`.
+pub fn record_fetched_archive(context: &Context, hash: &str, url: &str, file_name: &str) {
+    update_archive_index(&get_archives_dir(context), hash, url, file_name, None)
+}
+
+/// Adds the URL of the archive with `hash`, and the directory it was extracted to if any, to
+/// the index of `archives_dir`.
+fn update_archive_index(
+    archives_dir: &Path,
+    hash: &str,
+    url: &str,
+    file_name: &str,
+    directory: Option<&Path>,
+) {
+    let _lock = ARCHIVE_INDEX_LOCK.lock().unwrap();
+    let mut index = read_archive_index(archives_dir);
+    let archive = index.entry(hash.to_string()).or_default();
+    archive.file_name = file_name.to_string();
+    archive.urls.insert(url.to_string());
+    archive.directories.extend(directory.map(Path::to_path_buf));
+    write_archive_index(archives_dir, &index);
+}
+
+/// Size and hash of a file downloaded as is, e.g. `rustup-init`, recorded next to it once
+/// complete so that a truncated or corrupted file is not reused.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FileDigest {
+    /// Size of the file, in bytes.
+    pub size: u64,
+    /// SHA-256 hash of the file.
+    pub sha256: String,
+}
+
+/// Gets the path of the digest of a downloaded file.
+fn get_digest_path(file: &Path) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(format!(".{DIGEST_EXTENSION}"));
+    PathBuf::from(path)
+}
+
+/// Whether a file is the digest of a downloaded file.
+fn is_digest(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension == DIGEST_EXTENSION)
+}
+
+/// Records the digest of a downloaded file.
+pub fn record_digest(file: &Path, digest: &FileDigest) -> io::Result<()> {
+    write(get_digest_path(file), serde_json::to_string(digest)?)
+}
+
+/// Removes a downloaded file and its digest.
+pub fn remove_digested(file: &Path) -> io::Result<()> {
+    let _ = remove_file(get_digest_path(file));
+    remove_file(file)
+}
+
+/// Whether a downloaded file still matches its recorded digest. Files without a digest,
+/// e.g. interrupted downloads, are not trusted.
+pub fn is_intact(file: &Path) -> bool {
+    let digest: FileDigest = match read_to_string(get_digest_path(file))
+        .ok()
+        .and_then(|digest| serde_json::from_str(&digest).ok())
+    {
+        Some(digest) => digest,
+        None => return false,
+    };
+    // The size is checked first, as it catches truncated files without reading them.
+    if file.metadata().map(|metadata| metadata.len()).ok() != Some(digest.size) {
+        return false;
+    }
+    File::open(file)
+        .and_then(|file| HashingReader::new(file).finish())
+        .map_or(false, |sha256| sha256 == digest.sha256)
+}
+
+/// Reader storing the archive read through it into the cache, e.g. while it is extracted.
+pub struct CachingReader<R> {
+    reader: R,
+    file: Option<NamedTempFile>,
+    hasher: Sha256,
+    archives_dir: PathBuf,
+}
+
+impl<R: Read> CachingReader<R> {
+    /// Stores what is read from `reader` in the cache of the context, when `cache` is set,
+    /// and hashes it. Failing to create the archive only skips caching it.
+    pub fn new(context: &Context, reader: R, cache: bool) -> Self {
+        Self::new_in(reader, cache, get_archives_dir(context))
+    }
+
+    /// Stores what is read from `reader` in `archives_dir`.
+    fn new_in(reader: R, cache: bool, archives_dir: PathBuf) -> Self {
+        let file = cache
+            .then(|| {
+                create_dir_all(&archives_dir)
+                    .and_then(|_| NamedTempFile::new_in(&archives_dir))
+                    .ok()
+            })
+            .flatten();
+        Self {
+            reader,
+            file,
+            hasher: Sha256::new(),
+            archives_dir,
+        }
+    }
+
+    /// Stores the archive under its hash, reading the rest of the contents first, and returns
+    /// the hash.
+    pub fn finish(mut self) -> io::Result<String> {
+        io::copy(&mut self, &mut io::sink())?;
+        let hash = format!("{:x}", self.hasher.finalize());
+        if let Some(file) = self.file {
+            if file.persist(self.archives_dir.join(&hash)).is_err() {
+                debug!("{} Failed to cache the archive {}", emoji::DEBUG, hash);
+            }
+        }
+        Ok(hash)
+    }
+}
+
+impl<R: Read> Read for CachingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        if let Some(file) = &mut self.file {
+            if file.write_all(&buf[..read]).is_err() {
+                self.file = None;
+            }
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::{
+        get_dir_entries, get_extracted_archive_in, get_release_api_url, is_intact, is_obsolete,
+        matches_version, open_archive_in, record_archive_in, record_digest, strip_host_triple,
+        CachingReader, FileDigest,
+    };
+    use std::{
+        fs::{create_dir_all, write},
+        io::Read,
+    };
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_archive_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let archives_dir = temp_dir.path().join("archives");
+        let url = "https://example.com/openocd.tar.gz";
+        let mut reader = CachingReader::new_in("openocd".as_bytes(), true, archives_dir.clone());
+        let mut start = [0; 4];
+        reader.read_exact(&mut start).unwrap();
+        let hash = reader.finish().unwrap();
+        assert!(archives_dir.join(&hash).exists());
+        assert!(open_archive_in(&archives_dir, url, None).is_none());
+        // Archives are found by their published hash, whatever their URL.
+        let (cached_hash, _) = open_archive_in(&archives_dir, url, Some(&hash)).unwrap();
+        assert_eq!(cached_hash, hash);
+        assert!(open_archive_in(&archives_dir, url, Some("0123")).is_none());
+
+        let directory = temp_dir.path().join("openocd");
+        record_archive_in(&archives_dir, &hash, url, "openocd.tar.gz", &directory);
+        let (cached_hash, mut archive) = open_archive_in(&archives_dir, url, None).unwrap();
+        assert_eq!(cached_hash, hash);
+        let mut contents = String::new();
+        archive.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "openocd");
+        let entries = get_dir_entries(temp_dir.path());
+        assert_eq!(entries.len(), 1);
+        let archive = entries[0].archive.as_ref().unwrap();
+        assert_eq!(archive.file_name, "openocd.tar.gz");
+        assert!(!archive.is_installed());
+        let (extracted_hash, extracted) =
+            get_extracted_archive_in(&archives_dir, &directory).unwrap();
+        assert_eq!(extracted_hash, hash);
+        assert!(extracted.urls.contains(url));
+        assert!(get_extracted_archive_in(&archives_dir, temp_dir.path()).is_none());
+
+        // Archives that do not match their hash anymore are removed.
+        write(archives_dir.join(&hash), "corrupted").unwrap();
+        assert!(open_archive_in(&archives_dir, url, None).is_none());
+        assert!(!archives_dir.join(&hash).exists());
+    }
+
+    #[test]
+    fn test_get_release_api_url() {
+        assert_eq!(
+            get_release_api_url(
+                "https://github.com/esp-rs/rust-build/releases/download/v1.64.0.0/rust-src-1.64.0.0.tar.xz"
+            ),
+            Some((
+                "https://api.github.com/repos/esp-rs/rust-build/releases/tags/v1.64.0.0"
+                    .to_string(),
+                "rust-src-1.64.0.0.tar.xz".to_string()
+            ))
+        );
+        assert!(get_release_api_url("https://github.com/espressif/esp-idf.git").is_none());
+        assert!(get_release_api_url(
+            "https://dl.espressif.com/github_assets/espressif/crosstool-NG/releases/download/esp-2021r2/gcc.tar.gz"
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_get_dir_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        write(temp_dir.path().join("cmake.zip"), "cmake").unwrap();
+        create_dir_all(temp_dir.path().join("rust").join("rust-nightly")).unwrap();
+        write(temp_dir.path().join("rust").join("rust.tar.xz"), "rust").unwrap();
+        let mut entries = get_dir_entries(temp_dir.path());
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].path, temp_dir.path().join("cmake.zip"));
+        assert_eq!(entries[0].size, 5);
+        assert_eq!(
+            entries[1].path,
+            temp_dir.path().join("rust").join("rust-nightly")
+        );
+    }
+
+    #[test]
+    fn test_matches_version() {
+        assert!(matches_version(
+            "cmake-3.24.0-linux-x86_64.tar.gz",
+            "3.24.0"
+        ));
+        assert!(matches_version(
+            "xtensa-esp32-elf-gcc11_2_0-esp-2022r1-linux-amd64.tar.xz",
+            "esp-2022r1-11.2.0"
+        ));
+        assert!(matches_version(
+            "openocd-esp32-linux-amd64-0.11.0-esp32-20220706.tar.gz",
+            "v0.11.0-esp32-20220706"
+        ));
+        assert!(!matches_version(
+            "cmake-3.20.3-linux-x86_64.tar.gz",
+            "3.24.0"
+        ));
+    }
+
+    #[test]
+    fn test_is_obsolete() {
+        let versions = vec![
+            strip_host_triple("esp-15.0.0-20221201-x86_64-unknown-linux-gnu").to_string(),
+            strip_host_triple("esp-2022r1-11.2.0").to_string(),
+        ];
+        let rust_dist_names = vec![
+            "rust.tar.xz".to_string(),
+            "rust-nightly-x86_64-unknown-linux-gnu".to_string(),
+            "rust-src-nightly".to_string(),
+        ];
+        assert!(!is_obsolete(
+            "llvm-esp-15.0.0-20221201-linux-amd64.tar.xz",
+            &versions,
+            &rust_dist_names
+        ));
+        assert!(!is_obsolete(
+            "libs_llvm-esp-15.0.0-20221201-linux-amd64.tar.xz",
+            &versions,
+            &rust_dist_names
+        ));
+        assert!(is_obsolete(
+            "llvm-esp-14.0.0-20220415-linux-amd64.tar.xz",
+            &versions,
+            &rust_dist_names
+        ));
+        assert!(!is_obsolete(
+            "rust-nightly-x86_64-unknown-linux-gnu",
+            &versions,
+            &rust_dist_names
+        ));
+        assert!(!is_obsolete(
+            "rust-src-nightly",
+            &versions,
+            &rust_dist_names
+        ));
+        assert!(is_obsolete("rust-src-nightly", &versions, &[]));
+    }
+
+    #[test]
+    fn test_is_intact() {
+        let temp_dir = TempDir::new().unwrap();
+        let rustup_dir = temp_dir.path().join("rustup");
+        create_dir_all(&rustup_dir).unwrap();
+        let file = rustup_dir.join("rustup-init");
+        write(&file, "rustup").unwrap();
+        assert!(!is_intact(&file));
+
+        let digest = FileDigest {
+            size: 6,
+            sha256: "4d4240a2162a3fa435078c60eac770175d6179a28dcac8972411336997b361a2".to_string(),
+        };
+        record_digest(&file, &digest).unwrap();
+        assert!(is_intact(&file));
+        let entries = get_dir_entries(temp_dir.path());
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, file);
+
+        // Truncated files are caught by their size, corrupted ones by their hash.
+        write(&file, "rust").unwrap();
+        assert!(!is_intact(&file));
+        write(&file, "rusted").unwrap();
+        assert!(!is_intact(&file));
+    }
+}
@@ -1,47 +1,200 @@
-use crate::{error::Error, host_triple::HostTriple, targets::Target, toolchain::rust::XtensaRust};
+use crate::{
+    envs::get_env_config_path,
+    error::Error,
+    hooks::Hooks,
+    host_triple::HostTriple,
+    install::Profile,
+    logging::ColorChoice,
+    targets::Target,
+    toolchain::{espidf::EspIdfLayout, rust::XtensaRust},
+    verify::Receipt,
+};
 use directories_next::ProjectDirs;
 use miette::Result;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashSet},
     fs::{create_dir_all, read, write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
+/// Defaults of the console output, set in the `[output]` table of the configuration file,
+/// e.g.:
+///
+/// ```toml
+/// [output]
+/// log-level = "debug"
+/// color = "never"
+/// emoji = false
+/// ```
+///
+/// The command line options, and their environment variables, take precedence.
+#[derive(Debug, Deserialize, Serialize, Default, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Output {
+    /// Log level: `debug`, `info`, `warn` or `error`.
+    pub log_level: Option<String>,
+    /// Coloring of the output: `auto`, `always` or `never`.
+    pub color: Option<ColorChoice>,
+    /// Whether emojis are displayed.
+    pub emoji: Option<bool>,
+}
+
+impl Output {
+    /// Whether no default is set.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Timeouts of the HTTP client, in seconds, set in the `[timeouts]` table of the
+/// configuration file, e.g. for slow networks:
+///
+/// ```toml
+/// [timeouts]
+/// connect = 30
+/// read = 120
+/// ```
+///
+/// The `--connect-timeout` and `--read-timeout` options take precedence.
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Timeout of the connection to a server.
+    pub connect: Option<u64>,
+    /// Timeout of stalled reads and writes.
+    pub read: Option<u64>,
+}
+
+impl Timeouts {
+    /// Whether no timeout is set.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Periodic check for newer espup and Xtensa Rust releases, set in the `[update_check]`
+/// table of the configuration file. It is disabled unless enabled, e.g.:
+///
+/// ```toml
+/// [update_check]
+/// enabled = true
+/// interval = 168
+/// ```
+///
+/// Only the public release listings of crates.io and GitHub are requested: nothing about the
+/// installation is sent.
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UpdateCheck {
+    /// Whether the check is enabled.
+    pub enabled: Option<bool>,
+    /// Hours between two checks.
+    pub interval: Option<u64>,
+}
+
+impl UpdateCheck {
+    /// Whether no setting is set.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Base URLs replacing the default locations of the components, set in the `[urls]` table
+/// of the configuration file, e.g. to download through an artifact proxy:
+///
+/// ```toml
+/// [urls]
+/// gcc-esp32 = "https://artifactory.example.com/crosstool-NG/releases/download"
+/// llvm = "https://artifactory.example.com/llvm-project/releases/download"
+/// rust = "https://artifactory.example.com/rust-build/releases/download"
+/// esp-idf = "https://git.example.com/espressif/esp-idf"
+/// ```
+///
+/// The ESP-IDF repository can also be an SSH URL, e.g. `git@git.example.com:espressif/esp-idf`.
+pub type Urls = BTreeMap<String, String>;
+
 /// Deserialized contents of a configuration file
 #[derive(Debug, Deserialize, Serialize, Default, Clone)]
 pub struct Config {
+    /// Whether ESP-IDF was installed from its release archive instead of cloned.
+    pub esp_idf_from_archive: Option<bool>,
+    /// Naming scheme of the ESP-IDF checkouts.
+    pub esp_idf_layout: Option<EspIdfLayout>,
+    /// Path of the existing ESP-IDF checkout used, not managed by espup.
+    pub esp_idf_path: Option<PathBuf>,
+    /// ESP-IDF repository URL.
+    pub esp_idf_repository: Option<String>,
     /// ESP-IDF version
     pub esp_idf_version: Option<String>,
     /// Destination of the generated export file.
     pub export_file: Option<PathBuf>,
     /// Extra crates to installed.
     pub extra_crates: Option<HashSet<String>>,
+    /// Scripts run around the installation and its components.
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
     /// Host triple
     pub host_triple: HostTriple,
+    /// Directory of the ESP-IDF compatible export scripts.
+    pub idf_export_scripts: Option<PathBuf>,
+    /// Directory where the tools are installed.
+    pub install_dir: Option<PathBuf>,
     /// LLVM toolchain path.
     pub llvm_path: Option<PathBuf>,
+    /// Mirrors of the downloads, in order of preference.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
     /// Nightly Rust toolchain version.
     pub nightly_version: String,
+    /// Defaults of the console output.
+    #[serde(default, skip_serializing_if = "Output::is_empty")]
+    pub output: Output,
+    /// Installation profile, maintained by the updates.
+    pub profile: Option<Profile>,
+    /// Hashes of the installed binaries, to verify the installation.
+    pub receipt: Option<Receipt>,
     /// List of targets instaled.
     pub targets: HashSet<Target>,
+    /// Timeouts of the HTTP client.
+    #[serde(default, skip_serializing_if = "Timeouts::is_empty")]
+    pub timeouts: Timeouts,
+    /// Paths of the optional tools installed, e.g. QEMU or GDB.
+    pub tool_paths: Option<HashSet<PathBuf>>,
+    /// Periodic check for newer releases.
+    #[serde(default, skip_serializing_if = "UpdateCheck::is_empty")]
+    pub update_check: UpdateCheck,
+    /// Base URLs of the components.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub urls: Urls,
     /// Xtensa Rust toolchain.
     pub xtensa_rust: Option<XtensaRust>,
 }
 
 impl Config {
-    /// Gets the path to the configuration file.
-    pub fn get_config_path() -> Result<PathBuf, Error> {
+    /// Gets the path to the configuration file, the one of the named environment `env`, if
+    /// any.
+    pub fn get_config_path(env: Option<&str>) -> Result<PathBuf, Error> {
         let dirs = ProjectDirs::from("rs", "esp", "espup").unwrap();
-        let file = dirs.config_dir().join("espup.toml");
+        let file = match env {
+            Some(name) => get_env_config_path(dirs.config_dir(), name),
+            None => Self::get_default_config_path()?,
+        };
         Ok(file)
     }
 
-    /// Load the config from config file
-    pub fn load() -> Result<Self, Error> {
-        let file = Self::get_config_path()?;
+    /// Gets the path to the configuration file of the default environment.
+    fn get_default_config_path() -> Result<PathBuf, Error> {
+        let dirs = ProjectDirs::from("rs", "esp", "espup").unwrap();
+        Ok(dirs.config_dir().join("espup.toml"))
+    }
 
-        let config = if let Ok(data) = read(&file) {
+    /// Load the config from config file of the named environment `env`, if any.
+    pub fn load(env: Option<&str>) -> Result<Self, Error> {
+        Self::load_from(&Self::get_config_path(env)?)
+    }
+
+    /// Loads the config from `file`.
+    fn load_from(file: &Path) -> Result<Self, Error> {
+        let config = if let Ok(data) = read(file) {
             toml::from_slice(&data).map_err(|_| Error::FailedToDeserialize)?
         } else {
             return Err(Error::FileNotFound(file.to_string_lossy().into_owned()));
@@ -49,13 +202,172 @@ impl Config {
         Ok(config)
     }
 
-    /// Save the config to file
-    pub fn save(&self) -> Result<(), Error> {
-        let file = Self::get_config_path()?;
+    /// Loads a table of the configuration file, which may only contain the tables set by
+    /// the user, e.g. before the first installation. Named environments fall back to the
+    /// tables of the default one.
+    fn load_table<T: DeserializeOwned + Default>(env: Option<&str>, name: &str) -> T {
+        let default_config_path = env.and_then(|_| Self::get_default_config_path().ok());
+        Self::get_config_path(env)
+            .ok()
+            .into_iter()
+            .chain(default_config_path)
+            .find_map(|file| {
+                let data = read(file).ok()?;
+                let config = toml::from_slice::<toml::Value>(&data).ok()?;
+                config.get(name)?.clone().try_into().ok()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Loads the defaults of the console output.
+    pub fn load_output(env: Option<&str>) -> Output {
+        Self::load_table(env, "output")
+    }
+
+    /// Loads the mirrors of the downloads, in order of preference, e.g.
+    /// `mirrors = ["espressif", "github"]`.
+    pub fn load_mirrors(env: Option<&str>) -> Vec<String> {
+        Self::load_table(env, "mirrors")
+    }
 
-        let serialized = toml::to_string(&self.clone()).map_err(|_| Error::FailedToSerialize)?;
+    /// Saves the mirrors of the downloads, in order of preference, leaving the rest of the
+    /// configuration file, if any, untouched.
+    pub fn save_mirrors(env: Option<&str>, mirrors: &[String]) -> Result<(), Error> {
+        let file = Self::get_config_path(env)?;
+        let mut config = read(&file)
+            .ok()
+            .and_then(|data| toml::from_slice::<toml::value::Table>(&data).ok())
+            .unwrap_or_default();
+        config.insert(
+            "mirrors".to_string(),
+            toml::Value::try_from(mirrors).map_err(|_| Error::FailedToSerialize)?,
+        );
+        let serialized =
+            toml::to_string(&toml::Value::Table(config)).map_err(|_| Error::FailedToSerialize)?;
         create_dir_all(file.parent().unwrap()).map_err(|_| Error::FailedToCreateConfigFile)?;
         write(&file, serialized).map_err(|_| Error::FailedToWrite(file.display().to_string()))?;
         Ok(())
     }
+
+    /// Loads the timeouts of the HTTP client.
+    pub fn load_timeouts(env: Option<&str>) -> Timeouts {
+        Self::load_table(env, "timeouts")
+    }
+
+    /// Loads the settings of the periodic check for newer releases.
+    pub fn load_update_check(env: Option<&str>) -> UpdateCheck {
+        Self::load_table(env, "update_check")
+    }
+
+    /// Loads the base URLs of the components.
+    pub fn load_urls(env: Option<&str>) -> Urls {
+        Self::load_table(env, "urls")
+    }
+
+    /// Serializes the config. It goes through a TOML value, which emits the tables, e.g.
+    /// `[output]`, after the values that follow them in the struct, e.g. `targets`.
+    fn serialize(&self) -> Result<String, Error> {
+        toml::Value::try_from(self)
+            .and_then(|value| toml::to_string(&value))
+            .map_err(|_| Error::FailedToSerialize)
+    }
+
+    /// Save the config to the file of the named environment `env`, if any.
+    pub fn save(&self, env: Option<&str>) -> Result<(), Error> {
+        self.save_to(&Self::get_config_path(env)?)
+    }
+
+    /// Saves the config to `file`.
+    fn save_to(&self, file: &Path) -> Result<(), Error> {
+        let serialized = self.serialize()?;
+        create_dir_all(file.parent().unwrap()).map_err(|_| Error::FailedToCreateConfigFile)?;
+        write(file, serialized).map_err(|_| Error::FailedToWrite(file.display().to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::{Config, Hooks, Output, Timeouts, UpdateCheck},
+        logging::ColorChoice,
+        targets::Target,
+        verify::Receipt,
+    };
+    use serde::Deserialize;
+    use std::{collections::HashSet, path::PathBuf};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_output() {
+        #[derive(Deserialize)]
+        struct OutputFile {
+            output: Output,
+        }
+
+        let output_file: OutputFile =
+            toml::from_str("[output]\nlog-level = \"debug\"\ncolor = \"never\"\n").unwrap();
+        assert_eq!(output_file.output.log_level.as_deref(), Some("debug"));
+        assert_eq!(output_file.output.color, Some(ColorChoice::Never));
+        assert_eq!(output_file.output.emoji, None);
+        assert!(Output::default().is_empty());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let config = Config {
+            mirrors: vec!["espressif".to_string(), "github".to_string()],
+            output: Output {
+                emoji: Some(false),
+                ..Default::default()
+            },
+            timeouts: Timeouts {
+                connect: Some(30),
+                read: None,
+            },
+            tool_paths: Some(HashSet::from([PathBuf::from("/opt/espup/qemu")])),
+            update_check: UpdateCheck {
+                enabled: Some(true),
+                interval: None,
+            },
+            ..Default::default()
+        };
+        let serialized = config.serialize().unwrap();
+        let deserialized: Config = toml::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.mirrors, config.mirrors);
+        assert_eq!(deserialized.output, config.output);
+        assert_eq!(deserialized.timeouts, config.timeouts);
+        assert_eq!(deserialized.tool_paths, config.tool_paths);
+        assert_eq!(deserialized.update_check, config.update_check);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("espup.toml");
+        // Tables, e.g. the receipt, are declared before plain values, e.g. the targets.
+        let config = Config {
+            hooks: Hooks {
+                pre_install: Some("echo pre-install".to_string()),
+                ..Default::default()
+            },
+            nightly_version: "nightly".to_string(),
+            receipt: Some(Receipt::from([(
+                PathBuf::from("/opt/espup/rust/esp/bin/rustc"),
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_string(),
+            )])),
+            targets: HashSet::from([Target::ESP32]),
+            timeouts: Timeouts {
+                connect: Some(30),
+                read: None,
+            },
+            ..Default::default()
+        };
+        config.save_to(&file).unwrap();
+        let loaded = Config::load_from(&file).unwrap();
+        assert_eq!(loaded.receipt, config.receipt);
+        assert_eq!(loaded.hooks, config.hooks);
+        assert_eq!(loaded.targets, config.targets);
+        assert_eq!(loaded.timeouts, config.timeouts);
+    }
 }
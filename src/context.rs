@@ -0,0 +1,227 @@
+//! Context of an invocation: where the tools are installed, how they are downloaded and
+//! where the progress is reported. The installers receive it instead of reading
+//! process-wide settings, so that several installations can run in the same process, e.g.
+//! one per workspace of an IDE.
+
+use crate::{
+    config::Config,
+    download::{Downloader, HttpDownloader},
+    error::Error,
+    http::Http,
+    interactive::is_interactive,
+    progress::Progress,
+    rollback::Rollback,
+    ssh,
+    summary::Summary,
+    toolchain::{
+        espidf::{get_default_tools_path, EspIdfLayout},
+        rust::get_cargo_home,
+    },
+};
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Context of an invocation, passed to the installers.
+#[derive(Debug, Clone)]
+pub struct Context {
+    /// Named environment, if any, whose configuration and export files are used.
+    pub env: Option<String>,
+    /// Directory where the tools are installed.
+    pub tools_path: PathBuf,
+    /// Whether the tools directory was given for the invocation, with `--tools-path`. It
+    /// takes precedence over the installation directory recorded in the configuration file.
+    pub tools_path_overridden: bool,
+    /// Naming scheme of the ESP-IDF checkouts.
+    pub esp_idf_layout: EspIdfLayout,
+    /// HTTP client of the downloads and GitHub API queries.
+    pub http: Arc<Http>,
+    /// Transport of the downloaded files, HTTP with [`Context::http`] when not set.
+    pub downloader: Option<Arc<dyn Downloader>>,
+    /// Directory of the temporary files and staging directories, if not the temporary
+    /// directory of the system and the directory of the tools.
+    pub staging_dir: Option<PathBuf>,
+    /// Local archives of the components installed instead of the downloaded ones, by
+    /// component.
+    pub local_artifacts: HashMap<String, PathBuf>,
+    /// Whether the quarantine attribute of the extracted files is kept on macOS.
+    pub keep_quarantine: bool,
+    /// Whether the downloaded archives are stored in the cache.
+    pub persist_archives: bool,
+    /// Whether the signatures are verified, when they are published.
+    pub verify_signatures: bool,
+    /// Whether releases without a valid signature are refused.
+    pub require_signatures: bool,
+    /// Whether rustup-init adds cargo to the `PATH` in the profiles of the shells.
+    pub modify_path: bool,
+    /// Whether the rust-src component of the toolchains is installed.
+    pub install_rust_src: bool,
+    /// Python interpreter running the installers of ESP-IDF and its tools, if not the one
+    /// in `PATH`.
+    pub python: Option<String>,
+    /// Handlers of the progress events.
+    pub progress: Progress,
+    /// Changes undone if the installation fails.
+    pub rollback: Arc<Rollback>,
+    /// Downloads and timings of the installation.
+    pub summary: Arc<Summary>,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self {
+            env: None,
+            tools_path: PathBuf::from(get_default_tools_path()),
+            tools_path_overridden: false,
+            esp_idf_layout: EspIdfLayout::default(),
+            http: Arc::default(),
+            downloader: None,
+            staging_dir: None,
+            local_artifacts: HashMap::new(),
+            keep_quarantine: false,
+            persist_archives: false,
+            verify_signatures: false,
+            require_signatures: false,
+            modify_path: true,
+            install_rust_src: true,
+            python: None,
+            progress: Progress::default(),
+            rollback: Arc::default(),
+            summary: Arc::default(),
+        }
+    }
+}
+
+impl Context {
+    /// Loads the configuration file, and uses the installation directory, unless overridden,
+    /// and the ESP-IDF layout it records.
+    pub fn load_config(&mut self) -> Result<Config, Error> {
+        let config = Config::load(self.env.as_deref())?;
+        if let Some(install_dir) = &config.install_dir {
+            if !self.tools_path_overridden {
+                self.tools_path = install_dir.clone();
+            }
+        }
+        self.esp_idf_layout = config.esp_idf_layout.unwrap_or_default();
+        Ok(config)
+    }
+
+    /// Gets the directory where the tools are installed, as a string.
+    pub fn tools_path(&self) -> String {
+        self.tools_path.display().to_string()
+    }
+
+    /// Gets the transport of the downloaded files.
+    pub fn downloader(&self) -> Arc<dyn Downloader> {
+        match &self.downloader {
+            Some(downloader) => downloader.clone(),
+            None => Arc::new(HttpDownloader::new(self.http.clone())),
+        }
+    }
+
+    /// Gets the directory of the temporary files: the staging directory, if set, otherwise
+    /// the temporary directory of the system.
+    pub fn temp_dir(&self) -> PathBuf {
+        self.staging_dir.clone().unwrap_or_else(env::temp_dir)
+    }
+
+    /// Gets the URL of the archive of a component: the `file://` URL of its local archive,
+    /// if any, otherwise `url`.
+    pub fn artifact_url(&self, component: &str, url: String) -> String {
+        match self.local_artifacts.get(component) {
+            Some(path) => format!("file://{}", path.display()),
+            None => url,
+        }
+    }
+
+    /// Whether the signatures are verified.
+    pub fn verifies_signatures(&self) -> bool {
+        self.verify_signatures || self.require_signatures
+    }
+
+    /// Gets the environment variables of the programs espup runs, e.g. git, pip, cargo and
+    /// ESP-IDF's `idf_tools.py`: the tools directory, the proxy and the mirror, the options
+    /// of git when the user cannot be prompted, and a `PATH` including the binaries of
+    /// Cargo, where rustup is installed.
+    pub fn envs(&self) -> Vec<(&'static str, OsString)> {
+        let mut envs = vec![("IDF_TOOLS_PATH", self.tools_path.clone().into_os_string())];
+        envs.extend(
+            self.http
+                .envs()
+                .into_iter()
+                .map(|(key, value)| (key, OsString::from(value))),
+        );
+        if !is_interactive() {
+            envs.push(("GIT_TERMINAL_PROMPT", OsString::from("0")));
+        }
+        envs.extend(
+            ssh::get_envs()
+                .into_iter()
+                .map(|(key, value)| (key, OsString::from(value))),
+        );
+        if let Some(path) = get_path_with(&get_cargo_home().join("bin")) {
+            envs.push(("PATH", path));
+        }
+        envs
+    }
+}
+
+/// Gets the `PATH` of the process with `directory` appended, unless it already contains it.
+fn get_path_with(directory: &Path) -> Option<OsString> {
+    let path = env::var_os("PATH").unwrap_or_default();
+    let mut paths: Vec<PathBuf> = env::split_paths(&path).collect();
+    if paths.iter().any(|path| path == directory) {
+        return None;
+    }
+    paths.push(directory.to_path_buf());
+    env::join_paths(paths).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::{get_path_with, Context};
+    use std::{env, path::Path};
+
+    #[test]
+    fn test_get_path_with() {
+        let path = env::var_os("PATH").unwrap_or_default();
+        let directory = env::split_paths(&path).next().unwrap();
+        assert_eq!(get_path_with(&directory), None);
+        let extended = get_path_with(Path::new("/espup/cargo/bin")).unwrap();
+        assert_eq!(
+            env::split_paths(&extended).last().unwrap(),
+            Path::new("/espup/cargo/bin")
+        );
+    }
+
+    #[test]
+    fn test_context() {
+        let mut context = Context::default();
+        assert_eq!(
+            context.artifact_url("llvm", "https://example.com/llvm.tar.xz".to_string()),
+            "https://example.com/llvm.tar.xz"
+        );
+        context.local_artifacts.insert(
+            "llvm".to_string(),
+            Path::new("/tmp/llvm.tar.xz").to_path_buf(),
+        );
+        assert_eq!(
+            context.artifact_url("llvm", "https://example.com/llvm.tar.xz".to_string()),
+            "file:///tmp/llvm.tar.xz"
+        );
+        assert!(!context.verifies_signatures());
+        context.require_signatures = true;
+        assert!(context.verifies_signatures());
+        assert!(
+            context
+                .envs()
+                .iter()
+                .any(|(key, value)| *key == "IDF_TOOLS_PATH"
+                    && Path::new(value) == context.tools_path)
+        );
+    }
+}
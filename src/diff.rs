@@ -0,0 +1,197 @@
+//! Changes that an installation would make to the current environment, printed by
+//! `espup diff` so that they can be reviewed before being applied.
+
+use crate::{
+    config::Config,
+    context::Context,
+    environment::{get_environment, Environment},
+    install::get_export_file,
+    list::{format_size, get_installed_components, Component},
+    plan::Plan,
+};
+use console::style;
+use std::{collections::BTreeSet, path::Path};
+
+/// Change of the versions of a component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentChange {
+    /// Name of the component.
+    pub name: String,
+    /// Versions in the current environment.
+    pub old: Vec<String>,
+    /// Versions once installed.
+    pub new: Vec<String>,
+}
+
+impl ComponentChange {
+    /// Whether the versions of the component stay the same.
+    pub fn is_unchanged(&self) -> bool {
+        self.old == self.new
+    }
+
+    /// Formats the change as a line of a diff: `+` for added components, `-` for removed
+    /// ones, `~` for changed ones, colored when the output supports it.
+    pub fn format(&self) -> String {
+        let (old, new) = (self.old.join(", "), self.new.join(", "));
+        if self.old.is_empty() {
+            style(format!("+ {}: {}", self.name, new))
+                .green()
+                .to_string()
+        } else if self.new.is_empty() {
+            style(format!("- {}: {}", self.name, old)).red().to_string()
+        } else if self.is_unchanged() {
+            style(format!("  {}: {}", self.name, old)).dim().to_string()
+        } else {
+            style(format!("~ {}: {} → {}", self.name, old, new))
+                .yellow()
+                .to_string()
+        }
+    }
+}
+
+/// Whether a component is used by the environment: it is the Xtensa Rust toolchain of the
+/// configuration, or the export file refers to a path inside it.
+fn is_in_use(component: &Component, config: &Config, environment: &Environment) -> bool {
+    if let Some(xtensa_rust) = &config.xtensa_rust {
+        if component.path == xtensa_rust.toolchain_destination {
+            return true;
+        }
+    }
+    environment
+        .path
+        .iter()
+        .chain(environment.variables.iter().map(|(_, value)| value))
+        .any(|value| Path::new(value).starts_with(&component.path))
+}
+
+/// Gets the installed components used by the current environment. The other installed
+/// versions are left out, as an installation does not change them.
+pub fn get_current_components(context: &Context, config: &Config) -> Vec<Component> {
+    let environment = get_export_file(context.env.as_deref(), config.export_file.clone())
+        .and_then(|export_file| get_environment(&export_file))
+        .unwrap_or_default();
+    get_installed_components(context, Some(config))
+        .into_iter()
+        .filter(|component| is_in_use(component, config, &environment))
+        .collect()
+}
+
+/// Compares the current components to the planned ones, by name. When ESP-IDF installs
+/// its own tools, which are not resolved beforehand, the components that are only in the
+/// current environment are left out instead of being reported as removed.
+pub fn diff_components(
+    current: &[Component],
+    planned: &[Component],
+    esp_idf: bool,
+) -> Vec<ComponentChange> {
+    let names: BTreeSet<&str> = current
+        .iter()
+        .chain(planned)
+        .map(|component| component.name.as_str())
+        .collect();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let old: Vec<&Component> = current.iter().filter(|c| c.name == name).collect();
+            let new: Vec<&Component> = planned.iter().filter(|c| c.name == name).collect();
+            if new.is_empty() && esp_idf {
+                return None;
+            }
+            // The installed versions describe the component better, e.g. the exact
+            // version of an ESP-IDF branch.
+            let get_version = |component: &Component| {
+                old.iter()
+                    .find(|installed| installed.path == component.path)
+                    .map_or_else(|| component.version.clone(), |c| c.version.clone())
+            };
+            let mut old_versions: Vec<String> = old.iter().map(|c| get_version(c)).collect();
+            let mut new_versions: Vec<String> = new.iter().map(|c| get_version(c)).collect();
+            old_versions.sort();
+            old_versions.dedup();
+            new_versions.sort();
+            new_versions.dedup();
+            Some(ComponentChange {
+                name: name.to_string(),
+                old: old_versions,
+                new: new_versions,
+            })
+        })
+        .collect()
+}
+
+/// Prints the changes of the components, and the download size and disk usage of the plan.
+/// Resolves the size of the downloads with HEAD requests.
+pub fn print_diff(context: &Context, changes: &[ComponentChange], plan: &Plan) {
+    for change in changes {
+        println!("{}", change.format());
+    }
+    if changes.iter().all(ComponentChange::is_unchanged) {
+        println!("No component would change");
+    }
+    let (download_size, unknown) = plan.get_total_download_size(context);
+    println!(
+        "Download: {}{}",
+        format_size(download_size),
+        if unknown { " (plus unknown sizes)" } else { "" }
+    );
+    println!(
+        "Disk usage: {}",
+        format_size(plan.get_disk_usage().values().sum())
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        diff::{diff_components, ComponentChange},
+        list::Component,
+    };
+    use std::path::PathBuf;
+
+    fn component(name: &str, version: &str) -> Component {
+        Component {
+            name: name.to_string(),
+            version: version.to_string(),
+            path: PathBuf::from(format!("/espup/{name}/{version}")),
+        }
+    }
+
+    #[test]
+    fn test_diff_components() {
+        let current = [
+            component("Xtensa Rust", "1.74.0.0"),
+            component("Xtensa LLVM", "esp-16.0.4"),
+            component("qemu-xtensa", "esp_develop_8.1.2_20231017"),
+        ];
+        let planned = [
+            component("Xtensa Rust", "1.75.0.0"),
+            component("Xtensa LLVM", "esp-16.0.4"),
+            component("riscv32-esp-elf", "esp-13.2.0_20230928"),
+        ];
+        let changes = diff_components(&current, &planned, false);
+        assert_eq!(
+            changes
+                .iter()
+                .map(|change| change.name.as_str())
+                .collect::<Vec<&str>>(),
+            [
+                "Xtensa LLVM",
+                "Xtensa Rust",
+                "qemu-xtensa",
+                "riscv32-esp-elf"
+            ]
+        );
+        assert!(changes[0].is_unchanged());
+        assert_eq!(
+            changes[1],
+            ComponentChange {
+                name: "Xtensa Rust".to_string(),
+                old: vec!["1.74.0.0".to_string()],
+                new: vec!["1.75.0.0".to_string()],
+            }
+        );
+        assert!(changes[2].new.is_empty());
+        assert!(changes[3].old.is_empty());
+        assert_eq!(diff_components(&current, &planned, true).len(), 3);
+    }
+}
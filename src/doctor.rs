@@ -0,0 +1,832 @@
+//! Prerequisites and health checks of the host environment.
+
+#[cfg(windows)]
+use crate::toolchain::drivers::{format_drivers, get_missing_drivers};
+#[cfg(target_os = "linux")]
+use crate::udev::get_serial_group;
+use crate::{
+    config::Config,
+    context::Context,
+    emoji,
+    environment::{get_conflicting_clang_variables, get_conflicting_variables, get_environment},
+    error::Error,
+    header::{get_mismatches, hash_exports, parse_header, EXPORTS_HASH_VARIABLE},
+    host_triple::{self, get_host_triple, get_vs_build_tools, is_musl, HostTriple},
+    install::get_export_file,
+    list::get_installed_components,
+    packages::{
+        self, get_command_output, get_install_command, get_missing_packages, PackageManager,
+        RequiredBy,
+    },
+    targets::parse_esp_idf_version,
+    toolchain::espidf::get_python,
+    wsl::{self, Wsl},
+};
+use log::{error, info, warn};
+use std::{
+    env,
+    fs::{read_dir, read_to_string},
+    path::{Path, PathBuf},
+};
+
+/// Recommended free disk space, in bytes, for a full installation with ESP-IDF.
+const RECOMMENDED_DISK_SPACE: u64 = 5 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Check passed.
+    Ok,
+    /// Check failed, but only some features are affected.
+    Warning,
+    /// Check failed, installation will not work.
+    Error,
+}
+
+/// Result of a single health check.
+#[derive(Debug, Clone)]
+pub struct Check {
+    /// Name of the checked item.
+    pub name: &'static str,
+    /// Outcome of the check.
+    pub status: Status,
+    /// Details about the outcome.
+    pub message: String,
+    /// Steps to fix the problem, if any.
+    pub remediation: Option<String>,
+}
+
+impl Check {
+    /// Creates a passed check.
+    fn ok(name: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Ok,
+            message: message.into(),
+            remediation: None,
+        }
+    }
+
+    /// Creates a check with a warning.
+    fn warning(
+        name: &'static str,
+        message: impl Into<String>,
+        remediation: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            status: Status::Warning,
+            message: message.into(),
+            remediation: Some(remediation.into()),
+        }
+    }
+
+    /// Creates a failed check.
+    fn error(
+        name: &'static str,
+        message: impl Into<String>,
+        remediation: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            status: Status::Error,
+            message: message.into(),
+            remediation: Some(remediation.into()),
+        }
+    }
+
+    /// Logs the check result and its remediation.
+    pub fn print(&self) {
+        match self.status {
+            Status::Ok => info!("{} {}: {}", emoji::CHECK, self.name, self.message),
+            Status::Warning => warn!("{} {}: {}", emoji::WARN, self.name, self.message),
+            Status::Error => error!("{} {}: {}", emoji::ERROR, self.name, self.message),
+        }
+        if let Some(remediation) = &self.remediation {
+            warn!("   {} {}", emoji::INFO, remediation);
+        }
+    }
+}
+
+/// Checks the prerequisites and the health of the environment, printing the results, and
+/// fails when any check fails. With `install_prereqs`, installs the missing system packages
+/// first.
+pub fn doctor(
+    context: &Context,
+    esp_idf_version: Option<&str>,
+    install_prereqs: bool,
+) -> Result<(), Error> {
+    info!("{} Checking the environment", emoji::DISC);
+    if install_prereqs {
+        packages::check_system_packages(
+            context,
+            &[RequiredBy::EspIdf, RequiredBy::Espflash],
+            true,
+        )?;
+    }
+    let host_triple = Config::load(context.env.as_deref())
+        .ok()
+        .map(|config| config.host_triple);
+    let checks = run_checks(context, esp_idf_version, host_triple.as_ref());
+    for check in &checks {
+        check.print();
+    }
+
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == Status::Error)
+        .count();
+    if failed > 0 {
+        return Err(Error::DoctorChecksFailed(failed));
+    }
+
+    info!("{} No blocking problems found!", emoji::CHECK);
+    Ok(())
+}
+
+/// Runs all the health checks. The Python checks use the requirements of the given
+/// ESP-IDF version, or of the latest one if unknown, and the linker checks the host of the
+/// installation, or the detected one if unknown.
+pub fn run_checks(
+    context: &Context,
+    esp_idf_version: Option<&str>,
+    host_triple: Option<&HostTriple>,
+) -> Vec<Check> {
+    let mut checks = vec![check_git()];
+    checks.extend(check_python_requirements(context, esp_idf_version));
+    checks.extend([
+        check_cmake(),
+        check_ninja(),
+        check_rustup(),
+        check_libc(),
+        check_system_packages(context),
+        check_disk_space(context),
+        check_esp_idf_environment(),
+        check_clang_environment(context),
+        check_libclang(context),
+        check_export_file(context),
+        check_path(context),
+    ]);
+    #[cfg(windows)]
+    checks.extend(check_long_paths());
+    #[cfg(windows)]
+    checks.push(check_usb_drivers());
+    #[cfg(target_os = "linux")]
+    checks.push(check_serial_group());
+    let host_triple = host_triple.cloned().or_else(|| get_host_triple(None).ok());
+    if host_triple == Some(HostTriple::X86_64PcWindowsMsvc) {
+        checks.push(check_vs_build_tools());
+    }
+    if let Some(wsl) = wsl::detect() {
+        checks.push(check_usbipd(wsl));
+    }
+    checks
+}
+
+/// Checks the Python interpreter, `pip` and `venv` needed to install the given ESP-IDF
+/// version, or the latest one if unknown.
+pub fn check_python_requirements(context: &Context, esp_idf_version: Option<&str>) -> Vec<Check> {
+    let min_version = get_min_python_version(esp_idf_version);
+    let mut checks = vec![check_python(context, min_version), check_pip(context)];
+    // ESP-IDF v4 creates the environment with `virtualenv`, installed with pip.
+    if min_version >= (3, 7) {
+        checks.push(check_venv(context));
+    }
+    checks
+}
+
+/// Gets the minimum Python version required by an ESP-IDF version. Branches and commits
+/// that are not named after a version get the requirements of the latest ESP-IDF.
+pub fn get_min_python_version(esp_idf_version: Option<&str>) -> (u32, u32) {
+    match esp_idf_version.and_then(parse_esp_idf_version) {
+        Some((major, _)) if major < 5 => (3, 6),
+        Some((5, minor)) if minor < 2 => (3, 7),
+        _ => (3, 8),
+    }
+}
+
+/// Returns the first line of the output of a command, if it could be executed successfully.
+fn get_command_version(program: &str, args: &[&str]) -> Option<String> {
+    get_command_output(program, args)
+        .and_then(|output| output.lines().next().map(|line| line.trim().to_string()))
+}
+
+/// Parses the version of a `python --version` output.
+pub fn parse_python_version(output: &str) -> Option<(u32, u32, u32)> {
+    let mut version = output
+        .trim()
+        .strip_prefix("Python ")?
+        .split('.')
+        .map(|s| s.parse::<u32>());
+    let major = version.next()?.ok()?;
+    let minor = version.next()?.ok()?;
+    let patch = version.next().and_then(|patch| patch.ok()).unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+fn check_git() -> Check {
+    match get_command_version("git", &["--version"]) {
+        Some(version) => Check::ok("git", version),
+        None => Check::error(
+            "git",
+            "git was not found",
+            "Install git (https://git-scm.com/downloads) and make sure it is in your PATH.",
+        ),
+    }
+}
+
+fn check_python(context: &Context, min_version: (u32, u32)) -> Check {
+    let python = get_python(context);
+    let output = match get_command_version(python, &["--version"]) {
+        Some(output) => output,
+        None => {
+            return Check::warning(
+                "python",
+                format!("{python} was not found"),
+                "Python is only required when installing ESP-IDF. Install it from https://www.python.org/downloads/ or with your package manager.",
+            )
+        }
+    };
+    match parse_python_version(&output) {
+        Some((major, minor, _)) if (major, minor) >= min_version => Check::ok("python", output),
+        Some(_) => Check::warning(
+            "python",
+            format!(
+                "{output} is too old, at least Python {}.{} is required",
+                min_version.0, min_version.1
+            ),
+            "Install a newer Python version to be able to install ESP-IDF.",
+        ),
+        None => Check::warning(
+            "python",
+            format!("Unexpected output from '{python} --version': '{output}'"),
+            "Make sure that the Python in your PATH is a working Python 3 interpreter.",
+        ),
+    }
+}
+
+fn check_pip(context: &Context) -> Check {
+    match get_command_version(get_python(context), &["-m", "pip", "--version"]) {
+        Some(version) => Check::ok("pip", version),
+        None => Check::warning(
+            "pip",
+            "pip was not found",
+            "pip is only required when installing ESP-IDF. Install it with your package manager (e.g. 'python3-pip') or run 'python3 -m ensurepip'.",
+        ),
+    }
+}
+
+fn check_venv(context: &Context) -> Check {
+    match get_command_output(get_python(context), &["-c", "import venv, ensurepip"]) {
+        Some(_) => Check::ok("venv", "venv and ensurepip are available"),
+        None => Check::warning(
+            "venv",
+            "venv or ensurepip are not available",
+            "venv is only required when installing ESP-IDF. Install it with your package manager (e.g. 'python3-venv').",
+        ),
+    }
+}
+
+fn check_cmake() -> Check {
+    match get_command_version("cmake", &["--version"]) {
+        Some(version) => Check::ok("cmake", version),
+        None => Check::warning(
+            "cmake",
+            "cmake was not found in PATH",
+            "cmake is installed along with ESP-IDF tools when using '--esp-idf-version'. Otherwise, install it with your package manager.",
+        ),
+    }
+}
+
+fn check_ninja() -> Check {
+    match get_command_version("ninja", &["--version"]) {
+        Some(version) => Check::ok("ninja", version),
+        None => Check::warning(
+            "ninja",
+            "ninja was not found in PATH",
+            "ninja is installed along with ESP-IDF tools when using '--esp-idf-version'. Otherwise, install it with your package manager.",
+        ),
+    }
+}
+
+fn check_rustup() -> Check {
+    match get_command_version("rustup", &["--version"]) {
+        Some(version) => Check::ok("rustup", version),
+        None => Check::error(
+            "rustup",
+            "rustup was not found or is not working",
+            "Install rustup from https://rustup.rs, or with 'espup install --install-rustup', and make sure that '<cargo_home>/bin' is in your PATH.",
+        ),
+    }
+}
+
+fn check_vs_build_tools() -> Check {
+    match get_vs_build_tools() {
+        Some(path) => Check::ok("VS Build Tools", path),
+        None => Check::error(
+            "VS Build Tools",
+            "The MSVC linker of the Visual Studio Build Tools was not found",
+            "Install the Visual Studio Build Tools with the 'Desktop development with C++' workload, or install with '--host-abi gnu' to use MinGW-w64 instead.",
+        ),
+    }
+}
+
+fn check_libc() -> Check {
+    if !is_musl() {
+        return Check::ok("libc", "glibc");
+    }
+    let result =
+        get_host_triple(None).and_then(|host_triple| host_triple::check_libc(&host_triple));
+    match result {
+        Ok(()) => Check::ok("libc", "musl, with a glibc compatibility layer"),
+        Err(_) => Check::error(
+            "libc",
+            "musl libc detected, the prebuilt tools are linked against glibc",
+            "Install a glibc compatibility layer, e.g. 'apk add gcompat' on Alpine Linux, or use a glibc based distribution or container.",
+        ),
+    }
+}
+
+fn check_system_packages(context: &Context) -> Check {
+    let manager = PackageManager::detect();
+    let missing = get_missing_packages(
+        context,
+        &[RequiredBy::EspIdf, RequiredBy::Espflash],
+        manager,
+    );
+    if missing.is_empty() {
+        return Check::ok("system packages", "All required packages are installed");
+    }
+    let names: Vec<&str> = missing.iter().map(|package| package.name).collect();
+    let message = format!("Missing packages: {}", names.join(", "));
+    match manager.and_then(|manager| get_install_command(&missing, manager)) {
+        Some(command) => Check::warning(
+            "system packages",
+            message,
+            format!("Run '{}' or use '--install-prereqs'.", command.join(" ")),
+        ),
+        None => Check::warning(
+            "system packages",
+            message,
+            "Install the missing packages with your system package manager.",
+        ),
+    }
+}
+
+/// Checks that the user belongs to the group owning the serial ports, `dialout` or `uucp`
+/// depending on the distribution, to flash and monitor the chips without root.
+#[cfg(target_os = "linux")]
+fn check_serial_group() -> Check {
+    let group = match std::fs::read_to_string("/etc/group")
+        .ok()
+        .and_then(|groups| get_serial_group(&groups))
+    {
+        Some(group) => group,
+        None => return Check::ok("serial group", "Serial ports are not restricted to a group"),
+    };
+    let groups = get_command_output("id", &["-Gn"]).unwrap_or_default();
+    if groups.split_whitespace().any(|name| name == group) {
+        Check::ok("serial group", format!("Member of '{group}'"))
+    } else {
+        Check::warning(
+            "serial group",
+            format!("Not a member of '{group}', serial ports cannot be opened to flash and monitor the chips"),
+            format!("Run 'sudo usermod -aG {group} $USER' and log in again, or install the udev rules with 'espup install --with-udev-rules'."),
+        )
+    }
+}
+
+/// Checks that the USB serial drivers of the chips and development boards are installed,
+/// as boards are not detected without them.
+#[cfg(windows)]
+fn check_usb_drivers() -> Check {
+    match get_missing_drivers() {
+        Some(missing) if missing.is_empty() => Check::ok(
+            "USB drivers",
+            "CP210x, CH34x and USB-JTAG drivers are installed",
+        ),
+        Some(missing) => Check::warning(
+            "USB drivers",
+            format!(
+                "Missing USB drivers: {}, the boards using them are not detected",
+                format_drivers(&missing)
+            ),
+            "Run 'espup install --with-usb-drivers'.",
+        ),
+        None => Check::warning(
+            "USB drivers",
+            "Unable to list the installed drivers with pnputil",
+            "Check that the boards are listed under 'Ports (COM & LPT)' in the Device Manager.",
+        ),
+    }
+}
+
+/// Parses the output of `reg query` for the `LongPathsEnabled` value, set to `0x1` when
+/// Windows lifts the 260 characters limit of paths.
+#[cfg(any(windows, test))]
+fn parse_long_paths_enabled(output: &str) -> bool {
+    output
+        .lines()
+        .filter(|line| line.contains("LongPathsEnabled"))
+        .any(|line| line.split_whitespace().last() == Some("0x1"))
+}
+
+/// Checks that long paths are enabled, in Windows and in git: ESP-IDF and its tools are
+/// extracted and checked out in deep directories, which exceed 260 characters under long
+/// home directories.
+#[cfg(windows)]
+pub fn check_long_paths() -> Vec<Check> {
+    let long_paths_enabled = get_command_output(
+        "reg",
+        &[
+            "query",
+            r"HKLM\SYSTEM\CurrentControlSet\Control\FileSystem",
+            "/v",
+            "LongPathsEnabled",
+        ],
+    )
+    .map_or(false, |output| parse_long_paths_enabled(&output));
+    let windows = if long_paths_enabled {
+        Check::ok("long paths", "Enabled")
+    } else {
+        Check::warning(
+            "long paths",
+            "Paths longer than 260 characters are not enabled, tools in deep directories may fail to run",
+            r"Run 'New-ItemProperty -Path HKLM:\SYSTEM\CurrentControlSet\Control\FileSystem -Name LongPathsEnabled -Value 1 -PropertyType DWORD -Force' as administrator, or install in a shorter location with '--install-dir', e.g. 'C:\esp'.",
+        )
+    };
+    let git = match get_command_output("git", &["config", "--get", "core.longpaths"]) {
+        Some(value) if value == "true" => Check::ok("git long paths", "core.longpaths is enabled"),
+        _ => Check::warning(
+            "git long paths",
+            "core.longpaths is not enabled, checking out ESP-IDF may fail",
+            "Run 'git config --global core.longpaths true'.",
+        ),
+    };
+    vec![windows, git]
+}
+
+/// Checks that the USB devices of Windows can be attached to WSL2 with usbipd, to flash and
+/// monitor the chips. WSL1 maps the serial ports instead.
+fn check_usbipd(wsl: Wsl) -> Check {
+    if wsl == Wsl::Wsl1 {
+        return Check::ok(
+            "usbipd",
+            "Not needed on WSL1, the COM<N> ports of Windows are available as /dev/ttyS<N>",
+        );
+    }
+    match get_command_version("usbipd.exe", &["--version"]) {
+        Some(version) => Check::ok("usbipd", version),
+        None => Check::warning(
+            "usbipd",
+            "usbipd-win was not found, USB devices cannot be attached to WSL2 to flash and monitor",
+            "Install it on Windows with 'winget install usbipd' and attach the device with 'usbipd attach --wsl --busid <BUSID>', see https://learn.microsoft.com/en-us/windows/wsl/connect-usb.",
+        ),
+    }
+}
+
+/// Returns the closest existing ancestor of a path.
+pub fn get_existing_ancestor(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|ancestor| ancestor.exists())
+        .map(Path::to_path_buf)
+}
+
+fn check_disk_space(context: &Context) -> Check {
+    let tools_path = &context.tools_path;
+    let existing_path = match get_existing_ancestor(tools_path) {
+        Some(path) => path,
+        None => {
+            return Check::warning(
+                "disk space",
+                format!(
+                    "Unable to find the filesystem of '{}'",
+                    tools_path.display()
+                ),
+                "Make sure that the tools path is valid.",
+            )
+        }
+    };
+    match fs2::available_space(&existing_path) {
+        Ok(available) if available >= RECOMMENDED_DISK_SPACE => Check::ok(
+            "disk space",
+            format!("{} MiB available", available / (1024 * 1024)),
+        ),
+        Ok(available) => Check::warning(
+            "disk space",
+            format!(
+                "Only {} MiB available in '{}', {} MiB are recommended",
+                available / (1024 * 1024),
+                existing_path.display(),
+                RECOMMENDED_DISK_SPACE / (1024 * 1024)
+            ),
+            "Free some disk space or set IDF_TOOLS_PATH to a location with more space.",
+        ),
+        Err(e) => Check::warning(
+            "disk space",
+            format!("Unable to query available space: {e}"),
+            "Make sure that the tools path is accessible.",
+        ),
+    }
+}
+
+fn check_esp_idf_environment() -> Check {
+    let conflicts = get_conflicting_variables();
+    if conflicts.is_empty() {
+        return Check::ok("ESP-IDF environment", "Not active");
+    }
+    Check::warning(
+        "ESP-IDF environment",
+        format!(
+            "Already active: {}",
+            conflicts
+                .iter()
+                .map(|(key, value)| format!("{key}='{value}'"))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        "An ESP-IDF environment, e.g. from ESP-IDF's 'export' script, would mix with the installed toolchains: open a new terminal without sourcing it, or run 'espup install --override-env' to generate an export file that overrides it.",
+    )
+}
+
+fn check_clang_environment(context: &Context) -> Check {
+    let conflicts = get_conflicting_clang_variables(context);
+    if conflicts.is_empty() {
+        return Check::ok("clang environment", "No other LLVM selected");
+    }
+    Check::warning(
+        "clang environment",
+        format!(
+            "Set to another LLVM: {}",
+            conflicts
+                .iter()
+                .map(|(key, value)| format!("{key}='{value}'"))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        "bindgen would use this clang instead of the Xtensa one: unset these variables wherever they are set after sourcing the export file, e.g. system-wide or in a shell profile.",
+    )
+}
+
+/// Whether a file name is the one of a libclang library, e.g. `libclang.so.15`.
+fn is_libclang(file_name: &str) -> bool {
+    file_name == "libclang.dll"
+        || file_name == "libclang.dylib"
+        || file_name.starts_with("libclang.so")
+}
+
+/// Finds the libclang library of a `LIBCLANG_PATH`, which is either the library or the
+/// directory containing it, as bindgen accepts both. The unversioned library is preferred.
+pub fn find_libclang(libclang_path: &Path) -> Option<PathBuf> {
+    if libclang_path.is_file() {
+        return Some(libclang_path.to_path_buf());
+    }
+    let mut libraries: Vec<PathBuf> = read_dir(libclang_path)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|file_name| file_name.to_str())
+                .map_or(false, is_libclang)
+        })
+        .collect();
+    libraries.sort();
+    libraries.into_iter().next()
+}
+
+/// Loads a library and its dependencies, as bindgen does, then unloads it.
+#[cfg(unix)]
+fn load_library(library: &Path) -> Result<(), String> {
+    use std::{
+        ffi::{CStr, CString},
+        os::unix::ffi::OsStrExt,
+    };
+
+    let path = CString::new(library.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+    // SAFETY: the path is a valid NUL terminated string.
+    let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if handle.is_null() {
+        // SAFETY: dlerror returns a NUL terminated string describing the last error, if any.
+        let error = unsafe { libc::dlerror() };
+        if error.is_null() {
+            return Err("unknown error".to_string());
+        }
+        return Err(unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned());
+    }
+    // SAFETY: the handle was returned by dlopen and is not used afterwards.
+    unsafe { libc::dlclose(handle) };
+    Ok(())
+}
+
+/// Checks that the library exists. Its dependencies are installed along with it, in the
+/// same directory.
+#[cfg(windows)]
+fn load_library(library: &Path) -> Result<(), String> {
+    if library.is_file() {
+        Ok(())
+    } else {
+        Err("not found".to_string())
+    }
+}
+
+/// Checks that the libclang exported in the export file loads, e.g. that the libraries it
+/// depends on are installed.
+fn check_libclang(context: &Context) -> Check {
+    let env = context.env.as_deref();
+    let libclang_path = get_export_file(
+        env,
+        Config::load(env).ok().and_then(|config| config.export_file),
+    )
+    .ok()
+    .and_then(|export_file| get_environment(&export_file).ok())
+    .and_then(|environment| {
+        environment
+            .variables
+            .into_iter()
+            .find(|(key, _)| key == "LIBCLANG_PATH")
+            .map(|(_, value)| PathBuf::from(value))
+    });
+    let libclang_path = match libclang_path {
+        Some(libclang_path) => libclang_path,
+        None => return Check::ok("libclang", "Not exported"),
+    };
+    let library = match find_libclang(&libclang_path) {
+        Some(library) => library,
+        None => {
+            return Check::error(
+                "libclang",
+                format!("No libclang found in '{}'", libclang_path.display()),
+                "Reinstall the Xtensa LLVM with 'espup install'.",
+            )
+        }
+    };
+    match load_library(&library) {
+        Ok(()) => Check::ok("libclang", format!("'{}' loads", library.display())),
+        Err(e) => Check::error(
+            "libclang",
+            format!("Failed to load '{}': {}", library.display(), e),
+            "Install the libraries it depends on, e.g. libxml2 or zlib, or reinstall the Xtensa LLVM with 'espup install'.",
+        ),
+    }
+}
+
+/// Checks that the export file still matches the installation, and that the shell did not
+/// source an outdated one, with the header recorded when it was generated.
+fn check_export_file(context: &Context) -> Check {
+    let env = context.env.as_deref();
+    let export_file = match get_export_file(
+        env,
+        Config::load(env).ok().and_then(|config| config.export_file),
+    ) {
+        Ok(export_file) => export_file,
+        Err(_) => return Check::ok("export file", "Not generated"),
+    };
+    let contents = match read_to_string(&export_file) {
+        Ok(contents) => contents,
+        Err(_) => return Check::ok("export file", "Not generated"),
+    };
+    let header = match parse_header(&contents) {
+        Some(header) => header,
+        None => {
+            return Check::warning(
+                "export file",
+                format!("'{}' was generated by a previous espup version, without a header", export_file.display()),
+                "Regenerate it with 'espup install' or 'espup update', which reuse the installed components.",
+            )
+        }
+    };
+    let mismatches = get_mismatches(
+        &header,
+        &get_installed_components(context, Config::load(env).ok().as_ref()),
+    );
+    if !mismatches.is_empty() {
+        return Check::warning(
+            "export file",
+            format!(
+                "'{}' no longer matches the installation: {}",
+                export_file.display(),
+                mismatches.join(", ")
+            ),
+            "Regenerate it with 'espup install' or 'espup update', and source it again.",
+        );
+    }
+    if hash_exports(&contents) != header.sha256 {
+        return Check::warning(
+            "export file",
+            format!("'{}' was modified since espup {} generated it", export_file.display(), header.generated_by),
+            "Regenerate it with 'espup install' or 'espup update', and put the customizations in a separate file.",
+        );
+    }
+    match env::var(EXPORTS_HASH_VARIABLE) {
+        Ok(sourced) if sourced != header.sha256 => Check::warning(
+            "export file",
+            "This shell sourced an outdated export file",
+            format!(
+                "Source '{}' again, or open a new terminal.",
+                export_file.display()
+            ),
+        ),
+        _ => Check::ok(
+            "export file",
+            format!(
+                "Generated by espup {}, matches the installation",
+                header.generated_by
+            ),
+        ),
+    }
+}
+
+fn check_path(context: &Context) -> Check {
+    let conflicts: Vec<String> = env::split_paths(&env::var_os("PATH").unwrap_or_default())
+        .filter(|path| !path.starts_with(&context.tools_path))
+        // WSL appends the Windows PATH, whose tools are not used from Linux shells.
+        .filter(|path| !wsl::is_windows_path(path))
+        .map(|path| path.display().to_string())
+        .filter(|path| {
+            path.contains("xtensa-esp")
+                || path.contains("riscv32-esp-elf")
+                || path.contains("esp-idf")
+        })
+        .collect();
+    if conflicts.is_empty() {
+        Check::ok("PATH", "No conflicting entries found")
+    } else {
+        Check::warning(
+            "PATH",
+            format!(
+                "Found Espressif tools outside of '{}': {}",
+                context.tools_path.display(),
+                conflicts.join(", ")
+            ),
+            "Remove these entries from your PATH to avoid using mismatched toolchains.",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doctor::{
+        find_libclang, get_min_python_version, parse_long_paths_enabled, parse_python_version,
+    };
+    use std::fs::write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_python_version() {
+        assert_eq!(parse_python_version("Python 3.10.6"), Some((3, 10, 6)));
+        assert_eq!(parse_python_version("Python 3.8"), Some((3, 8, 0)));
+        assert_eq!(parse_python_version("Python 2.7.18\n"), Some((2, 7, 18)));
+        assert_eq!(parse_python_version("Python 3.12.0rc1"), Some((3, 12, 0)));
+        assert!(parse_python_version("python3: command not found").is_none());
+        assert!(parse_python_version("Python x.y").is_none());
+    }
+
+    #[test]
+    fn test_get_min_python_version() {
+        assert_eq!(get_min_python_version(Some("v4.4")), (3, 6));
+        assert_eq!(get_min_python_version(Some("5.0")), (3, 7));
+        assert_eq!(get_min_python_version(Some("tag:v5.1.2")), (3, 7));
+        assert_eq!(get_min_python_version(Some("release/v5.2")), (3, 8));
+        assert_eq!(get_min_python_version(Some("branch:release/v4.3")), (3, 6));
+        assert_eq!(get_min_python_version(Some("master")), (3, 8));
+        assert_eq!(get_min_python_version(None), (3, 8));
+    }
+
+    #[test]
+    fn test_parse_long_paths_enabled() {
+        let output = r"HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Control\FileSystem
+    LongPathsEnabled    REG_DWORD    0x1";
+        assert!(parse_long_paths_enabled(output));
+        assert!(!parse_long_paths_enabled(&output.replace("0x1", "0x0")));
+        assert!(!parse_long_paths_enabled(""));
+    }
+
+    #[test]
+    fn test_find_libclang() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(find_libclang(temp_dir.path()).is_none());
+        write(temp_dir.path().join("libclang.so.15"), "").unwrap();
+        write(temp_dir.path().join("libclang-cpp.so.15"), "").unwrap();
+        write(temp_dir.path().join("libclang.so"), "").unwrap();
+        assert_eq!(
+            find_libclang(temp_dir.path()).unwrap(),
+            temp_dir.path().join("libclang.so")
+        );
+        assert_eq!(
+            find_libclang(&temp_dir.path().join("libclang.so.15")).unwrap(),
+            temp_dir.path().join("libclang.so.15")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_load_library() {
+        use crate::doctor::load_library;
+
+        let temp_dir = TempDir::new().unwrap();
+        let library = temp_dir.path().join("libclang.so");
+        write(&library, "not a library").unwrap();
+        assert!(load_library(&library).is_err());
+    }
+}
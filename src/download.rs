@@ -0,0 +1,205 @@
+//! Transports of the downloaded files: HTTP by default, a local directory for offline
+//! installs or any other one, e.g. an artifact proxy, set in the
+//! [`Context`](crate::context::Context) of the installation.
+
+use crate::{
+    error::Error,
+    http::{Http, LowSpeedReader, RateLimitedReader},
+};
+use std::{fmt, fs::File, io::Read, path::PathBuf, sync::Arc};
+
+/// Contents of a download, as they are read.
+pub struct Download {
+    /// Reader of the contents.
+    pub reader: Box<dyn Read>,
+    /// Size of the contents, in bytes, when known.
+    pub size: Option<u64>,
+}
+
+/// Transport of the downloaded files.
+pub trait Downloader: fmt::Debug + Send + Sync {
+    /// Opens the contents of a URL.
+    fn open(&self, url: &str) -> Result<Download, Error>;
+
+    /// Gets the size of the contents of a URL, in bytes, when it is known without
+    /// downloading them.
+    fn size(&self, _url: &str) -> Option<u64> {
+        None
+    }
+}
+
+/// Downloads over HTTP, keeping the transfer rate between the limits. `file://` URLs, e.g.
+/// of the local artifacts, are read from the disk.
+#[derive(Debug, Default, Clone)]
+pub struct HttpDownloader {
+    http: Arc<Http>,
+}
+
+impl HttpDownloader {
+    /// Creates a downloader using the given HTTP client.
+    pub fn new(http: Arc<Http>) -> Self {
+        Self { http }
+    }
+}
+
+impl Downloader for HttpDownloader {
+    fn open(&self, url: &str) -> Result<Download, Error> {
+        if url.starts_with("file://") {
+            return FileDownloader::new(PathBuf::new()).open(url);
+        }
+        let response = self.http.client()?.get(url).send()?.error_for_status()?;
+        let size = response.content_length();
+        Ok(Download {
+            reader: Box::new(RateLimitedReader::new(
+                LowSpeedReader::new(response, self.http.low_speed_limit()),
+                self.http.limit_rate(),
+            )),
+            size,
+        })
+    }
+
+    fn size(&self, url: &str) -> Option<u64> {
+        if url.starts_with("file://") {
+            return FileDownloader::new(PathBuf::new()).size(url);
+        }
+        self.http
+            .client()
+            .ok()?
+            .head(url)
+            .send()
+            .ok()
+            .filter(|response| response.status().is_success())
+            .and_then(|response| response.content_length())
+    }
+}
+
+/// Reads the downloaded files from a local directory replicating the paths of their URLs,
+/// e.g. `<root>/espressif/crosstool-NG/releases/download/...` for a GitHub release asset,
+/// to install without network access. `file://` URLs are read as they are.
+#[derive(Debug, Clone)]
+pub struct FileDownloader {
+    root: PathBuf,
+}
+
+impl FileDownloader {
+    /// Creates a downloader reading the files from `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Gets the local path of a URL.
+    fn get_path(&self, url: &str) -> PathBuf {
+        if let Some(path) = url.strip_prefix("file://") {
+            return PathBuf::from(path);
+        }
+        let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+        let path = without_scheme
+            .split_once('/')
+            .map_or("", |(_, path)| path)
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default();
+        self.root.join(path)
+    }
+}
+
+impl Downloader for FileDownloader {
+    fn open(&self, url: &str) -> Result<Download, Error> {
+        let path = self.get_path(url);
+        let file =
+            File::open(&path).map_err(|_| Error::MissingDownload(path.display().to_string()))?;
+        Ok(Download {
+            size: file.metadata().ok().map(|metadata| metadata.len()),
+            reader: Box::new(file),
+        })
+    }
+
+    fn size(&self, url: &str) -> Option<u64> {
+        self.get_path(url)
+            .metadata()
+            .ok()
+            .map(|metadata| metadata.len())
+    }
+}
+
+/// Serves files from memory, recording the URLs opened, to test the installation without
+/// network access.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MockDownloader {
+    files: std::collections::HashMap<String, Vec<u8>>,
+    requests: std::sync::Mutex<Vec<String>>,
+}
+
+#[cfg(test)]
+impl MockDownloader {
+    /// Serves `contents` at `url`.
+    pub fn with_file(mut self, url: &str, contents: Vec<u8>) -> Self {
+        self.files.insert(url.to_string(), contents);
+        self
+    }
+
+    /// Gets the URLs opened so far.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl Downloader for MockDownloader {
+    fn open(&self, url: &str) -> Result<Download, Error> {
+        self.requests.lock().unwrap().push(url.to_string());
+        let contents = self
+            .files
+            .get(url)
+            .cloned()
+            .ok_or_else(|| Error::MissingDownload(url.to_string()))?;
+        Ok(Download {
+            size: Some(contents.len() as u64),
+            reader: Box::new(std::io::Cursor::new(contents)),
+        })
+    }
+
+    fn size(&self, url: &str) -> Option<u64> {
+        self.files.get(url).map(|contents| contents.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::download::{Downloader, FileDownloader, MockDownloader};
+    use std::{fs::write, io::Read, path::Path};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_file_downloader() {
+        let downloader = FileDownloader::new("/srv/espup");
+        assert_eq!(
+            downloader.get_path("https://github.com/espressif/crosstool-NG/releases/download/esp-12.2.0_20230208/riscv32-esp-elf.tar.xz?raw=true"),
+            Path::new("/srv/espup/espressif/crosstool-NG/releases/download/esp-12.2.0_20230208/riscv32-esp-elf.tar.xz")
+        );
+        assert_eq!(
+            downloader.get_path("file:///tmp/rust.tar.xz"),
+            Path::new("/tmp/rust.tar.xz")
+        );
+
+        let temp_dir = TempDir::new().unwrap();
+        write(temp_dir.path().join("tool.tar.gz"), "tool").unwrap();
+        let downloader = FileDownloader::new(temp_dir.path());
+        let mut download = downloader.open("https://example.com/tool.tar.gz").unwrap();
+        assert_eq!(download.size, Some(4));
+        let mut contents = String::new();
+        download.reader.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "tool");
+        assert!(downloader.open("https://example.com/missing.zip").is_err());
+    }
+
+    #[test]
+    fn test_mock_downloader() {
+        let downloader =
+            MockDownloader::default().with_file("https://example.com/a", b"a".to_vec());
+        assert_eq!(downloader.size("https://example.com/a"), Some(1));
+        assert!(downloader.open("https://example.com/b").is_err());
+        assert_eq!(downloader.requests(), vec!["https://example.com/b"]);
+    }
+}
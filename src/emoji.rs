@@ -1,10 +1,64 @@
-use console::Emoji;
-
-pub static ERROR: Emoji<'_, '_> = Emoji("⛔ ", "");
-pub static CHECK: Emoji<'_, '_> = Emoji("✅ ", "");
-pub static WARN: Emoji<'_, '_> = Emoji("⚠️ ", "");
-pub static WRENCH: Emoji<'_, '_> = Emoji("🔧 ", "");
-pub static DOWNLOAD: Emoji<'_, '_> = Emoji("📥 ", "");
-pub static INFO: Emoji<'_, '_> = Emoji("💡 ", "");
-pub static DISC: Emoji<'_, '_> = Emoji("💽 ", "");
-pub static DEBUG: Emoji<'_, '_> = Emoji("🐞 ", "");
+//! Prefixes of the output messages: emojis or, when they cannot be displayed, plain ASCII.
+
+use once_cell::sync::Lazy;
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Whether emojis were disabled, e.g. with `--no-emoji`.
+static DISABLED: AtomicBool = AtomicBool::new(false);
+/// Whether the terminal can display emojis: it uses UTF-8 and, on Windows, is not cmd.
+static TERMINAL_WANTS_EMOJI: Lazy<bool> =
+    Lazy::new(|| console::Term::stderr().features().wants_emoji());
+
+/// A message prefix, with its plain ASCII fallback.
+#[derive(Debug, Clone, Copy)]
+pub struct Emoji(&'static str, &'static str);
+
+impl Emoji {
+    /// Gets the prefix: the emoji when `enabled`, the ASCII fallback otherwise.
+    fn get(&self, enabled: bool) -> &'static str {
+        if enabled {
+            self.0
+        } else {
+            self.1
+        }
+    }
+}
+
+impl fmt::Display for Emoji {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.get(is_enabled()))
+    }
+}
+
+/// Disables emojis, using the ASCII fallbacks instead.
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether emojis are displayed.
+pub fn is_enabled() -> bool {
+    !DISABLED.load(Ordering::Relaxed) && *TERMINAL_WANTS_EMOJI
+}
+
+pub static ERROR: Emoji = Emoji("⛔ ", "[x] ");
+pub static CHECK: Emoji = Emoji("✅ ", "[ok] ");
+pub static WARN: Emoji = Emoji("⚠️ ", "[!] ");
+pub static WRENCH: Emoji = Emoji("🔧 ", "[*] ");
+pub static DOWNLOAD: Emoji = Emoji("📥 ", "[v] ");
+pub static INFO: Emoji = Emoji("💡 ", "[i] ");
+pub static DISC: Emoji = Emoji("💽 ", "[>] ");
+pub static DEBUG: Emoji = Emoji("🐞 ", "[d] ");
+
+#[cfg(test)]
+mod tests {
+    use crate::emoji::ERROR;
+
+    #[test]
+    fn test_get() {
+        assert_eq!(ERROR.get(true), "⛔ ");
+        assert_eq!(ERROR.get(false), "[x] ");
+    }
+}
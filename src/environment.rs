@@ -0,0 +1,954 @@
+//! Environment of the installation, printed by `espup env` for the shells, e.g. with
+//! `eval "$(espup env)"`, or written to the `rust-analyzer.server.extraEnv` setting of
+//! Visual Studio Code, so that rust-analyzer uses the Xtensa toolchain and finds libclang
+//! without sourcing the export file first.
+
+use crate::{config::Config, context::Context, emoji, error::Error, install::get_export_file};
+use dirs::home_dir;
+use log::info;
+use serde::Serialize;
+use serde_json::{ser::PrettyFormatter, Map, Serializer, Value};
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fs::{create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+use strum_macros::{Display, EnumString};
+
+/// Variables set by the export script of ESP-IDF, which select the ESP-IDF environment.
+pub const ESP_IDF_VARIABLES: [&str; 5] = [
+    "IDF_PATH",
+    "IDF_TOOLS_PATH",
+    "IDF_PYTHON_ENV_PATH",
+    "ESP_IDF_VERSION",
+    "IDF_DEACTIVATE_FILE_PATH",
+];
+/// Variables selecting the libclang and clang used by bindgen, exported for the Xtensa LLVM.
+pub const CLANG_VARIABLES: [&str; 2] = ["LIBCLANG_PATH", "CLANG_PATH"];
+/// Variable set in the environment of the commands run by `espup run` and `espup shell`,
+/// e.g. to show the environment in the prompt.
+pub const ACTIVE_VARIABLE: &str = "ESPUP_ACTIVE";
+/// Profiles of the shells, relative to the home directory, where the export file is
+/// usually sourced.
+const SHELL_PROFILES: [&str; 8] = [
+    ".profile",
+    ".bashrc",
+    ".bash_profile",
+    ".zshrc",
+    ".zprofile",
+    ".config/fish/config.fish",
+    "Documents/PowerShell/Microsoft.PowerShell_profile.ps1",
+    "Documents/WindowsPowerShell/Microsoft.PowerShell_profile.ps1",
+];
+/// Marker of the ESP-IDF compatible export scripts generated by espup, which are the only
+/// ones overwritten.
+const IDF_EXPORT_SCRIPT_MARKER: &str = "Generated by espup";
+/// Names of the ESP-IDF compatible export scripts, as in the root of ESP-IDF.
+#[cfg(windows)]
+const IDF_EXPORT_SCRIPTS: [&str; 2] = ["export.bat", "export.ps1"];
+#[cfg(unix)]
+const IDF_EXPORT_SCRIPTS: [&str; 1] = ["export.sh"];
+/// Setting of the environment variables of the rust-analyzer server.
+const EXTRA_ENV_SETTING: &str = "rust-analyzer.server.extraEnv";
+/// Separator of the `PATH` entries.
+#[cfg(windows)]
+const PATH_SEPARATOR: &str = ";";
+#[cfg(unix)]
+const PATH_SEPARATOR: &str = ":";
+
+/// Environment variables set by an export file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Environment {
+    /// Variables, other than `PATH`, in the order they are exported.
+    pub variables: Vec<(String, String)>,
+    /// Entries added to `PATH`.
+    pub path: Vec<String>,
+}
+
+/// Shell that the exports are printed for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(ascii_case_insensitive)]
+pub enum Shell {
+    #[strum(serialize = "bash", serialize = "zsh", serialize = "sh")]
+    Bash,
+    #[strum(serialize = "fish")]
+    Fish,
+    #[strum(serialize = "powershell", serialize = "pwsh")]
+    PowerShell,
+    #[strum(serialize = "cmd")]
+    Cmd,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        if cfg!(windows) {
+            Self::PowerShell
+        } else {
+            Self::Bash
+        }
+    }
+}
+
+impl Shell {
+    /// Detects the shell running espup on Windows, PowerShell or cmd, as PowerShell adds
+    /// its own module directories to `PSModulePath`. Other platforms use the default one.
+    pub fn detect() -> Self {
+        if !cfg!(windows) {
+            return Self::default();
+        }
+        let module_paths = env::var("PSModulePath").unwrap_or_default();
+        if module_paths
+            .split(';')
+            .filter(|path| !path.is_empty())
+            .count()
+            >= 3
+        {
+            Self::PowerShell
+        } else {
+            Self::Cmd
+        }
+    }
+
+    /// Quotes a value, escaping the characters that the shell expands in double quotes.
+    fn quote(&self, value: &str) -> String {
+        let escape: &[char] = match self {
+            Self::Bash => &['\\', '"', '$', '`'],
+            Self::Fish => &['\\', '"', '$'],
+            Self::PowerShell => &['`', '"', '$'],
+            Self::Cmd => &['%'],
+        };
+        let escape_char = match self {
+            Self::PowerShell => '`',
+            Self::Cmd => '%',
+            _ => '\\',
+        };
+        let mut quoted = String::from('"');
+        for c in value.chars() {
+            if escape.contains(&c) {
+                quoted.push(escape_char);
+            }
+            quoted.push(c);
+        }
+        quoted.push('"');
+        quoted
+    }
+
+    /// Formats the exports of an environment, with the `PATH` entries prepended to the
+    /// current one.
+    pub fn format_exports(&self, environment: &Environment) -> String {
+        let mut lines: Vec<String> = environment
+            .variables
+            .iter()
+            .map(|(key, value)| match self {
+                Self::Bash => format!("export {key}={}", self.quote(value)),
+                Self::Fish => format!("set -gx {key} {}", self.quote(value)),
+                Self::PowerShell => format!("$Env:{key} = {}", self.quote(value)),
+                // The quotes around the assignment are not part of the value.
+                Self::Cmd => format!("set {}", self.quote(&format!("{key}={value}"))),
+            })
+            .collect();
+        if !environment.path.is_empty() {
+            let quoted_entries: Vec<String> = environment
+                .path
+                .iter()
+                .map(|entry| self.quote(entry))
+                .collect();
+            lines.push(match self {
+                Self::Bash => format!(
+                    "export PATH={}:\"$PATH\"",
+                    self.quote(&environment.path.join(":"))
+                ),
+                Self::Fish => format!("set -gx PATH {} $PATH", quoted_entries.join(" ")),
+                Self::PowerShell => format!(
+                    "$Env:PATH = {} + $Env:PATH",
+                    self.quote(&format!("{};", environment.path.join(";")))
+                ),
+                Self::Cmd => format!(
+                    "set \"PATH={};%PATH%\"",
+                    environment.path.join(";").replace('%', "%%")
+                ),
+            });
+        }
+        lines.join("\n")
+    }
+}
+
+/// Removes the quotes around a value.
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Parses an `export KEY=VALUE` line on Unix or a `$Env:KEY="VALUE"` line on Windows,
+/// returning the key, the unquoted value and the separator of the `PATH` entries.
+fn parse_assignment(line: &str) -> Option<(&str, &str, char)> {
+    let line = line.trim();
+    let (assignment, separator) = match (line.strip_prefix("export "), line.strip_prefix("$Env:")) {
+        (Some(assignment), _) => (assignment, ':'),
+        (_, Some(assignment)) => (assignment, ';'),
+        _ => return None,
+    };
+    let (key, value) = assignment.split_once('=')?;
+    Some((
+        key.trim().trim_end_matches('+').trim_end(),
+        unquote(value),
+        separator,
+    ))
+}
+
+/// Whether an entry of a `PATH` value refers to the current `PATH`.
+fn is_current_path(entry: &str) -> bool {
+    entry == "$PATH" || entry == "$Env:PATH"
+}
+
+/// Parses the variables exported by an export file: `export KEY=VALUE` lines on Unix and
+/// `$Env:KEY="VALUE"` lines on Windows. Other lines, e.g. the sourced export script of
+/// ESP-IDF on Windows, are skipped.
+pub fn parse_exports(exports: &str) -> Environment {
+    let mut environment = Environment::default();
+    for line in exports.lines() {
+        let (key, value, separator) = match parse_assignment(line) {
+            Some(assignment) => assignment,
+            None => continue,
+        };
+        if key.eq_ignore_ascii_case("PATH") {
+            environment.path.extend(
+                value
+                    .split(separator)
+                    .filter(|entry| !entry.is_empty() && !is_current_path(entry))
+                    .map(str::to_string),
+            );
+        } else {
+            environment
+                .variables
+                .retain(|(existing_key, _)| existing_key != key);
+            environment
+                .variables
+                .push((key.to_string(), value.to_string()));
+        }
+    }
+    environment
+}
+
+/// Normalizes the exports of an installation, so that repeated installs do not grow the
+/// export file: duplicated lines are removed and the `PATH` assignments are merged into a
+/// single one, last, without duplicated entries nor directories that no longer exist. The
+/// entries keep the precedence they had in the original assignments.
+pub fn normalize_exports(exports: &[String]) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut path: Vec<&str> = Vec::new();
+    for line in exports {
+        match parse_assignment(line) {
+            Some((key, value, separator)) if key.eq_ignore_ascii_case("PATH") => {
+                let appends = line.contains("+=");
+                let mut entries = value.split(separator).filter(|entry| !entry.is_empty());
+                let mut new_path: Vec<&str> = entries
+                    .by_ref()
+                    .take_while(|entry| !is_current_path(entry))
+                    .collect();
+                if appends {
+                    new_path.splice(0..0, path.drain(..));
+                } else if value.split(separator).any(is_current_path) {
+                    new_path.append(&mut path);
+                }
+                new_path.extend(entries);
+                path = new_path;
+            }
+            _ => {
+                if !lines.contains(line) {
+                    lines.push(line.clone());
+                }
+            }
+        }
+    }
+    let mut entries: Vec<&str> = Vec::new();
+    for entry in path {
+        if !entries.contains(&entry) && Path::new(entry).is_dir() {
+            entries.push(entry);
+        }
+    }
+    if !entries.is_empty() {
+        #[cfg(windows)]
+        lines.push(format!("$Env:PATH=\"{};$Env:PATH\"", entries.join(";")));
+        #[cfg(unix)]
+        lines.push(format!("export PATH=\"{}:$PATH\"", entries.join(":")));
+    }
+    lines
+}
+
+/// Gets the ESP-IDF variables already set in the current shell, e.g. by a previously
+/// sourced `export.sh`, which would mix with the environment of the installation.
+pub fn get_conflicting_variables() -> Vec<(String, String)> {
+    ESP_IDF_VARIABLES
+        .iter()
+        .filter_map(|key| Some((key.to_string(), env::var(key).ok()?)))
+        .collect()
+}
+
+/// Selects the clang variables that point outside of the tools directory, e.g. to the LLVM
+/// of the system, which would shadow the Xtensa one.
+pub fn select_conflicting_clang_variables(
+    variables: &[(String, String)],
+    tools_path: &Path,
+) -> Vec<(String, String)> {
+    variables
+        .iter()
+        .filter(|(key, value)| {
+            CLANG_VARIABLES.contains(&key.as_str()) && !Path::new(value).starts_with(tools_path)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Gets the clang variables set in the current shell to another LLVM, e.g. system-wide by a
+/// previous LLVM installation. Where they are set after the export file is sourced, bindgen
+/// loads that libclang instead of the Xtensa one.
+pub fn get_conflicting_clang_variables(context: &Context) -> Vec<(String, String)> {
+    let variables: Vec<(String, String)> = CLANG_VARIABLES
+        .iter()
+        .filter_map(|key| Some((key.to_string(), env::var(key).ok()?)))
+        .collect();
+    select_conflicting_clang_variables(&variables, &context.tools_path)
+}
+
+/// Gets the exports overriding the conflicting variables: those that the installation
+/// exports already override them, the others are unset.
+pub fn get_override_exports(conflicts: &[(String, String)], exports: &[String]) -> Vec<String> {
+    let environment = parse_exports(&exports.join("\n"));
+    conflicts
+        .iter()
+        .filter(|(key, _)| {
+            !environment
+                .variables
+                .iter()
+                .any(|(exported_key, _)| exported_key == key)
+        })
+        .map(|(key, _)| {
+            #[cfg(windows)]
+            return format!("Remove-Item Env:{key} -ErrorAction SilentlyContinue");
+            #[cfg(unix)]
+            return format!("unset {key}");
+        })
+        .collect()
+}
+
+/// Whether a line of a shell profile sources the export file, e.g. `. $HOME/export-esp.sh`
+/// or `& "$env:USERPROFILE\export-esp.ps1"`.
+fn sources_export_file(line: &str, export_file: &Path, home_dir: &Path) -> bool {
+    let line = line.trim();
+    let sourced = ["source ", ". ", "& "]
+        .iter()
+        .find_map(|command| line.strip_prefix(command))
+        .unwrap_or(line)
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'');
+    let home = home_dir.display().to_string();
+    let sourced = ["~", "$HOME", "${HOME}", "%USERPROFILE%", "$env:USERPROFILE"]
+        .iter()
+        .find_map(|variable| {
+            sourced
+                .strip_prefix(variable)
+                .filter(|rest| rest.starts_with(['/', '\\']))
+                .map(|rest| format!("{home}{rest}"))
+        })
+        .unwrap_or_else(|| sourced.to_string());
+    Path::new(&sourced.replace('\\', "/"))
+        == Path::new(&export_file.display().to_string().replace('\\', "/"))
+}
+
+/// Removes the lines sourcing the export file from the contents of a shell profile,
+/// returning `None` if there are none.
+fn strip_sourcing(contents: &str, export_file: &Path, home_dir: &Path) -> Option<String> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let kept: Vec<&str> = lines
+        .iter()
+        .copied()
+        .filter(|line| !sources_export_file(line, export_file, home_dir))
+        .collect();
+    if kept.len() == lines.len() {
+        return None;
+    }
+    let mut stripped = kept.join("\n");
+    if contents.ends_with('\n') {
+        stripped.push('\n');
+    }
+    Some(stripped)
+}
+
+/// Gets the shell profiles that source the export file.
+pub fn get_sourcing_profiles(export_file: &Path) -> Vec<PathBuf> {
+    let home_dir = match home_dir() {
+        Some(home_dir) => home_dir,
+        None => return Vec::new(),
+    };
+    SHELL_PROFILES
+        .iter()
+        .map(|profile| home_dir.join(profile))
+        .filter(|profile| {
+            read_to_string(profile).map_or(false, |contents| {
+                strip_sourcing(&contents, export_file, &home_dir).is_some()
+            })
+        })
+        .collect()
+}
+
+/// Removes the lines sourcing the export file from the shell profiles, restoring them to
+/// their state before the installation. Returns the modified profiles.
+pub fn strip_shell_profiles(export_file: &Path) -> Result<Vec<PathBuf>, Error> {
+    let home_dir = match home_dir() {
+        Some(home_dir) => home_dir,
+        None => return Ok(Vec::new()),
+    };
+    let profiles = get_sourcing_profiles(export_file);
+    for profile in &profiles {
+        let contents = read_to_string(profile)?;
+        if let Some(stripped) = strip_sourcing(&contents, export_file, &home_dir) {
+            write(profile, stripped)
+                .map_err(|_| Error::FailedToWrite(profile.display().to_string()))?;
+        }
+    }
+    Ok(profiles)
+}
+
+/// Reads the environment of the export file of the installation.
+pub fn get_environment(export_file: &Path) -> Result<Environment, Error> {
+    let exports = read_to_string(export_file)
+        .map_err(|_| Error::MissingExportFile(export_file.display().to_string()))?;
+    Ok(parse_exports(&exports))
+}
+
+/// Reads the environment of the export file recorded in the configuration file.
+pub fn get_installation_environment(context: &mut Context) -> Result<Environment, Error> {
+    let config = context.load_config()?;
+    get_environment(&get_export_file(
+        context.env.as_deref(),
+        config.export_file,
+    )?)
+}
+
+/// Prints the exports of the installation for `shell`, the running one when `None`, or
+/// writes them to the VS Code settings of the project in `vscode`.
+pub fn print_env(
+    context: &mut Context,
+    shell: Option<Shell>,
+    vscode: Option<&Path>,
+) -> Result<(), Error> {
+    let config = context.load_config()?;
+    let environment = get_environment(&get_export_file(
+        context.env.as_deref(),
+        config.export_file.clone(),
+    )?)?;
+    if let Some(project_dir) = vscode {
+        let settings_path =
+            write_vscode_settings(project_dir, get_extra_env(&config, &environment))?;
+        info!(
+            "{} rust-analyzer environment written to '{}'",
+            emoji::CHECK,
+            settings_path.display()
+        );
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        shell
+            .unwrap_or_else(Shell::detect)
+            .format_exports(&environment)
+    );
+    Ok(())
+}
+
+/// Builds a command running `program` with the environment of the installation: its
+/// variables set and its `PATH` entries prepended to the current `PATH`.
+pub fn get_command(program: &OsStr, environment: &Environment) -> Result<Command, Error> {
+    let mut command = Command::new(program);
+    command.envs(
+        environment
+            .variables
+            .iter()
+            .map(|(key, value)| (key, value)),
+    );
+    let current_path = env::var_os("PATH").unwrap_or_default();
+    let path = env::join_paths(
+        environment
+            .path
+            .iter()
+            .map(PathBuf::from)
+            .chain(env::split_paths(&current_path)),
+    )
+    .map_err(|e| Error::FailedToRunCommand(program.to_string_lossy().to_string(), e.to_string()))?;
+    command.env("PATH", path).env(ACTIVE_VARIABLE, "1");
+    Ok(command)
+}
+
+/// Gets the shell spawned by `espup shell`: `SHELL` on Unix, and the running PowerShell or
+/// cmd on Windows.
+pub fn get_default_shell() -> OsString {
+    if cfg!(windows) {
+        return match Shell::detect() {
+            Shell::PowerShell => OsString::from("powershell.exe"),
+            _ => env::var_os("COMSPEC").unwrap_or_else(|| OsString::from("cmd.exe")),
+        };
+    }
+    env::var_os("SHELL")
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| OsString::from("/bin/sh"))
+}
+
+/// Gets the paths of the ESP-IDF compatible export scripts of a directory.
+pub fn get_idf_export_scripts(directory: &Path) -> Vec<PathBuf> {
+    IDF_EXPORT_SCRIPTS
+        .iter()
+        .map(|name| directory.join(name))
+        .collect()
+}
+
+/// Formats the exports of an environment as a cmd batch file, with a comment.
+fn format_batch_exports(comment: &str, environment: &Environment) -> String {
+    format!(
+        "@echo off\nrem {comment}\n{}\n",
+        Shell::Cmd.format_exports(environment)
+    )
+}
+
+/// Gets the cmd batch file generated along with an export file, e.g. `export-esp.bat`.
+pub fn get_batch_export_file(export_file: &Path) -> PathBuf {
+    export_file.with_extension("bat")
+}
+
+/// Writes the cmd batch file setting the same variables as the PowerShell export file,
+/// for the users of cmd.
+pub fn write_batch_export_file(export_file: &Path) -> Result<(), Error> {
+    let batch_export_file = get_batch_export_file(export_file);
+    if batch_export_file == export_file {
+        return Ok(());
+    }
+    write(
+        batch_export_file,
+        format_batch_exports(
+            &format!("Generated by espup from {}", export_file.display()),
+            &get_environment(export_file)?,
+        ),
+    )?;
+    Ok(())
+}
+
+/// Formats an ESP-IDF compatible export script, which sources the export file. Batch files
+/// cannot run the PowerShell export file in the calling shell, hence set the variables.
+fn format_idf_export_script(name: &str, export_file: &Path, environment: &Environment) -> String {
+    if name.ends_with(".bat") {
+        return format_batch_exports(
+            &format!(
+                "{IDF_EXPORT_SCRIPT_MARKER}, sets the environment of {}",
+                export_file.display()
+            ),
+            environment,
+        );
+    }
+    let shell = if name.ends_with(".ps1") {
+        Shell::PowerShell
+    } else {
+        Shell::Bash
+    };
+    format!(
+        "# {IDF_EXPORT_SCRIPT_MARKER}, sources the export file of espup\n. {}\n",
+        shell.quote(&export_file.display().to_string())
+    )
+}
+
+/// Writes ESP-IDF compatible export scripts, e.g. `export.sh`, to a directory, so that
+/// `. <directory>/export.sh` sets up the environment of the export file. Existing scripts
+/// not generated by espup, e.g. the ones of an ESP-IDF checkout, are never overwritten.
+pub fn write_idf_export_scripts(
+    directory: &Path,
+    export_file: &Path,
+) -> Result<Vec<PathBuf>, Error> {
+    let environment = get_environment(export_file)?;
+    let scripts = get_idf_export_scripts(directory);
+    for script in &scripts {
+        if script.exists()
+            && !read_to_string(script).map_or(false, |contents| {
+                contents.contains(IDF_EXPORT_SCRIPT_MARKER)
+            })
+        {
+            return Err(Error::ExistingExportScript(script.display().to_string()));
+        }
+    }
+    create_dir_all(directory)?;
+    for script in &scripts {
+        let name = script
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        write(
+            script,
+            format_idf_export_script(&name, export_file, &environment),
+        )?;
+    }
+    Ok(scripts)
+}
+
+/// Gets the `rust-analyzer.server.extraEnv` setting: the exported variables, the `PATH`
+/// entries prepended to the one of the editor, and the Xtensa Rust toolchain as override.
+pub fn get_extra_env(config: &Config, environment: &Environment) -> Map<String, Value> {
+    let mut extra_env = Map::new();
+    for (key, value) in &environment.variables {
+        extra_env.insert(key.clone(), Value::String(value.clone()));
+    }
+    if !environment.path.is_empty() {
+        let path = environment
+            .path
+            .iter()
+            .map(String::as_str)
+            .chain(["${env:PATH}"])
+            .collect::<Vec<&str>>()
+            .join(PATH_SEPARATOR);
+        extra_env.insert("PATH".to_string(), Value::String(path));
+    }
+    if let Some(xtensa_rust) = &config.xtensa_rust {
+        extra_env.insert(
+            "RUSTUP_TOOLCHAIN".to_string(),
+            Value::String(xtensa_rust.name.clone()),
+        );
+    }
+    extra_env
+}
+
+/// Removes the comments and trailing commas that VS Code allows in its settings, which are
+/// not valid JSON.
+fn strip_jsonc(text: &str) -> String {
+    let mut json = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            json.push(c);
+            match c {
+                '\\' => json.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                json.push(c);
+            }
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        json.push(c);
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            (']' | '}', _) => {
+                let trimmed_len = json.trim_end().len();
+                if json[..trimmed_len].ends_with(',') {
+                    json.remove(trimmed_len - 1);
+                }
+                json.push(c);
+            }
+            _ => json.push(c),
+        }
+    }
+    json
+}
+
+/// Merges the extra environment into the settings.
+fn merge_settings(settings: &mut Map<String, Value>, extra_env: Map<String, Value>) {
+    let setting = settings
+        .entry(EXTRA_ENV_SETTING)
+        .or_insert_with(|| Value::Object(Map::new()));
+    if !setting.is_object() {
+        *setting = Value::Object(Map::new());
+    }
+    if let Value::Object(existing_env) = setting {
+        existing_env.extend(extra_env);
+    }
+}
+
+/// Writes the extra environment into `.vscode/settings.json` of the project, merging it
+/// with the existing settings, whose comments are not preserved. Returns the path of the
+/// settings file.
+pub fn write_vscode_settings(
+    project_dir: &Path,
+    extra_env: Map<String, Value>,
+) -> Result<PathBuf, Error> {
+    let vscode_dir = project_dir.join(".vscode");
+    let settings_path = vscode_dir.join("settings.json");
+    let invalid =
+        |reason: String| Error::InvalidVscodeSettings(settings_path.display().to_string(), reason);
+    let mut settings = match read_to_string(&settings_path) {
+        Ok(contents) if !contents.trim().is_empty() => {
+            match serde_json::from_str(&strip_jsonc(&contents)) {
+                Ok(Value::Object(settings)) => settings,
+                Ok(_) => return Err(invalid("not a JSON object".to_string())),
+                Err(e) => return Err(invalid(e.to_string())),
+            }
+        }
+        _ => Map::new(),
+    };
+    merge_settings(&mut settings, extra_env);
+
+    create_dir_all(&vscode_dir)
+        .map_err(|_| Error::FailedToCreateDirectory(vscode_dir.display().to_string()))?;
+    let mut contents = Vec::new();
+    let mut serializer =
+        Serializer::with_formatter(&mut contents, PrettyFormatter::with_indent(b"    "));
+    Value::Object(settings)
+        .serialize(&mut serializer)
+        .map_err(|e| invalid(e.to_string()))?;
+    contents.push(b'\n');
+    write(&settings_path, contents)
+        .map_err(|_| Error::FailedToWrite(settings_path.display().to_string()))?;
+    Ok(settings_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        context::Context,
+        environment::{
+            get_command, get_extra_env, get_idf_export_scripts, get_override_exports,
+            normalize_exports, parse_exports, select_conflicting_clang_variables,
+            sources_export_file, strip_jsonc, strip_sourcing, write_idf_export_scripts,
+            write_vscode_settings, Environment, Shell, ACTIVE_VARIABLE, EXTRA_ENV_SETTING,
+        },
+        host_triple::HostTriple,
+        toolchain::rust::XtensaRust,
+    };
+    use serde_json::Value;
+    use std::{
+        env,
+        ffi::OsStr,
+        fs::{create_dir_all, read_to_string, write},
+        path::PathBuf,
+        str::FromStr,
+    };
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_get_command() {
+        let environment = Environment {
+            variables: vec![("LIBCLANG_PATH".to_string(), "/llvm/lib".to_string())],
+            path: vec!["/gcc/bin".to_string()],
+        };
+        let command = get_command(OsStr::new("cargo"), &environment).unwrap();
+        let envs: Vec<_> = command.get_envs().collect();
+        assert!(envs.contains(&(OsStr::new("LIBCLANG_PATH"), Some(OsStr::new("/llvm/lib")))));
+        assert!(envs.contains(&(OsStr::new(ACTIVE_VARIABLE), Some(OsStr::new("1")))));
+        let path = envs
+            .iter()
+            .find(|(key, _)| *key == "PATH")
+            .and_then(|(_, value)| *value)
+            .unwrap();
+        assert_eq!(
+            env::split_paths(path).next(),
+            Some(PathBuf::from("/gcc/bin"))
+        );
+    }
+
+    #[test]
+    fn test_write_idf_export_scripts() {
+        let temp_dir = TempDir::new().unwrap();
+        let export_file = temp_dir.path().join("export-esp.sh");
+        write(&export_file, "export LIBCLANG_PATH=\"/llvm/lib\"\n").unwrap();
+        let directory = temp_dir.path().join("esp-idf");
+
+        let scripts = write_idf_export_scripts(&directory, &export_file).unwrap();
+        assert_eq!(scripts, get_idf_export_scripts(&directory));
+        #[cfg(unix)]
+        assert!(read_to_string(&scripts[0])
+            .unwrap()
+            .contains(&format!(". \"{}\"", export_file.display())));
+        // The scripts generated by espup are overwritten, but not the others.
+        assert!(write_idf_export_scripts(&directory, &export_file).is_ok());
+        write(&scripts[0], "# export script of ESP-IDF").unwrap();
+        assert!(write_idf_export_scripts(&directory, &export_file).is_err());
+    }
+
+    #[test]
+    fn test_parse_exports() {
+        let environment = parse_exports(
+            "export LIBCLANG_PATH=\"/esp/llvm/lib\"\nexport PATH=\"/esp/gcc/bin:$PATH\"\n. /esp/other.sh\n$Env:PATH+=\";C:\\esp\\bin\"\n",
+        );
+        assert_eq!(
+            environment.variables,
+            vec![("LIBCLANG_PATH".to_string(), "/esp/llvm/lib".to_string())]
+        );
+        assert_eq!(environment.path, vec!["/esp/gcc/bin", "C:\\esp\\bin"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_normalize_exports() {
+        let temp_dir = TempDir::new().unwrap();
+        let gcc_path = temp_dir.path().join("gcc").display().to_string();
+        let gdb_path = temp_dir.path().join("gdb").display().to_string();
+        create_dir_all(&gcc_path).unwrap();
+        create_dir_all(&gdb_path).unwrap();
+        let exports: Vec<String> = vec![
+            "export LIBCLANG_PATH=\"/esp/llvm/lib\"".to_string(),
+            format!("export PATH={gcc_path}:$PATH"),
+            "export LIBCLANG_PATH=\"/esp/llvm/lib\"".to_string(),
+            format!("export PATH={gdb_path}:/esp/removed/bin:$PATH"),
+            format!("export PATH={gcc_path}:$PATH"),
+        ];
+        assert_eq!(
+            normalize_exports(&exports),
+            vec![
+                "export LIBCLANG_PATH=\"/esp/llvm/lib\"".to_string(),
+                format!("export PATH=\"{gcc_path}:{gdb_path}:$PATH\""),
+            ]
+        );
+        assert_eq!(
+            normalize_exports(&normalize_exports(&exports)),
+            normalize_exports(&exports)
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_get_override_exports() {
+        let conflicts = vec![
+            ("IDF_PATH".to_string(), "/old/esp-idf".to_string()),
+            (
+                "IDF_PYTHON_ENV_PATH".to_string(),
+                "/old/python_env".to_string(),
+            ),
+        ];
+        let exports = vec!["export IDF_PATH=/esp/esp-idf/v5.0".to_string()];
+        assert_eq!(
+            get_override_exports(&conflicts, &exports),
+            vec!["unset IDF_PYTHON_ENV_PATH".to_string()]
+        );
+        assert!(get_override_exports(&[], &exports).is_empty());
+    }
+
+    #[test]
+    fn test_select_conflicting_clang_variables() {
+        let variables = vec![
+            (
+                "LIBCLANG_PATH".to_string(),
+                "/home/esp/.espressif/tools/xtensa-esp32-elf-clang/esp-15.0.0/esp-clang/lib"
+                    .to_string(),
+            ),
+            ("CLANG_PATH".to_string(), "/usr/bin/clang-14".to_string()),
+            ("IDF_PATH".to_string(), "/opt/esp-idf".to_string()),
+        ];
+        assert_eq!(
+            select_conflicting_clang_variables(&variables, &PathBuf::from("/home/esp/.espressif")),
+            vec![("CLANG_PATH".to_string(), "/usr/bin/clang-14".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_strip_sourcing() {
+        let home_dir = PathBuf::from("/home/esp");
+        let export_file = home_dir.join("export-esp.sh");
+        let bashrc = "alias ll='ls -l'\n. $HOME/export-esp.sh\nsource \"/home/esp/export-esp.sh\"\n. ~/esp/esp-idf/export.sh\n";
+        assert_eq!(
+            strip_sourcing(bashrc, &export_file, &home_dir).as_deref(),
+            Some("alias ll='ls -l'\n. ~/esp/esp-idf/export.sh\n")
+        );
+        assert_eq!(
+            strip_sourcing("# . ~/export-esp.sh\n", &export_file, &home_dir),
+            None
+        );
+        assert!(sources_export_file(
+            "~/export-esp.sh",
+            &export_file,
+            &home_dir
+        ));
+        assert!(!sources_export_file(
+            ". ~/export-esp.sh.bak",
+            &export_file,
+            &home_dir
+        ));
+    }
+
+    #[test]
+    fn test_format_exports() {
+        let environment = parse_exports(
+            "export LIBCLANG_PATH=\"/esp/llvm/lib\"\nexport PATH=\"/esp/gcc/bin:/esp/gdb/bin:$PATH\"\n",
+        );
+        assert_eq!(Shell::from_str("zsh").unwrap(), Shell::Bash);
+        assert_eq!(
+            Shell::Bash.format_exports(&environment),
+            "export LIBCLANG_PATH=\"/esp/llvm/lib\"\nexport PATH=\"/esp/gcc/bin:/esp/gdb/bin\":\"$PATH\""
+        );
+        assert_eq!(
+            Shell::Fish.format_exports(&environment),
+            "set -gx LIBCLANG_PATH \"/esp/llvm/lib\"\nset -gx PATH \"/esp/gcc/bin\" \"/esp/gdb/bin\" $PATH"
+        );
+        assert_eq!(
+            Shell::PowerShell.format_exports(&parse_exports("$Env:IDF_PATH=\"C:\\esp$\"")),
+            "$Env:IDF_PATH = \"C:\\esp`$\""
+        );
+        assert_eq!(
+            Shell::Cmd.format_exports(&parse_exports(
+                "$Env:IDF_PATH=\"C:\\esp%\"\n$Env:PATH=\"C:\\gcc\\bin;$Env:PATH\""
+            )),
+            "set \"IDF_PATH=C:\\esp%%\"\nset \"PATH=C:\\gcc\\bin;%PATH%\""
+        );
+    }
+
+    #[test]
+    fn test_strip_jsonc() {
+        let jsonc = "{\n  // Editor\n  \"editor.rulers\": [100,], /* block */\n  \"url\": \"http://a//b\",\n}";
+        let json: Value = serde_json::from_str(&strip_jsonc(jsonc)).unwrap();
+        assert_eq!(json["url"], "http://a//b");
+        assert_eq!(json["editor.rulers"][0], 100);
+    }
+
+    #[test]
+    fn test_write_vscode_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let vscode_dir = temp_dir.path().join(".vscode");
+        create_dir_all(&vscode_dir).unwrap();
+        write(
+            vscode_dir.join("settings.json"),
+            "{\n    // Keep me\n    \"editor.formatOnSave\": true,\n    \"rust-analyzer.server.extraEnv\": {\"RUST_LOG\": \"info\"},\n}\n",
+        )
+        .unwrap();
+        let config = Config {
+            xtensa_rust: Some(XtensaRust::new(
+                &Context::default(),
+                "1.66.0.0",
+                "esp",
+                &HostTriple::X86_64UnknownLinuxGnu,
+            )),
+            ..Default::default()
+        };
+        let environment = parse_exports("export LIBCLANG_PATH=/esp/llvm/lib\n");
+        let settings_path =
+            write_vscode_settings(temp_dir.path(), get_extra_env(&config, &environment)).unwrap();
+
+        let settings: Value =
+            serde_json::from_str(&read_to_string(settings_path).unwrap()).unwrap();
+        assert_eq!(settings["editor.formatOnSave"], true);
+        let extra_env = &settings[EXTRA_ENV_SETTING];
+        assert_eq!(extra_env["RUST_LOG"], "info");
+        assert_eq!(extra_env["LIBCLANG_PATH"], "/esp/llvm/lib");
+        assert_eq!(extra_env["RUSTUP_TOOLCHAIN"], "esp");
+    }
+}
@@ -0,0 +1,95 @@
+//! Named environments, selected with `--env`, e.g. `espup install --env legacy-idf44`. Each
+//! one has its own tools directory, configuration file, export file and Xtensa Rust
+//! toolchain, so that projects pinned to very different ESP-IDF versions and toolchains do
+//! not share an installation.
+
+use crate::{context::Context, error::Error, toolchain::rust::DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME};
+use std::path::{Path, PathBuf};
+
+/// Directory of the named environments, in the tools directory and in the configuration one.
+const ENVS_DIRECTORY: &str = "envs";
+
+/// Parses the name of an environment, which is used in paths and toolchain names: ASCII
+/// letters, digits, `-` and `_`.
+pub fn parse_env_name(name: &str) -> Result<String, Error> {
+    let valid = !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(Error::InvalidEnvName(name.to_string()));
+    }
+    Ok(name.to_string())
+}
+
+/// Gets the tools directory of an environment, in `tools_path`.
+fn get_env_tools_path_in(tools_path: &Path, name: &str) -> PathBuf {
+    tools_path.join(ENVS_DIRECTORY).join(name)
+}
+
+/// Selects a named environment in `context`, installing its tools in the `envs/<name>`
+/// directory of the tools directory.
+pub fn select_env(context: &mut Context, name: &str) {
+    context.tools_path = get_env_tools_path_in(&context.tools_path, name);
+    context.env = Some(name.to_string());
+}
+
+/// Gets the configuration file of an environment, in the `envs` directory of `config_dir`.
+pub fn get_env_config_path(config_dir: &Path, name: &str) -> PathBuf {
+    config_dir.join(ENVS_DIRECTORY).join(format!("{name}.toml"))
+}
+
+/// Gets the default export file name of an environment, e.g. `export-esp-legacy.sh` for
+/// `export-esp.sh`.
+pub fn get_env_export_file_name(default_export_file: &str, name: &str) -> String {
+    let default_export_file = Path::new(default_export_file);
+    let stem = default_export_file
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    match default_export_file.extension() {
+        Some(extension) => format!("{stem}-{name}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{name}"),
+    }
+}
+
+/// Gets the default Xtensa Rust toolchain name of an environment, e.g. `esp-legacy`.
+pub fn get_env_toolchain_name(name: &str) -> String {
+    format!("{DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME}-{name}")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::envs::{
+        get_env_config_path, get_env_export_file_name, get_env_toolchain_name,
+        get_env_tools_path_in, parse_env_name,
+    };
+    use std::path::Path;
+
+    #[test]
+    fn test_env_paths() {
+        assert_eq!(parse_env_name("work-idf5").unwrap(), "work-idf5");
+        assert!(parse_env_name("legacy_idf44").is_ok());
+        assert!(parse_env_name("").is_err());
+        assert!(parse_env_name("-legacy").is_err());
+        assert!(parse_env_name("../legacy").is_err());
+
+        assert_eq!(
+            get_env_tools_path_in(Path::new("/home/esp/.espressif"), "legacy"),
+            Path::new("/home/esp/.espressif/envs/legacy")
+        );
+        assert_eq!(
+            get_env_config_path(Path::new("/home/esp/.config/espup"), "legacy"),
+            Path::new("/home/esp/.config/espup/envs/legacy.toml")
+        );
+        assert_eq!(
+            get_env_export_file_name("export-esp.sh", "legacy"),
+            "export-esp-legacy.sh"
+        );
+        assert_eq!(
+            get_env_export_file_name("export-esp.ps1", "work-idf5"),
+            "export-esp-work-idf5.ps1"
+        );
+        assert_eq!(get_env_toolchain_name("legacy"), "esp-legacy");
+    }
+}
@@ -1,11 +1,45 @@
 use crate::emoji;
 
+/// Exit code of the failures without a more specific category.
+pub const EXIT_FAILURE: u8 = 1;
+/// Exit code of invalid arguments, also used by clap for usage errors.
+pub const EXIT_INVALID_ARGUMENTS: u8 = 2;
+/// Exit code of network failures.
+pub const EXIT_NETWORK_FAILURE: u8 = 3;
+/// Exit code of missing or unmet prerequisites.
+pub const EXIT_PREREQUISITES_NOT_MET: u8 = 4;
+/// Exit code of file system failures.
+pub const EXIT_FILE_SYSTEM_FAILURE: u8 = 5;
+/// Exit code of runs blocked by another espup process.
+pub const EXIT_INSTALLATION_LOCKED: u8 = 6;
+/// Exit code of failed verifications and health checks.
+pub const EXIT_VERIFICATION_FAILED: u8 = 7;
+/// Exit code of `espup check-updates` when updates are available.
+pub const EXIT_UPDATES_AVAILABLE: u8 = 8;
+/// Exit code of runs interrupted with Ctrl-C or terminated, as set by the shells for `SIGINT`.
+pub const EXIT_INTERRUPTED: u8 = 130;
+
 #[derive(Debug, miette::Diagnostic, thiserror::Error)]
 pub enum Error {
     // Host Triple
     #[diagnostic(code(espup::host_triple::unsupported_host_triple))]
     #[error("{} Host triple '{0}' is not supported", emoji::ERROR)]
     UnsupportedHostTriple(String),
+    #[diagnostic(code(espup::host_triple::unsupported_host_abi))]
+    #[error(
+        "{} Host ABI '{0}' is not supported on '{1}', only Windows hosts can choose between 'msvc' and 'gnu'",
+        emoji::ERROR
+    )]
+    UnsupportedHostAbi(String, String),
+    #[diagnostic(code(espup::host_triple::missing_host_linker))]
+    #[error("{} The linker for '{0}' was not found. {1}.", emoji::ERROR)]
+    MissingHostLinker(String, String),
+    #[diagnostic(code(espup::host_triple::unsupported_musl_host))]
+    #[error(
+        "{} The prebuilt tools for '{0}' are linked against glibc and cannot run on this musl based host. Install a glibc compatibility layer, e.g. 'apk add gcompat' on Alpine Linux, or use a glibc based distribution or container, e.g. Debian.",
+        emoji::ERROR
+    )]
+    UnsupportedMuslHost(String),
     // Target
     #[diagnostic(code(espup::targets::unsupported_target))]
     #[error("{} Target '{0}' is not supported", emoji::ERROR)]
@@ -26,6 +60,49 @@ pub enum Error {
     #[diagnostic(code(espup::config::failed_to_write))]
     #[error("{} Failed to write config to '{0}'", emoji::ERROR)]
     FailedToWrite(String),
+    //  HTTP
+    #[diagnostic(code(espup::http::github_rate_limit_exceeded))]
+    #[error(
+        "{} GitHub API rate limit exceeded. Provide a token with '--github-token' or the 'GITHUB_TOKEN' environment variable, or wait until the limit resets.",
+        emoji::ERROR
+    )]
+    GithubRateLimitExceeded,
+    #[diagnostic(code(espup::http::invalid_github_token))]
+    #[error(
+        "{} Invalid GitHub token, it contains invalid characters",
+        emoji::ERROR
+    )]
+    InvalidGithubToken,
+    #[diagnostic(code(espup::http::failed_to_load_certificate))]
+    #[error("{} Failed to load certificate from '{0}'", emoji::ERROR)]
+    FailedToLoadCertificate(String),
+    #[diagnostic(code(espup::http::invalid_mirror))]
+    #[error(
+        "{} Invalid mirror '{0}', must be 'github', 'espressif', 'jihulab' or a URL",
+        emoji::ERROR
+    )]
+    InvalidMirror(String),
+    #[diagnostic(code(espup::mirror::no_reachable_mirror))]
+    #[error(
+        "{} None of the mirrors could be reached, their ranking is left unchanged",
+        emoji::ERROR
+    )]
+    NoReachableMirror,
+    #[diagnostic(code(espup::http::invalid_proxy))]
+    #[error("{} Invalid proxy URL: '{0}'", emoji::ERROR)]
+    InvalidProxy(String),
+    #[diagnostic(code(espup::http::invalid_limit_rate))]
+    #[error(
+        "{} Invalid transfer rate: '{0}'. Use a number of bytes per second, optionally followed by 'k', 'm' or 'g', e.g. '500k'",
+        emoji::ERROR
+    )]
+    InvalidLimitRate(String),
+    #[diagnostic(code(espup::http::unsupported_tls_backend))]
+    #[error(
+        "{} Unsupported TLS backend '{0}', the ones enabled in this build are: {1}",
+        emoji::ERROR
+    )]
+    UnsupportedTlsBackend(String, String),
     //  Toolchain
     #[error(transparent)]
     IoError(#[from] std::io::Error),
@@ -34,9 +111,36 @@ pub enum Error {
     #[diagnostic(code(espup::toolchain::failed_to_create_directory))]
     #[error("{} Creating directory '{0}' failed", emoji::ERROR)]
     FailedToCreateDirectory(String),
+    #[diagnostic(code(espup::toolchain::failed_to_extract))]
+    #[error("{} Failed to extract '{0}'", emoji::ERROR)]
+    FailedToExtract(String),
     #[diagnostic(code(espup::toolchain::unsupported_file_extension))]
     #[error("{} Unsuported file extension: '{0}'", emoji::ERROR)]
     UnsuportedFileExtension(String),
+    #[diagnostic(code(espup::toolchain::unstreamable_archive))]
+    #[error(
+        "{} The archive of '{0}' can not be extracted while downloading it",
+        emoji::ERROR
+    )]
+    UnstreamableArchive(String),
+    #[diagnostic(code(espup::toolchain::missing_download))]
+    #[error("{} Download not found: '{0}'", emoji::ERROR)]
+    MissingDownload(String),
+    #[diagnostic(code(espup::toolchain::missing_signature))]
+    #[error("{} No signature is published for '{0}'", emoji::ERROR)]
+    MissingSignature(String),
+    #[diagnostic(code(espup::toolchain::invalid_signature))]
+    #[error(
+        "{} The signature of '{0}' is not valid, or its signing key is not trusted",
+        emoji::ERROR
+    )]
+    InvalidSignature(String),
+    #[diagnostic(code(espup::toolchain::missing_gpg))]
+    #[error(
+        "{} GnuPG is required to verify the signature of '{0}', install it and add 'gpg' to the PATH",
+        emoji::ERROR
+    )]
+    MissingGpg(String),
     //  Toolchain - Rust
     #[diagnostic(code(espup::toolchain::rust::failed_to_get_latest_version))]
     #[error("{} Failed To serialize Json from string.", emoji::ERROR)]
@@ -44,17 +148,86 @@ pub enum Error {
     #[diagnostic(code(espup::toolchain::rust::xtensa_rust_already_installed))]
     #[error("{} Previous installation of Rust Toolchain exists in: '{0}'. Please, remove the directory before new installation.", emoji::ERROR)]
     XtensaToolchainAlreadyInstalled(String),
+    #[diagnostic(code(espup::toolchain::rust::xtensa_rust_not_installed))]
+    #[error(
+        "{} No Xtensa Rust toolchain named '{0}' is installed, install it with 'espup install --name {0}'",
+        emoji::ERROR
+    )]
+    XtensaToolchainNotInstalled(String),
+    #[diagnostic(code(espup::toolchain::rust::xtensa_rust_not_linked))]
+    #[error(
+        "{} The Xtensa Rust toolchain in '{0}' was not installed by espup and would be replaced. Please, remove the directory or reinstall it with '--name'.",
+        emoji::ERROR
+    )]
+    XtensaToolchainNotLinked(String),
     #[diagnostic(code(espup::toolchain::rust::invalid_version))]
     #[error(
         "{} Invalid toolchain version '{0}', must be in the form of '<major>.<minor>.<patch>.<subpatch>'",
         emoji::ERROR
     )]
     InvalidXtensaToolchanVersion(String),
+    #[diagnostic(code(espup::toolchain::rust::invalid_name))]
+    #[error(
+        "{} Invalid toolchain name '{0}', must only contain letters, digits, '.', '-' and '_', and not be a rustup channel name such as 'nightly'",
+        emoji::ERROR
+    )]
+    InvalidXtensaToolchainName(String),
+    #[diagnostic(code(espup::toolchain::rust::unavailable_version))]
+    #[error(
+        "{} Xtensa Rust toolchain '{0}' is not available for '{1}'. Available versions: {2}",
+        emoji::ERROR
+    )]
+    UnavailableXtensaToolchainVersion(String, String, String),
     #[diagnostic(code(espup::toolchain::rust::detection_error))]
     #[error("{} Error detecting rustup: {0}", emoji::ERROR)]
     RustupDetectionError(String),
+    #[diagnostic(code(espup::toolchain::rust::missing_rustup))]
+    #[error(
+        "{} rustup was not found. espup installs the Rust toolchains with rustup, and registers the Xtensa one in it: install rustup from https://rustup.rs, or rerun with '--install-rustup' to let espup install it.",
+        emoji::ERROR
+    )]
+    MissingRustup,
     #[error(transparent)]
     CmdError(#[from] embuild::cmd::CmdError),
+    #[diagnostic(code(espup::toolchain::rust::invalid_nightly_version))]
+    #[error(
+        "{} Invalid nightly version '{0}', must be 'nightly' or in the form of 'nightly-<year>-<month>-<day>'",
+        emoji::ERROR
+    )]
+    InvalidNightlyVersion(String),
+    // Toolchain - LLVM
+    #[diagnostic(code(espup::toolchain::llvm::invalid_version))]
+    #[error(
+        "{} Invalid LLVM version '{0}', must be '15' or a release in the form of 'esp-<major>.<minor>.<patch>-<date>'",
+        emoji::ERROR
+    )]
+    InvalidLlvmVersion(String),
+    // Toolchain - GCC
+    #[diagnostic(code(espup::toolchain::gcc::unsupported_gcc_version))]
+    #[error(
+        "{} GCC version '{0}' is not supported, supported versions are: {1}",
+        emoji::ERROR
+    )]
+    UnsupportedGccVersion(String, String),
+    #[diagnostic(code(espup::toolchain::gcc::invalid_gcc_version))]
+    #[error(
+        "{} Invalid GCC version '{0}', must be in the form of '<version>' or '<target>=<version>'",
+        emoji::ERROR
+    )]
+    InvalidGccVersion(String),
+    // Toolchain - Local artifacts
+    #[diagnostic(code(espup::toolchain::invalid_local_artifact))]
+    #[error(
+        "{} Invalid local artifact '{0}', must be in the form of '<component>=<path>'",
+        emoji::ERROR
+    )]
+    InvalidLocalArtifact(String),
+    #[diagnostic(code(espup::toolchain::unsupported_local_artifact))]
+    #[error(
+        "{} Component '{0}' cannot be installed from a local archive, supported components: {1}",
+        emoji::ERROR
+    )]
+    UnsupportedLocalArtifact(String, String),
     // Toolchain - ESP-IDF
     #[diagnostic(code(espup::toolchain::espidf::failed_to_instatiate_cmake))]
     #[error("{} Failed to add CMake to ESP-IDF tools", emoji::ERROR)]
@@ -62,16 +235,204 @@ pub enum Error {
     #[diagnostic(code(espup::toolchain::espidf::failed_to_create_esp_idf_install_closure))]
     #[error("{} Failed to create ESP-IDF  install closure", emoji::ERROR)]
     FailedToCreateEspIdfInstallClosure,
+    #[diagnostic(code(espup::toolchain::espidf::esp_idf_not_installed))]
+    #[error(
+        "{} ESP-IDF '{0}' is not installed, install it with 'espup install --esp-idf-version {0}'",
+        emoji::ERROR
+    )]
+    EspIdfNotInstalled(String),
+    #[diagnostic(code(espup::toolchain::espidf::no_esp_idf_installed))]
+    #[error("{} No ESP-IDF is installed", emoji::ERROR)]
+    NoEspIdfInstalled,
+    #[diagnostic(code(espup::toolchain::espidf::invalid_esp_idf_path))]
+    #[error("{} '{0}' is not an ESP-IDF git repository", emoji::ERROR)]
+    InvalidEspIdfPath(String),
+    #[diagnostic(code(espup::toolchain::espidf::existing_export_script))]
+    #[error(
+        "{} '{0}' already exists and was not generated by espup, e.g. it belongs to an ESP-IDF checkout",
+        emoji::ERROR
+    )]
+    ExistingExportScript(String),
+    #[diagnostic(code(espup::toolchain::espidf::missing_esp_idf_archive))]
+    #[error(
+        "{} ESP-IDF '{0}' has no release archive, only releases, e.g. 'v5.1', can be installed with '--idf-from-archive'",
+        emoji::ERROR
+    )]
+    MissingEspIdfArchive(String),
+    #[diagnostic(code(espup::toolchain::espidf::unreachable_ssh_repository))]
+    #[error(
+        "{} Failed to access the ESP-IDF repository '{0}' over SSH ({1}). Check that your key can read it and is loaded in the SSH agent, e.g. with 'ssh-add -l'",
+        emoji::ERROR
+    )]
+    UnreachableSshRepository(String, String),
+    #[diagnostic(code(espup::toolchain::espidf::unsupported_target))]
+    #[error(
+        "{} Target '{0}' requires ESP-IDF v{1} or newer, but v{2} was selected",
+        emoji::ERROR
+    )]
+    UnsupportedEspIdfTarget(String, String, String),
+    #[diagnostic(code(espup::toolchain::espidf::invalid_python))]
+    #[error("{} '{0}' is not a Python 3 interpreter", emoji::ERROR)]
+    InvalidPython(String),
+    #[diagnostic(code(espup::toolchain::espidf::failed_to_configure_python_env))]
+    #[error(
+        "{} Failed to configure the Python virtual environment in '{0}'",
+        emoji::ERROR
+    )]
+    FailedToConfigurePythonEnv(String),
+    #[diagnostic(code(espup::toolchain::espidf::python_env_already_exists))]
+    #[error(
+        "{} A Python virtual environment already exists in '{0}', remove it to use a different one",
+        emoji::ERROR
+    )]
+    PythonEnvAlreadyExists(String),
+    #[diagnostic(code(espup::toolchain::espidf::missing_python_env))]
+    #[error(
+        "{} Skipping the Python virtual environment of ESP-IDF requires an existing one, given with '--python-env' or activated ('VIRTUAL_ENV')",
+        emoji::ERROR
+    )]
+    MissingPythonEnv,
+    #[diagnostic(code(espup::toolchain::espidf::python_requirements_not_met))]
+    #[error(
+        "{} The Python requirements of ESP-IDF are not met, see the messages above to fix them",
+        emoji::ERROR
+    )]
+    PythonRequirementsNotMet,
     #[diagnostic(code(espup::toolchain::espidf::failed_to_install_esp_idf))]
     #[error("{} Failed to install ESP-IDF", emoji::ERROR)]
     FailedToInstallEspIdf,
+    //  Doctor
+    #[diagnostic(code(espup::doctor::checks_failed))]
+    #[error(
+        "{} {0} check(s) failed, see the messages above to fix them",
+        emoji::ERROR
+    )]
+    DoctorChecksFailed(usize),
+    //  Verify
+    #[diagnostic(code(espup::verify::verification_failed))]
+    #[error(
+        "{} {0} component(s) are broken, see the messages above and reinstall them",
+        emoji::ERROR
+    )]
+    VerificationFailed(usize),
+    //  Smoke test
+    #[diagnostic(code(espup::smoke::smoke_test_failed))]
+    #[error(
+        "{} The hello world for '{0}' failed to build with the installed toolchains, see the output above",
+        emoji::ERROR
+    )]
+    SmokeTestFailed(String),
+    //  Export
+    #[diagnostic(code(espup::export::failed_to_create_archive))]
+    #[error("{} Failed to create the archive '{0}'", emoji::ERROR)]
+    FailedToCreateArchive(String),
+    //  Env
+    #[diagnostic(code(espup::environment::missing_export_file))]
+    #[error(
+        "{} Export file '{0}' not found, run 'espup install' to create it",
+        emoji::ERROR
+    )]
+    MissingExportFile(String),
+    #[diagnostic(code(espup::environment::failed_to_run_command))]
+    #[error("{} Failed to run '{0}': {1}", emoji::ERROR)]
+    FailedToRunCommand(String, String),
+    #[diagnostic(code(espup::environment::invalid_vscode_settings))]
+    #[error("{} Invalid VS Code settings '{0}': {1}", emoji::ERROR)]
+    InvalidVscodeSettings(String, String),
+    //  Which
+    #[diagnostic(code(espup::which::unresolved_location))]
+    #[error(
+        "{} '{0}' is not installed, run 'espup install' to install it",
+        emoji::ERROR
+    )]
+    UnresolvedLocation(String),
+    //  Info
+    #[diagnostic(code(espup::info::component_not_installed))]
+    #[error(
+        "{} '{0}' is not installed, run 'espup list' to list the installed components",
+        emoji::ERROR
+    )]
+    ComponentNotInstalled(String),
+    //  Project
+    #[diagnostic(code(espup::project::no_project_requirements))]
+    #[error(
+        "{} No requirements found in '{0}': declare them in 'espup.toml', the '[package.metadata.espup]' table of 'Cargo.toml' or 'rust-toolchain.toml'",
+        emoji::ERROR
+    )]
+    NoProjectRequirements(String),
+    #[diagnostic(code(espup::project::invalid_project_file))]
+    #[error("{} Invalid project file '{0}': {1}", emoji::ERROR)]
+    InvalidProjectFile(String, String),
+    //  Scaffold
+    #[diagnostic(code(espup::scaffold::missing_cargo_generate))]
+    #[error(
+        "{} cargo-generate is required to generate projects, install it with 'cargo install cargo-generate'",
+        emoji::ERROR
+    )]
+    MissingCargoGenerate,
+    #[diagnostic(code(espup::scaffold::target_not_installed))]
+    #[error(
+        "{} Target '{0}' is not installed, install it with 'espup install --targets {0}'",
+        emoji::ERROR
+    )]
+    TargetNotInstalled(String),
+    //  Self update
+    #[diagnostic(code(espup::self_update::failed_to_self_update))]
+    #[error("{} Failed to update espup: '{0}'", emoji::ERROR)]
+    FailedToSelfUpdate(String),
+    #[diagnostic(code(espup::self_update::missing_checksum))]
+    #[error("{} No SHA-256 checksum is published for '{0}'", emoji::ERROR)]
+    MissingReleaseChecksum(String),
+    #[diagnostic(code(espup::self_update::checksum_mismatch))]
+    #[error(
+        "{} The SHA-256 checksum of '{0}' is '{1}', but the release publishes '{2}'",
+        emoji::ERROR
+    )]
+    ReleaseChecksumMismatch(String, String, String),
+    //  Envs
+    #[diagnostic(code(espup::envs::invalid_env_name))]
+    #[error(
+        "{} Invalid environment name '{0}', use ASCII letters, digits, '-' and '_'",
+        emoji::ERROR
+    )]
+    InvalidEnvName(String),
     //  Main
-    #[diagnostic(code(espup::wrong_windows_arguments))]
+    #[diagnostic(code(espup::hook_failed))]
+    #[error("{} The {0} hook failed: {1}", emoji::ERROR)]
+    HookFailed(String, String),
+    #[diagnostic(code(espup::wizard_not_interactive))]
+    #[error(
+        "{} The installation wizard needs a terminal, run it without --interactive or --non-interactive",
+        emoji::ERROR
+    )]
+    WizardNotInteractive,
+    #[diagnostic(code(espup::container_without_install_dir))]
+    #[error(
+        "{} The container profile installs into a prefix, set it with '--install-dir'",
+        emoji::ERROR
+    )]
+    ContainerWithoutInstallDir,
+    #[diagnostic(code(espup::invalid_target_host))]
+    #[error("{} Cannot provision an environment for '{0}': {1}", emoji::ERROR)]
+    InvalidTargetHost(String, String),
+    #[diagnostic(code(espup::invalid_standalone))]
+    #[error("{} Cannot install a standalone environment: {0}", emoji::ERROR)]
+    InvalidStandalone(String),
+    #[diagnostic(code(espup::installation_locked))]
+    #[error(
+        "{} Another espup process is using '{0}'. Wait for it to finish or run without '--no-wait'.",
+        emoji::ERROR
+    )]
+    InstallationLocked(String),
+    #[diagnostic(code(espup::interrupted))]
+    #[error("{} Interrupted, the changes were rolled back", emoji::ERROR)]
+    Interrupted,
+    #[diagnostic(code(espup::insufficient_disk_space))]
     #[error(
-        "{} When installing esp-idf in Windows, only --targets \"all\" is supported.",
+        "{} Not enough disk space in '{0}': {1} required, {2} available. Free some space, install in another location with '--install-dir' or use '--force' to install anyway.",
         emoji::ERROR
     )]
-    WrongWindowsArguments,
+    InsufficientDiskSpace(String, String, String),
     #[diagnostic(code(espup::failed_to_remove_directory))]
     #[error(
         "{} Failed to remove '{0}' direcretory. Please, manually verify that the directory is properly removed and run 'espup uninstall' again.",
@@ -84,4 +445,114 @@ pub enum Error {
         emoji::ERROR
     )]
     FailedToRemoveFile(String),
+    #[diagnostic(code(espup::failed_to_move))]
+    #[error(
+        "{} Failed to move '{0}' to '{1}'. Please, verify that both are on the same file system, or move it manually and link '{0}' to '{1}'.",
+        emoji::ERROR
+    )]
+    FailedToMove(String, String),
+}
+
+impl Error {
+    /// Gets the exit code of the category of the error, so that scripts can react to it.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::UnsupportedHostTriple(_)
+            | Self::UnsupportedHostAbi(_, _)
+            | Self::UnsupportedTarget(_)
+            | Self::InvalidGithubToken
+            | Self::InvalidMirror(_)
+            | Self::InvalidProxy(_)
+            | Self::InvalidLimitRate(_)
+            | Self::UnsupportedTlsBackend(_, _)
+            | Self::InvalidXtensaToolchanVersion(_)
+            | Self::InvalidXtensaToolchainName(_)
+            | Self::InvalidEnvName(_)
+            | Self::UnavailableXtensaToolchainVersion(_, _, _)
+            | Self::InvalidNightlyVersion(_)
+            | Self::InvalidLlvmVersion(_)
+            | Self::UnsupportedGccVersion(_, _)
+            | Self::InvalidGccVersion(_)
+            | Self::InvalidLocalArtifact(_)
+            | Self::UnsupportedLocalArtifact(_, _)
+            | Self::InvalidEspIdfPath(_)
+            | Self::MissingEspIdfArchive(_)
+            | Self::ExistingExportScript(_)
+            | Self::UnsupportedEspIdfTarget(_, _, _)
+            | Self::InvalidPython(_)
+            | Self::WizardNotInteractive
+            | Self::ContainerWithoutInstallDir
+            | Self::NoProjectRequirements(_)
+            | Self::InvalidProjectFile(_, _)
+            | Self::InvalidTargetHost(_, _)
+            | Self::InvalidStandalone(_) => EXIT_INVALID_ARGUMENTS,
+            Self::GithubRateLimitExceeded
+            | Self::FailedToLoadCertificate(_)
+            | Self::RewquestError(_)
+            | Self::MissingDownload(_)
+            | Self::NoReachableMirror
+            | Self::UnreachableSshRepository(_, _) => EXIT_NETWORK_FAILURE,
+            Self::FileNotFound(_)
+            | Self::XtensaToolchainAlreadyInstalled(_)
+            | Self::MissingHostLinker(_, _)
+            | Self::XtensaToolchainNotInstalled(_)
+            | Self::XtensaToolchainNotLinked(_)
+            | Self::RustupDetectionError(_)
+            | Self::MissingRustup
+            | Self::EspIdfNotInstalled(_)
+            | Self::NoEspIdfInstalled
+            | Self::PythonEnvAlreadyExists(_)
+            | Self::MissingPythonEnv
+            | Self::PythonRequirementsNotMet
+            | Self::InsufficientDiskSpace(_, _, _)
+            | Self::UnsupportedMuslHost(_)
+            | Self::MissingGpg(_)
+            | Self::MissingCargoGenerate
+            | Self::TargetNotInstalled(_)
+            | Self::MissingExportFile(_)
+            | Self::UnresolvedLocation(_)
+            | Self::ComponentNotInstalled(_) => EXIT_PREREQUISITES_NOT_MET,
+            Self::FailedToCreateConfigFile
+            | Self::FailedToWrite(_)
+            | Self::IoError(_)
+            | Self::FailedToCreateDirectory(_)
+            | Self::FailedToExtract(_)
+            | Self::UnstreamableArchive(_)
+            | Self::FailedToRemoveDirectory(_)
+            | Self::FailedToRemoveFile(_)
+            | Self::FailedToMove(_, _)
+            | Self::FailedToCreateArchive(_) => EXIT_FILE_SYSTEM_FAILURE,
+            Self::InstallationLocked(_) => EXIT_INSTALLATION_LOCKED,
+            Self::Interrupted => EXIT_INTERRUPTED,
+            Self::DoctorChecksFailed(_)
+            | Self::VerificationFailed(_)
+            | Self::SmokeTestFailed(_)
+            | Self::MissingSignature(_)
+            | Self::InvalidSignature(_)
+            | Self::MissingReleaseChecksum(_)
+            | Self::ReleaseChecksumMismatch(_, _, _) => EXIT_VERIFICATION_FAILED,
+            _ => EXIT_FAILURE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{
+        Error, EXIT_FAILURE, EXIT_INSTALLATION_LOCKED, EXIT_INTERRUPTED, EXIT_INVALID_ARGUMENTS,
+    };
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(
+            Error::InvalidMirror("gitlab".to_string()).exit_code(),
+            EXIT_INVALID_ARGUMENTS
+        );
+        assert_eq!(
+            Error::InstallationLocked("/tmp".to_string()).exit_code(),
+            EXIT_INSTALLATION_LOCKED
+        );
+        assert_eq!(Error::FailedToInstallEspIdf.exit_code(), EXIT_FAILURE);
+        assert_eq!(Error::Interrupted.exit_code(), EXIT_INTERRUPTED);
+    }
 }
@@ -0,0 +1,344 @@
+//! Archives of an installed environment, to provision identical hosts without downloading
+//! it again.
+
+use crate::{
+    config::Config,
+    context::Context,
+    emoji,
+    environment::get_environment,
+    error::Error,
+    header::strip_header,
+    install::get_export_file,
+    lock::{InstallLock, LOCK_FILE_NAME},
+    provision::{render, ProvisionFormat},
+};
+use flate2::{write::GzEncoder, Compression};
+use log::{info, warn};
+use std::{
+    fs::{read_dir, read_to_string, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+use xz2::write::XzEncoder;
+
+/// Directory of the archive holding the tools.
+const TOOLS_DIR: &str = "tools";
+/// Directory of the archive holding the Xtensa Rust toolchain.
+const TOOLCHAINS_DIR: &str = "toolchains";
+/// Entries of the tools directory that are not archived: the download cache and the lock.
+const EXCLUDED_ENTRIES: [&str; 2] = ["dist", LOCK_FILE_NAME];
+/// Name of the export script, at the root of the archive.
+#[cfg(windows)]
+const EXPORT_SCRIPT: &str = "export-esp.ps1";
+#[cfg(unix)]
+const EXPORT_SCRIPT: &str = "export-esp.sh";
+/// Variable of the export script holding the directory the archive was unpacked in.
+#[cfg(windows)]
+const ENV_DIR: &str = "$Env:ESPUP_ENV_DIR";
+#[cfg(unix)]
+const ENV_DIR: &str = "$ESPUP_ENV_DIR";
+
+/// Compression of an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveCompression {
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+impl ArchiveCompression {
+    /// Gets the compression of an archive from its file name.
+    fn from_path(path: &Path) -> Result<Self, Error> {
+        let file_name = path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+            Ok(Self::Gzip)
+        } else if file_name.ends_with(".tar.xz") {
+            Ok(Self::Xz)
+        } else if file_name.ends_with(".tar.zst") {
+            Ok(Self::Zstd)
+        } else {
+            Err(Error::UnsuportedFileExtension(file_name))
+        }
+    }
+}
+
+/// A directory to archive, with its path inside the archive.
+#[derive(Debug, Clone)]
+struct ArchiveEntry {
+    path: PathBuf,
+    name: String,
+}
+
+/// Gets the directories of the environment to archive: the contents of the tools directory,
+/// which include the ESP-IDF checkouts, and the Xtensa Rust toolchain.
+fn get_archive_entries(config: &Config, tools_path: &Path) -> Vec<ArchiveEntry> {
+    let mut entries: Vec<ArchiveEntry> = read_dir(tools_path)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            !EXCLUDED_ENTRIES
+                .iter()
+                .any(|excluded| entry.file_name() == *excluded)
+        })
+        .map(|entry| ArchiveEntry {
+            name: format!("{TOOLS_DIR}/{}", entry.file_name().to_string_lossy()),
+            path: entry.path(),
+        })
+        .collect();
+    if let Some(xtensa_rust) = &config.xtensa_rust {
+        if !xtensa_rust.toolchain_destination.starts_with(tools_path) {
+            entries.push(ArchiveEntry {
+                path: xtensa_rust.toolchain_destination.clone(),
+                name: format!("{TOOLCHAINS_DIR}/{}", xtensa_rust.name),
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    entries
+}
+
+/// Gets the path of an installed directory once the archive is unpacked, relative to the
+/// directory of the export script.
+fn get_relocated_path(path: &Path, tools_path: &Path, entries: &[ArchiveEntry]) -> Option<String> {
+    if let Ok(relative_path) = path.strip_prefix(tools_path) {
+        return Some(
+            Path::new(ENV_DIR)
+                .join(TOOLS_DIR)
+                .join(relative_path)
+                .display()
+                .to_string(),
+        );
+    }
+    entries
+        .iter()
+        .find(|entry| entry.path == path)
+        .map(|entry| Path::new(ENV_DIR).join(&entry.name).display().to_string())
+}
+
+/// Creates the export script of the archive: the exports of the installation, with the
+/// installed paths relative to the directory the archive is unpacked in, registering the
+/// Xtensa Rust toolchain in rustup.
+fn get_export_script(
+    exports: &str,
+    config: &Config,
+    tools_path: &Path,
+    entries: &[ArchiveEntry],
+) -> String {
+    let mut script = Vec::new();
+    #[cfg(windows)]
+    script.push("$Env:ESPUP_ENV_DIR = $PSScriptRoot".to_string());
+    #[cfg(unix)]
+    script.push(
+        "ESPUP_ENV_DIR=\"$(cd \"$(dirname \"${BASH_SOURCE[0]:-$0}\")\" && pwd)\"".to_string(),
+    );
+    if let Some(xtensa_rust) = &config.xtensa_rust {
+        if let Some(toolchain_path) =
+            get_relocated_path(&xtensa_rust.toolchain_destination, tools_path, entries)
+        {
+            script.push(format!(
+                "rustup toolchain link {} \"{toolchain_path}\"",
+                xtensa_rust.name
+            ));
+        }
+    }
+    let tools_path = tools_path.display().to_string();
+    let relocated_tools_path = Path::new(ENV_DIR).join(TOOLS_DIR).display().to_string();
+    script.extend(
+        exports
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.replace(&tools_path, &relocated_tools_path)),
+    );
+    script.join("\n") + "\n"
+}
+
+/// Writes the archive of the entries and of the export script.
+fn write_archive(
+    output: &Path,
+    compression: ArchiveCompression,
+    entries: &[ArchiveEntry],
+    export_script: &str,
+) -> Result<(), Error> {
+    let file = File::create(output)
+        .map_err(|_| Error::FailedToCreateArchive(output.display().to_string()))?;
+    let writer: Box<dyn Write> = match compression {
+        ArchiveCompression::Gzip => Box::new(GzEncoder::new(file, Compression::default())),
+        ArchiveCompression::Xz => Box::new(XzEncoder::new(file, 6)),
+        ArchiveCompression::Zstd => Box::new(
+            zstd::Encoder::new(file, 0)
+                .map_err(|_| Error::FailedToCreateArchive(output.display().to_string()))?
+                .auto_finish(),
+        ),
+    };
+    let mut builder = tar::Builder::new(writer);
+    builder.follow_symlinks(false);
+    let map_err = |_| Error::FailedToCreateArchive(output.display().to_string());
+    for entry in entries {
+        info!("{} Archiving '{}'", emoji::WRENCH, entry.path.display());
+        if entry.path.is_dir() {
+            builder
+                .append_dir_all(&entry.name, &entry.path)
+                .map_err(map_err)?;
+        } else {
+            builder
+                .append_path_with_name(&entry.path, &entry.name)
+                .map_err(map_err)?;
+        }
+    }
+    let mut header = tar::Header::new_gnu();
+    header.set_size(export_script.len() as u64);
+    header.set_mode(0o755);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, EXPORT_SCRIPT, export_script.as_bytes())
+        .map_err(map_err)?;
+    builder.into_inner().map_err(map_err)?.flush()?;
+    Ok(())
+}
+
+/// Archives the installed environment into `output`, a `.tar.zst`, `.tar.xz` or `.tar.gz`
+/// file, along with an export script that can be sourced wherever the archive is unpacked.
+pub fn create_archive(context: &Context, config: &Config, output: &Path) -> Result<(), Error> {
+    let compression = ArchiveCompression::from_path(output)?;
+    let tools_path = context.tools_path.clone();
+    let entries = get_archive_entries(config, &tools_path);
+
+    let export_file = get_export_file(context.env.as_deref(), config.export_file.clone())?;
+    let exports = read_to_string(&export_file).unwrap_or_else(|_| {
+        warn!(
+            "{} Export file '{}' not found, the archived export script only registers the Xtensa Rust toolchain",
+            emoji::WARN,
+            export_file.display()
+        );
+        String::new()
+    });
+    if config.esp_idf_version.is_some() {
+        warn!(
+            "{} The Python environment of ESP-IDF contains absolute paths, unpack the archive in '{}' to use it",
+            emoji::WARN,
+            tools_path.parent().unwrap_or(&tools_path).display()
+        );
+    }
+    // The header pins the paths of this host, which the export script relocates.
+    let exports = strip_header(&exports).join("\n");
+    let export_script = get_export_script(&exports, config, &tools_path, &entries);
+
+    write_archive(output, compression, &entries, &export_script)?;
+    info!(
+        "{} Archived the environment in '{}', unpack it and source '{}' to use it",
+        emoji::CHECK,
+        output.display(),
+        EXPORT_SCRIPT
+    );
+    Ok(())
+}
+
+/// Archives the installed environment into `output`, or prints the commands and the
+/// environment reproducing the installation in `format`.
+pub fn export(
+    context: &mut Context,
+    format: Option<ProvisionFormat>,
+    output: &Path,
+    no_wait: bool,
+) -> Result<(), Error> {
+    let config = context.load_config()?;
+    let context = &*context;
+    if let Some(format) = format {
+        let export_file = get_export_file(context.env.as_deref(), config.export_file.clone())?;
+        let environment = get_environment(&export_file).unwrap_or_default();
+        println!(
+            "{}",
+            render(format, &config, &environment, env!("CARGO_PKG_VERSION"))
+        );
+        return Ok(());
+    }
+    info!("{} Archiving the environment", emoji::DISC);
+    let _lock = InstallLock::acquire(context, no_wait)?;
+    create_archive(context, &config, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        context::Context,
+        export::{
+            get_archive_entries, get_export_script, write_archive, ArchiveCompression,
+            EXPORT_SCRIPT,
+        },
+        host_triple::HostTriple,
+        toolchain::rust::XtensaRust,
+    };
+    use std::{
+        fs::{create_dir_all, write, File},
+        path::Path,
+    };
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_archive_format() {
+        assert_eq!(
+            ArchiveCompression::from_path(Path::new("env.tar.zst")).unwrap(),
+            ArchiveCompression::Zstd
+        );
+        assert_eq!(
+            ArchiveCompression::from_path(Path::new("/tmp/env.tgz")).unwrap(),
+            ArchiveCompression::Gzip
+        );
+        assert!(ArchiveCompression::from_path(Path::new("env.zip")).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_create_archive() {
+        let temp_dir = TempDir::new().unwrap();
+        let tools_path = temp_dir.path().join("espressif");
+        let gcc_bin_path = tools_path.join("tools").join("riscv32-esp-elf").join("bin");
+        create_dir_all(&gcc_bin_path).unwrap();
+        write(gcc_bin_path.join("riscv32-esp-elf-gcc"), "gcc").unwrap();
+        create_dir_all(tools_path.join("dist")).unwrap();
+        let toolchain_path = temp_dir
+            .path()
+            .join("rustup")
+            .join("toolchains")
+            .join("esp");
+        create_dir_all(&toolchain_path).unwrap();
+        let config = Config {
+            xtensa_rust: Some(XtensaRust {
+                toolchain_destination: toolchain_path,
+                ..XtensaRust::new(
+                    &Context::default(),
+                    "1.65.0.1",
+                    "esp",
+                    &HostTriple::X86_64UnknownLinuxGnu,
+                )
+            }),
+            ..Default::default()
+        };
+
+        let entries = get_archive_entries(&config, &tools_path);
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["toolchains/esp", "tools/tools"]);
+
+        let exports = format!("export PATH={}:$PATH\n", gcc_bin_path.display());
+        let script = get_export_script(&exports, &config, &tools_path, &entries);
+        assert!(script.contains("rustup toolchain link esp \"$ESPUP_ENV_DIR/toolchains/esp\""));
+        assert!(script.contains("export PATH=$ESPUP_ENV_DIR/tools/tools/riscv32-esp-elf/bin:$PATH"));
+
+        let output = temp_dir.path().join("env.tar.zst");
+        write_archive(&output, ArchiveCompression::Zstd, &entries, &script).unwrap();
+        let decoder = zstd::Decoder::new(File::open(&output).unwrap()).unwrap();
+        let paths: Vec<String> = tar::Archive::new(decoder)
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+            .collect();
+        assert!(paths.contains(&"tools/tools/riscv32-esp-elf/bin/riscv32-esp-elf-gcc".to_string()));
+        assert!(paths.contains(&EXPORT_SCRIPT.to_string()));
+        assert!(!paths.iter().any(|path| path.starts_with("tools/dist")));
+    }
+}
@@ -0,0 +1,67 @@
+//! Warm-up of the download cache, e.g. in CI: `espup cache fetch` downloads and verifies the
+//! archives of an installation into the cache without installing them, and prints a key
+//! identifying them, under which the CI saves and restores the cache.
+
+use crate::{
+    cache::get_archives_dir, context::Context, emoji, error::Error, plan::Plan,
+    toolchain::fetch_archive,
+};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+
+/// Prefix of the cache keys.
+const CACHE_KEY_PREFIX: &str = "espup-";
+
+/// Gets the cache key of the artifacts, from their URLs, which name their versions. It does
+/// not depend on their order nor on the mirror they are downloaded from, so that it can be
+/// computed before downloading them.
+pub fn get_cache_key(artifacts: &[String]) -> String {
+    let artifacts: BTreeSet<&String> = artifacts.iter().collect();
+    let mut hasher = Sha256::new();
+    for artifact in artifacts {
+        hasher.update(artifact.as_bytes());
+        hasher.update(b"\n");
+    }
+    let hash = format!("{:x}", hasher.finalize());
+    format!("{CACHE_KEY_PREFIX}{}", &hash[..16])
+}
+
+/// Downloads the artifacts of the plan into the cache, and returns their cache key.
+/// Repositories, e.g. ESP-IDF when not installed from an archive, are not cached.
+pub fn fetch_artifacts(context: &Context, plan: &Plan) -> Result<String, Error> {
+    for (url, _) in &plan.clones {
+        warn!(
+            "{} Repository {} is not cached, it is cloned when installing",
+            emoji::WARN,
+            url
+        );
+    }
+    let artifacts: BTreeSet<&String> = plan.artifacts.iter().collect();
+    for artifact in &artifacts {
+        fetch_archive(context, artifact)?;
+    }
+    info!(
+        "{} {} archives cached in '{}'",
+        emoji::CHECK,
+        artifacts.len(),
+        get_archives_dir(context).display()
+    );
+    Ok(get_cache_key(&plan.artifacts))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fetch::get_cache_key;
+
+    #[test]
+    fn test_get_cache_key() {
+        let gcc = "https://github.com/espressif/crosstool-NG/releases/download/esp-12.2.0_20230208/xtensa-esp32-elf-12.2.0_20230208-x86_64-linux-gnu.tar.xz".to_string();
+        let llvm = "https://github.com/espressif/llvm-project/releases/download/esp-16.0.4-20231113/libs-clang-esp-16.0.4-20231113-x86_64-linux-gnu.tar.xz".to_string();
+        let key = get_cache_key(&[gcc.clone(), llvm.clone()]);
+        assert!(key.starts_with("espup-"));
+        assert_eq!(key.len(), "espup-".len() + 16);
+        assert_eq!(key, get_cache_key(&[llvm.clone(), gcc.clone(), llvm]));
+        assert_ne!(key, get_cache_key(&[gcc]));
+    }
+}
@@ -0,0 +1,227 @@
+//! Machine-readable header of the export file, as comments at its top: the espup version
+//! that generated it, the components it sets up, pinned to their versions, and the SHA-256
+//! hash of its exports. The export file also exports the hash, so that `espup doctor`, and
+//! other tools, detect an export file that no longer matches the installation and shells
+//! that sourced an outdated one.
+
+use crate::{
+    context::Context,
+    list::{get_installed_components, Component},
+    prune::mentions,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+/// Prefix of the lines of the header, comments in POSIX shells and PowerShell.
+const HEADER_PREFIX: &str = "# espup-";
+/// Variable exported with the hash of the exports, to detect shells that sourced an
+/// outdated export file.
+pub const EXPORTS_HASH_VARIABLE: &str = "ESPUP_EXPORTS_SHA256";
+
+/// Component set up by the export file, e.g.:
+///
+/// ```sh
+/// # espup-component: {"name":"Xtensa LLVM","version":"esp-16.0.4","path":"/home/esp/.espressif/tools/esp-clang/esp-16.0.4"}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PinnedComponent {
+    /// Name of the component.
+    pub name: String,
+    /// Installed version.
+    pub version: String,
+    /// Installation path.
+    pub path: PathBuf,
+}
+
+impl From<&Component> for PinnedComponent {
+    fn from(component: &Component) -> Self {
+        Self {
+            name: component.name.clone(),
+            version: component.version.clone(),
+            path: component.path.clone(),
+        }
+    }
+}
+
+/// Header of the export file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportHeader {
+    /// Version of espup that generated the export file.
+    pub generated_by: String,
+    /// Components set up by the export file.
+    pub components: Vec<PinnedComponent>,
+    /// SHA-256 hash of the exports, see [`hash_exports`].
+    pub sha256: String,
+}
+
+impl ExportHeader {
+    /// Formats the header as comment lines.
+    fn format(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{HEADER_PREFIX}generated-by: {}",
+            self.generated_by
+        )];
+        lines.extend(self.components.iter().filter_map(|component| {
+            serde_json::to_string(component)
+                .ok()
+                .map(|component| format!("{HEADER_PREFIX}component: {component}"))
+        }));
+        lines.push(format!("{HEADER_PREFIX}sha256: {}", self.sha256));
+        lines
+    }
+}
+
+/// Formats the assignment of the hash of the exports.
+fn format_hash_export(sha256: &str) -> String {
+    #[cfg(windows)]
+    return format!("$Env:{EXPORTS_HASH_VARIABLE}=\"{sha256}\"");
+    #[cfg(unix)]
+    return format!("export {EXPORTS_HASH_VARIABLE}=\"{sha256}\"");
+}
+
+/// Gets the exports of the contents of an export file, without its header and the
+/// assignment of their hash.
+pub fn strip_header(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .filter(|line| !line.starts_with(HEADER_PREFIX) && !line.contains(EXPORTS_HASH_VARIABLE))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Hashes the exports of the contents of an export file, ignoring its header.
+pub fn hash_exports(contents: &str) -> String {
+    let mut hasher = Sha256::new();
+    for line in strip_header(contents) {
+        hasher.update(line.as_bytes());
+        hasher.update(b"\n");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Gets the installed components that the exports set up, e.g. the toolchains added to
+/// `PATH`.
+pub fn get_exported_components(context: &Context, exports: &[String]) -> Vec<Component> {
+    let exports = exports.join("\n");
+    get_installed_components(context, None)
+        .into_iter()
+        .filter(|component| mentions(&exports, &component.path))
+        .collect()
+}
+
+/// Formats the contents of an export file: the header, the exports and the assignment of
+/// their hash.
+pub fn format_export_file(exports: &[String], components: &[Component]) -> String {
+    let sha256 = hash_exports(&exports.join("\n"));
+    let header = ExportHeader {
+        generated_by: env!("CARGO_PKG_VERSION").to_string(),
+        components: components.iter().map(PinnedComponent::from).collect(),
+        sha256: sha256.clone(),
+    };
+    let mut lines = header.format();
+    lines.extend(exports.iter().cloned());
+    lines.push(format_hash_export(&sha256));
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Parses the header of the contents of an export file, if it has one.
+pub fn parse_header(contents: &str) -> Option<ExportHeader> {
+    let mut header = ExportHeader::default();
+    for line in contents.lines() {
+        let (key, value) = match line
+            .strip_prefix(HEADER_PREFIX)
+            .and_then(|line| line.split_once(": "))
+        {
+            Some(entry) => entry,
+            None => break,
+        };
+        match key {
+            "generated-by" => header.generated_by = value.to_string(),
+            "component" => header.components.extend(serde_json::from_str(value).ok()),
+            "sha256" => header.sha256 = value.to_string(),
+            _ => {}
+        }
+    }
+    if header.sha256.is_empty() {
+        return None;
+    }
+    Some(header)
+}
+
+/// Compares the components pinned in the header with the installed ones, describing the
+/// ones that are no longer installed or changed version.
+pub fn get_mismatches(header: &ExportHeader, installed: &[Component]) -> Vec<String> {
+    header
+        .components
+        .iter()
+        .filter_map(|pinned| {
+            match installed
+                .iter()
+                .find(|component| component.path == pinned.path)
+            {
+                None => Some(format!(
+                    "{} {} is no longer installed",
+                    pinned.name, pinned.version
+                )),
+                Some(component) if component.version != pinned.version => Some(format!(
+                    "{} is {}, not {}",
+                    pinned.name, component.version, pinned.version
+                )),
+                Some(_) => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        header::{
+            format_export_file, get_mismatches, hash_exports, parse_header, strip_header,
+            PinnedComponent,
+        },
+        list::Component,
+    };
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_export_header() {
+        let llvm = Component {
+            name: "Xtensa LLVM".to_string(),
+            version: "esp-16.0.4".to_string(),
+            path: PathBuf::from("/esp/tools/esp-clang/esp-16.0.4"),
+        };
+        let exports = vec![
+            "export LIBCLANG_PATH=\"/esp/tools/esp-clang/esp-16.0.4/esp-clang/lib\"".to_string(),
+        ];
+        let contents = format_export_file(&exports, std::slice::from_ref(&llvm));
+        assert!(contents.starts_with("# espup-generated-by: "));
+        assert_eq!(strip_header(&contents), exports);
+
+        let header = parse_header(&contents).unwrap();
+        assert_eq!(header.generated_by, env!("CARGO_PKG_VERSION"));
+        assert_eq!(header.components, vec![PinnedComponent::from(&llvm)]);
+        assert_eq!(header.sha256, hash_exports(&contents));
+        assert_ne!(
+            header.sha256,
+            hash_exports(&contents.replace("esp-16.0.4/", "esp-17.0.1/"))
+        );
+        assert!(parse_header(&exports.join("\n")).is_none());
+
+        assert!(get_mismatches(&header, std::slice::from_ref(&llvm)).is_empty());
+        assert_eq!(
+            get_mismatches(&header, &[]),
+            vec!["Xtensa LLVM esp-16.0.4 is no longer installed"]
+        );
+        let updated = Component {
+            version: "esp-17.0.1".to_string(),
+            ..llvm
+        };
+        assert_eq!(
+            get_mismatches(&header, &[updated]),
+            vec!["Xtensa LLVM is esp-17.0.1, not esp-16.0.4"]
+        );
+    }
+}
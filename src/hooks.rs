@@ -0,0 +1,187 @@
+//! Scripts run before and after the installation, or one of its components, configured in
+//! the `[hooks]` table of the configuration file, e.g.:
+//!
+//! ```toml
+//! [hooks]
+//! pre-install = "cat /etc/espup/license-banner.txt"
+//! post-install = "cargo install --locked espflash"
+//!
+//! [hooks.components.esp-idf]
+//! post-install = "/opt/scripts/upload-cache.sh \"$ESPUP_INSTALL_DIR\""
+//! ```
+//!
+//! The scripts run in `sh` (PowerShell on Windows) with the exports of the components
+//! installed so far applied, along with `ESPUP_HOOK`, `ESPUP_COMPONENT` and
+//! `ESPUP_INSTALL_DIR`.
+
+use crate::{context::Context, emoji, error::Error};
+use embuild::cmd;
+use log::info;
+use miette::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Hooks of a component, e.g. `esp-idf`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ComponentHooks {
+    /// Script run before installing the component.
+    pub pre_install: Option<String>,
+    /// Script run once the component is installed.
+    pub post_install: Option<String>,
+}
+
+/// Hooks of the installation.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Hooks {
+    /// Script run before installing anything, once the installation is confirmed.
+    pub pre_install: Option<String>,
+    /// Script run once the installation is completed.
+    pub post_install: Option<String>,
+    /// Hooks of the components, by name: `xtensa-rust`, `llvm`, `riscv-target`, `esp-idf`,
+    /// `gcc`, `openocd`, `gdb`, `qemu`, `dfu-util`, `rom-elfs` and `extra-crates`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub components: BTreeMap<String, ComponentHooks>,
+}
+
+impl Hooks {
+    /// Whether no hook is configured.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Runs the pre-install hook, if any.
+    pub fn pre_install(&self, context: &Context, exports: &[String]) -> Result<(), Error> {
+        match &self.pre_install {
+            Some(script) => run_hook(context, "pre-install", None, script, exports),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs the post-install hook, if any.
+    pub fn post_install(&self, context: &Context, exports: &[String]) -> Result<(), Error> {
+        match &self.post_install {
+            Some(script) => run_hook(context, "post-install", None, script, exports),
+            None => Ok(()),
+        }
+    }
+
+    /// Installs a component between its hooks, adding its exports to `exports`.
+    pub fn install_component(
+        &self,
+        context: &Context,
+        component: &str,
+        exports: &mut Vec<String>,
+        install: impl FnOnce() -> Result<Vec<String>>,
+    ) -> Result<()> {
+        let hooks = self.components.get(component);
+        if let Some(script) = hooks.and_then(|hooks| hooks.pre_install.as_ref()) {
+            run_hook(context, "pre-install", Some(component), script, exports)?;
+        }
+        exports.extend(context.summary.measure(component, install)?);
+        if let Some(script) = hooks.and_then(|hooks| hooks.post_install.as_ref()) {
+            run_hook(context, "post-install", Some(component), script, exports)?;
+        }
+        Ok(())
+    }
+}
+
+/// Gets the script run by the shell: the exports, followed by the hook.
+fn get_shell_script(script: &str, exports: &[String]) -> String {
+    exports
+        .iter()
+        .map(String::as_str)
+        .chain([script])
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Runs the script of a hook, failing when it does.
+fn run_hook(
+    context: &Context,
+    hook: &str,
+    component: Option<&str>,
+    script: &str,
+    exports: &[String],
+) -> Result<(), Error> {
+    let name = match component {
+        Some(component) => format!("{component} {hook}"),
+        None => hook.to_string(),
+    };
+    info!("{} Running the {} hook", emoji::WRENCH, name);
+    let shell_script = get_shell_script(script, exports);
+    #[cfg(windows)]
+    let mut command = cmd!("powershell", "-NoProfile", "-Command", shell_script);
+    #[cfg(unix)]
+    let mut command = cmd!("sh", "-c", shell_script);
+    command
+        .envs(context.envs())
+        .env("ESPUP_HOOK", hook)
+        .env("ESPUP_COMPONENT", component.unwrap_or_default())
+        .env("ESPUP_INSTALL_DIR", &context.tools_path);
+    command
+        .run()
+        .map_err(|e| Error::HookFailed(name, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        context::Context,
+        hooks::{get_shell_script, ComponentHooks, Hooks},
+    };
+
+    #[test]
+    fn test_deserialize_hooks() {
+        let hooks: Hooks = toml::from_str(
+            r#"
+            pre-install = "echo banner"
+            [components.esp-idf]
+            post-install = "upload-cache.sh"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(hooks.pre_install.as_deref(), Some("echo banner"));
+        assert_eq!(
+            hooks.components.get("esp-idf"),
+            Some(&ComponentHooks {
+                pre_install: None,
+                post_install: Some("upload-cache.sh".to_string()),
+            })
+        );
+        assert!(Hooks::default().is_empty());
+        assert_eq!(
+            get_shell_script(
+                "cargo espflash",
+                &["export PATH=/opt/bin:$PATH".to_string()]
+            ),
+            "export PATH=/opt/bin:$PATH\ncargo espflash"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_install_component() {
+        let mut hooks = Hooks::default();
+        hooks.components.insert(
+            "gdb".to_string(),
+            ComponentHooks {
+                pre_install: Some("test \"$ESPUP_COMPONENT\" = gdb".to_string()),
+                post_install: Some("test \"$GDB_PATH\" = /opt/gdb".to_string()),
+            },
+        );
+        let context = Context::default();
+        let mut exports = Vec::new();
+        hooks
+            .install_component(&context, "gdb", &mut exports, || {
+                Ok(vec!["export GDB_PATH=/opt/gdb".to_string()])
+            })
+            .unwrap();
+        assert_eq!(exports.len(), 1);
+        hooks.components.get_mut("gdb").unwrap().post_install = Some("exit 1".to_string());
+        assert!(hooks
+            .install_component(&context, "gdb", &mut exports, || Ok(Vec::new()))
+            .is_err());
+    }
+}
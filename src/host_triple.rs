@@ -1,12 +1,20 @@
-use crate::error::Error;
+use crate::{emoji, error::Error, packages::get_command_output};
 use guess_host_triple::guess_host_triple;
+use log::{info, warn};
 use miette::Result;
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use std::{
+    env,
+    fs::read_dir,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 use strum::Display;
-use strum_macros::EnumString;
+use strum_macros::{EnumIter, EnumString};
 
-#[derive(Display, Debug, Clone, EnumString, Deserialize, Serialize, Default)]
+#[derive(
+    Display, Debug, Clone, PartialEq, Eq, EnumIter, EnumString, Deserialize, Serialize, Default,
+)]
 pub enum HostTriple {
     /// 64-bit Linux
     #[strum(serialize = "x86_64-unknown-linux-gnu")]
@@ -15,6 +23,12 @@ pub enum HostTriple {
     /// ARM64 Linux
     #[strum(serialize = "aarch64-unknown-linux-gnu")]
     Aarch64UnknownLinuxGnu,
+    /// 64-bit Linux with musl libc, e.g. Alpine Linux
+    #[strum(serialize = "x86_64-unknown-linux-musl")]
+    X86_64UnknownLinuxMusl,
+    /// ARM64 Linux with musl libc
+    #[strum(serialize = "aarch64-unknown-linux-musl")]
+    Aarch64UnknownLinuxMusl,
     /// 64-bit MSVC
     #[strum(serialize = "x86_64-pc-windows-msvc")]
     X86_64PcWindowsMsvc,
@@ -29,7 +43,122 @@ pub enum HostTriple {
     Aarch64AppleDarwin,
 }
 
-/// Parse the host triple if specified, otherwise guess it.
+/// ABI of the Windows hosts, which the Rust toolchain and the linker have to match.
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq, EnumString)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum HostAbi {
+    /// Microsoft Visual C++, linking with the Visual Studio Build Tools.
+    Msvc,
+    /// MinGW-w64, linking with its GCC.
+    Gnu,
+}
+
+impl HostTriple {
+    /// Returns true if the host runs Windows.
+    pub fn is_windows(&self) -> bool {
+        matches!(self, Self::X86_64PcWindowsMsvc | Self::X86_64PcWindowsGnu)
+    }
+
+    /// Returns the Windows host with the ABI `abi`. Other hosts have a single ABI.
+    pub fn with_abi(&self, abi: HostAbi) -> Result<Self, Error> {
+        match (self.is_windows(), abi) {
+            (true, HostAbi::Msvc) => Ok(Self::X86_64PcWindowsMsvc),
+            (true, HostAbi::Gnu) => Ok(Self::X86_64PcWindowsGnu),
+            (false, _) => Err(Error::UnsupportedHostAbi(abi.to_string(), self.to_string())),
+        }
+    }
+
+    /// Returns the host whose binaries run emulated on this one: x86_64 macOS binaries run
+    /// under Rosetta 2 on Apple Silicon, and glibc binaries run with a glibc compatibility
+    /// layer on musl hosts.
+    pub fn get_emulated_host(&self) -> Option<Self> {
+        match self {
+            Self::Aarch64AppleDarwin => Some(Self::X86_64AppleDarwin),
+            Self::X86_64UnknownLinuxMusl | Self::Aarch64UnknownLinuxMusl => {
+                Some(self.get_glibc_host())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns whether the host uses musl libc.
+    pub fn is_musl(&self) -> bool {
+        matches!(
+            self,
+            Self::X86_64UnknownLinuxMusl | Self::Aarch64UnknownLinuxMusl
+        )
+    }
+
+    /// Returns the glibc host of the same architecture as a musl host, whose builds are
+    /// installed for the tools that do not publish musl ones, or the host itself.
+    pub fn get_glibc_host(&self) -> Self {
+        match self {
+            Self::X86_64UnknownLinuxMusl => Self::X86_64UnknownLinuxGnu,
+            Self::Aarch64UnknownLinuxMusl => Self::Aarch64UnknownLinuxGnu,
+            _ => self.clone(),
+        }
+    }
+}
+
+/// Gets the host to install a component for: the native one when the component provides
+/// artifacts for it, otherwise the emulated one, if the component provides artifacts for it.
+pub fn get_component_host(
+    host_triple: &HostTriple,
+    is_available: impl Fn(&HostTriple) -> bool,
+) -> Option<HostTriple> {
+    if is_available(host_triple) {
+        Some(host_triple.clone())
+    } else {
+        host_triple
+            .get_emulated_host()
+            .filter(|emulated_host| is_available(emulated_host))
+    }
+}
+
+/// Logs that a component is installed for an emulated host.
+pub fn log_emulated_component(
+    component: &str,
+    host_triple: &HostTriple,
+    component_host: &HostTriple,
+) {
+    if host_triple.is_musl() && host_triple != component_host {
+        warn!(
+            "{} {} has no '{}' build, installing the '{}' one, which requires a glibc compatibility layer",
+            emoji::WARN,
+            component,
+            host_triple,
+            component_host
+        );
+    } else if host_triple != component_host {
+        info!(
+            "{} {} has no native '{}' build, installing the '{}' one, which runs emulated. On Apple Silicon, Rosetta 2 is required: 'softwareupdate --install-rosetta'",
+            emoji::INFO,
+            component,
+            host_triple,
+            component_host
+        );
+    }
+}
+
+/// Directory of the dynamic loaders, where musl installs its own.
+const LOADER_DIR: &str = "/lib";
+
+impl HostTriple {
+    /// Gets the paths of the glibc dynamic loader of the host, also provided by glibc
+    /// compatibility layers such as gcompat.
+    fn get_glibc_loaders(&self) -> &'static [&'static str] {
+        match self.get_glibc_host() {
+            Self::X86_64UnknownLinuxGnu => {
+                &["/lib64/ld-linux-x86-64.so.2", "/lib/ld-linux-x86-64.so.2"]
+            }
+            Self::Aarch64UnknownLinuxGnu => &["/lib/ld-linux-aarch64.so.1"],
+            _ => &[],
+        }
+    }
+}
+
+/// Parse the host triple if specified, otherwise guess it. The tools without musl builds
+/// install their glibc ones on musl hosts, see [`check_libc`].
 pub fn get_host_triple(host_triple_arg: Option<String>) -> Result<HostTriple, Error> {
     let host_triple = if let Some(host_triple) = &host_triple_arg {
         host_triple
@@ -40,9 +169,161 @@ pub fn get_host_triple(host_triple_arg: Option<String>) -> Result<HostTriple, Er
     HostTriple::from_str(host_triple).map_err(|_| Error::UnsupportedHostTriple(host_triple.into()))
 }
 
+/// Gets the installation path of the Visual Studio Build Tools, or of Visual Studio, with
+/// the MSVC toolset, using `vswhere`.
+pub fn get_vs_build_tools() -> Option<String> {
+    let vswhere = PathBuf::from(env::var_os("ProgramFiles(x86)")?)
+        .join("Microsoft Visual Studio")
+        .join("Installer")
+        .join("vswhere.exe");
+    get_command_output(
+        vswhere.to_str()?,
+        &[
+            "-latest",
+            "-products",
+            "*",
+            "-requires",
+            "Microsoft.VisualStudio.Component.VC.Tools.x86.x64",
+            "-property",
+            "installationPath",
+        ],
+    )
+    .filter(|path| !path.is_empty())
+}
+
+/// Checks that the linker of the ABI of a Windows host is installed: the MSVC linker of the
+/// Visual Studio Build Tools, or the GCC of MinGW-w64. Linkers of other hosts are checked
+/// with the system packages.
+pub fn check_host_linker(host_triple: &HostTriple) -> Result<(), Error> {
+    match host_triple {
+        HostTriple::X86_64PcWindowsMsvc if get_vs_build_tools().is_none() => {
+            Err(Error::MissingHostLinker(
+                host_triple.to_string(),
+                "Install the Visual Studio Build Tools with the 'Desktop development with C++' workload, e.g. 'winget install Microsoft.VisualStudio.2022.BuildTools --override \"--add Microsoft.VisualStudio.Workload.VCTools --includeRecommended\"'".to_string(),
+            ))
+        }
+        HostTriple::X86_64PcWindowsGnu if get_command_output("gcc", &["--version"]).is_none() => {
+            Err(Error::MissingHostLinker(
+                host_triple.to_string(),
+                "Install MinGW-w64, e.g. 'pacman -S mingw-w64-x86_64-gcc' in MSYS2, and add its 'bin' directory to PATH".to_string(),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Gets the host triple, see [`get_host_triple`], with the ABI `host_abi` on Windows.
+pub fn get_host_triple_with_abi(
+    host_triple_arg: Option<String>,
+    host_abi: Option<HostAbi>,
+) -> Result<HostTriple, Error> {
+    let host_triple = get_host_triple(host_triple_arg)?;
+    match host_abi {
+        Some(host_abi) => host_triple.with_abi(host_abi),
+        None => Ok(host_triple),
+    }
+}
+
+/// Whether a loader directory contains the musl dynamic loader, e.g. `ld-musl-x86_64.so.1`.
+fn has_musl_loader(loader_dir: &Path) -> bool {
+    read_dir(loader_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .any(|entry| entry.file_name().to_string_lossy().starts_with("ld-musl-"))
+}
+
+/// Whether the host uses musl libc, e.g. Alpine Linux.
+pub fn is_musl() -> bool {
+    cfg!(target_os = "linux") && has_musl_loader(Path::new(LOADER_DIR))
+}
+
+/// Checks that the prebuilt tools, linked against glibc, can run on the host: on musl hosts,
+/// they require a glibc compatibility layer. The RISC-V nightly toolchain is installed by
+/// rustup and sccache is static, both provide musl builds.
+pub fn check_libc(host_triple: &HostTriple) -> Result<(), Error> {
+    if host_triple.is_musl() {
+        warn!(
+            "{} No '{}' build of GCC, LLVM, OpenOCD, GDB and QEMU is published, installing the '{}' ones",
+            emoji::WARN,
+            host_triple,
+            host_triple.get_glibc_host()
+        );
+    }
+    if !is_musl() {
+        return Ok(());
+    }
+    if host_triple
+        .get_glibc_loaders()
+        .iter()
+        .any(|loader| Path::new(loader).exists())
+    {
+        warn!(
+            "{} musl libc detected, the prebuilt tools run with the glibc compatibility layer",
+            emoji::WARN
+        );
+        Ok(())
+    } else {
+        Err(Error::UnsupportedMuslHost(host_triple.to_string()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::host_triple::{get_host_triple, HostTriple};
+    use crate::host_triple::{
+        get_component_host, get_host_triple, has_musl_loader, HostAbi, HostTriple,
+    };
+    use std::{fs::write, str::FromStr};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_has_musl_loader() {
+        let temp_dir = TempDir::new().unwrap();
+        write(temp_dir.path().join("ld-linux-x86-64.so.2"), "").unwrap();
+        assert!(!has_musl_loader(temp_dir.path()));
+        write(temp_dir.path().join("ld-musl-x86_64.so.1"), "").unwrap();
+        assert!(has_musl_loader(temp_dir.path()));
+    }
+
+    #[test]
+    fn test_with_abi() {
+        assert_eq!(HostAbi::from_str("MSVC").unwrap(), HostAbi::Msvc);
+        assert!(HostAbi::from_str("musl").is_err());
+        assert_eq!(
+            HostTriple::X86_64PcWindowsMsvc
+                .with_abi(HostAbi::Gnu)
+                .unwrap(),
+            HostTriple::X86_64PcWindowsGnu
+        );
+        assert_eq!(
+            HostTriple::X86_64PcWindowsGnu
+                .with_abi(HostAbi::Msvc)
+                .unwrap(),
+            HostTriple::X86_64PcWindowsMsvc
+        );
+        assert!(HostTriple::X86_64UnknownLinuxGnu
+            .with_abi(HostAbi::Gnu)
+            .is_err());
+    }
+
+    #[test]
+    fn test_get_component_host() {
+        let host_triple = HostTriple::Aarch64AppleDarwin;
+        assert_eq!(
+            get_component_host(&host_triple, |_| true),
+            Some(HostTriple::Aarch64AppleDarwin)
+        );
+        assert_eq!(
+            get_component_host(&host_triple, |host| *host == HostTriple::X86_64AppleDarwin),
+            Some(HostTriple::X86_64AppleDarwin)
+        );
+        assert_eq!(
+            get_component_host(&HostTriple::Aarch64UnknownLinuxGnu, |host| {
+                *host == HostTriple::X86_64UnknownLinuxGnu
+            }),
+            None
+        );
+    }
 
     #[test]
     fn test_get_host_triple() {
@@ -71,6 +352,21 @@ mod tests {
             Ok(HostTriple::Aarch64AppleDarwin)
         ));
 
+        assert!(matches!(
+            get_host_triple(Some("x86_64-unknown-linux-musl".to_string())),
+            Ok(HostTriple::X86_64UnknownLinuxMusl)
+        ));
+        assert_eq!(
+            HostTriple::Aarch64UnknownLinuxMusl.get_glibc_host(),
+            HostTriple::Aarch64UnknownLinuxGnu
+        );
+        assert_eq!(
+            get_component_host(&HostTriple::X86_64UnknownLinuxMusl, |host| {
+                *host == HostTriple::X86_64UnknownLinuxGnu
+            }),
+            Some(HostTriple::X86_64UnknownLinuxGnu)
+        );
+
         assert!(get_host_triple(Some("some-fake-triple".to_string())).is_err());
 
         // Guessed Host Triples
@@ -0,0 +1,830 @@
+//! Shared HTTP client used for downloads and GitHub API queries.
+
+use crate::{
+    config::Config, emoji, error::Error, interrupt::is_interrupted, targets::Target,
+    toolchain::espidf::DEFAULT_GIT_REPOSITORY,
+};
+use log::{debug, warn};
+use once_cell::sync::OnceCell;
+use reqwest::{
+    blocking::{Client, ClientBuilder},
+    redirect::Policy,
+    Certificate, NoProxy, Proxy, StatusCode,
+};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::read,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    slice,
+    str::FromStr,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// Espressif's mirror of the GitHub release assets.
+const ESPRESSIF_ASSETS_MIRROR: &str = "https://dl.espressif.com/github_assets";
+/// Jihulab's mirror of the ESP-IDF repository.
+const JIHULAB_ESP_IDF_REPOSITORY: &str = "https://jihulab.com/esp-mirror/espressif/esp-idf";
+/// GitHub URL prefix rewritten by the mirrors.
+const GITHUB_URL: &str = "https://github.com";
+/// Components whose base URL can be overridden, besides the GCC toolchain of a single
+/// target, e.g. `gcc-esp32`.
+pub const URL_COMPONENTS: [&str; 11] = [
+    "dfu-util",
+    "esp-adf",
+    "esp-idf",
+    "esp-matter",
+    "gcc",
+    "gdb",
+    "llvm",
+    "openocd",
+    "qemu",
+    "rom-elfs",
+    "rust",
+];
+/// Default timeout, in seconds, of the connection to a server.
+pub const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+/// Default timeout, in seconds, of stalled reads and writes.
+pub const DEFAULT_READ_TIMEOUT: u64 = 30;
+/// Period, in seconds, over which the transfer rate of downloads is compared to the
+/// `low_speed_limit`.
+const LOW_SPEED_TIME: u64 = 30;
+/// Interval, in seconds, of the TCP keep-alive probes of the pooled connections.
+const TCP_KEEPALIVE: u64 = 60;
+/// Default number of retries of failed requests.
+pub const DEFAULT_RETRIES: u32 = 3;
+/// Default delay, in seconds, before the first retry. It doubles with every retry.
+pub const DEFAULT_RETRY_DELAY: u64 = 1;
+
+/// Location where artifacts and repositories are downloaded from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Mirror {
+    /// GitHub, the original location.
+    #[default]
+    Github,
+    /// Espressif's download server, for release assets.
+    Espressif,
+    /// Espressif's download server for release assets and Jihulab for ESP-IDF.
+    Jihulab,
+    /// Custom server replicating the GitHub layout.
+    Custom(String),
+}
+
+impl Mirror {
+    /// Parses a mirror name or the URL of a custom mirror.
+    pub fn parse(arg: &str) -> Result<Self, Error> {
+        match arg.to_lowercase().as_str() {
+            "github" => Ok(Self::Github),
+            "espressif" => Ok(Self::Espressif),
+            "jihulab" => Ok(Self::Jihulab),
+            _ if arg.starts_with("http://") || arg.starts_with("https://") => {
+                Ok(Self::Custom(arg.trim_end_matches('/').to_string()))
+            }
+            _ => Err(Error::InvalidMirror(arg.to_string())),
+        }
+    }
+
+    /// Base URL replacing `https://github.com` for release assets, if any.
+    fn assets_url(&self) -> Option<&str> {
+        match self {
+            Self::Github => None,
+            Self::Espressif | Self::Jihulab => Some(ESPRESSIF_ASSETS_MIRROR),
+            Self::Custom(url) => Some(url),
+        }
+    }
+
+    /// Rewrites a GitHub URL to point to the mirror.
+    pub fn rewrite_url(&self, url: &str) -> String {
+        match (self.assets_url(), url.strip_prefix(GITHUB_URL)) {
+            (Some(assets_url), Some(path)) => format!("{assets_url}{path}"),
+            _ => url.to_string(),
+        }
+    }
+
+    /// Returns the URL of the ESP-IDF repository.
+    pub fn esp_idf_repository(&self) -> String {
+        match self {
+            Self::Github | Self::Espressif => DEFAULT_GIT_REPOSITORY.to_string(),
+            Self::Jihulab => JIHULAB_ESP_IDF_REPOSITORY.to_string(),
+            Self::Custom(url) => format!("{url}/espressif/esp-idf"),
+        }
+    }
+}
+
+impl fmt::Display for Mirror {
+    /// Formats the mirror as it is parsed, e.g. in the `mirrors` of the configuration file.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Github => write!(f, "github"),
+            Self::Espressif => write!(f, "espressif"),
+            Self::Jihulab => write!(f, "jihulab"),
+            Self::Custom(url) => write!(f, "{url}"),
+        }
+    }
+}
+
+/// TLS implementation of the HTTPS requests, among the ones enabled by the `native-tls`
+/// and `rustls` features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsBackend {
+    /// TLS library of the system: SChannel on Windows, Security.framework on macOS and
+    /// OpenSSL elsewhere.
+    #[cfg(feature = "native-tls")]
+    NativeTls,
+    /// rustls, trusting the Mozilla root certificates.
+    #[cfg(feature = "rustls")]
+    Rustls,
+}
+
+impl TlsBackend {
+    /// Parses the name of a TLS backend enabled in this build.
+    pub fn parse(arg: &str) -> Result<Self, Error> {
+        get_tls_backends()
+            .into_iter()
+            .find(|backend| backend.to_string() == arg.to_lowercase())
+            .ok_or_else(|| {
+                let backends: Vec<String> = get_tls_backends()
+                    .iter()
+                    .map(|backend| backend.to_string())
+                    .collect();
+                Error::UnsupportedTlsBackend(arg.to_string(), backends.join(", "))
+            })
+    }
+}
+
+impl Default for TlsBackend {
+    /// The native TLS library when it is enabled, as it uses the certificates of the system.
+    fn default() -> Self {
+        get_tls_backends()[0]
+    }
+}
+
+impl fmt::Display for TlsBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls => write!(f, "native-tls"),
+            #[cfg(feature = "rustls")]
+            Self::Rustls => write!(f, "rustls"),
+        }
+    }
+}
+
+/// Gets the TLS backends enabled in this build.
+fn get_tls_backends() -> Vec<TlsBackend> {
+    vec![
+        #[cfg(feature = "native-tls")]
+        TlsBackend::NativeTls,
+        #[cfg(feature = "rustls")]
+        TlsBackend::Rustls,
+    ]
+}
+
+/// Settings of the HTTP client.
+#[derive(Debug, Clone)]
+pub struct HttpSettings {
+    /// Additional root certificate, in PEM or DER format, trusted by the client.
+    pub cacert: Option<PathBuf>,
+    /// Timeout, in seconds, of the connection to a server. When not set, the `connect` value
+    /// of the `[timeouts]` table of the configuration file is used, if any.
+    pub connect_timeout: Option<u64>,
+    /// Token used to authenticate GitHub API requests.
+    pub github_token: Option<String>,
+    /// Maximum transfer rate of downloads, in bytes per second.
+    pub limit_rate: Option<u64>,
+    /// Minimum transfer rate of downloads, in bytes per second, under which they fail.
+    pub low_speed_limit: Option<u64>,
+    /// Mirrors used for release assets, in order of preference, the first one being also used
+    /// for repositories. When empty, the `mirrors` of the configuration file are used, if any,
+    /// otherwise GitHub.
+    pub mirrors: Vec<Mirror>,
+    /// Proxy used for HTTP and HTTPS requests. When not set, the `HTTP_PROXY`,
+    /// `HTTPS_PROXY` and `NO_PROXY` environment variables are honored.
+    pub proxy: Option<String>,
+    /// Timeout, in seconds, of stalled reads and writes, after which the request is retried.
+    /// When not set, the `read` value of the `[timeouts]` table of the configuration file is
+    /// used, if any.
+    pub read_timeout: Option<u64>,
+    /// Number of retries of requests that failed due to a transient error.
+    pub retries: u32,
+    /// Delay, in seconds, before the first retry. It doubles with every retry.
+    pub retry_delay: u64,
+    /// TLS implementation of the HTTPS requests.
+    pub tls_backend: TlsBackend,
+    /// Base URLs replacing the default locations of the components, e.g. an artifact
+    /// proxy, from the `[urls]` table of the configuration file.
+    pub urls: BTreeMap<String, String>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            cacert: None,
+            connect_timeout: None,
+            github_token: None,
+            limit_rate: None,
+            low_speed_limit: None,
+            mirrors: Vec::new(),
+            proxy: None,
+            read_timeout: None,
+            retries: DEFAULT_RETRIES,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            tls_backend: TlsBackend::default(),
+            urls: BTreeMap::new(),
+        }
+    }
+}
+
+/// Mirror used when none is configured.
+static DEFAULT_MIRROR: Mirror = Mirror::Github;
+
+/// HTTP client of an invocation, along with its settings. The clients are built on their
+/// first request and shared by the following ones, reusing the kept-alive connections.
+#[derive(Debug, Default)]
+pub struct Http {
+    settings: HttpSettings,
+    client: OnceCell<Client>,
+    api_client: OnceCell<Client>,
+}
+
+impl Http {
+    /// Creates the client of the given settings, completed by the `[timeouts]` and the
+    /// `mirrors` of the configuration file of the named environment `env`, if any.
+    pub fn new(settings: HttpSettings, env: Option<&str>) -> Result<Self, Error> {
+        Ok(Self {
+            settings: complete_settings(settings, env)?,
+            ..Default::default()
+        })
+    }
+
+    /// Returns the settings of the client.
+    pub fn settings(&self) -> &HttpSettings {
+        &self.settings
+    }
+
+    /// Returns the client, following the redirects of the release assets.
+    pub fn client(&self) -> Result<&Client, Error> {
+        self.client.get_or_try_init(|| build_client(&self.settings))
+    }
+
+    /// Returns the client of the GitHub API, which does not follow redirects.
+    pub fn api_client(&self) -> Result<&Client, Error> {
+        self.api_client
+            .get_or_try_init(|| build_api_client(&self.settings))
+    }
+
+    /// Returns the preferred mirror, used for release assets and repositories.
+    pub fn mirror(&self) -> &Mirror {
+        &self.mirrors()[0]
+    }
+
+    /// Returns the mirrors used for release assets, in order of preference, or GitHub when
+    /// none is configured.
+    fn mirrors(&self) -> &[Mirror] {
+        match self.settings.mirrors.as_slice() {
+            [] => slice::from_ref(&DEFAULT_MIRROR),
+            mirrors => mirrors,
+        }
+    }
+
+    /// Returns the URLs a file is downloaded from, in order of preference.
+    pub fn mirror_urls(&self, url: &str) -> Vec<String> {
+        rewrite_urls(self.mirrors(), url)
+    }
+
+    /// Returns the base URL of a component: the one configured in the `[urls]` table, if
+    /// any, otherwise `default`.
+    pub fn base_url(&self, component: &str, default: &str) -> String {
+        self.settings
+            .urls
+            .get(component)
+            .map_or(default, |url| url.trim_end_matches('/'))
+            .to_string()
+    }
+
+    /// Returns the URL of the ESP-IDF repository: the one configured in the `[urls]` table,
+    /// if any, otherwise the one of the mirror.
+    pub fn esp_idf_repository(&self) -> String {
+        self.base_url("esp-idf", &self.mirror().esp_idf_repository())
+    }
+
+    /// Returns the token used to authenticate GitHub API requests, if any.
+    pub fn github_token(&self) -> Option<&str> {
+        self.settings.github_token.as_deref()
+    }
+
+    /// Returns the maximum transfer rate of downloads, in bytes per second, if any.
+    pub fn limit_rate(&self) -> Option<u64> {
+        self.settings.limit_rate
+    }
+
+    /// Returns the minimum transfer rate of downloads, in bytes per second, if any.
+    pub fn low_speed_limit(&self) -> Option<u64> {
+        self.settings.low_speed_limit
+    }
+
+    /// Returns the environment variables passing the proxy and the mirror to the programs
+    /// espup runs, e.g. git, pip and ESP-IDF's `idf_tools.py`, which read them from their
+    /// environment.
+    pub fn envs(&self) -> Vec<(&'static str, String)> {
+        let mut envs = Vec::new();
+        if let Some(proxy) = &self.settings.proxy {
+            envs.push(("HTTP_PROXY", proxy.clone()));
+            envs.push(("HTTPS_PROXY", proxy.clone()));
+        }
+        if let Some(assets_url) = self.mirror().assets_url() {
+            envs.push((
+                "IDF_GITHUB_ASSETS",
+                assets_url
+                    .trim_start_matches("https://")
+                    .trim_start_matches("http://")
+                    .to_string(),
+            ));
+        }
+        envs
+    }
+
+    /// Runs a network operation, retrying it with exponential backoff when it fails due to
+    /// a transient error.
+    pub fn retry<T>(
+        &self,
+        description: &str,
+        mut operation: impl FnMut() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut delay = self.settings.retry_delay;
+        let mut attempt = 0;
+        loop {
+            match operation() {
+                Err(e) if attempt < self.settings.retries && is_transient(&e) => {
+                    attempt += 1;
+                    warn!(
+                        "{} {} failed: {}. Retrying in {}s ({}/{})",
+                        emoji::WARN,
+                        description,
+                        e,
+                        delay,
+                        attempt,
+                        self.settings.retries
+                    );
+                    sleep(Duration::from_secs(delay));
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Completes the settings with the `[timeouts]` and the `mirrors` of the configuration file,
+/// checking them.
+fn complete_settings(mut settings: HttpSettings, env: Option<&str>) -> Result<HttpSettings, Error> {
+    let timeouts = Config::load_timeouts(env);
+    settings.connect_timeout = settings.connect_timeout.or(timeouts.connect);
+    settings.read_timeout = settings.read_timeout.or(timeouts.read);
+    if settings.mirrors.is_empty() {
+        settings.mirrors = Config::load_mirrors(env)
+            .iter()
+            .map(|mirror| Mirror::parse(mirror))
+            .collect::<Result<_, _>>()?;
+    }
+    if let (Some(limit_rate), Some(low_speed_limit)) =
+        (settings.limit_rate, settings.low_speed_limit)
+    {
+        if limit_rate <= low_speed_limit {
+            warn!(
+                "{} Ignoring the low speed limit, downloads are limited to a lower rate",
+                emoji::WARN
+            );
+            settings.low_speed_limit = None;
+        }
+    }
+    if let Some(cacert) = &settings.cacert {
+        load_certificate(cacert)?;
+    }
+    if let Some(proxy) = &settings.proxy {
+        parse_proxy(proxy)?;
+    }
+    for component in settings.urls.keys() {
+        if !is_url_component(component) {
+            warn!(
+                "{} Ignoring the URL of unknown component '{}', supported components: {}, gcc-<target>",
+                emoji::WARN,
+                component,
+                URL_COMPONENTS.join(", ")
+            );
+        }
+    }
+    debug!(
+        "{} HTTP settings:
+            - CA certificate: {:?}
+            - GitHub token: {}
+            - Limit rate: {:?} (low speed limit: {:?})
+            - Mirrors: {:?}
+            - Proxy: {:?}
+            - Retries: {} (delay: {}s)
+            - TLS backend: {}
+            - Timeouts: {:?} (connect), {:?} (read)
+            - URLs: {:?}",
+        emoji::DEBUG,
+        settings.cacert,
+        if settings.github_token.is_some() {
+            "set"
+        } else {
+            "not set"
+        },
+        settings.limit_rate,
+        settings.low_speed_limit,
+        settings.mirrors,
+        settings.proxy,
+        settings.retries,
+        settings.retry_delay,
+        settings.tls_backend,
+        settings.connect_timeout,
+        settings.read_timeout,
+        settings.urls
+    );
+    Ok(settings)
+}
+
+/// Whether the base URL of a component can be overridden.
+fn is_url_component(component: &str) -> bool {
+    URL_COMPONENTS.contains(&component)
+        || component
+            .strip_prefix("gcc-")
+            .map_or(false, |target| Target::from_str(target).is_ok())
+}
+
+/// Parses a proxy URL, which applies to HTTP and HTTPS requests.
+fn parse_proxy(proxy: &str) -> Result<Proxy, Error> {
+    Proxy::all(proxy)
+        .map(|proxy| proxy.no_proxy(NoProxy::from_env()))
+        .map_err(|_| Error::InvalidProxy(proxy.to_string()))
+}
+
+/// Loads a certificate in PEM or DER format.
+fn load_certificate(path: &Path) -> Result<Certificate, Error> {
+    let error = || Error::FailedToLoadCertificate(path.display().to_string());
+    let certificate = read(path).map_err(|_| error())?;
+    Certificate::from_pem(&certificate)
+        .or_else(|_| Certificate::from_der(&certificate))
+        .map_err(|_| error())
+}
+
+/// Creates the builder of a client with the given settings.
+fn client_builder(settings: &HttpSettings) -> Result<ClientBuilder, Error> {
+    // The client is shared by all the requests, reusing the kept-alive connections to a host
+    // for the successive downloads. Stalled requests fail with a timeout, and are retried.
+    let mut builder = ClientBuilder::new()
+        .user_agent("espup")
+        .connect_timeout(Duration::from_secs(
+            settings.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT),
+        ))
+        .timeout(Duration::from_secs(
+            settings.read_timeout.unwrap_or(DEFAULT_READ_TIMEOUT),
+        ))
+        .tcp_keepalive(Duration::from_secs(TCP_KEEPALIVE));
+    builder = match settings.tls_backend {
+        #[cfg(feature = "native-tls")]
+        TlsBackend::NativeTls => builder.use_native_tls(),
+        #[cfg(feature = "rustls")]
+        TlsBackend::Rustls => builder.use_rustls_tls(),
+    };
+    if let Some(proxy) = &settings.proxy {
+        builder = builder.proxy(parse_proxy(proxy)?);
+    }
+    if let Some(cacert) = &settings.cacert {
+        builder = builder.add_root_certificate(load_certificate(cacert)?);
+    }
+    Ok(builder)
+}
+
+/// Builds a client with the given settings.
+fn build_client(settings: &HttpSettings) -> Result<Client, Error> {
+    Ok(client_builder(settings)?.build()?)
+}
+
+/// Builds a client of the GitHub API with the given settings. Redirects are not followed,
+/// so that API responses are never taken from another host.
+fn build_api_client(settings: &HttpSettings) -> Result<Client, Error> {
+    Ok(client_builder(settings)?.redirect(Policy::none()).build()?)
+}
+
+/// Rewrites a URL for each mirror, without duplicates, e.g. of the URLs that no mirror
+/// rewrites.
+fn rewrite_urls(mirrors: &[Mirror], url: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for mirror_url in mirrors.iter().map(|mirror| mirror.rewrite_url(url)) {
+        if !urls.contains(&mirror_url) {
+            urls.push(mirror_url);
+        }
+    }
+    urls
+}
+
+/// Parses a transfer rate in bytes per second, optionally followed by a `k`, `m` or `g`
+/// multiplier, e.g. `500k`.
+pub fn parse_limit_rate(arg: &str) -> Result<u64, Error> {
+    let error = || Error::InvalidLimitRate(arg.to_string());
+    let arg = arg.trim().to_lowercase();
+    let (number, multiplier) = match arg.chars().last().ok_or_else(error)? {
+        'k' => (&arg[..arg.len() - 1], 1024),
+        'm' => (&arg[..arg.len() - 1], 1024 * 1024),
+        'g' => (&arg[..arg.len() - 1], 1024 * 1024 * 1024),
+        _ => (arg.as_str(), 1),
+    };
+    match number.parse::<u64>() {
+        Ok(number) if number > 0 => number.checked_mul(multiplier).ok_or_else(error),
+        _ => Err(error()),
+    }
+}
+
+/// Reader sleeping as needed to keep the transfer rate under `limit_rate` bytes per second.
+pub struct RateLimitedReader<R> {
+    inner: R,
+    limit_rate: Option<u64>,
+    start: Instant,
+    bytes: u64,
+}
+
+impl<R: Read> RateLimitedReader<R> {
+    /// Wraps a reader, which is not limited when `limit_rate` is `None`.
+    pub fn new(inner: R, limit_rate: Option<u64>) -> Self {
+        Self {
+            inner,
+            limit_rate,
+            start: Instant::now(),
+            bytes: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for RateLimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let limit_rate = match self.limit_rate {
+            Some(limit_rate) => limit_rate,
+            None => return self.inner.read(buf),
+        };
+        let chunk_size = buf.len().min(limit_rate.clamp(1, 64 * 1024) as usize);
+        let read = self.inner.read(&mut buf[..chunk_size])?;
+        self.bytes += read as u64;
+        let expected = Duration::from_secs_f64(self.bytes as f64 / limit_rate as f64);
+        if let Some(ahead) = expected.checked_sub(self.start.elapsed()) {
+            sleep(ahead);
+        }
+        Ok(read)
+    }
+}
+
+/// Reader failing with a timeout once its transfer rate stays under `low_speed_limit` bytes
+/// per second for [`LOW_SPEED_TIME`] seconds.
+pub struct LowSpeedReader<R> {
+    inner: R,
+    low_speed_limit: Option<u64>,
+    period: Duration,
+    start: Instant,
+    bytes: u64,
+}
+
+impl<R: Read> LowSpeedReader<R> {
+    /// Wraps a reader, which never fails when `low_speed_limit` is `None`.
+    pub fn new(inner: R, low_speed_limit: Option<u64>) -> Self {
+        Self {
+            inner,
+            low_speed_limit,
+            period: Duration::from_secs(LOW_SPEED_TIME),
+            start: Instant::now(),
+            bytes: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for LowSpeedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        let low_speed_limit = match self.low_speed_limit {
+            Some(low_speed_limit) => low_speed_limit,
+            None => return Ok(read),
+        };
+        self.bytes += read as u64;
+        let elapsed = self.start.elapsed();
+        if elapsed >= self.period {
+            let rate = self.bytes as f64 / elapsed.as_secs_f64();
+            if rate < low_speed_limit as f64 {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "transfer rate of {rate:.0} bytes/s under the low speed limit for {}s",
+                        self.period.as_secs()
+                    ),
+                ));
+            }
+            self.start = Instant::now();
+            self.bytes = 0;
+        }
+        Ok(read)
+    }
+}
+
+/// Copies a reader into a writer, sleeping as needed to keep the transfer rate under
+/// `limit_rate` bytes per second.
+pub fn copy_with_limit(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    limit_rate: Option<u64>,
+) -> io::Result<u64> {
+    io::copy(&mut RateLimitedReader::new(reader, limit_rate), writer)
+}
+
+/// Whether an error is worth retrying: network errors and server side failures.
+pub fn is_transient(error: &Error) -> bool {
+    // Interrupted downloads fail with I/O errors, which are not retried.
+    if is_interrupted() {
+        return false;
+    }
+    match error {
+        Error::RewquestError(e) => match e.status() {
+            Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+            None => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+        },
+        Error::IoError(_) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::Error,
+        http::{
+            build_api_client, build_client, copy_with_limit, get_tls_backends, is_transient,
+            is_url_component, parse_limit_rate, rewrite_urls, HttpSettings, LowSpeedReader, Mirror,
+            TlsBackend,
+        },
+    };
+    use reqwest::StatusCode;
+    use std::{
+        io::{self, Read, Write},
+        net::TcpListener,
+        path::PathBuf,
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn test_parse_limit_rate() {
+        assert_eq!(parse_limit_rate("1000").unwrap(), 1000);
+        assert_eq!(parse_limit_rate("500k").unwrap(), 500 * 1024);
+        assert_eq!(parse_limit_rate("2M").unwrap(), 2 * 1024 * 1024);
+        assert!(parse_limit_rate("0").is_err());
+        assert!(parse_limit_rate("k").is_err());
+        assert!(parse_limit_rate("fast").is_err());
+        assert!(parse_limit_rate("18446744073709551615g").is_err());
+    }
+
+    #[test]
+    fn test_copy_with_limit() {
+        let data = vec![7; 4096];
+        let mut output = Vec::new();
+        assert_eq!(
+            copy_with_limit(&mut data.as_slice(), &mut output, Some(1024 * 1024)).unwrap(),
+            4096
+        );
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn test_low_speed_reader() {
+        let data = vec![7; 4096];
+        let mut output = Vec::new();
+        LowSpeedReader::new(data.as_slice(), Some(1024 * 1024 * 1024))
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, data);
+        let mut reader = LowSpeedReader::new(data.as_slice(), Some(u64::MAX));
+        reader.period = Duration::ZERO;
+        assert_eq!(
+            reader.read_to_end(&mut output).unwrap_err().kind(),
+            io::ErrorKind::TimedOut
+        );
+    }
+
+    #[test]
+    fn test_api_client_redirects() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/repos", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            stream
+                .write_all(
+                    b"HTTP/1.1 302 Found\r\nLocation: http://127.0.0.1:1/moved\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .unwrap();
+        });
+        let response = build_api_client(&HttpSettings::default())
+            .unwrap()
+            .get(&url)
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FOUND);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_build_client() {
+        for tls_backend in get_tls_backends() {
+            let settings = HttpSettings {
+                tls_backend,
+                ..Default::default()
+            };
+            assert!(build_client(&settings).is_ok());
+            assert!(build_client(&HttpSettings {
+                proxy: Some("http://proxy.example.com:8080".to_string()),
+                ..settings.clone()
+            })
+            .is_ok());
+            assert!(build_client(&HttpSettings {
+                connect_timeout: Some(30),
+                read_timeout: Some(120),
+                ..settings.clone()
+            })
+            .is_ok());
+            assert!(build_client(&HttpSettings {
+                proxy: Some("not a proxy".to_string()),
+                ..settings.clone()
+            })
+            .is_err());
+            assert!(build_client(&HttpSettings {
+                cacert: Some(PathBuf::from("missing-cacert.pem")),
+                ..settings.clone()
+            })
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn test_tls_backend() {
+        for tls_backend in get_tls_backends() {
+            assert_eq!(
+                TlsBackend::parse(&tls_backend.to_string()).unwrap(),
+                tls_backend
+            );
+        }
+        assert_eq!(TlsBackend::default(), get_tls_backends()[0]);
+        assert!(matches!(
+            TlsBackend::parse("openssl"),
+            Err(Error::UnsupportedTlsBackend(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(&Error::IoError(io::Error::from(
+            io::ErrorKind::ConnectionReset
+        ))));
+        assert!(!is_transient(&Error::FailedToSerializeJson));
+        assert!(!is_transient(&Error::InvalidProxy("proxy".to_string())));
+    }
+
+    #[test]
+    fn test_is_url_component() {
+        assert!(is_url_component("llvm"));
+        assert!(is_url_component("gcc-esp32c3"));
+        assert!(!is_url_component("gcc-esp33"));
+        assert!(!is_url_component("cmake"));
+    }
+
+    #[test]
+    fn test_mirror() {
+        let url = "https://github.com/espressif/crosstool-NG/releases/download/esp-2021r2-patch5/xtensa-esp32-elf.tar.gz";
+        assert_eq!(Mirror::parse("github").unwrap().rewrite_url(url), url);
+        assert_eq!(
+            Mirror::parse("Espressif").unwrap().rewrite_url(url),
+            "https://dl.espressif.com/github_assets/espressif/crosstool-NG/releases/download/esp-2021r2-patch5/xtensa-esp32-elf.tar.gz"
+        );
+        let custom = Mirror::parse("https://mirror.example.com/github/").unwrap();
+        assert_eq!(
+            custom.rewrite_url(url),
+            "https://mirror.example.com/github/espressif/crosstool-NG/releases/download/esp-2021r2-patch5/xtensa-esp32-elf.tar.gz"
+        );
+        assert_eq!(
+            custom.esp_idf_repository(),
+            "https://mirror.example.com/github/espressif/esp-idf"
+        );
+        assert_eq!(
+            Mirror::Jihulab.rewrite_url("https://api.github.com/repos/esp-rs/espup"),
+            "https://api.github.com/repos/esp-rs/espup"
+        );
+        assert!(Mirror::parse("gitee").is_err());
+        assert_eq!(
+            rewrite_urls(&[Mirror::Espressif, Mirror::Jihulab, Mirror::Github], url),
+            [
+                "https://dl.espressif.com/github_assets/espressif/crosstool-NG/releases/download/esp-2021r2-patch5/xtensa-esp32-elf.tar.gz",
+                url
+            ]
+        );
+    }
+}
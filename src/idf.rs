@@ -0,0 +1,184 @@
+//! Management of the installed ESP-IDF versions: switching between them, fetching their
+//! submodules and removing them.
+
+use crate::{
+    config::Config,
+    context::Context,
+    emoji,
+    environment::write_idf_export_scripts,
+    error::Error,
+    install::{export_environment, get_export_file},
+    list::format_size,
+    lock::InstallLock,
+    prune::{get_esp_idf_components, prune},
+    toolchain::{
+        espidf::{
+            get_esp_idf_install_dir, get_exports, link_current, unlink_current, update_submodules,
+            Submodules,
+        },
+        llvm::Llvm,
+    },
+};
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+
+/// Gets the directory of an ESP-IDF version installed by espup.
+pub fn get_installed_esp_idf_dir(
+    context: &Context,
+    config: &Config,
+    esp_idf_version: &str,
+) -> Result<PathBuf, Error> {
+    let espidf_dir = get_esp_idf_install_dir(
+        context,
+        config.esp_idf_repository.as_deref(),
+        esp_idf_version,
+    );
+    if !espidf_dir.exists() {
+        return Err(Error::EspIdfNotInstalled(esp_idf_version.to_string()));
+    }
+    Ok(espidf_dir)
+}
+
+/// Writes the export file, and the ESP-IDF compatible export scripts, for an installed
+/// ESP-IDF checkout, or without ESP-IDF when `None`, returning the export file.
+pub fn write_esp_idf_exports(
+    context: &Context,
+    config: &Config,
+    espidf_dir: Option<&Path>,
+) -> Result<PathBuf, Error> {
+    let mut exports: Vec<String> = Vec::new();
+    if let Some(llvm_path) = &config.llvm_path {
+        let llvm = Llvm {
+            path: llvm_path.clone(),
+            ..Default::default()
+        };
+        exports.extend(llvm.get_exports());
+    }
+    if let Some(espidf_dir) = espidf_dir {
+        exports.extend(get_exports(context, espidf_dir)?);
+    }
+    let export_file = get_export_file(context.env.as_deref(), config.export_file.clone())?;
+    export_environment(context, &export_file, &exports)?;
+    if let (Some(directory), Some(_)) = (&config.idf_export_scripts, espidf_dir) {
+        write_idf_export_scripts(directory, &export_file)?;
+    }
+    Ok(export_file)
+}
+
+/// Removes an installed ESP-IDF version, along with the tool versions that no other
+/// ESP-IDF version requires. With `dry_run`, only prints them.
+pub fn remove_esp_idf(
+    context: &mut Context,
+    esp_idf_version: &str,
+    dry_run: bool,
+    no_wait: bool,
+) -> Result<(), Error> {
+    info!("{} Removing ESP-IDF '{}'", emoji::WRENCH, esp_idf_version);
+    let mut config = context.load_config()?;
+    let context = &*context;
+    let espidf_dir = get_installed_esp_idf_dir(context, &config, esp_idf_version)?;
+    let _lock = InstallLock::acquire(context, no_wait)?;
+    let components = get_esp_idf_components(context, &config, &espidf_dir);
+    let mut size = 0;
+    for component in &components {
+        let component_size = component.size();
+        size += component_size;
+        println!(
+            "{:<20} {:<36} {:>10}  {}",
+            component.name,
+            component.version,
+            format_size(component_size),
+            component.path.display()
+        );
+    }
+    if dry_run {
+        info!("{} {} would be freed", emoji::INFO, format_size(size));
+        return Ok(());
+    }
+
+    let active = config.esp_idf_path.is_none()
+        && config
+            .esp_idf_version
+            .as_ref()
+            .map_or(false, |esp_idf_version| {
+                get_installed_esp_idf_dir(context, &config, esp_idf_version)
+                    .map_or(false, |active_dir| active_dir == espidf_dir)
+            });
+    prune(&components)?;
+    if active {
+        unlink_current(&espidf_dir);
+        config.esp_idf_version = None;
+        config.esp_idf_from_archive = None;
+        config.export_file = Some(write_esp_idf_exports(context, &config, None)?);
+        warn!(
+            "{} ESP-IDF '{}' was in use, the export file no longer sets up ESP-IDF",
+            emoji::WARN,
+            esp_idf_version
+        );
+    }
+    if let Some(receipt) = &mut config.receipt {
+        receipt.retain(|binary, _| {
+            !components
+                .iter()
+                .any(|component| binary.starts_with(&component.path))
+        });
+    }
+    info!("{} Saving configuration file", emoji::WRENCH);
+    config.save(context.env.as_deref())?;
+
+    info!(
+        "{} Removed ESP-IDF '{}', freeing {}",
+        emoji::CHECK,
+        esp_idf_version,
+        format_size(size)
+    );
+    Ok(())
+}
+
+/// Fetches every submodule of an installed ESP-IDF version, the one in use when `None`.
+pub fn fetch_submodules(
+    context: &mut Context,
+    esp_idf_version: Option<String>,
+    no_wait: bool,
+) -> Result<(), Error> {
+    let config = context.load_config()?;
+    let context = &*context;
+    let espidf_dir = match esp_idf_version.or_else(|| config.esp_idf_version.clone()) {
+        Some(esp_idf_version) => get_installed_esp_idf_dir(context, &config, &esp_idf_version)?,
+        None => match &config.esp_idf_path {
+            Some(esp_idf_path) => esp_idf_path.clone(),
+            None => return Err(Error::NoEspIdfInstalled),
+        },
+    };
+    if config.esp_idf_from_archive == Some(true) && !espidf_dir.join(".git").exists() {
+        info!(
+            "{} ESP-IDF was installed from its release archive, which includes every submodule",
+            emoji::CHECK
+        );
+        return Ok(());
+    }
+    let _lock = InstallLock::acquire(context, no_wait)?;
+    let shallow = espidf_dir.join(".git").join("shallow").exists();
+    update_submodules(context, &espidf_dir, shallow, &Submodules::All)?;
+    info!("{} Submodules updated", emoji::CHECK);
+    Ok(())
+}
+
+/// Regenerates the export file to use an installed ESP-IDF version.
+pub fn use_esp_idf(context: &mut Context, esp_idf_version: &str) -> Result<(), Error> {
+    info!("{} Switching to ESP-IDF '{}'", emoji::DISC, esp_idf_version);
+    let mut config = context.load_config()?;
+    let context = &*context;
+    let espidf_dir = get_installed_esp_idf_dir(context, &config, esp_idf_version)?;
+    link_current(&espidf_dir);
+    let export_file = write_esp_idf_exports(context, &config, Some(&espidf_dir))?;
+
+    info!("{} Saving configuration file", emoji::WRENCH);
+    config.esp_idf_path = None;
+    config.esp_idf_version = Some(esp_idf_version.to_string());
+    config.export_file = Some(export_file);
+    config.save(context.env.as_deref())?;
+
+    info!("{} Using ESP-IDF '{}'", emoji::CHECK, esp_idf_version);
+    Ok(())
+}
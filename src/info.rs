@@ -0,0 +1,265 @@
+//! Details of an installed component, printed by `espup info` to debug mismatched
+//! toolchains: where it was downloaded from, what it provides and which ESP-IDF versions
+//! require it.
+
+use crate::{
+    cache::get_extracted_archive,
+    config::Config,
+    context::Context,
+    error::Error,
+    list::{format_size, get_esp_idf_checkouts, get_installed_components, Component},
+    prune::read_esp_idf_tools,
+    sbom::get_origin,
+    verify::get_component_binaries,
+};
+use serde::Serialize;
+use std::{collections::BTreeSet, path::PathBuf};
+
+/// Details of an installed component.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentInfo {
+    /// Name of the component.
+    pub name: String,
+    /// Installed version.
+    pub version: String,
+    /// Installation path.
+    pub path: PathBuf,
+    /// Disk usage, in bytes.
+    pub size: u64,
+    /// URL the component was downloaded from or, when unknown, the repository it is
+    /// released from.
+    pub origin: Option<String>,
+    /// SHA-256 hash of the archive the component was extracted from.
+    pub sha256: Option<String>,
+    /// Executables the component provides.
+    pub tools: Vec<String>,
+    /// ESP-IDF checkouts requiring the component.
+    pub esp_idf_versions: Vec<String>,
+}
+
+impl ComponentInfo {
+    /// Formats the details as `key: value` lines.
+    pub fn format(&self) -> String {
+        let or_unknown =
+            |value: &Option<String>| value.clone().unwrap_or_else(|| "(unknown)".to_string());
+        let or_none = |values: &[String]| {
+            if values.is_empty() {
+                "(none)".to_string()
+            } else {
+                values.join(", ")
+            }
+        };
+        [
+            format!("Name: {}", self.name),
+            format!("Version: {}", self.version),
+            format!("Path: {}", self.path.display()),
+            format!("Size: {}", format_size(self.size)),
+            format!("Origin: {}", or_unknown(&self.origin)),
+            format!("SHA-256: {}", or_unknown(&self.sha256)),
+            format!("Tools: {}", or_none(&self.tools)),
+            format!("ESP-IDF versions: {}", or_none(&self.esp_idf_versions)),
+        ]
+        .join("\n")
+    }
+}
+
+/// Whether a component is the one named `name`: either by its name, e.g. `Xtensa Rust` or
+/// `xtensa-rust`, or by its tool directory, e.g. `esp-clang`, ignoring the case.
+fn matches_name(component: &Component, name: &str) -> bool {
+    let name = name.to_lowercase();
+    let component_name = component.name.to_lowercase();
+    component_name == name
+        || component_name.replace(' ', "-") == name
+        || component
+            .path
+            .parent()
+            .and_then(|tool_path| tool_path.file_name())
+            .map_or(false, |tool| tool.to_string_lossy().to_lowercase() == name)
+}
+
+/// Selects the installed components named `name`, of `version` when set. ESP-IDF versions
+/// also match their git reference, e.g. `v5.1` for `v5.1 (v5.1.2)`.
+pub fn select_components(
+    components: Vec<Component>,
+    name: &str,
+    version: Option<&str>,
+) -> Vec<Component> {
+    components
+        .into_iter()
+        .filter(|component| matches_name(component, name))
+        .filter(|component| {
+            version.map_or(true, |version| {
+                component.version == version
+                    || component.version.starts_with(&format!("{version} ("))
+            })
+        })
+        .collect()
+}
+
+/// Gets the ESP-IDF checkouts, managed by espup or not, whose tools include the component.
+fn get_requiring_esp_idf_versions(
+    context: &Context,
+    component: &Component,
+    config: Option<&Config>,
+) -> Vec<String> {
+    let tool_version = component
+        .path
+        .parent()
+        .and_then(|tool_path| tool_path.file_name())
+        .zip(component.path.file_name())
+        .map(|(tool, version)| {
+            (
+                tool.to_string_lossy().into_owned(),
+                version.to_string_lossy().into_owned(),
+            )
+        });
+    let tool_version = match tool_version {
+        Some(tool_version) => tool_version,
+        None => return Vec::new(),
+    };
+    let unmanaged = config
+        .and_then(|config| config.esp_idf_path.clone())
+        .map(|path| Component {
+            name: "ESP-IDF (unmanaged)".to_string(),
+            version: path.display().to_string(),
+            path,
+        });
+    get_esp_idf_checkouts(context)
+        .into_iter()
+        .chain(unmanaged)
+        .filter(|checkout| read_esp_idf_tools(&checkout.path).contains(&tool_version))
+        .map(|checkout| checkout.version)
+        .collect()
+}
+
+/// Gets the details of an installed component.
+pub fn get_component_info(
+    context: &Context,
+    component: &Component,
+    config: Option<&Config>,
+) -> ComponentInfo {
+    let archive = get_extracted_archive(context, &component.path);
+    let origin = archive
+        .as_ref()
+        .and_then(|(_, archive)| archive.urls.iter().next().cloned())
+        .or_else(|| get_origin(component, config));
+    let tools: BTreeSet<String> = get_component_binaries(component)
+        .iter()
+        .filter_map(|binary| binary.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect();
+    ComponentInfo {
+        name: component.name.clone(),
+        version: component.version.clone(),
+        path: component.path.clone(),
+        size: component.size(),
+        origin,
+        sha256: archive.map(|(hash, _)| hash),
+        tools: tools.into_iter().collect(),
+        esp_idf_versions: get_requiring_esp_idf_versions(context, component, config),
+    }
+}
+
+/// Prints the details of the installed versions of a component, or only of `version`, as
+/// text or as a JSON array with `json`.
+pub fn print_info(
+    context: &mut Context,
+    component: &str,
+    version: Option<&str>,
+    json: bool,
+) -> Result<(), Error> {
+    let config = context.load_config().ok();
+    let components = select_components(
+        get_installed_components(context, config.as_ref()),
+        component,
+        version,
+    );
+    if components.is_empty() {
+        let component = match version {
+            Some(version) => format!("{component} {version}"),
+            None => component.to_string(),
+        };
+        return Err(Error::ComponentNotInstalled(component));
+    }
+    let infos: Vec<_> = components
+        .iter()
+        .map(|component| get_component_info(context, component, config.as_ref()))
+        .collect();
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&infos).map_err(|_| Error::FailedToSerializeJson)?
+        );
+        return Ok(());
+    }
+    let formatted: Vec<_> = infos.iter().map(|info| info.format()).collect();
+    println!("{}", formatted.join("\n\n"));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        info::{select_components, ComponentInfo},
+        list::Component,
+    };
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_select_components() {
+        let component = |name: &str, version: &str, path: &str| Component {
+            name: name.to_string(),
+            version: version.to_string(),
+            path: PathBuf::from(path),
+        };
+        let components = vec![
+            component("Xtensa Rust", "1.74.0.0", "/rustup/toolchains/esp/1.74.0.0"),
+            component(
+                "Xtensa LLVM",
+                "esp-16.0.4",
+                "/esp/tools/esp-clang/esp-16.0.4",
+            ),
+            component("ESP-IDF", "v5.1 (v5.1.2)", "/esp/esp-idf/v5.1"),
+            component("ESP-IDF", "v4.4 (v4.4.6)", "/esp/esp-idf/v4.4"),
+        ];
+        assert_eq!(
+            select_components(components.clone(), "xtensa-rust", None).len(),
+            1
+        );
+        assert_eq!(
+            select_components(components.clone(), "esp-clang", None)[0].name,
+            "Xtensa LLVM"
+        );
+        assert_eq!(
+            select_components(components.clone(), "esp-idf", None).len(),
+            2
+        );
+        assert_eq!(
+            select_components(components.clone(), "ESP-IDF", Some("v5.1"))[0].path,
+            PathBuf::from("/esp/esp-idf/v5.1")
+        );
+        assert!(select_components(components, "esp-idf", Some("v5.0")).is_empty());
+    }
+
+    #[test]
+    fn test_format_component_info() {
+        let info = ComponentInfo {
+            name: "xtensa-esp32-elf".to_string(),
+            version: "esp-2022r1-11.2.0".to_string(),
+            path: PathBuf::from("/esp/tools/xtensa-esp32-elf/esp-2022r1-11.2.0"),
+            size: 3 * 1024 * 1024,
+            origin: None,
+            sha256: None,
+            tools: vec![
+                "xtensa-esp32-elf-gcc".to_string(),
+                "xtensa-esp32-elf-ld".to_string(),
+            ],
+            esp_idf_versions: Vec::new(),
+        };
+        let formatted = info.format();
+        assert!(formatted.contains("Size: 3.0 MiB"));
+        assert!(formatted.contains("Origin: (unknown)"));
+        assert!(formatted.contains("Tools: xtensa-esp32-elf-gcc, xtensa-esp32-elf-ld"));
+        assert!(formatted.contains("ESP-IDF versions: (none)"));
+    }
+}
@@ -0,0 +1,2017 @@
+//! Installation of the Rust for ESP chips environment.
+
+#[cfg(windows)]
+use crate::doctor::check_long_paths;
+#[cfg(windows)]
+use crate::environment::{write_batch_export_file, Shell};
+use crate::{
+    cache::{get_obsolete_entries, remove_entries},
+    config::Config,
+    context::Context,
+    diff::{diff_components, get_current_components, print_diff},
+    doctor::{check_python_requirements, get_existing_ancestor, Check, Status},
+    emoji,
+    environment::{
+        get_batch_export_file, get_conflicting_clang_variables, get_conflicting_variables,
+        get_idf_export_scripts, get_override_exports, normalize_exports, parse_exports,
+        write_idf_export_scripts, Environment,
+    },
+    envs::get_env_export_file_name,
+    error::Error,
+    fetch::{fetch_artifacts, get_cache_key},
+    header::{format_export_file, get_exported_components},
+    host_triple::{check_host_linker, check_libc, get_host_triple_with_abi, HostAbi, HostTriple},
+    idf::{get_installed_esp_idf_dir, write_esp_idf_exports},
+    interactive::is_interactive,
+    interrupt::is_interrupted,
+    list::{format_size, get_installed_components, get_tool_component, Component},
+    lock::InstallLock,
+    migrate::warn_legacy_layout,
+    packages::{check_system_packages, RequiredBy},
+    plan::Plan,
+    resume::is_installed,
+    smoke::run_smoke_test,
+    summary::{format_table, get_rows},
+    targets::{parse_esp_idf_version, select_esp_idf_targets, Target},
+    toolchain::{
+        create_staging_dir,
+        dfu_util::DfuUtil,
+        drivers::{get_missing_drivers, install_usb_drivers},
+        espidf::{
+            fast_forward, get_archive_url, get_ccache_dir, get_dist_path, get_esp_idf_install_dir,
+            get_esp_idf_remote, get_install_path, install_tools as install_esp_idf_tools,
+            is_ref_name, parse_esp_idf_ref, reset_to_ref, resolve_python, BranchUpdate,
+            EspIdfLayout, EspIdfRepo, Generator, MinifyProfile, Submodules,
+        },
+        frameworks::Framework,
+        gcc::{install_gcc_targets, Gcc, GccVersion},
+        gdb::get_gdb_targets,
+        get_local_artifacts, install_concurrently,
+        llvm::{Llvm, DEFAULT_LLVM_15_VERSION},
+        openocd::{get_openocd, install_openocd},
+        qemu::{get_qemu_targets, get_unemulated_targets},
+        rom_elfs::get_rom_elfs,
+        rust::{
+            check_rust_installation, get_riscv_targets, get_rustup_home, install_extra_crates,
+            install_riscv_target, Crate, XtensaRust, DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME,
+            LATEST_VERSION,
+        },
+        sccache::{get_sccache_dir, Sccache},
+        LocalArtifact,
+    },
+    udev::{install_udev_rules, UDEV_RULES_PATH},
+    verify::create_receipt,
+    wsl,
+};
+use console::Term;
+use dirs::home_dir;
+use embuild::{espidf::EspIdfVersion, git};
+use log::{debug, info, warn};
+use miette::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashSet},
+    env,
+    fs::{create_dir_all, remove_dir_all, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumString};
+
+#[cfg(windows)]
+pub const DEFAULT_EXPORT_FILE: &str = "export-esp.ps1";
+#[cfg(not(windows))]
+pub const DEFAULT_EXPORT_FILE: &str = "export-esp.sh";
+
+/// ESP-IDF version installed by the full profile, unless another one is given.
+pub const FULL_PROFILE_ESP_IDF_VERSION: &str = "v5.1";
+/// Extra crates installed by the full profile, along with the ones given.
+const FULL_PROFILE_CRATES: [&str; 2] = ["cargo-generate", "espflash"];
+
+/// Installation profile, selected with `--profile` and kept in the configuration file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString, Deserialize, Serialize)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// Minifies the installation, like `--profile-minimal`: the Rust toolchains and the GCC
+    /// toolchains of the targets.
+    Minimal,
+    /// Installs the components selected with the options.
+    Default,
+    /// Installs, along with the components selected with the options, ESP-IDF, OpenOCD, GDB,
+    /// QEMU and the `cargo-generate` and `espflash` crates.
+    Full,
+    /// Installs into `--install-dir` for container images: the shell profiles are left
+    /// untouched, the download cache is cleared and the environment is printed as `ENV`
+    /// instructions of a Dockerfile.
+    Container,
+}
+
+impl Profile {
+    /// Selects the components of the profile in `options`, on top of the ones already
+    /// selected. ESP-IDF and the extra crates, which need the rustup installation of the
+    /// current host, are left out of standalone environments and of the ones for another
+    /// host.
+    pub fn apply(&self, options: &mut InstallOptions) {
+        match self {
+            Self::Minimal => options.profile_minimal = true,
+            Self::Default => {}
+            Self::Full => {
+                options.with_gdb = true;
+                options.with_openocd = true;
+                options.with_qemu = true;
+                if options.target_host.is_none() && !options.standalone {
+                    if options.idf_path.is_none() {
+                        options
+                            .esp_idf_version
+                            .get_or_insert_with(|| FULL_PROFILE_ESP_IDF_VERSION.to_string());
+                    }
+                    options
+                        .extra_crates
+                        .get_or_insert_with(HashSet::new)
+                        .extend(FULL_PROFILE_CRATES.into_iter().map(Crate::new));
+                }
+            }
+            Self::Container => options.profile_container = true,
+        }
+        options.profile = Some(*self);
+    }
+}
+
+/// Component excluded from the installation and from the exported environment with `--skip`,
+/// e.g. when it is already installed system-wide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, EnumString)]
+#[strum(serialize_all = "kebab-case", ascii_case_insensitive)]
+pub enum SkippedComponent {
+    /// CMake, installed along with the ESP-IDF tools.
+    Cmake,
+    /// GCC toolchains of the targets, installed along with ESP-IDF or by espup.
+    Gcc,
+    /// Xtensa LLVM toolchain, like `--skip-llvm`.
+    Llvm,
+    /// Ninja, installed along with the ESP-IDF tools.
+    Ninja,
+    /// OpenOCD, like `--skip-openocd`.
+    Openocd,
+    /// Python virtual environment of ESP-IDF: an existing one, given with `--python-env` or
+    /// activated, is used as is, without linking it or installing the requirements of
+    /// ESP-IDF into it.
+    PythonEnv,
+    /// rust-src component of the Rust toolchains, required to build the standard library.
+    RustSrc,
+    /// Toolchains of the ULP coprocessors, like `--skip-ulp`.
+    Ulp,
+}
+
+const MIB: u64 = 1024 * 1024;
+// Estimated disk usage of the components, including their downloaded archives, used to
+// check the available space before installing them.
+const XTENSA_RUST_DISK_USAGE: u64 = 1200 * MIB;
+const LLVM_DISK_USAGE: u64 = 1400 * MIB;
+const LLVM_LIBS_DISK_USAGE: u64 = 350 * MIB;
+const GCC_DISK_USAGE: u64 = 300 * MIB;
+const ESP_IDF_DISK_USAGE: u64 = 2048 * MIB;
+const ESP_IDF_SHALLOW_DISK_USAGE: u64 = 1024 * MIB;
+const ESP_IDF_TOOLS_DISK_USAGE: u64 = 1536 * MIB;
+const TOOL_DISK_USAGE: u64 = 100 * MIB;
+/// Estimated disk usage above which the installation has to be confirmed.
+const CONFIRM_DISK_USAGE: u64 = 1024 * MIB;
+
+/// Options of an installation.
+#[derive(Debug, Clone)]
+pub struct InstallOptions {
+    /// Stores the downloaded archives in the cache.
+    pub cache_archives: bool,
+    /// Builds a hello world project with the installed toolchains once installed.
+    pub check: bool,
+    /// CMake generator used by ESP-IDF, or `None` to use Ninja when the ESP-IDF tools
+    /// provide a build for the host. Ninja is only installed for ninja generators.
+    pub cmake_generator: Option<Generator>,
+    /// Target triple of the host. Detected when not set.
+    pub default_host: Option<String>,
+    /// Prints the changes to the current environment instead of installing.
+    pub diff: bool,
+    /// Prints the actions that would be performed without performing them.
+    pub dry_run: bool,
+    /// Naming scheme of the ESP-IDF checkouts.
+    pub esp_idf_layout: EspIdfLayout,
+    /// ESP-IDF submodules to initialize.
+    pub esp_idf_submodules: Submodules,
+    /// ESP-IDF version to install, if any.
+    pub esp_idf_version: Option<String>,
+    /// Destination of the generated export file. Defaults to the home directory.
+    pub export_file: Option<PathBuf>,
+    /// Extra crates to install.
+    pub extra_crates: Option<HashSet<Crate>>,
+    /// Espressif frameworks to install along with ESP-IDF.
+    pub extra_frameworks: Vec<Framework>,
+    /// Downloads the archives into the cache and prints their cache key instead of
+    /// installing.
+    pub fetch: bool,
+    /// Installs even when the estimated disk usage exceeds the available disk space.
+    pub force: bool,
+    /// GCC versions to install, when not installing ESP-IDF.
+    pub gcc_version: Vec<GccVersion>,
+    /// ABI of the Windows host, whose linker has to be installed. Detected when not set.
+    pub host_abi: Option<HostAbi>,
+    /// Directory of the ESP-IDF compatible export scripts, if any.
+    pub idf_export_scripts: Option<PathBuf>,
+    /// Downloads the release archive of ESP-IDF instead of cloning it.
+    pub idf_from_archive: bool,
+    /// Path of an existing ESP-IDF checkout to use instead of cloning one.
+    pub idf_path: Option<PathBuf>,
+    /// Directory where the tools are installed.
+    pub install_dir: Option<PathBuf>,
+    /// Installs the missing system packages using the system package manager.
+    pub install_prereqs: bool,
+    /// Installs rustup when it is not installed.
+    pub install_rustup: bool,
+    /// Keeps the `com.apple.quarantine` attribute of the downloaded tools on macOS.
+    pub keep_quarantine: bool,
+    /// Installs only the LLVM libraries.
+    pub llvm_libs_only: bool,
+    /// LLVM release, e.g. `esp-15.0.0-20221201`.
+    pub llvm_version: String,
+    /// Local archives of the components, installed instead of downloading them.
+    pub local_artifact: Vec<LocalArtifact>,
+    /// ESP-IDF paths to keep in minified installations.
+    pub minify_keep: Vec<String>,
+    /// Additional ESP-IDF paths to exclude in minified installations.
+    pub minify_remove: Vec<String>,
+    /// Name of the Xtensa Rust toolchain in rustup.
+    pub name: String,
+    /// Nightly Rust toolchain version.
+    pub nightly_version: String,
+    /// Fails right away when another espup process is using the installation directory.
+    pub no_wait: bool,
+    /// Overrides, in the export file, the ESP-IDF environment already active in the shell.
+    pub override_env: bool,
+    /// Installation profile, saved in the configuration file.
+    pub profile: Option<Profile>,
+    /// Installs into `install_dir` for container images, without touching the shell
+    /// profiles, and prints the environment as `ENV` instructions of a Dockerfile.
+    pub profile_container: bool,
+    /// Minifies the installation.
+    pub profile_minimal: bool,
+    /// Removes, once installed, the cached archives of the versions no longer installed.
+    pub prune_cache: bool,
+    /// Python interpreter used to install ESP-IDF.
+    pub python: Option<PathBuf>,
+    /// Location of the Python virtual environment of ESP-IDF.
+    pub python_env: Option<PathBuf>,
+    /// Refuses the releases without a valid signature.
+    pub require_signatures: bool,
+    /// Clones only the last commit of ESP-IDF, the extra frameworks and their submodules,
+    /// instead of their full history.
+    pub shallow: bool,
+    /// Components excluded from the installation and from the exported environment.
+    pub skip: HashSet<SkippedComponent>,
+    /// Skips the LLVM installation.
+    pub skip_llvm: bool,
+    /// Skips the OpenOCD installation, which ESP-IDF installs by default on Windows.
+    pub skip_openocd: bool,
+    /// Skips the toolchains of the ULP coprocessors installed along with ESP-IDF.
+    pub skip_ulp: bool,
+    /// Directory of the temporary files and the staging directories of the extracted
+    /// archives, instead of the temporary directory of the system and the tools directory.
+    pub staging_dir: Option<PathBuf>,
+    /// Installs a hermetic environment into `install_dir`, without rustup: the Xtensa Rust
+    /// toolchain is used for every target and the export file selects it with `PATH` and
+    /// `RUSTC`.
+    pub standalone: bool,
+    /// Host triple of another machine to provision the environment for, into
+    /// `install_dir`. The Xtensa Rust toolchain is installed there instead of being
+    /// registered in rustup, and nothing that has to run on the host is installed.
+    pub target_host: Option<String>,
+    /// Targets to install the environment for.
+    pub targets: HashSet<Target>,
+    /// Xtensa Rust toolchain version: `latest`, `pre-release` or an exact version.
+    pub toolchain_version: String,
+    /// Verifies the signatures of the releases that publish one.
+    pub verify_signatures: bool,
+    /// Installs Espressif's ccache and enables it in the ESP-IDF builds.
+    pub with_ccache: bool,
+    /// Installs dfu-util, to flash the targets through USB DFU.
+    pub with_dfu_util: bool,
+    /// Installs Espressif's GDB.
+    pub with_gdb: bool,
+    /// Installs Espressif's OpenOCD, even without ESP-IDF.
+    pub with_openocd: bool,
+    /// Installs Espressif's QEMU fork.
+    pub with_qemu: bool,
+    /// Installs the ROM ELFs of the chips.
+    pub with_rom_elfs: bool,
+    /// Installs sccache and enables it in the Cargo and CMake builds.
+    pub with_sccache: bool,
+    /// Installs the udev rules of the USB devices of the chips, on Linux.
+    pub with_udev_rules: bool,
+    /// Installs the missing USB serial drivers, on Windows.
+    pub with_usb_drivers: bool,
+    /// Skips the confirmation of large installations.
+    pub yes: bool,
+}
+
+impl Default for InstallOptions {
+    fn default() -> Self {
+        Self {
+            cache_archives: false,
+            check: false,
+            cmake_generator: None,
+            default_host: None,
+            diff: false,
+            dry_run: false,
+            esp_idf_layout: EspIdfLayout::default(),
+            esp_idf_submodules: Submodules::default(),
+            esp_idf_version: None,
+            export_file: None,
+            extra_crates: None,
+            extra_frameworks: Vec::new(),
+            fetch: false,
+            force: false,
+            gcc_version: Vec::new(),
+            host_abi: None,
+            idf_export_scripts: None,
+            idf_from_archive: false,
+            idf_path: None,
+            install_dir: None,
+            install_prereqs: false,
+            install_rustup: false,
+            keep_quarantine: false,
+            llvm_libs_only: false,
+            llvm_version: DEFAULT_LLVM_15_VERSION.to_string(),
+            local_artifact: Vec::new(),
+            minify_keep: Vec::new(),
+            minify_remove: Vec::new(),
+            name: DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME.to_string(),
+            nightly_version: "nightly".to_string(),
+            no_wait: false,
+            override_env: false,
+            profile: None,
+            profile_container: false,
+            profile_minimal: false,
+            prune_cache: false,
+            python: None,
+            python_env: None,
+            require_signatures: false,
+            shallow: false,
+            skip: HashSet::new(),
+            skip_llvm: false,
+            skip_openocd: false,
+            skip_ulp: false,
+            staging_dir: None,
+            standalone: false,
+            target_host: None,
+            targets: Target::iter().collect(),
+            toolchain_version: LATEST_VERSION.to_string(),
+            verify_signatures: false,
+            with_ccache: false,
+            with_dfu_util: false,
+            with_gdb: false,
+            with_openocd: false,
+            with_qemu: false,
+            with_rom_elfs: false,
+            with_sccache: false,
+            with_udev_rules: false,
+            with_usb_drivers: false,
+            yes: false,
+        }
+    }
+}
+
+impl InstallOptions {
+    /// Whether a component is skipped, with `--skip` or its own option, e.g. `--skip-llvm`.
+    pub fn skips(&self, component: SkippedComponent) -> bool {
+        self.skip.contains(&component)
+            || match component {
+                SkippedComponent::Llvm => self.skip_llvm,
+                SkippedComponent::Openocd => self.skip_openocd,
+                SkippedComponent::Ulp => self.skip_ulp,
+                _ => false,
+            }
+    }
+
+    /// Gets the Python virtual environment of ESP-IDF: the one of `--python-env` or, when the
+    /// environment is skipped, the active one.
+    fn get_python_env(&self) -> Result<Option<PathBuf>, Error> {
+        if !self.skips(SkippedComponent::PythonEnv) {
+            return Ok(self.python_env.clone());
+        }
+        self.python_env
+            .clone()
+            .or_else(|| env::var_os("VIRTUAL_ENV").map(PathBuf::from))
+            .map(Some)
+            .ok_or(Error::MissingPythonEnv)
+    }
+}
+
+/// Options of an update of the installation.
+#[derive(Debug, Clone)]
+pub struct UpdateOptions {
+    /// Stores the downloaded archives in the cache.
+    pub cache_archives: bool,
+    /// Target triple of the host. Detected when not set.
+    pub default_host: Option<String>,
+    /// Prints the actions that would be performed without performing them.
+    pub dry_run: bool,
+    /// ABI of the Windows host, selecting the matching Xtensa Rust build.
+    pub host_abi: Option<HostAbi>,
+    /// Keeps the quarantine attribute of the downloaded toolchain on macOS.
+    pub keep_quarantine: bool,
+    /// Fails right away when another espup process is using the installation directory.
+    pub no_wait: bool,
+    /// Directory of the temporary files and of the archives being extracted.
+    pub staging_dir: Option<PathBuf>,
+    /// Xtensa Rust toolchain version: `latest`, `pre-release` or an exact version.
+    pub toolchain_version: String,
+}
+
+impl Default for UpdateOptions {
+    fn default() -> Self {
+        Self {
+            cache_archives: false,
+            default_host: None,
+            dry_run: false,
+            host_abi: None,
+            keep_quarantine: false,
+            no_wait: false,
+            staging_dir: None,
+            toolchain_version: LATEST_VERSION.to_string(),
+        }
+    }
+}
+
+/// Installs the Rust for ESP chips environment. On failure, everything created during the
+/// installation is removed, leaving the machine in its prior state. The options of the
+/// installation override the ones of `context`, e.g. its installation directory.
+pub fn install(context: &Context, options: InstallOptions) -> Result<()> {
+    // The environment provisioned for another host cannot be built on this one, and the
+    // standalone ones are not selected with `cargo +<toolchain>`.
+    let check = options.check && options.target_host.is_none() && !options.standalone;
+    // The lock is held until the rollback is completed.
+    let mut lock = None;
+    let mut result = install_esp_rs(context, options, &mut lock);
+    if result.is_err() {
+        if is_interrupted() {
+            warn!("{} Interrupted, rolling back the installation", emoji::WARN);
+            result = Err(Error::Interrupted.into());
+        }
+        context.rollback.rollback();
+    } else {
+        context.rollback.commit();
+    }
+    drop(lock);
+    // The timings of a failed installation are not summarized by the next one.
+    context.summary.take_timings();
+    // A failed check does not roll the installation back, it is reported instead.
+    if let Some((context, config)) = result? {
+        if check {
+            run_smoke_test(&context, &config)?;
+        }
+    }
+    Ok(())
+}
+
+/// Updates the installation recorded in the configuration file: the Xtensa Rust toolchain,
+/// the ESP-IDF checkouts of branches and references, and the components of its profile.
+pub fn update(context: &mut Context, options: UpdateOptions) -> Result<()> {
+    context.keep_quarantine |= options.keep_quarantine;
+    context.persist_archives |= options.cache_archives;
+    if let Some(staging_dir) = &options.staging_dir {
+        context.staging_dir = Some(create_staging_dir(staging_dir)?);
+    }
+
+    info!("{} Updating ESP Rust environment", emoji::DISC);
+    let host_triple = get_host_triple_with_abi(options.default_host, options.host_abi)?;
+    if options.host_abi.is_some() && !options.dry_run {
+        check_host_linker(&host_triple)?;
+    }
+    let mut config = context.load_config()?;
+    let context = &*context;
+    // The toolchain keeps the name it was installed with.
+    let name = config
+        .xtensa_rust
+        .as_ref()
+        .map_or(DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME, |xtensa_rust| {
+            &xtensa_rust.name
+        })
+        .to_string();
+    let (version, toolchain_host) =
+        XtensaRust::resolve_version(context, &options.toolchain_version, &host_triple)?;
+    let xtensa_rust = XtensaRust::new(context, &version, &name, &toolchain_host);
+
+    debug!(
+        "{} Arguments:
+            - Host triple: {}
+            - Toolchain version: {:#?}
+            - Config: {:#?}",
+        emoji::INFO,
+        host_triple,
+        xtensa_rust,
+        config
+    );
+
+    let mut plan = Plan::default();
+    let _lock = if options.dry_run {
+        None
+    } else {
+        Some(InstallLock::acquire(context, options.no_wait)?)
+    };
+    if let Some(config_xtensa_rust) = config.xtensa_rust.clone() {
+        if config_xtensa_rust.version == xtensa_rust.version {
+            info!(
+                "{} Toolchain '{}' is already up to date",
+                emoji::CHECK,
+                xtensa_rust.version
+            );
+        } else if options.dry_run {
+            plan.remove(&config_xtensa_rust.toolchain_destination);
+            add_xtensa_rust_to_plan(context, &mut plan, &xtensa_rust);
+        } else {
+            xtensa_rust.update(context, &config_xtensa_rust)?;
+            xtensa_rust.link(context)?;
+            config.xtensa_rust = Some(xtensa_rust);
+            config.receipt = Some(create_receipt(&get_installed_components(
+                context,
+                Some(&config),
+            )));
+        }
+    }
+
+    // Branches, unlike tags and commits, move: their checkout is fast-forwarded. References,
+    // e.g. of pull requests, are force-pushed: their checkout is reset.
+    if let Some(esp_idf_version) = config.esp_idf_version.clone() {
+        let reference = match parse_esp_idf_ref(&esp_idf_version) {
+            git::Ref::Branch(branch) => Some((branch, false)),
+            git::Ref::Commit(name) if is_ref_name(&name) => Some((name, true)),
+            _ => None,
+        };
+        if let Some((branch, reset)) = reference {
+            let espidf_dir = get_installed_esp_idf_dir(context, &config, &esp_idf_version)?;
+            let updated = if options.dry_run {
+                plan.command(format!(
+                    "git -C {} fetch origin {} && git -C {} {} FETCH_HEAD",
+                    espidf_dir.display(),
+                    branch,
+                    espidf_dir.display(),
+                    if reset {
+                        "reset --keep"
+                    } else {
+                        "merge --ff-only"
+                    }
+                ));
+                None
+            } else if reset {
+                Some(reset_to_ref(context, &espidf_dir, &branch)?)
+            } else {
+                Some(fast_forward(context, &espidf_dir, &branch)?)
+            };
+            if let Some(updated) = updated {
+                match updated {
+                    BranchUpdate::UpToDate => info!(
+                        "{} ESP-IDF '{}' is already up to date",
+                        emoji::CHECK,
+                        esp_idf_version
+                    ),
+                    BranchUpdate::Updated => {
+                        info!("{} ESP-IDF '{}' updated", emoji::CHECK, esp_idf_version)
+                    }
+                    BranchUpdate::ToolsChanged => {
+                        install_esp_idf_tools(context, &espidf_dir, &config.targets)?;
+                        config.export_file =
+                            Some(write_esp_idf_exports(context, &config, Some(&espidf_dir))?);
+                        info!(
+                            "{} ESP-IDF '{}' and its tools updated",
+                            emoji::CHECK,
+                            esp_idf_version
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // The profile of the installation is maintained: the minimal ones clear the download
+    // cache again, and the full ones install the extra crates that are missing.
+    match config.profile {
+        Some(Profile::Minimal) | Some(Profile::Container) => {
+            if options.dry_run {
+                plan.remove(get_dist_path(context, ""));
+            } else {
+                clear_dist_folder(context)?;
+            }
+        }
+        Some(Profile::Full) => {
+            for extra_crate in config.extra_crates.iter().flatten() {
+                if options.dry_run {
+                    plan.command(format!("cargo install {extra_crate} (if not installed)"));
+                } else {
+                    Crate::new(extra_crate).install(context)?;
+                }
+            }
+        }
+        Some(Profile::Default) | None => {}
+    }
+
+    if options.dry_run {
+        plan.print(context);
+        return Ok(());
+    }
+    config.save(context.env.as_deref())?;
+
+    info!("{} Update successfully completed!", emoji::CHECK);
+    Ok(())
+}
+
+/// Installs the components of the Rust for ESP chips environment, locking the installation
+/// directory with `lock`. Returns the context and the configuration of the installation,
+/// unless nothing was installed, e.g. on dry runs.
+fn install_esp_rs(
+    context: &Context,
+    options: InstallOptions,
+    lock: &mut Option<InstallLock>,
+) -> Result<Option<(Context, Config)>> {
+    info!("{} Installing esp-rs", emoji::DISC);
+    let mut context = context.clone();
+    context.keep_quarantine |= options.keep_quarantine;
+    context.persist_archives |= options.cache_archives;
+    if options.skips(SkippedComponent::RustSrc) {
+        context.install_rust_src = false;
+    }
+    context
+        .local_artifacts
+        .extend(get_local_artifacts(&options.local_artifact));
+    if let Some(staging_dir) = &options.staging_dir {
+        context.staging_dir = Some(create_staging_dir(staging_dir)?);
+    }
+    context.require_signatures |= options.require_signatures;
+    context.verify_signatures |= options.verify_signatures;
+    if options.profile_container {
+        if options.install_dir.is_none() {
+            return Err(Error::ContainerWithoutInstallDir.into());
+        }
+        context.modify_path = false;
+    }
+    if options.standalone {
+        check_standalone(&options)?;
+    }
+    if let Some(install_dir) = &options.install_dir {
+        context.rollback.track(install_dir);
+        create_dir_all(install_dir)
+            .map_err(|_| Error::FailedToCreateDirectory(install_dir.display().to_string()))?;
+        context.tools_path = install_dir
+            .canonicalize()
+            .map_err(|_| Error::FailedToCreateDirectory(install_dir.display().to_string()))?;
+        context.tools_path_overridden = true;
+    }
+    context.esp_idf_layout = options.esp_idf_layout;
+    if let Some(python) = &options.python {
+        if options.esp_idf_version.is_some() || options.idf_path.is_some() {
+            context.python = Some(resolve_python(python)?);
+        }
+    }
+    let context = &context;
+    warn_legacy_layout(context, options.esp_idf_layout);
+    info!(
+        "{} Installation directory: {}",
+        emoji::INFO,
+        context.tools_path()
+    );
+    let targets = options.targets.clone();
+    let mut target_names: Vec<String> = targets.iter().map(Target::to_string).collect();
+    target_names.sort();
+    info!("{} Targets: {}", emoji::INFO, target_names.join(", "));
+    if let Some(wsl) = wsl::detect() {
+        info!("{} Running under {}", emoji::INFO, wsl);
+        wsl::check_install_path(wsl, &context.tools_path);
+    }
+    let host_triple = get_host_triple_with_abi(
+        options
+            .target_host
+            .clone()
+            .or_else(|| options.default_host.clone()),
+        options.host_abi,
+    )?;
+    if options.target_host.is_some() {
+        check_target_host(&options, &host_triple)?;
+        info!("{} Provisioning for '{}'", emoji::INFO, host_triple);
+    } else {
+        check_libc(&host_triple)?;
+        if options.host_abi.is_some() {
+            check_host_linker(&host_triple)?;
+        }
+    }
+    let mut extra_crates = options.extra_crates.clone();
+    let mut exports: Vec<String> = Vec::new();
+    let xtensa = targets.iter().any(Target::is_xtensa);
+    // Standalone environments build the RISC-V targets with the Xtensa Rust toolchain too.
+    let xtensa_rust = if xtensa || options.standalone {
+        let (version, toolchain_host) =
+            XtensaRust::resolve_version(context, &options.toolchain_version, &host_triple)?;
+        let mut xtensa_rust = XtensaRust::new(context, &version, &options.name, &toolchain_host);
+        if options.target_host.is_some() || options.standalone {
+            xtensa_rust.toolchain_destination =
+                context.tools_path.join("toolchains").join(&options.name);
+        }
+        Some(xtensa_rust)
+    } else {
+        None
+    };
+    // Containers and standalone environments keep everything, including the export file, in
+    // the installation directory.
+    let export_file =
+        if (options.profile_container || options.standalone) && options.export_file.is_none() {
+            context.tools_path.join(DEFAULT_EXPORT_FILE)
+        } else {
+            get_export_file(context.env.as_deref(), options.export_file.clone())?
+        };
+    // RISC-V targets are supported by the upstream toolchains, only Xtensa needs the LLVM fork.
+    let llvm = (!options.skips(SkippedComponent::Llvm) && xtensa).then(|| {
+        Llvm::new(
+            context,
+            options.llvm_version.clone(),
+            options.profile_minimal || options.llvm_libs_only,
+            &host_triple,
+        )
+    });
+
+    debug!(
+        "{} Arguments:
+            - Host triple: {}
+            - Targets: {:?}
+            - ESP-IDF version: {:?}
+            - Export file: {:?}
+            - Extra crates: {:?}
+            - GCC versions: {:?}
+            - LLVM Toolchain: {:?}
+            - Nightly version: {:?}
+            - Rust Toolchain: {:?}
+            - Profile Minimal: {:?}
+            - Toolchain version: {:?}",
+        emoji::INFO,
+        host_triple,
+        targets,
+        &options.esp_idf_version,
+        &export_file,
+        &extra_crates,
+        &options.gcc_version,
+        llvm,
+        &options.nightly_version,
+        xtensa_rust,
+        options.profile_minimal,
+        options.toolchain_version,
+    );
+
+    #[cfg(windows)]
+    for check in check_long_paths() {
+        if check.status != Status::Ok {
+            check.print();
+        }
+    }
+
+    if options.diff {
+        let plan = get_install_plan(
+            context,
+            &options,
+            &host_triple,
+            &xtensa_rust,
+            &llvm,
+            &extra_crates,
+            &export_file,
+        )?;
+        let current = Config::load(context.env.as_deref())
+            .map(|config| get_current_components(context, &config))
+            .unwrap_or_default();
+        let planned = get_planned_components(context, &options, &host_triple, &xtensa_rust, &llvm)?;
+        let esp_idf = options.esp_idf_version.is_some() || options.idf_path.is_some();
+        print_diff(
+            context,
+            &diff_components(&current, &planned, esp_idf),
+            &plan,
+        );
+        return Ok(None);
+    }
+
+    // Fetching on a dry run only prints the cache key, which does not require downloading.
+    if options.fetch {
+        let plan = get_install_plan(
+            context,
+            &options,
+            &host_triple,
+            &xtensa_rust,
+            &llvm,
+            &extra_crates,
+            &export_file,
+        )?;
+        let cache_key = if options.dry_run {
+            plan.print(context);
+            get_cache_key(&plan.artifacts)
+        } else {
+            *lock = Some(InstallLock::acquire(context, options.no_wait)?);
+            fetch_artifacts(context, &plan)?
+        };
+        println!("{cache_key}");
+        return Ok(None);
+    }
+
+    if options.dry_run {
+        get_install_plan(
+            context,
+            &options,
+            &host_triple,
+            &xtensa_rust,
+            &llvm,
+            &extra_crates,
+            &export_file,
+        )?
+        .print(context);
+        return Ok(None);
+    }
+
+    let plan = get_install_plan(
+        context,
+        &options,
+        &host_triple,
+        &xtensa_rust,
+        &llvm,
+        &extra_crates,
+        &export_file,
+    )?;
+    if !options.force {
+        check_disk_space(&plan)?;
+    }
+    if !options.yes && !confirm_install(context, &plan)? {
+        info!("{} Installation cancelled", emoji::INFO);
+        return Ok(None);
+    }
+
+    // The environment of the shell does not apply to the installations for another host.
+    let conflicts = match options.target_host {
+        Some(_) => Vec::new(),
+        None => get_conflicting_variables(),
+    };
+    let override_env = !conflicts.is_empty() && confirm_override_env(&conflicts, &options)?;
+    // The export file sets both clang variables, but another LLVM wins wherever they are set
+    // after it is sourced.
+    if llvm.is_some() && options.target_host.is_none() {
+        for (key, value) in get_conflicting_clang_variables(context) {
+            warn!(
+                "{} {} is set to '{}', outside of espup. Make sure it is not set after sourcing the export file, e.g. system-wide or in a shell profile, or bindgen would use another clang than the Xtensa one",
+                emoji::WARN,
+                key,
+                value
+            );
+        }
+    }
+
+    *lock = Some(InstallLock::acquire(context, options.no_wait)?);
+    // Hooks are configured in the configuration file, kept across installations.
+    let hooks = Config::load(context.env.as_deref())
+        .map(|config| config.hooks)
+        .unwrap_or_default();
+    hooks.pre_install(context, &exports)?;
+    let mut required_by = Vec::new();
+    if options.esp_idf_version.is_some() || options.idf_path.is_some() {
+        required_by.push(RequiredBy::EspIdf);
+        check_python_preflight(context, &options)?;
+        check_esp_idf_targets(&options)?;
+        options.get_python_env()?;
+    }
+    if let Some(extra_crates) = &extra_crates {
+        if extra_crates.contains(&Crate::new("espflash"))
+            || extra_crates.contains(&Crate::new("cargo-espflash"))
+        {
+            required_by.push(RequiredBy::Espflash);
+        }
+    }
+    if options.with_dfu_util && DfuUtil::new(context, &host_triple).is_none() {
+        required_by.push(RequiredBy::DfuUtil);
+    }
+    if options.target_host.is_none() {
+        check_system_packages(context, &required_by, options.install_prereqs)?;
+        if !options.standalone {
+            check_rust_installation(
+                context,
+                &options.nightly_version,
+                &host_triple,
+                options.install_rustup,
+            )?;
+        }
+    }
+
+    if let Some(ref xtensa_rust) = xtensa_rust {
+        hooks.install_component(context, "xtensa-rust", &mut exports, || {
+            xtensa_rust.install(context)?;
+            if options.standalone {
+                return Ok(xtensa_rust.get_standalone_exports());
+            }
+            // Toolchains provisioned for another host are not registered with rustup.
+            if options.target_host.is_none() {
+                xtensa_rust.link(context)?;
+            }
+            Ok(Vec::new())
+        })?;
+    }
+
+    if let Some(ref llvm) = llvm {
+        hooks.install_component(context, "llvm", &mut exports, || llvm.install(context))?;
+    } else if xtensa {
+        info!(
+            "{} Skipping LLVM installation, make sure 'LIBCLANG_PATH' points to a suitable libclang",
+            emoji::INFO
+        );
+    } else if !options.standalone {
+        info!(
+            "{} Only RISC-V targets selected, skipping Xtensa LLVM and Rust toolchains",
+            emoji::INFO
+        );
+    }
+
+    if options.standalone {
+        if targets.iter().any(Target::is_riscv) {
+            info!(
+                "{} RISC-V targets are built with '-Zbuild-std=core' from the rust-src of the Xtensa Rust toolchain",
+                emoji::INFO
+            );
+        }
+    } else if options.target_host.is_some() {
+        info!(
+            "{} Skipping the rustup toolchains, register them on the provisioned host",
+            emoji::INFO
+        );
+    } else if targets.iter().any(Target::is_riscv) {
+        hooks.install_component(context, "riscv-target", &mut exports, || {
+            install_riscv_target(context, &options.nightly_version, &targets)?;
+            Ok(Vec::new())
+        })?;
+        if options.esp_idf_version.is_some() || options.idf_path.is_some() {
+            let mut std_targets: Vec<&str> = targets
+                .iter()
+                .filter(|target| target.is_riscv())
+                .map(Target::std_rust_target)
+                .collect();
+            std_targets.sort_unstable();
+            std_targets.dedup();
+            info!(
+                "{} Targets {} are built with '-Zbuild-std' from the rust-src of {}",
+                emoji::INFO,
+                std_targets.join(", "),
+                options.nightly_version
+            );
+        }
+    }
+
+    // ESP-IDF installs OpenOCD by default on Windows only.
+    let skip_openocd = options.skips(SkippedComponent::Openocd);
+    let openocd = !skip_openocd && (options.with_openocd || cfg!(windows));
+    let esp_idf_repo = match (&options.idf_path, &options.esp_idf_version) {
+        (Some(idf_path), _) => Some(EspIdfRepo::from_path(
+            idf_path,
+            options.cmake_generator,
+            openocd,
+            &targets,
+        )),
+        (None, Some(esp_idf_version)) => Some(EspIdfRepo::new(
+            get_esp_idf_remote(Some(&context.http.esp_idf_repository()), esp_idf_version),
+            options
+                .profile_minimal
+                .then(|| MinifyProfile::new(&options.minify_keep, &options.minify_remove)),
+            options.shallow,
+            options.esp_idf_submodules.clone(),
+            options.cmake_generator,
+            openocd,
+            &targets,
+        )),
+        (None, None) => None,
+    };
+    let esp_idf_installed = esp_idf_repo.is_some();
+    if options.with_ccache && !esp_idf_installed {
+        warn!(
+            "{} Skipping ccache, it is installed along with ESP-IDF",
+            emoji::WARN
+        );
+    }
+    if !options.extra_frameworks.is_empty() && !esp_idf_installed {
+        warn!(
+            "{} Skipping the extra frameworks, they are installed along with ESP-IDF",
+            emoji::WARN
+        );
+    }
+    if let Some(mut repo) = esp_idf_repo {
+        repo.ccache = options.with_ccache;
+        repo.from_archive = options.idf_from_archive;
+        repo.python_env = options.get_python_env()?;
+        repo.skip_python_env = options.skips(SkippedComponent::PythonEnv);
+        repo.skip_ulp = options.skips(SkippedComponent::Ulp);
+        repo.skip_gcc = options.skips(SkippedComponent::Gcc);
+        repo.skip_cmake = options.skips(SkippedComponent::Cmake);
+        repo.skip_ninja = options.skips(SkippedComponent::Ninja);
+        hooks.install_component(context, "esp-idf", &mut exports, || {
+            Ok(repo.install(context)?)
+        })?;
+        if let Some(ref mut extra_crates) = extra_crates {
+            extra_crates.insert(Crate::new("ldproxy"));
+        } else {
+            let mut crates = HashSet::new();
+            crates.insert(Crate::new("ldproxy"));
+            extra_crates = Some(crates);
+        };
+    } else if options.skips(SkippedComponent::Gcc) {
+        info!(
+            "{} Skipping GCC installation, make sure the GCC toolchains of the targets are in 'PATH'",
+            emoji::INFO
+        );
+    } else {
+        hooks.install_component(context, "gcc", &mut exports, || {
+            Ok(install_gcc_targets(
+                context,
+                &targets,
+                &host_triple,
+                &options.gcc_version,
+            )?)
+        })?;
+    }
+
+    let mut tool_paths = HashSet::new();
+    if let Some(espidf_dir) = get_esp_idf_dir(context, &options) {
+        for framework in &options.extra_frameworks {
+            hooks.install_component(context, &framework.to_string(), &mut exports, || {
+                Ok(framework.install(context, &espidf_dir, options.shallow)?)
+            })?;
+            tool_paths.insert(framework.get_path(context));
+        }
+    }
+    if options.with_openocd && !skip_openocd && !esp_idf_installed {
+        let openocd = get_openocd(context, &host_triple);
+        hooks.install_component(context, "openocd", &mut exports, || {
+            Ok(install_openocd(context, &openocd)?)
+        })?;
+        tool_paths.insert(openocd.path);
+    }
+    if options.with_gdb {
+        let gdb_targets = get_gdb_targets(context, &targets, &host_triple);
+        hooks.install_component(context, "gdb", &mut exports, || {
+            let installs = gdb_targets
+                .iter()
+                .cloned()
+                .map(|gdb| {
+                    let context = context.clone();
+                    move || gdb.install(&context)
+                })
+                .collect();
+            Ok(install_concurrently(installs)?.concat())
+        })?;
+        tool_paths.extend(gdb_targets.into_iter().map(|gdb| gdb.path));
+    }
+    if options.with_qemu {
+        let unemulated_targets = get_unemulated_targets(&targets);
+        if !unemulated_targets.is_empty() {
+            warn!(
+                "{} Skipping QEMU for {}, it only emulates the ESP32, ESP32-S3 and ESP32-C3",
+                emoji::WARN,
+                unemulated_targets
+                    .iter()
+                    .map(Target::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        let qemu_targets = get_qemu_targets(context, &targets, &host_triple);
+        hooks.install_component(context, "qemu", &mut exports, || {
+            let installs = qemu_targets
+                .iter()
+                .cloned()
+                .map(|qemu| {
+                    let context = context.clone();
+                    move || qemu.install(&context)
+                })
+                .collect();
+            Ok(install_concurrently(installs)?.concat())
+        })?;
+        tool_paths.extend(qemu_targets.into_iter().map(|qemu| qemu.path));
+    }
+    // ESP-IDF installs dfu-util along with its tools on Windows.
+    if options.with_dfu_util && !esp_idf_installed {
+        if let Some(dfu_util) = DfuUtil::new(context, &host_triple) {
+            hooks.install_component(context, "dfu-util", &mut exports, || {
+                Ok(dfu_util.install(context)?)
+            })?;
+            tool_paths.insert(dfu_util.path);
+        }
+    }
+    if options.with_rom_elfs {
+        let rom_elfs = get_rom_elfs(context);
+        hooks.install_component(context, "rom-elfs", &mut exports, || {
+            Ok(rom_elfs.install(context)?)
+        })?;
+        tool_paths.insert(rom_elfs.path);
+    }
+    if options.with_sccache {
+        let sccache = Sccache::new(context, &host_triple);
+        hooks.install_component(context, "sccache", &mut exports, || {
+            Ok(sccache.install(context)?)
+        })?;
+        tool_paths.insert(sccache.path);
+    }
+    if options.with_udev_rules {
+        if installs_udev_rules(&options) {
+            hooks.install_component(context, "udev-rules", &mut exports, || {
+                install_udev_rules(context)?;
+                Ok(Vec::new())
+            })?;
+        } else {
+            warn!(
+                "{} Skipping the udev rules, they are only installed for the current Linux host",
+                emoji::WARN
+            );
+        }
+    }
+    if options.with_usb_drivers {
+        if installs_usb_drivers(&options) {
+            hooks.install_component(context, "usb-drivers", &mut exports, || {
+                install_usb_drivers(context, options.yes)?;
+                Ok(Vec::new())
+            })?;
+        } else {
+            warn!(
+                "{} Skipping the USB drivers, they are only installed for the current Windows host",
+                emoji::WARN
+            );
+        }
+    }
+
+    if let Some(ref extra_crates) = &extra_crates {
+        hooks.install_component(context, "extra-crates", &mut exports, || {
+            install_extra_crates(context, extra_crates)?;
+            Ok(Vec::new())
+        })?;
+    }
+
+    if options.profile_minimal || options.profile_container {
+        clear_dist_folder(context)?;
+    }
+
+    if override_env {
+        let override_exports = get_override_exports(&conflicts, &exports);
+        exports.splice(0..0, override_exports);
+    }
+
+    if options.profile_container {
+        write_export_file(context, &export_file, &exports)?;
+    } else {
+        export_environment(context, &export_file, &exports)?;
+    }
+    if let Some(directory) = &options.idf_export_scripts {
+        for script in write_idf_export_scripts(directory, &export_file)? {
+            info!(
+                "{} Created the ESP-IDF compatible export script '{}'",
+                emoji::WRENCH,
+                script.display()
+            );
+        }
+    }
+
+    let mut config = Config {
+        esp_idf_layout: options
+            .esp_idf_version
+            .as_ref()
+            .map(|_| options.esp_idf_layout),
+        esp_idf_from_archive: (options.esp_idf_version.is_some() && options.idf_from_archive)
+            .then_some(true),
+        esp_idf_path: options.idf_path,
+        esp_idf_repository: options
+            .esp_idf_version
+            .as_ref()
+            .map(|_| context.http.esp_idf_repository()),
+        esp_idf_version: options.esp_idf_version,
+        export_file: Some(export_file),
+        extra_crates: extra_crates.as_ref().map(|extra_crates| {
+            extra_crates
+                .iter()
+                .map(|x| x.name.clone())
+                .collect::<HashSet<String>>()
+        }),
+        hooks: hooks.clone(),
+        host_triple,
+        idf_export_scripts: options.idf_export_scripts.clone(),
+        install_dir: Some(context.tools_path.clone()),
+        llvm_path: llvm.map(|llvm| llvm.path),
+        mirrors: Config::load_mirrors(context.env.as_deref()),
+        nightly_version: options.nightly_version,
+        output: Config::load_output(context.env.as_deref()),
+        profile: options
+            .profile
+            .or_else(|| options.profile_minimal.then_some(Profile::Minimal)),
+        receipt: None,
+        targets,
+        timeouts: Config::load_timeouts(context.env.as_deref()),
+        tool_paths: (!tool_paths.is_empty()).then_some(tool_paths),
+        update_check: Config::load_update_check(context.env.as_deref()),
+        urls: Config::load_urls(context.env.as_deref()),
+        xtensa_rust,
+    };
+    let installed = get_installed_components(context, Some(&config));
+    config.receipt = Some(create_receipt(&installed));
+    // The configuration file describes the rustup installation of the running host.
+    if options.target_host.is_none() && !options.standalone {
+        info!("{} Saving configuration file", emoji::WRENCH);
+        config.save(context.env.as_deref())?;
+    }
+
+    if options.prune_cache {
+        info!("{} Pruning cached archives", emoji::WRENCH);
+        if let Err(e) = remove_entries(context, &get_obsolete_entries(context)) {
+            warn!("{} Failed to prune cached archives: {}", emoji::WARN, e);
+        }
+    }
+
+    hooks.post_install(context, &exports)?;
+
+    info!("{} Installation successfully completed!", emoji::CHECK);
+    let timings = context.summary.take_timings();
+    if !timings.is_empty() {
+        for line in format_table(&get_rows(&timings, &installed)) {
+            info!("    {}", line);
+        }
+    }
+    if options.profile_container {
+        println!(
+            "{}",
+            format_dockerfile_env(&parse_exports(&normalize_exports(&exports).join("\n")))
+        );
+        return Ok(Some((context.clone(), config)));
+    }
+    warn!(
+        "{} Please, source the export file, as state above, to properly setup the environment!",
+        emoji::WARN
+    );
+    if let Some(wsl) = wsl::detect() {
+        wsl::print_guidance(wsl);
+    }
+    Ok(Some((context.clone(), config)))
+}
+
+/// Gets the ESP-IDF checkout of an installation, if ESP-IDF is installed.
+fn get_esp_idf_dir(context: &Context, options: &InstallOptions) -> Option<PathBuf> {
+    match (&options.idf_path, &options.esp_idf_version) {
+        (Some(idf_path), _) => Some(idf_path.clone()),
+        (None, Some(esp_idf_version)) => Some(get_esp_idf_install_dir(
+            context,
+            Some(&context.http.esp_idf_repository()),
+            esp_idf_version,
+        )),
+        (None, None) => None,
+    }
+}
+
+/// Checks that the environment can be provisioned for another host: into its own directory,
+/// without the components that run on the current host and, as the Xtensa Rust toolchain is
+/// installed with platform-specific steps, from the same operating system family.
+fn check_target_host(options: &InstallOptions, host_triple: &HostTriple) -> Result<(), Error> {
+    let reason = if options.install_dir.is_none() {
+        "an installation directory is required, use '--install-dir'"
+    } else if options.esp_idf_version.is_some() || options.idf_path.is_some() {
+        "ESP-IDF installs its tools and Python environment for the current host"
+    } else if options.extra_crates.is_some() {
+        "extra crates are built for the current host"
+    } else if host_triple.is_windows() != cfg!(windows) {
+        "Windows and Unix hosts can only be provisioned from the same operating system family"
+    } else {
+        return Ok(());
+    };
+    Err(Error::InvalidTargetHost(
+        host_triple.to_string(),
+        reason.to_string(),
+    ))
+}
+
+/// Whether the udev rules are installed: only on Linux, and not when provisioning another
+/// host.
+fn installs_udev_rules(options: &InstallOptions) -> bool {
+    cfg!(target_os = "linux") && options.target_host.is_none()
+}
+
+/// Whether the USB drivers are installed: only on Windows, and not when provisioning another
+/// host.
+fn installs_usb_drivers(options: &InstallOptions) -> bool {
+    cfg!(windows) && options.target_host.is_none()
+}
+
+/// Checks that a standalone environment can be installed: into its own directory, and
+/// without the components that need rustup and cargo.
+fn check_standalone(options: &InstallOptions) -> Result<(), Error> {
+    let reason = if options.install_dir.is_none() {
+        "an installation directory is required, use '--standalone <DIR>'"
+    } else if options.target_host.is_some() {
+        "environments for another host are provisioned with '--target-host'"
+    } else if options.esp_idf_version.is_some() || options.idf_path.is_some() {
+        "ESP-IDF builds need ldproxy, which is installed with cargo"
+    } else if options.extra_crates.is_some() {
+        "extra crates are installed with cargo"
+    } else {
+        return Ok(());
+    };
+    Err(Error::InvalidStandalone(reason.to_string()))
+}
+
+/// Gets the version of the ESP-IDF to install, e.g. `v5.1`, or its branch or commit.
+fn get_esp_idf_version(options: &InstallOptions) -> Option<String> {
+    match (&options.idf_path, &options.esp_idf_version) {
+        (Some(idf_path), _) => EspIdfVersion::try_from(&git::Repository::new(idf_path))
+            .ok()
+            .map(|version| format!("v{}.{}", version.major, version.minor)),
+        (None, esp_idf_version) => esp_idf_version.clone(),
+    }
+}
+
+/// Checks that the Python requirements of the ESP-IDF to install are met, before
+/// downloading anything.
+fn check_python_preflight(context: &Context, options: &InstallOptions) -> Result<(), Error> {
+    let esp_idf_version = get_esp_idf_version(options);
+    let failed: Vec<Check> = check_python_requirements(context, esp_idf_version.as_deref())
+        .into_iter()
+        .filter(|check| check.status != Status::Ok)
+        .collect();
+    if failed.is_empty() {
+        return Ok(());
+    }
+    for check in &failed {
+        check.print();
+    }
+    Err(Error::PythonRequirementsNotMet)
+}
+
+/// Checks that the ESP-IDF to install supports the selected targets, before downloading
+/// anything, as the ESP-IDF tools installer fails otherwise. Branches and commits that are
+/// not named after a version are checked once checked out.
+fn check_esp_idf_targets(options: &InstallOptions) -> Result<(), Error> {
+    if let Some(version) = get_esp_idf_version(options)
+        .as_deref()
+        .and_then(parse_esp_idf_version)
+    {
+        select_esp_idf_targets(&options.targets, version)?;
+    }
+    Ok(())
+}
+
+/// Adds the Xtensa Rust toolchain installation to the plan.
+fn add_xtensa_rust_to_plan(context: &Context, plan: &mut Plan, xtensa_rust: &XtensaRust) {
+    #[cfg(unix)]
+    {
+        plan.download(
+            context,
+            &xtensa_rust.dist_url,
+            get_dist_path(context, "rust"),
+        );
+        if context.install_rust_src {
+            plan.download(
+                context,
+                &xtensa_rust.src_dist_url,
+                get_dist_path(context, "rust-src"),
+            );
+        }
+    }
+    #[cfg(windows)]
+    plan.download(
+        context,
+        &xtensa_rust.dist_url,
+        get_dist_path(context, "rust"),
+    );
+    plan.create(&xtensa_rust.toolchain_destination);
+    plan.use_disk_space(get_rustup_home(), XTENSA_RUST_DISK_USAGE);
+}
+
+/// Resolves the actions that an installation would perform.
+fn get_install_plan(
+    context: &Context,
+    options: &InstallOptions,
+    host_triple: &HostTriple,
+    xtensa_rust: &Option<XtensaRust>,
+    llvm: &Option<Llvm>,
+    extra_crates: &Option<HashSet<Crate>>,
+    export_file: &PathBuf,
+) -> Result<Plan, Error> {
+    let mut plan = Plan::default();
+    let tools_path = &context.tools_path;
+    let register_toolchains = options.target_host.is_none() && !options.standalone;
+    if register_toolchains {
+        plan.command(format!(
+            "rustup toolchain install {} --profile minimal (if not installed)",
+            options.nightly_version
+        ));
+    }
+
+    if let Some(xtensa_rust) = xtensa_rust {
+        add_xtensa_rust_to_plan(context, &mut plan, xtensa_rust);
+    }
+
+    if let Some(llvm) = llvm {
+        if !is_installed(context, &llvm.path) {
+            plan.download(context, &llvm.repository_url, &llvm.path);
+            plan.create(&llvm.path);
+            plan.use_disk_space(
+                tools_path,
+                if options.profile_minimal || options.llvm_libs_only {
+                    LLVM_LIBS_DISK_USAGE
+                } else {
+                    LLVM_DISK_USAGE
+                },
+            );
+        }
+        plan.export("LIBCLANG_PATH", llvm.get_lib_path());
+        if !llvm.is_minified() {
+            plan.export("CLANG_PATH", llvm.get_clang_path());
+        }
+    }
+
+    if register_toolchains && options.targets.iter().any(Target::is_riscv) {
+        if !options.skips(SkippedComponent::RustSrc) {
+            plan.command(format!(
+                "rustup component add rust-src --toolchain {}",
+                options.nightly_version
+            ));
+        }
+        plan.command(format!(
+            "rustup target add --toolchain {} {}",
+            options.nightly_version,
+            get_riscv_targets(&options.targets).join(" ")
+        ));
+    }
+
+    let mut extra_crates = extra_crates.clone().unwrap_or_default();
+    let esp_idf_path = match (&options.idf_path, &options.esp_idf_version) {
+        (Some(idf_path), _) => Some(idf_path.clone()),
+        (None, Some(esp_idf_version)) => {
+            let repo =
+                get_esp_idf_remote(Some(&context.http.esp_idf_repository()), esp_idf_version);
+            let install_path = get_install_path(context, repo.clone());
+            if !is_installed(context, &install_path) {
+                plan.use_disk_space(
+                    tools_path,
+                    if options.shallow {
+                        ESP_IDF_SHALLOW_DISK_USAGE
+                    } else {
+                        ESP_IDF_DISK_USAGE
+                    },
+                );
+            }
+            if options.idf_from_archive {
+                plan.download(context, get_archive_url(&repo.git_ref)?, &install_path);
+            } else {
+                plan.clone_repository(repo.repo_url(), &install_path);
+            }
+            Some(install_path)
+        }
+        (None, None) => None,
+    };
+    if let Some(install_path) = esp_idf_path {
+        plan.command(format!(
+            "{}/tools/idf_tools.py install-python-env",
+            install_path.display()
+        ));
+        plan.command(format!(
+            "{}/tools/idf_tools.py install <tools for {:?}>",
+            install_path.display(),
+            options.targets
+        ));
+        plan.use_disk_space(tools_path, ESP_IDF_TOOLS_DISK_USAGE);
+        plan.export("IDF_PATH", install_path.display());
+        plan.export("IDF_TOOLS_PATH", context.tools_path());
+        if !options.skips(SkippedComponent::PythonEnv) {
+            if let Some(python_env) = &options.python_env {
+                plan.export("IDF_PYTHON_ENV_PATH", python_env.display());
+            }
+        }
+        plan.export("PATH", "<ESP-IDF tools>");
+        if options.with_ccache {
+            plan.export("IDF_CCACHE_ENABLE", 1);
+            plan.export("CCACHE_DIR", get_ccache_dir(context).display());
+        }
+        for framework in &options.extra_frameworks {
+            let path = framework.get_path(context);
+            if !path.exists() {
+                plan.clone_repository(framework.get_repository_url(context), &path);
+                plan.use_disk_space(tools_path, framework.disk_usage());
+            }
+            if let Some(install_command) = framework.get_install_command() {
+                plan.command(format!("{}/{}", path.display(), install_command));
+            }
+            plan.export(framework.path_variable(), path.display());
+        }
+        extra_crates.insert(Crate::new("ldproxy"));
+    } else if !options.skips(SkippedComponent::Gcc) {
+        // RISC-V targets share the same GCC.
+        let mut gcc_paths = HashSet::new();
+        for target in &options.targets {
+            let gcc = Gcc::new(
+                context,
+                target,
+                host_triple,
+                GccVersion::resolve(target, &options.gcc_version),
+            )?;
+            let gcc_path = gcc.get_path(context);
+            if !is_installed(context, Path::new(&gcc_path)) && gcc_paths.insert(gcc_path.clone()) {
+                plan.download(context, gcc.get_dist_url(context), &gcc_path);
+                plan.create(&gcc_path);
+                plan.use_disk_space(tools_path, GCC_DISK_USAGE);
+            }
+            plan.export("PATH", gcc.get_bin_path(context));
+        }
+    }
+
+    if options.with_openocd
+        && !options.skips(SkippedComponent::Openocd)
+        && options.idf_path.is_none()
+        && options.esp_idf_version.is_none()
+    {
+        get_openocd(context, host_triple).plan(context, &mut plan, TOOL_DISK_USAGE);
+    }
+    if options.with_dfu_util && options.idf_path.is_none() && options.esp_idf_version.is_none() {
+        match DfuUtil::new(context, host_triple) {
+            Some(dfu_util) => {
+                if !is_installed(context, &dfu_util.path) {
+                    plan.download(context, &dfu_util.repository_url, &dfu_util.path);
+                    plan.create(&dfu_util.path);
+                    plan.use_disk_space(tools_path, TOOL_DISK_USAGE);
+                }
+                plan.export("PATH", dfu_util.get_bin_path());
+            }
+            None => plan.command("Install the dfu-util system package, if missing"),
+        }
+    }
+    if options.with_gdb {
+        for gdb in get_gdb_targets(context, &options.targets, host_triple) {
+            gdb.plan(context, &mut plan, TOOL_DISK_USAGE);
+        }
+    }
+    if options.with_qemu {
+        for qemu in get_qemu_targets(context, &options.targets, host_triple) {
+            qemu.plan(context, &mut plan, TOOL_DISK_USAGE);
+        }
+    }
+    if options.with_rom_elfs {
+        get_rom_elfs(context).plan(context, &mut plan, TOOL_DISK_USAGE);
+    }
+    if options.with_sccache {
+        let sccache = Sccache::new(context, host_triple);
+        if !is_installed(context, &sccache.path) {
+            plan.download(context, &sccache.repository_url, &sccache.path);
+            plan.create(&sccache.path);
+            plan.use_disk_space(tools_path, TOOL_DISK_USAGE);
+        }
+        plan.export("PATH", sccache.get_bin_path());
+        for variable in [
+            "RUSTC_WRAPPER",
+            "CMAKE_C_COMPILER_LAUNCHER",
+            "CMAKE_CXX_COMPILER_LAUNCHER",
+        ] {
+            plan.export(variable, "sccache");
+        }
+        plan.export("SCCACHE_DIR", get_sccache_dir(context).display());
+    }
+    if options.with_udev_rules && installs_udev_rules(options) {
+        plan.create(UDEV_RULES_PATH);
+        plan.command("udevadm control --reload-rules && udevadm trigger");
+    }
+    if options.with_usb_drivers && installs_usb_drivers(options) {
+        for driver in get_missing_drivers().unwrap_or_default() {
+            plan.download(context, driver.get_url(context), driver.get_path(context));
+            plan.command(format!(
+                "pnputil /add-driver {}\\*.inf /subdirs /install (as administrator, once confirmed)",
+                driver.get_path(context)
+            ));
+        }
+    }
+
+    for extra_crate in extra_crates {
+        plan.command(format!("cargo install {}", extra_crate.name));
+    }
+
+    if options.profile_minimal {
+        plan.remove(get_dist_path(context, ""));
+    }
+
+    plan.create(export_file);
+    if cfg!(windows) {
+        plan.create(get_batch_export_file(export_file));
+    }
+    if let Some(directory) = &options.idf_export_scripts {
+        for script in get_idf_export_scripts(directory) {
+            plan.create(script);
+        }
+    }
+    if register_toolchains {
+        plan.create(Config::get_config_path(context.env.as_deref())?);
+    }
+    Ok(plan)
+}
+
+/// Resolves the components that an installation would set up, named and versioned as the
+/// installed ones. The tools that ESP-IDF installs itself are not resolved.
+fn get_planned_components(
+    context: &Context,
+    options: &InstallOptions,
+    host_triple: &HostTriple,
+    xtensa_rust: &Option<XtensaRust>,
+    llvm: &Option<Llvm>,
+) -> Result<Vec<Component>, Error> {
+    let mut components = Vec::new();
+    if let Some(xtensa_rust) = xtensa_rust {
+        components.push(Component {
+            name: "Xtensa Rust".to_string(),
+            version: xtensa_rust.version.clone(),
+            path: xtensa_rust.toolchain_destination.clone(),
+        });
+    }
+    if let Some(llvm) = llvm {
+        components.push(Component {
+            name: "Xtensa LLVM".to_string(),
+            ..get_tool_component(&llvm.path)
+        });
+    }
+    let esp_idf_installed = options.idf_path.is_some() || options.esp_idf_version.is_some();
+    let mut tool_paths: BTreeSet<PathBuf> = BTreeSet::new();
+    match (&options.idf_path, &options.esp_idf_version) {
+        (Some(idf_path), _) => components.push(Component {
+            name: "ESP-IDF (unmanaged)".to_string(),
+            version: EspIdfVersion::format(&EspIdfVersion::try_from(&git::Repository::new(
+                idf_path,
+            ))),
+            path: idf_path.clone(),
+        }),
+        (None, Some(esp_idf_version)) => {
+            if let Some(path) = get_esp_idf_dir(context, options) {
+                components.push(Component {
+                    name: "ESP-IDF".to_string(),
+                    version: esp_idf_version.clone(),
+                    path,
+                });
+            }
+        }
+        (None, None) => {
+            for target in &options.targets {
+                let gcc = Gcc::new(
+                    context,
+                    target,
+                    host_triple,
+                    GccVersion::resolve(target, &options.gcc_version),
+                )?;
+                tool_paths.insert(PathBuf::from(gcc.get_path(context)));
+            }
+        }
+    }
+    if options.with_openocd && !esp_idf_installed {
+        tool_paths.insert(get_openocd(context, host_triple).path);
+    }
+    if options.with_dfu_util && !esp_idf_installed {
+        tool_paths.extend(DfuUtil::new(context, host_triple).map(|dfu_util| dfu_util.path));
+    }
+    if options.with_gdb {
+        tool_paths.extend(
+            get_gdb_targets(context, &options.targets, host_triple)
+                .into_iter()
+                .map(|gdb| gdb.path),
+        );
+    }
+    if options.with_qemu {
+        tool_paths.extend(
+            get_qemu_targets(context, &options.targets, host_triple)
+                .into_iter()
+                .map(|qemu| qemu.path),
+        );
+    }
+    if options.with_rom_elfs {
+        tool_paths.insert(get_rom_elfs(context).path);
+    }
+    if options.with_sccache {
+        tool_paths.insert(Sccache::new(context, host_triple).path);
+    }
+    components.extend(tool_paths.iter().map(|path| get_tool_component(path)));
+    Ok(components)
+}
+
+/// Deletes dist folder.
+pub fn clear_dist_folder(context: &Context) -> Result<(), Error> {
+    let dist_path = PathBuf::from(get_dist_path(context, ""));
+    if dist_path.exists() {
+        info!("{} Clearing dist folder", emoji::WRENCH);
+        remove_dir_all(&dist_path)
+            .map_err(|_| Error::FailedToRemoveDirectory(dist_path.display().to_string()))?;
+    }
+    Ok(())
+}
+
+/// Checks that the directories of the planned components have enough free disk space.
+fn check_disk_space(plan: &Plan) -> Result<(), Error> {
+    for (directory, required) in plan.get_disk_usage() {
+        let available = get_existing_ancestor(&directory)
+            .and_then(|existing_path| fs2::available_space(existing_path).ok());
+        match available {
+            Some(available) if available < required => {
+                return Err(Error::InsufficientDiskSpace(
+                    directory.display().to_string(),
+                    format_size(required),
+                    format_size(available),
+                ))
+            }
+            Some(_) => {}
+            None => warn!(
+                "{} Unable to query the available disk space in '{}'",
+                emoji::WARN,
+                directory.display()
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Reports the total download size and disk usage of the plan and, for large installations
+/// in an interactive terminal, asks for confirmation. Returns whether to proceed.
+fn confirm_install(context: &Context, plan: &Plan) -> Result<bool, Error> {
+    let (download_size, unknown) = plan.get_total_download_size(context);
+    let disk_usage: u64 = plan.get_disk_usage().values().sum();
+    let message = format!(
+        "This will download {}{} and use {} of disk",
+        format_size(download_size),
+        if unknown { " (plus unknown sizes)" } else { "" },
+        format_size(disk_usage)
+    );
+    if disk_usage < CONFIRM_DISK_USAGE || !is_interactive() {
+        info!("{} {}", emoji::INFO, message);
+        return Ok(true);
+    }
+    let term = Term::stderr();
+    term.write_str(&format!("{}{}, continue? [Y/n] ", emoji::INFO, message))?;
+    let answer = term.read_line()?;
+    Ok(matches!(
+        answer.trim().to_lowercase().as_str(),
+        "" | "y" | "yes"
+    ))
+}
+
+/// Warns about the ESP-IDF environment already active in the shell, which would mix with the
+/// installed toolchains, and asks whether the export file should override it.
+fn confirm_override_env(
+    conflicts: &[(String, String)],
+    options: &InstallOptions,
+) -> Result<bool, Error> {
+    for (key, value) in conflicts {
+        warn!("{} {} is already set to '{}'", emoji::WARN, key, value);
+    }
+    warn!(
+        "{} An ESP-IDF environment is already active in this shell, it would mix with the installed toolchains",
+        emoji::WARN
+    );
+    if options.override_env {
+        return Ok(true);
+    }
+    if options.yes || !is_interactive() {
+        warn!(
+            "{} Open a new terminal, or use '--override-env' to generate an export file that overrides it",
+            emoji::WARN
+        );
+        return Ok(false);
+    }
+    let term = Term::stderr();
+    term.write_str(&format!(
+        "{} Override it in the export file? [Y/n] ",
+        emoji::INFO
+    ))?;
+    let answer = term.read_line()?;
+    Ok(matches!(
+        answer.trim().to_lowercase().as_str(),
+        "" | "y" | "yes"
+    ))
+}
+
+/// Returns the absolute path to the export file, uses the DEFAULT_EXPORT_FILE if no arg is provided,
+/// suffixed with the name of the named environment `env`, if any.
+pub fn get_export_file(env: Option<&str>, export_file: Option<PathBuf>) -> Result<PathBuf, Error> {
+    if let Some(export_file) = export_file {
+        if export_file.is_absolute() {
+            Ok(export_file)
+        } else {
+            let current_dir = std::env::current_dir()?;
+            Ok(current_dir.join(export_file))
+        }
+    } else {
+        let home_dir = home_dir().unwrap();
+        Ok(match env {
+            Some(name) => home_dir.join(get_env_export_file_name(DEFAULT_EXPORT_FILE, name)),
+            None => home_dir.join(DEFAULT_EXPORT_FILE),
+        })
+    }
+}
+
+/// Writes the normalized exports to the export file.
+fn write_export_file(
+    context: &Context,
+    export_file: &Path,
+    exports: &[String],
+) -> Result<(), Error> {
+    info!("{} Creating export file", emoji::WRENCH);
+    let exports = normalize_exports(exports);
+    let components = get_exported_components(context, &exports);
+    let mut file = File::create(export_file)?;
+    file.write_all(format_export_file(&exports, &components).as_bytes())?;
+    drop(file);
+    #[cfg(windows)]
+    write_batch_export_file(export_file)?;
+    Ok(())
+}
+
+/// Formats an environment as `ENV` instructions of a Dockerfile, with the `PATH` entries
+/// prepended to the one of the image.
+pub fn format_dockerfile_env(environment: &Environment) -> String {
+    let quote = |value: &str| format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut lines: Vec<String> = environment
+        .variables
+        .iter()
+        .map(|(key, value)| format!("ENV {key}={}", quote(value)))
+        .collect();
+    if !environment.path.is_empty() {
+        #[cfg(windows)]
+        let path = format!("{};$PATH", environment.path.join(";"));
+        #[cfg(unix)]
+        let path = format!("{}:$PATH", environment.path.join(":"));
+        lines.push(format!("ENV PATH={}", quote(&path)));
+    }
+    lines.join("\n")
+}
+
+/// Creates the export file with the necessary environment variables.
+pub fn export_environment(
+    context: &Context,
+    export_file: &Path,
+    exports: &[String],
+) -> Result<(), Error> {
+    write_export_file(context, export_file, exports)?;
+    #[cfg(windows)]
+    match Shell::detect() {
+        Shell::Cmd => warn!(
+            "{} PLEASE set up the environment variables running: '{}'",
+            emoji::INFO,
+            get_batch_export_file(export_file).display()
+        ),
+        _ => warn!(
+            "{} PLEASE set up the environment variables running: '. {}'",
+            emoji::INFO,
+            export_file.display()
+        ),
+    }
+    #[cfg(unix)]
+    warn!(
+        "{} PLEASE set up the environment variables running: '. {}'",
+        emoji::INFO,
+        export_file.display()
+    );
+    warn!(
+        "{} This step must be done every time you open a new terminal.",
+        emoji::WARN
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        environment::parse_exports,
+        host_triple::HostTriple,
+        install::{
+            check_standalone, check_target_host, format_dockerfile_env, get_export_file,
+            InstallOptions, Profile, SkippedComponent, DEFAULT_EXPORT_FILE,
+            FULL_PROFILE_ESP_IDF_VERSION,
+        },
+        toolchain::rust::Crate,
+    };
+    use dirs::home_dir;
+    use std::{collections::HashSet, env::current_dir, path::PathBuf, str::FromStr};
+
+    #[test]
+    #[allow(unused_variables)]
+    fn test_get_export_file() {
+        // No arg provided
+        let home_dir = home_dir().unwrap();
+        let export_file = home_dir.join(DEFAULT_EXPORT_FILE);
+        assert!(matches!(get_export_file(None, None), Ok(export_file)));
+        // Relative path
+        let current_dir = current_dir().unwrap();
+        let export_file = current_dir.join("export.sh");
+        assert!(matches!(
+            get_export_file(None, Some(PathBuf::from("export.sh"))),
+            Ok(export_file)
+        ));
+        // Absolute path
+        let export_file = PathBuf::from("/home/user/export.sh");
+        assert!(matches!(
+            get_export_file(None, Some(PathBuf::from("/home/user/export.sh"))),
+            Ok(export_file)
+        ));
+    }
+
+    #[test]
+    fn test_check_target_host() {
+        let host_triple = HostTriple::from_str("aarch64-unknown-linux-gnu").unwrap();
+        let mut options = InstallOptions {
+            target_host: Some(host_triple.to_string()),
+            ..Default::default()
+        };
+        assert!(check_target_host(&options, &host_triple).is_err());
+        options.install_dir = Some(PathBuf::from("/mnt/arm64/espup"));
+        assert_eq!(
+            check_target_host(&options, &host_triple).is_ok(),
+            cfg!(unix)
+        );
+        options.esp_idf_version = Some("v5.0".to_string());
+        assert!(check_target_host(&options, &host_triple).is_err());
+    }
+
+    #[test]
+    fn test_profile_apply() {
+        let mut options = InstallOptions::default();
+        Profile::Minimal.apply(&mut options);
+        assert!(options.profile_minimal && !options.with_gdb);
+        assert_eq!(options.profile, Some(Profile::Minimal));
+
+        let mut options = InstallOptions {
+            esp_idf_version: Some("v5.0".to_string()),
+            extra_crates: Some([Crate::new("ldproxy")].into_iter().collect()),
+            ..Default::default()
+        };
+        Profile::from_str("full").unwrap().apply(&mut options);
+        assert!(options.with_gdb && options.with_openocd && options.with_qemu);
+        assert_eq!(options.esp_idf_version.as_deref(), Some("v5.0"));
+        assert_eq!(options.extra_crates.unwrap().len(), 3);
+
+        let mut options = InstallOptions::default();
+        Profile::Full.apply(&mut options);
+        assert_eq!(
+            options.esp_idf_version.as_deref(),
+            Some(FULL_PROFILE_ESP_IDF_VERSION)
+        );
+        let mut options = InstallOptions {
+            target_host: Some("x86_64-unknown-linux-gnu".to_string()),
+            ..Default::default()
+        };
+        Profile::Full.apply(&mut options);
+        assert!(options.esp_idf_version.is_none() && options.extra_crates.is_none());
+    }
+
+    #[test]
+    fn test_check_standalone() {
+        let mut options = InstallOptions {
+            standalone: true,
+            ..Default::default()
+        };
+        assert!(check_standalone(&options).is_err());
+        options.install_dir = Some(PathBuf::from("/opt/espup"));
+        assert!(check_standalone(&options).is_ok());
+        options.extra_crates = Some(HashSet::new());
+        assert!(check_standalone(&options).is_err());
+        options.extra_crates = None;
+        options.idf_path = Some(PathBuf::from("/opt/esp-idf"));
+        assert!(check_standalone(&options).is_err());
+    }
+
+    #[test]
+    fn test_skips() {
+        assert_eq!(
+            SkippedComponent::from_str("python-env").unwrap(),
+            SkippedComponent::PythonEnv
+        );
+        assert_eq!(SkippedComponent::RustSrc.to_string(), "rust-src");
+        assert!(SkippedComponent::from_str("rust").is_err());
+
+        let mut options = InstallOptions {
+            skip: [SkippedComponent::Gcc, SkippedComponent::Ninja]
+                .into_iter()
+                .collect(),
+            skip_llvm: true,
+            ..Default::default()
+        };
+        assert!(options.skips(SkippedComponent::Gcc));
+        assert!(options.skips(SkippedComponent::Llvm));
+        assert!(!options.skips(SkippedComponent::Cmake));
+        assert!(!options.skips(SkippedComponent::Openocd));
+
+        options.python_env = Some(PathBuf::from("/opt/venv"));
+        options.skip.insert(SkippedComponent::PythonEnv);
+        assert_eq!(
+            options.get_python_env().unwrap(),
+            Some(PathBuf::from("/opt/venv"))
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_format_dockerfile_env() {
+        assert_eq!(Profile::from_str("container").unwrap(), Profile::Container);
+        let environment = parse_exports(
+            "export LIBCLANG_PATH=\"/opt/espup/llvm/lib\"\nexport PATH=\"/opt/espup/gcc/bin:$PATH\"\n",
+        );
+        assert_eq!(
+            format_dockerfile_env(&environment),
+            "ENV LIBCLANG_PATH=\"/opt/espup/llvm/lib\"\nENV PATH=\"/opt/espup/gcc/bin:$PATH\""
+        );
+    }
+}
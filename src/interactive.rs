@@ -0,0 +1,24 @@
+//! Detection of non-interactive runs, e.g. in CI, where espup must never wait for input.
+
+use is_terminal::IsTerminal;
+use std::{
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Whether the run was made non-interactive, with `--non-interactive`.
+static NON_INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Disables every prompt, including the credential prompts of the git commands run with
+/// the environment of [`Context::envs`](crate::context::Context::envs).
+pub fn set_non_interactive() {
+    NON_INTERACTIVE.store(true, Ordering::Relaxed);
+}
+
+/// Whether the user can be prompted: the run was not made non-interactive and both stdin
+/// and stdout are terminals.
+pub fn is_interactive() -> bool {
+    !NON_INTERACTIVE.load(Ordering::Relaxed)
+        && io::stdin().is_terminal()
+        && console::user_attended()
+}
@@ -0,0 +1,99 @@
+//! Handling of Ctrl-C and termination requests: the downloads are stopped and the
+//! installation is rolled back, instead of leaving the tools directory half installed. A
+//! second interruption exits right away.
+
+use crate::error::{Error, EXIT_INTERRUPTED};
+use std::{
+    io::{self, Read},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Whether the process was interrupted.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Records an interruption, exiting if it is the second one.
+fn interrupt() {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        // Only async-signal-safe functions can be called from a signal handler.
+        #[cfg(unix)]
+        unsafe {
+            libc::_exit(EXIT_INTERRUPTED as i32)
+        };
+        #[cfg(windows)]
+        std::process::exit(EXIT_INTERRUPTED as i32);
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn handle_signal(_signal: libc::c_int) {
+    interrupt();
+}
+
+#[cfg(windows)]
+extern "system" {
+    fn SetConsoleCtrlHandler(
+        handler: Option<unsafe extern "system" fn(u32) -> i32>,
+        add: i32,
+    ) -> i32;
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn handle_console_event(_event: u32) -> i32 {
+    interrupt();
+    1
+}
+
+/// Handles `SIGINT` and `SIGTERM`, or the console Ctrl-C and Ctrl-Break events on Windows,
+/// by recording the interruption, which the installation checks.
+pub fn set_handler() {
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+        libc::signal(
+            libc::SIGTERM,
+            handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+    #[cfg(windows)]
+    unsafe {
+        SetConsoleCtrlHandler(Some(handle_console_event), 1);
+    }
+}
+
+/// Whether the process was interrupted.
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Fails with [`Error::Interrupted`] once the process was interrupted.
+pub fn check() -> Result<(), Error> {
+    if is_interrupted() {
+        return Err(Error::Interrupted);
+    }
+    Ok(())
+}
+
+/// Reader failing once the process is interrupted, so that downloads stop.
+pub struct InterruptibleReader<R> {
+    inner: R,
+}
+
+impl<R: Read> InterruptibleReader<R> {
+    /// Wraps a reader.
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+}
+
+impl<R: Read> Read for InterruptibleReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // `ErrorKind::Interrupted` would be retried by the readers.
+        if is_interrupted() {
+            return Err(io::Error::new(io::ErrorKind::Other, Error::Interrupted));
+        }
+        self.inner.read(buf)
+    }
+}
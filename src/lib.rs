@@ -1,40 +1,524 @@
+//! Tool for installing and maintaining the Rust for ESP chips environment.
+//!
+//! Besides the `espup` binary, the installation can be embedded, e.g. by IDE extensions or
+//! provisioning tools, instead of running the binary and parsing its logs:
+//!
+//! ```no_run
+//! use espup::{
+//!     context::Context,
+//!     install::{install, InstallOptions},
+//!     progress::Phase,
+//!     targets::parse_targets,
+//! };
+//!
+//! let mut context = Context::default();
+//! context.progress.set_callback(|event| {
+//!     if event.phase == Phase::Downloading {
+//!         println!("{}: {:?}%", event.component, event.percent);
+//!     }
+//! });
+//! install(
+//!     &context,
+//!     InstallOptions {
+//!         targets: parse_targets("esp32c3").unwrap(),
+//!         yes: true,
+//!         ..Default::default()
+//!     },
+//! )
+//! .unwrap();
+//! ```
+//!
+//! Everything an installation depends on is in its [`context::Context`], so that several
+//! installations, e.g. into different directories, can run in the same process. Besides a
+//! closure, the progress can be received by a [`progress::ProgressHandler`] registered with
+//! [`progress::Progress::add_handler`], whose methods are called when downloads start,
+//! progress and finish, when components change phase, and for the warnings logged by
+//! [`logging::initialize_logger`].
+//!
+//! Downloads use the defaults of [`http::HttpSettings`] unless the context is given another
+//! [`http::Http`] client, or another transport, e.g. a [`download::FileDownloader`] for
+//! offline installs. Nothing is logged until a logger, e.g. [`logging::initialize_logger`],
+//! is initialized.
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!("At least one of the `native-tls` and `rustls` features must be enabled");
+
+pub mod cache;
 pub mod config;
+pub mod context;
+pub mod diff;
+pub mod doctor;
+pub mod download;
 pub mod emoji;
+pub mod environment;
+pub mod envs;
 pub mod error;
+pub mod export;
+pub mod fetch;
+pub mod header;
+pub mod hooks;
 pub mod host_triple;
+pub mod http;
+pub mod idf;
+pub mod info;
+pub mod install;
+pub mod interactive;
+pub mod interrupt;
+pub mod list;
+pub mod lock;
+pub mod migrate;
+pub mod mirror;
+pub mod outdated;
+pub mod output;
+pub mod packages;
+pub mod plan;
+pub mod progress;
+pub mod project;
+pub mod provision;
+pub mod prune;
+pub mod resume;
+pub mod rollback;
+pub mod sbom;
+pub mod scaffold;
+pub mod self_update;
+pub mod smoke;
+pub mod ssh;
+pub mod store;
+pub mod summary;
 pub mod targets;
 pub mod toolchain;
+pub mod udev;
+pub mod uninstall;
+pub mod verify;
+pub mod which;
+pub mod wizard;
+pub mod wsl;
 pub mod logging {
-    use env_logger::{Builder, Env, WriteStyle};
+    use crate::progress::Progress;
+    use env_logger::{fmt::Target, Builder, Env, Logger, WriteStyle};
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+    use once_cell::sync::OnceCell;
+    use serde::{Deserialize, Serialize};
+    use std::{
+        env,
+        fs::{rename, File, OpenOptions},
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicBool, Ordering},
+    };
+    use strum_macros::{Display, EnumString};
+
+    /// Size, in bytes, above which the log file is rotated.
+    const MAX_LOG_FILE_SIZE: u64 = 5 * 1024 * 1024;
+    /// Number of rotated log files kept, e.g. `espup.log.1` to `espup.log.3`.
+    const LOG_FILE_ROTATIONS: usize = 3;
+
+    /// Whether the console output is colored.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Display, EnumString)]
+    #[serde(rename_all = "lowercase")]
+    #[strum(serialize_all = "lowercase")]
+    pub enum ColorChoice {
+        /// Colored when writing to a terminal.
+        Auto,
+        /// Always colored.
+        Always,
+        /// Never colored.
+        Never,
+    }
+
+    /// Whether only errors are logged to the console, set with `--quiet`.
+    static QUIET: AtomicBool = AtomicBool::new(false);
+    /// Log level replacing the default one of the subcommands, set in the configuration file.
+    static LOG_LEVEL: OnceCell<String> = OnceCell::new();
+    /// Coloring of the console output, set with `--color` or in the configuration file.
+    static COLOR: OnceCell<ColorChoice> = OnceCell::new();
+
+    /// Logs only errors to the console, overriding the log level.
+    pub fn set_quiet() {
+        QUIET.store(true, Ordering::Relaxed);
+    }
+
+    /// Replaces the log level passed to [`initialize_logger`], e.g. with the default one of
+    /// the configuration file when no `--log-level` is given.
+    pub fn set_log_level(log_level: &str) {
+        LOG_LEVEL.set(log_level.to_string()).ok();
+    }
+
+    /// Sets the coloring of the console output.
+    pub fn set_color(color: ColorChoice) {
+        COLOR.set(color).ok();
+    }
+
+    /// Gets the style of the console output: as set with [`set_color`] or, by default,
+    /// colored unless the `NO_COLOR` environment variable is set, see
+    /// <https://no-color.org>.
+    fn get_write_style() -> WriteStyle {
+        let no_color = env::var_os("NO_COLOR").map_or(false, |no_color| !no_color.is_empty());
+        match COLOR.get() {
+            Some(ColorChoice::Always) => WriteStyle::Always,
+            Some(ColorChoice::Never) => WriteStyle::Never,
+            _ if no_color => WriteStyle::Never,
+            Some(ColorChoice::Auto) => WriteStyle::Auto,
+            None => WriteStyle::Always,
+        }
+    }
+
+    /// Logs to the console, above the progress bars of [`crate::output`], and, at debug
+    /// level, to a file if any. The warnings are also passed to the progress handlers,
+    /// without their prefix.
+    struct TeeLogger {
+        console: Logger,
+        file: Option<Logger>,
+        progress: Progress,
+    }
+
+    impl TeeLogger {
+        /// Installs the logger.
+        fn init(self) {
+            let file_filter = self.file.as_ref().map_or(LevelFilter::Off, Logger::filter);
+            log::set_max_level(self.console.filter().max(file_filter));
+            log::set_boxed_logger(Box::new(self)).ok();
+        }
+    }
+
+    impl Log for TeeLogger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            self.console.enabled(metadata)
+                || self
+                    .file
+                    .as_ref()
+                    .map_or(false, |file| file.enabled(metadata))
+        }
+
+        fn log(&self, record: &Record) {
+            if record.level() == Level::Warn && self.console.enabled(record.metadata()) {
+                let message = record.args().to_string();
+                let prefix = crate::emoji::WARN.to_string();
+                self.progress
+                    .warning(message.strip_prefix(&prefix).unwrap_or(&message));
+            }
+            if self.console.enabled(record.metadata()) {
+                crate::output::suspend(|| self.console.log(record));
+            }
+            if let Some(file) = &self.file {
+                file.log(record);
+            }
+        }
+
+        fn flush(&self) {
+            self.console.flush();
+            if let Some(file) = &self.file {
+                file.flush();
+            }
+        }
+    }
+
+    /// Gets the path of a rotated log file, e.g. `espup.log.1`.
+    fn get_rotated_path(log_file: &Path, index: usize) -> PathBuf {
+        let mut path = log_file.as_os_str().to_owned();
+        path.push(format!(".{index}"));
+        PathBuf::from(path)
+    }
 
-    /// Initializes the logger
-    pub fn initialize_logger(log_level: &str) {
-        Builder::from_env(Env::default().default_filter_or(log_level))
+    /// Rotates the log file once it exceeds the maximum size, discarding the oldest one.
+    fn rotate_log_file(log_file: &Path) {
+        if log_file
+            .metadata()
+            .map_or(true, |metadata| metadata.len() < MAX_LOG_FILE_SIZE)
+        {
+            return;
+        }
+        for index in (1..LOG_FILE_ROTATIONS).rev() {
+            let _ = rename(
+                get_rotated_path(log_file, index),
+                get_rotated_path(log_file, index + 1),
+            );
+        }
+        let _ = rename(log_file, get_rotated_path(log_file, 1));
+    }
+
+    /// Opens the log file for appending, after rotating it if needed.
+    fn open_log_file(log_file: &Path) -> std::io::Result<File> {
+        rotate_log_file(log_file);
+        OpenOptions::new().create(true).append(true).open(log_file)
+    }
+
+    /// Initializes the logger. If `log_file` is set, the full debug log is also appended
+    /// to it, regardless of the console log level. The warnings are passed to the handlers
+    /// of `progress`.
+    pub fn initialize_logger(log_level: &str, log_file: Option<&Path>, progress: &Progress) {
+        let log_level = if QUIET.load(Ordering::Relaxed) {
+            "error"
+        } else {
+            LOG_LEVEL.get().map_or(log_level, String::as_str)
+        };
+        let mut console = Builder::from_env(Env::default().default_filter_or(log_level));
+        console
             .format_target(false)
             .format_timestamp_secs()
-            .write_style(WriteStyle::Always)
-            .init();
+            .write_style(get_write_style());
+        let file = match log_file.map(|log_file| (log_file, open_log_file(log_file))) {
+            Some((_, Ok(file))) => file,
+            Some((log_file, Err(e))) => {
+                TeeLogger {
+                    console: console.build(),
+                    file: None,
+                    progress: progress.clone(),
+                }
+                .init();
+                log::warn!(
+                    "{} Unable to open log file '{}': {}",
+                    crate::emoji::WARN,
+                    log_file.display(),
+                    e
+                );
+                return;
+            }
+            None => {
+                TeeLogger {
+                    console: console.build(),
+                    file: None,
+                    progress: progress.clone(),
+                }
+                .init();
+                return;
+            }
+        };
+
+        let file = Builder::new()
+            .filter_level(LevelFilter::Debug)
+            .format_timestamp_secs()
+            .write_style(WriteStyle::Never)
+            .target(Target::Pipe(Box::new(file)))
+            .build();
+        TeeLogger {
+            console: console.build(),
+            file: Some(file),
+            progress: progress.clone(),
+        }
+        .init();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::logging::{get_rotated_path, rotate_log_file, MAX_LOG_FILE_SIZE};
+        use std::fs::{read_to_string, write};
+        use tempfile::TempDir;
+
+        #[test]
+        fn test_rotate_log_file() {
+            let temp_dir = TempDir::new().unwrap();
+            let log_file = temp_dir.path().join("espup.log");
+            write(&log_file, "small").unwrap();
+            rotate_log_file(&log_file);
+            assert!(log_file.exists());
+
+            write(&log_file, vec![b'a'; MAX_LOG_FILE_SIZE as usize]).unwrap();
+            write(get_rotated_path(&log_file, 1), "older").unwrap();
+            rotate_log_file(&log_file);
+            assert!(!log_file.exists());
+            assert!(get_rotated_path(&log_file, 1).exists());
+            assert_eq!(
+                read_to_string(get_rotated_path(&log_file, 2)).unwrap(),
+                "older"
+            );
+        }
     }
 }
 
 pub mod update {
-    use crate::emoji;
-    use log::warn;
-    use std::time::Duration;
+    use crate::{
+        config::Config,
+        context::Context,
+        emoji,
+        outdated::{format_summary, get_update, Update},
+        toolchain::rust::XtensaRust,
+    };
+    use directories_next::ProjectDirs;
+    use log::{debug, warn};
+    use once_cell::sync::Lazy;
+    use serde::{Deserialize, Serialize};
+    use std::{
+        fs::{create_dir_all, read, write},
+        path::PathBuf,
+        sync::Mutex,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
     use update_informer::{registry, Check};
 
-    /// Check crates.io for a new version of the application
-    pub fn check_for_update(name: &str, version: &str) {
-        // By setting the interval to 0 seconds we invalidate the cache with each
-        // invocation and ensure we're getting up-to-date results
-        let informer =
-            update_informer::new(registry::Crates, name, version).interval(Duration::ZERO);
-
-        if let Some(version) = informer.check_version().ok().flatten() {
-            warn!(
-                "{} A new version of {name} ('{version}') is available.",
-                emoji::WARN
+    /// Hours between two checks, unless set in the `[update_check]` table.
+    pub const DEFAULT_UPDATE_CHECK_INTERVAL: u64 = 24;
+
+    /// Result of the check of the running command, printed at its end.
+    static LAST_CHECK: Lazy<Mutex<Option<LastCheck>>> = Lazy::new(|| Mutex::new(None));
+
+    /// Latest releases found by the last check, stored in the cache directory of espup so
+    /// that the releases are requested at most once per interval.
+    #[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+    struct LastCheck {
+        /// When the check ran, in seconds since the Unix epoch.
+        checked_at: u64,
+        /// Newer espup release, if any.
+        espup: Option<String>,
+        /// Latest Xtensa Rust release.
+        xtensa_rust: Option<String>,
+    }
+
+    impl LastCheck {
+        /// Whether it is older than `interval` hours, so that a new check is due.
+        fn is_due(&self, interval: u64, now: u64) -> bool {
+            now.saturating_sub(self.checked_at) >= interval * 60 * 60
+        }
+
+        /// Gets the updates of espup, running `version`, and of the installed Xtensa Rust
+        /// toolchain.
+        fn get_updates(&self, name: &str, version: &str, xtensa_rust: Option<&str>) -> Vec<Update> {
+            let espup = self
+                .espup
+                .as_deref()
+                .and_then(|latest| get_update(name, version, latest));
+            let xtensa_rust = xtensa_rust
+                .zip(self.xtensa_rust.as_deref())
+                .and_then(|(installed, latest)| get_update("Xtensa Rust", installed, latest));
+            espup.into_iter().chain(xtensa_rust).collect()
+        }
+    }
+
+    /// Gets the file of the last check.
+    fn get_last_check_path() -> Option<PathBuf> {
+        ProjectDirs::from("rs", "esp", "espup")
+            .map(|dirs| dirs.cache_dir().join("update-check.json"))
+    }
+
+    /// Seconds since the Unix epoch.
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs())
+    }
+
+    /// Requests the latest espup release from crates.io and the latest Xtensa Rust one from
+    /// GitHub. A failed request is only logged: the check must not fail the command.
+    fn check(context: &Context, name: &str, version: &str) -> LastCheck {
+        // The interval is handled by `LastCheck`, so the cache of the informer is bypassed.
+        let espup = update_informer::new(registry::Crates, name, version)
+            .interval(Duration::ZERO)
+            .check_version()
+            .map_err(|e| debug!("{} Failed to check the {name} releases: {e}", emoji::DEBUG))
+            .ok()
+            .flatten()
+            .map(|latest| latest.to_string().trim_start_matches('v').to_string());
+        let xtensa_rust = XtensaRust::get_latest_version(context)
+            .map_err(|e| {
+                debug!(
+                    "{} Failed to check the Xtensa Rust releases: {e}",
+                    emoji::DEBUG
+                )
+            })
+            .ok();
+        LastCheck {
+            checked_at: now(),
+            espup,
+            xtensa_rust,
+        }
+    }
+
+    /// Checks for newer espup and Xtensa Rust releases when enabled in the `[update_check]`
+    /// table of the configuration file, at most once per interval. The notice is printed by
+    /// [`print_notice`] at the end of the command.
+    pub fn check_for_update(context: &Context, name: &str, version: &str) {
+        let settings = Config::load_update_check(context.env.as_deref());
+        if settings.enabled != Some(true) {
+            return;
+        }
+        let path = match get_last_check_path() {
+            Some(path) => path,
+            None => return,
+        };
+        let mut last_check: LastCheck = read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        let interval = settings.interval.unwrap_or(DEFAULT_UPDATE_CHECK_INTERVAL);
+        if last_check.is_due(interval, now()) {
+            last_check = check(context, name, version);
+            let saved = path
+                .parent()
+                .map_or(Ok(()), create_dir_all)
+                .and_then(|_| write(&path, serde_json::to_vec(&last_check)?));
+            if let Err(e) = saved {
+                debug!(
+                    "{} Failed to save the update check to '{}': {e}",
+                    emoji::DEBUG,
+                    path.display()
+                );
+            }
+        }
+        *LAST_CHECK.lock().unwrap() = Some(last_check);
+    }
+
+    /// Formats the notice of the updates, with the commands applying them.
+    fn format_notice(name: &str, updates: &[Update]) -> Option<String> {
+        if updates.is_empty() {
+            return None;
+        }
+        let mut commands = Vec::new();
+        if updates.iter().any(|update| update.name == name) {
+            commands.push(format!("'{name} self-update'"));
+        }
+        if updates.iter().any(|update| update.name != name) {
+            commands.push(format!("'{name} update'"));
+        }
+        Some(format!(
+            "{}, run {} to update",
+            format_summary(updates),
+            commands.join(" and ")
+        ))
+    }
+
+    /// Prints the single notice of the updates found by [`check_for_update`], if any,
+    /// against the Xtensa Rust toolchain installed once the command completed.
+    pub fn print_notice(context: &Context, name: &str, version: &str) {
+        let last_check = match LAST_CHECK.lock().unwrap().take() {
+            Some(last_check) => last_check,
+            None => return,
+        };
+        let xtensa_rust = Config::load(context.env.as_deref())
+            .ok()
+            .and_then(|config| config.xtensa_rust)
+            .map(|xtensa_rust| xtensa_rust.version);
+        let updates = last_check.get_updates(name, version, xtensa_rust.as_deref());
+        if let Some(notice) = format_notice(name, &updates) {
+            warn!("{} {notice}", emoji::WARN);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::update::{format_notice, LastCheck};
+
+        #[test]
+        fn test_last_check() {
+            let last_check = LastCheck {
+                checked_at: 1_700_000_000,
+                espup: Some("0.3.0".to_string()),
+                xtensa_rust: Some("1.74.0.0".to_string()),
+            };
+            assert!(!last_check.is_due(24, 1_700_000_000 + 60 * 60));
+            assert!(last_check.is_due(24, 1_700_000_000 + 24 * 60 * 60));
+            assert!(LastCheck::default().is_due(24, 1_700_000_000));
+
+            let updates = last_check.get_updates("espup", "0.2.4", Some("1.73.0.1"));
+            assert_eq!(
+                format_notice("espup", &updates).unwrap(),
+                "Updates available: espup 0.2.4 -> 0.3.0, Xtensa Rust 1.73.0.1 -> 1.74.0.0, \
+                 run 'espup self-update' and 'espup update' to update"
             );
+            let updates = last_check.get_updates("espup", "0.3.0", None);
+            assert!(format_notice("espup", &updates).is_none());
+            let updates = last_check.get_updates("espup", "0.3.0", Some("1.73.0.1"));
+            assert!(format_notice("espup", &updates)
+                .unwrap()
+                .ends_with("run 'espup update' to update"));
         }
     }
 }
@@ -0,0 +1,298 @@
+//! Inspection of the installed components.
+
+use crate::{
+    config::Config,
+    context::Context,
+    emoji,
+    error::Error,
+    sbom::write_sbom,
+    targets::Target,
+    toolchain::{
+        dfu_util::DFU_UTIL_NAME,
+        espidf::{get_remote_versions, get_tool_path, CURRENT_LINK_NAME},
+        gcc::get_toolchain_name,
+        gdb::{RISCV_GDB_NAME, XTENSA_GDB_NAME},
+        get_dir_size,
+        llvm::CLANG_NAME,
+        openocd::OPENOCD_NAME,
+        qemu::QemuArch,
+        rom_elfs::ROM_ELFS_NAME,
+        rust::{
+            get_rustup_home, get_xtensa_rust_path, XtensaRust, DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME,
+        },
+        sccache::SCCACHE_NAME,
+    },
+};
+use embuild::{espidf::EspIdfVersion, git};
+use log::{info, warn};
+use miette::Result;
+use std::{
+    collections::BTreeSet,
+    fs::{read_dir, read_link},
+    path::{Path, PathBuf},
+};
+use strum::IntoEnumIterator;
+
+/// ULP toolchains installed along with ESP-IDF.
+const ULP_TOOLCHAINS: [&str; 2] = ["esp32ulp-elf", "esp32s2ulp-elf"];
+
+/// An installed component.
+#[derive(Debug, Clone)]
+pub struct Component {
+    /// Name of the component.
+    pub name: String,
+    /// Installed version.
+    pub version: String,
+    /// Installation path.
+    pub path: PathBuf,
+}
+
+impl Component {
+    /// Disk usage of the component, in bytes.
+    pub fn size(&self) -> u64 {
+        get_dir_size(&self.path)
+    }
+}
+
+/// Returns the names of the subdirectories of a directory, sorted.
+fn get_subdirectories(path: &Path) -> Vec<String> {
+    let mut dirs: Vec<String> = read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    dirs.sort();
+    dirs
+}
+
+/// Returns the versions of a tool installed under the tools directory.
+pub fn get_tool_versions(context: &Context, tool_name: &str) -> Vec<Component> {
+    let tool_path = PathBuf::from(get_tool_path(context, tool_name));
+    get_subdirectories(&tool_path)
+        .into_iter()
+        .map(|version| Component {
+            name: tool_name.to_string(),
+            path: tool_path.join(&version),
+            version,
+        })
+        .collect()
+}
+
+/// Returns the component of a tool version installed in `path`, named after the tool
+/// directory as [`get_tool_versions`] does.
+pub fn get_tool_component(path: &Path) -> Component {
+    let file_name = |path: Option<&Path>| {
+        path.and_then(Path::file_name)
+            .map_or_else(String::new, |name| name.to_string_lossy().to_string())
+    };
+    Component {
+        name: file_name(path.parent()),
+        version: file_name(Some(path)),
+        path: path.to_path_buf(),
+    }
+}
+
+/// Returns the ESP-IDF checkouts installed under the tools path.
+pub fn get_esp_idf_checkouts(context: &Context) -> Vec<Component> {
+    let tools_path = &context.tools_path;
+    get_subdirectories(tools_path)
+        .into_iter()
+        .filter(|dir| dir.starts_with("esp-idf"))
+        .flat_map(|repos_dir| {
+            let repos_path = tools_path.join(repos_dir);
+            get_subdirectories(&repos_path)
+                .into_iter()
+                .filter(|git_ref| git_ref != CURRENT_LINK_NAME)
+                .map(move |git_ref| {
+                    let path = repos_path.join(&git_ref);
+                    let version = EspIdfVersion::format(&EspIdfVersion::try_from(
+                        &git::Repository::new(&path),
+                    ));
+                    Component {
+                        name: "ESP-IDF".to_string(),
+                        version: format!("{git_ref} ({version})"),
+                        path,
+                    }
+                })
+        })
+        .collect()
+}
+
+/// Returns all the components installed by espup.
+pub fn get_installed_components(context: &Context, config: Option<&Config>) -> Vec<Component> {
+    let mut components = Vec::new();
+
+    let xtensa_rust_path = get_xtensa_rust_path(context);
+    components.extend(
+        get_subdirectories(&xtensa_rust_path)
+            .into_iter()
+            .map(|version| Component {
+                name: "Xtensa Rust".to_string(),
+                path: xtensa_rust_path.join(&version),
+                version,
+            }),
+    );
+    // Toolchains installed by previous versions of espup, or for another host, are not
+    // installed by version.
+    match config.and_then(|config| config.xtensa_rust.as_ref()) {
+        Some(xtensa_rust) => {
+            if !xtensa_rust
+                .toolchain_destination
+                .starts_with(&xtensa_rust_path)
+            {
+                components.push(Component {
+                    name: "Xtensa Rust".to_string(),
+                    version: xtensa_rust.version.clone(),
+                    path: xtensa_rust.toolchain_destination.clone(),
+                });
+            }
+        }
+        None => {
+            let toolchain_path = get_rustup_home()
+                .join("toolchains")
+                .join(DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME);
+            if toolchain_path.exists() && read_link(&toolchain_path).is_err() {
+                components.push(Component {
+                    name: "Xtensa Rust".to_string(),
+                    version: "(unknown version)".to_string(),
+                    path: toolchain_path,
+                });
+            }
+        }
+    }
+
+    components.extend(
+        get_tool_versions(context, CLANG_NAME)
+            .into_iter()
+            .map(|component| Component {
+                name: "Xtensa LLVM".to_string(),
+                ..component
+            }),
+    );
+
+    let toolchain_names: BTreeSet<String> = Target::iter()
+        .map(|target| get_toolchain_name(&target))
+        .chain(ULP_TOOLCHAINS.iter().map(|name| name.to_string()))
+        .collect();
+    for toolchain_name in toolchain_names {
+        components.extend(get_tool_versions(context, &toolchain_name));
+    }
+
+    for tool_name in [
+        XTENSA_GDB_NAME,
+        RISCV_GDB_NAME,
+        OPENOCD_NAME,
+        DFU_UTIL_NAME,
+        ROM_ELFS_NAME,
+        SCCACHE_NAME,
+    ] {
+        components.extend(get_tool_versions(context, tool_name));
+    }
+    for arch in [QemuArch::Xtensa, QemuArch::Riscv32] {
+        components.extend(get_tool_versions(context, &format!("qemu-{arch}")));
+    }
+
+    components.extend(get_esp_idf_checkouts(context));
+    if let Some(path) = config.and_then(|config| config.esp_idf_path.as_ref()) {
+        components.push(Component {
+            name: "ESP-IDF (unmanaged)".to_string(),
+            version: EspIdfVersion::format(&EspIdfVersion::try_from(&git::Repository::new(path))),
+            path: path.clone(),
+        });
+    }
+
+    components
+}
+
+/// Prints the Xtensa Rust toolchain and ESP-IDF versions available for installation.
+pub fn print_remote_versions(context: &Context) -> Result<()> {
+    info!("{} Listing available versions", emoji::DISC);
+    let xtensa_rust_versions = XtensaRust::get_available_versions(context)?;
+    println!("Xtensa Rust toolchain versions (--toolchain-version):");
+    for version in xtensa_rust_versions {
+        println!("  {version}");
+    }
+    let esp_idf_versions = get_remote_versions(context, &context.http.esp_idf_repository())?;
+    println!("ESP-IDF versions (--esp-idf-version):");
+    for version in esp_idf_versions {
+        println!("  {version}");
+    }
+    Ok(())
+}
+
+/// Prints the installed components, along with the extra crates and the export file, or
+/// writes their software bill of materials to `sbom`.
+pub fn list(context: &mut Context, sbom: Option<&Path>) -> Result<(), Error> {
+    info!("{} Listing installed components", emoji::DISC);
+    let config = context.load_config().ok();
+    let components = get_installed_components(context, config.as_ref());
+    if components.is_empty() {
+        warn!("{} No installed components found", emoji::WARN);
+        return Ok(());
+    }
+    if let Some(sbom) = sbom {
+        write_sbom(sbom, &components, config.as_ref())?;
+        info!(
+            "{} Software bill of materials written to '{}'",
+            emoji::CHECK,
+            sbom.display()
+        );
+        return Ok(());
+    }
+
+    for component in components {
+        println!(
+            "{:<20} {:<36} {:>10}  {}",
+            component.name,
+            component.version,
+            format_size(component.size()),
+            component.path.display()
+        );
+    }
+    if let Some(config) = config {
+        if let Some(extra_crates) = config.extra_crates {
+            let mut extra_crates: Vec<String> = extra_crates.into_iter().collect();
+            extra_crates.sort();
+            println!("{:<20} {}", "Extra crates", extra_crates.join(", "));
+        }
+        if let Some(export_file) = config.export_file {
+            println!("{:<20} {}", "Export file", export_file.display());
+        }
+    }
+    Ok(())
+}
+
+/// Formats a size in bytes as a human readable string.
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", size, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::list::format_size;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(1536 * 1024), "1.5 MiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+        assert_eq!(format_size(5000 * 1024 * 1024 * 1024), "5000.0 GiB");
+    }
+}
@@ -0,0 +1,70 @@
+//! Advisory lock of the installation directory, preventing concurrent espup runs from
+//! corrupting it.
+
+use crate::{context::Context, emoji, error::Error};
+use fs2::FileExt;
+use log::{debug, info};
+use std::{
+    fs::{create_dir_all, File, OpenOptions},
+    path::Path,
+};
+
+/// Name of the lock file, in the installation directory.
+pub const LOCK_FILE_NAME: &str = ".espup.lock";
+
+/// Exclusive lock of the installation directory, released when dropped.
+#[derive(Debug)]
+pub struct InstallLock {
+    _file: File,
+}
+
+impl InstallLock {
+    /// Locks the installation directory. When another espup process holds the lock, waits
+    /// for it to be released or, if `no_wait`, fails right away.
+    pub fn acquire(context: &Context, no_wait: bool) -> Result<Self, Error> {
+        Self::acquire_in(&context.tools_path, no_wait)
+    }
+
+    /// Locks the given directory.
+    fn acquire_in(directory: &Path, no_wait: bool) -> Result<Self, Error> {
+        create_dir_all(directory)
+            .map_err(|_| Error::FailedToCreateDirectory(directory.display().to_string()))?;
+        let lock_path = directory.join(LOCK_FILE_NAME);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)?;
+        if file.try_lock_exclusive().is_err() {
+            if no_wait {
+                return Err(Error::InstallationLocked(directory.display().to_string()));
+            }
+            info!(
+                "{} Blocking waiting for file lock on '{}', used by another espup process",
+                emoji::INFO,
+                directory.display()
+            );
+            file.lock_exclusive()?;
+        }
+        debug!("{} Locked '{}'", emoji::DEBUG, lock_path.display());
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::Error, lock::InstallLock};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_install_lock() {
+        let temp_dir = TempDir::new().unwrap();
+        let lock = InstallLock::acquire_in(temp_dir.path(), true).unwrap();
+        assert!(matches!(
+            InstallLock::acquire_in(temp_dir.path(), true),
+            Err(Error::InstallationLocked(_))
+        ));
+        drop(lock);
+        assert!(InstallLock::acquire_in(temp_dir.path(), true).is_ok());
+    }
+}
@@ -1,41 +1,87 @@
-use clap::Parser;
-use dirs::home_dir;
-use embuild::{
-    cmd,
-    espidf::{parse_esp_idf_git_ref, EspIdfRemote},
+use clap::{
+    parser::ValueSource, value_parser, ArgAction, ArgGroup, ArgMatches, Args, CommandFactory,
+    FromArgMatches, Parser,
 };
+use clap_complete::Shell;
+use console::Term;
 use espup::{
+    cache,
     config::Config,
-    emoji,
-    error::Error,
-    host_triple::get_host_triple,
-    logging::initialize_logger,
+    context::Context,
+    doctor, emoji,
+    environment::{
+        self, get_command, get_default_shell, get_installation_environment, print_env,
+        ACTIVE_VARIABLE,
+    },
+    envs::{get_env_toolchain_name, parse_env_name, select_env},
+    error::{Error, EXIT_FAILURE, EXIT_INTERRUPTED, EXIT_UPDATES_AVAILABLE},
+    export,
+    host_triple::HostAbi,
+    http::{
+        parse_limit_rate, Http, HttpSettings, Mirror, TlsBackend, DEFAULT_RETRIES,
+        DEFAULT_RETRY_DELAY,
+    },
+    idf::{fetch_submodules, remove_esp_idf, use_esp_idf},
+    info::print_info,
+    install::{self, InstallOptions, Profile, SkippedComponent, UpdateOptions},
+    interactive::{is_interactive, set_non_interactive},
+    interrupt,
+    list::{self, print_remote_versions},
+    logging::{initialize_logger, set_color, set_log_level, set_quiet, ColorChoice},
+    migrate::migrate_installation,
+    mirror::{self, get_candidate_mirrors},
+    outdated::{check_updates as get_updates, format_summary},
+    output,
+    progress::{Event, JsonLinesHandler},
+    project::{read_project, ProjectRequirements},
+    provision::ProvisionFormat,
+    prune::prune_unused,
+    scaffold::new_project,
+    self_update::update_espup,
     targets::{parse_targets, Target},
     toolchain::{
-        espidf::{
-            get_dist_path, get_install_path, get_tool_path, EspIdfRepo, DEFAULT_GIT_REPOSITORY,
-        },
-        gcc::{get_toolchain_name, install_gcc_targets},
-        llvm::Llvm,
+        create_staging_dir,
+        espidf::{EspIdfLayout, Generator, Submodules},
+        frameworks::Framework,
+        gcc::GccVersion,
+        llvm::{Llvm, DEFAULT_LLVM_15_VERSION},
         rust::{
-            check_rust_installation, install_extra_crates, install_riscv_target, Crate, XtensaRust,
+            parse_nightly_version, switch_default_toolchain, Crate, XtensaRust,
+            DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME, LATEST_VERSION,
         },
+        LocalArtifact,
     },
-    update::check_for_update,
+    uninstall,
+    update::{check_for_update, print_notice},
+    verify,
+    which::{print_locations, Location},
+    wizard::{self, run_install_wizard},
 };
 use log::{debug, info, warn};
-use miette::{IntoDiagnostic, Result};
+use miette::Result;
 use std::{
     collections::HashSet,
-    fs::{remove_dir_all, remove_file, File},
-    io::Write,
+    ffi::OsString,
     path::PathBuf,
+    process::{Command, ExitCode},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
 };
+use strum::IntoEnumIterator;
 
-#[cfg(windows)]
-const DEFAULT_EXPORT_FILE: &str = "export-esp.ps1";
-#[cfg(not(windows))]
-const DEFAULT_EXPORT_FILE: &str = "export-esp.sh";
+/// Documentation of the exit codes, see [`Error::exit_code`].
+const EXIT_CODES_HELP: &str = "Exit codes:
+  0  Success
+  1  Uncategorized failure
+  2  Invalid arguments
+  3  Network failure
+  4  Prerequisites not met
+  5  File system failure
+  6  Installation locked by another espup process
+  7  Verification failed
+  8  Updates available, with check-updates
+  130  Interrupted with Ctrl-C or terminated";
 
 #[derive(Parser)]
 #[command(
@@ -44,32 +90,378 @@ const DEFAULT_EXPORT_FILE: &str = "export-esp.sh";
     version,
     propagate_version = true,
     about,
+    after_help = EXIT_CODES_HELP,
     arg_required_else_help(true)
 )]
 struct Cli {
     #[command(subcommand)]
     subcommand: SubCommand,
+    /// Coloring of the output: `auto`, `always` or `never`. Defaults to the `color` of the
+    /// `[output]` table of the configuration file, or to colored unless `NO_COLOR` is set.
+    #[arg(long, env = "ESPUP_COLOR", global = true, value_parser = ColorChoice::from_str)]
+    color: Option<ColorChoice>,
+    /// Named environment, with its own tools, configuration, export file and Xtensa Rust
+    /// toolchain, e.g. `work-idf5`. Uses the default environment when not set.
+    #[arg(long, env = "ESPUP_ENV", global = true, value_parser = parse_env_name)]
+    env: Option<String>,
+    /// Format of the messages printed to stdout. With `json`, progress events are printed
+    /// as one JSON object per line.
+    #[arg(long, env = "ESPUP_MESSAGE_FORMAT", global = true, default_value = "human", value_parser = ["human", "json"])]
+    message_format: String,
+    /// Uses plain ASCII prefixes instead of emojis in the output.
+    #[arg(long, env = "ESPUP_NO_EMOJI", global = true)]
+    no_emoji: bool,
+    /// Never prompts, e.g. for confirmations or credentials. Enabled when stdin is not a
+    /// terminal.
+    #[arg(long, env = "ESPUP_NON_INTERACTIVE", global = true)]
+    non_interactive: bool,
+    /// Only prints errors.
+    #[arg(short = 'q', long, env = "ESPUP_QUIET", global = true)]
+    quiet: bool,
+    /// Directory of the ESP-IDF tools, their downloaded archives and the ESP-IDF checkouts.
+    /// Takes precedence over the installation directory recorded in the configuration file.
+    #[arg(long, env = "IDF_TOOLS_PATH", global = true, value_name = "DIR")]
+    tools_path: Option<PathBuf>,
 }
 
 #[derive(Parser)]
 pub enum SubCommand {
+    /// Manages the cache of downloaded archives
+    Cache(CacheOpts),
+    /// Checks whether newer releases of the installed components are available
+    CheckUpdates(CheckUpdatesOpts),
+    /// Generates completions for the given shell
+    Completions(CompletionsOpts),
+    /// Sets the installed Xtensa Rust toolchain that `esp` resolves to
+    Default(DefaultOpts),
+    /// Prints what an installation with the given options would change in the environment
+    Diff(Box<InstallOpts>),
+    /// Checks the prerequisites and the health of the environment
+    Doctor(DoctorOpts),
+    /// Prints the exports of the installation, or writes them to the VS Code settings
+    Env(EnvOpts),
+    /// Archives the installed environment to provision identical hosts
+    Export(ExportOpts),
+    /// Manages the installed ESP-IDF versions
+    Idf(IdfOpts),
+    /// Prints the details of an installed component
+    Info(InfoOpts),
     /// Installs esp-rs environment
     Install(Box<InstallOpts>),
+    /// Lists installed components
+    List(ListOpts),
+    /// Moves the installation of a previous espup version to the current layout
+    Migrate(MigrateOpts),
+    /// Manages the mirrors of the downloads
+    Mirror(MirrorOpts),
+    /// Generates a new project for the installation from the official templates
+    New(NewOpts),
+    /// Removes the tool and ESP-IDF versions no longer used by the environment
+    Prune(PruneOpts),
+    /// Runs a command with the environment of the installation
+    Run(RunOpts),
+    /// Updates espup to the latest release
+    SelfUpdate(SelfUpdateOpts),
+    /// Spawns a shell with the environment of the installation
+    Shell(ShellOpts),
     /// Uninstalls esp-rs environment
     Uninstall(UninstallOpts),
-    /// Updates Xtensa Rust toolchain
+    /// Updates Xtensa Rust toolchain, and the ESP-IDF checkout of a branch
     Update(UpdateOpts),
+    /// Verifies the integrity of the installed components
+    Verify(VerifyOpts),
+    /// Prints the locations of the installation, e.g. the libclang or ESP-IDF directories
+    #[command(alias = "paths")]
+    Which(WhichOpts),
+}
+
+/// Options of the logs.
+#[derive(Debug, Args)]
+pub struct LogArgs {
+    /// Appends the full debug log to the given file, regardless of `--log-level`. The file
+    /// is rotated once larger than 5 MiB.
+    #[arg(long, env = "ESPUP_LOG_FILE")]
+    pub log_file: Option<PathBuf>,
+    /// Verbosity level of the logs.
+    #[arg(short = 'l', long, env = "ESPUP_LOG_LEVEL", default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
+    pub log_level: String,
+}
+
+/// Options of the HTTP client.
+#[derive(Debug, Args)]
+pub struct HttpArgs {
+    /// Path to an additional root certificate, in PEM or DER format, for HTTPS requests.
+    #[arg(long, env = "ESPUP_CACERT")]
+    pub cacert: Option<PathBuf>,
+    /// Timeout, in seconds, of the connection to a server. Defaults to the `connect` value of
+    /// the `[timeouts]` table of the configuration file, or 10 seconds.
+    #[arg(long, env = "ESPUP_CONNECT_TIMEOUT", value_parser = value_parser!(u64).range(1..))]
+    pub connect_timeout: Option<u64>,
+    /// Proxy URL for HTTP and HTTPS requests. Defaults to the `HTTP_PROXY` and `HTTPS_PROXY` environment variables.
+    #[arg(long, env = "ESPUP_PROXY")]
+    pub proxy: Option<String>,
+    /// Timeout, in seconds, of stalled reads and writes, after which the request is retried.
+    /// Defaults to the `read` value of the `[timeouts]` table of the configuration file, or
+    /// 30 seconds.
+    #[arg(long, env = "ESPUP_READ_TIMEOUT", value_parser = value_parser!(u64).range(1..))]
+    pub read_timeout: Option<u64>,
+    /// TLS implementation of HTTPS requests: `native-tls`, the TLS library of the system, or
+    /// `rustls`, among the ones enabled in this build.
+    #[arg(long, env = "ESPUP_TLS_BACKEND", value_parser = TlsBackend::parse, default_value_t = TlsBackend::default())]
+    pub tls_backend: TlsBackend,
+}
+
+/// Options of the GitHub API queries and of the retries of the requests.
+#[derive(Debug, Args)]
+pub struct GithubArgs {
+    /// GitHub token used to authenticate GitHub API requests, avoiding rate limits.
+    #[arg(long, env = "GITHUB_TOKEN", hide_env_values = true)]
+    pub github_token: Option<String>,
+    /// Number of retries of downloads and GitHub API queries that fail due to network errors.
+    #[arg(long, env = "ESPUP_RETRIES", default_value_t = DEFAULT_RETRIES)]
+    pub retries: u32,
+    /// Delay, in seconds, before the first retry. It doubles with every retry.
+    #[arg(long, env = "ESPUP_RETRY_DELAY", default_value_t = DEFAULT_RETRY_DELAY)]
+    pub retry_delay: u64,
+}
+
+/// Options of the downloads.
+#[derive(Debug, Args)]
+pub struct DownloadArgs {
+    /// Maximum transfer rate of downloads, in bytes per second, optionally followed by `k`,
+    /// `m` or `g`, e.g. `500k`. ESP-IDF and its tools are downloaded at full speed.
+    #[arg(long, env = "ESPUP_LIMIT_RATE", value_parser = parse_limit_rate)]
+    pub limit_rate: Option<u64>,
+    /// Stores the downloaded archives in the cache, so that the next installations extract
+    /// them again instead of downloading them. Archives are otherwise only cached by
+    /// `espup cache fetch`.
+    #[arg(long, env = "ESPUP_CACHE_ARCHIVES")]
+    pub cache_archives: bool,
+    /// Minimum transfer rate of downloads, in bytes per second, optionally followed by `k`,
+    /// `m` or `g`. Downloads slower than it for 30 seconds fail, and are retried from the
+    /// next mirror, if any.
+    #[arg(long, env = "ESPUP_LOW_SPEED_LIMIT", value_parser = parse_limit_rate)]
+    pub low_speed_limit: Option<u64>,
+}
+
+/// Mirrors of the downloads and of the ESP-IDF repository.
+#[derive(Debug, Args)]
+pub struct MirrorArgs {
+    /// Mirrors to download the release assets from, in order of preference: `github`,
+    /// `espressif`, `jihulab` or the URL of a custom mirror replicating the GitHub layout.
+    /// Downloads failing from a mirror, after their retries, are retried from the next one.
+    /// ESP-IDF is cloned, and its releases queried, from the first one. Can be repeated or
+    /// comma separated. Defaults to the `mirrors` of the configuration file, or `github`.
+    #[arg(long, env = "ESPUP_MIRROR", value_parser = Mirror::parse, value_delimiter = ',')]
+    pub mirror: Vec<Mirror>,
+}
+
+#[derive(Debug, Parser)]
+pub struct CacheOpts {
+    #[command(subcommand)]
+    pub subcommand: CacheSubCommand,
+}
+
+#[derive(Debug, Parser)]
+pub enum CacheSubCommand {
+    /// Removes cached archives
+    Clean(CacheCleanOpts),
+    /// Downloads the archives of an installation into the cache, without installing them, and
+    /// prints their cache key
+    Fetch(Box<InstallOpts>),
+    /// Lists the cached archives, and the directories they were extracted to
+    List(CacheListOpts),
+    /// Prints the total size of the cache
+    Size(CacheListOpts),
+}
+
+#[derive(Debug, Parser)]
+#[command(group(ArgGroup::new("selection").required(true).args(["all", "older_than"])))]
+pub struct CacheCleanOpts {
+    /// Removes every cached archive.
+    #[arg(long)]
+    pub all: bool,
+    /// Prints the archives that would be removed without removing them.
+    #[arg(long, env = "ESPUP_DRY_RUN")]
+    pub dry_run: bool,
+    /// Removes only the archives older than the given number of days.
+    #[arg(long, value_name = "DAYS")]
+    pub older_than: Option<u64>,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct CacheListOpts {
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct CheckUpdatesOpts {
+    #[command(flatten)]
+    pub github: GithubArgs,
+    #[command(flatten)]
+    pub http: HttpArgs,
+    #[command(flatten)]
+    pub mirror: MirrorArgs,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct CompletionsOpts {
+    /// Shell to generate the completions for.
+    #[arg(value_enum)]
+    pub shell: Shell,
+}
+
+#[derive(Debug, Parser)]
+pub struct DefaultOpts {
+    /// Fails right away when another espup process is using the installation directory,
+    /// instead of waiting for it to finish.
+    #[arg(long, env = "ESPUP_NO_WAIT")]
+    pub no_wait: bool,
+    /// Name of the installed Xtensa Rust toolchain, as given to `--name` of the install
+    /// subcommand, e.g. `esp-1.64`.
+    #[arg(value_name = "TOOLCHAIN")]
+    pub toolchain: String,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct DoctorOpts {
+    /// ESP-IDF version to check the Python requirements against. Defaults to the latest.
+    #[arg(short = 'e', long, env = "ESPUP_ESP_IDF_VERSION")]
+    pub esp_idf_version: Option<String>,
+    /// Installs the missing system packages using the system package manager.
+    #[arg(long, env = "ESPUP_INSTALL_PREREQS")]
+    pub install_prereqs: bool,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct EnvOpts {
+    /// Shell to print the exports for: `bash`, `zsh`, `fish`, `powershell` or `cmd`. Defaults
+    /// to the running shell on Windows, PowerShell or cmd, and to bash elsewhere.
+    #[arg(long, env = "ESPUP_SHELL", value_parser = environment::Shell::from_str, conflicts_with = "vscode")]
+    pub shell: Option<environment::Shell>,
+    /// Project directory whose `.vscode/settings.json` is created or merged with the
+    /// `rust-analyzer.server.extraEnv` of the installation: its `PATH`, `LIBCLANG_PATH` and
+    /// the Xtensa Rust toolchain as `RUSTUP_TOOLCHAIN`.
+    #[arg(long, env = "ESPUP_VSCODE", value_name = "DIR")]
+    pub vscode: Option<PathBuf>,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct IdfOpts {
+    #[command(subcommand)]
+    pub subcommand: IdfSubCommand,
+}
+
+#[derive(Debug, Parser)]
+pub enum IdfSubCommand {
+    /// Removes an installed ESP-IDF version and the tool versions only it requires
+    Remove(IdfRemoveOpts),
+    /// Fetches the submodules of an installed ESP-IDF version
+    Submodules(IdfSubmodulesOpts),
+    /// Points the export file to an installed ESP-IDF version
+    Use(IdfUseOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct IdfRemoveOpts {
+    /// Prints what would be removed without removing it.
+    #[arg(long, env = "ESPUP_DRY_RUN")]
+    pub dry_run: bool,
+    /// Fails right away when another espup process is using the installation directory,
+    /// instead of waiting for it to finish.
+    #[arg(long, env = "ESPUP_NO_WAIT")]
+    pub no_wait: bool,
+    /// Installed ESP-IDF version to remove, in the same format as `--esp-idf-version` of the
+    /// install subcommand.
+    #[arg(value_name = "VERSION")]
+    pub esp_idf_version: String,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct IdfSubmodulesOpts {
+    /// Fails right away when another espup process is using the installation directory,
+    /// instead of waiting for it to finish.
+    #[arg(long, env = "ESPUP_NO_WAIT")]
+    pub no_wait: bool,
+    /// Installed ESP-IDF version, in the same format as `--esp-idf-version` of the install
+    /// subcommand. Defaults to the version in use.
+    #[arg(value_name = "VERSION")]
+    pub esp_idf_version: Option<String>,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct IdfUseOpts {
+    /// Installed ESP-IDF version to use, in the same format as `--esp-idf-version` of the
+    /// install subcommand.
+    #[arg(value_name = "VERSION")]
+    pub esp_idf_version: String,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct InfoOpts {
+    /// Component to inspect, by name, e.g. `xtensa-rust` or `esp-idf`, or by tool directory,
+    /// e.g. `esp-clang`.
+    pub component: String,
+    /// Installed version to inspect. Prints every installed version when not set.
+    #[arg(value_name = "VERSION")]
+    pub component_version: Option<String>,
+    /// Prints the details as a JSON array.
+    #[arg(long, env = "ESPUP_JSON")]
+    pub json: bool,
 }
 
 #[derive(Debug, Parser)]
 pub struct InstallOpts {
+    /// Builds a hello world project for one of the targets once installed, `std` when
+    /// ESP-IDF is installed and `no_std` otherwise, to check the installation.
+    #[arg(long, env = "ESPUP_CHECK", conflicts_with_all = ["dry_run", "target_host"])]
+    pub check: bool,
+    /// CMake generator, ninja is only installed for ninja generators [ninja, ninja-multi-config, unix-makefiles, borland-makefiles, msys-makefiles, mingw-makefiles, nmake-makefiles, nmake-makefiles-jom, watcom-wmake].
+    /// Defaults to ninja, or unix-makefiles when the ESP-IDF tools provide no Ninja build for the host.
+    #[arg(long, env = "ESPUP_CMAKE_GENERATOR", value_parser = Generator::from_str)]
+    pub cmake_generator: Option<Generator>,
     /// Target triple of the host.
-    #[arg(short = 'd', long, required = false)]
+    #[arg(short = 'd', long, env = "ESPUP_DEFAULT_HOST", required = false)]
     pub default_host: Option<String>,
+    /// Prints the actions that would be performed without performing them.
+    #[arg(long, env = "ESPUP_DRY_RUN")]
+    pub dry_run: bool,
+    /// Naming scheme of the ESP-IDF checkouts: `readable`, `esp-idf/<version>` for the
+    /// default repository, or `hashed`, `esp-idf-<hash of the repository URL>/<version>` as
+    /// previous versions. Checkouts of other repositories are always hashed.
+    #[arg(long, env = "ESPUP_ESP_IDF_LAYOUT", default_value = "readable", value_parser = EspIdfLayout::from_str, conflicts_with = "idf_path")]
+    pub esp_idf_layout: EspIdfLayout,
+    /// ESP-IDF submodules to initialize: `all`, `none` or a comma separated list of paths
+    /// (e.g. `components/mbedtls,components/lwip`). The rest can be fetched later with
+    /// `espup idf submodules`.
+    #[arg(long, env = "ESPUP_ESP_IDF_SUBMODULES", default_value = "all", value_parser = Submodules::parse, conflicts_with = "idf_path")]
+    pub esp_idf_submodules: Submodules,
     /// ESP-IDF version to install. If empty, no esp-idf is installed. Version format:
     ///
     /// - `commit:<hash>`: Uses the commit `<hash>` of the `esp-idf` repository.
     ///
+    /// - `<hash>`: Uses the commit `<hash>`, full or abbreviated to at least 7 digits.
+    ///
+    /// - `refs/<name>`: Uses the commit of the reference, e.g. `refs/pull/1234/head` for a
+    ///   pull request, installed in `pull-1234`. Updates reset it to the latest commit.
+    ///
     /// - `tag:<tag>`: Uses the tag `<tag>` of the `esp-idf` repository.
     ///
     /// - `branch:<branch>`: Uses the branch `<branch>` of the `esp-idf` repository.
@@ -79,409 +471,1335 @@ pub struct InstallOpts {
     /// - `<branch>`: Uses the branch `<branch>` of the `esp-idf` repository.
     ///
     /// When using this option, `ldproxy` crate will also be installed.
-    #[arg(short = 'e', long, required = false)]
+    #[arg(short = 'e', long, env = "ESPUP_ESP_IDF_VERSION", required = false)]
     pub esp_idf_version: Option<String>,
     /// Destination of the generated export file.
-    #[arg(short = 'f', long)]
+    #[arg(short = 'f', long, env = "ESPUP_EXPORT_FILE")]
     pub export_file: Option<PathBuf>,
-    /// Comma or space list of extra crates to install.
-    #[arg(short = 'c', long, required = false, value_parser = Crate::parse_crates)]
+    /// Comma or space list of extra crates to install, e.g. `espflash,cargo-espflash,ldproxy,cargo-generate`.
+    /// Prebuilt binaries are installed with `cargo-binstall` when available, otherwise the
+    /// crates are built with `cargo install`.
+    #[arg(short = 'c', long, env = "ESPUP_EXTRA_CRATES", required = false, value_parser = Crate::parse_crates)]
     pub extra_crates: Option<HashSet<Crate>>,
-    /// LLVM version.
-    #[arg(short = 'x', long, default_value = "15", value_parser = ["15"])]
+    /// Comma separated list of Espressif frameworks to install along with ESP-IDF:
+    /// `esp-adf` and `esp-matter`. They are cloned next to ESP-IDF and their path is
+    /// exported as `ADF_PATH` and `ESP_MATTER_PATH`.
+    #[arg(long, env = "ESPUP_EXTRA_FRAMEWORKS", value_parser = Framework::from_str, value_delimiter = ',')]
+    pub extra_frameworks: Vec<Framework>,
+    /// Installs even when the estimated disk usage exceeds the available disk space.
+    #[arg(long, env = "ESPUP_FORCE")]
+    pub force: bool,
+    /// GCC version to install, for every target (e.g. `11.2.0`) or for a single one
+    /// (e.g. `esp32=11.2.0`). Can be repeated or comma separated. Supported versions:
+    /// 8.4.0 (default) and 11.2.0. Not used when installing ESP-IDF, which provides its own GCC.
+    #[arg(long, env = "ESPUP_GCC_VERSION", value_parser = GccVersion::parse, value_delimiter = ',', conflicts_with_all = ["esp_idf_version", "idf_path"])]
+    pub gcc_version: Vec<GccVersion>,
+    /// ABI of the Windows host, `msvc` or `gnu`, selecting the matching Xtensa Rust build.
+    /// The linker of the ABI, from the Visual Studio Build Tools or MinGW-w64, has to be
+    /// installed. Defaults to the ABI of the host triple.
+    #[arg(long, env = "ESPUP_HOST_ABI", value_parser = HostAbi::from_str)]
+    pub host_abi: Option<HostAbi>,
+    /// Writes ESP-IDF compatible export scripts, `export.sh` or `export.bat` and `export.ps1`
+    /// on Windows, to the directory, which source the export file of espup. Existing scripts
+    /// not generated by espup are never overwritten.
+    #[arg(long, env = "ESPUP_IDF_EXPORT_SCRIPTS")]
+    pub idf_export_scripts: Option<PathBuf>,
+    /// Downloads the release archive of ESP-IDF, which includes every submodule, instead of
+    /// cloning the repository: faster and without git, but only for releases, e.g. `v5.1`,
+    /// and the checkout cannot be updated with git.
+    #[arg(long, env = "ESPUP_IDF_FROM_ARCHIVE", requires = "esp_idf_version", conflicts_with_all = ["idf_path", "shallow"])]
+    pub idf_from_archive: bool,
+    /// Path of an existing ESP-IDF checkout to use instead of cloning one. The matching
+    /// tools are installed and the environment is exported for it, but the checkout is
+    /// never modified nor removed.
+    #[arg(long, env = "ESPUP_IDF_PATH", conflicts_with = "esp_idf_version")]
+    pub idf_path: Option<PathBuf>,
+    /// Directory where the tools are installed. Defaults to `$XDG_DATA_HOME/espup` on Linux,
+    /// unless a previous installation exists in `~/.espressif`, which is the default on other
+    /// platforms.
+    #[arg(long, env = "ESPUP_HOME")]
+    pub install_dir: Option<PathBuf>,
+    /// Installs the missing system packages using the system package manager.
+    #[arg(long, env = "ESPUP_INSTALL_PREREQS")]
+    pub install_prereqs: bool,
+    /// Installs rustup, when it is not installed, by running rustup-init non-interactively.
+    /// It honors the `RUSTUP_HOME` and `CARGO_HOME` environment variables.
+    #[arg(long, env = "ESPUP_INSTALL_RUSTUP")]
+    pub install_rustup: bool,
+    /// Walks through the targets, ESP-IDF, the optional tools and the export file, then
+    /// prints the equivalent command line, for scripts.
+    #[arg(short = 'i', long, env = "ESPUP_INTERACTIVE")]
+    pub interactive: bool,
+    /// Keeps the `com.apple.quarantine` attribute of the downloaded tools on macOS, which
+    /// is removed by default so that Gatekeeper does not block them.
+    #[arg(long, env = "ESPUP_KEEP_QUARANTINE")]
+    pub keep_quarantine: bool,
+    /// Installs only the LLVM libraries, enough for `LIBCLANG_PATH`, saving about 1 GB.
+    /// Implied by `--profile-minimal`.
+    #[arg(long, env = "ESPUP_LLVM_LIBS_ONLY", conflicts_with = "skip_llvm")]
+    pub llvm_libs_only: bool,
+    /// LLVM version: `15` or a release of the form `esp-<major>.<minor>.<patch>-<date>`
+    /// (e.g. `esp-15.0.0-20221201`).
+    #[arg(short = 'x', long, env = "ESPUP_LLVM_VERSION", default_value = "15", value_parser = Llvm::parse_version)]
     pub llvm_version: String,
-    /// Verbosity level of the logs.
-    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
-    pub log_level: String,
-    /// Nightly Rust toolchain version.
-    #[arg(short = 'n', long, default_value = "nightly")]
+    /// Local archive of a component, installed instead of downloading it, of the form
+    /// `<component>=<path>`: `llvm`, `rust`, `rust-src` or a GCC toolchain, e.g.
+    /// `xtensa-esp32-elf=./xtensa-esp32-elf.tar.xz`. Can be repeated or comma separated.
+    #[arg(long, env = "ESPUP_LOCAL_ARTIFACT", value_parser = LocalArtifact::parse, value_delimiter = ',')]
+    pub local_artifact: Vec<LocalArtifact>,
+    /// Comma separated list of ESP-IDF paths to keep in minified installations, from the
+    /// ones excluded by default: docs, examples, tools/esp_app_trace and tools/test_idf_size.
+    #[arg(
+        long,
+        env = "ESPUP_MINIFY_KEEP",
+        requires = "profile_minimal",
+        value_delimiter = ','
+    )]
+    pub minify_keep: Vec<String>,
+    /// Comma separated list of additional ESP-IDF paths to exclude in minified installations.
+    #[arg(
+        long,
+        env = "ESPUP_MINIFY_REMOVE",
+        requires = "profile_minimal",
+        value_delimiter = ','
+    )]
+    pub minify_remove: Vec<String>,
+    /// Name of the Xtensa Rust toolchain in rustup, e.g. `esp-1.64`, used as `cargo +<name>`
+    /// and in `rust-toolchain.toml`. Each version is also registered as `esp-<version>`, and
+    /// kept side by side with the other versions.
+    #[arg(short = 'a', long, env = "ESPUP_NAME", default_value = DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME, value_parser = XtensaRust::parse_toolchain_name)]
+    pub name: String,
+    /// Nightly Rust toolchain version, used for RISC-V targets: `nightly` or a dated nightly,
+    /// e.g. `nightly-2023-01-01`, to reproduce the toolchain of a project exactly.
+    #[arg(short = 'n', long, env = "ESPUP_NIGHTLY_VERSION", default_value = "nightly", value_parser = parse_nightly_version)]
     pub nightly_version: String,
+    /// Fails right away when another espup process is using the installation directory,
+    /// instead of waiting for it to finish.
+    #[arg(long, env = "ESPUP_NO_WAIT")]
+    pub no_wait: bool,
+    /// Overrides, in the export file, the ESP-IDF environment already active in the shell,
+    /// e.g. `IDF_PATH` from a previously sourced `export.sh`, instead of only warning about it.
+    #[arg(long, env = "ESPUP_OVERRIDE_ENV")]
+    pub override_env: bool,
+    /// Installation profile, kept in the configuration file so that `espup update` maintains
+    /// it: `minimal`, like `--profile-minimal`, installs the Rust toolchains and the GCC
+    /// toolchains of the targets; `default` the components selected with the options; `full`
+    /// adds ESP-IDF (v5.1 unless `--esp-idf-version` is given), OpenOCD, GDB, QEMU and the
+    /// `cargo-generate` and `espflash` crates; `container` installs into `--install-dir`
+    /// without touching the shell profiles, clears the download cache and prints the
+    /// environment as `ENV` instructions of a Dockerfile.
+    #[arg(long, env = "ESPUP_PROFILE", value_parser = Profile::from_str)]
+    pub profile: Option<Profile>,
     ///  Minifies the installation.
-    #[arg(short = 'm', long)]
+    #[arg(short = 'm', long, env = "ESPUP_PROFILE_MINIMAL")]
     pub profile_minimal: bool,
-    /// Comma or space separated list of targets [esp32,esp32s2,esp32s3,esp32c2,esp32c3,all].
-    #[arg(short = 't', long, default_value = "all", value_parser = parse_targets)]
-    pub targets: HashSet<Target>,
-    /// Xtensa Rust toolchain version.
-    #[arg(short = 'v', long, value_parser = XtensaRust::parse_version)]
-    pub toolchain_version: Option<String>,
+    /// Directory of a project whose requirements are installed: the targets, ESP-IDF and
+    /// Xtensa Rust versions, and the GCC and LLVM versions of the `[toolchains]` table, of
+    /// `espup.toml` or of the `[package.metadata.espup]` table of `Cargo.toml`, and the
+    /// toolchain of `rust-toolchain.toml`. Options given explicitly take precedence.
+    #[arg(
+        long,
+        env = "ESPUP_PROJECT",
+        value_name = "DIR",
+        conflicts_with = "target_host"
+    )]
+    pub project: Option<PathBuf>,
+    /// Removes, once installed, the cached archives of the versions that are no longer
+    /// installed, keeping the ones needed to reinstall the current versions.
+    #[arg(long, env = "ESPUP_PRUNE_CACHE", conflicts_with = "profile_minimal")]
+    pub prune_cache: bool,
+    /// Python interpreter, path or name in `PATH` (e.g. `python3.11`), used to install ESP-IDF.
+    #[arg(long, env = "ESPUP_PYTHON")]
+    pub python: Option<PathBuf>,
+    /// Location of the Python virtual environment of ESP-IDF. An existing environment is
+    /// reused, otherwise it is created. Defaults to a directory under the tools path.
+    #[arg(long, env = "ESPUP_PYTHON_ENV")]
+    pub python_env: Option<PathBuf>,
+    /// Refuses the releases without a valid signature: the downloaded archives without a
+    /// detached OpenPGP signature, or with one that `gpg` does not verify, and ESP-IDF
+    /// versions other than signed tags. Implies `--verify-signatures`.
+    #[arg(long, env = "ESPUP_REQUIRE_SIGNATURES")]
+    pub require_signatures: bool,
+    /// Clones only the last commit of ESP-IDF, the extra frameworks and their submodules,
+    /// reducing the download size and disk usage. By default, their full history is cloned.
+    #[arg(long, env = "ESPUP_SHALLOW")]
+    pub shallow: bool,
+    /// Comma separated list of components to exclude from the installation and from the
+    /// exported environment, when they are already installed system-wide: `gcc`, `llvm`,
+    /// `rust-src`, `openocd`, `python-env`, `ulp`, `cmake` and `ninja`. A skipped
+    /// `python-env` requires an existing one, given with `--python-env` or activated.
+    #[arg(long, env = "ESPUP_SKIP", value_parser = SkippedComponent::from_str, value_delimiter = ',', value_name = "COMPONENT")]
+    pub skip: Vec<SkippedComponent>,
+    /// Skips the LLVM installation, when a suitable libclang is already available in
+    /// `LIBCLANG_PATH`.
+    #[arg(long, env = "ESPUP_SKIP_LLVM", conflicts_with = "llvm_version")]
+    pub skip_llvm: bool,
+    /// Skips the OpenOCD installation, which ESP-IDF installs by default on Windows.
+    #[arg(long, env = "ESPUP_SKIP_OPENOCD", conflicts_with = "with_openocd")]
+    pub skip_openocd: bool,
+    /// Skips the toolchains of the ULP coprocessors installed along with ESP-IDF: the ULP FSM
+    /// toolchain of the ESP32, ESP32-S2 and ESP32-S3, and the RISC-V toolchain of the ULP
+    /// RISC-V of the ESP32-S2 and ESP32-S3, unless a RISC-V target is selected.
+    #[arg(long, env = "ESPUP_SKIP_ULP")]
+    pub skip_ulp: bool,
+    /// Directory of the temporary files and of the archives being extracted, instead of the
+    /// temporary directory of the system and the tools directory, e.g. when the former is a
+    /// small tmpfs or the latter a network home. The extracted tools are moved into place.
+    #[arg(long, env = "ESPUP_TMPDIR", value_name = "DIR")]
+    pub staging_dir: Option<PathBuf>,
+    /// Installs a hermetic environment into DIR without rustup, for build machines where it
+    /// is not available: the Xtensa Rust toolchain, used for every target, GCC and LLVM,
+    /// with an export file in DIR that sets `PATH` and `RUSTC`. `~/.rustup` and the
+    /// configuration file are left untouched.
+    #[arg(long, env = "ESPUP_STANDALONE", value_name = "DIR", conflicts_with_all = ["install_dir", "target_host", "esp_idf_version", "idf_path", "extra_crates", "check", "install_rustup"])]
+    pub standalone: Option<PathBuf>,
+    /// Target triple of the host to provision, when different from this one: its artifacts
+    /// are downloaded into `--install-dir` and no toolchain is registered with rustup.
+    #[arg(long, env = "ESPUP_TARGET_HOST", requires = "install_dir", conflicts_with_all = ["default_host", "esp_idf_version", "idf_path", "extra_crates"])]
+    pub target_host: Option<String>,
+    /// Comma or space separated list of targets [esp32,esp32s2,esp32s3,esp32c2,esp32c3,esp32c6,esp32h2,esp32p4]
+    /// or target groups [all,xtensa,riscv]. When omitted, the chips are picked from a list
+    /// in interactive terminals, otherwise all of them are installed.
+    #[arg(short = 't', long, env = "ESPUP_TARGETS", value_parser = parse_targets)]
+    pub targets: Option<HashSet<Target>>,
+    /// Xtensa Rust toolchain version: `latest`, `pre-release` for the latest release including
+    /// pre-releases, or an exact version, e.g. `1.64.0.0`.
+    #[arg(short = 'v', long, env = "ESPUP_TOOLCHAIN_VERSION", default_value = LATEST_VERSION, value_parser = XtensaRust::parse_version_request)]
+    pub toolchain_version: String,
+    /// Verifies, before extracting them, the downloaded archives against their detached
+    /// OpenPGP signature (`<url>.asc` or `<url>.sig`) and the ESP-IDF tags with
+    /// `git verify-tag`, when a signature is published. The signing keys must be imported
+    /// into the keyring of `gpg`.
+    #[arg(long, env = "ESPUP_VERIFY_SIGNATURES")]
+    pub verify_signatures: bool,
+    /// Installs Espressif's ccache along with ESP-IDF, and enables it in the ESP-IDF builds
+    /// with `IDF_CCACHE_ENABLE` and a `CCACHE_DIR` in the tools path.
+    #[arg(long, env = "ESPUP_WITH_CCACHE")]
+    pub with_ccache: bool,
+    /// Installs dfu-util, to flash the ESP32-S2 and ESP32-S3 through USB DFU: Espressif's
+    /// build on Windows, the system package elsewhere.
+    #[arg(long, env = "ESPUP_WITH_DFU_UTIL")]
+    pub with_dfu_util: bool,
+    /// Installs Espressif's GDB, to debug the selected targets.
+    #[arg(long, env = "ESPUP_WITH_GDB")]
+    pub with_gdb: bool,
+    /// Installs Espressif's OpenOCD, to flash and debug the targets through JTAG, even
+    /// without ESP-IDF.
+    #[arg(long, env = "ESPUP_WITH_OPENOCD")]
+    pub with_openocd: bool,
+    /// Installs Espressif's QEMU fork, to emulate the selected targets it supports: the
+    /// ESP32, ESP32-S3 and ESP32-C3.
+    #[arg(long, env = "ESPUP_WITH_QEMU")]
+    pub with_qemu: bool,
+    /// Installs the ROM ELFs of the chips, used by GDB to decode backtraces into ROM code.
+    #[arg(long, env = "ESPUP_WITH_ROM_ELFS")]
+    pub with_rom_elfs: bool,
+    /// Installs sccache and enables it in the Cargo builds with `RUSTC_WRAPPER`, and in the
+    /// CMake builds, e.g. the ESP-IDF ones, with `CMAKE_C_COMPILER_LAUNCHER` and
+    /// `CMAKE_CXX_COMPILER_LAUNCHER`. The cache is shared by the projects, in the tools path.
+    #[arg(long, env = "ESPUP_WITH_SCCACHE", conflicts_with = "with_ccache")]
+    pub with_sccache: bool,
+    /// Installs, on Linux, the udev rules of the USB-JTAG/serial of the chips and of the USB
+    /// to UART bridges of the development boards into `/etc/udev/rules.d`, asking for the sudo
+    /// password when needed, and reloads them, to flash and debug the chips without root.
+    #[arg(long, env = "ESPUP_WITH_UDEV_RULES", conflicts_with = "target_host")]
+    pub with_udev_rules: bool,
+    /// Installs, on Windows, the CP210x, CH34x and USB-JTAG drivers that `pnputil` does not
+    /// list as installed: their official packages are downloaded and installed as
+    /// administrator, once confirmed, or right away with `--yes`.
+    #[arg(long, env = "ESPUP_WITH_USB_DRIVERS", conflicts_with = "target_host")]
+    pub with_usb_drivers: bool,
+    /// Skips the confirmation of large installations, for non-interactive use.
+    #[arg(short = 'y', long, env = "ESPUP_YES")]
+    pub yes: bool,
+    #[command(flatten)]
+    pub download: DownloadArgs,
+    #[command(flatten)]
+    pub github: GithubArgs,
+    #[command(flatten)]
+    pub http: HttpArgs,
+    #[command(flatten)]
+    pub mirror: MirrorArgs,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+impl From<InstallOpts> for InstallOptions {
+    fn from(args: InstallOpts) -> Self {
+        let mut options = Self {
+            cache_archives: args.download.cache_archives,
+            check: args.check,
+            cmake_generator: args.cmake_generator,
+            default_host: args.default_host,
+            diff: false,
+            dry_run: args.dry_run,
+            esp_idf_layout: args.esp_idf_layout,
+            esp_idf_submodules: args.esp_idf_submodules,
+            esp_idf_version: args.esp_idf_version,
+            export_file: args.export_file,
+            extra_crates: args.extra_crates,
+            extra_frameworks: args.extra_frameworks,
+            fetch: false,
+            force: args.force,
+            gcc_version: args.gcc_version,
+            host_abi: args.host_abi,
+            idf_export_scripts: args.idf_export_scripts,
+            idf_from_archive: args.idf_from_archive,
+            idf_path: args.idf_path,
+            install_dir: args.standalone.clone().or(args.install_dir),
+            install_prereqs: args.install_prereqs,
+            install_rustup: args.install_rustup,
+            keep_quarantine: args.keep_quarantine,
+            llvm_libs_only: args.llvm_libs_only,
+            llvm_version: args.llvm_version,
+            local_artifact: args.local_artifact,
+            minify_keep: args.minify_keep,
+            minify_remove: args.minify_remove,
+            name: args.name,
+            nightly_version: args.nightly_version,
+            no_wait: args.no_wait,
+            override_env: args.override_env,
+            profile: None,
+            profile_container: false,
+            profile_minimal: args.profile_minimal,
+            prune_cache: args.prune_cache,
+            python: args.python,
+            python_env: args.python_env,
+            require_signatures: args.require_signatures,
+            shallow: args.shallow,
+            skip: args.skip.into_iter().collect(),
+            skip_llvm: args.skip_llvm,
+            skip_openocd: args.skip_openocd,
+            skip_ulp: args.skip_ulp,
+            staging_dir: args.staging_dir,
+            standalone: args.standalone.is_some(),
+            target_host: args.target_host,
+            targets: args.targets.unwrap_or_else(|| Target::iter().collect()),
+            toolchain_version: args.toolchain_version,
+            verify_signatures: args.verify_signatures,
+            with_ccache: args.with_ccache,
+            with_dfu_util: args.with_dfu_util,
+            with_gdb: args.with_gdb,
+            with_openocd: args.with_openocd,
+            with_qemu: args.with_qemu,
+            with_rom_elfs: args.with_rom_elfs,
+            with_sccache: args.with_sccache,
+            with_udev_rules: args.with_udev_rules,
+            with_usb_drivers: args.with_usb_drivers,
+            yes: args.yes,
+        };
+        if let Some(profile) = args.profile {
+            profile.apply(&mut options);
+        }
+        options
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct ListOpts {
+    /// Lists the ESP-IDF and Xtensa Rust toolchain versions available for installation.
+    #[arg(short = 'r', long, env = "ESPUP_REMOTE")]
+    pub remote: bool,
+    /// Writes a CycloneDX software bill of materials of the installed components to the given
+    /// file, with their versions, origins and the SHA-256 hashes of their binaries.
+    #[arg(long, env = "ESPUP_SBOM", conflicts_with = "remote")]
+    pub sbom: Option<PathBuf>,
+    #[command(flatten)]
+    pub github: GithubArgs,
+    #[command(flatten)]
+    pub http: HttpArgs,
+    #[command(flatten)]
+    pub mirror: MirrorArgs,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct MigrateOpts {
+    /// Prints the directories that would be moved without moving them.
+    #[arg(long, env = "ESPUP_DRY_RUN")]
+    pub dry_run: bool,
+    /// Fails right away when another espup process is using the installation directory,
+    /// instead of waiting for it to finish.
+    #[arg(long, env = "ESPUP_NO_WAIT")]
+    pub no_wait: bool,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct MirrorOpts {
+    #[command(subcommand)]
+    pub subcommand: MirrorSubCommand,
+}
+
+#[derive(Debug, Parser)]
+pub enum MirrorSubCommand {
+    /// Ranks the mirrors by the speed of a probe download, for the next installations
+    Test(MirrorTestOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct MirrorTestOpts {
+    /// Prints the ranking without saving it.
+    #[arg(long, env = "ESPUP_DRY_RUN")]
+    pub dry_run: bool,
+    /// Mirrors to test: `github`, `espressif`, `jihulab` or the URL of a custom mirror
+    /// replicating the GitHub layout. Can be repeated or comma separated. Defaults to the
+    /// `mirrors` of the configuration file, or to `github`, `espressif` and `jihulab`.
+    #[arg(long, env = "ESPUP_MIRROR", value_parser = Mirror::parse, value_delimiter = ',')]
+    pub mirror: Vec<Mirror>,
+    #[command(flatten)]
+    pub http: HttpArgs,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct NewOpts {
+    /// Name of the project, and of its directory.
+    pub name: String,
+    /// Generates a `no_std` project, even when ESP-IDF is installed.
+    #[arg(long, env = "ESPUP_NO_STD")]
+    pub no_std: bool,
+    /// Chip of the project. Defaults to the first installed target.
+    #[arg(short = 't', long, env = "ESPUP_TARGET", value_parser = Target::from_str)]
+    pub target: Option<Target>,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct PruneOpts {
+    /// Prints the versions that would be removed without removing them.
+    #[arg(long, env = "ESPUP_DRY_RUN")]
+    pub dry_run: bool,
+    /// Number of the most recent unused versions of each tool and of ESP-IDF to keep.
+    #[arg(long, env = "ESPUP_KEEP", default_value_t = 0)]
+    pub keep: usize,
+    /// Fails right away when another espup process is using the installation directory,
+    /// instead of waiting for it to finish.
+    #[arg(long, env = "ESPUP_NO_WAIT")]
+    pub no_wait: bool,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct RunOpts {
+    /// Command to run, with its arguments, e.g. `espup run -- cargo build`.
+    #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    pub command: Vec<OsString>,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct SelfUpdateOpts {
+    /// Target triple of the host.
+    #[arg(short = 'd', long, env = "ESPUP_DEFAULT_HOST", required = false)]
+    pub default_host: Option<String>,
+    /// Directory of the temporary files and of the archives being extracted, instead of the
+    /// temporary directory of the system and the tools directory.
+    #[arg(long, env = "ESPUP_TMPDIR", value_name = "DIR")]
+    pub staging_dir: Option<PathBuf>,
+    #[command(flatten)]
+    pub download: DownloadArgs,
+    #[command(flatten)]
+    pub github: GithubArgs,
+    #[command(flatten)]
+    pub http: HttpArgs,
+    #[command(flatten)]
+    pub mirror: MirrorArgs,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Debug, Parser)]
+pub struct ShellOpts {
+    /// Shell to spawn. Defaults to `SHELL` on Unix, and to the running PowerShell or cmd on
+    /// Windows.
+    #[arg(long, env = "ESPUP_SHELL_PROGRAM", value_name = "PROGRAM")]
+    pub program: Option<OsString>,
+    #[command(flatten)]
+    pub log: LogArgs,
 }
 
 #[derive(Debug, Parser)]
 pub struct UpdateOpts {
     /// Target triple of the host.
-    #[arg(short = 'd', long, required = false)]
+    #[arg(short = 'd', long, env = "ESPUP_DEFAULT_HOST", required = false)]
     pub default_host: Option<String>,
-    /// Verbosity level of the logs.
-    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
-    pub log_level: String,
-    /// Xtensa Rust toolchain version.
-    #[arg(short = 'v', long, value_parser = XtensaRust::parse_version)]
-    pub toolchain_version: Option<String>,
+    /// Prints the actions that would be performed without performing them.
+    #[arg(long, env = "ESPUP_DRY_RUN")]
+    pub dry_run: bool,
+    /// ABI of the Windows host, `msvc` or `gnu`, selecting the matching Xtensa Rust build.
+    /// The linker of the ABI, from the Visual Studio Build Tools or MinGW-w64, has to be
+    /// installed. Defaults to the ABI of the host triple.
+    #[arg(long, env = "ESPUP_HOST_ABI", value_parser = HostAbi::from_str)]
+    pub host_abi: Option<HostAbi>,
+    /// Keeps the `com.apple.quarantine` attribute of the downloaded toolchain on macOS,
+    /// which is removed by default so that Gatekeeper does not block it.
+    #[arg(long, env = "ESPUP_KEEP_QUARANTINE")]
+    pub keep_quarantine: bool,
+    /// Fails right away when another espup process is using the installation directory,
+    /// instead of waiting for it to finish.
+    #[arg(long, env = "ESPUP_NO_WAIT")]
+    pub no_wait: bool,
+    /// Directory of the temporary files and of the archives being extracted, instead of the
+    /// temporary directory of the system and the tools directory.
+    #[arg(long, env = "ESPUP_TMPDIR", value_name = "DIR")]
+    pub staging_dir: Option<PathBuf>,
+    /// Xtensa Rust toolchain version: `latest`, `pre-release` for the latest release including
+    /// pre-releases, or an exact version, e.g. `1.64.0.0`.
+    #[arg(short = 'v', long, env = "ESPUP_TOOLCHAIN_VERSION", default_value = LATEST_VERSION, value_parser = XtensaRust::parse_version_request)]
+    pub toolchain_version: String,
+    #[command(flatten)]
+    pub download: DownloadArgs,
+    #[command(flatten)]
+    pub github: GithubArgs,
+    #[command(flatten)]
+    pub http: HttpArgs,
+    #[command(flatten)]
+    pub mirror: MirrorArgs,
+    #[command(flatten)]
+    pub log: LogArgs,
 }
 
 #[derive(Debug, Parser)]
 pub struct UninstallOpts {
-    /// Verbosity level of the logs.
-    #[arg(short = 'l', long, default_value = "info", value_parser = ["debug", "info", "warn", "error"])]
-    pub log_level: String,
+    /// Prints the actions that would be performed without performing them.
+    #[arg(long, env = "ESPUP_DRY_RUN")]
+    pub dry_run: bool,
+    /// Fails right away when another espup process is using the installation directory,
+    /// instead of waiting for it to finish.
+    #[arg(long, env = "ESPUP_NO_WAIT")]
+    pub no_wait: bool,
+    #[command(flatten)]
+    pub log: LogArgs,
 }
 
-/// Installs the Rust for ESP chips environment
-fn install(args: InstallOpts) -> Result<()> {
-    initialize_logger(&args.log_level);
-    check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
-    info!("{} Installing esp-rs", emoji::DISC);
-    let targets = args.targets;
-    let host_triple = get_host_triple(args.default_host)?;
-    let mut extra_crates = args.extra_crates;
-    let mut exports: Vec<String> = Vec::new();
-    let xtensa_rust = if targets.contains(&Target::ESP32)
-        || targets.contains(&Target::ESP32S2)
-        || targets.contains(&Target::ESP32S3)
-    {
-        let xtensa_rust: XtensaRust = if let Some(toolchain_version) = &args.toolchain_version {
-            XtensaRust::new(toolchain_version, &host_triple)
-        } else {
-            let latest_version = XtensaRust::get_latest_version()?;
-            XtensaRust::new(&latest_version, &host_triple)
-        };
-        Some(xtensa_rust)
-    } else {
-        None
-    };
-    let export_file = get_export_file(args.export_file)?;
-    let llvm = Llvm::new(args.llvm_version, args.profile_minimal, &host_triple);
-
-    debug!(
-        "{} Arguments:
-            - Host triple: {}
-            - Targets: {:?}
-            - ESP-IDF version: {:?}
-            - Export file: {:?}
-            - Extra crates: {:?}
-            - LLVM Toolchain: {:?}
-            - Nightly version: {:?}
-            - Rust Toolchain: {:?}
-            - Profile Minimal: {:?}
-            - Toolchain version: {:?}",
-        emoji::INFO,
-        host_triple,
-        targets,
-        &args.esp_idf_version,
-        &export_file,
-        &extra_crates,
-        llvm,
-        &args.nightly_version,
-        xtensa_rust,
-        args.profile_minimal,
-        args.toolchain_version,
-    );
+#[derive(Debug, Parser)]
+pub struct ExportOpts {
+    /// Prints, instead of archiving the environment, the commands and the environment
+    /// reproducing the installation: `dockerfile`, `ansible` or `nix`.
+    #[arg(long, env = "ESPUP_FORMAT", value_parser = ProvisionFormat::from_str, conflicts_with = "output")]
+    pub format: Option<ProvisionFormat>,
+    /// Fails right away when another espup process is using the installation directory,
+    /// instead of waiting for it to finish.
+    #[arg(long, env = "ESPUP_NO_WAIT")]
+    pub no_wait: bool,
+    /// Path of the archive: a `.tar.zst`, `.tar.xz` or `.tar.gz` file.
+    #[arg(
+        short = 'o',
+        long,
+        env = "ESPUP_OUTPUT",
+        default_value = "espup-env.tar.zst"
+    )]
+    pub output: PathBuf,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
 
-    #[cfg(windows)]
-    check_arguments(&targets, &args.esp_idf_version)?;
+#[derive(Debug, Parser)]
+pub struct VerifyOpts {
+    #[command(flatten)]
+    pub log: LogArgs,
+}
 
-    check_rust_installation(&args.nightly_version, &host_triple)?;
+#[derive(Debug, Parser)]
+pub struct WhichOpts {
+    /// Prints the locations as a JSON object, with `null` for the components that are not
+    /// installed.
+    #[arg(long, env = "ESPUP_JSON")]
+    pub json: bool,
+    /// Location to print: `tools`, `dist`, `export-file`, `idf-path`, `libclang` or
+    /// `xtensa-gcc`. Prints every location when not set.
+    #[arg(value_parser = Location::from_str)]
+    pub location: Option<Location>,
+}
 
-    if let Some(ref xtensa_rust) = xtensa_rust {
-        xtensa_rust.install()?;
-    }
+/// Removes cached archives.
+fn cache_clean(context: &mut Context, args: CacheCleanOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
 
-    exports.extend(llvm.install()?);
+    let older_than = if args.all {
+        None
+    } else {
+        args.older_than
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60))
+    };
+    Ok(cache::clean(context, older_than, args.dry_run)?)
+}
 
-    if targets.contains(&Target::ESP32C3) {
-        install_riscv_target(&args.nightly_version)?;
+/// Downloads the archives of an installation with the given options, or with the
+/// requirements of a project, into the cache and prints their cache key, e.g. to warm up the
+/// cache of a CI before installing.
+fn cache_fetch(context: &mut Context, mut args: InstallOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    context.http = Arc::new(Http::new(
+        HttpSettings {
+            cacert: args.http.cacert.clone(),
+            connect_timeout: args.http.connect_timeout,
+            github_token: args.github.github_token.clone(),
+            limit_rate: args.download.limit_rate,
+            low_speed_limit: args.download.low_speed_limit,
+            mirrors: args.mirror.mirror.clone(),
+            proxy: args.http.proxy.clone(),
+            read_timeout: args.http.read_timeout,
+            retries: args.github.retries,
+            retry_delay: args.github.retry_delay,
+            tls_backend: args.http.tls_backend,
+            urls: Config::load_urls(context.env.as_deref()),
+        },
+        context.env.as_deref(),
+    )?);
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    if let Some(project_dir) = args.project.clone() {
+        apply_project_requirements(&mut args, read_project(&project_dir)?);
     }
+    let options = InstallOptions {
+        fetch: true,
+        ..args.into()
+    };
+    install::install(context, options)
+}
 
-    if let Some(esp_idf_version) = &args.esp_idf_version {
-        let repo = EspIdfRepo::new(esp_idf_version, args.profile_minimal, &targets);
-        exports.extend(repo.install()?);
-        if let Some(ref mut extra_crates) = extra_crates {
-            extra_crates.insert(Crate::new("ldproxy"));
-        } else {
-            let mut crates = HashSet::new();
-            crates.insert(Crate::new("ldproxy"));
-            extra_crates = Some(crates);
-        };
-    } else {
-        exports.extend(install_gcc_targets(&targets, &host_triple)?);
-    }
+/// Lists the cached archives.
+fn cache_list(context: &mut Context, args: CacheListOpts, json: bool) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    cache::print_entries(context, json);
+    Ok(())
+}
 
-    if let Some(ref extra_crates) = &extra_crates {
-        install_extra_crates(extra_crates)?;
-    }
+/// Prints the total size of the cache.
+fn cache_size(context: &mut Context, args: CacheListOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    cache::print_size(context);
+    Ok(())
+}
 
-    if args.profile_minimal {
-        clear_dist_folder()?;
-    }
+/// Prints whether newer releases of the installed components are available, returning
+/// whether there are any. Quiet enough for cron jobs and shell prompt hooks.
+fn check_updates(context: &mut Context, args: CheckUpdatesOpts) -> Result<bool> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    context.http = Arc::new(Http::new(
+        HttpSettings {
+            cacert: args.http.cacert.clone(),
+            connect_timeout: args.http.connect_timeout,
+            github_token: args.github.github_token.clone(),
+            limit_rate: None,
+            low_speed_limit: None,
+            mirrors: args.mirror.mirror.clone(),
+            proxy: args.http.proxy.clone(),
+            read_timeout: args.http.read_timeout,
+            retries: args.github.retries,
+            retry_delay: args.github.retry_delay,
+            tls_backend: args.http.tls_backend,
+            urls: Config::load_urls(context.env.as_deref()),
+        },
+        context.env.as_deref(),
+    )?);
 
-    export_environment(&export_file, &exports)?;
-
-    info!("{} Saving configuration file", emoji::WRENCH);
-    let config = Config {
-        esp_idf_version: args.esp_idf_version,
-        export_file: Some(export_file),
-        extra_crates: extra_crates.as_ref().map(|extra_crates| {
-            extra_crates
-                .iter()
-                .map(|x| x.name.clone())
-                .collect::<HashSet<String>>()
-        }),
-        host_triple,
-        llvm_path: Some(llvm.path),
-        nightly_version: args.nightly_version,
-        targets,
-        xtensa_rust,
-    };
-    config.save()?;
+    let config = context.load_config()?;
+    let updates = get_updates(context, &config)?;
+    println!("{}", format_summary(&updates));
+    Ok(!updates.is_empty())
+}
 
-    info!("{} Installation successfully completed!", emoji::CHECK);
-    warn!(
-        "{} Please, source the export file, as state above, to properly setup the environment!",
-        emoji::WARN
+/// Prints the shell completions to stdout.
+fn completions(args: CompletionsOpts) -> Result<()> {
+    clap_complete::generate(
+        args.shell,
+        &mut Cli::command(),
+        "espup",
+        &mut std::io::stdout(),
     );
     Ok(())
 }
 
-/// Uninstalls the Rust for ESP chips environment
-fn uninstall(args: UninstallOpts) -> Result<()> {
-    initialize_logger(&args.log_level);
-    check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+/// Makes `esp` resolve to another installed Xtensa Rust toolchain.
+fn default_toolchain(context: &mut Context, args: DefaultOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    Ok(switch_default_toolchain(
+        context,
+        &args.toolchain,
+        args.no_wait,
+    )?)
+}
 
-    info!("{} Uninstalling esp-rs", emoji::DISC);
-    let mut config = Config::load()?;
+/// Checks the prerequisites and the health of the environment.
+fn doctor(context: &mut Context, args: DoctorOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    Ok(doctor::doctor(
+        context,
+        args.esp_idf_version.as_deref(),
+        args.install_prereqs,
+    )?)
+}
 
-    debug!(
-        "{} Arguments:
-            - Config: {:#?}",
-        emoji::INFO,
-        config
+/// Prints the exports of the installation for a shell, or writes them to the VS Code
+/// settings.
+fn env(context: &mut Context, args: EnvOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
     );
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    Ok(print_env(context, args.shell, args.vscode.as_deref())?)
+}
 
-    if let Some(xtensa_rust) = config.xtensa_rust {
-        info!("{} Deleting Xtensa Rust toolchain", emoji::WRENCH);
-        config.xtensa_rust = None;
-        config.save()?;
-        xtensa_rust.uninstall()?;
-    }
+/// Archives the installed environment.
+fn export(context: &mut Context, args: ExportOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    Ok(export::export(
+        context,
+        args.format,
+        &args.output,
+        args.no_wait,
+    )?)
+}
 
-    if let Some(llvm_path) = config.llvm_path {
-        info!("{} Deleting Xtensa LLVM", emoji::WRENCH);
-        config.llvm_path = None;
-        config.save()?;
-        remove_dir_all(&llvm_path)
-            .map_err(|_| Error::FailedToRemoveDirectory(llvm_path.display().to_string()))?;
-    }
+/// Removes an installed ESP-IDF version, along with the tool versions that no other
+/// ESP-IDF version requires.
+fn idf_remove(context: &mut Context, args: IdfRemoveOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    Ok(remove_esp_idf(
+        context,
+        &args.esp_idf_version,
+        args.dry_run,
+        args.no_wait,
+    )?)
+}
 
-    if let Some(esp_idf_version) = config.esp_idf_version {
-        info!("{} Deleting ESP-IDF {}", emoji::WRENCH, esp_idf_version);
-        config.esp_idf_version = None;
-        config.save()?;
-        let repo = EspIdfRemote {
-            git_ref: parse_esp_idf_git_ref(&esp_idf_version),
-            repo_url: Some(DEFAULT_GIT_REPOSITORY.to_string()),
-        };
+/// Fetches the submodules of an installed ESP-IDF version.
+fn idf_submodules(context: &mut Context, args: IdfSubmodulesOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    Ok(fetch_submodules(
+        context,
+        args.esp_idf_version,
+        args.no_wait,
+    )?)
+}
 
-        remove_dir_all(get_install_path(repo.clone()).parent().unwrap()).map_err(|_| {
-            Error::FailedToRemoveDirectory(
-                get_install_path(repo)
-                    .parent()
-                    .unwrap()
-                    .display()
-                    .to_string(),
-            )
-        })?;
-    } else {
-        info!("{} Deleting GCC targets", emoji::WRENCH);
-        for target in &config.targets.clone() {
-            config.targets.remove(target);
-            config.save()?;
-            let gcc_path = get_tool_path(&get_toolchain_name(target));
-            remove_dir_all(&gcc_path).map_err(|_| Error::FailedToRemoveDirectory(gcc_path))?;
+/// Regenerates the export file to use an installed ESP-IDF version.
+fn idf_use(context: &mut Context, args: IdfUseOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    Ok(use_esp_idf(context, &args.esp_idf_version)?)
+}
+
+/// Applies the requirements of a project to the options that were not given explicitly.
+fn apply_project_requirements(args: &mut InstallOpts, requirements: ProjectRequirements) {
+    if args.targets.is_none() {
+        args.targets = requirements.targets;
+    }
+    if args.esp_idf_version.is_none() && args.idf_path.is_none() {
+        args.esp_idf_version = requirements.esp_idf_version;
+    }
+    if args.toolchain_version == LATEST_VERSION {
+        if let Some(toolchain_version) = requirements.toolchain_version {
+            args.toolchain_version = toolchain_version;
         }
     }
-
-    if config.extra_crates.is_some() {
-        info!("{} Uninstalling extra crates", emoji::WRENCH);
-        let mut updated_extra_crates: HashSet<String> = config.extra_crates.clone().unwrap();
-        for extra_crate in &config.extra_crates.clone().unwrap() {
-            updated_extra_crates.remove(extra_crate);
-            config.extra_crates = Some(updated_extra_crates.clone());
-            config.save()?;
-            cmd!("cargo", "uninstall", extra_crate)
-                .run()
-                .into_diagnostic()?;
+    if args.name == DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME {
+        if let Some(toolchain_name) = requirements.toolchain_name {
+            args.name = toolchain_name;
         }
     }
-
-    if let Some(export_file) = config.export_file {
-        info!("{} Deleting export file", emoji::WRENCH);
-        config.export_file = None;
-        config.save()?;
-        remove_file(&export_file)
-            .map_err(|_| Error::FailedToRemoveFile(export_file.display().to_string()))?;
+    if args.nightly_version == "nightly" {
+        if let Some(nightly_version) = requirements.nightly_version {
+            args.nightly_version = nightly_version;
+        }
     }
+    if args.llvm_version == DEFAULT_LLVM_15_VERSION {
+        if let Some(llvm_version) = requirements.llvm_version {
+            args.llvm_version = llvm_version;
+        }
+    }
+    if let Some(gcc_version) = requirements.gcc_version {
+        // ESP-IDF provides its own GCC.
+        if args.esp_idf_version.is_some() || args.idf_path.is_some() {
+            warn!(
+                "{} The GCC version of the project is not used, ESP-IDF provides its own GCC",
+                emoji::WARN
+            );
+        } else if args.gcc_version.is_empty() {
+            args.gcc_version = gcc_version;
+        }
+    }
+}
 
-    clear_dist_folder()?;
-    info!("{} Deleting config file", emoji::WRENCH);
-    let conf_file = Config::get_config_path()?;
-    remove_file(&conf_file)
-        .map_err(|_| Error::FailedToRemoveFile(conf_file.display().to_string()))?;
-
-    info!("{} Uninstallation successfully completed!", emoji::CHECK);
-    Ok(())
+/// Installs the Rust for ESP chips environment.
+fn install(context: &mut Context, mut args: InstallOpts, matches: &ArgMatches) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    context.http = Arc::new(Http::new(
+        HttpSettings {
+            cacert: args.http.cacert.clone(),
+            connect_timeout: args.http.connect_timeout,
+            github_token: args.github.github_token.clone(),
+            limit_rate: args.download.limit_rate,
+            low_speed_limit: args.download.low_speed_limit,
+            mirrors: args.mirror.mirror.clone(),
+            proxy: args.http.proxy.clone(),
+            read_timeout: args.http.read_timeout,
+            retries: args.github.retries,
+            retry_delay: args.github.retry_delay,
+            tls_backend: args.http.tls_backend,
+            urls: Config::load_urls(context.env.as_deref()),
+        },
+        context.env.as_deref(),
+    )?);
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    if let Some(project_dir) = args.project.clone() {
+        info!(
+            "{} Installing the requirements of the project in '{}'",
+            emoji::INFO,
+            project_dir.display()
+        );
+        apply_project_requirements(&mut args, read_project(&project_dir)?);
+    }
+    let interactive = args.interactive;
+    // The wizard asks for the targets itself, and `--yes` is used in scripts.
+    let pick_targets = args.targets.is_none() && !interactive && !args.yes && is_interactive();
+    let mut options: InstallOptions = args.into();
+    if pick_targets {
+        options.targets = wizard::pick_targets(&Term::stderr())?;
+    }
+    if interactive {
+        if !is_interactive() {
+            return Err(Error::WizardNotInteractive.into());
+        }
+        run_install_wizard(context.env.as_deref(), &mut options, &Term::stderr())?;
+        let install_matches = matches.subcommand_matches("install").unwrap_or(matches);
+        let command_line = wizard::get_command_line(
+            &get_given_arguments(&Cli::command(), matches),
+            &get_given_arguments(&InstallOpts::command(), install_matches),
+            &options,
+        );
+        info!(
+            "{} Equivalent command line, for scripts: {}",
+            emoji::INFO,
+            command_line
+        );
+    }
+    install::install(context, options)
 }
 
-/// Updates Xtensa Rust toolchain.
-fn update(args: UpdateOpts) -> Result<()> {
-    initialize_logger(&args.log_level);
-    check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
-
-    info!("{} Updating ESP Rust environment", emoji::DISC);
-    let host_triple = get_host_triple(args.default_host)?;
-    let mut config = Config::load()?;
-    let xtensa_rust: XtensaRust = if let Some(toolchain_version) = args.toolchain_version {
-        XtensaRust::new(&toolchain_version, &host_triple)
-    } else {
-        let latest_version = XtensaRust::get_latest_version()?;
-        XtensaRust::new(&latest_version, &host_triple)
+/// Prints the components that an installation with the given options would add, change
+/// or remove in the current environment, and what it would download.
+fn diff(context: &mut Context, mut args: InstallOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    context.http = Arc::new(Http::new(
+        HttpSettings {
+            cacert: args.http.cacert.clone(),
+            connect_timeout: args.http.connect_timeout,
+            github_token: args.github.github_token.clone(),
+            limit_rate: args.download.limit_rate,
+            low_speed_limit: args.download.low_speed_limit,
+            mirrors: args.mirror.mirror.clone(),
+            proxy: args.http.proxy.clone(),
+            read_timeout: args.http.read_timeout,
+            retries: args.github.retries,
+            retry_delay: args.github.retry_delay,
+            tls_backend: args.http.tls_backend,
+            urls: Config::load_urls(context.env.as_deref()),
+        },
+        context.env.as_deref(),
+    )?);
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    if let Some(project_dir) = args.project.clone() {
+        apply_project_requirements(&mut args, read_project(&project_dir)?);
+    }
+    // The components of the current environment are looked up in its installation
+    // directory, and the targets default to the installed ones rather than to every target.
+    let config = context.load_config().ok();
+    if args.targets.is_none() {
+        args.targets = config.map(|config| config.targets);
+    }
+    let options = InstallOptions {
+        diff: true,
+        ..args.into()
     };
+    install::install(context, options)
+}
 
-    debug!(
-        "{} Arguments:
-            - Host triple: {}
-            - Toolchain version: {:#?}
-            - Config: {:#?}",
-        emoji::INFO,
-        host_triple,
-        xtensa_rust,
-        config
+/// Lists the installed components.
+fn list(context: &mut Context, args: ListOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
     );
+    context.http = Arc::new(Http::new(
+        HttpSettings {
+            cacert: args.http.cacert.clone(),
+            connect_timeout: args.http.connect_timeout,
+            github_token: args.github.github_token.clone(),
+            limit_rate: None,
+            low_speed_limit: None,
+            mirrors: args.mirror.mirror.clone(),
+            proxy: args.http.proxy.clone(),
+            read_timeout: args.http.read_timeout,
+            retries: args.github.retries,
+            retry_delay: args.github.retry_delay,
+            tls_backend: args.http.tls_backend,
+            urls: Config::load_urls(context.env.as_deref()),
+        },
+        context.env.as_deref(),
+    )?);
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
 
-    if let Some(config_xtensa_rust) = config.xtensa_rust {
-        if config_xtensa_rust.version == xtensa_rust.version {
-            info!(
-                "{} Toolchain '{}' is already up to date",
-                emoji::CHECK,
-                xtensa_rust.version
-            );
-            return Ok(());
-        }
-        config_xtensa_rust.uninstall()?;
-        xtensa_rust.install()?;
-        config.xtensa_rust = Some(xtensa_rust);
+    if args.remote {
+        return print_remote_versions(context);
     }
+    Ok(list::list(context, args.sbom.as_deref())?)
+}
+
+/// Moves the directories of a previous espup version to the current layout, leaving links
+/// in their place, and updates the configuration and export files.
+fn migrate(context: &mut Context, args: MigrateOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    Ok(migrate_installation(context, args.dry_run, args.no_wait)?)
+}
 
-    config.save()?;
+/// Ranks the mirrors by the speed of a probe download, and saves the ranking as the
+/// `mirrors` of the configuration file, the fastest one being used by the next downloads.
+fn mirror_test(context: &mut Context, args: MirrorTestOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    let mirrors = get_candidate_mirrors(context.env.as_deref(), args.mirror.clone())?;
+    context.http = Arc::new(Http::new(
+        HttpSettings {
+            cacert: args.http.cacert.clone(),
+            connect_timeout: args.http.connect_timeout,
+            github_token: None,
+            limit_rate: None,
+            low_speed_limit: None,
+            mirrors: mirrors.clone(),
+            proxy: args.http.proxy.clone(),
+            read_timeout: args.http.read_timeout,
+            retries: 0,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            tls_backend: args.http.tls_backend,
+            urls: Config::load_urls(context.env.as_deref()),
+        },
+        context.env.as_deref(),
+    )?);
+    Ok(mirror::rank(context, &mirrors, args.dry_run)?)
+}
 
-    info!("{} Update successfully completed!", emoji::CHECK);
-    Ok(())
+/// Generates a new project for the installation.
+fn new(context: &mut Context, args: NewOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    Ok(new_project(context, &args.name, args.target, args.no_std)?)
 }
 
-fn main() -> Result<()> {
-    match Cli::parse().subcommand {
-        SubCommand::Install(args) => install(*args),
-        SubCommand::Update(args) => update(args),
-        SubCommand::Uninstall(args) => uninstall(args),
-    }
+/// Removes the tool and ESP-IDF versions that are no longer referenced.
+fn prune(context: &mut Context, args: PruneOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    Ok(prune_unused(
+        context,
+        args.keep,
+        args.dry_run,
+        args.no_wait,
+    )?)
 }
 
-/// Deletes dist folder.
-fn clear_dist_folder() -> Result<(), Error> {
-    let dist_path = PathBuf::from(get_dist_path(""));
-    if dist_path.exists() {
-        info!("{} Clearing dist folder", emoji::WRENCH);
-        remove_dir_all(&dist_path)
-            .map_err(|_| Error::FailedToRemoveDirectory(dist_path.display().to_string()))?;
+/// Replaces espup with a command, or runs it and gets its exit code where processes cannot
+/// be replaced.
+fn exec(mut command: Command) -> Result<u8, Error> {
+    let program = command.get_program().to_string_lossy().to_string();
+    debug!("{} Running '{}'", emoji::DEBUG, program);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let error = command.exec();
+        Err(Error::FailedToRunCommand(program, error.to_string()))
+    }
+    #[cfg(windows)]
+    {
+        let status = command
+            .status()
+            .map_err(|e| Error::FailedToRunCommand(program, e.to_string()))?;
+        Ok(status.code().map_or(EXIT_FAILURE, |code| code as u8))
     }
-    Ok(())
 }
 
-/// Returns the absolute path to the export file, uses the DEFAULT_EXPORT_FILE if no arg is provided.
-fn get_export_file(export_file: Option<PathBuf>) -> Result<PathBuf, Error> {
-    if let Some(export_file) = export_file {
-        if export_file.is_absolute() {
-            Ok(export_file)
-        } else {
-            let current_dir = std::env::current_dir()?;
-            Ok(current_dir.join(export_file))
-        }
-    } else {
-        let home_dir = home_dir().unwrap();
-        Ok(home_dir.join(DEFAULT_EXPORT_FILE))
+/// Runs a command with the environment of the installation.
+fn run(context: &mut Context, args: RunOpts) -> Result<u8> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+
+    let environment = get_installation_environment(context)?;
+    let mut command = get_command(&args.command[0], &environment)?;
+    command.args(&args.command[1..]);
+    Ok(exec(command)?)
+}
+
+/// Updates espup to the latest release.
+fn self_update_espup(context: &mut Context, args: SelfUpdateOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    context.http = Arc::new(Http::new(
+        HttpSettings {
+            cacert: args.http.cacert.clone(),
+            connect_timeout: args.http.connect_timeout,
+            github_token: args.github.github_token.clone(),
+            limit_rate: args.download.limit_rate,
+            low_speed_limit: args.download.low_speed_limit,
+            mirrors: args.mirror.mirror.clone(),
+            proxy: args.http.proxy.clone(),
+            read_timeout: args.http.read_timeout,
+            retries: args.github.retries,
+            retry_delay: args.github.retry_delay,
+            tls_backend: args.http.tls_backend,
+            urls: Config::load_urls(context.env.as_deref()),
+        },
+        context.env.as_deref(),
+    )?);
+    if let Some(staging_dir) = &args.staging_dir {
+        context.staging_dir = Some(create_staging_dir(staging_dir)?);
     }
+    update_espup(context, args.default_host)
 }
 
-/// Creates the export file with the necessary environment variables.
-fn export_environment(export_file: &PathBuf, exports: &[String]) -> Result<(), Error> {
-    info!("{} Creating export file", emoji::WRENCH);
-    let mut file = File::create(export_file)?;
-    for e in exports.iter() {
-        file.write_all(e.as_bytes())?;
-        file.write_all(b"\n")?;
+/// Spawns a shell with the environment of the installation.
+fn shell(context: &mut Context, args: ShellOpts) -> Result<u8> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+
+    let environment = get_installation_environment(context)?;
+    if std::env::var_os(ACTIVE_VARIABLE).is_some() {
+        warn!(
+            "{} This shell already has the espup environment, exit it to leave the new one",
+            emoji::WARN
+        );
     }
-    #[cfg(windows)]
-    warn!(
-        "{} PLEASE set up the environment variables running: '{}'",
+    let program = args.program.unwrap_or_else(get_default_shell);
+    info!(
+        "{} Spawning '{}' with the espup environment, exit it to leave the environment",
         emoji::INFO,
-        export_file.display()
+        program.to_string_lossy()
     );
-    #[cfg(unix)]
-    warn!(
-        "{} PLEASE set up the environment variables running: '. {}'",
-        emoji::INFO,
-        export_file.display()
+    Ok(exec(get_command(&program, &environment)?)?)
+}
+
+/// Uninstalls the Rust for ESP chips environment
+fn uninstall(context: &mut Context, args: UninstallOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
+    );
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+
+    uninstall::uninstall(context, args.dry_run, args.no_wait)
+}
+
+/// Updates Xtensa Rust toolchain.
+fn update(context: &mut Context, args: UpdateOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
     );
-    warn!(
-        "{} This step must be done every time you open a new terminal.",
-        emoji::WARN
+    context.http = Arc::new(Http::new(
+        HttpSettings {
+            cacert: args.http.cacert.clone(),
+            connect_timeout: args.http.connect_timeout,
+            github_token: args.github.github_token.clone(),
+            limit_rate: args.download.limit_rate,
+            low_speed_limit: args.download.low_speed_limit,
+            mirrors: args.mirror.mirror.clone(),
+            proxy: args.http.proxy.clone(),
+            read_timeout: args.http.read_timeout,
+            retries: args.github.retries,
+            retry_delay: args.github.retry_delay,
+            tls_backend: args.http.tls_backend,
+            urls: Config::load_urls(context.env.as_deref()),
+        },
+        context.env.as_deref(),
+    )?);
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let options = UpdateOptions {
+        cache_archives: args.download.cache_archives,
+        default_host: args.default_host,
+        dry_run: args.dry_run,
+        host_abi: args.host_abi,
+        keep_quarantine: args.keep_quarantine,
+        no_wait: args.no_wait,
+        staging_dir: args.staging_dir,
+        toolchain_version: args.toolchain_version,
+    };
+    install::update(context, options)
+}
+
+/// Verifies the integrity of the installed components.
+fn verify(context: &mut Context, args: VerifyOpts) -> Result<()> {
+    initialize_logger(
+        &args.log.log_level,
+        args.log.log_file.as_deref(),
+        &context.progress,
     );
-    Ok(())
+    check_for_update(context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    Ok(verify::verify(context)?)
 }
 
-#[cfg(windows)]
-/// For Windows, we need to check that we are installing all the targets if we are installing esp-idf.
-pub fn check_arguments(
-    targets: &HashSet<Target>,
-    espidf_version: &Option<String>,
-) -> Result<(), Error> {
-    if espidf_version.is_some()
-        && (!targets.contains(&Target::ESP32)
-            || !targets.contains(&Target::ESP32C3)
-            || !targets.contains(&Target::ESP32S2)
-            || !targets.contains(&Target::ESP32S3))
-    {
-        return Err(Error::WrongWindowsArguments);
+/// Prints the details of an installed component. It skips the update check, so that the JSON
+/// output can be parsed.
+fn info(context: &mut Context, args: InfoOpts) -> Result<()> {
+    Ok(print_info(
+        context,
+        &args.component,
+        args.component_version.as_deref(),
+        args.json,
+    )?)
+}
+
+/// Prints the locations of the installation. It skips the update check, as it is meant to be
+/// run by build scripts.
+fn which(context: &mut Context, args: WhichOpts) -> Result<()> {
+    Ok(print_locations(context, args.location, args.json)?)
+}
+
+/// Whether the log level of the subcommand is its default one, neither passed with
+/// `--log-level` nor set with `ESPUP_LOG_LEVEL`.
+fn is_default_log_level(matches: &ArgMatches) -> bool {
+    match matches.subcommand() {
+        Some((_, subcommand_matches)) => is_default_log_level(subcommand_matches),
+        None => {
+            matches.ids().any(|id| id == "log_level")
+                && matches.value_source("log_level") == Some(ValueSource::DefaultValue)
+        }
     }
+}
 
-    Ok(())
+/// Gets the options given to a command, on the command line or in the environment, with
+/// their values as given, to print an equivalent command line. `--interactive` is left
+/// out, as the command line is meant for scripts.
+fn get_given_arguments(command: &clap::Command, matches: &ArgMatches) -> Vec<wizard::Argument> {
+    command
+        .get_arguments()
+        .filter_map(|arg| {
+            let id = arg.get_id().as_str();
+            let long = arg.get_long().filter(|long| *long != "interactive")?;
+            match matches.value_source(id)? {
+                ValueSource::CommandLine | ValueSource::EnvVariable => {}
+                _ => return None,
+            }
+            let values = if arg.get_action().takes_values() {
+                matches
+                    .get_raw(id)?
+                    .map(|value| value.to_string_lossy().into_owned())
+                    .collect()
+            } else if matches!(arg.get_action(), ArgAction::SetTrue) && !matches.get_flag(id) {
+                return None;
+            } else {
+                Vec::new()
+            };
+            Some((format!("--{long}"), values))
+        })
+        .collect()
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::{get_export_file, DEFAULT_EXPORT_FILE};
-    use dirs::home_dir;
-    use std::{env::current_dir, path::PathBuf};
-
-    #[test]
-    #[allow(unused_variables)]
-    fn test_get_export_file() {
-        // No arg provided
-        let home_dir = home_dir().unwrap();
-        let export_file = home_dir.join(DEFAULT_EXPORT_FILE);
-        assert!(matches!(get_export_file(None), Ok(export_file)));
-        // Relative path
-        let current_dir = current_dir().unwrap();
-        let export_file = current_dir.join("export.sh");
-        assert!(matches!(
-            get_export_file(Some(PathBuf::from("export.sh"))),
-            Ok(export_file)
-        ));
-        // Absolute path
-        let export_file = PathBuf::from("/home/user/export.sh");
-        assert!(matches!(
-            get_export_file(Some(PathBuf::from("/home/user/export.sh"))),
-            Ok(export_file)
-        ));
+fn main() -> ExitCode {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let mut context = Context::default();
+    if let Some(tools_path) = &cli.tools_path {
+        context.tools_path = tools_path.clone();
+        context.tools_path_overridden = true;
+    }
+    if let Some(env) = &cli.env {
+        select_env(&mut context, env);
+        // The Xtensa Rust toolchain of a named environment is its own, unless named.
+        if let SubCommand::Install(args) | SubCommand::Diff(args) = &mut cli.subcommand {
+            let is_default_name = matches.subcommand().map_or(false, |(_, matches)| {
+                matches.value_source("name") == Some(ValueSource::DefaultValue)
+            });
+            if is_default_name {
+                args.name = get_env_toolchain_name(env);
+            }
+        }
+    }
+    // The defaults of the configuration file apply when no option is given.
+    let output = Config::load_output(context.env.as_deref());
+    if let Some(log_level) = output.log_level.as_deref() {
+        if is_default_log_level(&matches) {
+            set_log_level(log_level);
+        }
+    }
+    if let Some(color) = cli.color.or(output.color) {
+        set_color(color);
+    }
+    if cli.no_emoji || output.emoji == Some(false) {
+        emoji::disable();
+    }
+    if cli.quiet {
+        set_quiet();
+    }
+    if cli.non_interactive {
+        set_non_interactive();
+    }
+    if cli.message_format == "json" {
+        context.progress.add_handler(JsonLinesHandler);
+    } else if !cli.quiet {
+        output::enable(&mut context.progress);
+    }
+    // The commands running a program or a shell leave Ctrl-C to it.
+    if !matches!(cli.subcommand, SubCommand::Run(_) | SubCommand::Shell(_)) {
+        interrupt::set_handler();
+    }
+    let result = match cli.subcommand {
+        SubCommand::Cache(args) => match args.subcommand {
+            CacheSubCommand::Clean(args) => cache_clean(&mut context, args),
+            CacheSubCommand::Fetch(args) => cache_fetch(&mut context, *args),
+            CacheSubCommand::List(args) => {
+                cache_list(&mut context, args, cli.message_format == "json")
+            }
+            CacheSubCommand::Size(args) => cache_size(&mut context, args),
+        },
+        SubCommand::CheckUpdates(args) => match check_updates(&mut context, args) {
+            Ok(true) => return ExitCode::from(EXIT_UPDATES_AVAILABLE),
+            result => result.map(|_| ()),
+        },
+        SubCommand::Completions(args) => completions(args),
+        SubCommand::Default(args) => default_toolchain(&mut context, args),
+        SubCommand::Doctor(args) => doctor(&mut context, args),
+        SubCommand::Env(args) => env(&mut context, args),
+        SubCommand::Export(args) => export(&mut context, args),
+        SubCommand::Idf(args) => match args.subcommand {
+            IdfSubCommand::Remove(args) => idf_remove(&mut context, args),
+            IdfSubCommand::Submodules(args) => idf_submodules(&mut context, args),
+            IdfSubCommand::Use(args) => idf_use(&mut context, args),
+        },
+        SubCommand::Diff(args) => diff(&mut context, *args),
+        SubCommand::Info(args) => info(&mut context, args),
+        SubCommand::Install(args) => install(&mut context, *args, &matches),
+        SubCommand::List(args) => list(&mut context, args),
+        SubCommand::Migrate(args) => migrate(&mut context, args),
+        SubCommand::Mirror(args) => match args.subcommand {
+            MirrorSubCommand::Test(args) => mirror_test(&mut context, args),
+        },
+        SubCommand::New(args) => new(&mut context, args),
+        SubCommand::Prune(args) => prune(&mut context, args),
+        SubCommand::Run(args) => match run(&mut context, args) {
+            Ok(code) => return ExitCode::from(code),
+            Err(e) => Err(e),
+        },
+        SubCommand::SelfUpdate(args) => self_update_espup(&mut context, args),
+        SubCommand::Shell(args) => match shell(&mut context, args) {
+            Ok(code) => return ExitCode::from(code),
+            Err(e) => Err(e),
+        },
+        SubCommand::Update(args) => update(&mut context, args),
+        SubCommand::Uninstall(args) => uninstall(&mut context, args),
+        SubCommand::Verify(args) => verify(&mut context, args),
+        SubCommand::Which(args) => which(&mut context, args),
+    };
+    match result {
+        Ok(()) => {
+            print_notice(&context, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            let exit_code = if interrupt::is_interrupted() {
+                EXIT_INTERRUPTED
+            } else {
+                e.downcast_ref().map_or(EXIT_FAILURE, Error::exit_code)
+            };
+            context
+                .progress
+                .emit(Event::failed(env!("CARGO_PKG_NAME"), &*e, exit_code));
+            eprintln!("Error: {e:?}");
+            ExitCode::from(exit_code)
+        }
     }
 }
@@ -0,0 +1,433 @@
+//! Migration of the installations of previous espup versions to the current layout, with
+//! `espup migrate`, instead of installing everything again: the tools directory in
+//! HOME/.espressif moves to `$XDG_DATA_HOME/espup` on Linux, and the checkouts of the
+//! default ESP-IDF repository move from `esp-idf-<hash>/<version>` to `esp-idf/<version>`.
+//! A link to the new location is left in place of each moved directory, so that the Python
+//! environments and the builds referencing the previous paths keep working.
+
+use crate::{
+    config::Config,
+    context::Context,
+    emoji,
+    error::Error,
+    header::{format_export_file, get_exported_components, strip_header},
+    install::get_export_file,
+    lock::InstallLock,
+    prune::PATH_TERMINATORS,
+    toolchain::espidf::{
+        get_hashed_checkouts_dir_name, get_legacy_tools_path, get_xdg_tools_path, link_current,
+        EspIdfLayout, CURRENT_LINK_NAME, DEFAULT_GIT_REPOSITORY, ESPUP_HOME, ESP_IDF_DIR_NAME,
+    },
+};
+use log::{debug, info, warn};
+use std::{
+    env,
+    fs::{create_dir_all, read_dir, read_link, read_to_string, rename, write},
+    path::{Path, PathBuf},
+};
+
+/// Move of a directory of a previous layout to the current one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    /// Directory of the previous layout.
+    pub from: PathBuf,
+    /// Directory of the current layout.
+    pub to: PathBuf,
+}
+
+impl Migration {
+    /// Gets the path of `path` once migrated, if it is in the moved directory.
+    pub fn relocate(&self, path: &Path) -> Option<PathBuf> {
+        let relative = path.strip_prefix(&self.from).ok()?;
+        if relative.as_os_str().is_empty() {
+            Some(self.to.clone())
+        } else {
+            Some(self.to.join(relative))
+        }
+    }
+
+    /// Replaces the mentions of the moved directory in the contents of a file, e.g. the
+    /// export file.
+    fn relocate_text(&self, text: &str) -> String {
+        let from = self.from.display().to_string();
+        let to = self.to.display().to_string();
+        let mut relocated = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(index) = rest.find(&from) {
+            let end = index + from.len();
+            let is_path = rest[end..]
+                .chars()
+                .next()
+                .map_or(true, |c| PATH_TERMINATORS.contains(&c));
+            relocated.push_str(&rest[..index]);
+            relocated.push_str(if is_path { &to } else { &from });
+            rest = &rest[end..];
+        }
+        relocated.push_str(rest);
+        relocated
+    }
+}
+
+/// Whether espup installed into the tools directory `tools_path`: the configuration or the
+/// export file reference it. HOME/.espressif is also the default tools directory of ESP-IDF
+/// itself, e.g. of `install.sh`, which has to be left in place.
+fn is_installed_by_espup(
+    tools_path: &Path,
+    config: Option<&Config>,
+    export_file_contents: Option<&str>,
+) -> bool {
+    let in_tools_path = |path: &PathBuf| path.starts_with(tools_path);
+    let configured = config.map_or(false, |config| {
+        config.install_dir.iter().any(in_tools_path)
+            || config.llvm_path.iter().any(in_tools_path)
+            || config.tool_paths.iter().flatten().any(in_tools_path)
+            || config
+                .receipt
+                .iter()
+                .flat_map(|receipt| receipt.keys())
+                .any(in_tools_path)
+            || config
+                .xtensa_rust
+                .iter()
+                .any(|xtensa_rust| in_tools_path(&xtensa_rust.toolchain_destination))
+    });
+    // Installations of previous versions may predate the configuration file.
+    let exported = export_file_contents.map_or(false, |contents| {
+        Migration {
+            from: tools_path.to_path_buf(),
+            to: PathBuf::new(),
+        }
+        .relocate_text(contents)
+            != contents
+    });
+    configured || exported
+}
+
+/// Gets the migration of the tools directory of previous espup versions on Linux,
+/// HOME/.espressif, to `$XDG_DATA_HOME/espup`, unless another tools directory is set or it
+/// was not installed by espup.
+fn get_tools_migration(context: &Context) -> Option<Migration> {
+    if !cfg!(target_os = "linux")
+        || context.tools_path_overridden
+        || env::var_os(ESPUP_HOME).is_some()
+    {
+        return None;
+    }
+    let from = PathBuf::from(get_legacy_tools_path());
+    let to = get_xdg_tools_path()?;
+    // A link means that the directory was already migrated.
+    let is_dir = from
+        .symlink_metadata()
+        .map_or(false, |metadata| metadata.is_dir());
+    if !is_dir || context.tools_path != from || to.exists() {
+        return None;
+    }
+    let config = Config::load(context.env.as_deref()).ok();
+    let export_file_contents = get_export_file(
+        context.env.as_deref(),
+        config
+            .as_ref()
+            .and_then(|config| config.export_file.clone()),
+    )
+    .ok()
+    .and_then(|export_file| read_to_string(export_file).ok());
+    if !is_installed_by_espup(&from, config.as_ref(), export_file_contents.as_deref()) {
+        debug!(
+            "{} '{}' is not referenced by the espup installation, leaving it in place",
+            emoji::DEBUG,
+            from.display()
+        );
+        return None;
+    }
+    Some(Migration { from, to })
+}
+
+/// Gets the migrations of the checkouts of the default ESP-IDF repository in `tools_path`,
+/// from the hashed naming scheme to the readable one, see [`EspIdfLayout`].
+fn get_checkout_migrations_in(tools_path: &Path) -> Vec<Migration> {
+    let hashed_dir = tools_path.join(get_hashed_checkouts_dir_name(Some(DEFAULT_GIT_REPOSITORY)));
+    let readable_dir = tools_path.join(ESP_IDF_DIR_NAME);
+    let mut migrations: Vec<Migration> = read_dir(hashed_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        // The links left by previous migrations are not directories.
+        .filter(|entry| {
+            entry
+                .file_type()
+                .map_or(false, |file_type| file_type.is_dir())
+        })
+        .filter(|entry| entry.file_name() != CURRENT_LINK_NAME)
+        .map(|entry| Migration {
+            from: entry.path(),
+            to: readable_dir.join(entry.file_name()),
+        })
+        .filter(|migration| !migration.to.exists())
+        .collect();
+    migrations.sort_by(|a, b| a.from.cmp(&b.from));
+    migrations
+}
+
+/// Gets the directories of previous layouts to migrate, in order. The checkouts are kept
+/// hashed when the hashed naming scheme is used.
+pub fn get_migrations(context: &Context, layout: EspIdfLayout) -> Vec<Migration> {
+    let mut migrations: Vec<Migration> = get_tools_migration(context).into_iter().collect();
+    if layout == EspIdfLayout::Readable {
+        migrations.extend(get_checkout_migrations_in(&context.tools_path));
+    }
+    migrations
+}
+
+/// Warns about the directories of previous layouts, which are used as they are until
+/// migrated.
+pub fn warn_legacy_layout(context: &Context, layout: EspIdfLayout) {
+    let migrations = get_migrations(context, layout);
+    if let Some(migration) = migrations.first() {
+        warn!(
+            "{} Found {} directories of a previous espup version, e.g. '{}'. Run 'espup migrate' to move them to the current layout",
+            emoji::WARN,
+            migrations.len(),
+            migration.from.display()
+        );
+    }
+}
+
+/// Moves a directory of a previous layout, leaving a link to its new location in its place.
+fn move_dir(migration: &Migration) -> Result<(), Error> {
+    if let Some(parent) = migration.to.parent() {
+        create_dir_all(parent)
+            .map_err(|_| Error::FailedToCreateDirectory(parent.display().to_string()))?;
+    }
+    rename(&migration.from, &migration.to).map_err(|_| {
+        Error::FailedToMove(
+            migration.from.display().to_string(),
+            migration.to.display().to_string(),
+        )
+    })?;
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink(&migration.to, &migration.from);
+    #[cfg(windows)]
+    let result = std::os::windows::fs::symlink_dir(&migration.to, &migration.from);
+    if let Err(e) = result {
+        warn!(
+            "{} Failed to link '{}' to '{}': {}",
+            emoji::WARN,
+            migration.from.display(),
+            migration.to.display(),
+            e
+        );
+    }
+    Ok(())
+}
+
+/// Updates the paths of the configuration in the moved directory.
+fn relocate_config(config: &mut Config, migration: &Migration) {
+    let relocate = |path: &mut PathBuf| {
+        if let Some(relocated) = migration.relocate(path) {
+            *path = relocated;
+        }
+    };
+    config.install_dir.iter_mut().for_each(relocate);
+    config.llvm_path.iter_mut().for_each(relocate);
+    if let Some(tool_paths) = &mut config.tool_paths {
+        *tool_paths = tool_paths
+            .iter()
+            .map(|path| migration.relocate(path).unwrap_or_else(|| path.clone()))
+            .collect();
+    }
+    if let Some(receipt) = &mut config.receipt {
+        *receipt = receipt
+            .iter()
+            .map(|(path, hash)| {
+                let path = migration.relocate(path).unwrap_or_else(|| path.clone());
+                (path, hash.clone())
+            })
+            .collect();
+    }
+    if let Some(xtensa_rust) = &mut config.xtensa_rust {
+        relocate(&mut xtensa_rust.toolchain_destination);
+    }
+}
+
+/// Moves the directories of previous layouts, in order, updating the tools directory of
+/// `context`, the paths of the configuration, which is saved by the caller, and the export
+/// file.
+pub fn migrate(
+    context: &mut Context,
+    migrations: &[Migration],
+    mut config: Option<&mut Config>,
+) -> Result<(), Error> {
+    let export_file = get_export_file(
+        context.env.as_deref(),
+        config
+            .as_ref()
+            .and_then(|config| config.export_file.clone()),
+    )?;
+    for (index, migration) in migrations.iter().enumerate() {
+        // The previous migrations may have moved the directory, e.g. with the tools one.
+        let migration =
+            migrations[..index]
+                .iter()
+                .fold(migration.clone(), |migration, previous| Migration {
+                    from: previous.relocate(&migration.from).unwrap_or(migration.from),
+                    to: previous.relocate(&migration.to).unwrap_or(migration.to),
+                });
+        info!(
+            "{} Moving '{}' to '{}'",
+            emoji::WRENCH,
+            migration.from.display(),
+            migration.to.display()
+        );
+        let is_current = migration
+            .from
+            .parent()
+            .zip(migration.from.file_name())
+            .map_or(false, |(parent, name)| {
+                read_link(parent.join(CURRENT_LINK_NAME)).map_or(false, |target| target == name)
+            });
+        move_dir(&migration)?;
+        if is_current {
+            link_current(&migration.to);
+        }
+        if let Some(tools_path) = migration.relocate(&context.tools_path) {
+            context.tools_path = tools_path;
+        }
+        if let Some(config) = config.as_deref_mut() {
+            relocate_config(config, &migration);
+        }
+        if let Ok(contents) = read_to_string(&export_file) {
+            // The header pins the components to their paths, and hashes the exports.
+            let exports = strip_header(&migration.relocate_text(&contents));
+            let components = get_exported_components(context, &exports);
+            write(&export_file, format_export_file(&exports, &components))
+                .map_err(|_| Error::FailedToWrite(export_file.display().to_string()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Moves the directories of previous espup versions to the current layout, and updates the
+/// configuration and export files. With `dry_run`, only prints the moves.
+pub fn migrate_installation(
+    context: &mut Context,
+    dry_run: bool,
+    no_wait: bool,
+) -> Result<(), Error> {
+    info!(
+        "{} Looking for directories of previous versions",
+        emoji::DISC
+    );
+    // Installations of previous versions may predate the configuration file.
+    let mut config = context.load_config().ok();
+    let layout = config
+        .as_ref()
+        .and_then(|config| config.esp_idf_layout)
+        .unwrap_or_default();
+    let _lock = InstallLock::acquire(context, no_wait)?;
+    let migrations = get_migrations(context, layout);
+    if migrations.is_empty() {
+        info!(
+            "{} The installation already uses the current layout",
+            emoji::CHECK
+        );
+        return Ok(());
+    }
+    for migration in &migrations {
+        println!("{} -> {}", migration.from.display(), migration.to.display());
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    migrate(context, &migrations, config.as_mut())?;
+    if let Some(config) = &config {
+        info!("{} Saving configuration file", emoji::WRENCH);
+        config.save(context.env.as_deref())?;
+    }
+    info!(
+        "{} Migrated {} directories, source the export file again to use them",
+        emoji::CHECK,
+        migrations.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        migrate::{get_checkout_migrations_in, is_installed_by_espup, Migration},
+        toolchain::espidf::{get_hashed_checkouts_dir_name, DEFAULT_GIT_REPOSITORY},
+    };
+    use std::{
+        fs::create_dir_all,
+        path::{Path, PathBuf},
+    };
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_relocate() {
+        let migration = Migration {
+            from: PathBuf::from("/home/esp/.espressif"),
+            to: PathBuf::from("/home/esp/.local/share/espup"),
+        };
+        assert_eq!(
+            migration.relocate(Path::new("/home/esp/.espressif/tools/esp-clang")),
+            Some(PathBuf::from(
+                "/home/esp/.local/share/espup/tools/esp-clang"
+            ))
+        );
+        assert_eq!(
+            migration.relocate(Path::new("/home/esp/.espressif")),
+            Some(PathBuf::from("/home/esp/.local/share/espup"))
+        );
+        assert_eq!(migration.relocate(Path::new("/home/esp/.espressif2")), None);
+        assert_eq!(
+            migration.relocate_text(
+                "export LIBCLANG_PATH=\"/home/esp/.espressif/tools/esp-clang/lib\"\nexport FOO=/home/esp/.espressif2\n"
+            ),
+            "export LIBCLANG_PATH=\"/home/esp/.local/share/espup/tools/esp-clang/lib\"\nexport FOO=/home/esp/.espressif2\n"
+        );
+    }
+
+    #[test]
+    fn test_is_installed_by_espup() {
+        let tools_path = Path::new("/home/esp/.espressif");
+        // The tools directory of an installation of ESP-IDF with its own installer.
+        assert!(!is_installed_by_espup(tools_path, None, None));
+        assert!(!is_installed_by_espup(
+            tools_path,
+            Some(&Config::default()),
+            Some("export PATH=\"/home/esp/.espressif2/bin:$PATH\"\n")
+        ));
+        let config = Config {
+            llvm_path: Some(tools_path.join("tools").join("xtensa-esp32-elf-clang")),
+            ..Default::default()
+        };
+        assert!(is_installed_by_espup(tools_path, Some(&config), None));
+        assert!(is_installed_by_espup(
+            tools_path,
+            None,
+            Some("export LIBCLANG_PATH=\"/home/esp/.espressif/tools/esp-clang/lib\"\n")
+        ));
+    }
+
+    #[test]
+    fn test_get_checkout_migrations() {
+        let temp_dir = TempDir::new().unwrap();
+        let hashed_dir = temp_dir
+            .path()
+            .join(get_hashed_checkouts_dir_name(Some(DEFAULT_GIT_REPOSITORY)));
+        create_dir_all(hashed_dir.join("v4.4")).unwrap();
+        create_dir_all(hashed_dir.join("v5.1")).unwrap();
+        // Already installed with the readable naming scheme.
+        create_dir_all(temp_dir.path().join("esp-idf").join("v5.1")).unwrap();
+        assert_eq!(
+            get_checkout_migrations_in(temp_dir.path()),
+            vec![Migration {
+                from: hashed_dir.join("v4.4"),
+                to: temp_dir.path().join("esp-idf").join("v4.4"),
+            }]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Benchmark of the download mirrors, run by `espup mirror test` to rank them in the
+//! configuration file, so that the installations download from the fastest one.
+
+use crate::{
+    config::Config,
+    context::Context,
+    emoji,
+    error::Error,
+    http::{Http, Mirror},
+    list::format_size,
+};
+use log::{debug, info, warn};
+use std::{
+    cmp::Reverse,
+    io::{copy, sink, Read},
+    time::{Duration, Instant},
+};
+
+/// Release asset downloaded from the mirrors: the ROM ELFs, published by Espressif and thus
+/// replicated by each mirror.
+const PROBE_URL: &str =
+    "https://github.com/espressif/esp-rom-elfs/releases/download/20230320/esp-rom-elfs-20230320.tar.gz";
+/// Bytes of the probe downloaded to measure the throughput.
+const PROBE_SIZE: u64 = 1024 * 1024;
+
+/// Speed of a mirror.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Benchmark {
+    /// Mirror the probe was downloaded from.
+    pub mirror: Mirror,
+    /// Time until the response headers were received.
+    pub latency: Duration,
+    /// Transfer rate of the response body, in bytes per second.
+    pub throughput: u64,
+}
+
+impl Benchmark {
+    /// Formats the benchmark as a line of the ranking.
+    pub fn format(&self) -> String {
+        format!(
+            "{}: {} ms, {}/s",
+            self.mirror,
+            self.latency.as_millis(),
+            format_size(self.throughput)
+        )
+    }
+}
+
+/// Downloads the beginning of the probe artifact from a mirror, measuring its latency and
+/// throughput.
+pub fn probe(http: &Http, mirror: &Mirror) -> Result<Benchmark, Error> {
+    let url = mirror.rewrite_url(PROBE_URL);
+    debug!(
+        "{} Probing mirror '{}' with '{}'",
+        emoji::DEBUG,
+        mirror,
+        url
+    );
+    let start = Instant::now();
+    let response = http.client()?.get(&url).send()?.error_for_status()?;
+    let latency = start.elapsed();
+    let start = Instant::now();
+    let bytes = copy(&mut response.take(PROBE_SIZE), &mut sink())?;
+    let elapsed = start.elapsed().as_secs_f64().max(0.001);
+    Ok(Benchmark {
+        mirror: mirror.clone(),
+        latency,
+        throughput: (bytes as f64 / elapsed) as u64,
+    })
+}
+
+/// Ranks the mirrors from the fastest to the slowest, then the unreachable ones, which are
+/// kept as a last resort. Mirrors with the same throughput are ranked by latency.
+pub fn rank_mirrors(benchmarks: &[Benchmark], unreachable: &[Mirror]) -> Vec<Mirror> {
+    let mut benchmarks = benchmarks.to_vec();
+    benchmarks.sort_by_key(|benchmark| (Reverse(benchmark.throughput), benchmark.latency));
+    benchmarks
+        .into_iter()
+        .map(|benchmark| benchmark.mirror)
+        .chain(unreachable.iter().cloned())
+        .collect()
+}
+
+/// Probes the mirrors and prints their speed. Returns their ranking.
+pub fn test_mirrors(http: &Http, mirrors: &[Mirror]) -> Result<Vec<Mirror>, Error> {
+    let mut benchmarks: Vec<Benchmark> = Vec::new();
+    let mut unreachable: Vec<Mirror> = Vec::new();
+    for mirror in mirrors {
+        match probe(http, mirror) {
+            Ok(benchmark) => {
+                println!("{}", benchmark.format());
+                benchmarks.push(benchmark);
+            }
+            Err(e) => {
+                warn!("{} Mirror '{}' is unreachable: {}", emoji::WARN, mirror, e);
+                unreachable.push(mirror.clone());
+            }
+        }
+    }
+    if benchmarks.is_empty() {
+        return Err(Error::NoReachableMirror);
+    }
+    Ok(rank_mirrors(&benchmarks, &unreachable))
+}
+
+/// Gets the mirrors to test: the given ones, otherwise the `mirrors` of the configuration
+/// file of the named environment `env`, otherwise every known mirror.
+pub fn get_candidate_mirrors(
+    env: Option<&str>,
+    mut mirrors: Vec<Mirror>,
+) -> Result<Vec<Mirror>, Error> {
+    if mirrors.is_empty() {
+        mirrors = Config::load_mirrors(env)
+            .iter()
+            .map(|mirror| Mirror::parse(mirror))
+            .collect::<Result<_, _>>()?;
+    }
+    if mirrors.is_empty() {
+        mirrors = vec![Mirror::Github, Mirror::Espressif, Mirror::Jihulab];
+    }
+    Ok(mirrors)
+}
+
+/// Ranks the mirrors by the speed of a probe download with the client of `context`, and
+/// saves the ranking as the `mirrors` of the configuration file, unless `dry_run`.
+pub fn rank(context: &Context, mirrors: &[Mirror], dry_run: bool) -> Result<(), Error> {
+    info!("{} Testing {} mirrors", emoji::DISC, mirrors.len());
+    let ranking: Vec<String> = test_mirrors(&context.http, mirrors)?
+        .iter()
+        .map(Mirror::to_string)
+        .collect();
+    if dry_run {
+        println!("Ranking: {}", ranking.join(", "));
+        return Ok(());
+    }
+    Config::save_mirrors(context.env.as_deref(), &ranking)?;
+    info!(
+        "{} Saved the ranking of the mirrors, '{}' is used first: {}",
+        emoji::CHECK,
+        ranking[0],
+        ranking.join(", ")
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        http::Mirror,
+        mirror::{rank_mirrors, Benchmark},
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn test_rank_mirrors() {
+        let benchmark = |mirror: Mirror, latency: u64, throughput: u64| Benchmark {
+            mirror,
+            latency: Duration::from_millis(latency),
+            throughput,
+        };
+        let benchmarks = [
+            benchmark(Mirror::Github, 120, 2_000_000),
+            benchmark(Mirror::Espressif, 80, 5_000_000),
+            benchmark(Mirror::Jihulab, 60, 5_000_000),
+        ];
+        let unreachable = [Mirror::parse("https://mirror.example.com").unwrap()];
+        assert_eq!(
+            rank_mirrors(&benchmarks, &unreachable),
+            [
+                Mirror::Jihulab,
+                Mirror::Espressif,
+                Mirror::Github,
+                unreachable[0].clone()
+            ]
+        );
+        assert_eq!(benchmarks[1].format(), "espressif: 80 ms, 4.8 MiB/s");
+    }
+}
@@ -0,0 +1,189 @@
+//! Comparison of the installed Xtensa Rust toolchain, LLVM and ESP-IDF versions against the
+//! latest releases, for `espup check-updates`.
+
+use crate::{
+    config::Config,
+    context::Context,
+    emoji,
+    toolchain::{
+        espidf::{get_remote_versions, parse_esp_idf_ref},
+        llvm::DEFAULT_LLVM_15_VERSION,
+        rust::XtensaRust,
+    },
+};
+use embuild::git;
+use log::debug;
+use miette::Result;
+use std::fmt;
+
+/// A component with a newer release than the installed one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Update {
+    /// Name of the component.
+    pub name: String,
+    /// Installed version.
+    pub installed: String,
+    /// Latest release.
+    pub latest: String,
+}
+
+impl fmt::Display for Update {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} -> {}", self.name, self.installed, self.latest)
+    }
+}
+
+/// Parses the numbers of a version, e.g. `1.65.0.1`, `esp-15.0.0-20221201` or `v5.0.1`,
+/// so that versions of the same component compare in release order.
+fn parse_version_numbers(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim();
+    let version = version.strip_prefix("esp-").unwrap_or(version);
+    let version = version.strip_prefix('v').unwrap_or(version);
+    version
+        .split(['.', '-'])
+        .map(|n| n.parse::<u64>().ok())
+        .collect()
+}
+
+/// Returns the update of a component, if `latest` is newer than `installed`.
+pub fn get_update(name: &str, installed: &str, latest: &str) -> Option<Update> {
+    debug!(
+        "{} {}: installed '{}', latest '{}'",
+        emoji::DEBUG,
+        name,
+        installed,
+        latest
+    );
+    match (
+        parse_version_numbers(installed),
+        parse_version_numbers(latest),
+    ) {
+        (Some(installed_numbers), Some(latest_numbers)) if latest_numbers > installed_numbers => {
+            Some(Update {
+                name: name.to_string(),
+                installed: installed.to_string(),
+                latest: latest.to_string(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Gets the installed LLVM version from its directory, e.g.
+/// `xtensa-esp32-elf-clang/esp-15.0.0-20221201-x86_64-unknown-linux-gnu`.
+pub fn get_llvm_version(config: &Config) -> Option<String> {
+    let dir_name = config.llvm_path.as_ref()?.file_name()?.to_string_lossy();
+    let version: Vec<&str> = dir_name.splitn(4, '-').take(3).collect();
+    if version.len() < 3 {
+        return None;
+    }
+    Some(version.join("-"))
+}
+
+/// Gets the installed ESP-IDF release, e.g. `v5.0`. Branches and commits are not releases,
+/// nor are the checkouts that espup does not manage.
+fn get_esp_idf_release(config: &Config) -> Option<String> {
+    match parse_esp_idf_ref(config.esp_idf_version.as_ref()?) {
+        git::Ref::Tag(tag) => Some(tag),
+        _ => None,
+    }
+}
+
+/// Checks the installed Xtensa Rust toolchain, LLVM and ESP-IDF against their latest
+/// releases, returning the components that can be updated.
+pub fn check_updates(context: &Context, config: &Config) -> Result<Vec<Update>> {
+    let mut updates = Vec::new();
+    if let Some(xtensa_rust) = &config.xtensa_rust {
+        let latest = XtensaRust::get_latest_version(context)?;
+        updates.extend(get_update("Xtensa Rust", &xtensa_rust.version, &latest));
+    }
+    if let Some(llvm_version) = get_llvm_version(config) {
+        updates.extend(get_update(
+            "Xtensa LLVM",
+            &llvm_version,
+            DEFAULT_LLVM_15_VERSION,
+        ));
+    }
+    if let Some(esp_idf_release) = get_esp_idf_release(config) {
+        let repository_url = config
+            .esp_idf_repository
+            .clone()
+            .unwrap_or_else(|| context.http.esp_idf_repository());
+        if let Some(latest) = get_remote_versions(context, &repository_url)?.first() {
+            updates.extend(get_update("ESP-IDF", &esp_idf_release, latest));
+        }
+    }
+    Ok(updates)
+}
+
+/// Formats the one-line summary printed by `espup check-updates`.
+pub fn format_summary(updates: &[Update]) -> String {
+    if updates.is_empty() {
+        return "Up to date".to_string();
+    }
+    format!(
+        "Updates available: {}",
+        updates
+            .iter()
+            .map(Update::to_string)
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        outdated::{
+            format_summary, get_esp_idf_release, get_llvm_version, get_update,
+            parse_version_numbers,
+        },
+    };
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_version_numbers() {
+        assert_eq!(parse_version_numbers("1.65.0.1"), Some(vec![1, 65, 0, 1]));
+        assert_eq!(
+            parse_version_numbers("esp-15.0.0-20221201"),
+            Some(vec![15, 0, 0, 20221201])
+        );
+        assert_eq!(parse_version_numbers("v5.0.1"), Some(vec![5, 0, 1]));
+        assert_eq!(parse_version_numbers("release/v5.0"), None);
+    }
+
+    #[test]
+    fn test_get_update() {
+        let update = get_update("ESP-IDF", "v5.0", "v5.0.1").unwrap();
+        assert_eq!(update.to_string(), "ESP-IDF v5.0 -> v5.0.1");
+        assert!(get_update("Xtensa Rust", "1.66.0.0", "1.66.0.0").is_none());
+        assert!(get_update("Xtensa Rust", "1.66.0.0", "1.65.0.1").is_none());
+        assert_eq!(
+            format_summary(&[
+                get_update("Xtensa Rust", "1.65.0.1", "1.66.0.0").unwrap(),
+                update
+            ]),
+            "Updates available: Xtensa Rust 1.65.0.1 -> 1.66.0.0, ESP-IDF v5.0 -> v5.0.1"
+        );
+        assert_eq!(format_summary(&[]), "Up to date");
+    }
+
+    #[test]
+    fn test_get_installed_versions() {
+        let mut config = Config {
+            llvm_path: Some(PathBuf::from(
+                "/home/esp/.espressif/tools/xtensa-esp32-elf-clang/esp-15.0.0-20221201-x86_64-unknown-linux-gnu",
+            )),
+            esp_idf_version: Some("v5.0".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            get_llvm_version(&config).as_deref(),
+            Some("esp-15.0.0-20221201")
+        );
+        assert_eq!(get_esp_idf_release(&config).as_deref(), Some("v5.0"));
+        config.esp_idf_version = Some("release/v5.0".to_string());
+        assert_eq!(get_esp_idf_release(&config), None);
+    }
+}
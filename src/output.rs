@@ -0,0 +1,207 @@
+//! Owner of the terminal: the downloads running concurrently are drawn as a region of
+//! progress bars at the bottom of stderr, while the log lines are printed above it, one at a
+//! time. With `--message-format json` or `--quiet`, or when stderr is not a terminal, no
+//! bars are drawn and the log lines are printed as they are.
+
+use crate::progress::{Event, Phase, Progress, ProgressHandler};
+use console::{truncate_str, Term};
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+
+/// Width of the bar of a download, in characters.
+const BAR_WIDTH: usize = 24;
+
+/// The region of progress bars, locked while anything is written to the terminal.
+static REGION: Lazy<Mutex<Region>> = Lazy::new(|| Mutex::new(Region::default()));
+
+/// Progress of a download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Bar {
+    /// Name of the downloaded component.
+    component: String,
+    /// Bytes downloaded so far.
+    bytes: u64,
+    /// Total size of the download, when known.
+    total_bytes: Option<u64>,
+    /// Whether the archive is extracted while it is downloaded.
+    extracting: bool,
+}
+
+impl Bar {
+    /// Formats the bar, e.g. `cmake.zip [######------------------]  25%  1.2/4.8 MiB`.
+    fn format(&self) -> String {
+        let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+        let mut line = match self.total_bytes {
+            Some(total_bytes) if total_bytes > 0 => {
+                let bytes = self.bytes.min(total_bytes);
+                let filled = (bytes as u128 * BAR_WIDTH as u128 / total_bytes as u128) as usize;
+                format!(
+                    "{} [{}{}] {:>3}% {:.1}/{:.1} MiB",
+                    self.component,
+                    "#".repeat(filled),
+                    "-".repeat(BAR_WIDTH - filled),
+                    bytes * 100 / total_bytes,
+                    mib(bytes),
+                    mib(total_bytes)
+                )
+            }
+            _ => format!("{} {:.1} MiB", self.component, mib(self.bytes)),
+        };
+        if self.extracting {
+            line.push_str(" (extracting)");
+        }
+        line
+    }
+}
+
+/// Progress bars drawn at the bottom of stderr.
+#[derive(Debug, Default)]
+struct Region {
+    /// Whether the bars are drawn, see [`enable`].
+    enabled: bool,
+    /// Downloads in progress, in the order they started.
+    bars: Vec<Bar>,
+    /// Number of lines currently drawn.
+    drawn: usize,
+}
+
+impl Region {
+    /// Erases the drawn bars.
+    fn clear(&mut self, term: &Term) {
+        if self.drawn > 0 {
+            let _ = term.clear_last_lines(self.drawn);
+            self.drawn = 0;
+        }
+    }
+
+    /// Draws the bars, truncated to the width of the terminal.
+    fn draw(&mut self, term: &Term) {
+        if !self.enabled {
+            return;
+        }
+        let width = usize::from(term.size().1).saturating_sub(1);
+        for bar in &self.bars {
+            let _ = term.write_line(&truncate_str(&bar.format(), width, "…"));
+        }
+        self.drawn = self.bars.len();
+    }
+
+    /// Updates the bars with `update` and redraws them.
+    fn update(&mut self, update: impl FnOnce(&mut Vec<Bar>)) {
+        let term = Term::stderr();
+        self.clear(&term);
+        update(&mut self.bars);
+        self.draw(&term);
+    }
+}
+
+/// Runs `write`, which writes to the terminal, with the progress bars erased, then redraws
+/// them below its output. The writes of concurrent threads are serialized.
+pub fn suspend<T>(write: impl FnOnce() -> T) -> T {
+    let mut region = REGION.lock().unwrap();
+    let term = Term::stderr();
+    region.clear(&term);
+    let result = write();
+    region.draw(&term);
+    result
+}
+
+/// Handler drawing the downloads as progress bars, registered with [`enable`].
+struct TerminalHandler;
+
+impl TerminalHandler {
+    /// Adds or updates the bar of a download.
+    fn set_bar(&self, event: &Event) {
+        REGION.lock().unwrap().update(|bars| {
+            let bytes = event.bytes.unwrap_or_default();
+            match bars.iter_mut().find(|bar| bar.component == event.component) {
+                Some(bar) => {
+                    bar.bytes = bytes;
+                    bar.total_bytes = event.total_bytes;
+                }
+                None => bars.push(Bar {
+                    component: event.component.clone(),
+                    bytes,
+                    total_bytes: event.total_bytes,
+                    extracting: false,
+                }),
+            }
+        });
+    }
+
+    /// Removes the bar of a download.
+    fn remove_bar(&self, component: &str) {
+        REGION
+            .lock()
+            .unwrap()
+            .update(|bars| bars.retain(|bar| bar.component != component));
+    }
+}
+
+impl ProgressHandler for TerminalHandler {
+    fn download_started(&self, event: &Event) {
+        self.set_bar(event);
+    }
+
+    fn download_progress(&self, event: &Event) {
+        self.set_bar(event);
+    }
+
+    fn download_finished(&self, event: &Event) {
+        self.remove_bar(&event.component);
+    }
+
+    fn phase_changed(&self, event: &Event) {
+        match event.phase {
+            Phase::Extracting => REGION.lock().unwrap().update(|bars| {
+                if let Some(bar) = bars.iter_mut().find(|bar| bar.component == event.component) {
+                    bar.extracting = true;
+                }
+            }),
+            Phase::Finished | Phase::Failed => self.remove_bar(&event.component),
+            Phase::Downloading => {}
+        }
+    }
+}
+
+/// Draws the downloads as progress bars when stderr is a terminal, unless the output is
+/// JSON lines or quiet, with a handler of `progress`.
+pub fn enable(progress: &mut Progress) {
+    if !Term::stderr().is_term() {
+        return;
+    }
+    REGION.lock().unwrap().enabled = true;
+    progress.add_handler(TerminalHandler);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{suspend, Bar};
+
+    #[test]
+    fn test_format_bar() {
+        let mut bar = Bar {
+            component: "cmake.zip".to_string(),
+            bytes: 1024 * 1024,
+            total_bytes: Some(4 * 1024 * 1024),
+            extracting: false,
+        };
+        assert_eq!(
+            bar.format(),
+            "cmake.zip [######------------------]  25% 1.0/4.0 MiB"
+        );
+        bar.extracting = true;
+        bar.bytes = 8 * 1024 * 1024;
+        assert_eq!(
+            bar.format(),
+            "cmake.zip [########################] 100% 4.0/4.0 MiB (extracting)"
+        );
+        bar.total_bytes = None;
+        assert_eq!(bar.format(), "cmake.zip 8.0 MiB (extracting)");
+    }
+
+    #[test]
+    fn test_suspend() {
+        assert_eq!(suspend(|| 42), 42);
+    }
+}
@@ -0,0 +1,387 @@
+//! Detection and installation of the required system packages.
+
+use crate::{
+    context::Context, emoji, error::Error, interactive::is_interactive,
+    toolchain::espidf::get_python,
+};
+use embuild::cmd;
+use log::{debug, info, warn};
+use miette::Result;
+use std::process::Stdio;
+use strum::{Display, EnumIter, IntoEnumIterator};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumIter)]
+#[strum(serialize_all = "lowercase")]
+pub enum PackageManager {
+    /// Debian, Ubuntu and derivatives.
+    Apt,
+    /// Fedora, RHEL and derivatives.
+    Dnf,
+    /// Arch Linux and derivatives.
+    Pacman,
+    /// openSUSE.
+    Zypper,
+    /// Homebrew on macOS.
+    Brew,
+    /// Chocolatey on Windows.
+    Choco,
+}
+
+impl PackageManager {
+    /// Detects the package manager available in the host.
+    pub fn detect() -> Option<Self> {
+        Self::iter()
+            .filter(|manager| manager.is_supported_os())
+            .find(|manager| get_command_output(&manager.to_string(), &["--version"]).is_some())
+    }
+
+    /// Whether the package manager is used in the current operating system.
+    fn is_supported_os(&self) -> bool {
+        match self {
+            Self::Apt | Self::Dnf | Self::Pacman | Self::Zypper => cfg!(target_os = "linux"),
+            Self::Brew => cfg!(target_os = "macos"),
+            Self::Choco => cfg!(windows),
+        }
+    }
+
+    /// Whether the package manager needs root privileges to install packages.
+    fn requires_root(&self) -> bool {
+        !matches!(self, Self::Brew | Self::Choco)
+    }
+
+    /// Returns the command that installs the given packages.
+    pub fn install_command(&self, packages: &[&str]) -> Vec<String> {
+        let mut command: Vec<String> = match self {
+            Self::Apt => vec!["apt-get", "install", "-y"],
+            Self::Dnf => vec!["dnf", "-y", "install"],
+            Self::Pacman => vec!["pacman", "-S", "--needed", "--noconfirm"],
+            Self::Zypper => vec!["zypper", "install", "-y"],
+            Self::Brew => vec!["brew", "install"],
+            Self::Choco => vec!["choco", "install", "-y"],
+        }
+        .into_iter()
+        .map(String::from)
+        .collect();
+        if self.requires_root() && !is_root() {
+            command.insert(0, "sudo".to_string());
+        }
+        command.extend(packages.iter().map(|package| package.to_string()));
+        command
+    }
+
+    /// Whether a package is installed, according to the package manager.
+    fn is_installed(&self, package: &str) -> bool {
+        match self {
+            Self::Apt => get_command_output("dpkg", &["-s", package]).is_some(),
+            Self::Dnf | Self::Zypper => get_command_output("rpm", &["-q", package]).is_some(),
+            Self::Pacman => get_command_output("pacman", &["-Q", package]).is_some(),
+            Self::Brew => get_command_output("brew", &["list", package]).is_some(),
+            Self::Choco => {
+                get_command_output("choco", &["list", "--local-only", "--exact", package])
+                    .map(|output| output.to_lowercase().contains(&package.to_lowercase()))
+                    .unwrap_or(false)
+            }
+        }
+    }
+}
+
+/// Component that requires a system package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequiredBy {
+    /// ESP-IDF and its tools.
+    EspIdf,
+    /// `espflash` and `cargo-espflash` crates.
+    Espflash,
+    /// dfu-util, on the hosts without an Espressif build.
+    DfuUtil,
+}
+
+/// How to detect whether a prerequisite is present.
+#[derive(Debug, Clone, Copy)]
+enum Detection {
+    /// Running the command succeeds.
+    Command(&'static str, &'static [&'static str]),
+    /// Running the Python interpreter with the arguments succeeds.
+    Python(&'static [&'static str]),
+    /// The package manager reports the package as installed.
+    Package,
+}
+
+/// A native prerequisite of espup or of the tools it installs.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemPackage {
+    /// Human readable name.
+    pub name: &'static str,
+    /// Component that requires the package.
+    pub required_by: RequiredBy,
+    detection: Detection,
+    /// Package names for [apt, dnf, pacman, zypper, brew, choco].
+    names: [Option<&'static str>; 6],
+}
+
+impl SystemPackage {
+    /// Returns the name of the package for the given package manager.
+    pub fn package_name(&self, manager: PackageManager) -> Option<&'static str> {
+        self.names[manager as usize]
+    }
+
+    /// Whether the prerequisite is present in the host.
+    fn is_present(&self, context: &Context, manager: Option<PackageManager>) -> bool {
+        match self.detection {
+            Detection::Command(program, args) => get_command_output(program, args).is_some(),
+            Detection::Python(args) => get_command_output(get_python(context), args).is_some(),
+            Detection::Package => match (manager, manager.and_then(|m| self.package_name(m))) {
+                (Some(manager), Some(package)) => manager.is_installed(package),
+                // Nothing we can check or install.
+                _ => true,
+            },
+        }
+    }
+}
+
+/// Known prerequisites.
+const SYSTEM_PACKAGES: &[SystemPackage] = &[
+    SystemPackage {
+        name: "git",
+        required_by: RequiredBy::EspIdf,
+        detection: Detection::Command("git", &["--version"]),
+        names: [
+            Some("git"),
+            Some("git"),
+            Some("git"),
+            Some("git"),
+            Some("git"),
+            Some("git"),
+        ],
+    },
+    SystemPackage {
+        name: "python",
+        required_by: RequiredBy::EspIdf,
+        detection: Detection::Python(&["--version"]),
+        names: [
+            Some("python3"),
+            Some("python3"),
+            Some("python"),
+            Some("python3"),
+            Some("python3"),
+            Some("python"),
+        ],
+    },
+    SystemPackage {
+        name: "pip",
+        required_by: RequiredBy::EspIdf,
+        detection: Detection::Python(&["-m", "pip", "--version"]),
+        names: [
+            Some("python3-pip"),
+            Some("python3-pip"),
+            Some("python-pip"),
+            Some("python3-pip"),
+            None,
+            None,
+        ],
+    },
+    SystemPackage {
+        name: "python venv",
+        required_by: RequiredBy::EspIdf,
+        detection: Detection::Python(&["-m", "venv", "-h"]),
+        names: [Some("python3-venv"), None, None, None, None, None],
+    },
+    SystemPackage {
+        name: "flex",
+        required_by: RequiredBy::EspIdf,
+        detection: Detection::Command("flex", &["--version"]),
+        names: [
+            Some("flex"),
+            Some("flex"),
+            Some("flex"),
+            Some("flex"),
+            None,
+            None,
+        ],
+    },
+    SystemPackage {
+        name: "bison",
+        required_by: RequiredBy::EspIdf,
+        detection: Detection::Command("bison", &["--version"]),
+        names: [
+            Some("bison"),
+            Some("bison"),
+            Some("bison"),
+            Some("bison"),
+            None,
+            None,
+        ],
+    },
+    SystemPackage {
+        name: "libusb",
+        required_by: RequiredBy::EspIdf,
+        detection: Detection::Package,
+        names: [
+            Some("libusb-1.0-0"),
+            Some("libusb1"),
+            Some("libusb"),
+            Some("libusb-1_0-0"),
+            Some("libusb"),
+            None,
+        ],
+    },
+    SystemPackage {
+        name: "libudev",
+        required_by: RequiredBy::Espflash,
+        detection: Detection::Package,
+        names: [
+            Some("libudev-dev"),
+            Some("systemd-devel"),
+            None,
+            Some("libudev-devel"),
+            None,
+            None,
+        ],
+    },
+    SystemPackage {
+        name: "dfu-util",
+        required_by: RequiredBy::DfuUtil,
+        detection: Detection::Command("dfu-util", &["--version"]),
+        names: [
+            Some("dfu-util"),
+            Some("dfu-util"),
+            Some("dfu-util"),
+            Some("dfu-util"),
+            Some("dfu-util"),
+            None,
+        ],
+    },
+];
+
+/// Runs a command without printing its output and returns its stdout, if the
+/// command could be executed and succeeded.
+pub fn get_command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = cmd!(program; args=(args))
+        .into_inner()
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        None
+    }
+}
+
+/// Returns whether the current user is root.
+pub fn is_root() -> bool {
+    cfg!(unix) && get_command_output("id", &["-u"]).map_or(false, |uid| uid == "0")
+}
+
+/// Returns the prerequisites that are not present in the host.
+pub fn get_missing_packages(
+    context: &Context,
+    required_by: &[RequiredBy],
+    manager: Option<PackageManager>,
+) -> Vec<&'static SystemPackage> {
+    SYSTEM_PACKAGES
+        .iter()
+        .filter(|package| required_by.contains(&package.required_by))
+        .filter(|package| !package.is_present(context, manager))
+        .collect()
+}
+
+/// Returns the command that installs the missing packages, if they can be installed
+/// with the package manager.
+pub fn get_install_command(
+    missing: &[&SystemPackage],
+    manager: PackageManager,
+) -> Option<Vec<String>> {
+    let packages: Vec<&str> = missing
+        .iter()
+        .filter_map(|package| package.package_name(manager))
+        .collect();
+    if packages.is_empty() {
+        None
+    } else {
+        Some(manager.install_command(&packages))
+    }
+}
+
+/// Checks the system prerequisites of the components and, if `install` is true,
+/// installs the missing ones. Otherwise, prints the command to install them.
+pub fn check_system_packages(
+    context: &Context,
+    required_by: &[RequiredBy],
+    install: bool,
+) -> Result<(), Error> {
+    if required_by.is_empty() {
+        return Ok(());
+    }
+    info!("{} Checking system packages", emoji::WRENCH);
+    let manager = PackageManager::detect();
+    debug!("{} Package manager: {:?}", emoji::DEBUG, manager);
+    let missing = get_missing_packages(context, required_by, manager);
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let names: Vec<&str> = missing.iter().map(|package| package.name).collect();
+    warn!(
+        "{} Missing system packages: {}",
+        emoji::WARN,
+        names.join(", ")
+    );
+    let mut command = match manager.and_then(|manager| get_install_command(&missing, manager)) {
+        Some(command) => command,
+        None => {
+            warn!(
+                "{} Unable to install them automatically, please install them manually",
+                emoji::WARN
+            );
+            return Ok(());
+        }
+    };
+    if install {
+        // Fails instead of asking for a password when nobody can type it.
+        if command[0] == "sudo" && !is_interactive() {
+            command.insert(1, "-n".to_string());
+        }
+        info!(
+            "{} Installing system packages: '{}'",
+            emoji::WRENCH,
+            command.join(" ")
+        );
+        cmd!(&command[0]; args=(&command[1..])).run()?;
+    } else {
+        warn!(
+            "{} Install them running: '{}' or use '--install-prereqs'",
+            emoji::INFO,
+            command.join(" ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packages::{get_install_command, PackageManager, SYSTEM_PACKAGES};
+
+    #[test]
+    fn test_get_install_command() {
+        let missing: Vec<_> = SYSTEM_PACKAGES
+            .iter()
+            .filter(|package| ["pip", "libusb", "python venv"].contains(&package.name))
+            .collect();
+        let command = get_install_command(&missing, PackageManager::Brew).unwrap();
+        assert_eq!(command, ["brew", "install", "libusb"]);
+        let command = get_install_command(&missing, PackageManager::Apt).unwrap();
+        assert!(command.ends_with(&[
+            "install".to_string(),
+            "-y".to_string(),
+            "python3-pip".to_string(),
+            "python3-venv".to_string(),
+            "libusb-1.0-0".to_string(),
+        ]));
+        let missing: Vec<_> = SYSTEM_PACKAGES
+            .iter()
+            .filter(|package| package.name == "python venv")
+            .collect();
+        assert!(get_install_command(&missing, PackageManager::Choco).is_none());
+    }
+}
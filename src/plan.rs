@@ -0,0 +1,193 @@
+//! Actions planned by a command, printed instead of executed in dry-run mode.
+
+use crate::{context::Context, emoji, list::format_size, toolchain::get_download_size};
+use log::info;
+use std::{collections::BTreeMap, path::PathBuf};
+
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    /// Artifacts to download, along with their destination.
+    pub downloads: Vec<(String, PathBuf)>,
+    /// URLs of the artifacts to download, as published rather than rewritten for the mirror.
+    pub artifacts: Vec<String>,
+    /// Repositories to clone, along with their destination.
+    pub clones: Vec<(String, PathBuf)>,
+    /// Commands to run.
+    pub commands: Vec<String>,
+    /// Files and directories to create.
+    pub create: Vec<PathBuf>,
+    /// Files and directories to remove.
+    pub remove: Vec<PathBuf>,
+    /// Files to modify.
+    pub modify: Vec<PathBuf>,
+    /// Environment variables to export.
+    pub exports: Vec<String>,
+    /// Estimated disk usage, in bytes, of the installed components, along with the
+    /// directory they are installed in.
+    pub disk_usage: Vec<(PathBuf, u64)>,
+}
+
+impl Plan {
+    /// Adds an artifact download, from the mirror of the context.
+    pub fn download(
+        &mut self,
+        context: &Context,
+        url: impl Into<String>,
+        destination: impl Into<PathBuf>,
+    ) {
+        let url = url.into();
+        self.downloads
+            .push((context.http.mirror().rewrite_url(&url), destination.into()));
+        self.artifacts.push(url);
+    }
+
+    /// Adds a repository clone.
+    pub fn clone_repository(&mut self, url: impl Into<String>, destination: impl Into<PathBuf>) {
+        self.clones.push((url.into(), destination.into()));
+    }
+
+    /// Adds a command.
+    pub fn command(&mut self, command: impl Into<String>) {
+        self.commands.push(command.into());
+    }
+
+    /// Adds a file or directory to create.
+    pub fn create(&mut self, path: impl Into<PathBuf>) {
+        self.create.push(path.into());
+    }
+
+    /// Adds a file or directory to remove, if it exists.
+    pub fn remove(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        if path.exists() {
+            self.remove.push(path);
+        }
+    }
+
+    /// Adds a file to modify.
+    pub fn modify(&mut self, path: impl Into<PathBuf>) {
+        self.modify.push(path.into());
+    }
+
+    /// Adds the estimated disk usage of a component installed in the given directory.
+    pub fn use_disk_space(&mut self, directory: impl Into<PathBuf>, size: u64) {
+        self.disk_usage.push((directory.into(), size));
+    }
+
+    /// Gets the estimated disk usage, in bytes, grouped by directory.
+    pub fn get_disk_usage(&self) -> BTreeMap<PathBuf, u64> {
+        let mut disk_usage = BTreeMap::new();
+        for (directory, size) in &self.disk_usage {
+            *disk_usage.entry(directory.clone()).or_insert(0) += size;
+        }
+        disk_usage
+    }
+
+    /// Adds an exported environment variable.
+    pub fn export(&mut self, name: &str, value: impl std::fmt::Display) {
+        self.exports.push(format!("{name}={value}"));
+    }
+
+    /// Gets the total size of the downloads, resolved with HEAD requests, and whether the
+    /// size of any of them is unknown.
+    pub fn get_total_download_size(&self, context: &Context) -> (u64, bool) {
+        let sizes: Vec<Option<u64>> = self
+            .downloads
+            .iter()
+            .map(|(url, _)| get_download_size(context, url))
+            .collect();
+        (
+            sizes.iter().flatten().sum(),
+            sizes.iter().any(Option::is_none),
+        )
+    }
+
+    /// Prints the planned actions. Resolves the size of the downloads with HEAD requests.
+    pub fn print(&self, context: &Context) {
+        info!(
+            "{} Dry run, the following actions would be performed:",
+            emoji::INFO
+        );
+        if !self.downloads.is_empty() {
+            let mut total = 0;
+            let mut unknown = false;
+            info!("{} Downloads:", emoji::DOWNLOAD);
+            for (url, destination) in &self.downloads {
+                let size = get_download_size(context, url);
+                total += size.unwrap_or(0);
+                unknown |= size.is_none();
+                info!(
+                    "    {} ({}) -> '{}'",
+                    url,
+                    size.map_or_else(|| "unknown size".to_string(), format_size),
+                    destination.display()
+                );
+            }
+            info!(
+                "    Total: {}{}",
+                format_size(total),
+                if unknown { " (plus unknown sizes)" } else { "" }
+            );
+        }
+        if !self.clones.is_empty() {
+            info!("{} Repositories to clone:", emoji::DOWNLOAD);
+            for (url, destination) in &self.clones {
+                info!("    {} -> '{}'", url, destination.display());
+            }
+        }
+        if !self.create.is_empty() {
+            info!("{} Files and directories to create:", emoji::WRENCH);
+            for path in &self.create {
+                info!("    {}", path.display());
+            }
+        }
+        if !self.remove.is_empty() {
+            info!("{} Files and directories to remove:", emoji::WRENCH);
+            for path in &self.remove {
+                info!("    {}", path.display());
+            }
+        }
+        if !self.modify.is_empty() {
+            info!("{} Files to modify:", emoji::WRENCH);
+            for path in &self.modify {
+                info!("    {}", path.display());
+            }
+        }
+        if !self.disk_usage.is_empty() {
+            info!("{} Estimated disk usage:", emoji::INFO);
+            for (directory, size) in self.get_disk_usage() {
+                info!("    {} in '{}'", format_size(size), directory.display());
+            }
+        }
+        if !self.commands.is_empty() {
+            info!("{} Commands to run:", emoji::WRENCH);
+            for command in &self.commands {
+                info!("    {}", command);
+            }
+        }
+        if !self.exports.is_empty() {
+            info!("{} Environment variables to export:", emoji::INFO);
+            for export in &self.exports {
+                info!("    {}", export);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plan::Plan;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_get_disk_usage() {
+        let mut plan = Plan::default();
+        plan.use_disk_space("/tools", 100);
+        plan.use_disk_space("/rustup", 50);
+        plan.use_disk_space("/tools", 20);
+        let disk_usage = plan.get_disk_usage();
+        assert_eq!(disk_usage.len(), 2);
+        assert_eq!(disk_usage[&PathBuf::from("/tools")], 120);
+        assert_eq!(disk_usage[&PathBuf::from("/rustup")], 50);
+    }
+}
@@ -0,0 +1,377 @@
+//! Machine readable progress events, passed to the [`ProgressHandler`]s registered in the
+//! [`Progress`] of an installation. Crates embedding espup, e.g. IDE extensions, implement
+//! their own, while `--message-format json` prints them to stdout as JSON lines with
+//! [`JsonLinesHandler`].
+
+use miette::Diagnostic;
+use serde::Serialize;
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    sync::Arc,
+};
+
+/// Receiver of the progress of espup. Every method does nothing by default, so that a
+/// handler only implements the ones it needs.
+pub trait ProgressHandler: Send + Sync {
+    /// A download started, with its total size when known.
+    fn download_started(&self, _event: &Event) {}
+    /// Another percent of a download, or MiB when its size is unknown, was received.
+    fn download_progress(&self, _event: &Event) {}
+    /// A download completed, with the number of bytes received.
+    fn download_finished(&self, _event: &Event) {}
+    /// A component entered another phase: it is extracted, ready or failed.
+    fn phase_changed(&self, _event: &Event) {}
+    /// A warning was logged.
+    fn warning(&self, _message: &str) {}
+}
+
+/// Phase of a component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    /// The component is being downloaded.
+    Downloading,
+    /// The archive is being extracted, while it is downloaded.
+    Extracting,
+    /// The component is ready.
+    Finished,
+    /// The component could not be installed.
+    Failed,
+}
+
+/// A progress event, serialized as one JSON object per line.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Event {
+    /// Name of the component, e.g. the downloaded file.
+    pub component: String,
+    /// Phase of the component.
+    pub phase: Phase,
+    /// Bytes downloaded so far.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    /// Total size of the download, in bytes, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_bytes: Option<u64>,
+    /// Percentage of the download completed, when its size is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percent: Option<u8>,
+    /// Error that made the component fail.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Stable code of the error, e.g. `espup::installation_locked`, when it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Exit code of the category of the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<u8>,
+}
+
+impl Event {
+    /// Creates an event without download progress.
+    pub fn new(component: &str, phase: Phase) -> Self {
+        Self {
+            component: component.to_string(),
+            phase,
+            bytes: None,
+            total_bytes: None,
+            percent: None,
+            error: None,
+            code: None,
+            exit_code: None,
+        }
+    }
+
+    /// Creates the event of a failed component, with the exit code of the error category.
+    pub fn failed(component: &str, error: &dyn Diagnostic, exit_code: u8) -> Self {
+        Self {
+            error: Some(error.to_string()),
+            code: error.code().map(|code| code.to_string()),
+            exit_code: Some(exit_code),
+            ..Self::new(component, Phase::Failed)
+        }
+    }
+
+    /// Creates the download progress event of a component.
+    fn downloading(component: &str, bytes: u64, total_bytes: Option<u64>) -> Self {
+        Self {
+            bytes: Some(bytes),
+            total_bytes,
+            percent: total_bytes.map(|total_bytes| get_percent(bytes, total_bytes)),
+            ..Self::new(component, Phase::Downloading)
+        }
+    }
+}
+
+/// Warning printed by [`JsonLinesHandler`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct Warning<'a> {
+    /// The logged message.
+    warning: &'a str,
+}
+
+/// Handler of `--message-format json`, printing the events and warnings to stdout as one
+/// JSON object per line.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonLinesHandler;
+
+impl JsonLinesHandler {
+    /// Prints a JSON line.
+    fn print(&self, value: &impl Serialize) {
+        if let Ok(line) = serde_json::to_string(value) {
+            let mut stdout = io::stdout().lock();
+            let _ = writeln!(stdout, "{line}");
+            let _ = stdout.flush();
+        }
+    }
+}
+
+impl ProgressHandler for JsonLinesHandler {
+    fn download_started(&self, event: &Event) {
+        self.print(event);
+    }
+
+    fn download_progress(&self, event: &Event) {
+        self.print(event);
+    }
+
+    fn download_finished(&self, event: &Event) {
+        self.print(event);
+    }
+
+    fn phase_changed(&self, event: &Event) {
+        self.print(event);
+    }
+
+    fn warning(&self, message: &str) {
+        self.print(&Warning { warning: message });
+    }
+}
+
+/// Handler calling a closure with every event, registered with [`Progress::set_callback`].
+struct CallbackHandler<F>(F);
+
+impl<F: Fn(&Event) + Send + Sync> ProgressHandler for CallbackHandler<F> {
+    fn download_started(&self, event: &Event) {
+        (self.0)(event);
+    }
+
+    fn download_progress(&self, event: &Event) {
+        (self.0)(event);
+    }
+
+    fn download_finished(&self, event: &Event) {
+        (self.0)(event);
+    }
+
+    fn phase_changed(&self, event: &Event) {
+        (self.0)(event);
+    }
+}
+
+/// Handlers receiving the progress events of an installation, e.g. in its
+/// [`crate::context::Context`]. Cloning it shares the handlers.
+#[derive(Clone, Default)]
+pub struct Progress {
+    handlers: Vec<Arc<dyn ProgressHandler>>,
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Progress")
+            .field("handlers", &self.handlers.len())
+            .finish()
+    }
+}
+
+impl Progress {
+    /// Registers a handler receiving every progress event and warning from now on, along
+    /// with the ones already registered.
+    pub fn add_handler(&mut self, handler: impl ProgressHandler + 'static) {
+        self.handlers.push(Arc::new(handler));
+    }
+
+    /// Registers a callback receiving every progress event, e.g. to display the progress of
+    /// an installation in a GUI.
+    pub fn set_callback(&mut self, callback: impl Fn(&Event) + Send + Sync + 'static) {
+        self.add_handler(CallbackHandler(callback));
+    }
+
+    /// Passes something to every handler.
+    fn dispatch(&self, notify: impl Fn(&dyn ProgressHandler)) {
+        for handler in &self.handlers {
+            notify(handler.as_ref());
+        }
+    }
+
+    /// Passes the phase change of a component to the handlers.
+    pub fn emit(&self, event: Event) {
+        self.dispatch(|handler| handler.phase_changed(&event));
+    }
+
+    /// Passes a logged warning to the handlers.
+    pub fn warning(&self, message: &str) {
+        self.dispatch(|handler| handler.warning(message));
+    }
+}
+
+/// Prints the output of a command, e.g. a row of `cache list`, as a JSON line.
+pub fn print(value: &impl Serialize) {
+    JsonLinesHandler.print(value);
+}
+
+/// Gets the percentage of `bytes` out of `total_bytes`.
+fn get_percent(bytes: u64, total_bytes: u64) -> u8 {
+    (bytes.min(total_bytes) * 100)
+        .checked_div(total_bytes)
+        .map_or(100, |percent| percent as u8)
+}
+
+/// Reader passing the start of a download to the handlers, then its progress whenever
+/// another percent, or MiB when the size is unknown, was read, and its end.
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Progress,
+    component: String,
+    bytes: u64,
+    total_bytes: Option<u64>,
+    last_step: u64,
+    finished: bool,
+}
+
+impl<R: Read> ProgressReader<R> {
+    /// Wraps the reader of a download of `total_bytes`, if known, passing its progress to
+    /// the handlers of `progress`.
+    pub fn new(inner: R, progress: &Progress, component: &str, total_bytes: Option<u64>) -> Self {
+        let event = Event::downloading(component, 0, total_bytes);
+        progress.dispatch(|handler| handler.download_started(&event));
+        Self {
+            inner,
+            progress: progress.clone(),
+            component: component.to_string(),
+            bytes: 0,
+            total_bytes,
+            last_step: 0,
+            finished: false,
+        }
+    }
+
+    /// Creates the event of the progress so far.
+    fn event(&self) -> Event {
+        Event::downloading(&self.component, self.bytes, self.total_bytes)
+    }
+
+    /// Current step of the progress: the percentage or the number of MiB read.
+    fn step(&self) -> u64 {
+        match self.total_bytes {
+            Some(total_bytes) => get_percent(self.bytes, total_bytes).into(),
+            None => self.bytes / (1024 * 1024),
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes += read as u64;
+        if read == 0 && !buf.is_empty() {
+            if !self.finished {
+                self.finished = true;
+                let event = self.event();
+                self.progress
+                    .dispatch(|handler| handler.download_finished(&event));
+            }
+            return Ok(read);
+        }
+        let step = self.step();
+        if self.last_step != step {
+            self.last_step = step;
+            let event = self.event();
+            self.progress
+                .dispatch(|handler| handler.download_progress(&event));
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::Error,
+        progress::{get_percent, Event, Phase, Progress, ProgressHandler, ProgressReader},
+    };
+    use std::{
+        io::{self, Cursor},
+        sync::{Arc, Mutex},
+    };
+
+    /// Handler recording the downloads: the method, along with the bytes.
+    #[derive(Clone, Default)]
+    struct RecordingHandler(Arc<Mutex<Vec<(&'static str, u64)>>>);
+
+    impl RecordingHandler {
+        fn record(&self, method: &'static str, event: &Event) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((method, event.bytes.unwrap_or_default()));
+        }
+    }
+
+    impl ProgressHandler for RecordingHandler {
+        fn download_started(&self, event: &Event) {
+            self.record("started", event);
+        }
+
+        fn download_progress(&self, event: &Event) {
+            self.record("progress", event);
+        }
+
+        fn download_finished(&self, event: &Event) {
+            self.record("finished", event);
+        }
+    }
+
+    #[test]
+    fn test_serialize_event() {
+        assert_eq!(
+            serde_json::to_string(&Event::downloading("cmake.zip", 512, Some(2048))).unwrap(),
+            r#"{"component":"cmake.zip","phase":"downloading","bytes":512,"total_bytes":2048,"percent":25}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Event::new("cmake.zip", Phase::Finished)).unwrap(),
+            r#"{"component":"cmake.zip","phase":"finished"}"#
+        );
+        let error = Error::InstallationLocked("/tmp".to_string());
+        let event = Event::failed("espup", &error, error.exit_code());
+        assert_eq!(event.code.as_deref(), Some("espup::installation_locked"));
+        assert_eq!(event.exit_code, Some(6));
+        assert_eq!(get_percent(0, 0), 100);
+    }
+
+    #[test]
+    fn test_progress_handler() {
+        let handler = RecordingHandler::default();
+        let mut progress = Progress::default();
+        progress.add_handler(handler.clone());
+        let size = 4 * 1024 * 1024;
+        let mut reader = ProgressReader::new(
+            Cursor::new(vec![0; size]),
+            &progress,
+            "progress-test.bin",
+            Some(size as u64),
+        );
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        let recorded = handler.0.lock().unwrap();
+        assert_eq!(recorded.first(), Some(&("started", 0)));
+        assert_eq!(recorded.last(), Some(&("finished", size as u64)));
+        assert_eq!(
+            recorded
+                .iter()
+                .filter(|(method, _)| *method == "finished")
+                .count(),
+            1
+        );
+        assert!(recorded.iter().any(|(method, _)| *method == "progress"));
+    }
+}
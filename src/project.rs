@@ -0,0 +1,304 @@
+//! Requirements of a project, read by `espup install --project <DIR>` so that onboarding is
+//! a single command. They are declared in `espup.toml`, or in the `[package.metadata.espup]`
+//! table of `Cargo.toml`, e.g.:
+//!
+//! ```toml
+//! [package.metadata.espup]
+//! targets = "esp32c3"
+//! esp-idf-version = "v5.0"
+//! toolchain-version = "1.66.0.0"
+//!
+//! [package.metadata.espup.toolchains]
+//! gcc = "11.2.0"
+//! llvm = "esp-15.0.0-20221201"
+//! ```
+//!
+//! Pinning the GCC and LLVM versions in the `[toolchains]` table keeps the compilers of the
+//! project when the defaults of espup change.
+//!
+//! The channel of `rust-toolchain.toml` selects the name of the Xtensa Rust toolchain, e.g.
+//! `esp`, or the nightly version, e.g. `nightly-2023-01-01`.
+
+use crate::{
+    emoji,
+    error::Error,
+    targets::{parse_targets, Target},
+    toolchain::{
+        gcc::GccVersion,
+        llvm::Llvm,
+        rust::{parse_nightly_version, XtensaRust},
+    },
+};
+use log::debug;
+use miette::Result;
+use serde::Deserialize;
+use std::{collections::HashSet, fs::read_to_string, path::Path};
+
+/// File declaring the requirements of a project.
+pub const PROJECT_FILE_NAME: &str = "espup.toml";
+/// Toolchain files of rustup, the legacy one being either TOML or a plain channel name.
+const RUST_TOOLCHAIN_FILE_NAMES: [&str; 2] = ["rust-toolchain.toml", "rust-toolchain"];
+
+/// Requirements, as declared in the project files.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Declaration {
+    targets: Option<String>,
+    esp_idf_version: Option<String>,
+    toolchain_version: Option<String>,
+    #[serde(default)]
+    toolchains: Toolchains,
+}
+
+/// Versions of the compilers, as declared in the `[toolchains]` table.
+#[derive(Debug, Default, Deserialize)]
+struct Toolchains {
+    /// GCC versions, comma separated, for every target or for a single one, e.g.
+    /// `11.2.0,esp32=8.4.0`.
+    gcc: Option<String>,
+    /// LLVM version.
+    llvm: Option<String>,
+}
+
+/// Requirements of a project.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProjectRequirements {
+    /// Targets of the project.
+    pub targets: Option<HashSet<Target>>,
+    /// ESP-IDF version of the `std` projects.
+    pub esp_idf_version: Option<String>,
+    /// Xtensa Rust toolchain version.
+    pub toolchain_version: Option<String>,
+    /// Name of the Xtensa Rust toolchain, from `rust-toolchain.toml`.
+    pub toolchain_name: Option<String>,
+    /// Nightly Rust toolchain version, from `rust-toolchain.toml`.
+    pub nightly_version: Option<String>,
+    /// GCC versions, from the `[toolchains]` table.
+    pub gcc_version: Option<Vec<GccVersion>>,
+    /// LLVM version, from the `[toolchains]` table.
+    pub llvm_version: Option<String>,
+}
+
+impl ProjectRequirements {
+    /// Whether the project declares no requirement.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Reads a TOML file of the project, if it exists.
+fn read_toml(path: &Path) -> Result<Option<toml::Value>, Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = read_to_string(path)?;
+    toml::from_str(&contents)
+        .map(Some)
+        .map_err(|e| Error::InvalidProjectFile(path.display().to_string(), e.to_string()))
+}
+
+/// Reads the declaration of `espup.toml` or, if missing, the `[package.metadata.espup]`
+/// table of `Cargo.toml`, falling back to `[workspace.metadata.espup]`.
+fn read_declaration(project_dir: &Path) -> Result<Option<Declaration>, Error> {
+    let (path, table) = match read_toml(&project_dir.join(PROJECT_FILE_NAME))? {
+        Some(espup_toml) => (project_dir.join(PROJECT_FILE_NAME), Some(espup_toml)),
+        None => {
+            let path = project_dir.join("Cargo.toml");
+            let table = read_toml(&path)?.and_then(|cargo_toml| {
+                ["package", "workspace"]
+                    .iter()
+                    .find_map(|section| cargo_toml.get(section)?.get("metadata")?.get("espup"))
+                    .cloned()
+            });
+            (path, table)
+        }
+    };
+    table
+        .map(|table| {
+            table
+                .try_into()
+                .map_err(|e| Error::InvalidProjectFile(path.display().to_string(), e.to_string()))
+        })
+        .transpose()
+}
+
+/// Parses the channel of a rustup toolchain file.
+fn parse_channel(contents: &str) -> Option<String> {
+    match toml::from_str::<toml::Value>(contents) {
+        Ok(toolchain_file) => toolchain_file
+            .get("toolchain")?
+            .get("channel")?
+            .as_str()
+            .map(str::to_string),
+        Err(_) => contents
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(str::to_string),
+    }
+}
+
+/// Reads the channel of the rustup toolchain file of the project, if any.
+fn read_channel(project_dir: &Path) -> Result<Option<String>, Error> {
+    for file_name in RUST_TOOLCHAIN_FILE_NAMES {
+        let path = project_dir.join(file_name);
+        if path.is_file() {
+            return Ok(parse_channel(&read_to_string(path)?));
+        }
+    }
+    Ok(None)
+}
+
+/// Reads the requirements of the project in `project_dir`.
+pub fn read_project(project_dir: &Path) -> Result<ProjectRequirements> {
+    let mut requirements = ProjectRequirements::default();
+    if let Some(declaration) = read_declaration(project_dir)? {
+        requirements.targets = declaration
+            .targets
+            .as_deref()
+            .map(parse_targets)
+            .transpose()?;
+        requirements.esp_idf_version = declaration.esp_idf_version;
+        requirements.toolchain_version = declaration
+            .toolchain_version
+            .as_deref()
+            .map(XtensaRust::parse_version_request)
+            .transpose()?;
+        requirements.gcc_version = declaration
+            .toolchains
+            .gcc
+            .as_deref()
+            .map(|versions| versions.split(',').map(GccVersion::parse).collect())
+            .transpose()?;
+        requirements.llvm_version = declaration
+            .toolchains
+            .llvm
+            .as_deref()
+            .map(Llvm::parse_version)
+            .transpose()?;
+    }
+    match read_channel(project_dir)? {
+        Some(channel) if channel.starts_with("nightly") => {
+            requirements.nightly_version = Some(parse_nightly_version(&channel)?);
+        }
+        // Other channels, e.g. `stable` or `1.70`, are not installed by espup.
+        Some(channel) => {
+            requirements.toolchain_name = XtensaRust::parse_toolchain_name(&channel).ok()
+        }
+        None => {}
+    }
+    debug!(
+        "{} Requirements of the project in '{}': {:#?}",
+        emoji::DEBUG,
+        project_dir.display(),
+        requirements
+    );
+    if requirements.is_empty() {
+        return Err(Error::NoProjectRequirements(project_dir.display().to_string()).into());
+    }
+    Ok(requirements)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        project::{parse_channel, read_project, PROJECT_FILE_NAME},
+        targets::{parse_targets, Target},
+        toolchain::{gcc::GccVersion, llvm::DEFAULT_LLVM_15_VERSION},
+    };
+    use std::fs::write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_channel() {
+        assert_eq!(
+            parse_channel("[toolchain]\nchannel = \"esp\"\n").as_deref(),
+            Some("esp")
+        );
+        assert_eq!(
+            parse_channel("nightly-2023-01-01\n").as_deref(),
+            Some("nightly-2023-01-01")
+        );
+        assert_eq!(
+            parse_channel("[toolchain]\ncomponents = [\"rust-src\"]"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_read_project() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(read_project(temp_dir.path()).is_err());
+
+        write(
+            temp_dir.path().join("Cargo.toml"),
+            "[package]\nname = \"blinky\"\n\n[package.metadata.espup]\ntargets = \"esp32c3\"\nesp-idf-version = \"v5.0\"\n",
+        )
+        .unwrap();
+        write(
+            temp_dir.path().join("rust-toolchain.toml"),
+            "[toolchain]\nchannel = \"nightly-2023-01-01\"\n",
+        )
+        .unwrap();
+        let requirements = read_project(temp_dir.path()).unwrap();
+        assert_eq!(
+            requirements.targets,
+            Some([Target::ESP32C3].into_iter().collect())
+        );
+        assert_eq!(requirements.esp_idf_version.as_deref(), Some("v5.0"));
+        assert_eq!(
+            requirements.nightly_version.as_deref(),
+            Some("nightly-2023-01-01")
+        );
+        assert_eq!(requirements.toolchain_name, None);
+
+        write(
+            temp_dir.path().join(PROJECT_FILE_NAME),
+            "targets = \"xtensa\"\ntoolchain-version = \"1.66.0.0\"\n",
+        )
+        .unwrap();
+        write(
+            temp_dir.path().join("rust-toolchain.toml"),
+            "[toolchain]\nchannel = \"esp\"\n",
+        )
+        .unwrap();
+        let requirements = read_project(temp_dir.path()).unwrap();
+        assert_eq!(requirements.targets, Some(parse_targets("xtensa").unwrap()));
+        assert_eq!(requirements.esp_idf_version, None);
+        assert_eq!(requirements.toolchain_version.as_deref(), Some("1.66.0.0"));
+        assert_eq!(requirements.toolchain_name.as_deref(), Some("esp"));
+        assert_eq!(requirements.gcc_version, None);
+
+        write(
+            temp_dir.path().join(PROJECT_FILE_NAME),
+            "targets = \"esp32\"\n\n[toolchains]\ngcc = \"8.4.0,esp32=11.2.0\"\nllvm = \"15\"\n",
+        )
+        .unwrap();
+        let requirements = read_project(temp_dir.path()).unwrap();
+        assert_eq!(
+            requirements.gcc_version,
+            Some(vec![
+                GccVersion::parse("8.4.0").unwrap(),
+                GccVersion::parse("esp32=11.2.0").unwrap()
+            ])
+        );
+        assert_eq!(
+            requirements.llvm_version.as_deref(),
+            Some(DEFAULT_LLVM_15_VERSION)
+        );
+
+        write(
+            temp_dir.path().join(PROJECT_FILE_NAME),
+            "[toolchains]\ngcc = \"12.2.0\"\n",
+        )
+        .unwrap();
+        assert!(read_project(temp_dir.path()).is_err());
+
+        write(
+            temp_dir.path().join(PROJECT_FILE_NAME),
+            "targets = \"esp33\"\n",
+        )
+        .unwrap();
+        assert!(read_project(temp_dir.path()).is_err());
+    }
+}
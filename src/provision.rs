@@ -0,0 +1,318 @@
+//! Provisioning snippets printed by `espup export --format`, which reproduce the installation
+//! recorded in the configuration file inside a container or a configuration management run:
+//! the non-interactive `espup install` command and the environment it exports.
+
+use crate::{
+    config::Config,
+    environment::Environment,
+    host_triple::HostTriple,
+    install::format_dockerfile_env,
+    outdated::get_llvm_version,
+    self_update::ESPUP_RELEASES_URL,
+    toolchain::{
+        dfu_util::DFU_UTIL_NAME,
+        frameworks::Framework,
+        gdb::{RISCV_GDB_NAME, XTENSA_GDB_NAME},
+        openocd::OPENOCD_NAME,
+        rom_elfs::ROM_ELFS_NAME,
+        rust::DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME,
+        sccache::SCCACHE_NAME,
+    },
+};
+use std::{collections::BTreeSet, str::FromStr};
+use strum_macros::{Display, EnumString};
+
+/// Format of a provisioning snippet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum ProvisionFormat {
+    /// Tasks of an Ansible playbook.
+    Ansible,
+    /// Instructions of a Dockerfile.
+    Dockerfile,
+    /// Nix shell, installing the environment when entered.
+    Nix,
+}
+
+/// Gets the option installing an optional tool, from the directory of the tool.
+fn get_tool_option(tool: &str) -> Option<String> {
+    let option = match tool {
+        OPENOCD_NAME => "--with-openocd",
+        XTENSA_GDB_NAME | RISCV_GDB_NAME => "--with-gdb",
+        DFU_UTIL_NAME => "--with-dfu-util",
+        ROM_ELFS_NAME => "--with-rom-elfs",
+        SCCACHE_NAME => "--with-sccache",
+        _ if tool.starts_with("qemu-") => "--with-qemu",
+        _ => return None,
+    };
+    Some(option.to_string())
+}
+
+/// Gets the arguments of the `espup install` command reproducing the installation recorded
+/// in the configuration file. The udev rules and the USB drivers are not recorded, as they
+/// are installed in the system.
+pub fn get_install_arguments(config: &Config) -> Vec<String> {
+    let mut arguments = vec![
+        "install".to_string(),
+        "--non-interactive".to_string(),
+        "--yes".to_string(),
+    ];
+    let targets: BTreeSet<String> = config.targets.iter().map(ToString::to_string).collect();
+    arguments.extend([
+        "--targets".to_string(),
+        targets.into_iter().collect::<Vec<_>>().join(","),
+    ]);
+    if let Some(xtensa_rust) = &config.xtensa_rust {
+        arguments.extend([
+            "--toolchain-version".to_string(),
+            xtensa_rust.version.clone(),
+        ]);
+        if xtensa_rust.name != DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME {
+            arguments.extend(["--name".to_string(), xtensa_rust.name.clone()]);
+        }
+    }
+    arguments.extend([
+        "--nightly-version".to_string(),
+        config.nightly_version.clone(),
+    ]);
+    if let Some(llvm_version) = get_llvm_version(config) {
+        arguments.extend(["--llvm-version".to_string(), llvm_version]);
+    }
+    // ESP-IDF checkouts not managed by espup cannot be reproduced.
+    if let Some(esp_idf_version) = &config.esp_idf_version {
+        arguments.extend(["--esp-idf-version".to_string(), esp_idf_version.clone()]);
+        if config.esp_idf_from_archive == Some(true) {
+            arguments.push("--idf-from-archive".to_string());
+        }
+    }
+    if let Some(extra_crates) = &config.extra_crates {
+        let extra_crates: BTreeSet<&String> = extra_crates.iter().collect();
+        let extra_crates: Vec<&str> = extra_crates.into_iter().map(String::as_str).collect();
+        arguments.extend(["--extra-crates".to_string(), extra_crates.join(",")]);
+    }
+    let mut tool_options = BTreeSet::new();
+    let mut frameworks = BTreeSet::new();
+    for tool_path in config.tool_paths.iter().flatten() {
+        let tool = tool_path
+            .parent()
+            .and_then(|tool_dir| tool_dir.file_name())
+            .map(|tool| tool.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        tool_options.extend(get_tool_option(&tool));
+        // Frameworks are checked out at the root of the tools directory.
+        if let Some(framework) = tool_path
+            .file_name()
+            .and_then(|name| Framework::from_str(&name.to_string_lossy()).ok())
+        {
+            frameworks.insert(framework);
+        }
+    }
+    if !frameworks.is_empty() {
+        let frameworks: Vec<String> = frameworks.iter().map(ToString::to_string).collect();
+        arguments.extend(["--extra-frameworks".to_string(), frameworks.join(",")]);
+    }
+    arguments.extend(tool_options);
+    arguments
+}
+
+/// Quotes an argument for a POSIX shell, when needed.
+fn quote(argument: &str) -> String {
+    let is_plain = !argument.is_empty()
+        && argument
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.,/:=+@".contains(c));
+    if is_plain {
+        argument.to_string()
+    } else {
+        format!("'{}'", argument.replace('\'', "'\\''"))
+    }
+}
+
+/// Formats a command line for a POSIX shell.
+fn format_command(arguments: &[String]) -> String {
+    arguments
+        .iter()
+        .map(|argument| quote(argument))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Renders the instructions of a Dockerfile. The tools, rustup and Cargo are installed in the
+/// same directories as on this host, so that its environment applies as is.
+fn render_dockerfile(config: &Config, environment: &Environment, version: &str) -> String {
+    let mut arguments = vec!["espup".to_string()];
+    arguments.extend(get_install_arguments(config));
+    arguments.push("--install-rustup".to_string());
+    if let Some(install_dir) = &config.install_dir {
+        arguments.extend([
+            "--install-dir".to_string(),
+            install_dir.display().to_string(),
+        ]);
+    }
+    let mut environment = environment.clone();
+    let mut lines = vec![
+        "# Reproduces the espup installation, generated by `espup export --format dockerfile`"
+            .to_string(),
+    ];
+    if let Some(xtensa_rust) = &config.xtensa_rust {
+        lines.push(format!(
+            "ENV RUSTUP_HOME=\"{}\" CARGO_HOME=\"{}\"",
+            xtensa_rust.rustup_home.display(),
+            xtensa_rust.cargo_home.display()
+        ));
+        let cargo_bin = xtensa_rust.cargo_home.join("bin").display().to_string();
+        if !environment.path.contains(&cargo_bin) {
+            environment.path.push(cargo_bin);
+        }
+    }
+    // Container images run Linux, on the architecture of the host.
+    let host_triple = match config.host_triple {
+        HostTriple::Aarch64UnknownLinuxGnu
+        | HostTriple::Aarch64UnknownLinuxMusl
+        | HostTriple::Aarch64AppleDarwin => HostTriple::Aarch64UnknownLinuxGnu,
+        _ => HostTriple::X86_64UnknownLinuxGnu,
+    };
+    lines.push(format!(
+        "RUN curl -fsSL {ESPUP_RELEASES_URL}/v{version}/espup-{host_triple} -o /usr/local/bin/espup \\\n    && chmod a+x /usr/local/bin/espup"
+    ));
+    lines.push(format!("RUN {}", format_command(&arguments)));
+    lines.push(format_dockerfile_env(&environment));
+    lines.join("\n")
+}
+
+/// Renders the tasks of an Ansible playbook, installing for the user of the play.
+fn render_ansible(config: &Config, version: &str) -> String {
+    let mut arguments = vec!["{{ ansible_env.HOME }}/.local/bin/espup".to_string()];
+    arguments.extend(get_install_arguments(config));
+    arguments.push("--install-rustup".to_string());
+    let argv: Vec<String> = arguments
+        .iter()
+        .map(|argument| serde_json::Value::String(argument.clone()).to_string())
+        .collect();
+    format!(
+        r#"# Reproduces the espup installation, generated by `espup export --format ansible`
+- name: Download espup
+  ansible.builtin.get_url:
+    url: "{ESPUP_RELEASES_URL}/v{version}/espup-{{{{ ansible_architecture }}}}-unknown-linux-gnu"
+    dest: "{{{{ ansible_env.HOME }}}}/.local/bin/espup"
+    mode: "0755"
+- name: Install the esp-rs environment
+  ansible.builtin.command:
+    argv: [{}]
+- name: Export the esp-rs environment in the login shells
+  ansible.builtin.lineinfile:
+    path: "{{{{ ansible_env.HOME }}}}/.profile"
+    line: ". {{{{ ansible_env.HOME }}}}/export-esp.sh""#,
+        argv.join(", ")
+    )
+}
+
+/// Renders a Nix shell, installing the environment the first time it is entered.
+fn render_nix(config: &Config) -> String {
+    let mut arguments = vec!["espup".to_string()];
+    arguments.extend(get_install_arguments(config));
+    format!(
+        r#"# Reproduces the espup installation, generated by `espup export --format nix`
+{{ pkgs ? import <nixpkgs> {{ }} }}:
+pkgs.mkShell {{
+  buildInputs = [ pkgs.espup pkgs.rustup ];
+  shellHook = ''
+    [ -f "$HOME/export-esp.sh" ] || {}
+    . "$HOME/export-esp.sh"
+  '';
+}}"#,
+        format_command(&arguments)
+    )
+}
+
+/// Renders the snippet reproducing the installation, with the environment of its export file
+/// and the version of espup to download.
+pub fn render(
+    format: ProvisionFormat,
+    config: &Config,
+    environment: &Environment,
+    version: &str,
+) -> String {
+    match format {
+        ProvisionFormat::Ansible => render_ansible(config, version),
+        ProvisionFormat::Dockerfile => render_dockerfile(config, environment, version),
+        ProvisionFormat::Nix => render_nix(config),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        environment::Environment,
+        provision::{format_command, get_install_arguments, render, ProvisionFormat},
+        targets::Target,
+    };
+    use std::{
+        collections::HashSet,
+        path::{Path, PathBuf},
+        str::FromStr,
+    };
+
+    #[test]
+    fn test_get_install_arguments() {
+        let tools = Path::new("/home/esp/.espressif/tools");
+        let config = Config {
+            esp_idf_version: Some("v5.1".to_string()),
+            extra_crates: Some(HashSet::from([
+                "ldproxy".to_string(),
+                "espflash".to_string(),
+            ])),
+            llvm_path: Some(PathBuf::from(
+                "/home/esp/.espressif/tools/xtensa-esp32-elf-clang/esp-15.0.0-20221201-x86_64-unknown-linux-gnu",
+            )),
+            nightly_version: "nightly-2023-01-01".to_string(),
+            targets: HashSet::from([Target::ESP32S3, Target::ESP32]),
+            tool_paths: Some(HashSet::from([
+                tools.join("xtensa-esp-elf-gdb").join("12.1_20221002"),
+                tools.join("riscv32-esp-elf-gdb").join("12.1_20221002"),
+                tools.join("qemu-xtensa").join("esp_develop_8.1.0_20230907"),
+            ])),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_command(&get_install_arguments(&config)),
+            "install --non-interactive --yes --targets esp32,esp32s3 \
+             --nightly-version nightly-2023-01-01 --llvm-version esp-15.0.0-20221201 \
+             --esp-idf-version v5.1 --extra-crates espflash,ldproxy --with-gdb --with-qemu"
+        );
+        assert_eq!(
+            format_command(&["echo".to_string(), "it's here".to_string()]),
+            "echo 'it'\\''s here'"
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let config = Config {
+            targets: HashSet::from([Target::ESP32C3]),
+            nightly_version: "nightly".to_string(),
+            ..Default::default()
+        };
+        let environment = Environment {
+            variables: vec![("LIBCLANG_PATH".to_string(), "/esp/llvm/lib".to_string())],
+            path: Vec::new(),
+        };
+        let dockerfile = render(ProvisionFormat::Dockerfile, &config, &environment, "0.3.0");
+        assert!(dockerfile.contains("/v0.3.0/espup-x86_64-unknown-linux-gnu"));
+        assert!(dockerfile.contains(
+            "RUN espup install --non-interactive --yes --targets esp32c3 --nightly-version nightly --install-rustup"
+        ));
+        assert!(dockerfile.ends_with("ENV LIBCLANG_PATH=\"/esp/llvm/lib\""));
+        let ansible = render(
+            ProvisionFormat::from_str("ansible").unwrap(),
+            &config,
+            &environment,
+            "0.3.0",
+        );
+        assert!(ansible.contains(r#"argv: ["{{ ansible_env.HOME }}/.local/bin/espup", "install""#));
+        assert!(ansible.contains("espup-{{ ansible_architecture }}-unknown-linux-gnu"));
+        let nix = render(ProvisionFormat::Nix, &config, &environment, "0.3.0");
+        assert!(nix.contains("|| espup install --non-interactive"));
+    }
+}
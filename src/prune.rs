@@ -0,0 +1,379 @@
+//! Removal of the tool and ESP-IDF versions superseded by updates, which are no longer
+//! referenced by the current environment, and of single ESP-IDF versions along with the
+//! tool versions only they require.
+
+use crate::{
+    config::Config,
+    context::Context,
+    emoji,
+    error::Error,
+    install::get_export_file,
+    list::{format_size, get_esp_idf_checkouts, get_tool_versions, Component},
+    lock::InstallLock,
+    toolchain::espidf::{get_esp_idf_install_dir, get_tool_path},
+};
+use log::info;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs::{read_dir, read_to_string, remove_dir_all},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Characters that can follow a path mentioned in the export file.
+pub const PATH_TERMINATORS: [char; 8] = ['/', '\\', '"', '\'', ':', ';', ' ', '\n'];
+
+/// What the current environment references: installed paths, the contents of the export
+/// file and the tool versions required by the ESP-IDF in use.
+#[derive(Debug, Default)]
+struct References {
+    paths: Vec<PathBuf>,
+    exports: String,
+    esp_idf_tools: HashSet<(String, String)>,
+}
+
+impl References {
+    /// Whether the component is part of the current environment.
+    fn contains(&self, component: &Component) -> bool {
+        let tool_version = component
+            .path
+            .parent()
+            .and_then(|tool_path| tool_path.file_name())
+            .zip(component.path.file_name())
+            .map(|(tool, version)| {
+                (
+                    tool.to_string_lossy().into_owned(),
+                    version.to_string_lossy().into_owned(),
+                )
+            });
+        self.paths
+            .iter()
+            .any(|path| path.starts_with(&component.path))
+            || mentions(&self.exports, &component.path)
+            || tool_version.map_or(false, |tool_version| {
+                self.esp_idf_tools.contains(&tool_version)
+            })
+    }
+}
+
+/// Whether a text mentions a path, and not only a path starting with the same characters,
+/// e.g. `v5.0.1` when looking for `v5.0`.
+pub fn mentions(text: &str, path: &Path) -> bool {
+    let path = path.display().to_string();
+    text.match_indices(&path).any(|(index, _)| {
+        text[index + path.len()..]
+            .chars()
+            .next()
+            .map_or(true, |c| PATH_TERMINATORS.contains(&c))
+    })
+}
+
+/// Parses the tool versions listed in the `tools/tools.json` file of ESP-IDF.
+fn parse_esp_idf_tools(tools_json: &str) -> HashSet<(String, String)> {
+    let json: Value = serde_json::from_str(tools_json).unwrap_or_default();
+    json["tools"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|tool| {
+            let name = tool["name"].as_str().unwrap_or_default().to_string();
+            tool["versions"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(move |version| {
+                    Some((name.clone(), version["name"].as_str()?.to_string()))
+                })
+        })
+        .collect()
+}
+
+/// Reads the tool versions required by an ESP-IDF checkout.
+pub fn read_esp_idf_tools(espidf_dir: &Path) -> HashSet<(String, String)> {
+    read_to_string(espidf_dir.join("tools").join("tools.json"))
+        .map(|tools_json| parse_esp_idf_tools(&tools_json))
+        .unwrap_or_default()
+}
+
+/// Selects the tool versions required by a removed ESP-IDF checkout that none of the
+/// remaining ones requires.
+fn select_unshared_tools(
+    removed: HashSet<(String, String)>,
+    remaining: &[HashSet<(String, String)>],
+) -> BTreeSet<(String, String)> {
+    removed
+        .into_iter()
+        .filter(|tool| !remaining.iter().any(|tools| tools.contains(tool)))
+        .collect()
+}
+
+/// Gets the components removed along with an ESP-IDF checkout: the checkout itself and the
+/// installed tool versions that no other ESP-IDF checkout, managed or not, requires.
+pub fn get_esp_idf_components(
+    context: &Context,
+    config: &Config,
+    espidf_dir: &Path,
+) -> Vec<Component> {
+    let checkouts = get_esp_idf_checkouts(context);
+    let remaining: Vec<HashSet<(String, String)>> = checkouts
+        .iter()
+        .map(|checkout| checkout.path.as_path())
+        .filter(|path| *path != espidf_dir)
+        .chain(config.esp_idf_path.as_deref())
+        .map(read_esp_idf_tools)
+        .collect();
+    let mut components: Vec<Component> =
+        select_unshared_tools(read_esp_idf_tools(espidf_dir), &remaining)
+            .into_iter()
+            .map(|(name, version)| Component {
+                path: PathBuf::from(get_tool_path(context, &name)).join(&version),
+                name,
+                version,
+            })
+            .filter(|component| component.path.is_dir())
+            .collect();
+    components.push(
+        checkouts
+            .into_iter()
+            .find(|checkout| checkout.path == espidf_dir)
+            .unwrap_or_else(|| Component {
+                name: "ESP-IDF".to_string(),
+                version: espidf_dir
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                path: espidf_dir.to_path_buf(),
+            }),
+    );
+    components
+}
+
+/// Gets what the current environment references.
+fn get_references(context: &Context, config: &Config) -> References {
+    let mut references = References::default();
+    references.paths.extend(config.llvm_path.clone());
+    references
+        .paths
+        .extend(config.tool_paths.iter().flatten().cloned());
+    let esp_idf_dir = match &config.esp_idf_version {
+        Some(esp_idf_version) => Some(get_esp_idf_install_dir(
+            context,
+            config.esp_idf_repository.as_deref(),
+            esp_idf_version,
+        )),
+        None => config.esp_idf_path.clone(),
+    };
+    if let Some(esp_idf_dir) = esp_idf_dir {
+        references.esp_idf_tools = read_esp_idf_tools(&esp_idf_dir);
+        references.paths.push(esp_idf_dir);
+    }
+    if let Ok(export_file) = get_export_file(context.env.as_deref(), config.export_file.clone()) {
+        references.exports = read_to_string(export_file).unwrap_or_default();
+    }
+    references
+}
+
+/// Gets the installed versions of every tool and the ESP-IDF checkouts.
+fn get_versioned_components(context: &Context) -> Vec<Component> {
+    let mut components: Vec<Component> = read_dir(get_tool_path(context, ""))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .flat_map(|entry| get_tool_versions(context, &entry.file_name().to_string_lossy()))
+        .collect();
+    components.extend(get_esp_idf_checkouts(context));
+    components
+}
+
+/// Last modification time of a component, to tell the most recent versions apart.
+fn get_modified(component: &Component) -> SystemTime {
+    component
+        .path
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Selects the components to prune: those not referenced, except the `keep` most recent
+/// ones of each tool and of ESP-IDF.
+fn select_prunable(
+    components: Vec<Component>,
+    references: &References,
+    keep: usize,
+) -> Vec<Component> {
+    let mut groups: BTreeMap<PathBuf, Vec<Component>> = BTreeMap::new();
+    for component in components {
+        if references.contains(&component) {
+            continue;
+        }
+        let group = component
+            .path
+            .parent()
+            .unwrap_or(&component.path)
+            .to_path_buf();
+        groups.entry(group).or_default().push(component);
+    }
+    groups
+        .into_values()
+        .flat_map(|mut group| {
+            group.sort_by_key(|component| std::cmp::Reverse(get_modified(component)));
+            group.into_iter().skip(keep)
+        })
+        .collect()
+}
+
+/// Gets the tool and ESP-IDF versions that are not referenced by the current environment,
+/// keeping the `keep` most recent ones of each.
+pub fn get_prunable_components(context: &Context, config: &Config, keep: usize) -> Vec<Component> {
+    select_prunable(
+        get_versioned_components(context),
+        &get_references(context, config),
+        keep,
+    )
+}
+
+/// Removes the components.
+pub fn prune(components: &[Component]) -> Result<(), Error> {
+    for component in components {
+        remove_dir_all(&component.path)
+            .map_err(|_| Error::FailedToRemoveDirectory(component.path.display().to_string()))?;
+    }
+    Ok(())
+}
+
+/// Removes the tool and ESP-IDF versions that are no longer referenced, but the `keep` most
+/// recent ones of each. With `dry_run`, only prints them.
+pub fn prune_unused(
+    context: &mut Context,
+    keep: usize,
+    dry_run: bool,
+    no_wait: bool,
+) -> Result<(), Error> {
+    info!("{} Looking for unused versions", emoji::DISC);
+    let config = context.load_config()?;
+    let context = &*context;
+    let _lock = InstallLock::acquire(context, no_wait)?;
+    let components = get_prunable_components(context, &config, keep);
+    if components.is_empty() {
+        info!("{} No unused versions found", emoji::CHECK);
+        return Ok(());
+    }
+    let mut size = 0;
+    for component in &components {
+        let component_size = component.size();
+        size += component_size;
+        println!(
+            "{:<20} {:<36} {:>10}  {}",
+            component.name,
+            component.version,
+            format_size(component_size),
+            component.path.display()
+        );
+    }
+    if dry_run {
+        info!("{} {} would be freed", emoji::INFO, format_size(size));
+        return Ok(());
+    }
+
+    prune(&components)?;
+    info!(
+        "{} Removed {} unused versions, freeing {}",
+        emoji::CHECK,
+        components.len(),
+        format_size(size)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        list::Component,
+        prune::{
+            mentions, parse_esp_idf_tools, select_prunable, select_unshared_tools, References,
+        },
+    };
+    use std::{collections::HashSet, fs::create_dir_all, path::PathBuf};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_mentions() {
+        let exports = "export PATH=/esp/tools/gcc/v5.0.1/bin:$PATH\nexport IDF_PATH=\"/esp/v5.0\"";
+        assert!(mentions(exports, &PathBuf::from("/esp/tools/gcc/v5.0.1")));
+        assert!(mentions(exports, &PathBuf::from("/esp/v5.0")));
+        assert!(!mentions(exports, &PathBuf::from("/esp/tools/gcc/v5.0")));
+    }
+
+    #[test]
+    fn test_parse_esp_idf_tools() {
+        let tools = parse_esp_idf_tools(
+            r#"{"tools": [{"name": "xtensa-esp32-elf", "versions": [{"name": "esp-2022r1-11.2.0"}]}]}"#,
+        );
+        assert!(tools.contains(&(
+            "xtensa-esp32-elf".to_string(),
+            "esp-2022r1-11.2.0".to_string()
+        )));
+        assert!(parse_esp_idf_tools("not json").is_empty());
+    }
+
+    #[test]
+    fn test_select_unshared_tools() {
+        let tools = |versions: &[(&str, &str)]| -> HashSet<(String, String)> {
+            versions
+                .iter()
+                .map(|(name, version)| (name.to_string(), version.to_string()))
+                .collect()
+        };
+        let removed = tools(&[
+            ("xtensa-esp32-elf", "esp-2022r1-11.2.0"),
+            ("openocd-esp32", "v0.11.0-esp32-20221026"),
+        ]);
+        let remaining = [tools(&[("openocd-esp32", "v0.11.0-esp32-20221026")])];
+        assert_eq!(
+            select_unshared_tools(removed.clone(), &remaining)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            [(
+                "xtensa-esp32-elf".to_string(),
+                "esp-2022r1-11.2.0".to_string()
+            )]
+        );
+        assert_eq!(select_unshared_tools(removed, &[]).len(), 2);
+    }
+
+    #[test]
+    fn test_select_prunable() {
+        let temp_dir = TempDir::new().unwrap();
+        let gcc_path = temp_dir.path().join("xtensa-esp32-elf");
+        let components: Vec<Component> = ["8.4.0", "11.2.0", "12.2.0"]
+            .into_iter()
+            .map(|version| {
+                let path = gcc_path.join(version);
+                create_dir_all(&path).unwrap();
+                Component {
+                    name: "xtensa-esp32-elf".to_string(),
+                    version: version.to_string(),
+                    path,
+                }
+            })
+            .collect();
+        let references = References {
+            exports: format!(
+                "export PATH={}:$PATH",
+                gcc_path.join("12.2.0").join("bin").display()
+            ),
+            ..Default::default()
+        };
+        let prunable = select_prunable(components.clone(), &references, 0);
+        let mut versions: Vec<&str> = prunable
+            .iter()
+            .map(|component| component.version.as_str())
+            .collect();
+        versions.sort();
+        assert_eq!(versions, vec!["11.2.0", "8.4.0"]);
+        assert_eq!(select_prunable(components, &references, 2).len(), 0);
+    }
+}
@@ -0,0 +1,175 @@
+//! Markers of the components being installed, so that an installation interrupted before
+//! it could roll back, e.g. killed by a CI timeout, is resumed by the next one: complete
+//! components are reused, while the interrupted ones are removed and installed again.
+
+use crate::{context::Context, emoji, error::Error, interrupt};
+use log::warn;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{create_dir_all, remove_dir_all, remove_file, write},
+    path::{Path, PathBuf},
+};
+
+/// Name of the directory of the markers, under the tools directory.
+const MARKERS_DIRECTORY: &str = "incomplete";
+
+/// Gets the marker of a component in `markers_directory`, named after the hash of its path,
+/// as the components are installed in several directories, e.g. the rustup toolchains one.
+fn get_marker_path_in(markers_directory: &Path, path: &Path) -> PathBuf {
+    let hash = format!(
+        "{:x}",
+        Sha256::digest(path.display().to_string().as_bytes())
+    );
+    markers_directory.join(&hash[..16])
+}
+
+/// Gets the marker of a component, present while it is being installed.
+fn get_marker_path(context: &Context, path: &Path) -> PathBuf {
+    get_marker_path_in(&context.tools_path.join(MARKERS_DIRECTORY), path)
+}
+
+/// Whether a component is installed in `path` and its installation was not interrupted.
+pub fn is_installed(context: &Context, path: &Path) -> bool {
+    is_installed_with(path, &get_marker_path(context, path))
+}
+
+/// Whether the component of [`is_installed`] is installed, given its marker.
+fn is_installed_with(path: &Path, marker: &Path) -> bool {
+    path.exists() && !marker.exists()
+}
+
+/// Marks a component as being installed, removing what an interrupted installation left
+/// in `path`.
+fn start(context: &Context, path: &Path, marker: &Path) -> Result<(), Error> {
+    if marker.exists() {
+        if path.exists() {
+            warn!(
+                "{} Installation of '{}' was interrupted, installing it again",
+                emoji::WARN,
+                path.display()
+            );
+            let removed = if path.is_dir() {
+                remove_dir_all(path)
+            } else {
+                remove_file(path)
+            };
+            removed.map_err(|_| Error::FailedToRemoveDirectory(path.display().to_string()))?;
+        }
+        remove_file(marker)?;
+    }
+    context.rollback.track(marker);
+    if let Some(markers_directory) = marker.parent() {
+        create_dir_all(markers_directory)
+            .map_err(|_| Error::FailedToCreateDirectory(markers_directory.display().to_string()))?;
+    }
+    write(marker, path.display().to_string())?;
+    Ok(())
+}
+
+/// Installs a component in `path` with `install`. It is marked as incomplete until `install`
+/// succeeds, so that it is installed again if the installation is interrupted.
+pub fn install_component<T, E: From<Error>>(
+    context: &Context,
+    path: &Path,
+    install: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    install_component_with(context, path, &get_marker_path(context, path), install)
+}
+
+/// Installs the component of [`install_component`], given its marker.
+fn install_component_with<T, E: From<Error>>(
+    context: &Context,
+    path: &Path,
+    marker: &Path,
+    install: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    interrupt::check()?;
+    start(context, path, marker)?;
+    let installed = install()?;
+    remove_file(marker).map_err(|_| Error::FailedToRemoveFile(marker.display().to_string()))?;
+    Ok(installed)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        context::Context,
+        error::Error,
+        resume::{get_marker_path_in, install_component_with, is_installed_with},
+        toolchain::stage,
+    };
+    use std::fs::{create_dir_all, write};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_install_component() {
+        let temp_dir = TempDir::new().unwrap();
+        let context = Context::default();
+        let path = temp_dir.path().join("tools").join("gcc");
+        let marker = get_marker_path_in(&temp_dir.path().join("incomplete"), &path);
+        assert_ne!(
+            marker,
+            get_marker_path_in(&temp_dir.path().join("incomplete"), &path.join("bin"))
+        );
+
+        // An installation interrupted after extracting part of the component.
+        let interrupted: Result<(), Error> =
+            install_component_with(&context, &path, &marker, || {
+                create_dir_all(&path).unwrap();
+                write(path.join("partial"), "partial").unwrap();
+                Err(Error::FailedToInstallEspIdf)
+            });
+        assert!(interrupted.is_err());
+        assert!(marker.exists());
+        assert!(!is_installed_with(&path, &marker));
+
+        // The next one starts over and completes it.
+        let installed: Result<(), Error> = install_component_with(&context, &path, &marker, || {
+            assert!(!path.exists());
+            create_dir_all(&path).unwrap();
+            write(path.join("complete"), "complete").unwrap();
+            Ok(())
+        });
+        installed.unwrap();
+        assert!(!marker.exists());
+        assert!(is_installed_with(&path, &marker));
+        assert!(path.join("complete").exists());
+    }
+
+    #[test]
+    fn test_install_staged_component() {
+        let temp_dir = TempDir::new().unwrap();
+        let context = Context::default();
+        let path = temp_dir.path().join("frameworks").join("esp-idf-v5.0");
+        let marker = get_marker_path_in(&temp_dir.path().join("incomplete"), &path);
+        let staging_path = temp_dir
+            .path()
+            .join("frameworks")
+            .join("esp-idf-v5.0.staging");
+
+        // A clone interrupted in its staging directory.
+        let interrupted: Result<(), Error> =
+            install_component_with(&context, &path, &marker, || {
+                stage(&context, &path, |staging_dir| {
+                    write(staging_dir.join("partial"), "partial").unwrap();
+                    Err(Error::FailedToInstallEspIdf)
+                })
+            });
+        assert!(interrupted.is_err());
+        assert!(marker.exists());
+        assert!(!is_installed_with(&path, &marker));
+
+        // The next one clones it again from scratch.
+        let installed: Result<(), Error> = install_component_with(&context, &path, &marker, || {
+            stage(&context, &path, |staging_dir| {
+                assert!(!staging_dir.join("partial").exists());
+                create_dir_all(staging_dir.join(".git")).unwrap();
+                Ok(())
+            })
+        });
+        installed.unwrap();
+        assert!(is_installed_with(&path, &marker));
+        assert!(path.join(".git").exists());
+        assert!(!staging_path.exists());
+    }
+}
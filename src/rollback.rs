@@ -0,0 +1,168 @@
+//! Tracking of the paths created and the changes made during an installation, e.g. the
+//! rustup targets added, so that a failed installation leaves the machine in its prior
+//! state.
+
+use crate::emoji;
+use log::{debug, warn};
+use std::{
+    fs::{remove_dir_all, remove_file, rename},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+/// Change made during the current installation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Change {
+    /// Path created, removed on rollback.
+    Path(PathBuf),
+    /// Command undoing a change made by another program, e.g. `rustup target remove`.
+    Command(Vec<String>),
+    /// Path moved from the first path to the second one, moved back on rollback.
+    Move(PathBuf, PathBuf),
+}
+
+/// Gets the topmost ancestor of the path, including itself, that does not exist.
+fn get_first_missing_ancestor(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+        .last()
+        .map(Path::to_path_buf)
+}
+
+/// Changes made during an installation, in order, kept in its
+/// [`crate::context::Context`].
+#[derive(Debug, Default)]
+pub struct Rollback {
+    changes: Mutex<Vec<Change>>,
+}
+
+impl Rollback {
+    /// Registers a path that is about to be created. Nothing is registered if it already
+    /// exists, otherwise its topmost missing ancestor is, as it will be created as well.
+    pub fn track(&self, path: &Path) {
+        if let Some(created) = get_first_missing_ancestor(path) {
+            debug!("{} Tracking '{}'", emoji::DEBUG, created.display());
+            self.changes.lock().unwrap().push(Change::Path(created));
+        }
+    }
+
+    /// Registers the command undoing a change about to be made by another program, e.g.
+    /// `rustup target remove` before the target is added. The command is run on rollback,
+    /// failing silently if the change was not made.
+    pub fn track_command(&self, command: &[&str]) {
+        debug!("{} Tracking '{}'", emoji::DEBUG, command.join(" "));
+        self.changes.lock().unwrap().push(Change::Command(
+            command.iter().map(|arg| arg.to_string()).collect(),
+        ));
+    }
+
+    /// Registers a path that is about to be moved, e.g. a component reused from a previous
+    /// installation, so that it is moved back on rollback.
+    pub fn track_move(&self, from: &Path, to: &Path) {
+        debug!(
+            "{} Tracking the move of '{}' to '{}'",
+            emoji::DEBUG,
+            from.display(),
+            to.display()
+        );
+        self.changes
+            .lock()
+            .unwrap()
+            .push(Change::Move(from.to_path_buf(), to.to_path_buf()));
+    }
+
+    /// Undoes every change made during the installation, newest first.
+    pub fn rollback(&self) {
+        let changes: Vec<Change> = self.changes.lock().unwrap().drain(..).collect();
+        undo(&changes);
+    }
+
+    /// Forgets the tracked changes, keeping them, once the installation succeeded.
+    pub fn commit(&self) {
+        self.changes.lock().unwrap().clear();
+    }
+}
+
+/// Undoes the changes, newest first.
+fn undo(changes: &[Change]) {
+    for change in changes.iter().rev() {
+        match change {
+            Change::Path(path) if path.is_symlink() || path.is_file() => {
+                warn!("{} Rolling back '{}'", emoji::WARN, path.display());
+                let _ = remove_file(path);
+            }
+            Change::Path(path) if path.exists() => {
+                warn!("{} Rolling back '{}'", emoji::WARN, path.display());
+                let _ = remove_dir_all(path);
+            }
+            Change::Path(_) => {}
+            Change::Move(from, to) if to.exists() => {
+                warn!("{} Moving '{}' back", emoji::WARN, to.display());
+                let _ = rename(to, from);
+            }
+            Change::Move(_, _) => {}
+            Change::Command(command) => {
+                warn!("{} Rolling back with '{}'", emoji::WARN, command.join(" "));
+                let _ = Command::new(&command[0])
+                    .args(&command[1..])
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rollback::{get_first_missing_ancestor, undo, Change};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_get_first_missing_ancestor() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("a").join("b");
+        assert_eq!(
+            get_first_missing_ancestor(&path),
+            Some(temp_dir.path().join("a"))
+        );
+        assert_eq!(get_first_missing_ancestor(temp_dir.path()), None);
+    }
+
+    #[test]
+    fn test_undo() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("tools").join("gcc");
+        std::fs::create_dir_all(&path).unwrap();
+        // Commands failing, e.g. missing programs, do not stop the rollback.
+        undo(&[
+            Change::Path(temp_dir.path().join("tools")),
+            Change::Command(vec!["espup-missing-program".to_string()]),
+        ]);
+        assert!(!temp_dir.path().join("tools").exists());
+        assert!(temp_dir.path().exists());
+    }
+
+    #[test]
+    fn test_undo_move() {
+        let temp_dir = TempDir::new().unwrap();
+        let previous = temp_dir.path().join("previous").join("src");
+        let new = temp_dir.path().join("new");
+        std::fs::create_dir_all(&new).unwrap();
+        std::fs::write(new.join("lib.rs"), "").unwrap();
+        // The moved path is moved back before the directory it was moved into is removed.
+        undo(&[
+            Change::Path(temp_dir.path().join("previous")),
+            Change::Move(previous.clone(), new.join("lib.rs")),
+        ]);
+        assert!(!temp_dir.path().join("previous").exists());
+        std::fs::create_dir_all(&previous).unwrap();
+        undo(&[
+            Change::Path(new.clone()),
+            Change::Move(previous.join("lib.rs"), new.join("lib.rs")),
+        ]);
+        assert!(previous.join("lib.rs").exists());
+        assert!(!new.exists());
+    }
+}
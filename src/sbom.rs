@@ -0,0 +1,291 @@
+//! Software bill of materials of the installed environment, in the CycloneDX format, for
+//! organizations that inventory their build toolchains.
+
+use crate::{
+    config::Config,
+    error::Error,
+    list::Component,
+    toolchain::{
+        dfu_util::{DEFAULT_DFU_UTIL_REPOSITORY, DFU_UTIL_NAME},
+        espidf::DEFAULT_GIT_REPOSITORY,
+        gcc::DEFAULT_GCC_REPOSITORY,
+        gdb::{DEFAULT_GDB_REPOSITORY, RISCV_GDB_NAME, XTENSA_GDB_NAME},
+        llvm::DEFAULT_LLVM_REPOSITORY,
+        openocd::{DEFAULT_OPENOCD_REPOSITORY, OPENOCD_NAME},
+        qemu::DEFAULT_QEMU_REPOSITORY,
+        rom_elfs::{DEFAULT_ROM_ELFS_REPOSITORY, ROM_ELFS_NAME},
+        rust::DEFAULT_XTENSA_RUST_REPOSITORY,
+        sccache::{DEFAULT_SCCACHE_REPOSITORY, SCCACHE_NAME},
+    },
+    verify::{get_component_binaries, hash_file},
+};
+use serde::Serialize;
+use std::{fs::write, path::Path};
+
+/// Version of the CycloneDX specification of the documents.
+const CYCLONEDX_SPEC_VERSION: &str = "1.4";
+/// Repository of the ULP toolchains installed along with ESP-IDF.
+const ULP_REPOSITORY: &str = "https://github.com/espressif/binutils-esp32ulp";
+
+/// A CycloneDX document.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Bom {
+    bom_format: &'static str,
+    spec_version: &'static str,
+    version: u32,
+    metadata: Metadata,
+    components: Vec<BomComponent>,
+}
+
+/// Metadata of a CycloneDX document: the tool that created it.
+#[derive(Debug, Serialize)]
+struct Metadata {
+    tools: Vec<Tool>,
+}
+
+/// Tool that created a CycloneDX document.
+#[derive(Debug, Serialize)]
+struct Tool {
+    vendor: &'static str,
+    name: &'static str,
+    version: &'static str,
+}
+
+/// Component of a CycloneDX document, nesting the files of an installed component.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BomComponent {
+    #[serde(rename = "type")]
+    component_type: &'static str,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    purl: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hashes: Vec<Hash>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    external_references: Vec<ExternalReference>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    components: Vec<BomComponent>,
+}
+
+/// Hash of a file.
+#[derive(Debug, Serialize)]
+struct Hash {
+    alg: &'static str,
+    content: String,
+}
+
+/// Location a component originates from.
+#[derive(Debug, Serialize)]
+struct ExternalReference {
+    #[serde(rename = "type")]
+    reference_type: &'static str,
+    url: String,
+}
+
+/// Gets the repository a component is released from.
+pub fn get_origin(component: &Component, config: Option<&Config>) -> Option<String> {
+    let releases = match component.name.as_str() {
+        "Xtensa Rust" => DEFAULT_XTENSA_RUST_REPOSITORY,
+        "Xtensa LLVM" => DEFAULT_LLVM_REPOSITORY,
+        "ESP-IDF" => {
+            return Some(
+                config
+                    .and_then(|config| config.esp_idf_repository.clone())
+                    .unwrap_or_else(|| DEFAULT_GIT_REPOSITORY.to_string()),
+            )
+        }
+        XTENSA_GDB_NAME | RISCV_GDB_NAME => DEFAULT_GDB_REPOSITORY,
+        OPENOCD_NAME => DEFAULT_OPENOCD_REPOSITORY,
+        DFU_UTIL_NAME => DEFAULT_DFU_UTIL_REPOSITORY,
+        ROM_ELFS_NAME => DEFAULT_ROM_ELFS_REPOSITORY,
+        SCCACHE_NAME => DEFAULT_SCCACHE_REPOSITORY,
+        name if name.starts_with("qemu-") => DEFAULT_QEMU_REPOSITORY,
+        name if name.contains("ulp-elf") => ULP_REPOSITORY,
+        name if name.ends_with("-elf") => DEFAULT_GCC_REPOSITORY,
+        _ => return None,
+    };
+    Some(releases.trim_end_matches("/releases/download").to_string())
+}
+
+/// Describes an installed component, with the hashes of its binaries.
+fn get_bom_component(component: &Component, config: Option<&Config>) -> BomComponent {
+    let files = get_component_binaries(component)
+        .into_iter()
+        .filter_map(|binary| {
+            let hash = hash_file(&binary).ok()?;
+            let name = binary
+                .strip_prefix(&component.path)
+                .unwrap_or(&binary)
+                .display()
+                .to_string();
+            Some(BomComponent {
+                component_type: "file",
+                name,
+                version: None,
+                purl: None,
+                hashes: vec![Hash {
+                    alg: "SHA-256",
+                    content: hash,
+                }],
+                external_references: Vec::new(),
+                components: Vec::new(),
+            })
+        })
+        .collect();
+    BomComponent {
+        component_type: if component.name.starts_with("ESP-IDF") {
+            "framework"
+        } else {
+            "application"
+        },
+        name: component.name.clone(),
+        version: Some(component.version.clone()),
+        purl: None,
+        hashes: Vec::new(),
+        external_references: get_origin(component, config)
+            .map(|url| ExternalReference {
+                reference_type: "distribution",
+                url,
+            })
+            .into_iter()
+            .collect(),
+        components: files,
+    }
+}
+
+/// Describes the crates installed with `cargo install`, which are identified by their
+/// package URL.
+fn get_crate_components(config: Option<&Config>) -> Vec<BomComponent> {
+    let mut crates: Vec<&String> = config
+        .and_then(|config| config.extra_crates.as_ref())
+        .map(|extra_crates| extra_crates.iter().collect())
+        .unwrap_or_default();
+    crates.sort();
+    crates
+        .into_iter()
+        .map(|name| BomComponent {
+            component_type: "application",
+            name: name.clone(),
+            version: None,
+            purl: Some(format!("pkg:cargo/{name}")),
+            hashes: Vec::new(),
+            external_references: vec![ExternalReference {
+                reference_type: "distribution",
+                url: format!("https://crates.io/crates/{name}"),
+            }],
+            components: Vec::new(),
+        })
+        .collect()
+}
+
+/// Creates the CycloneDX document of the installed components.
+fn create_bom(components: &[Component], config: Option<&Config>) -> Bom {
+    Bom {
+        bom_format: "CycloneDX",
+        spec_version: CYCLONEDX_SPEC_VERSION,
+        version: 1,
+        metadata: Metadata {
+            tools: vec![Tool {
+                vendor: "esp-rs",
+                name: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+            }],
+        },
+        components: components
+            .iter()
+            .map(|component| get_bom_component(component, config))
+            .chain(get_crate_components(config))
+            .collect(),
+    }
+}
+
+/// Writes the CycloneDX document of the installed components, with their versions, the
+/// repositories they are released from and the SHA-256 hashes of their binaries.
+pub fn write_sbom(
+    output: &Path,
+    components: &[Component],
+    config: Option<&Config>,
+) -> Result<(), Error> {
+    let bom = create_bom(components, config);
+    let json = serde_json::to_string_pretty(&bom).map_err(|_| Error::FailedToSerializeJson)?;
+    write(output, json + "\n").map_err(|_| Error::FailedToWrite(output.display().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        list::Component,
+        sbom::{create_bom, get_origin},
+    };
+    use std::{
+        collections::HashSet,
+        fs::{create_dir_all, write},
+        path::PathBuf,
+    };
+    use tempfile::TempDir;
+
+    fn component(name: &str, path: PathBuf) -> Component {
+        Component {
+            name: name.to_string(),
+            version: "esp-12.2.0_20230208".to_string(),
+            path,
+        }
+    }
+
+    #[test]
+    fn test_get_origin() {
+        assert_eq!(
+            get_origin(&component("riscv32-esp-elf", PathBuf::new()), None).as_deref(),
+            Some("https://github.com/espressif/crosstool-NG")
+        );
+        assert_eq!(
+            get_origin(&component("esp32ulp-elf", PathBuf::new()), None).as_deref(),
+            Some("https://github.com/espressif/binutils-esp32ulp")
+        );
+        assert_eq!(
+            get_origin(&component("ESP-IDF", PathBuf::new()), None).as_deref(),
+            Some("https://github.com/espressif/esp-idf")
+        );
+        assert_eq!(
+            get_origin(&component("ESP-IDF (unmanaged)", PathBuf::new()), None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_create_bom() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin_path = temp_dir.path().join("riscv32-esp-elf").join("bin");
+        create_dir_all(&bin_path).unwrap();
+        write(bin_path.join("riscv32-esp-elf-gcc"), "gcc").unwrap();
+        let config = Config {
+            extra_crates: Some(HashSet::from(["ldproxy".to_string()])),
+            ..Default::default()
+        };
+        let components = [component("riscv32-esp-elf", temp_dir.path().to_path_buf())];
+
+        let bom = serde_json::to_value(create_bom(&components, Some(&config))).unwrap();
+        assert_eq!(bom["bomFormat"], "CycloneDX");
+        let gcc = &bom["components"][0];
+        assert_eq!(gcc["version"], "esp-12.2.0_20230208");
+        assert_eq!(
+            gcc["externalReferences"][0]["url"],
+            "https://github.com/espressif/crosstool-NG"
+        );
+        let file = &gcc["components"][0];
+        assert!(file["name"]
+            .as_str()
+            .unwrap()
+            .ends_with("riscv32-esp-elf-gcc"));
+        assert_eq!(
+            file["hashes"][0]["content"],
+            "94f0fa7f897ccce65856dc5a98bae4bf6957a346766613d79414c976d093aa4a"
+        );
+        assert_eq!(bom["components"][1]["purl"], "pkg:cargo/ldproxy");
+    }
+}
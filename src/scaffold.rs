@@ -0,0 +1,266 @@
+//! Scaffolding of new projects, run by `espup new`: the official templates are generated
+//! with `cargo-generate` for a chip of the installation, then pinned to its toolchain and,
+//! for `std` projects, to its ESP-IDF version.
+
+use crate::{
+    context::Context,
+    emoji,
+    error::Error,
+    packages::get_command_output,
+    smoke::get_check_target,
+    targets::Target,
+    toolchain::{espidf::parse_esp_idf_ref, rust::DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME},
+};
+use embuild::{cmd, git};
+use log::{debug, info};
+use std::{
+    fs::{read_to_string, write},
+    path::Path,
+};
+use toml::{map::Map, Value};
+
+/// Template of the `std` projects, built on ESP-IDF, and its subfolder of Cargo projects.
+const STD_TEMPLATE: [&str; 2] = ["esp-rs/esp-idf-template", "cargo"];
+/// Template of the `no_std` projects.
+const NO_STD_TEMPLATE: [&str; 1] = ["esp-rs/esp-template"];
+
+/// Project to generate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scaffold {
+    /// Name of the project, and of its directory.
+    pub name: String,
+    /// Chip the project is generated for.
+    pub target: Target,
+    /// ESP-IDF version of `std` projects, `None` for `no_std` ones.
+    pub esp_idf_version: Option<String>,
+    /// Rust toolchain the project is built with: the Xtensa one or the nightly one.
+    pub toolchain: String,
+}
+
+impl Scaffold {
+    /// Creates a project for `target`, `std` when an ESP-IDF version is given, built with the
+    /// Xtensa toolchain `xtensa_toolchain` or the nightly toolchain `nightly_version`.
+    pub fn new(
+        name: &str,
+        target: Target,
+        esp_idf_version: Option<String>,
+        xtensa_toolchain: Option<&str>,
+        nightly_version: &str,
+    ) -> Self {
+        let toolchain = if target.is_xtensa() {
+            xtensa_toolchain.unwrap_or(DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME)
+        } else {
+            nightly_version
+        };
+        Self {
+            name: name.to_string(),
+            target,
+            esp_idf_version,
+            toolchain: toolchain.to_string(),
+        }
+    }
+
+    /// Gets the arguments of `cargo` generating the project.
+    pub fn get_generate_args(&self) -> Vec<String> {
+        let template: &[&str] = match self.esp_idf_version {
+            Some(_) => &STD_TEMPLATE,
+            None => &NO_STD_TEMPLATE,
+        };
+        let mut args = vec!["generate".to_string()];
+        args.extend(template.iter().map(|arg| arg.to_string()));
+        args.extend([
+            "--name".to_string(),
+            self.name.clone(),
+            "--define".to_string(),
+            format!("mcu={}", self.target),
+            // The other placeholders keep the default values of the template.
+            "--silent".to_string(),
+        ]);
+        args
+    }
+
+    /// Pins the generated project to the toolchain and the ESP-IDF version.
+    pub fn configure(&self, project_dir: &Path) -> Result<(), Error> {
+        update_toml(&project_dir.join("rust-toolchain.toml"), |toolchain_file| {
+            get_table(toolchain_file, "toolchain")
+                .insert("channel".to_string(), Value::String(self.toolchain.clone()));
+        })?;
+        if let Some(esp_idf_version) = &self.esp_idf_version {
+            update_toml(
+                &project_dir.join(".cargo").join("config.toml"),
+                |cargo_config| {
+                    // The build of ESP-IDF only reads commits with the `commit:` prefix.
+                    let esp_idf_version = match parse_esp_idf_ref(esp_idf_version) {
+                        git::Ref::Commit(commit) => format!("commit:{commit}"),
+                        _ => esp_idf_version.clone(),
+                    };
+                    get_table(cargo_config, "env").insert(
+                        "ESP_IDF_VERSION".to_string(),
+                        Value::String(esp_idf_version),
+                    );
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Generates the project, with `cargo-generate`, in the current directory.
+    pub fn generate(&self) -> Result<(), Error> {
+        if get_command_output("cargo", &["generate", "--version"]).is_none() {
+            return Err(Error::MissingCargoGenerate);
+        }
+        let args = self.get_generate_args();
+        debug!("{} Running 'cargo {}'", emoji::DEBUG, args.join(" "));
+        info!(
+            "{} Generating the {} project '{}' for '{}'",
+            emoji::WRENCH,
+            if self.esp_idf_version.is_some() {
+                "std"
+            } else {
+                "no_std"
+            },
+            self.name,
+            self.target
+        );
+        cmd!("cargo"; args=(args)).run()?;
+        self.configure(Path::new(&self.name))
+    }
+}
+
+/// Generates the project `name` for `target`, the first installed target when `None`,
+/// pinned to the toolchains of the installation. The project is `std` when ESP-IDF is
+/// installed, unless `no_std`.
+pub fn new_project(
+    context: &mut Context,
+    name: &str,
+    target: Option<Target>,
+    no_std: bool,
+) -> Result<(), Error> {
+    let config = context.load_config()?;
+    let target = match target {
+        Some(target) if !config.targets.contains(&target) => {
+            return Err(Error::TargetNotInstalled(target.to_string()))
+        }
+        Some(target) => target,
+        None => get_check_target(&config.targets)
+            .ok_or_else(|| Error::TargetNotInstalled("esp32".to_string()))?,
+    };
+    let esp_idf_version = match (&config.esp_idf_version, &config.esp_idf_path) {
+        _ if no_std => None,
+        (Some(esp_idf_version), _) => Some(esp_idf_version.clone()),
+        // The version of a custom checkout is its last tag, e.g. `v5.1`.
+        (None, Some(esp_idf_path)) => Some(
+            cmd!("git", "describe", "--tags", "--abbrev=0"; current_dir=(esp_idf_path)).stdout()?,
+        ),
+        (None, None) => None,
+    };
+    let scaffold = Scaffold::new(
+        name,
+        target,
+        esp_idf_version,
+        config
+            .xtensa_rust
+            .as_ref()
+            .map(|xtensa_rust| xtensa_rust.name.as_str()),
+        &config.nightly_version,
+    );
+    scaffold.generate()?;
+    info!(
+        "{} Project '{}' generated, build it with 'cd {} && espup run -- cargo build'",
+        emoji::CHECK,
+        name,
+        name
+    );
+    Ok(())
+}
+
+/// Gets the table `key` of a TOML table, inserting it if missing.
+fn get_table<'a>(table: &'a mut Map<String, Value>, key: &str) -> &'a mut Map<String, Value> {
+    let value = table
+        .entry(key.to_string())
+        .or_insert_with(|| Value::Table(Map::new()));
+    if !value.is_table() {
+        *value = Value::Table(Map::new());
+    }
+    value.as_table_mut().unwrap()
+}
+
+/// Updates a TOML file with `update`, creating it if missing.
+fn update_toml(path: &Path, update: impl FnOnce(&mut Map<String, Value>)) -> Result<(), Error> {
+    let mut table = match read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents)
+            .map_err(|e| Error::InvalidProjectFile(path.display().to_string(), e.to_string()))?,
+        Err(_) => Map::new(),
+    };
+    update(&mut table);
+    let contents = toml::to_string(&table)
+        .map_err(|e| Error::InvalidProjectFile(path.display().to_string(), e.to_string()))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    write(path, contents)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{scaffold::Scaffold, targets::Target};
+    use std::fs::{create_dir_all, read_to_string, write};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scaffold() {
+        let scaffold = Scaffold::new(
+            "blinky",
+            Target::ESP32,
+            Some("v5.1".to_string()),
+            Some("esp"),
+            "nightly",
+        );
+        assert_eq!(
+            scaffold.get_generate_args(),
+            [
+                "generate",
+                "esp-rs/esp-idf-template",
+                "cargo",
+                "--name",
+                "blinky",
+                "--define",
+                "mcu=esp32",
+                "--silent"
+            ]
+        );
+        let scaffold = Scaffold::new("blinky", Target::ESP32C3, None, Some("esp"), "nightly");
+        assert_eq!(scaffold.toolchain, "nightly");
+        assert_eq!(scaffold.get_generate_args()[1], "esp-rs/esp-template");
+
+        let temp_dir = TempDir::new().unwrap();
+        let cargo_dir = temp_dir.path().join(".cargo");
+        create_dir_all(&cargo_dir).unwrap();
+        write(
+            cargo_dir.join("config.toml"),
+            "[build]\ntarget = \"xtensa-esp32-espidf\"\n\n[env]\nESP_IDF_VERSION = \"v4.4\"\n",
+        )
+        .unwrap();
+        Scaffold::new("blinky", Target::ESP32, Some("v5.1".to_string()), None, "")
+            .configure(temp_dir.path())
+            .unwrap();
+        let cargo_config = read_to_string(cargo_dir.join("config.toml")).unwrap();
+        assert!(cargo_config.contains("ESP_IDF_VERSION = \"v5.1\""));
+        Scaffold::new(
+            "blinky",
+            Target::ESP32,
+            Some("2b8d1a3".to_string()),
+            None,
+            "",
+        )
+        .configure(temp_dir.path())
+        .unwrap();
+        let cargo_config = read_to_string(cargo_dir.join("config.toml")).unwrap();
+        assert!(cargo_config.contains("ESP_IDF_VERSION = \"commit:2b8d1a3\""));
+        assert!(cargo_config.contains("target = \"xtensa-esp32-espidf\""));
+        assert!(read_to_string(temp_dir.path().join("rust-toolchain.toml"))
+            .unwrap()
+            .contains("channel = \"esp\""));
+    }
+}
@@ -0,0 +1,290 @@
+//! Replacement of the running espup binary with the latest release.
+
+use crate::{
+    context::Context,
+    emoji,
+    error::Error,
+    host_triple::{get_host_triple, HostTriple},
+    toolchain::{download_file, get_github_json},
+    verify::hash_file,
+};
+use embuild::cmd;
+use log::{debug, info};
+use miette::Result;
+use semver::{BuildMetadata, Version};
+use std::{
+    env,
+    fs::{copy, read_to_string, remove_file, rename, File},
+    path::Path,
+};
+
+/// espup releases API URL.
+const ESPUP_API_URL: &str = "https://api.github.com/repos/esp-rs/espup/releases/latest";
+/// espup release by tag API URL.
+const ESPUP_RELEASE_TAG_API_URL: &str = "https://api.github.com/repos/esp-rs/espup/releases/tags";
+/// espup releases download URL.
+pub const ESPUP_RELEASES_URL: &str = "https://github.com/esp-rs/espup/releases/download";
+
+/// Parses a `<major>.<minor>.<patch>[-<pre>][+<build>]` version, optionally prefixed by `v`.
+pub fn parse_semver(version: &str) -> Option<Version> {
+    Version::parse(version.trim().trim_start_matches('v')).ok()
+}
+
+/// Gets the latest released version of espup.
+pub fn get_latest_version(context: &Context) -> Result<String> {
+    let json = get_github_json(context, ESPUP_API_URL)?;
+    let version = json["tag_name"]
+        .as_str()
+        .ok_or(Error::FailedToSerializeJson)?
+        .trim_start_matches('v')
+        .to_string();
+    debug!("{} Latest espup version: {}", emoji::DEBUG, version);
+    Ok(version)
+}
+
+/// Returns whether `latest` is newer than `current`, following the SemVer precedence: a
+/// release is newer than the pre-releases of its version, and build metadata is ignored.
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    match (parse_semver(latest), parse_semver(current)) {
+        (Some(mut latest), Some(mut current)) => {
+            // Unlike the pre-release, the build metadata does not take part in the precedence.
+            latest.build = BuildMetadata::EMPTY;
+            current.build = BuildMetadata::EMPTY;
+            latest > current
+        }
+        _ => false,
+    }
+}
+
+/// Name of the espup binary for the host.
+fn get_binary_name(host_triple: &HostTriple) -> &'static str {
+    match host_triple {
+        HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => "espup.exe",
+        _ => "espup",
+    }
+}
+
+/// Gets the SHA-256 checksum published for a release asset: the digest GitHub computes for
+/// it or, for releases predating it, the `<asset>.sha256` asset.
+fn get_published_checksum(
+    context: &Context,
+    release: &serde_json::Value,
+    asset_name: &str,
+) -> Result<String> {
+    let assets = release["assets"].as_array().cloned().unwrap_or_default();
+    let find_asset = |name: &str| {
+        assets
+            .iter()
+            .find(|asset| asset["name"].as_str() == Some(name))
+            .cloned()
+    };
+    if let Some(digest) = find_asset(asset_name)
+        .as_ref()
+        .and_then(|asset| asset["digest"].as_str())
+        .and_then(|digest| digest.strip_prefix("sha256:"))
+    {
+        return Ok(digest.to_lowercase());
+    }
+    let checksum_name = format!("{asset_name}.sha256");
+    let checksum_url = find_asset(&checksum_name)
+        .as_ref()
+        .and_then(|asset| asset["browser_download_url"].as_str())
+        .map(str::to_string)
+        .ok_or_else(|| Error::MissingReleaseChecksum(asset_name.to_string()))?;
+    let download_dir = tempfile::tempdir_in(context.temp_dir()).map_err(Error::from)?;
+    let checksum_file = download_file(
+        context,
+        checksum_url,
+        &checksum_name,
+        &download_dir.path().display().to_string(),
+        false,
+    )?;
+    parse_checksum(&read_to_string(checksum_file).map_err(Error::from)?)
+        .ok_or_else(|| Error::MissingReleaseChecksum(asset_name.to_string()).into())
+}
+
+/// Parses the checksum of a `sha256sum` line, e.g. `<hash>  espup-x86_64-unknown-linux-gnu.zip`.
+fn parse_checksum(contents: &str) -> Option<String> {
+    contents
+        .split_whitespace()
+        .next()
+        .filter(|hash| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_lowercase)
+}
+
+/// Updates espup to the latest release, unless it is already up to date.
+pub fn update_espup(context: &Context, default_host: Option<String>) -> Result<()> {
+    info!("{} Updating espup", emoji::DISC);
+    let host_triple = get_host_triple(default_host)?;
+    let current_version = env!("CARGO_PKG_VERSION");
+    let latest_version = get_latest_version(context)?;
+    if !is_newer(&latest_version, current_version) {
+        info!(
+            "{} espup '{}' is already up to date",
+            emoji::CHECK,
+            current_version
+        );
+        return Ok(());
+    }
+
+    info!(
+        "{} Updating espup from '{}' to '{}'",
+        emoji::WRENCH,
+        current_version,
+        latest_version
+    );
+    self_update(context, &latest_version, &host_triple)?;
+
+    info!("{} espup successfully updated!", emoji::CHECK);
+    Ok(())
+}
+
+/// Downloads the given version of espup and replaces the running executable with it.
+pub fn self_update(context: &Context, version: &str, host_triple: &HostTriple) -> Result<()> {
+    let current_exe = env::current_exe().map_err(Error::from)?;
+    let install_dir = current_exe
+        .parent()
+        .ok_or_else(|| Error::FailedToSelfUpdate(current_exe.display().to_string()))?;
+    let asset_name = format!("espup-{host_triple}.zip");
+    let release = get_github_json(context, &format!("{ESPUP_RELEASE_TAG_API_URL}/v{version}"))?;
+    let checksum = get_published_checksum(context, &release, &asset_name)?;
+
+    let download_dir = tempfile::tempdir_in(context.temp_dir()).map_err(Error::from)?;
+    let url = format!("{ESPUP_RELEASES_URL}/v{version}/{asset_name}");
+    let archive = download_file(
+        context,
+        url,
+        &asset_name,
+        &download_dir.path().display().to_string(),
+        false,
+    )?;
+    info!("{} Verifying the checksum of {}", emoji::WRENCH, asset_name);
+    let hash = hash_file(Path::new(&archive)).map_err(Error::from)?;
+    if hash != checksum {
+        return Err(Error::ReleaseChecksumMismatch(asset_name, hash, checksum).into());
+    }
+    zip::ZipArchive::new(File::open(&archive).map_err(Error::from)?)
+        .and_then(|mut zipfile| zipfile.extract(download_dir.path()))
+        .map_err(|_| Error::FailedToExtract(archive.clone()))?;
+
+    let new_exe = download_dir.path().join(get_binary_name(host_triple));
+    verify_binary(&new_exe, version)?;
+
+    // Copy the new binary next to the current one, so the final rename is atomic.
+    let staged_exe = install_dir.join(format!(".{}.new", get_binary_name(host_triple)));
+    copy(&new_exe, &staged_exe)
+        .map_err(|_| Error::FailedToSelfUpdate(staged_exe.display().to_string()))?;
+    replace_executable(&staged_exe, &current_exe)?;
+    Ok(())
+}
+
+/// Verifies that the downloaded binary runs and reports the expected version.
+fn verify_binary(path: &Path, version: &str) -> Result<(), Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    info!("{} Verifying downloaded binary", emoji::WRENCH);
+    let output = cmd!(path, "--version")
+        .stdout()
+        .map_err(|_| Error::FailedToSelfUpdate(path.display().to_string()))?;
+    if !output.ends_with(version) {
+        return Err(Error::FailedToSelfUpdate(format!(
+            "{}: unexpected version '{output}'",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Atomically replaces the current executable with the staged one.
+fn replace_executable(staged_exe: &Path, current_exe: &Path) -> Result<(), Error> {
+    // Running executables cannot be overwritten on Windows, but they can be renamed.
+    #[cfg(windows)]
+    let old_exe = {
+        let old_exe = current_exe.with_extension("old.exe");
+        if old_exe.exists() {
+            remove_file(&old_exe)?;
+        }
+        rename(current_exe, &old_exe)
+            .map_err(|_| Error::FailedToSelfUpdate(current_exe.display().to_string()))?;
+        old_exe
+    };
+    if let Err(e) = rename(staged_exe, current_exe) {
+        debug!("{} Failed to replace executable: {}", emoji::DEBUG, e);
+        remove_file(staged_exe).ok();
+        // The running executable is moved back, so that espup remains installed.
+        #[cfg(windows)]
+        if let Err(e) = rename(&old_exe, current_exe) {
+            debug!(
+                "{} Failed to restore executable from '{}': {}",
+                emoji::DEBUG,
+                old_exe.display(),
+                e
+            );
+        }
+        return Err(Error::FailedToSelfUpdate(current_exe.display().to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        context::Context,
+        self_update::{get_published_checksum, is_newer, parse_checksum, parse_semver},
+    };
+    use semver::Version;
+
+    #[test]
+    fn test_parse_semver() {
+        assert_eq!(parse_semver("0.2.5"), Some(Version::new(0, 2, 5)));
+        assert_eq!(parse_semver("v1.10.0"), Some(Version::new(1, 10, 0)));
+        assert_eq!(parse_semver("0.2.5-dev").unwrap().pre.as_str(), "dev");
+        assert!(parse_semver("0.2").is_none());
+        assert!(parse_semver("0.2.5.1").is_none());
+        assert!(parse_semver("latest").is_none());
+    }
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("0.2.6", "0.2.5-dev"));
+        assert!(is_newer("1.0.0", "0.99.99"));
+        assert!(is_newer("0.2.5", "0.2.5-dev"));
+        assert!(is_newer("0.2.5-rc.2", "0.2.5-rc.1"));
+        assert!(!is_newer("0.2.5-dev", "0.2.5"));
+        assert!(!is_newer("0.2.5+build", "0.2.5"));
+        assert!(!is_newer("0.2.4", "0.2.5"));
+        assert!(!is_newer("invalid", "0.2.5"));
+    }
+
+    #[test]
+    fn test_published_checksum() {
+        let hash = "ab".repeat(32);
+        assert_eq!(
+            parse_checksum(&format!("{hash}  espup-x86_64-unknown-linux-gnu.zip\n")),
+            Some(hash.clone())
+        );
+        assert!(parse_checksum("not-a-hash espup.zip").is_none());
+
+        let context = Context::default();
+        let release = serde_json::json!({
+            "assets": [
+                {
+                    "name": "espup-x86_64-unknown-linux-gnu.zip",
+                    "digest": format!("sha256:{}", hash.to_uppercase()),
+                },
+                { "name": "espup-aarch64-apple-darwin.zip" },
+            ]
+        });
+        assert_eq!(
+            get_published_checksum(&context, &release, "espup-x86_64-unknown-linux-gnu.zip")
+                .unwrap(),
+            hash
+        );
+        assert!(
+            get_published_checksum(&context, &release, "espup-aarch64-apple-darwin.zip").is_err()
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Smoke test of an installation, run by `espup install --check`: a hello world project is
+//! generated for one of the installed targets and built with the installed toolchains and
+//! the environment of the export file, so that broken installations are caught right away.
+
+use crate::{
+    config::Config,
+    context::Context,
+    emoji,
+    environment::{get_command, get_environment},
+    error::Error,
+    install::get_export_file,
+    targets::Target,
+    toolchain::rust::DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME,
+};
+use log::{debug, info};
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    fs::{create_dir_all, write},
+    path::Path,
+};
+use strum::IntoEnumIterator;
+use tempfile::TempDir;
+
+/// Name of the generated project.
+const PROJECT_NAME: &str = "espup-check";
+/// Version of `esp-idf-sys` used by the `std` project.
+const ESP_IDF_SYS_VERSION: &str = "0.33";
+/// Version of `embuild` used by the build script of the `std` project.
+const EMBUILD_VERSION: &str = "0.31";
+
+/// Gets the target the project is built for: the first installed one, in the order of
+/// [`Target`].
+pub fn get_check_target(targets: &HashSet<Target>) -> Option<Target> {
+    Target::iter().find(|target| targets.contains(target))
+}
+
+/// Gets the `Cargo.toml` of the project.
+fn format_manifest(std: bool) -> String {
+    let mut manifest = format!(
+        "[package]\nname = \"{PROJECT_NAME}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n"
+    );
+    if std {
+        manifest.push_str(&format!(
+            "[dependencies]\nesp-idf-sys = {{ version = \"{ESP_IDF_SYS_VERSION}\", features = [\"binstart\"] }}\n\n[build-dependencies]\nembuild = \"{EMBUILD_VERSION}\"\n"
+        ));
+    } else {
+        // Bare metal binaries need the linker scripts of a HAL, so only the library is built.
+        manifest.push_str("[lib]\npath = \"src/lib.rs\"\n");
+    }
+    manifest
+}
+
+/// Writes the hello world project, `std` when ESP-IDF is installed, into `project_dir`.
+fn write_project(project_dir: &Path, target: Target, std: bool) -> Result<(), Error> {
+    let src_dir = project_dir.join("src");
+    create_dir_all(&src_dir)?;
+    write(project_dir.join("Cargo.toml"), format_manifest(std))?;
+    if std {
+        write(
+            project_dir.join("build.rs"),
+            "fn main() {\n    embuild::espidf::sysenv::output();\n}\n",
+        )?;
+        write(
+            src_dir.join("main.rs"),
+            "fn main() {\n    esp_idf_sys::link_patches();\n    println!(\"Hello, world!\");\n}\n",
+        )?;
+        let cargo_dir = project_dir.join(".cargo");
+        create_dir_all(&cargo_dir)?;
+        write(
+            cargo_dir.join("config.toml"),
+            format!(
+                "[target.{}]\nlinker = \"ldproxy\"\nrustflags = [\"--cfg\", \"espidf_time64\"]\n\n[env]\nMCU = \"{}\"\n",
+                target.std_rust_target(),
+                target
+            ),
+        )?;
+    } else {
+        write(
+            src_dir.join("lib.rs"),
+            "#![no_std]\n\npub fn hello() -> &'static str {\n    \"Hello, world!\"\n}\n",
+        )?;
+    }
+    Ok(())
+}
+
+/// Gets the arguments of `cargo` building the project for `target`.
+fn get_build_args(config: &Config, target: Target, std: bool) -> Vec<String> {
+    let toolchain = if target.is_xtensa() {
+        config
+            .xtensa_rust
+            .as_ref()
+            .map_or(DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME, |xtensa_rust| {
+                &xtensa_rust.name
+            })
+    } else {
+        &config.nightly_version
+    };
+    let mut args = vec![
+        format!("+{toolchain}"),
+        "build".to_string(),
+        "--target".to_string(),
+    ];
+    if std {
+        args.push(target.std_rust_target().to_string());
+        args.push("-Zbuild-std=std,panic_abort".to_string());
+    } else {
+        args.push(target.rust_target().to_string());
+        // Only the RISC-V bare metal targets are distributed by rustup.
+        if target.is_xtensa() {
+            args.push("-Zbuild-std=core".to_string());
+        }
+    }
+    args
+}
+
+/// Builds a hello world project for one of the installed targets, `std` when ESP-IDF is
+/// installed and `no_std` otherwise, with the environment of the installation.
+pub fn run_smoke_test(context: &Context, config: &Config) -> Result<(), Error> {
+    let target = match get_check_target(&config.targets) {
+        Some(target) => target,
+        None => return Ok(()),
+    };
+    let std = config.esp_idf_version.is_some() || config.esp_idf_path.is_some();
+    info!(
+        "{} Checking the installation by building a {} hello world for '{}'",
+        emoji::WRENCH,
+        if std { "std" } else { "no_std" },
+        target
+    );
+    let project_dir = TempDir::new_in(context.temp_dir())?;
+    write_project(project_dir.path(), target, std)?;
+    let environment = get_environment(&get_export_file(
+        context.env.as_deref(),
+        config.export_file.clone(),
+    )?)?;
+    let args = get_build_args(config, target, std);
+    debug!("{} Running 'cargo {}'", emoji::DEBUG, args.join(" "));
+    let status = get_command(OsStr::new("cargo"), &environment)?
+        .args(&args)
+        .current_dir(project_dir.path())
+        .status()
+        .map_err(|e| Error::FailedToRunCommand("cargo".to_string(), e.to_string()))?;
+    if !status.success() {
+        return Err(Error::SmokeTestFailed(target.to_string()));
+    }
+    info!(
+        "{} The hello world for '{}' was successfully built",
+        emoji::CHECK,
+        target
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        smoke::{get_build_args, get_check_target, write_project},
+        targets::Target,
+    };
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_smoke_project() {
+        let targets: HashSet<Target> = [Target::ESP32C3, Target::ESP32S3].into_iter().collect();
+        assert_eq!(get_check_target(&targets), Some(Target::ESP32S3));
+        assert_eq!(get_check_target(&HashSet::new()), None);
+
+        let config = Config {
+            nightly_version: "nightly-2023-01-01".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            get_build_args(&config, Target::ESP32C3, false),
+            [
+                "+nightly-2023-01-01",
+                "build",
+                "--target",
+                "riscv32imc-unknown-none-elf"
+            ]
+        );
+        assert_eq!(
+            get_build_args(&config, Target::ESP32, true),
+            [
+                "+esp",
+                "build",
+                "--target",
+                "xtensa-esp32-espidf",
+                "-Zbuild-std=std,panic_abort"
+            ]
+        );
+
+        let temp_dir = TempDir::new().unwrap();
+        write_project(temp_dir.path(), Target::ESP32, true).unwrap();
+        assert!(temp_dir.path().join("build.rs").exists());
+        assert!(temp_dir.path().join(".cargo").join("config.toml").exists());
+    }
+}
@@ -0,0 +1,128 @@
+//! Cloning ESP-IDF over SSH, e.g. from a corporate mirror only reachable through
+//! `git@mirror.example.com:espressif/esp-idf.git`. git authenticates through the SSH agent
+//! (`SSH_AUTH_SOCK`), inherited by every git command, or the keys of `~/.ssh`. Runs that
+//! cannot prompt never wait for a passphrase or a host key confirmation, and fail with a
+//! hint instead.
+
+use crate::{emoji, error::Error, interactive::is_interactive};
+use embuild::cmd;
+use log::{debug, warn};
+use std::{env, process::Stdio};
+
+/// Options of the SSH client spawned by git when the user cannot be prompted: unknown host
+/// keys and encrypted keys without an agent fail instead of prompting.
+const NON_INTERACTIVE_SSH_COMMAND: &str = "ssh -o BatchMode=yes";
+
+/// Whether a git URL is cloned over SSH: `ssh://[user@]host/path` or the scp-like
+/// `[user@]host:path`.
+pub fn is_ssh_url(url: &str) -> bool {
+    if url.starts_with("ssh://") || url.starts_with("git+ssh://") {
+        return true;
+    }
+    if url.contains("://") {
+        return false;
+    }
+    match url.find(':') {
+        // A single letter before the colon is a Windows drive, e.g. `C:\esp-idf`.
+        Some(colon) => colon > 1 && !url[..colon].contains(['/', '\\']),
+        None => false,
+    }
+}
+
+/// Gets the host of an SSH URL, e.g. `mirror.example.com`.
+pub fn get_host(url: &str) -> Option<&str> {
+    let address = url
+        .strip_prefix("ssh://")
+        .or_else(|| url.strip_prefix("git+ssh://"))
+        .unwrap_or(url);
+    let address = address.split(['/', ':']).next()?;
+    let host = address.rsplit('@').next()?;
+    (!host.is_empty()).then_some(host)
+}
+
+/// Gets the environment variables configuring the SSH client spawned by git, when the user
+/// cannot be prompted. A client configured with `GIT_SSH_COMMAND` or `GIT_SSH` is kept.
+pub fn get_envs() -> Vec<(&'static str, &'static str)> {
+    if is_interactive()
+        || env::var_os("GIT_SSH_COMMAND").is_some()
+        || env::var_os("GIT_SSH").is_some()
+    {
+        return Vec::new();
+    }
+    vec![("GIT_SSH_COMMAND", NON_INTERACTIVE_SSH_COMMAND)]
+}
+
+/// Checks that the repository at the SSH URL `url` can be read before cloning it, failing
+/// with the reason reported by SSH, e.g. an unknown host key or a denied key.
+pub fn check_access(url: &str) -> Result<(), Error> {
+    if !is_ssh_url(url) {
+        return Ok(());
+    }
+    if env::var_os("SSH_AUTH_SOCK").is_none() {
+        debug!(
+            "{} No SSH agent is running, git uses the keys of ~/.ssh",
+            emoji::DEBUG
+        );
+    }
+    debug!("{} Checking the access to '{}'", emoji::DEBUG, url);
+    let output = cmd!("git", "ls-remote", "--exit-code", url, "HEAD"; envs=(get_envs()))
+        .into_inner()
+        .stdin(if is_interactive() {
+            Stdio::inherit()
+        } else {
+            Stdio::null()
+        })
+        .output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reason = stderr
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("unknown error")
+        .to_string();
+    if reason.contains("Host key verification failed") {
+        warn!(
+            "{} Add the host key of '{}' to ~/.ssh/known_hosts, e.g. with 'ssh-keyscan {} >> ~/.ssh/known_hosts' after checking its fingerprint",
+            emoji::WARN,
+            get_host(url).unwrap_or(url),
+            get_host(url).unwrap_or(url)
+        );
+    }
+    Err(Error::UnreachableSshRepository(url.to_string(), reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ssh::{get_host, is_ssh_url};
+
+    #[test]
+    fn test_is_ssh_url() {
+        assert!(is_ssh_url("git@github.com:espressif/esp-idf.git"));
+        assert!(is_ssh_url(
+            "ssh://git@mirror.example.com:2222/espressif/esp-idf"
+        ));
+        assert!(is_ssh_url("mirror.example.com:espressif/esp-idf"));
+        assert!(!is_ssh_url("https://github.com/espressif/esp-idf"));
+        assert!(!is_ssh_url("C:\\esp-idf"));
+        assert!(!is_ssh_url("./esp-idf:v5.1"));
+    }
+
+    #[test]
+    fn test_get_host() {
+        assert_eq!(
+            get_host("git@github.com:espressif/esp-idf.git"),
+            Some("github.com")
+        );
+        assert_eq!(
+            get_host("ssh://git@mirror.example.com:2222/espressif/esp-idf"),
+            Some("mirror.example.com")
+        );
+        assert_eq!(
+            get_host("mirror.example.com:esp-idf"),
+            Some("mirror.example.com")
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Content-addressed store of the extracted archives: the directories extracted from an
+//! archive are indexed by its SHA-256 hash, so that extracting the same archive again, e.g.
+//! the same toolchain pinned by several versions or installed under another name, hard links
+//! the files of the existing directory instead of storing them twice. Removing either
+//! directory keeps the other one intact.
+
+use crate::{context::Context, emoji, toolchain::reflink::reflink};
+use log::debug;
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{hard_link, read_dir, read_to_string, remove_file, rename, write},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Name of the index of the store, in the tools path.
+const STORE_FILE_NAME: &str = "store.json";
+
+/// Serializes the updates of the index, as archives can be extracted concurrently.
+static INDEX_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Directories extracted from each archive, by the hash of the archive.
+type Index = BTreeMap<String, Vec<PathBuf>>;
+
+/// Reader hashing what is read through it, e.g. an archive while it is extracted.
+pub struct HashingReader<R> {
+    reader: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashingReader<R> {
+    /// Hashes what is read from `reader`.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Gets the hash of what was read, reading the rest of the contents first.
+    pub fn finish(mut self) -> io::Result<String> {
+        io::copy(&mut self, &mut io::sink())?;
+        Ok(format!("{:x}", self.hasher.finalize()))
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Gets the path of the index of the store, which covers the directories of the tools path.
+fn get_index_path(context: &Context, directory: &Path) -> Option<PathBuf> {
+    let tools_path = &context.tools_path;
+    directory
+        .starts_with(tools_path)
+        .then(|| tools_path.join(STORE_FILE_NAME))
+}
+
+/// Reads the index of the store, empty if it does not exist.
+fn read_index(path: &Path) -> Index {
+    read_to_string(path)
+        .ok()
+        .and_then(|index| serde_json::from_str(&index).ok())
+        .unwrap_or_default()
+}
+
+/// Replaces the files of `directory` with hard links to the identical files of `source`, or
+/// with reflinks when they cannot be hard linked, returning the number of files linked.
+/// Files that can be neither, e.g. across filesystems, are kept.
+fn link_files(source: &Path, directory: &Path) -> usize {
+    let mut linked = 0;
+    for entry in read_dir(directory).into_iter().flatten().flatten() {
+        let path = entry.path();
+        let source_path = source.join(entry.file_name());
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+        if file_type.is_dir() {
+            linked += link_files(&source_path, &path);
+            continue;
+        }
+        let same_size = match (path.metadata(), source_path.symlink_metadata()) {
+            (Ok(metadata), Ok(source_metadata)) => {
+                file_type.is_file()
+                    && source_metadata.is_file()
+                    && metadata.len() == source_metadata.len()
+            }
+            _ => false,
+        };
+        if !same_size {
+            continue;
+        }
+        let mut temporary_name = entry.file_name();
+        temporary_name.push(".store-link");
+        let temporary_path = path.with_file_name(temporary_name);
+        if hard_link(&source_path, &temporary_path).is_ok()
+            || reflink(&source_path, &temporary_path).is_ok()
+        {
+            if remove_file(&path).is_ok() && rename(&temporary_path, &path).is_ok() {
+                linked += 1;
+            } else {
+                let _ = remove_file(&temporary_path);
+            }
+        }
+    }
+    linked
+}
+
+/// Hard links the files of `directory`, freshly extracted from the archive with `hash`, to
+/// the ones of a directory extracted from the same archive before, if any.
+pub fn link_identical(context: &Context, hash: &str, directory: &Path, output_directory: &Path) {
+    let index_path = match get_index_path(context, output_directory) {
+        Some(index_path) => index_path,
+        None => return,
+    };
+    let _lock = INDEX_LOCK.lock().unwrap();
+    let index = read_index(&index_path);
+    let source = index.get(hash).and_then(|directories| {
+        directories
+            .iter()
+            .find(|source| source.as_path() != output_directory && source.is_dir())
+    });
+    if let Some(source) = source {
+        let linked = link_files(source, directory);
+        debug!(
+            "{} Linked {} files of '{}' to the identical ones of '{}'",
+            emoji::DEBUG,
+            linked,
+            output_directory.display(),
+            source.display()
+        );
+    }
+}
+
+/// Records that `directory` was extracted from the archive with `hash`, forgetting the
+/// directories that no longer exist.
+pub fn record(context: &Context, hash: &str, directory: &Path) {
+    let index_path = match get_index_path(context, directory) {
+        Some(index_path) => index_path,
+        None => return,
+    };
+    let _lock = INDEX_LOCK.lock().unwrap();
+    let mut index = read_index(&index_path);
+    for directories in index.values_mut() {
+        directories.retain(|directory| directory.is_dir());
+    }
+    let directories = index.entry(hash.to_string()).or_default();
+    if !directories.iter().any(|known| known == directory) {
+        directories.push(directory.to_path_buf());
+    }
+    index.retain(|_, directories| !directories.is_empty());
+    if let Ok(contents) = serde_json::to_string_pretty(&index) {
+        if write(&index_path, contents).is_err() {
+            debug!(
+                "{} Failed to update the store index '{}'",
+                emoji::DEBUG,
+                index_path.display()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::store::{link_files, HashingReader};
+    use std::{
+        fs::{create_dir_all, read_to_string, write},
+        io::Read,
+    };
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_hashing_reader() {
+        let mut reader = HashingReader::new("gcc".as_bytes());
+        let mut start = [0; 1];
+        reader.read_exact(&mut start).unwrap();
+        assert_eq!(
+            reader.finish().unwrap(),
+            "94f0fa7f897ccce65856dc5a98bae4bf6957a346766613d79414c976d093aa4a"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_link_files() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("8.4.0");
+        let directory = temp_dir.path().join("8_4_0");
+        for dir in [&source, &directory] {
+            create_dir_all(dir.join("bin")).unwrap();
+            write(dir.join("bin").join("gcc"), "gcc").unwrap();
+        }
+        write(source.join("README"), "source").unwrap();
+        write(directory.join("README"), "other").unwrap();
+        write(directory.join("LICENSE"), "license").unwrap();
+
+        assert_eq!(link_files(&source, &directory), 1);
+        assert_eq!(
+            directory.join("bin").join("gcc").metadata().unwrap().ino(),
+            source.join("bin").join("gcc").metadata().unwrap().ino()
+        );
+        // Files missing from the source are kept.
+        assert_eq!(
+            read_to_string(directory.join("LICENSE")).unwrap(),
+            "license"
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Summary printed at the end of an installation: the version and size of each installed
+//! component, and how long its download and its setup took, so that slow mirrors or steps
+//! can be reported precisely.
+
+use crate::list::{format_size, Component};
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Time spent installing a component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timing {
+    /// Name of the component, as in the hooks, e.g. `gcc`.
+    pub component: String,
+    /// Paths written by the component.
+    pub paths: Vec<PathBuf>,
+    /// Time spent downloading, archives being extracted while they are downloaded.
+    pub download: Duration,
+    /// Time spent installing the component, downloads included.
+    pub total: Duration,
+}
+
+/// A row of the summary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    /// Name of the component.
+    pub component: String,
+    /// Installed versions, `-` when unknown.
+    pub version: String,
+    /// Disk usage, `-` when unknown.
+    pub size: String,
+    /// Time spent downloading and extracting.
+    pub download: String,
+    /// Time spent on the remaining steps.
+    pub setup: String,
+}
+
+/// Timings of an installation, kept in its [`crate::context::Context`].
+#[derive(Debug, Default)]
+pub struct Summary {
+    /// Paths written and time spent downloading by the component being installed.
+    downloads: Mutex<Vec<(PathBuf, Duration)>>,
+    /// Timings of the components installed so far, in installation order.
+    timings: Mutex<Vec<Timing>>,
+}
+
+impl Summary {
+    /// Records a path written by the component being installed, along with the time spent
+    /// downloading it.
+    pub fn record(&self, path: &Path, download: Duration) {
+        self.downloads
+            .lock()
+            .unwrap()
+            .push((path.to_path_buf(), download));
+    }
+
+    /// Installs a component with `install`, recording how long it took.
+    pub fn measure<T>(&self, component: &str, install: impl FnOnce() -> T) -> T {
+        self.downloads.lock().unwrap().clear();
+        let start = Instant::now();
+        let result = install();
+        let total = start.elapsed();
+        let downloads: Vec<(PathBuf, Duration)> =
+            self.downloads.lock().unwrap().drain(..).collect();
+        self.timings.lock().unwrap().push(Timing {
+            component: component.to_string(),
+            paths: downloads.iter().map(|(path, _)| path.clone()).collect(),
+            download: downloads.iter().map(|(_, download)| *download).sum(),
+            total,
+        });
+        result
+    }
+
+    /// Takes the timings of the components installed so far.
+    pub fn take_timings(&self) -> Vec<Timing> {
+        self.timings.lock().unwrap().drain(..).collect()
+    }
+}
+/// Formats a duration, e.g. `1m 05s` or `3.2s`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 60 {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{:.1}s", duration.as_secs_f64())
+    }
+}
+
+/// Gets the rows of the summary, matching the paths written by each component with the
+/// installed ones.
+pub fn get_rows(timings: &[Timing], installed: &[Component]) -> Vec<Row> {
+    timings
+        .iter()
+        .map(|timing| {
+            let components: Vec<&Component> = installed
+                .iter()
+                .filter(|component| {
+                    timing.paths.iter().any(|path| {
+                        path.starts_with(&component.path) || component.path.starts_with(path)
+                    })
+                })
+                .collect();
+            let mut versions: Vec<&str> = components
+                .iter()
+                .map(|component| component.version.as_str())
+                .collect();
+            versions.dedup();
+            let (version, size) = if components.is_empty() {
+                ("-".to_string(), "-".to_string())
+            } else {
+                (
+                    versions.join(", "),
+                    format_size(components.iter().map(|component| component.size()).sum()),
+                )
+            };
+            Row {
+                component: timing.component.clone(),
+                version,
+                size,
+                download: format_duration(timing.download),
+                setup: format_duration(timing.total.saturating_sub(timing.download)),
+            }
+        })
+        .collect()
+}
+
+/// Formats the rows as a table, one line per component.
+pub fn format_table(rows: &[Row]) -> Vec<String> {
+    let header = Row {
+        component: "Component".to_string(),
+        version: "Version".to_string(),
+        size: "Size".to_string(),
+        download: "Download".to_string(),
+        setup: "Setup".to_string(),
+    };
+    let rows: Vec<&Row> = [&header].into_iter().chain(rows).collect();
+    let width = |column: fn(&Row) -> &str| {
+        rows.iter()
+            .map(|row| column(row).len())
+            .max()
+            .unwrap_or_default()
+    };
+    let widths = [
+        width(|row| &row.component),
+        width(|row| &row.version),
+        width(|row| &row.size),
+        width(|row| &row.download),
+    ];
+    rows.iter()
+        .map(|row| {
+            format!(
+                "{:w0$}  {:w1$}  {:w2$}  {:w3$}  {}",
+                row.component,
+                row.version,
+                row.size,
+                row.download,
+                row.setup,
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3],
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        list::Component,
+        summary::{format_duration, format_table, get_rows, Timing},
+    };
+    use std::{fs::write, time::Duration};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(3240)), "3.2s");
+        assert_eq!(format_duration(Duration::from_secs(65)), "1m 05s");
+    }
+
+    #[test]
+    fn test_get_rows() {
+        let temp_dir = TempDir::new().unwrap();
+        write(temp_dir.path().join("gcc"), "gcc").unwrap();
+        let installed = vec![Component {
+            name: "xtensa-esp32-elf".to_string(),
+            version: "esp-2021r2-patch5-8_4_0".to_string(),
+            path: temp_dir.path().to_path_buf(),
+        }];
+        let timings = vec![
+            Timing {
+                component: "gcc".to_string(),
+                paths: vec![temp_dir.path().join("xtensa-esp32-elf")],
+                download: Duration::from_secs(2),
+                total: Duration::from_secs(3),
+            },
+            Timing {
+                component: "riscv-target".to_string(),
+                paths: Vec::new(),
+                download: Duration::ZERO,
+                total: Duration::from_secs(70),
+            },
+        ];
+        let rows = get_rows(&timings, &installed);
+        assert_eq!(rows[0].version, "esp-2021r2-patch5-8_4_0");
+        assert_eq!(rows[0].size, "3 B");
+        assert_eq!(rows[0].download, "2.0s");
+        assert_eq!(rows[0].setup, "1.0s");
+        assert_eq!(rows[1].version, "-");
+        assert_eq!(rows[1].setup, "1m 10s");
+        let table = format_table(&rows);
+        assert_eq!(table.len(), 3);
+        assert!(table[0].starts_with("Component     Version"));
+    }
+}
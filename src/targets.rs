@@ -23,27 +23,134 @@ pub enum Target {
     ESP32C2,
     /// RISC-V based single core
     ESP32C3,
+    /// RISC-V based single core
+    ESP32C6,
+    /// RISC-V based single core
+    ESP32H2,
+    /// RISC-V based dual core
+    ESP32P4,
+}
+
+impl Target {
+    /// Returns true if the target has an Xtensa core.
+    pub fn is_xtensa(&self) -> bool {
+        matches!(self, Target::ESP32 | Target::ESP32S2 | Target::ESP32S3)
+    }
+
+    /// Returns true if the target has a RISC-V core.
+    pub fn is_riscv(&self) -> bool {
+        !self.is_xtensa()
+    }
+
+    /// Returns the bare metal Rust target triple.
+    pub fn rust_target(&self) -> &'static str {
+        match self {
+            Target::ESP32 => "xtensa-esp32-none-elf",
+            Target::ESP32S2 => "xtensa-esp32s2-none-elf",
+            Target::ESP32S3 => "xtensa-esp32s3-none-elf",
+            Target::ESP32C2 | Target::ESP32C3 => "riscv32imc-unknown-none-elf",
+            Target::ESP32C6 | Target::ESP32H2 => "riscv32imac-unknown-none-elf",
+            Target::ESP32P4 => "riscv32imafc-unknown-none-elf",
+        }
+    }
+
+    /// Returns the ESP-IDF (`std`) Rust target triple. These targets are not distributed by
+    /// rustup, they are built with `-Zbuild-std` from the `rust-src` component.
+    pub fn std_rust_target(&self) -> &'static str {
+        match self {
+            Target::ESP32 => "xtensa-esp32-espidf",
+            Target::ESP32S2 => "xtensa-esp32s2-espidf",
+            Target::ESP32S3 => "xtensa-esp32s3-espidf",
+            Target::ESP32C2 | Target::ESP32C3 => "riscv32imc-esp-espidf",
+            Target::ESP32C6 | Target::ESP32H2 => "riscv32imac-esp-espidf",
+            Target::ESP32P4 => "riscv32imafc-esp-espidf",
+        }
+    }
+
+    /// Returns the minimum ESP-IDF version, as `(major, minor)`, supporting the target.
+    pub fn min_esp_idf_version(&self) -> (u64, u64) {
+        match self {
+            Target::ESP32 => (4, 0),
+            Target::ESP32S2 => (4, 2),
+            Target::ESP32C3 => (4, 3),
+            Target::ESP32S3 => (4, 4),
+            Target::ESP32C2 => (5, 0),
+            Target::ESP32C6 | Target::ESP32H2 => (5, 1),
+            Target::ESP32P4 => (5, 3),
+        }
+    }
+}
+
+/// Parses the `(major, minor)` version of an ESP-IDF version argument, e.g. `v5.1`,
+/// `tag:v5.1.2` or `branch:release/v4.4`. Branches and commits that are not named after a
+/// version, e.g. `master`, have no version.
+pub fn parse_esp_idf_version(esp_idf_version: &str) -> Option<(u64, u64)> {
+    let version = esp_idf_version.rsplit([':', '/']).next()?;
+    let mut numbers = version
+        .strip_prefix('v')
+        .unwrap_or(version)
+        .split('.')
+        .map(|n| n.parse::<u64>());
+    Some((numbers.next()?.ok()?, numbers.next()?.ok()?))
+}
+
+/// Selects the targets supported by an ESP-IDF version, as `(major, minor)`. Selecting
+/// every target is the default, so only the explicitly selected targets are required to be
+/// supported, the others are skipped.
+pub fn select_esp_idf_targets(
+    targets: &HashSet<Target>,
+    version: (u64, u64),
+) -> Result<HashSet<Target>, Error> {
+    let all_targets = targets.len() == Target::iter().count();
+    let mut selected = targets.clone();
+    for target in targets {
+        let (major, minor) = target.min_esp_idf_version();
+        if version >= (major, minor) {
+            continue;
+        }
+        if !all_targets {
+            return Err(Error::UnsupportedEspIdfTarget(
+                target.to_string(),
+                format!("{major}.{minor}"),
+                format!("{}.{}", version.0, version.1),
+            ));
+        }
+        selected.remove(target);
+    }
+    Ok(selected)
+}
+
+/// Expands a target group alias: `all`, `xtensa` or `riscv`.
+fn expand_alias(alias: &str) -> Option<HashSet<Target>> {
+    let filter: fn(&Target) -> bool = match alias {
+        "all" => |_| true,
+        "xtensa" => Target::is_xtensa,
+        "riscv" => Target::is_riscv,
+        _ => return None,
+    };
+    Some(Target::iter().filter(filter).collect())
 }
 
-/// Returns a vector of Chips from a comma or space separated string.
+/// Returns a vector of Chips from a comma or space separated string of targets and
+/// target group aliases.
 pub fn parse_targets(targets_str: &str) -> Result<HashSet<Target>, Error> {
     debug!("{} Parsing targets: {}", emoji::DEBUG, targets_str);
 
     let targets_str = targets_str.to_lowercase();
-    let targets_str = targets_str.trim();
-
-    let targets: HashSet<Target> = if targets_str.contains("all") {
-        Target::iter().collect()
-    } else {
-        let mut targets = HashSet::new();
-        for target in targets_str.split([',', ' ']) {
+    let mut targets = HashSet::new();
+    for target in targets_str.split([',', ' ']).filter(|t| !t.is_empty()) {
+        if let Some(expanded) = expand_alias(target) {
+            debug!("{} Expanded '{}' to: {:?}", emoji::DEBUG, target, expanded);
+            targets.extend(expanded);
+        } else {
             targets.insert(
                 Target::from_str(target).map_err(|_| Error::UnsupportedTarget(target.into()))?,
             );
         }
-
-        targets
-    };
+    }
+    if targets.is_empty() {
+        return Err(Error::UnsupportedTarget(targets_str));
+    }
 
     debug!("{} Parsed targets: {:?}", emoji::DEBUG, targets);
     Ok(targets)
@@ -51,8 +158,9 @@ pub fn parse_targets(targets_str: &str) -> Result<HashSet<Target>, Error> {
 
 #[cfg(test)]
 mod tests {
-    use crate::targets::{parse_targets, Target};
+    use crate::targets::{parse_esp_idf_version, parse_targets, select_esp_idf_targets, Target};
     use std::collections::HashSet;
+    use strum::IntoEnumIterator;
 
     #[test]
     #[allow(unused_variables)]
@@ -76,9 +184,82 @@ mod tests {
             Target::ESP32S3,
             Target::ESP32C2,
             Target::ESP32C3,
+            Target::ESP32C6,
+            Target::ESP32H2,
+            Target::ESP32P4,
         ]
         .into_iter()
         .collect();
         assert!(matches!(parse_targets("all"), Ok(targets)));
     }
+
+    #[test]
+    fn test_parse_target_aliases() {
+        let xtensa: HashSet<Target> = [Target::ESP32, Target::ESP32S2, Target::ESP32S3]
+            .into_iter()
+            .collect();
+        assert_eq!(parse_targets("xtensa").unwrap(), xtensa);
+        let riscv = parse_targets("RISCV").unwrap();
+        assert!(riscv.iter().all(Target::is_riscv));
+        assert!(riscv.contains(&Target::ESP32C3));
+        let mixed = parse_targets("xtensa, esp32c3").unwrap();
+        assert_eq!(mixed.len(), 4);
+        assert_eq!(
+            parse_targets("xtensa,riscv").unwrap(),
+            parse_targets("all").unwrap()
+        );
+        assert!(parse_targets("arm").is_err());
+        assert!(parse_targets(" ").is_err());
+    }
+
+    #[test]
+    fn test_target_architecture() {
+        assert!(Target::ESP32.is_xtensa());
+        assert!(Target::ESP32S3.is_xtensa());
+        assert!(!Target::ESP32C3.is_xtensa());
+        assert!(Target::ESP32C2.is_riscv());
+        assert!(!Target::ESP32S2.is_riscv());
+        assert!(Target::ESP32P4.is_riscv());
+        assert_eq!(
+            Target::ESP32H2.rust_target(),
+            "riscv32imac-unknown-none-elf"
+        );
+        assert_eq!(Target::ESP32S3.rust_target(), "xtensa-esp32s3-none-elf");
+        assert_eq!(Target::ESP32C3.std_rust_target(), "riscv32imc-esp-espidf");
+    }
+
+    #[test]
+    fn test_parse_new_targets() {
+        let targets: HashSet<Target> = [Target::ESP32C6, Target::ESP32H2, Target::ESP32P4]
+            .into_iter()
+            .collect();
+        assert_eq!(parse_targets("esp32c6,esp32h2 esp32p4").unwrap(), targets);
+        assert_eq!(Target::ESP32C6.min_esp_idf_version(), (5, 1));
+    }
+
+    #[test]
+    fn test_parse_esp_idf_version() {
+        assert_eq!(parse_esp_idf_version("v4.4"), Some((4, 4)));
+        assert_eq!(parse_esp_idf_version("tag:v5.1.2"), Some((5, 1)));
+        assert_eq!(parse_esp_idf_version("branch:release/v5.0"), Some((5, 0)));
+        assert_eq!(parse_esp_idf_version("master"), None);
+        assert_eq!(parse_esp_idf_version("commit:4c1ff6016a"), None);
+    }
+
+    #[test]
+    fn test_select_esp_idf_targets() {
+        let targets: HashSet<Target> = [Target::ESP32, Target::ESP32C6].into_iter().collect();
+        assert_eq!(select_esp_idf_targets(&targets, (5, 1)).unwrap(), targets);
+        assert!(select_esp_idf_targets(&targets, (4, 4))
+            .unwrap_err()
+            .to_string()
+            .contains("requires ESP-IDF v5.1 or newer, but v4.4 was selected"));
+
+        // Every target is the default, the unsupported ones are skipped.
+        let targets: HashSet<Target> = Target::iter().collect();
+        let selected = select_esp_idf_targets(&targets, (5, 0)).unwrap();
+        assert!(selected.contains(&Target::ESP32C2));
+        assert!(!selected.contains(&Target::ESP32C6));
+        assert!(!selected.contains(&Target::ESP32P4));
+    }
 }
@@ -0,0 +1,121 @@
+//! dfu-util source and installation tools
+
+use crate::{
+    context::Context,
+    emoji,
+    error::Error,
+    host_triple::HostTriple,
+    resume::{install_component, is_installed},
+    toolchain::{download_file, espidf::get_tool_path},
+};
+use log::{debug, info, warn};
+use std::path::PathBuf;
+
+/// Location of the dfu-util builds of the ESP-IDF tools index.
+pub const DEFAULT_DFU_UTIL_REPOSITORY: &str = "https://dl.espressif.com/dl";
+const DEFAULT_DFU_UTIL_VERSION: &str = "0.11";
+/// Name of the dfu-util tool directory.
+pub const DFU_UTIL_NAME: &str = "dfu-util";
+
+#[derive(Debug, Clone)]
+pub struct DfuUtil {
+    /// Host triple.
+    pub host_triple: HostTriple,
+    /// dfu-util installation path.
+    pub path: PathBuf,
+    /// URL of the dfu-util artifact.
+    pub repository_url: String,
+}
+
+impl DfuUtil {
+    /// Gets the binary path.
+    pub fn get_bin_path(&self) -> String {
+        format!(
+            "{}/{}-{}-win64",
+            self.path.display(),
+            DFU_UTIL_NAME,
+            DEFAULT_DFU_UTIL_VERSION
+        )
+    }
+
+    /// Gets the environment variables to export.
+    pub fn get_exports(&self) -> Vec<String> {
+        let mut exports: Vec<String> = Vec::new();
+        #[cfg(windows)]
+        exports.push(format!("$Env:PATH += \";{}\"", self.get_bin_path()));
+        #[cfg(unix)]
+        exports.push(format!("export PATH={}:$PATH", self.get_bin_path()));
+        exports
+    }
+
+    /// Installs dfu-util.
+    pub fn install(&self, context: &Context) -> Result<Vec<String>, Error> {
+        debug!("{} dfu-util path: {}", emoji::DEBUG, self.path.display());
+        if is_installed(context, &self.path) {
+            warn!(
+                "{} Previous installation of dfu-util exist in: '{}'. Reusing this installation.",
+                emoji::WARN,
+                self.path.display()
+            );
+        } else {
+            info!("{} Installing dfu-util", emoji::WRENCH);
+            install_component(context, &self.path, || {
+                download_file(
+                    context,
+                    self.repository_url.clone(),
+                    &format!("{DFU_UTIL_NAME}.zip"),
+                    &self.path.display().to_string(),
+                    true,
+                )
+            })?;
+        }
+        Ok(self.get_exports())
+    }
+
+    /// Create a new instance with default values and proper artifact URL, if the ESP-IDF
+    /// tools index provides a build for the host. Espressif only builds dfu-util for
+    /// Windows, the other hosts install it as a system package.
+    pub fn new(context: &Context, host_triple: &HostTriple) -> Option<Self> {
+        if !matches!(
+            host_triple,
+            HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu
+        ) {
+            return None;
+        }
+        let repository_url = format!(
+            "{}/{}-{}-win64.zip",
+            context
+                .http
+                .base_url("dfu-util", DEFAULT_DFU_UTIL_REPOSITORY),
+            DFU_UTIL_NAME,
+            DEFAULT_DFU_UTIL_VERSION
+        );
+        let path = PathBuf::from(format!(
+            "{}/v{}",
+            get_tool_path(context, DFU_UTIL_NAME),
+            DEFAULT_DFU_UTIL_VERSION
+        ));
+        Some(Self {
+            host_triple: host_triple.clone(),
+            path,
+            repository_url,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{context::Context, host_triple::HostTriple, toolchain::dfu_util::DfuUtil};
+
+    #[test]
+    fn test_dfu_util_new() {
+        let context = Context::default();
+        let dfu_util = DfuUtil::new(&context, &HostTriple::X86_64PcWindowsMsvc).unwrap();
+        assert_eq!(
+            dfu_util.repository_url,
+            "https://dl.espressif.com/dl/dfu-util-0.11-win64.zip"
+        );
+        assert!(dfu_util.path.ends_with("dfu-util/v0.11"));
+        assert!(DfuUtil::new(&context, &HostTriple::X86_64UnknownLinuxGnu).is_none());
+    }
+}
@@ -0,0 +1,235 @@
+//! USB serial drivers of the chips and development boards on Windows: the installed driver
+//! packages are listed with `pnputil` and the missing ones are downloaded and installed,
+//! with the consent of the user, as the most common reason for undetected boards.
+
+use crate::{
+    context::Context,
+    emoji,
+    error::Error,
+    interactive::is_interactive,
+    packages::get_command_output,
+    toolchain::{download_file, espidf::get_dist_path},
+};
+use console::Term;
+use embuild::cmd;
+use log::{info, warn};
+use strum::{Display, EnumIter, IntoEnumIterator};
+
+/// USB driver of Windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumIter)]
+pub enum UsbDriver {
+    /// Silicon Labs CP210x USB to UART bridges.
+    #[strum(serialize = "CP210x")]
+    Cp210x,
+    /// WCH CH340, CH343 and CH9102 USB to UART bridges.
+    #[strum(serialize = "CH34x")]
+    Ch34x,
+    /// USB-JTAG/serial of the ESP32-S3, ESP32-C3 and newer chips.
+    #[strum(serialize = "USB-JTAG")]
+    UsbJtag,
+}
+
+/// Driver package listed by `pnputil /enum-drivers`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DriverPackage {
+    /// Name of the `.inf` file the package was installed from, e.g. `silabser.inf`.
+    pub original_name: String,
+    /// Provider of the package, e.g. `Silicon Labs`.
+    pub provider_name: String,
+}
+
+impl UsbDriver {
+    /// Gets the name of the driver in the `[urls]` table of the configuration file.
+    pub fn component(&self) -> &'static str {
+        match self {
+            Self::Cp210x => "cp210x-driver",
+            Self::Ch34x => "ch34x-driver",
+            Self::UsbJtag => "usb-jtag-driver",
+        }
+    }
+
+    /// Gets the default URL of the official driver package.
+    fn default_url(&self) -> &'static str {
+        match self {
+            Self::Cp210x => {
+                "https://www.silabs.com/documents/public/software/CP210x_Universal_Windows_Driver.zip"
+            }
+            Self::Ch34x => "https://www.wch.cn/downloads/file/314.html",
+            Self::UsbJtag => {
+                "https://dl.espressif.com/dl/idf-driver/idf-driver-esp32-usb-jtag-2021-07-15.zip"
+            }
+        }
+    }
+
+    /// Gets the URL of the driver package, which can be replaced in the `[urls]` table of
+    /// the configuration file.
+    pub fn get_url(&self, context: &Context) -> String {
+        context.http.base_url(self.component(), self.default_url())
+    }
+
+    /// Gets the directory the driver package is extracted to.
+    pub fn get_path(&self, context: &Context) -> String {
+        get_dist_path(context, self.component())
+    }
+
+    /// Whether an installed driver package provides the driver.
+    fn is_provided_by(&self, package: &DriverPackage) -> bool {
+        let original_name = package.original_name.to_lowercase();
+        match self {
+            Self::Cp210x => original_name.starts_with("silabser"),
+            Self::Ch34x => ["ch341ser", "ch343ser"]
+                .iter()
+                .any(|name| original_name.starts_with(name)),
+            Self::UsbJtag => package.provider_name.to_lowercase().contains("espressif"),
+        }
+    }
+
+    /// Downloads the driver package and installs its drivers with `pnputil`, which Windows
+    /// runs as administrator once the user accepts the UAC prompt.
+    fn install(&self, context: &Context) -> Result<(), Error> {
+        info!("{} Installing the {} USB driver", emoji::WRENCH, self);
+        let path = self.get_path(context);
+        download_file(
+            context,
+            self.get_url(context),
+            &format!("{}.zip", self.component()),
+            &path,
+            true,
+        )?;
+        let arguments = format!("'/add-driver','\"{path}\\*.inf\"','/subdirs','/install'");
+        cmd!(
+            "powershell",
+            "-NoProfile",
+            "-Command",
+            format!("Start-Process pnputil -Verb RunAs -Wait -ArgumentList {arguments}")
+        )
+        .run()?;
+        Ok(())
+    }
+}
+
+/// Parses the output of `pnputil /enum-drivers`, made of blocks of `Key: Value` lines
+/// separated by blank lines.
+pub fn parse_driver_packages(output: &str) -> Vec<DriverPackage> {
+    let mut packages = Vec::new();
+    let mut package = DriverPackage::default();
+    for line in output.lines().chain([""]) {
+        if line.trim().is_empty() {
+            if !package.original_name.is_empty() {
+                packages.push(std::mem::take(&mut package));
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            match key.trim() {
+                "Original Name" => package.original_name = value.trim().to_string(),
+                "Provider Name" => package.provider_name = value.trim().to_string(),
+                _ => {}
+            }
+        }
+    }
+    packages
+}
+
+/// Gets the drivers that no installed package provides.
+pub fn select_missing_drivers(packages: &[DriverPackage]) -> Vec<UsbDriver> {
+    UsbDriver::iter()
+        .filter(|driver| {
+            !packages
+                .iter()
+                .any(|package| driver.is_provided_by(package))
+        })
+        .collect()
+}
+
+/// Gets the USB drivers missing in the host, if the driver packages can be listed.
+pub fn get_missing_drivers() -> Option<Vec<UsbDriver>> {
+    get_command_output("pnputil", &["/enum-drivers"])
+        .map(|output| select_missing_drivers(&parse_driver_packages(&output)))
+}
+
+/// Formats a list of drivers, e.g. `CP210x, CH34x`.
+pub fn format_drivers(drivers: &[UsbDriver]) -> String {
+    drivers
+        .iter()
+        .map(UsbDriver::to_string)
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Asks whether to install the missing drivers. Returns whether to proceed.
+fn confirm_install(drivers: &[UsbDriver], yes: bool) -> Result<bool, Error> {
+    if yes {
+        return Ok(true);
+    }
+    if !is_interactive() {
+        warn!(
+            "{} Skipping the {} USB drivers, installing them needs a confirmation, use '--yes'",
+            emoji::WARN,
+            format_drivers(drivers)
+        );
+        return Ok(false);
+    }
+    let term = Term::stderr();
+    term.write_str(&format!(
+        "{} Install the {} USB drivers? Windows asks for administrator rights [Y/n] ",
+        emoji::INFO,
+        format_drivers(drivers)
+    ))?;
+    let answer = term.read_line()?;
+    Ok(matches!(
+        answer.trim().to_lowercase().as_str(),
+        "" | "y" | "yes"
+    ))
+}
+
+/// Installs the missing USB drivers, once confirmed or with `yes`.
+pub fn install_usb_drivers(context: &Context, yes: bool) -> Result<(), Error> {
+    let missing = match get_missing_drivers() {
+        Some(missing) => missing,
+        None => {
+            warn!(
+                "{} Unable to list the installed drivers with pnputil, skipping the USB drivers",
+                emoji::WARN
+            );
+            return Ok(());
+        }
+    };
+    if missing.is_empty() {
+        info!("{} USB drivers are already installed", emoji::INFO);
+        return Ok(());
+    }
+    if !confirm_install(&missing, yes)? {
+        return Ok(());
+    }
+    for driver in missing {
+        driver.install(context)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::toolchain::drivers::{parse_driver_packages, select_missing_drivers, UsbDriver};
+
+    #[test]
+    fn test_select_missing_drivers() {
+        let output = "Microsoft PnP Utility
+
+Published Name:     oem3.inf
+Original Name:      silabser.inf
+Provider Name:      Silicon Laboratories Inc.
+Class Name:         Ports (COM & LPT)
+Driver Version:     10/05/2021 11.1.0.22
+
+Published Name:     oem7.inf
+Original Name:      usb_jtag_debug_unit.inf
+Provider Name:      Espressif
+Class Name:         Universal Serial Bus devices
+";
+        let packages = parse_driver_packages(output);
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].original_name, "silabser.inf");
+        assert_eq!(packages[1].provider_name, "Espressif");
+        assert_eq!(select_missing_drivers(&packages), [UsbDriver::Ch34x]);
+        assert_eq!(select_missing_drivers(&[]).len(), 3);
+    }
+}
@@ -1,71 +1,241 @@
 //! GCC Toolchain source and installation tools
 use crate::{
+    context::Context,
     emoji,
     error::Error,
-    targets::Target,
+    packages::get_command_output,
+    resume::{install_component, is_installed},
+    ssh,
+    targets::{select_esp_idf_targets, Target},
     toolchain::{
-        gcc::{get_toolchain_name, get_ulp_toolchain_name},
+        download_file,
+        gcc::{
+            get_esp_idf_toolchain_name, get_ulp_toolchain_names, is_target_toolchain,
+            is_ulp_toolchain,
+        },
         get_home_dir,
+        openocd::OPENOCD_NAME,
+        signature, stage,
     },
 };
-use embuild::{espidf, espidf::EspIdfRemote, git};
-use log::{debug, info};
+use embuild::{cmd, espidf, espidf::EspIdfRemote, git, python::PYTHON};
+use log::{debug, info, warn};
 use miette::Result;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::hash_map::DefaultHasher,
     collections::HashSet,
     env,
-    fs::remove_dir_all,
+    ffi::OsString,
+    fs::{create_dir_all, remove_dir_all, remove_file},
     hash::{Hash, Hasher},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 use strum::{Display, EnumIter, EnumString, IntoStaticStr};
 
 pub const DEFAULT_GIT_REPOSITORY: &str = "https://github.com/espressif/esp-idf";
+/// Base URL of the ESP-IDF releases, rewritten to the mirror when downloading.
+const ESP_IDF_RELEASES_URL: &str = "https://github.com/espressif/esp-idf/releases/download";
+/// Prefix of the release archives of ESP-IDF, followed by the version, e.g.
+/// `esp-idf-v5.1.zip`.
+const ESP_IDF_ARCHIVE_PREFIX: &str = "esp-idf-";
+/// Directory of the checkouts of the default repository, with the readable naming scheme.
+pub const ESP_IDF_DIR_NAME: &str = "esp-idf";
+/// Name of the link to the active ESP-IDF checkout, next to the checkouts.
+pub const CURRENT_LINK_NAME: &str = "current";
+/// Environment variable setting the installation directory.
+pub const ESPUP_HOME: &str = "ESPUP_HOME";
+/// Paths of ESP-IDF excluded by default in minified installations.
+pub const DEFAULT_MINIFY_PATHS: [&str; 4] = [
+    "docs",
+    "examples",
+    "tools/esp_app_trace",
+    "tools/test_idf_size",
+];
+/// Number of submodules fetched in parallel.
+pub const SUBMODULE_JOBS: &str = "8";
 
-const DEFAULT_CMAKE_GENERATOR: Generator = {
-    // No Ninja builds for linux=aarch64 from Espressif yet
-    #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
-    {
-        Generator::UnixMakefiles
-    }
-
-    #[cfg(not(all(target_os = "linux", target_arch = "aarch64")))]
-    {
-        Generator::Ninja
-    }
-};
+/// CMake generator used when the ESP-IDF tools provide a Ninja build for the host.
+pub const DEFAULT_CMAKE_GENERATOR: Generator = Generator::Ninja;
+/// CMake generator used when the ESP-IDF tools provide no Ninja build for the host.
+const FALLBACK_CMAKE_GENERATOR: Generator = Generator::UnixMakefiles;
+/// Platform of the ESP-IDF tools index for linux/aarch64 hosts.
+const LINUX_ARM64_PLATFORM: &str = "linux-arm64";
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, EnumString, Display, EnumIter, IntoStaticStr)]
+#[strum(ascii_case_insensitive)]
 pub enum Generator {
+    #[strum(serialize = "ninja")]
     Ninja,
+    #[strum(serialize = "ninja-multi-config")]
     NinjaMultiConfig,
+    #[strum(serialize = "unix-makefiles")]
     UnixMakefiles,
+    #[strum(serialize = "borland-makefiles")]
     BorlandMakefiles,
+    #[strum(serialize = "msys-makefiles")]
     MSYSMakefiles,
+    #[strum(serialize = "mingw-makefiles")]
     MinGWMakefiles,
+    #[strum(serialize = "nmake-makefiles")]
     NMakeMakefiles,
+    #[strum(serialize = "nmake-makefiles-jom")]
     NMakeMakefilesJOM,
+    #[strum(serialize = "watcom-wmake")]
     WatcomWMake,
 }
-#[derive(Debug, Clone, Default)]
+
+impl Default for Generator {
+    fn default() -> Self {
+        DEFAULT_CMAKE_GENERATOR
+    }
+}
+/// Paths excluded from the ESP-IDF checkout in minified installations.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MinifyProfile {
+    /// Paths, relative to the ESP-IDF directory, to exclude.
+    pub paths: Vec<String>,
+}
+
+impl MinifyProfile {
+    /// Creates a profile from the default paths, keeping and adding the given ones.
+    pub fn new(keep: &[String], remove: &[String]) -> Self {
+        let normalize = |path: &str| path.trim().trim_matches('/').replace('\\', "/");
+        let keep: Vec<String> = keep.iter().map(|path| normalize(path)).collect();
+        let mut paths: Vec<String> = Vec::new();
+        for path in DEFAULT_MINIFY_PATHS
+            .iter()
+            .map(|path| path.to_string())
+            .chain(remove.iter().map(|path| normalize(path)))
+        {
+            if !path.is_empty() && !keep.contains(&path) && !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        Self { paths }
+    }
+
+    /// Returns the sparse-checkout patterns that exclude the paths.
+    fn sparse_checkout_patterns(&self) -> Vec<String> {
+        std::iter::once("/*".to_string())
+            .chain(self.paths.iter().map(|path| format!("!/{path}/")))
+            .collect()
+    }
+
+    /// Excludes the paths from the checkout using git sparse-checkout, so they are not
+    /// reported as deleted by git. Falls back to removing them.
+    fn apply(&self, espidf_dir: &Path) -> Result<(), Error> {
+        info!("{} Minifying ESP-IDF", emoji::INFO);
+        let patterns = self.sparse_checkout_patterns();
+        let sparse_checkout = cmd!(
+            "git", "sparse-checkout", "set", "--no-cone";
+            args=(&patterns), current_dir=(espidf_dir)
+        )
+        .run();
+        if let Err(e) = sparse_checkout {
+            warn!(
+                "{} Failed to configure sparse-checkout ({}), removing the paths instead",
+                emoji::WARN,
+                e
+            );
+            for path in &self.paths {
+                let path = espidf_dir.join(path);
+                if path.exists() {
+                    remove_dir_all(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Submodules of ESP-IDF to initialize.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Submodules {
+    /// All the submodules.
+    #[default]
+    All,
+    /// No submodules.
+    None,
+    /// Submodules under the given paths.
+    Only(Vec<String>),
+}
+
+impl Submodules {
+    /// Parses `all`, `none` or a comma separated list of paths.
+    pub fn parse(arg: &str) -> Result<Self, Error> {
+        match arg.trim().to_lowercase().as_str() {
+            "all" => Ok(Self::All),
+            "none" | "" => Ok(Self::None),
+            _ => Ok(Self::Only(
+                arg.split(',')
+                    .map(|path| path.trim().trim_matches('/').to_string())
+                    .filter(|path| !path.is_empty())
+                    .collect(),
+            )),
+        }
+    }
+}
+
+/// Naming scheme of the ESP-IDF checkouts in the tools path.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, Display, EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum EspIdfLayout {
+    /// `esp-idf/<version>` for the default repository, `esp-idf-<hash>/<version>` for the
+    /// others.
+    #[default]
+    Readable,
+    /// `esp-idf-<hash>/<version>`, hashing the repository URL, as previous espup versions.
+    Hashed,
+}
+
+#[derive(Debug, Clone)]
 pub struct EspIdfRepo {
-    /// The repository containing GCC sources.
-    pub repository_url: String,
-    /// ESP-IDF Version.
-    pub version: String,
-    /// Minify ESP-IDF?.
-    pub minified: bool,
-    /// Installation directory.
-    pub install_path: PathBuf,
+    /// Repository and git reference of ESP-IDF, resolved once for the clone, the tools
+    /// installation and the installation path.
+    pub remote: EspIdfRemote,
+    /// Paths to exclude when minifying ESP-IDF, if it is minified.
+    pub minify_profile: Option<MinifyProfile>,
+    /// Clone only the last commit of ESP-IDF and its submodules?.
+    pub shallow: bool,
+    /// Submodules to initialize.
+    pub submodules: Submodules,
+    /// Existing ESP-IDF checkout, used instead of cloning the repository.
+    pub custom_path: Option<PathBuf>,
+    /// Download the release archive, which includes the submodules, instead of cloning?.
+    pub from_archive: bool,
+    /// CMake generator used to build ESP-IDF projects, or `None` to pick it from the tools
+    /// available for the host, see [`get_default_generator`].
+    pub cmake_generator: Option<Generator>,
+    /// Install OpenOCD along with the ESP-IDF tools?.
+    pub openocd: bool,
+    /// Install and enable ccache for the ESP-IDF builds?.
+    pub ccache: bool,
+    /// Skip the toolchains of the ULP coprocessors?.
+    pub skip_ulp: bool,
+    /// Skip the GCC toolchains of the targets?.
+    pub skip_gcc: bool,
+    /// Skip CMake?.
+    pub skip_cmake: bool,
+    /// Skip Ninja?.
+    pub skip_ninja: bool,
+    /// Location of the Python virtual environment, when not the default one.
+    pub python_env: Option<PathBuf>,
+    /// Leaves the existing Python virtual environment of `python_env` as is, without linking,
+    /// modifying or exporting it.
+    pub skip_python_env: bool,
     /// ESP targets.
     pub targets: HashSet<Target>,
 }
 
 impl EspIdfRepo {
     /// Installs esp-idf.
-    pub fn install(self) -> Result<Vec<String>, Error> {
-        let cmake_generator = DEFAULT_CMAKE_GENERATOR;
+    pub fn install(self, context: &Context) -> Result<Vec<String>, Error> {
+        let cmake_generator = self.cmake_generator;
         let mut exports: Vec<String> = Vec::new();
 
         // A closure to specify which tools `idf-tools.py` should install.
@@ -83,41 +253,103 @@ impl EspIdfRepo {
                 repo.worktree().display()
             );
 
-            let mut tools = vec![];
-            let mut subtools = Vec::new();
-            for target in self.targets {
-                let gcc_toolchain_name = get_toolchain_name(&target);
-                subtools.push(gcc_toolchain_name);
-
-                let ulp_toolchain_name = get_ulp_toolchain_name(target, version.as_ref().ok());
-                if !cfg!(target_os = "linux") || !cfg!(target_arch = "aarch64") {
-                    if let Some(ulp_toolchain_name) = ulp_toolchain_name {
-                        subtools.push(ulp_toolchain_name);
+            let targets = match version {
+                Ok(version) => {
+                    let targets =
+                        select_esp_idf_targets(&self.targets, (version.major, version.minor))?;
+                    for target in self.targets.difference(&targets) {
+                        let (major, minor) = target.min_esp_idf_version();
+                        warn!(
+                            "{} Skipping target '{}', it requires ESP-IDF v{}.{} or newer",
+                            emoji::WARN,
+                            target,
+                            major,
+                            minor
+                        );
                     }
+                    targets
                 }
+                Err(_) => self.targets.clone(),
+            };
+
+            let cmake_generator =
+                cmake_generator.unwrap_or_else(|| get_default_generator(repo.worktree()));
+            let mut tools = vec![];
+            let mut subtools = Vec::new();
+            // The arguments are passed to `idf_tools.py install`, whose `--targets` option,
+            // available since ESP-IDF v4.4, also records the targets for its `export` action.
+            if matches!(version, Ok(version) if (version.major, version.minor) >= (4, 4)) {
+                subtools.push(get_targets_arg(&targets));
             }
 
+            // Tools installed on top of the toolchains of the targets.
+            let mut optional_tools = Vec::new();
             // Use custom cmake for esp-idf<4.4, because we need at least cmake-3.20
             match version.as_ref().map(|v| (v.major, v.minor, v.patch)) {
-                Ok((major, minor, _)) if major >= 4 && minor >= 4 => {
-                    subtools.push("cmake".to_string())
-                }
+                _ if self.skip_cmake => {}
+                Ok((major, minor, _)) if major >= 4 && minor >= 4 => optional_tools.push("cmake"),
                 _ => {
                     tools
                         .push(espidf::Tools::cmake().map_err(|_| Error::FailedToInstantiateCmake)?);
                 }
             }
+            if self.openocd {
+                optional_tools.push(OPENOCD_NAME);
+            }
             #[cfg(windows)]
-            subtools.push("openocd-esp32".to_string());
-            #[cfg(windows)]
-            subtools.push("idf-exe".to_string());
-            #[cfg(windows)]
-            subtools.push("ccache".to_string());
+            optional_tools.push("idf-exe");
+            if cfg!(windows) || self.ccache {
+                optional_tools.push("ccache");
+            }
             #[cfg(windows)]
-            subtools.push("dfu-util".to_string());
+            optional_tools.push("dfu-util");
+            if !self.skip_ninja
+                && matches!(
+                    cmake_generator,
+                    Generator::Ninja | Generator::NinjaMultiConfig
+                )
+            {
+                optional_tools.push("ninja");
+            }
 
-            if cmake_generator == Generator::Ninja {
-                subtools.push("ninja".to_string())
+            match read_tools_index(repo.worktree()) {
+                Some(tools_index) => {
+                    for (name, version) in get_index_tools(
+                        &tools_index,
+                        get_tools_platform(),
+                        &targets,
+                        &optional_tools,
+                    ) {
+                        debug!("{} Installing {} {}", emoji::DEBUG, name, version);
+                        subtools.push(name);
+                    }
+                }
+                // Checkouts without a tools index use the tool names of ESP-IDF v4 and v5.
+                None => {
+                    for target in &targets {
+                        let toolchains = std::iter::once(get_esp_idf_toolchain_name(
+                            target,
+                            version.as_ref().ok(),
+                        ))
+                        .chain(get_ulp_toolchain_names(
+                            *target,
+                            version.as_ref().ok(),
+                            get_tools_platform(),
+                        ));
+                        for toolchain in toolchains {
+                            if !subtools.contains(&toolchain) {
+                                subtools.push(toolchain);
+                            }
+                        }
+                    }
+                    subtools.extend(optional_tools.iter().map(|tool| tool.to_string()));
+                }
+            }
+            if self.skip_ulp {
+                subtools.retain(|tool| !is_ulp_toolchain(tool, &targets));
+            }
+            if self.skip_gcc {
+                subtools.retain(|tool| !is_target_toolchain(tool, &targets, version.as_ref().ok()));
             }
 
             tools.push(espidf::Tools::new(subtools));
@@ -125,101 +357,1402 @@ impl EspIdfRepo {
             Ok(tools)
         };
 
-        let install = |esp_idf_origin: espidf::EspIdfOrigin| -> Result<espidf::EspIdf, Error> {
-            espidf::Installer::new(esp_idf_origin)
-                .install_dir(Some(self.install_path.clone()))
-                .with_tools(make_tools)
-                .install()
-                .map_err(|_| Error::FailedToCreateEspIdfInstallClosure)
+        // Installs the tools with `idf_tools.py`, returning the `PATH` including them. The
+        // commands are run with the environment of the context, e.g. its proxy and mirror,
+        // hence not by the installer of embuild, which only passes them the one of espup.
+        let install = |repository: git::Repository,
+                       espidf_dir: &Path,
+                       python_env: Option<&Path>|
+         -> Result<OsString, Error> {
+            let to_error = |e: anyhow::Error| {
+                e.downcast::<Error>()
+                    .unwrap_or(Error::FailedToInstallEspIdf)
+            };
+            let version = espidf::EspIdfVersion::try_from(&repository);
+            let tools = make_tools(&repository, &version).map_err(to_error)?;
+            install_idf_tools(context, espidf_dir, python_env, tools)
         };
 
-        let repo = espidf::EspIdfRemote {
-            git_ref: espidf::parse_esp_idf_git_ref(&self.version),
-            repo_url: Some("https://github.com/espressif/esp-idf".to_string()),
+        let (repository, espidf_dir) = if let Some(path) = &self.custom_path {
+            let repository = git::Repository::open(path)
+                .map_err(|_| Error::InvalidEspIdfPath(path.display().to_string()))?;
+            let espidf_dir = repository.worktree().to_path_buf();
+            (repository, espidf_dir)
+        } else if self.from_archive {
+            let repo = self.remote.clone();
+            let espidf_dir = get_install_path(context, repo.clone());
+            if !is_installed(context, &espidf_dir) {
+                context.rollback.track(&espidf_dir);
+                install_component(context, &espidf_dir, || {
+                    download_archive(context, &repo.git_ref, &espidf_dir)
+                })?;
+            }
+            // The archive is not a git repository, which is only read for its version.
+            (git::Repository::new(&espidf_dir), espidf_dir)
+        } else {
+            let repo = self.remote.clone();
+            let espidf_dir = get_install_path(context, repo.clone());
+            if !espidf_dir.exists() {
+                ssh::check_access(repo.repo_url())?;
+            }
+            context.rollback.track(&espidf_dir);
+            // The checkout is cloned into a staging directory, so that a failed clone leaves
+            // no partial checkout behind, and its signature is verified before its tools are
+            // installed. It is marked as incomplete while it is cloned, so that a clone
+            // interrupted before it could roll back, e.g. killed by a CI timeout, is cloned
+            // again by the next installation.
+            if !espidf_dir.join(".git").exists() || !is_installed(context, &espidf_dir) {
+                install_component(context, &espidf_dir, || {
+                    stage(context, &espidf_dir, |staging_dir| {
+                        clone_repository(
+                            context,
+                            repo.repo_url(),
+                            &repo.git_ref,
+                            staging_dir,
+                            self.shallow,
+                            &self.submodules,
+                        )
+                    })
+                })?;
+            }
+            let tag = match &repo.git_ref {
+                git::Ref::Tag(tag) => Some(tag.as_str()),
+                _ => None,
+            };
+            signature::verify_tag(context, &espidf_dir, tag)?;
+            let repository = git::Repository::open(&espidf_dir)
+                .map_err(|_| Error::InvalidEspIdfPath(espidf_dir.display().to_string()))?;
+            (repository, espidf_dir)
         };
 
-        let espidf_origin = espidf::EspIdfOrigin::Managed(repo.clone());
-        #[cfg(unix)]
-        let espidf = install(espidf_origin).map_err(|_| Error::FailedToInstallEspIdf)?;
-        #[cfg(windows)]
-        install(espidf_origin).map_err(|_| Error::FailedToInstallEspIdf)?;
-        let espidf_dir = get_install_path(repo);
+        let python_env = match &self.python_env {
+            Some(python_env) => Some(env::current_dir()?.join(python_env)),
+            None => None,
+        };
+        // A skipped environment, e.g. the active one, is neither linked nor modified, and is
+        // left to the shell.
+        if let Some(python_env) = &python_env {
+            if self.skip_python_env {
+                if !python_env.exists() {
+                    return Err(Error::MissingPythonEnv);
+                }
+            } else {
+                context.rollback.track(python_env);
+                link_python_env(context, &espidf_dir, python_env)?;
+                #[cfg(windows)]
+                exports.push(format!(
+                    "$Env:IDF_PYTHON_ENV_PATH=\"{}\"",
+                    python_env.display()
+                ));
+                #[cfg(unix)]
+                exports.push(format!(
+                    "export IDF_PYTHON_ENV_PATH=\"{}\"",
+                    python_env.display()
+                ));
+            }
+        }
+
+        context.rollback.track(&context.tools_path.join("tools"));
+        // A skipped environment is not replaced by the default one, which the tools are
+        // installed without.
+        if self.python_env.is_none() {
+            create_default_python_env(context, &espidf_dir)?;
+        }
+        context.summary.record(&espidf_dir, Duration::ZERO);
+        let exported_path = install(repository, &espidf_dir, python_env.as_deref())?;
+        if self.custom_path.is_none() {
+            link_current(&espidf_dir);
+        }
         #[cfg(windows)]
         exports.push(format!("$Env:IDF_PATH=\"{}\"", espidf_dir.display()));
         #[cfg(unix)]
         exports.push(format!("export IDF_PATH={}", espidf_dir.display()));
+        // The path of the installed tools is exported instead of sourcing the export script of
+        // ESP-IDF, which requires the tools of every target.
         #[cfg(windows)]
-        exports.push(espidf_dir.join("export.ps1").display().to_string());
+        exports.push(format!("$Env:PATH=\"{}\"", exported_path.to_string_lossy()));
         #[cfg(unix)]
-        exports.push(format!("export PATH={:?}", espidf.exported_path));
-        if self.minified {
-            info!("{} Minifying ESP-IDF", emoji::INFO);
-            remove_dir_all(espidf_dir.join("docs"))?;
-            remove_dir_all(espidf_dir.join("examples"))?;
-            remove_dir_all(espidf_dir.join("tools").join("esp_app_trace"))?;
-            remove_dir_all(espidf_dir.join("tools").join("test_idf_size"))?;
+        exports.push(format!("export PATH={:?}", exported_path));
+        if let Some(minify_profile) = &self.minify_profile {
+            minify_profile.apply(&espidf_dir)?;
         }
 
         #[cfg(windows)]
-        exports.push(format!("$Env:IDF_TOOLS_PATH=\"{}\"", get_tools_path()));
+        exports.push(format!("$Env:IDF_TOOLS_PATH=\"{}\"", context.tools_path()));
         #[cfg(unix)]
-        exports.push(format!("export IDF_TOOLS_PATH=\"{}\"", get_tools_path()));
+        exports.push(format!(
+            "export IDF_TOOLS_PATH=\"{}\"",
+            context.tools_path()
+        ));
+        if self.ccache {
+            exports.extend(get_ccache_exports(context));
+        }
 
         Ok(exports)
     }
 
-    /// Create a new instance with the proper arguments.
-    pub fn new(version: &str, minified: bool, targets: &HashSet<Target>) -> EspIdfRepo {
-        let install_path = PathBuf::from(get_tools_path());
-        debug!(
-            "{} ESP-IDF install path: '{}'",
-            emoji::DEBUG,
-            install_path.display()
-        );
+    /// Create a new instance with the proper arguments, cloned from `remote`, see
+    /// [`get_esp_idf_remote`].
+    pub fn new(
+        remote: EspIdfRemote,
+        minify_profile: Option<MinifyProfile>,
+        shallow: bool,
+        submodules: Submodules,
+        cmake_generator: Option<Generator>,
+        openocd: bool,
+        targets: &HashSet<Target>,
+    ) -> EspIdfRepo {
         Self {
-            repository_url: DEFAULT_GIT_REPOSITORY.to_string(),
-            version: version.to_string(),
-            minified,
-            install_path,
+            remote,
+            minify_profile,
+            shallow,
+            submodules,
+            custom_path: None,
+            from_archive: false,
+            cmake_generator,
+            openocd,
+            ccache: false,
+            skip_ulp: false,
+            skip_gcc: false,
+            skip_cmake: false,
+            skip_ninja: false,
+            python_env: None,
+            skip_python_env: false,
             targets: targets.clone(),
         }
     }
+
+    /// Create a new instance that uses an existing ESP-IDF checkout. The checkout is
+    /// never modified, hence it is not minified.
+    pub fn from_path(
+        path: &Path,
+        cmake_generator: Option<Generator>,
+        openocd: bool,
+        targets: &HashSet<Target>,
+    ) -> EspIdfRepo {
+        Self {
+            custom_path: Some(path.to_path_buf()),
+            ..Self::new(
+                get_esp_idf_remote(None, ""),
+                None,
+                false,
+                Submodules::All,
+                cmake_generator,
+                openocd,
+                targets,
+            )
+        }
+    }
+}
+
+/// Gets the directory of the ccache cache, kept along with the tools.
+pub fn get_ccache_dir(context: &Context) -> PathBuf {
+    context.tools_path.join("ccache")
+}
+
+/// Gets the environment variables enabling ccache in the ESP-IDF builds.
+fn get_ccache_exports(context: &Context) -> Vec<String> {
+    let mut exports = Vec::new();
+    #[cfg(windows)]
+    exports.push("$Env:IDF_CCACHE_ENABLE=\"1\"".to_string());
+    #[cfg(windows)]
+    exports.push(format!(
+        "$Env:CCACHE_DIR=\"{}\"",
+        get_ccache_dir(context).display()
+    ));
+    #[cfg(unix)]
+    exports.push("export IDF_CCACHE_ENABLE=1".to_string());
+    #[cfg(unix)]
+    exports.push(format!(
+        "export CCACHE_DIR=\"{}\"",
+        get_ccache_dir(context).display()
+    ));
+    exports
+}
+
+/// Gets the `--targets` option of `idf_tools.py install`, so that only the tools of the
+/// selected chips are installed and required.
+fn get_targets_arg(targets: &HashSet<Target>) -> String {
+    let mut targets: Vec<String> = targets.iter().map(Target::to_string).collect();
+    targets.sort();
+    format!("--targets={}", targets.join(","))
+}
+
+/// Reads the ESP-IDF tools index, `tools/tools.json`, of a checkout.
+fn read_tools_index(espidf_dir: &Path) -> Option<serde_json::Value> {
+    std::fs::read(espidf_dir.join("tools").join("tools.json"))
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+}
+
+/// Gets the platform of the host in the ESP-IDF tools index, e.g. `linux-arm64`.
+fn get_tools_platform() -> &'static str {
+    if cfg!(all(target_os = "linux", target_arch = "aarch64")) {
+        LINUX_ARM64_PLATFORM
+    } else if cfg!(all(target_os = "linux", target_arch = "arm")) {
+        "linux-armhf"
+    } else if cfg!(all(target_os = "linux", target_arch = "x86")) {
+        "linux-i686"
+    } else if cfg!(target_os = "linux") {
+        "linux-amd64"
+    } else if cfg!(all(target_os = "macos", target_arch = "aarch64")) {
+        "macos-arm64"
+    } else if cfg!(target_os = "macos") {
+        "macos"
+    } else if cfg!(all(windows, target_arch = "x86")) {
+        "win32"
+    } else {
+        "win64"
+    }
+}
+
+/// Gets the recommended version of a tool of the ESP-IDF tools index, `tools/tools.json`,
+/// that provides a build for the platform, e.g. `linux-arm64`, if any.
+fn get_recommended_version<'a>(tool: &'a serde_json::Value, platform: &str) -> Option<&'a str> {
+    tool["versions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|version| version["status"] == "recommended")
+        .find(|version| version.get(platform).is_some() || version.get("any").is_some())
+        .and_then(|version| version["name"].as_str())
+}
+
+/// Whether the recommended version of a tool of the ESP-IDF tools index, `tools/tools.json`,
+/// provides a build for the platform, e.g. `linux-arm64`.
+fn has_tool_build(tools_index: &serde_json::Value, tool_name: &str, platform: &str) -> bool {
+    tools_index["tools"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|tool| tool["name"] == tool_name)
+        .any(|tool| get_recommended_version(tool, platform).is_some())
+}
+
+/// Gets the installation mode of a tool of the ESP-IDF tools index on the platform,
+/// `always`, `on_request` or `never`.
+fn get_install_mode<'a>(tool: &'a serde_json::Value, platform: &str) -> &'a str {
+    tool["platform_overrides"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|platform_override| {
+            platform_override["platforms"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .any(|name| name == platform)
+        })
+        .filter_map(|platform_override| platform_override["install"].as_str())
+        .next_back()
+        .or_else(|| tool["install"].as_str())
+        .unwrap_or("never")
+}
+
+/// Gets the names and versions of the tools of the ESP-IDF tools index to install on the
+/// platform: the toolchains installed by default that support one of the targets, and the
+/// `optional` tools provided by the index, e.g. `ninja`. Tools without a build for the
+/// platform are skipped.
+fn get_index_tools(
+    tools_index: &serde_json::Value,
+    platform: &str,
+    targets: &HashSet<Target>,
+    optional: &[&str],
+) -> Vec<(String, String)> {
+    tools_index["tools"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|tool| {
+            let name = tool["name"].as_str().unwrap_or_default();
+            if optional.contains(&name) {
+                return true;
+            }
+            let supports_targets = match tool["supported_targets"].as_array() {
+                Some(supported_targets) => supported_targets.iter().any(|supported| {
+                    supported == "all"
+                        || targets
+                            .iter()
+                            .any(|target| supported.as_str() == Some(&target.to_string()))
+                }),
+                None => true,
+            };
+            // Toolchains are the only default tools ending with `-elf`, unlike the GDBs
+            // or the ROM ELF files.
+            name.ends_with("-elf")
+                && get_install_mode(tool, platform) == "always"
+                && supports_targets
+        })
+        .filter_map(|tool| {
+            let name = tool["name"].as_str()?;
+            match get_recommended_version(tool, platform) {
+                Some(version) => Some((name.to_string(), version.to_string())),
+                None => {
+                    debug!(
+                        "{} Skipping '{}', it provides no build for {}",
+                        emoji::DEBUG,
+                        name,
+                        platform
+                    );
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Gets the CMake generator of an ESP-IDF checkout: Ninja, unless its tools provide no
+/// Ninja build for the host, which is the case of older releases on linux/aarch64.
+pub fn get_default_generator(espidf_dir: &Path) -> Generator {
+    if !cfg!(all(target_os = "linux", target_arch = "aarch64")) {
+        return DEFAULT_CMAKE_GENERATOR;
+    }
+    let has_ninja = read_tools_index(espidf_dir).map_or(false, |tools_index| {
+        has_tool_build(&tools_index, "ninja", LINUX_ARM64_PLATFORM)
+    });
+    if has_ninja {
+        DEFAULT_CMAKE_GENERATOR
+    } else {
+        info!(
+            "{} No Ninja build available for {}, using the '{}' CMake generator",
+            emoji::INFO,
+            LINUX_ARM64_PLATFORM,
+            FALLBACK_CMAKE_GENERATOR
+        );
+        FALLBACK_CMAKE_GENERATOR
+    }
+}
+
+/// Clones a repository with the selected submodules and, if `shallow`, fetching only the
+/// commit of the reference.
+///
+/// The installer reuses an existing clone when it matches the repository and reference,
+/// so cloning it beforehand avoids the full clone of the installer.
+pub fn clone_repository(
+    context: &Context,
+    url: &str,
+    git_ref: &git::Ref,
+    path: &Path,
+    shallow: bool,
+    submodules: &Submodules,
+) -> Result<(), Error> {
+    if path.join(".git").exists() {
+        return Ok(());
+    }
+    info!(
+        "{} Cloning '{}' into '{}'",
+        emoji::DOWNLOAD,
+        url,
+        path.display()
+    );
+    create_dir_all(path)?;
+    let depth: &[&str] = if shallow { &["--depth", "1"] } else { &[] };
+    match git_ref {
+        git::Ref::Branch(name) | git::Ref::Tag(name) => {
+            cmd!("git", "clone", "--branch", name; args=(depth), arg=(url), arg=(path), envs=(context.envs())).run()?
+        }
+        git::Ref::Commit(hash) => {
+            cmd!("git", "init", "--quiet"; current_dir=(path)).run()?;
+            cmd!("git", "remote", "add", "origin", url; current_dir=(path)).run()?;
+            if hash.len() == 40 || is_ref_name(hash) {
+                cmd!("git", "fetch", "origin", hash; args=(depth), current_dir=(path), envs=(context.envs())).run()?;
+                cmd!("git", "checkout", "--quiet", "FETCH_HEAD"; current_dir=(path)).run()?;
+            } else {
+                // Servers only serve full hashes, the abbreviated ones are looked up in the
+                // history of the branches.
+                if shallow {
+                    warn!(
+                        "{} Fetching the full history to find the abbreviated commit '{}'",
+                        emoji::WARN,
+                        hash
+                    );
+                }
+                cmd!("git", "fetch", "origin"; current_dir=(path), envs=(context.envs())).run()?;
+                cmd!("git", "checkout", "--quiet", hash; current_dir=(path)).run()?;
+            }
+        }
+    }
+    update_submodules(context, path, shallow, submodules)
+}
+
+/// Gets the URL of the release archive of an ESP-IDF version. Only releases, i.e. tags,
+/// publish one.
+pub fn get_archive_url(git_ref: &git::Ref) -> Result<String, Error> {
+    match git_ref {
+        git::Ref::Tag(tag) => Ok(format!(
+            "{ESP_IDF_RELEASES_URL}/{tag}/{ESP_IDF_ARCHIVE_PREFIX}{tag}.zip"
+        )),
+        git::Ref::Branch(name) | git::Ref::Commit(name) => {
+            Err(Error::MissingEspIdfArchive(name.to_string()))
+        }
+    }
+}
+
+/// Downloads the release archive of an ESP-IDF version, which includes the submodules,
+/// and extracts it into `espidf_dir`.
+fn download_archive(context: &Context, git_ref: &git::Ref, espidf_dir: &Path) -> Result<(), Error> {
+    let url = get_archive_url(git_ref)?;
+    let file_name = url.rsplit('/').next().unwrap_or_default().to_string();
+    // The archive contains a single `esp-idf-<version>` directory.
+    let mut extraction_dir = espidf_dir.as_os_str().to_owned();
+    extraction_dir.push(".archive");
+    let extraction_dir = PathBuf::from(extraction_dir);
+    context.rollback.track(&extraction_dir);
+    download_file(
+        context,
+        url,
+        &file_name,
+        &extraction_dir.display().to_string(),
+        true,
+    )?;
+    let extracted_dir = extraction_dir.join(file_name.trim_end_matches(".zip"));
+    std::fs::rename(extracted_dir, espidf_dir)?;
+    remove_dir_all(extraction_dir)?;
+    Ok(())
+}
+
+/// Initializes and updates the selected submodules of a repository.
+pub fn update_submodules(
+    context: &Context,
+    path: &Path,
+    shallow: bool,
+    submodules: &Submodules,
+) -> Result<(), Error> {
+    let mut args = vec![
+        "submodule",
+        "update",
+        "--init",
+        "--recursive",
+        "--jobs",
+        SUBMODULE_JOBS,
+    ];
+    if shallow {
+        args.extend(["--depth", "1"]);
+    }
+    match submodules {
+        Submodules::All => {}
+        Submodules::None => return Ok(()),
+        Submodules::Only(paths) => {
+            args.push("--");
+            args.extend(paths.iter().map(String::as_str));
+        }
+    }
+    info!(
+        "{} Updating submodules of '{}'",
+        emoji::DOWNLOAD,
+        path.display()
+    );
+    cmd!("git"; args=(args), current_dir=(path), envs=(context.envs())).run()?;
+    Ok(())
+}
+
+/// Result of the fast-forward of an ESP-IDF checkout tracking a branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BranchUpdate {
+    /// The checkout already is at the last commit of the branch.
+    UpToDate,
+    /// The checkout was fast-forwarded, and requires the same tools.
+    Updated,
+    /// The checkout was fast-forwarded, and its tools index changed.
+    ToolsChanged,
+}
+
+/// Fetches the last commit of `branch` and fast-forwards the ESP-IDF checkout to it, along
+/// with its initialized submodules. Shallow checkouts only fetch the last commit.
+pub fn fast_forward(
+    context: &Context,
+    espidf_dir: &Path,
+    branch: &str,
+) -> Result<BranchUpdate, Error> {
+    update_checkout(context, espidf_dir, branch, false)
+}
+
+/// Fetches the commit of a reference, e.g. `refs/pull/1234/head`, and resets the ESP-IDF
+/// checkout to it, along with its initialized submodules, as pull requests are force-pushed.
+pub fn reset_to_ref(
+    context: &Context,
+    espidf_dir: &Path,
+    reference: &str,
+) -> Result<BranchUpdate, Error> {
+    update_checkout(context, espidf_dir, reference, true)
+}
+
+/// Updates the ESP-IDF checkout to the last commit of `branch`, a branch or a reference,
+/// fast-forwarding it unless `reset`.
+fn update_checkout(
+    context: &Context,
+    espidf_dir: &Path,
+    branch: &str,
+    reset: bool,
+) -> Result<BranchUpdate, Error> {
+    let tools_index = read_tools_index(espidf_dir);
+    let get_head = || cmd!("git", "rev-parse", "HEAD"; current_dir=(espidf_dir)).stdout();
+    let head = get_head()?;
+    info!(
+        "{} Fetching the last commit of '{}' into '{}'",
+        emoji::DOWNLOAD,
+        branch,
+        espidf_dir.display()
+    );
+    if espidf_dir.join(".git").join("shallow").exists() {
+        cmd!("git", "fetch", "--depth", "1", "origin", branch; current_dir=(espidf_dir), envs=(context.envs())).run()?;
+        // The history of a shallow checkout does not link its commit to the fetched one.
+        cmd!("git", "reset", "--keep", "FETCH_HEAD"; current_dir=(espidf_dir)).run()?;
+    } else if reset {
+        cmd!("git", "fetch", "origin", branch; current_dir=(espidf_dir), envs=(context.envs()))
+            .run()?;
+        cmd!("git", "reset", "--keep", "FETCH_HEAD"; current_dir=(espidf_dir)).run()?;
+    } else {
+        cmd!("git", "fetch", "origin", branch; current_dir=(espidf_dir), envs=(context.envs()))
+            .run()?;
+        cmd!("git", "merge", "--ff-only", "FETCH_HEAD"; current_dir=(espidf_dir)).run()?;
+    }
+    if get_head()? == head {
+        return Ok(BranchUpdate::UpToDate);
+    }
+    // Only the submodules that were initialized are updated.
+    cmd!("git", "submodule", "update", "--recursive", "--jobs", SUBMODULE_JOBS; current_dir=(espidf_dir), envs=(context.envs())).run()?;
+    if read_tools_index(espidf_dir) == tools_index {
+        Ok(BranchUpdate::Updated)
+    } else {
+        Ok(BranchUpdate::ToolsChanged)
+    }
+}
+
+/// Installs the tools required by an ESP-IDF checkout for the targets, and its Python
+/// environment, e.g. once its tools index changed.
+pub fn install_tools(
+    context: &Context,
+    espidf_dir: &Path,
+    targets: &HashSet<Target>,
+) -> Result<(), Error> {
+    info!(
+        "{} Installing the tools of '{}'",
+        emoji::WRENCH,
+        espidf_dir.display()
+    );
+    let idf_tools = espidf_dir.join("tools").join("idf_tools.py");
+    cmd!(
+        get_python(context), &idf_tools, "--idf-path", espidf_dir, "install", get_targets_arg(targets);
+        envs=(context.envs())
+    )
+    .run()?;
+    cmd!(
+        get_python(context), &idf_tools, "--idf-path", espidf_dir, "install-python-env";
+        envs=(context.envs())
+    )
+    .run()?;
+    Ok(())
+}
+
+/// Points the Python virtual environment of an ESP-IDF checkout to `python_env`, creating
+/// the environment if it does not exist yet.
+///
+/// The installer always looks for the environment in its default location, under the
+/// tools path, so the default location is linked to `python_env`.
+fn link_python_env(context: &Context, espidf_dir: &Path, python_env: &Path) -> Result<(), Error> {
+    let idf_tools = espidf_dir.join("tools").join("idf_tools.py");
+    let default_env = get_default_python_env(context, espidf_dir)
+        .map_err(|_| Error::FailedToConfigurePythonEnv(python_env.display().to_string()))?;
+
+    if python_env.exists() {
+        info!(
+            "{} Reusing the Python virtual environment in '{}'",
+            emoji::INFO,
+            python_env.display()
+        );
+    } else {
+        info!(
+            "{} Creating the Python virtual environment in '{}'",
+            emoji::WRENCH,
+            python_env.display()
+        );
+        cmd!(
+            get_python(context), &idf_tools, "--idf-path", espidf_dir, "--non-interactive", "install-python-env";
+            envs=(context.envs()), env=("IDF_PYTHON_ENV_PATH", python_env)
+        )
+        .run()?;
+    }
+
+    if default_env.is_symlink() {
+        // Directory links and junctions are directories on Windows.
+        remove_file(&default_env).or_else(|_| std::fs::remove_dir(&default_env))?;
+    } else if default_env.exists() {
+        return Err(Error::PythonEnvAlreadyExists(
+            default_env.display().to_string(),
+        ));
+    }
+    debug!(
+        "{} Linking '{}' to '{}'",
+        emoji::DEBUG,
+        default_env.display(),
+        python_env.display()
+    );
+    if let Some(parent) = default_env.parent() {
+        create_dir_all(parent)?;
+    }
+    link_dir(python_env, &default_env)
+}
+
+/// Gets the default location of the Python virtual environment of an ESP-IDF checkout,
+/// under the tools path.
+fn get_default_python_env(context: &Context, espidf_dir: &Path) -> Result<PathBuf, Error> {
+    let idf_tools = espidf_dir.join("tools").join("idf_tools.py");
+    let output = cmd!(
+        get_python(context), &idf_tools, "--idf-path", espidf_dir, "--quiet", "export", "--format=key-value";
+        ignore_exitcode=(), envs=(context.envs()), env_remove=("IDF_PYTHON_ENV_PATH")
+    )
+    .stdout()?;
+    parse_key_values(&output)
+        .into_iter()
+        .find(|(key, _)| key == "IDF_PYTHON_ENV_PATH")
+        .map(|(_, value)| PathBuf::from(value))
+        .ok_or(Error::FailedToInstallEspIdf)
+}
+
+/// Gets the Python interpreter running the installers of ESP-IDF and its tools: the one
+/// of the context, selected with `--python`, or the one in `PATH`.
+pub fn get_python(context: &Context) -> &str {
+    context.python.as_deref().unwrap_or(PYTHON)
+}
+
+/// Resolves `python`, the interpreter used to install ESP-IDF and its tools, to the path
+/// set in [`Context::python`].
+///
+/// `python` is either a path or the name of an executable in `PATH`. The environment of
+/// espup is left untouched, the interpreter is passed to the commands it runs.
+pub fn resolve_python(python: &Path) -> Result<String, Error> {
+    let invalid = || Error::InvalidPython(python.display().to_string());
+    let interpreter = if python.components().count() > 1 {
+        python.to_path_buf()
+    } else {
+        env::split_paths(&env::var_os("PATH").unwrap_or_default())
+            .map(|dir| dir.join(python))
+            .find(|path| path.is_file())
+            .ok_or_else(invalid)?
+    };
+    let interpreter = interpreter.canonicalize().map_err(|_| invalid())?;
+    let version = get_command_output(&interpreter.display().to_string(), &["--version"])
+        .ok_or_else(invalid)?;
+    if !version.starts_with("Python 3") {
+        return Err(invalid());
+    }
+    info!(
+        "{} Using {} from '{}'",
+        emoji::INFO,
+        version,
+        interpreter.display()
+    );
+    Ok(interpreter.display().to_string())
+}
+
+/// Installs the ESP-IDF tools with `idf_tools.py` as the installer of ESP-IDF does, but
+/// without creating its Python virtual environment, and returns the `PATH` including them.
+/// `python_env`, if any, is only read to export the tools.
+fn install_idf_tools(
+    context: &Context,
+    espidf_dir: &Path,
+    python_env: Option<&Path>,
+    tools: Vec<espidf::Tools>,
+) -> Result<OsString, Error> {
+    let idf_tools = espidf_dir.join("tools").join("idf_tools.py");
+    let mut exported_paths: Vec<PathBuf> = Vec::new();
+    for tool in tools {
+        let tools_json: Vec<OsString> = tool
+            .index
+            .iter()
+            .flat_map(|index| [OsString::from("--tools-json"), index.clone().into()])
+            .collect();
+        cmd!(
+            get_python(context), &idf_tools, "--idf-path", espidf_dir, @tools_json.clone(), "install";
+            envs=(context.envs()), args=(tool.tools)
+        )
+        .run()?;
+        let mut export = cmd!(
+            get_python(context), &idf_tools, "--idf-path", espidf_dir, @tools_json, "--quiet", "export", "--format=key-value";
+            ignore_exitcode=(), envs=(context.envs()), env_remove=("MSYSTEM")
+        );
+        if let Some(python_env) = python_env {
+            export.env("IDF_PYTHON_ENV_PATH", python_env);
+        }
+        for path in get_exported_tool_paths(&export.stdout()?) {
+            if !exported_paths.contains(&path) {
+                exported_paths.push(path);
+            }
+        }
+    }
+    let path = env::var_os("PATH").unwrap_or_default();
+    env::join_paths(exported_paths.into_iter().chain(env::split_paths(&path)))
+        .map_err(|_| Error::FailedToInstallEspIdf)
+}
+
+/// Creates the Python virtual environment of an ESP-IDF checkout in its default location,
+/// with the interpreter of the context, unless it exists.
+fn create_default_python_env(context: &Context, espidf_dir: &Path) -> Result<(), Error> {
+    if get_default_python_env(context, espidf_dir)?.exists() {
+        return Ok(());
+    }
+    let idf_tools = espidf_dir.join("tools").join("idf_tools.py");
+    cmd!(
+        get_python(context), &idf_tools, "--idf-path", espidf_dir, "--non-interactive", "install-python-env";
+        envs=(context.envs()), env_remove=("IDF_PYTHON_ENV_PATH")
+    )
+    .run()?;
+    Ok(())
+}
+
+/// Links the directory `link` to `target`. Creating symbolic links on Windows requires the
+/// developer mode or elevated rights, a junction is created instead when it is denied.
+fn link_dir(target: &Path, link: &Path) -> Result<(), Error> {
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, link)?;
+    #[cfg(windows)]
+    if let Err(e) = std::os::windows::fs::symlink_dir(target, link) {
+        debug!(
+            "{} Failed to link '{}', creating a junction: {}",
+            emoji::DEBUG,
+            link.display(),
+            e
+        );
+        cmd!("cmd", "/C", "mklink", "/J", link, target).stdout()?;
+    }
+    Ok(())
+}
+
+/// Parses the output of `idf_tools.py export --format=key-value`.
+fn parse_key_values(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Gets the paths of the tools from the output of `idf_tools.py export --format=key-value`,
+/// whose `PATH` ends with the current one.
+fn get_exported_tool_paths(output: &str) -> Vec<PathBuf> {
+    let separator = if cfg!(windows) { ';' } else { ':' };
+    parse_key_values(output)
+        .into_iter()
+        .find(|(key, _)| key == "PATH")
+        .and_then(|(_, paths)| {
+            paths
+                .rsplit_once(separator)
+                .map(|(paths, _)| paths.split(separator).map(PathBuf::from).collect())
+        })
+        .unwrap_or_default()
+}
+
+/// Gets the environment variables to export for an installed ESP-IDF, using the tools
+/// and the Python environment that are already installed.
+pub fn get_exports(context: &Context, espidf_dir: &Path) -> Result<Vec<String>, Error> {
+    let mut exports: Vec<String> = Vec::new();
+    #[cfg(windows)]
+    exports.push(format!("$Env:IDF_PATH=\"{}\"", espidf_dir.display()));
+    #[cfg(unix)]
+    exports.push(format!("export IDF_PATH={}", espidf_dir.display()));
+    let idf_tools = espidf_dir.join("tools").join("idf_tools.py");
+    let output = cmd!(
+        get_python(context), idf_tools, "--idf-path", espidf_dir, "--quiet", "export", "--format=key-value";
+        envs=(context.envs())
+    )
+    .stdout()?;
+    for (key, value) in parse_key_values(&output) {
+        #[cfg(windows)]
+        exports.push(format!(
+            "$Env:{key}=\"{}\"",
+            value
+                .replace("%PATH%", "$Env:PATH")
+                .replace("$PATH", "$Env:PATH")
+        ));
+        #[cfg(unix)]
+        exports.push(format!("export {key}=\"{value}\""));
+    }
+    #[cfg(windows)]
+    exports.push(format!("$Env:IDF_TOOLS_PATH=\"{}\"", context.tools_path()));
+    #[cfg(unix)]
+    exports.push(format!(
+        "export IDF_TOOLS_PATH=\"{}\"",
+        context.tools_path()
+    ));
+    Ok(exports)
+}
+
+/// Gets the released ESP-IDF versions from the tags of the remote repository,
+/// newest first.
+pub fn get_remote_versions(context: &Context, repository_url: &str) -> Result<Vec<String>, Error> {
+    let output =
+        cmd!("git", "ls-remote", "--tags", "--refs", repository_url; envs=(context.envs()))
+            .stdout()?;
+    Ok(parse_version_tags(&output))
 }
 
-/// Gets the esp-idf installation path.
-pub fn get_install_path(repo: EspIdfRemote) -> PathBuf {
-    let mut hasher = DefaultHasher::new();
-    repo.repo_url.as_ref().unwrap().hash(&mut hasher);
-    let repo_url_hash = format!("{:x}", hasher.finish());
-    let repo_dir = match repo.git_ref {
+/// Parses the output of `git ls-remote --tags` and returns the stable version tags
+/// (`v<major>.<minor>[.<patch>]`), newest first.
+fn parse_version_tags(ls_remote: &str) -> Vec<String> {
+    let mut versions: Vec<(Vec<u64>, String)> = ls_remote
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .filter_map(|git_ref| git_ref.strip_prefix("refs/tags/"))
+        .filter_map(|tag| {
+            let numbers = tag
+                .strip_prefix('v')?
+                .split('.')
+                .map(|n| n.parse::<u64>().ok())
+                .collect::<Option<Vec<u64>>>()?;
+            if numbers.len() < 2 || numbers.len() > 3 {
+                return None;
+            }
+            Some((numbers, tag.to_string()))
+        })
+        .collect();
+    versions.sort_by(|a, b| b.0.cmp(&a.0));
+    versions.into_iter().map(|(_, tag)| tag).collect()
+}
+
+/// Gets the name of the directory of a git reference, e.g. `v5.1`, `release-v5.1` or
+/// `pull-1234` for `refs/pull/1234/head`.
+fn get_ref_dir(git_ref: &git::Ref) -> String {
+    let repo_dir = match git_ref {
+        git::Ref::Commit(n) if is_ref_name(n) => {
+            let name = n.trim_start_matches("refs/");
+            name.strip_suffix("/head").unwrap_or(name)
+        }
         git::Ref::Branch(n) | git::Ref::Tag(n) | git::Ref::Commit(n) => n,
     };
     // Replace all directory separators with a dash `-`, so that we don't create
     // subfolders for tag or branch names that contain such characters.
-    let repo_dir = repo_dir.replace(['/', '\\'], "-");
+    repo_dir.replace(['/', '\\'], "-")
+}
+
+/// Parses an ESP-IDF version: the formats of ESP-IDF's build, e.g. `v5.1`, `branch:master`
+/// or `commit:<hash>`, along with bare commit hashes, e.g. `2b8d1a3`, and references, e.g.
+/// `refs/pull/1234/head`, which are fetched as commits.
+pub fn parse_esp_idf_ref(version: &str) -> git::Ref {
+    let version = version.trim();
+    if is_commit_hash(version) || is_ref_name(version) {
+        return git::Ref::Commit(version.to_string());
+    }
+    espidf::parse_esp_idf_git_ref(version)
+}
+
+/// Whether a version is a full or abbreviated commit hash, of 7 to 40 hexadecimal digits.
+fn is_commit_hash(version: &str) -> bool {
+    (7..=40).contains(&version.len()) && version.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Whether the name of a commit is a reference, e.g. `refs/pull/1234/head`, which can move,
+/// rather than a hash.
+pub fn is_ref_name(name: &str) -> bool {
+    name.starts_with("refs/")
+}
+
+/// Resolves the ESP-IDF remote of a repository, the default one when `None`, and of a
+/// version, e.g. `v5.1` or `branch:master`. The remote is used for every path of the
+/// checkout, so that they match the ones of the ESP-IDF installer.
+pub fn get_esp_idf_remote(repository_url: Option<&str>, version: &str) -> EspIdfRemote {
+    EspIdfRemote {
+        git_ref: parse_esp_idf_ref(version),
+        repo_url: Some(
+            repository_url
+                .unwrap_or(DEFAULT_GIT_REPOSITORY)
+                .trim_end_matches('/')
+                .to_string(),
+        ),
+    }
+}
 
-    let mut install_path = PathBuf::from(get_tools_path());
-    install_path = install_path.join(PathBuf::from(format!("esp-idf-{}", repo_url_hash)));
-    install_path = install_path.join(PathBuf::from(repo_dir));
+/// Gets the directory where espup installs an ESP-IDF version from a repository, the
+/// default one when `None`, e.g. for external tools locating the checkouts of espup.
+pub fn get_esp_idf_install_dir(
+    context: &Context,
+    repository_url: Option<&str>,
+    version: &str,
+) -> PathBuf {
+    get_install_path(context, get_esp_idf_remote(repository_url, version))
+}
+
+/// Gets the esp-idf installation path of the hashed naming scheme, the one of the ESP-IDF
+/// installer: `esp-idf-<hash of the repository URL>/<version>`, or `esp-idf/<version>`
+/// without a repository URL.
+fn get_hashed_install_path(context: &Context, repo: &EspIdfRemote) -> PathBuf {
+    context
+        .tools_path
+        .join(get_hashed_checkouts_dir_name(repo.repo_url.as_deref()))
+        .join(get_ref_dir(&repo.git_ref))
+}
+
+/// Gets the name of the directory of the checkouts of a repository with the hashed naming
+/// scheme, `esp-idf-<hash of the repository URL>`.
+pub fn get_hashed_checkouts_dir_name(repo_url: Option<&str>) -> String {
+    match repo_url {
+        Some(repo_url) => {
+            let mut hasher = DefaultHasher::new();
+            repo_url.hash(&mut hasher);
+            format!("{}-{:x}", ESP_IDF_DIR_NAME, hasher.finish())
+        }
+        None => ESP_IDF_DIR_NAME.to_string(),
+    }
+}
+
+/// Whether a URL is the one of the default ESP-IDF repository, over HTTPS or SSH.
+fn is_default_repository(url: &str) -> bool {
+    let url = url.trim_end_matches('/').trim_end_matches(".git");
+    url == DEFAULT_GIT_REPOSITORY
+        || url == "git@github.com:espressif/esp-idf"
+        || url == "ssh://git@github.com/espressif/esp-idf"
+}
+
+/// Gets the esp-idf installation path, `esp-idf/<version>` for the default repository with
+/// the readable naming scheme, see [`EspIdfLayout`]. Checkouts of previous espup versions
+/// keep their hashed path.
+pub fn get_install_path(context: &Context, repo: EspIdfRemote) -> PathBuf {
+    let hashed_path = get_hashed_install_path(context, &repo);
+    if context.esp_idf_layout == EspIdfLayout::Hashed || !is_default_repository(repo.repo_url()) {
+        return hashed_path;
+    }
+    let install_path = context
+        .tools_path
+        .join(ESP_IDF_DIR_NAME)
+        .join(get_ref_dir(&repo.git_ref));
+    if !install_path.exists() && hashed_path.exists() {
+        return hashed_path;
+    }
     install_path
 }
 
-/// Gets path where esp-idf tools where be downloaded and installed. If environment
-/// variable IDF_TOOLS_PATH is not set. Uses HOME/.espressif on Linux and macOS,
-/// and %USER_PROFILE%\.espressif on Windows.
-pub fn get_tools_path() -> String {
-    env::var("IDF_TOOLS_PATH").unwrap_or_else(|_e| get_home_dir() + "/.espressif")
+/// Points the `current` link, next to the ESP-IDF checkouts of the same repository, at
+/// the active checkout.
+pub fn link_current(espidf_dir: &Path) {
+    let (parent, target) = match (espidf_dir.parent(), espidf_dir.file_name()) {
+        (Some(parent), Some(target)) => (parent, target),
+        _ => return,
+    };
+    let link = parent.join(CURRENT_LINK_NAME);
+    if link.symlink_metadata().is_ok() {
+        // Directory links are directories on Windows.
+        let _ = remove_file(&link).or_else(|_| std::fs::remove_dir(&link));
+    }
+    // The link is relative, so that it survives moving the tools path.
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink(target, &link);
+    #[cfg(windows)]
+    let result = std::os::windows::fs::symlink_dir(target, &link);
+    if let Err(e) = result {
+        debug!(
+            "{} Failed to link '{}' to '{}': {}",
+            emoji::DEBUG,
+            link.display(),
+            espidf_dir.display(),
+            e
+        );
+    }
+}
+
+/// Removes the `current` link of the ESP-IDF checkouts next to `espidf_dir` when it points
+/// to `espidf_dir`.
+pub fn unlink_current(espidf_dir: &Path) {
+    let (parent, target) = match (espidf_dir.parent(), espidf_dir.file_name()) {
+        (Some(parent), Some(target)) => (parent, target),
+        _ => return,
+    };
+    let link = parent.join(CURRENT_LINK_NAME);
+    if std::fs::read_link(&link).map_or(false, |linked| linked.as_os_str() == target) {
+        let _ = remove_file(&link).or_else(|_| std::fs::remove_dir(&link));
+    }
+}
+
+/// Gets path where esp-idf tools where be downloaded and installed. Uses, in order,
+/// the IDF_TOOLS_PATH and ESPUP_HOME environment variables, `$XDG_DATA_HOME/espup` on
+/// Linux (unless a previous installation exists in HOME/.espressif) and HOME/.espressif
+/// on macOS, or %USER_PROFILE%\.espressif on Windows.
+pub fn get_default_tools_path() -> String {
+    env::var("IDF_TOOLS_PATH")
+        .or_else(|_e| env::var(ESPUP_HOME))
+        .unwrap_or_else(|_e| {
+            if use_xdg_dirs() {
+                if let Some(xdg_tools_path) = get_xdg_tools_path() {
+                    return xdg_tools_path.display().to_string();
+                }
+            }
+            get_legacy_tools_path()
+        })
+}
+
+/// Gets the HOME/.espressif path used by previous espup versions and ESP-IDF.
+pub fn get_legacy_tools_path() -> String {
+    get_home_dir() + "/.espressif"
+}
+
+/// Gets the tools path of the XDG base directories, `$XDG_DATA_HOME/espup`, only used on
+/// Linux.
+pub fn get_xdg_tools_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|data_dir| data_dir.join("espup"))
+}
+
+/// Returns whether the XDG base directories should be used: only on Linux, when no
+/// installation directory is set and there is no previous installation in HOME/.espressif,
+/// other than the link left by `espup migrate`.
+fn use_xdg_dirs() -> bool {
+    let legacy_tools_path = get_legacy_tools_path();
+    cfg!(target_os = "linux")
+        && env::var_os("IDF_TOOLS_PATH").is_none()
+        && env::var_os(ESPUP_HOME).is_none()
+        && (!Path::new(&legacy_tools_path).exists()
+            || std::fs::read_link(&legacy_tools_path).ok() == get_xdg_tools_path())
 }
 
 /// Gets the espressif tools directory path. Tools directory is where the tools
 /// are extracted.
-pub fn get_tool_path(tool_name: &str) -> String {
-    format!("{}/tools/{}", get_tools_path(), tool_name)
+pub fn get_tool_path(context: &Context, tool_name: &str) -> String {
+    format!("{}/tools/{}", context.tools_path(), tool_name)
 }
 
 /// Gets the Espressif dist directory path. Dist directory is where the archives
-/// of the tools are downloaded.
-pub fn get_dist_path(tool_name: &str) -> String {
-    let tools_path = get_tools_path();
-    format!("{}/dist/{}", tools_path, tool_name)
+/// of the tools are downloaded, in the XDG cache directory when the tools are installed
+/// in the default XDG directory.
+pub fn get_dist_path(context: &Context, tool_name: &str) -> String {
+    if use_xdg_dirs() && context.tools_path == Path::new(&get_default_tools_path()) {
+        if let Some(cache_dir) = dirs::cache_dir() {
+            return format!("{}/espup/dist/{}", cache_dir.display(), tool_name);
+        }
+    }
+    format!("{}/dist/{}", context.tools_path(), tool_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        context::Context,
+        targets::{parse_targets, Target},
+        toolchain::espidf::{
+            fast_forward, get_archive_url, get_esp_idf_install_dir, get_esp_idf_remote,
+            get_exported_tool_paths, get_hashed_install_path, get_index_tools, get_ref_dir,
+            get_targets_arg, has_tool_build, is_default_repository, is_ref_name, parse_esp_idf_ref,
+            parse_key_values, parse_version_tags, resolve_python, BranchUpdate, EspIdfLayout,
+            Generator, MinifyProfile, Submodules,
+        },
+    };
+    use embuild::{cmd, git};
+    use std::{
+        fs::{create_dir_all, write},
+        path::{Path, PathBuf},
+        str::FromStr,
+    };
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_fast_forward() {
+        let temp_dir = TempDir::new().unwrap();
+        let origin = temp_dir.path().join("origin");
+        create_dir_all(origin.join("tools")).unwrap();
+        let commit = |path: &Path, message: &str| {
+            cmd!("git", "add", "-A"; current_dir=(path)).run().unwrap();
+            cmd!("git", "-c", "user.name=espup", "-c", "user.email=espup@example.com", "commit", "--quiet", "-m", message; current_dir=(path)).run().unwrap();
+        };
+        cmd!("git", "init", "--quiet", "--initial-branch", "master"; current_dir=(&origin))
+            .run()
+            .unwrap();
+        write(origin.join("tools").join("tools.json"), "{\"tools\": []}").unwrap();
+        commit(&origin, "Initial commit");
+        let espidf_dir = temp_dir.path().join("esp-idf");
+        cmd!("git", "clone", "--quiet", &origin, &espidf_dir)
+            .run()
+            .unwrap();
+        let context = Context::default();
+        assert_eq!(
+            fast_forward(&context, &espidf_dir, "master").unwrap(),
+            BranchUpdate::UpToDate
+        );
+
+        write(origin.join("README.md"), "ESP-IDF").unwrap();
+        commit(&origin, "Add README");
+        assert_eq!(
+            fast_forward(&context, &espidf_dir, "master").unwrap(),
+            BranchUpdate::Updated
+        );
+        assert!(espidf_dir.join("README.md").exists());
+
+        write(
+            origin.join("tools").join("tools.json"),
+            "{\"tools\": [{\"name\": \"ninja\"}]}",
+        )
+        .unwrap();
+        commit(&origin, "Add ninja");
+        assert_eq!(
+            fast_forward(&context, &espidf_dir, "master").unwrap(),
+            BranchUpdate::ToolsChanged
+        );
+    }
+
+    #[test]
+    fn test_get_archive_url() {
+        assert_eq!(
+            get_archive_url(&git::Ref::Tag("v5.1".to_string())).unwrap(),
+            "https://github.com/espressif/esp-idf/releases/download/v5.1/esp-idf-v5.1.zip"
+        );
+        assert!(get_archive_url(&git::Ref::Branch("master".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_esp_idf_layout() {
+        assert_eq!(
+            EspIdfLayout::from_str("Hashed").unwrap(),
+            EspIdfLayout::Hashed
+        );
+        assert_eq!(EspIdfLayout::default().to_string(), "readable");
+        assert!(is_default_repository(
+            "https://github.com/espressif/esp-idf.git"
+        ));
+        assert!(is_default_repository(
+            "https://github.com/espressif/esp-idf/"
+        ));
+        assert!(is_default_repository(
+            "git@github.com:espressif/esp-idf.git"
+        ));
+        assert!(!is_default_repository(
+            "https://jihulab.com/esp-mirror/espressif/esp-idf"
+        ));
+        assert_eq!(get_ref_dir(&git::Ref::Tag("v5.1".to_string())), "v5.1");
+        assert_eq!(
+            get_ref_dir(&git::Ref::Branch("release/v5.1".to_string())),
+            "release-v5.1"
+        );
+        assert_eq!(
+            get_ref_dir(&git::Ref::Commit("refs/pull/1234/head".to_string())),
+            "pull-1234"
+        );
+        assert_eq!(
+            get_ref_dir(&git::Ref::Commit(
+                "refs/merge-requests/56/merge".to_string()
+            )),
+            "merge-requests-56-merge"
+        );
+        assert_eq!(
+            get_ref_dir(&git::Ref::Commit("2b8d1a3".to_string())),
+            "2b8d1a3"
+        );
+    }
+
+    #[test]
+    fn test_parse_esp_idf_ref() {
+        assert!(matches!(parse_esp_idf_ref("v5.1"), git::Ref::Tag(tag) if tag == "v5.1"));
+        assert!(matches!(parse_esp_idf_ref("5.1"), git::Ref::Tag(tag) if tag == "v5.1"));
+        assert!(
+            matches!(parse_esp_idf_ref("master"), git::Ref::Branch(branch) if branch == "master")
+        );
+        // Hashes starting with a digit are not versions.
+        assert!(
+            matches!(parse_esp_idf_ref("2b8d1a3"), git::Ref::Commit(hash) if hash == "2b8d1a3")
+        );
+        let hash = "a9d7cc0e83ae1d5b2e8fc3c22cfc0f7e62a26a3f";
+        assert!(matches!(parse_esp_idf_ref(hash), git::Ref::Commit(commit) if commit == hash));
+        assert!(matches!(
+            parse_esp_idf_ref(&format!("commit:{hash}")),
+            git::Ref::Commit(commit) if commit == hash
+        ));
+        assert!(matches!(
+            parse_esp_idf_ref("refs/pull/1234/head"),
+            git::Ref::Commit(reference) if reference == "refs/pull/1234/head"
+        ));
+        assert!(matches!(
+            parse_esp_idf_ref("branch:feature"),
+            git::Ref::Branch(_)
+        ));
+        assert!(is_ref_name("refs/pull/1234/head"));
+        assert!(!is_ref_name(hash));
+    }
+
+    #[test]
+    fn test_get_esp_idf_install_dir() {
+        let custom = "https://git.example.com/espressif/esp-idf";
+        let remote = get_esp_idf_remote(Some(&format!("{custom}/")), "branch:release/v5.1");
+        assert_eq!(remote.repo_url(), custom);
+        assert!(matches!(&remote.git_ref, git::Ref::Branch(branch) if branch == "release/v5.1"));
+        // The custom repositories use the hashed path of the ESP-IDF installer, the same
+        // whichever way their URL is given.
+        let context = Context::default();
+        let install_dir = get_esp_idf_install_dir(&context, Some(custom), "branch:release/v5.1");
+        assert_eq!(install_dir, get_hashed_install_path(&context, &remote));
+        assert_eq!(
+            install_dir,
+            get_esp_idf_install_dir(&context, Some(&format!("{custom}/")), "branch:release/v5.1")
+        );
+        assert!(install_dir.ends_with("release-v5.1"));
+        assert_ne!(
+            install_dir.parent(),
+            get_esp_idf_install_dir(&context, None, "branch:release/v5.1").parent()
+        );
+    }
+
+    #[test]
+    fn test_get_targets_arg() {
+        assert_eq!(
+            get_targets_arg(&parse_targets("esp32s3,esp32c3").unwrap()),
+            "--targets=esp32c3,esp32s3"
+        );
+        assert_eq!(
+            get_targets_arg(&[Target::ESP32].into_iter().collect()),
+            "--targets=esp32"
+        );
+    }
+
+    #[test]
+    fn test_has_tool_build() {
+        let tools_index = serde_json::json!({
+            "tools": [{
+                "name": "ninja",
+                "versions": [
+                    { "name": "1.11.1", "status": "recommended", "linux-amd64": {}, "linux-arm64": {} },
+                    { "name": "1.10.2", "status": "supported", "linux-amd64": {} }
+                ]
+            }]
+        });
+        assert!(has_tool_build(&tools_index, "ninja", "linux-arm64"));
+        assert!(!has_tool_build(&tools_index, "ninja", "linux-armhf"));
+        assert!(!has_tool_build(&tools_index, "cmake", "linux-arm64"));
+    }
+
+    #[test]
+    fn test_get_index_tools() {
+        let build = |platform: &str| serde_json::json!({ "name": "1.0", "status": "recommended", platform: {} });
+        let tools_index = serde_json::json!({
+            "tools": [
+                { "name": "xtensa-esp-elf", "install": "always", "supported_targets": ["esp32", "esp32s3"], "versions": [build("linux-amd64")] },
+                { "name": "riscv32-esp-elf", "install": "always", "supported_targets": ["esp32c3"], "versions": [build("linux-amd64")] },
+                { "name": "esp32ulp-elf", "install": "always", "supported_targets": ["esp32"], "versions": [build("win64")] },
+                { "name": "xtensa-esp-elf-gdb", "install": "always", "supported_targets": ["all"], "versions": [build("linux-amd64")] },
+                { "name": "ninja", "install": "on_request", "versions": [build("linux-amd64")] },
+                { "name": "idf-exe", "install": "never", "platform_overrides": [{ "install": "always", "platforms": ["win64"] }], "versions": [build("win64")] }
+            ]
+        });
+        let targets = [Target::ESP32].into_iter().collect();
+        assert_eq!(
+            get_index_tools(&tools_index, "linux-amd64", &targets, &["ninja", "ccache"]),
+            vec![
+                ("xtensa-esp-elf".to_string(), "1.0".to_string()),
+                ("ninja".to_string(), "1.0".to_string())
+            ]
+        );
+        let tools: Vec<String> = get_index_tools(&tools_index, "win64", &targets, &["idf-exe"])
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(tools, vec!["esp32ulp-elf", "idf-exe"]);
+    }
+
+    #[test]
+    fn test_parse_generator() {
+        assert_eq!(Generator::from_str("ninja").unwrap(), Generator::Ninja);
+        assert_eq!(
+            Generator::from_str("Unix-Makefiles").unwrap(),
+            Generator::UnixMakefiles
+        );
+        assert_eq!(
+            Generator::NMakeMakefilesJOM.to_string(),
+            "nmake-makefiles-jom"
+        );
+        assert!(Generator::from_str("Unix Makefiles").is_err());
+    }
+
+    #[test]
+    fn test_parse_version_tags() {
+        let ls_remote = "\
+1111111111111111111111111111111111111111\trefs/tags/v4.4
+2222222222222222222222222222222222222222\trefs/tags/v4.4.3
+3333333333333333333333333333333333333333\trefs/tags/v5.0
+4444444444444444444444444444444444444444\trefs/tags/v5.0-beta1
+5555555555555555555555555555555555555555\trefs/tags/v5.1-dev
+6666666666666666666666666666666666666666\trefs/tags/v4.10.1
+7777777777777777777777777777777777777777\trefs/tags/qa-test-v5.0";
+        assert_eq!(
+            parse_version_tags(ls_remote),
+            vec!["v5.0", "v4.10.1", "v4.4.3", "v4.4"]
+        );
+        assert!(parse_version_tags("").is_empty());
+    }
+
+    #[test]
+    fn test_minify_profile() {
+        let profile =
+            MinifyProfile::new(&["examples/".to_string()], &["components/bt".to_string()]);
+        assert_eq!(
+            profile.paths,
+            vec![
+                "docs",
+                "tools/esp_app_trace",
+                "tools/test_idf_size",
+                "components/bt"
+            ]
+        );
+        assert_eq!(
+            profile.sparse_checkout_patterns(),
+            vec![
+                "/*",
+                "!/docs/",
+                "!/tools/esp_app_trace/",
+                "!/tools/test_idf_size/",
+                "!/components/bt/"
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_get_exported_tool_paths() {
+        let output = "IDF_PYTHON_ENV_PATH=/opt/venv\nPATH=/opt/espup/tools/cmake/bin:/opt/espup/tools/ninja:$PATH\n";
+        assert_eq!(
+            get_exported_tool_paths(output),
+            vec![
+                PathBuf::from("/opt/espup/tools/cmake/bin"),
+                PathBuf::from("/opt/espup/tools/ninja")
+            ]
+        );
+        assert!(get_exported_tool_paths("IDF_PYTHON_ENV_PATH=/opt/venv\n").is_empty());
+    }
+
+    #[test]
+    fn test_parse_key_values() {
+        let output = "\
+OPENOCD_SCRIPTS=/home/esp/.espressif/tools/openocd-esp32/share/openocd/scripts
+IDF_PYTHON_ENV_PATH=/home/esp/.espressif/python_env/idf5.0_py3.10_env
+PATH=/home/esp/.espressif/tools/xtensa-esp32-elf/bin:$PATH
+";
+        assert_eq!(
+            parse_key_values(output),
+            vec![
+                (
+                    "OPENOCD_SCRIPTS".to_string(),
+                    "/home/esp/.espressif/tools/openocd-esp32/share/openocd/scripts".to_string()
+                ),
+                (
+                    "IDF_PYTHON_ENV_PATH".to_string(),
+                    "/home/esp/.espressif/python_env/idf5.0_py3.10_env".to_string()
+                ),
+                (
+                    "PATH".to_string(),
+                    "/home/esp/.espressif/tools/xtensa-esp32-elf/bin:$PATH".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_submodules() {
+        assert_eq!(Submodules::parse("all").unwrap(), Submodules::All);
+        assert_eq!(Submodules::parse("None").unwrap(), Submodules::None);
+        assert_eq!(
+            Submodules::parse("components/mbedtls/, components/lwip").unwrap(),
+            Submodules::Only(vec![
+                "components/mbedtls".to_string(),
+                "components/lwip".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_use_invalid_python() {
+        assert!(resolve_python(Path::new("espup-missing-python")).is_err());
+        assert!(resolve_python(Path::new("/espup/missing/python3")).is_err());
+    }
 }
@@ -0,0 +1,214 @@
+//! Espressif frameworks built on top of ESP-IDF, e.g. ESP-ADF for audio applications or
+//! ESP-Matter, cloned next to the ESP-IDF checkouts like ESP-IDF itself.
+
+use crate::{
+    context::Context,
+    emoji,
+    error::Error,
+    ssh,
+    toolchain::espidf::{clone_repository, get_python, Submodules, SUBMODULE_JOBS},
+};
+use embuild::{cmd, git};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use strum::{Display, EnumIter, EnumString};
+
+/// Framework installed along with ESP-IDF.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Display,
+    EnumIter,
+    EnumString,
+    Deserialize,
+    Serialize,
+)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case", ascii_case_insensitive)]
+pub enum Framework {
+    /// Audio Development Framework.
+    EspAdf,
+    /// Matter SDK.
+    EspMatter,
+}
+
+impl Framework {
+    /// Gets the default repository of the framework.
+    fn default_repository(&self) -> &'static str {
+        match self {
+            Self::EspAdf => "https://github.com/espressif/esp-adf",
+            Self::EspMatter => "https://github.com/espressif/esp-matter",
+        }
+    }
+
+    /// Gets the branch that is cloned.
+    pub fn branch(&self) -> &'static str {
+        match self {
+            Self::EspAdf => "master",
+            Self::EspMatter => "main",
+        }
+    }
+
+    /// Gets the variable exported with the path of the framework.
+    pub fn path_variable(&self) -> &'static str {
+        match self {
+            Self::EspAdf => "ADF_PATH",
+            Self::EspMatter => "ESP_MATTER_PATH",
+        }
+    }
+
+    /// Gets the estimated disk usage of the checkout, in bytes.
+    pub fn disk_usage(&self) -> u64 {
+        match self {
+            Self::EspAdf => 512 * 1024 * 1024,
+            // The connectedhomeip submodule and its bootstrapped environment.
+            Self::EspMatter => 4096 * 1024 * 1024,
+        }
+    }
+
+    /// Gets the URL of the repository, which can be replaced in the `[urls]` table of the
+    /// configuration file.
+    pub fn get_repository_url(&self, context: &Context) -> String {
+        context
+            .http
+            .base_url(&self.to_string(), self.default_repository())
+    }
+
+    /// Gets the path of the checkout, next to the ESP-IDF checkouts.
+    pub fn get_path(&self, context: &Context) -> PathBuf {
+        context.tools_path.join(self.to_string())
+    }
+
+    /// Gets the command of the install script of the framework, run from its checkout.
+    pub fn get_install_command(&self) -> Option<&'static str> {
+        match self {
+            // The install script of ESP-ADF installs the tools of its own ESP-IDF submodule.
+            Self::EspAdf => None,
+            Self::EspMatter => Some("./install.sh"),
+        }
+    }
+
+    /// Gets the environment variables to export.
+    pub fn get_exports(&self, context: &Context) -> Vec<String> {
+        let mut exports: Vec<String> = Vec::new();
+        #[cfg(windows)]
+        exports.push(format!(
+            "$Env:{}=\"{}\"",
+            self.path_variable(),
+            self.get_path(context).display()
+        ));
+        #[cfg(unix)]
+        exports.push(format!(
+            "export {}=\"{}\"",
+            self.path_variable(),
+            self.get_path(context).display()
+        ));
+        exports
+    }
+
+    /// Initializes the submodules of the checkout, skipping the copies of ESP-IDF.
+    fn update_submodules(
+        &self,
+        context: &Context,
+        path: &Path,
+        shallow: bool,
+    ) -> Result<(), Error> {
+        info!(
+            "{} Updating submodules of '{}'",
+            emoji::DOWNLOAD,
+            path.display()
+        );
+        let depth: &[&str] = if shallow { &["--depth", "1"] } else { &[] };
+        match self {
+            Self::EspAdf => {
+                cmd!("git", "-c", "submodule.esp-idf.update=none", "submodule", "update", "--init", "--recursive", "--jobs", SUBMODULE_JOBS; args=(depth), current_dir=(path), envs=(context.envs())).run()?;
+            }
+            // The submodules of connectedhomeip are only needed for the ESP32 and the host.
+            Self::EspMatter => {
+                cmd!("git", "submodule", "update", "--init", "--jobs", SUBMODULE_JOBS; args=(depth), current_dir=(path), envs=(context.envs())).run()?;
+                let checkout_submodules = path
+                    .join("connectedhomeip")
+                    .join("connectedhomeip")
+                    .join("scripts")
+                    .join("checkout_submodules.py");
+                let shallow_arg: &[&str] = if shallow { &["--shallow"] } else { &[] };
+                cmd!(get_python(context), checkout_submodules, "--platform", "esp32", "linux"; args=(shallow_arg), current_dir=(path), envs=(context.envs())).run()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Clones the framework, if not cloned yet, and runs its install script for the
+    /// ESP-IDF checkout `espidf_dir`.
+    pub fn install(
+        &self,
+        context: &Context,
+        espidf_dir: &Path,
+        shallow: bool,
+    ) -> Result<Vec<String>, Error> {
+        let path = self.get_path(context);
+        debug!("{} {} path: {}", emoji::DEBUG, self, path.display());
+        if path.join(".git").exists() {
+            warn!(
+                "{} Previous installation of {} exist in: '{}'. Reusing this installation.",
+                emoji::WARN,
+                self,
+                path.display()
+            );
+        } else {
+            let url = self.get_repository_url(context);
+            ssh::check_access(&url)?;
+            context.rollback.track(&path);
+            clone_repository(
+                context,
+                &url,
+                &git::Ref::Branch(self.branch().to_string()),
+                &path,
+                shallow,
+                &Submodules::None,
+            )?;
+            self.update_submodules(context, &path, shallow)?;
+        }
+
+        if let Some(install_command) = self.get_install_command() {
+            if cfg!(windows) {
+                warn!(
+                    "{} Skipping the install script of {}, it only runs on Linux and macOS",
+                    emoji::WARN,
+                    self
+                );
+            } else {
+                info!("{} Running the install script of {}", emoji::WRENCH, self);
+                cmd!(install_command; current_dir=(&path), envs=(context.envs()), env=("IDF_PATH", espidf_dir)).run()?;
+            }
+        }
+        Ok(self.get_exports(context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{context::Context, toolchain::frameworks::Framework};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_framework() {
+        assert_eq!(Framework::from_str("esp-adf").unwrap(), Framework::EspAdf);
+        assert_eq!(
+            Framework::from_str("ESP-Matter").unwrap(),
+            Framework::EspMatter
+        );
+        assert!(Framework::from_str("esp-idf").is_err());
+        assert_eq!(Framework::EspAdf.path_variable(), "ADF_PATH");
+        assert!(Framework::EspMatter
+            .get_path(&Context::default())
+            .ends_with(Framework::EspMatter.to_string()));
+    }
+}
@@ -1,23 +1,78 @@
 //! GCC Toolchain source and installation tools
 
 use crate::{
+    context::Context,
     emoji,
     error::Error,
-    host_triple::HostTriple,
+    host_triple::{get_component_host, log_emulated_component, HostTriple},
+    resume::{install_component, is_installed},
     targets::Target,
-    toolchain::{download_file, espidf::get_tool_path},
+    toolchain::{download_file, espidf::get_tool_path, install_concurrently},
 };
 use embuild::espidf::EspIdfVersion;
 use log::{debug, info, warn};
 use miette::Result;
 use std::{
-    collections::HashSet,
-    path::{Path, PathBuf},
+    collections::{BTreeMap, HashSet},
+    path::Path,
+    str::FromStr,
 };
 
-const DEFAULT_GCC_REPOSITORY: &str = "https://github.com/espressif/crosstool-NG/releases/download";
-const DEFAULT_GCC_RELEASE: &str = "esp-2021r2-patch5";
-const DEFAULT_GCC_VERSION: &str = "8_4_0";
+pub const DEFAULT_GCC_REPOSITORY: &str =
+    "https://github.com/espressif/crosstool-NG/releases/download";
+pub const DEFAULT_GCC_VERSION: &str = "8.4.0";
+/// Supported GCC versions, along with the crosstool-NG release providing them, the
+/// extension of its non-Windows artifacts and whether it provides native Apple Silicon ones.
+const GCC_RELEASES: [(&str, &str, &str, bool); 2] = [
+    ("8.4.0", "esp-2021r2-patch5", "tar.gz", false),
+    ("11.2.0", "esp-2022r1", "tar.xz", true),
+];
+
+/// GCC version to install, for every target or for a single one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GccVersion {
+    /// Target the version applies to. `None` applies to every target.
+    pub target: Option<Target>,
+    /// GCC version, e.g. `11.2.0`.
+    pub version: String,
+}
+
+impl GccVersion {
+    /// Parses a `<version>` or `<target>=<version>` argument.
+    pub fn parse(arg: &str) -> Result<Self, Error> {
+        let (target, version) = match arg.split_once('=') {
+            Some((target, version)) => (
+                Some(
+                    Target::from_str(&target.trim().to_lowercase())
+                        .map_err(|_| Error::InvalidGccVersion(arg.to_string()))?,
+                ),
+                version.trim(),
+            ),
+            None => (None, arg.trim()),
+        };
+        get_release(version)?;
+        Ok(Self {
+            target,
+            version: version.to_string(),
+        })
+    }
+
+    /// Resolves the GCC version of a target: target specific versions take
+    /// precedence over global ones, which take precedence over the default one.
+    pub fn resolve<'a>(target: &Target, versions: &'a [GccVersion]) -> &'a str {
+        versions
+            .iter()
+            .rev()
+            .find(|version| version.target.as_ref() == Some(target))
+            .or_else(|| {
+                versions
+                    .iter()
+                    .rev()
+                    .find(|version| version.target.is_none())
+            })
+            .map_or(DEFAULT_GCC_VERSION, |version| &version.version)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Gcc {
@@ -35,21 +90,42 @@ pub struct Gcc {
 
 impl Gcc {
     /// Gets the binary path.
-    pub fn get_bin_path(&self) -> String {
+    pub fn get_bin_path(&self, context: &Context) -> String {
         let toolchain_path = format!(
             "{}/{}-{}/{}/bin",
             &self.toolchain_name, self.release, self.version, &self.toolchain_name
         );
-        get_tool_path(&toolchain_path)
+        get_tool_path(context, &toolchain_path)
     }
 
-    /// Installs the gcc toolchain.
-    pub fn install(&self) -> Result<(), Error> {
+    /// Gets the URL of the toolchain artifact.
+    pub fn get_dist_url(&self, context: &Context) -> String {
+        let gcc_file = format!(
+            "{}-gcc{}-{}-{}.{}",
+            self.toolchain_name,
+            self.version,
+            self.release,
+            get_arch(&self.host_triple).unwrap(),
+            get_artifact_extension(&self.host_triple, &self.release)
+        );
+        context.artifact_url(
+            &self.toolchain_name,
+            format!("{}/{}/{}", self.repository_url, self.release, gcc_file),
+        )
+    }
+
+    /// Gets the installation path.
+    pub fn get_path(&self, context: &Context) -> String {
         let target_dir = format!("{}/{}-{}", self.toolchain_name, self.release, self.version);
-        let gcc_path = get_tool_path(&target_dir);
-        let extension = get_artifact_extension(&self.host_triple);
+        get_tool_path(context, &target_dir)
+    }
+
+    /// Installs the gcc toolchain.
+    pub fn install(&self, context: &Context) -> Result<(), Error> {
+        let gcc_path = self.get_path(context);
+        let extension = get_artifact_extension(&self.host_triple, &self.release);
         debug!("{} GCC path: {}", emoji::DEBUG, gcc_path);
-        if Path::new(&PathBuf::from(&gcc_path)).exists() {
+        if is_installed(context, Path::new(&gcc_path)) {
             warn!(
                 "{} Previous installation of GCC exist in: '{}'. Reusing this installation.",
                 emoji::WARN,
@@ -57,52 +133,103 @@ impl Gcc {
             );
             return Ok(());
         }
-        let gcc_file = format!(
-            "{}-gcc{}-{}-{}.{}",
-            self.toolchain_name,
-            self.version,
-            self.release,
-            get_arch(&self.host_triple).unwrap(),
-            extension
-        );
-        let gcc_dist_url = format!("{}/{}/{}", self.repository_url, self.release, gcc_file);
-        download_file(
-            gcc_dist_url,
-            &format!("{}.{}", &self.toolchain_name, extension),
-            &gcc_path,
-            true,
-        )?;
+        install_component(context, Path::new(&gcc_path), || {
+            download_file(
+                context,
+                self.get_dist_url(context),
+                &format!("{}.{}", &self.toolchain_name, extension),
+                &gcc_path,
+                true,
+            )
+        })?;
         Ok(())
     }
 
-    /// Create a new instance with default values and proper toolchain name.
-    pub fn new(target: &Target, host_triple: &HostTriple) -> Self {
-        Self {
-            host_triple: host_triple.clone(),
-            release: DEFAULT_GCC_RELEASE.to_string(),
-            repository_url: DEFAULT_GCC_REPOSITORY.to_string(),
-            toolchain_name: get_toolchain_name(target),
-            version: DEFAULT_GCC_VERSION.to_string(),
-        }
+    /// Create a new instance of the given version with the proper toolchain name.
+    /// Apple Silicon hosts get the x86_64 toolchain, run under Rosetta 2, of the releases
+    /// without native ones.
+    pub fn new(
+        context: &Context,
+        target: &Target,
+        host_triple: &HostTriple,
+        version: &str,
+    ) -> Result<Self, Error> {
+        let release = get_release(version)?;
+        let toolchain_name = get_toolchain_name(target);
+        let toolchain_host = get_component_host(host_triple, |host_triple| {
+            has_artifacts(release, host_triple)
+        })
+        .unwrap_or_else(|| host_triple.clone());
+        log_emulated_component(
+            &format!("{toolchain_name} {version}"),
+            host_triple,
+            &toolchain_host,
+        );
+        Ok(Self {
+            host_triple: toolchain_host,
+            release: release.to_string(),
+            repository_url: context.http.base_url(
+                &format!("gcc-{target}"),
+                &context.http.base_url("gcc", DEFAULT_GCC_REPOSITORY),
+            ),
+            toolchain_name,
+            version: version.replace('.', "_"),
+        })
     }
 }
 
+/// Gets the crosstool-NG release providing a GCC version.
+fn get_release(version: &str) -> Result<&'static str, Error> {
+    GCC_RELEASES
+        .iter()
+        .find(|(gcc_version, _, _, _)| *gcc_version == version)
+        .map(|(_, release, _, _)| *release)
+        .ok_or_else(|| {
+            Error::UnsupportedGccVersion(
+                version.to_string(),
+                GCC_RELEASES
+                    .iter()
+                    .map(|(gcc_version, _, _, _)| *gcc_version)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )
+        })
+}
+
 /// Gets the name of the GCC arch based on the host triple.
 fn get_arch(host_triple: &HostTriple) -> Result<&str> {
     match host_triple {
-        HostTriple::Aarch64AppleDarwin | HostTriple::X86_64AppleDarwin => Ok("macos"),
-        HostTriple::X86_64UnknownLinuxGnu => Ok("linux-amd64"),
-        HostTriple::Aarch64UnknownLinuxGnu => Ok("linux-arm64"),
+        HostTriple::Aarch64AppleDarwin => Ok("macos-arm64"),
+        HostTriple::X86_64AppleDarwin => Ok("macos"),
+        HostTriple::X86_64UnknownLinuxGnu | HostTriple::X86_64UnknownLinuxMusl => Ok("linux-amd64"),
+        HostTriple::Aarch64UnknownLinuxGnu | HostTriple::Aarch64UnknownLinuxMusl => {
+            Ok("linux-arm64")
+        }
         HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => Ok("win64"),
     }
 }
 
-/// Gets the artifact extension based on the host triple.
-fn get_artifact_extension(host_triple: &HostTriple) -> &str {
+/// Gets the artifact extension based on the host triple and the release.
+fn get_artifact_extension(host_triple: &HostTriple, release: &str) -> &'static str {
     match host_triple {
         HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => "zip",
-        _ => "tar.gz",
+        _ => GCC_RELEASES
+            .iter()
+            .find(|(_, gcc_release, _, _)| *gcc_release == release)
+            .map_or("tar.gz", |(_, _, extension, _)| *extension),
+    }
+}
+
+/// Whether a crosstool-NG release provides artifacts for the host.
+fn has_artifacts(release: &str, host_triple: &HostTriple) -> bool {
+    // No release provides musl artifacts.
+    if host_triple.is_musl() {
+        return false;
     }
+    *host_triple != HostTriple::Aarch64AppleDarwin
+        || GCC_RELEASES
+            .iter()
+            .any(|(_, gcc_release, _, macos_arm64)| *gcc_release == release && *macos_arm64)
 }
 
 /// Gets the toolchain name based on the Target
@@ -111,47 +238,275 @@ pub fn get_toolchain_name(target: &Target) -> String {
         Target::ESP32 => "xtensa-esp32-elf".to_string(),
         Target::ESP32S2 => "xtensa-esp32s2-elf".to_string(),
         Target::ESP32S3 => "xtensa-esp32s3-elf".to_string(),
-        Target::ESP32C2 | Target::ESP32C3 => "riscv32-esp-elf".to_string(),
+        Target::ESP32C2 | Target::ESP32C3 | Target::ESP32C6 | Target::ESP32H2 | Target::ESP32P4 => {
+            "riscv32-esp-elf".to_string()
+        }
     }
 }
 
-/// Gets the toolchain name based on the Target
-pub fn get_ulp_toolchain_name(target: Target, version: Option<&EspIdfVersion>) -> Option<String> {
-    match target {
-        Target::ESP32 => Some("esp32ulp-elf".to_string()),
-        Target::ESP32S2 | Target::ESP32S3 => Some(
-            if version
-                .map(|version| {
-                    version.major > 4
-                        || version.major == 4 && version.minor > 4
-                        || version.major == 4 && version.minor == 4 && version.patch >= 2
-                })
-                .unwrap_or(true)
-            {
-                "esp32ulp-elf".to_string()
-            } else {
-                "esp32s2ulp-elf".to_string()
-            },
-        ),
+/// Gets the name of the GCC toolchain of a target among the ESP-IDF tools of a version, the
+/// latest one when unknown: the Xtensa chips share `xtensa-esp-elf` since ESP-IDF v5.2.
+pub fn get_esp_idf_toolchain_name(target: &Target, version: Option<&EspIdfVersion>) -> String {
+    if target.is_xtensa() && is_at_least(version, 5, 2, 0) {
+        return "xtensa-esp-elf".to_string();
+    }
+    get_toolchain_name(target)
+}
+
+/// Whether the ESP-IDF version, the latest one when unknown, is at least `major.minor.patch`.
+fn is_at_least(version: Option<&EspIdfVersion>, major: u64, minor: u64, patch: u64) -> bool {
+    version.map_or(true, |version| {
+        (version.major, version.minor, version.patch) >= (major, minor, patch)
+    })
+}
+
+/// Gets the toolchains of the ULP coprocessors of a target for an ESP-IDF version, the
+/// latest one when unknown, that have a build for the platform of the ESP-IDF tools, e.g.
+/// `linux-arm64`:
+/// - The ULP FSM of the ESP32, ESP32-S2 and ESP32-S3 uses `esp32ulp-elf`, which replaced
+///   `esp32s2ulp-elf` for the ESP32-S2 and ESP32-S3 in ESP-IDF v4.4.2, and is only built
+///   for `linux-arm64` since ESP-IDF v5.0.
+/// - The ULP RISC-V of the ESP32-S2 and ESP32-S3 uses `riscv32-esp-elf`, since ESP-IDF v4.3.
+///
+/// The LP core of the newer chips uses the RISC-V toolchain of the chip itself.
+pub fn get_ulp_toolchain_names(
+    target: Target,
+    version: Option<&EspIdfVersion>,
+    platform: &str,
+) -> Vec<String> {
+    let mut toolchains: Vec<String> = Vec::new();
+    let fsm = match target {
+        Target::ESP32 => Some("esp32ulp-elf"),
+        Target::ESP32S2 | Target::ESP32S3 if is_at_least(version, 4, 4, 2) => Some("esp32ulp-elf"),
+        Target::ESP32S2 | Target::ESP32S3 => Some("esp32s2ulp-elf"),
         _ => None,
+    };
+    if let Some(fsm) = fsm {
+        let built =
+            platform != "linux-arm64" || fsm == "esp32ulp-elf" && is_at_least(version, 5, 0, 0);
+        if built {
+            toolchains.push(fsm.to_string());
+        }
     }
+    if matches!(target, Target::ESP32S2 | Target::ESP32S3) && is_at_least(version, 4, 3, 0) {
+        toolchains.push(get_toolchain_name(&Target::ESP32C3));
+    }
+    toolchains
+}
+
+/// Whether an ESP-IDF tool is only needed for the ULP coprocessors of the targets: the ULP
+/// FSM toolchains, and the RISC-V toolchain when no target is a RISC-V chip.
+pub fn is_ulp_toolchain(name: &str, targets: &HashSet<Target>) -> bool {
+    name.ends_with("ulp-elf")
+        || name == get_toolchain_name(&Target::ESP32C3)
+            && !targets
+                .iter()
+                .any(|target| get_toolchain_name(target) == name)
+}
+
+/// Whether an ESP-IDF tool is the GCC toolchain of one of the targets, for an ESP-IDF
+/// version, the latest one when unknown.
+pub fn is_target_toolchain(
+    name: &str,
+    targets: &HashSet<Target>,
+    version: Option<&EspIdfVersion>,
+) -> bool {
+    targets
+        .iter()
+        .any(|target| get_esp_idf_toolchain_name(target, version) == name)
 }
 
 /// Installs GCC toolchain the selected targets.
 pub fn install_gcc_targets(
+    context: &Context,
     targets: &HashSet<Target>,
     host_triple: &HostTriple,
+    versions: &[GccVersion],
 ) -> Result<Vec<String>, Error> {
     info!("{} Installing gcc for build targets", emoji::WRENCH);
-    let mut exports: Vec<String> = Vec::new();
+    // Targets sharing a toolchain, e.g. the RISC-V ones, install it once.
+    let mut gccs: BTreeMap<String, Gcc> = BTreeMap::new();
     for target in targets {
-        let gcc = Gcc::new(target, host_triple);
-        gcc.install()?;
+        let gcc = Gcc::new(
+            context,
+            target,
+            host_triple,
+            GccVersion::resolve(target, versions),
+        )?;
+        gccs.insert(gcc.get_path(context), gcc);
+    }
+    let installs = gccs
+        .into_values()
+        .map(|gcc| {
+            let context = context.clone();
+            move || {
+                gcc.install(&context)?;
+                #[cfg(windows)]
+                return Ok(format!("$Env:PATH += \";{}\"", gcc.get_bin_path(&context)));
+                #[cfg(unix)]
+                return Ok(format!("export PATH={}:$PATH", gcc.get_bin_path(&context)));
+            }
+        })
+        .collect();
+    install_concurrently(installs)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        context::Context,
+        host_triple::HostTriple,
+        targets::Target,
+        toolchain::gcc::{
+            get_esp_idf_toolchain_name, get_ulp_toolchain_names, is_target_toolchain,
+            is_ulp_toolchain, Gcc, GccVersion, DEFAULT_GCC_VERSION,
+        },
+    };
+    use embuild::espidf::EspIdfVersion;
+
+    #[test]
+    fn test_gcc_version() {
+        assert_eq!(
+            GccVersion::parse("11.2.0").unwrap(),
+            GccVersion {
+                target: None,
+                version: "11.2.0".to_string()
+            }
+        );
+        assert_eq!(
+            GccVersion::parse("ESP32=11.2.0").unwrap(),
+            GccVersion {
+                target: Some(Target::ESP32),
+                version: "11.2.0".to_string()
+            }
+        );
+        assert!(GccVersion::parse("esp8266=11.2.0").is_err());
+        assert!(GccVersion::parse("esp32=7.2.0").is_err());
+        assert!(GccVersion::parse("").is_err());
+
+        let versions = vec![
+            GccVersion::parse("esp32s3=8.4.0").unwrap(),
+            GccVersion::parse("11.2.0").unwrap(),
+        ];
+        assert_eq!(GccVersion::resolve(&Target::ESP32S3, &versions), "8.4.0");
+        assert_eq!(GccVersion::resolve(&Target::ESP32, &versions), "11.2.0");
+        assert_eq!(
+            GccVersion::resolve(&Target::ESP32, &[]),
+            DEFAULT_GCC_VERSION
+        );
+    }
+
+    #[test]
+    fn test_gcc_dist_url() {
+        let context = Context::default();
+        let gcc = Gcc::new(
+            &context,
+            &Target::ESP32,
+            &HostTriple::X86_64UnknownLinuxGnu,
+            "11.2.0",
+        )
+        .unwrap();
+        assert_eq!(
+            gcc.get_dist_url(&context),
+            "https://github.com/espressif/crosstool-NG/releases/download/esp-2022r1/xtensa-esp32-elf-gcc11_2_0-esp-2022r1-linux-amd64.tar.xz"
+        );
+
+        // Releases without Apple Silicon artifacts fall back to the x86_64 ones.
+        let gcc = Gcc::new(
+            &context,
+            &Target::ESP32C3,
+            &HostTriple::Aarch64AppleDarwin,
+            "11.2.0",
+        )
+        .unwrap();
+        assert!(gcc
+            .get_dist_url(&context)
+            .ends_with("-esp-2022r1-macos-arm64.tar.xz"));
+        let gcc = Gcc::new(
+            &context,
+            &Target::ESP32C3,
+            &HostTriple::Aarch64AppleDarwin,
+            "8.4.0",
+        )
+        .unwrap();
+        assert_eq!(gcc.host_triple, HostTriple::X86_64AppleDarwin);
+        assert!(gcc
+            .get_dist_url(&context)
+            .ends_with("-esp-2021r2-patch5-macos.tar.gz"));
+    }
+
+    #[test]
+    fn test_get_ulp_toolchain_names() {
+        let version = |major, minor, patch| EspIdfVersion {
+            major,
+            minor,
+            patch,
+        };
+        assert_eq!(
+            get_ulp_toolchain_names(Target::ESP32, None, "linux-amd64"),
+            ["esp32ulp-elf"]
+        );
+        assert_eq!(
+            get_ulp_toolchain_names(Target::ESP32S3, Some(&version(5, 1, 0)), "macos"),
+            ["esp32ulp-elf", "riscv32-esp-elf"]
+        );
+        assert_eq!(
+            get_ulp_toolchain_names(Target::ESP32S2, Some(&version(4, 4, 1)), "win64"),
+            ["esp32s2ulp-elf", "riscv32-esp-elf"]
+        );
+        assert_eq!(
+            get_ulp_toolchain_names(Target::ESP32S2, Some(&version(4, 2, 0)), "win64"),
+            ["esp32s2ulp-elf"]
+        );
+        assert_eq!(
+            get_ulp_toolchain_names(Target::ESP32S2, Some(&version(4, 4, 1)), "linux-arm64"),
+            ["riscv32-esp-elf"]
+        );
+        assert!(
+            get_ulp_toolchain_names(Target::ESP32, Some(&version(4, 4, 4)), "linux-arm64")
+                .is_empty()
+        );
+        assert!(get_ulp_toolchain_names(Target::ESP32C6, None, "linux-amd64").is_empty());
+
+        let targets = [Target::ESP32S3].into_iter().collect();
+        assert!(is_ulp_toolchain("esp32ulp-elf", &targets));
+        assert!(is_ulp_toolchain("riscv32-esp-elf", &targets));
+        assert!(!is_ulp_toolchain("xtensa-esp32s3-elf", &targets));
+        let targets = [Target::ESP32S3, Target::ESP32C3].into_iter().collect();
+        assert!(!is_ulp_toolchain("riscv32-esp-elf", &targets));
+    }
+
+    #[test]
+    fn test_get_esp_idf_toolchain_name() {
+        let version = |major, minor| EspIdfVersion {
+            major,
+            minor,
+            patch: 0,
+        };
+        assert_eq!(
+            get_esp_idf_toolchain_name(&Target::ESP32S3, Some(&version(5, 1))),
+            "xtensa-esp32s3-elf"
+        );
+        assert_eq!(
+            get_esp_idf_toolchain_name(&Target::ESP32S3, Some(&version(5, 2))),
+            "xtensa-esp-elf"
+        );
+        assert_eq!(
+            get_esp_idf_toolchain_name(&Target::ESP32, None),
+            "xtensa-esp-elf"
+        );
+        assert_eq!(
+            get_esp_idf_toolchain_name(&Target::ESP32C6, Some(&version(5, 2))),
+            "riscv32-esp-elf"
+        );
 
-        #[cfg(windows)]
-        exports.push(format!("$Env:PATH += \";{}\"", gcc.get_bin_path()));
-        #[cfg(unix)]
-        exports.push(format!("export PATH={}:$PATH", gcc.get_bin_path()));
+        let targets = [Target::ESP32S3].into_iter().collect();
+        assert!(is_target_toolchain(
+            "xtensa-esp32s3-elf",
+            &targets,
+            Some(&version(5, 1))
+        ));
+        assert!(is_target_toolchain("xtensa-esp-elf", &targets, None));
+        assert!(!is_target_toolchain("riscv32-esp-elf", &targets, None));
     }
-    Ok(exports)
 }
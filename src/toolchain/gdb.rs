@@ -0,0 +1,114 @@
+//! GDB source and installation tools
+
+use crate::{
+    context::Context,
+    host_triple::HostTriple,
+    targets::Target,
+    toolchain::tool::{get_host_archive_extension, ReleaseAsset, ReleaseTool},
+};
+use std::collections::HashSet;
+
+pub const DEFAULT_GDB_REPOSITORY: &str =
+    "https://github.com/espressif/binutils-gdb/releases/download";
+const DEFAULT_GDB_VERSION: &str = "12.1_20231023";
+/// Name of the GDB supporting every Xtensa chip.
+pub const XTENSA_GDB_NAME: &str = "xtensa-esp-elf-gdb";
+/// Name of the GDB supporting every RISC-V chip.
+pub const RISCV_GDB_NAME: &str = "riscv32-esp-elf-gdb";
+
+/// Gets a GDB, e.g. `xtensa-esp-elf-gdb`.
+pub fn get_gdb(context: &Context, name: &str, host_triple: &HostTriple) -> ReleaseTool {
+    let asset = format!(
+        "{}-{}-{}.{}",
+        name,
+        DEFAULT_GDB_VERSION,
+        get_arch(host_triple),
+        get_host_archive_extension(host_triple)
+    );
+    ReleaseTool::new(
+        context,
+        name,
+        ReleaseAsset {
+            name,
+            component: "gdb",
+            repository: DEFAULT_GDB_REPOSITORY,
+            tag: &format!("esp-gdb-v{DEFAULT_GDB_VERSION}"),
+            asset: &asset,
+            version: DEFAULT_GDB_VERSION,
+        },
+    )
+    .with_bin_dir(&format!("{name}/bin"))
+}
+
+/// Gets the name of the GDB arch based on the host triple.
+fn get_arch(host_triple: &HostTriple) -> &str {
+    match host_triple {
+        HostTriple::Aarch64AppleDarwin => "aarch64-apple-darwin21.1",
+        HostTriple::X86_64AppleDarwin => "x86_64-apple-darwin21.1",
+        HostTriple::X86_64UnknownLinuxGnu | HostTriple::X86_64UnknownLinuxMusl => {
+            "x86_64-linux-gnu"
+        }
+        HostTriple::Aarch64UnknownLinuxGnu | HostTriple::Aarch64UnknownLinuxMusl => {
+            "aarch64-linux-gnu"
+        }
+        HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => "x86_64-w64-mingw32",
+    }
+}
+
+/// Gets the GDB instances needed to debug the selected targets.
+pub fn get_gdb_targets(
+    context: &Context,
+    targets: &HashSet<Target>,
+    host_triple: &HostTriple,
+) -> Vec<ReleaseTool> {
+    let mut gdbs = Vec::new();
+    if targets.iter().any(Target::is_xtensa) {
+        gdbs.push(get_gdb(context, XTENSA_GDB_NAME, host_triple));
+    }
+    if targets.iter().any(Target::is_riscv) {
+        gdbs.push(get_gdb(context, RISCV_GDB_NAME, host_triple));
+    }
+    gdbs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        context::Context,
+        host_triple::HostTriple,
+        targets::Target,
+        toolchain::gdb::{get_gdb, get_gdb_targets, RISCV_GDB_NAME, XTENSA_GDB_NAME},
+    };
+
+    #[test]
+    fn test_get_gdb() {
+        let gdb = get_gdb(
+            &Context::default(),
+            XTENSA_GDB_NAME,
+            &HostTriple::X86_64PcWindowsMsvc,
+        );
+        assert_eq!(
+            gdb.repository_url,
+            "https://github.com/espressif/binutils-gdb/releases/download/esp-gdb-v12.1_20231023/xtensa-esp-elf-gdb-12.1_20231023-x86_64-w64-mingw32.zip"
+        );
+        assert!(gdb
+            .get_bin_path()
+            .unwrap()
+            .ends_with("xtensa-esp-elf-gdb/bin"));
+    }
+
+    #[test]
+    fn test_get_gdb_targets() {
+        let context = Context::default();
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let targets = [Target::ESP32S2, Target::ESP32S3].into_iter().collect();
+        let gdbs = get_gdb_targets(&context, &targets, &host_triple);
+        assert_eq!(gdbs.len(), 1);
+        assert_eq!(gdbs[0].name, XTENSA_GDB_NAME);
+        let targets = [Target::ESP32C6].into_iter().collect();
+        assert_eq!(
+            get_gdb_targets(&context, &targets, &host_triple)[0].name,
+            RISCV_GDB_NAME
+        );
+    }
+}
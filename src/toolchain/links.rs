@@ -0,0 +1,310 @@
+//! Permissions and symbolic links of the extracted archives. Zip archives only record them
+//! in their central directory, which the extraction does not apply, and some filesystems,
+//! e.g. on Windows without the developer mode or FAT ones, do not support symbolic links,
+//! which are replaced by copies of their targets.
+
+use crate::{emoji, toolchain::reflink};
+use log::{debug, warn};
+use std::{
+    fs::{create_dir_all, read_to_string, remove_file},
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Signature of the headers of the central directory of a zip archive.
+pub const CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x02014b50;
+/// Length of the fixed part of a central directory header.
+const CENTRAL_DIRECTORY_HEADER_LENGTH: usize = 46;
+/// Host system of the zip archives created on Unix, in their `version made by` field.
+const ZIP_UNIX_HOST: u16 = 3;
+/// Mask of the file type bits of a Unix mode.
+const S_IFMT: u32 = 0o170000;
+/// File type of the regular files, in a Unix mode.
+#[cfg(unix)]
+const S_IFREG: u32 = 0o100000;
+/// File type of the symbolic links, in a Unix mode.
+const S_IFLNK: u32 = 0o120000;
+
+/// Symbolic link of an archive, created once the archive is extracted, as its target may
+/// come later in the archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symlink {
+    /// Path of the link, relative to the extraction directory.
+    pub path: PathBuf,
+    /// Target of the link, usually relative to its directory.
+    pub target: PathBuf,
+}
+
+/// Whether a path of an archive stays inside the extraction directory.
+fn is_enclosed(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Whether a path stays inside the canonicalized extraction directory `root` once the
+/// symbolic links already created are followed, which the lexical checks miss, e.g.
+/// `a/tmp/evil` after `a -> /`. The closest existing ancestor of the path is checked, as the
+/// rest is still to be created.
+fn is_contained(root: &Path, path: &Path) -> bool {
+    let mut ancestor = path;
+    while ancestor.symlink_metadata().is_err() {
+        ancestor = match ancestor.parent() {
+            Some(parent) => parent,
+            None => return false,
+        };
+    }
+    ancestor
+        .canonicalize()
+        .map_or(false, |ancestor| ancestor.starts_with(root))
+}
+
+/// Resolves the target of a symbolic link inside the extraction directory, if it does not
+/// point outside of it.
+fn resolve_target(directory: &Path, symlink: &Symlink) -> Option<PathBuf> {
+    let mut resolved = PathBuf::new();
+    let parent = symlink.path.parent().unwrap_or_else(|| Path::new(""));
+    for component in parent.join(&symlink.target).components() {
+        match component {
+            Component::Normal(name) => resolved.push(name),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return None;
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(directory.join(resolved))
+}
+
+/// Creates a symbolic link.
+fn symlink(target: &Path, path: &Path, is_dir: bool) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let _ = is_dir;
+        std::os::unix::fs::symlink(target, path)
+    }
+    #[cfg(windows)]
+    if is_dir {
+        std::os::windows::fs::symlink_dir(target, path)
+    } else {
+        std::os::windows::fs::symlink_file(target, path)
+    }
+}
+
+/// Creates the symbolic links of an archive extracted to `directory`, copying their
+/// targets where the filesystem does not support them. Links to paths outside of the
+/// archive are skipped in that case, and so are the links placed outside of it through
+/// another link.
+pub fn create_symlinks(directory: &Path, symlinks: &[Symlink]) -> io::Result<()> {
+    let root = directory.canonicalize()?;
+    for symlink in symlinks {
+        let path = directory.join(&symlink.path);
+        if !is_enclosed(&symlink.path)
+            || !path
+                .parent()
+                .map_or(false, |parent| is_contained(&root, parent))
+        {
+            warn!(
+                "{} Skipping the symbolic link '{}', outside of the archive",
+                emoji::WARN,
+                symlink.path.display()
+            );
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        let target =
+            resolve_target(directory, symlink).filter(|target| is_contained(&root, target));
+        let is_dir = target.as_ref().map_or(false, |target| target.is_dir());
+        if let Err(e) = self::symlink(&symlink.target, &path, is_dir) {
+            let target = match target.filter(|target| target.exists()) {
+                Some(target) => target,
+                None => {
+                    warn!(
+                        "{} Failed to create the symbolic link '{}': {}",
+                        emoji::WARN,
+                        path.display(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            debug!(
+                "{} Failed to create the symbolic link '{}', copying '{}'",
+                emoji::DEBUG,
+                path.display(),
+                target.display()
+            );
+            if is_dir {
+                reflink::copy_dir(&target, &path)?;
+            } else {
+                reflink::reflink_or_copy(&target, &path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parses the Unix modes of the entries of a zip archive from its central directory, for
+/// the entries created on Unix.
+pub fn parse_zip_modes(central_directory: &[u8]) -> Vec<(PathBuf, u32)> {
+    let read_u16 = |offset: usize| {
+        u16::from_le_bytes([central_directory[offset], central_directory[offset + 1]])
+    };
+    let read_u32 = |offset: usize| (read_u16(offset + 2) as u32) << 16 | read_u16(offset) as u32;
+    let mut modes = Vec::new();
+    let mut offset = 0;
+    while offset + CENTRAL_DIRECTORY_HEADER_LENGTH <= central_directory.len()
+        && read_u32(offset) == CENTRAL_DIRECTORY_SIGNATURE
+    {
+        let version_made_by = read_u16(offset + 4);
+        let name_length = read_u16(offset + 28) as usize;
+        let extra_length = read_u16(offset + 30) as usize;
+        let comment_length = read_u16(offset + 32) as usize;
+        let external_attributes = read_u32(offset + 38);
+        let name_start = offset + CENTRAL_DIRECTORY_HEADER_LENGTH;
+        let name = match central_directory.get(name_start..name_start + name_length) {
+            Some(name) => PathBuf::from(String::from_utf8_lossy(name).into_owned()),
+            None => break,
+        };
+        let mode = external_attributes >> 16;
+        if version_made_by >> 8 == ZIP_UNIX_HOST && mode != 0 && is_enclosed(&name) {
+            modes.push((name, mode));
+        }
+        offset = name_start + name_length + extra_length + comment_length;
+    }
+    modes
+}
+
+/// Applies the Unix modes of the entries of an archive extracted to `directory`: the
+/// permissions of the files, on Unix, and the symbolic links, extracted as files holding
+/// their target.
+pub fn restore_modes(directory: &Path, modes: &[(PathBuf, u32)]) -> io::Result<()> {
+    let mut symlinks = Vec::new();
+    for (path, mode) in modes {
+        let file = directory.join(path);
+        if mode & S_IFMT == S_IFLNK {
+            let target = read_to_string(&file)?;
+            remove_file(&file)?;
+            symlinks.push(Symlink {
+                path: path.clone(),
+                target: PathBuf::from(target),
+            });
+            continue;
+        }
+        #[cfg(unix)]
+        if mode & S_IFMT == S_IFREG {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&file, std::fs::Permissions::from_mode(mode & 0o777))?;
+        }
+    }
+    create_symlinks(directory, &symlinks)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::toolchain::links::{
+        create_symlinks, parse_zip_modes, resolve_target, restore_modes, Symlink,
+    };
+    use std::{
+        fs::{create_dir_all, read_to_string, write},
+        io::{Cursor, Write},
+        path::{Path, PathBuf},
+    };
+    use tempfile::TempDir;
+    use zip::write::FileOptions;
+
+    #[test]
+    fn test_resolve_target() {
+        let symlink = |path: &str, target: &str| Symlink {
+            path: PathBuf::from(path),
+            target: PathBuf::from(target),
+        };
+        let directory = Path::new("/tools/gcc");
+        assert_eq!(
+            resolve_target(directory, &symlink("bin/cc", "gcc")),
+            Some(directory.join("bin").join("gcc"))
+        );
+        assert_eq!(
+            resolve_target(directory, &symlink("bin/cc", "../libexec/./cc1")),
+            Some(directory.join("libexec").join("cc1"))
+        );
+        assert_eq!(resolve_target(directory, &symlink("cc", "../gcc")), None);
+        assert_eq!(
+            resolve_target(directory, &symlink("cc", "/usr/bin/gcc")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_restore_modes() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("bin/gcc", FileOptions::default().unix_permissions(0o755))
+            .unwrap();
+        writer.write_all(b"gcc").unwrap();
+        writer
+            .add_symlink("bin/cc", "gcc", FileOptions::default())
+            .unwrap();
+        let zipfile = writer.finish().unwrap().into_inner();
+        let central_directory = &zipfile[zipfile
+            .windows(4)
+            .position(|window| window == b"PK\x01\x02")
+            .unwrap()..];
+        let modes = parse_zip_modes(central_directory);
+        assert_eq!(modes.len(), 2);
+        assert_eq!(modes[0], (PathBuf::from("bin/gcc"), 0o100755));
+
+        create_dir_all(temp_dir.path().join("bin")).unwrap();
+        write(temp_dir.path().join("bin").join("gcc"), "gcc").unwrap();
+        write(temp_dir.path().join("bin").join("cc"), "gcc").unwrap();
+        restore_modes(temp_dir.path(), &modes).unwrap();
+        assert!(temp_dir.path().join("bin").join("cc").is_symlink());
+        assert_eq!(
+            read_to_string(temp_dir.path().join("bin").join("cc")).unwrap(),
+            "gcc"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = temp_dir.path().join("bin").join("gcc").metadata().unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        }
+
+        // Links outside of the archive are skipped.
+        create_symlinks(
+            temp_dir.path(),
+            &[Symlink {
+                path: PathBuf::from("../cc"),
+                target: PathBuf::from("bin/gcc"),
+            }],
+        )
+        .unwrap();
+        assert!(!temp_dir.path().join("..").join("cc").exists());
+    }
+
+    #[test]
+    fn test_create_chained_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let outside_dir = TempDir::new().unwrap();
+        let symlink = |path: &str, target: &Path| Symlink {
+            path: PathBuf::from(path),
+            target: target.to_path_buf(),
+        };
+        // The second link would be created outside of the archive through the first one.
+        create_symlinks(
+            temp_dir.path(),
+            &[
+                symlink("a", outside_dir.path()),
+                symlink("a/tmp/evil", Path::new("x")),
+            ],
+        )
+        .unwrap();
+        assert!(!outside_dir.path().join("tmp").exists());
+        assert!(!outside_dir.path().join("evil").exists());
+    }
+}
@@ -1,16 +1,24 @@
 //! LLVM Toolchain source and installation tools
 
 use crate::{
+    context::Context,
     emoji,
+    error::Error,
     host_triple::HostTriple,
+    resume::{install_component, is_installed},
     toolchain::{download_file, espidf::get_tool_path},
 };
-use log::{info, warn};
+use log::{debug, info, warn};
 use miette::Result;
+use regex::Regex;
 use std::path::{Path, PathBuf};
 
-const DEFAULT_LLVM_REPOSITORY: &str = "https://github.com/espressif/llvm-project/releases/download";
-const DEFAULT_LLVM_15_VERSION: &str = "esp-15.0.0-20221201";
+pub const DEFAULT_LLVM_REPOSITORY: &str =
+    "https://github.com/espressif/llvm-project/releases/download";
+pub const DEFAULT_LLVM_15_VERSION: &str = "esp-15.0.0-20221201";
+const RE_LLVM_VERSION: &str = r"^esp-(\d+)\.(\d+)\.(\d+)-(\d{8})$";
+/// Name of the LLVM tool directory.
+pub const CLANG_NAME: &str = "xtensa-esp32-elf-clang";
 
 #[derive(Debug, Clone, Default)]
 pub struct Llvm {
@@ -22,7 +30,7 @@ pub struct Llvm {
     pub path: PathBuf,
     /// The repository containing LLVM sources.
     pub repository_url: String,
-    /// LLVM Version ["15"].
+    /// LLVM release, e.g. `esp-15.0.0-20221201`.
     pub version: String,
 }
 
@@ -32,14 +40,18 @@ impl Llvm {
         match host_triple {
             HostTriple::Aarch64AppleDarwin => Ok("macos-arm64"),
             HostTriple::X86_64AppleDarwin => Ok("macos"),
-            HostTriple::X86_64UnknownLinuxGnu => Ok("linux-amd64"),
-            HostTriple::Aarch64UnknownLinuxGnu => Ok("linux-arm64"),
+            HostTriple::X86_64UnknownLinuxGnu | HostTriple::X86_64UnknownLinuxMusl => {
+                Ok("linux-amd64")
+            }
+            HostTriple::Aarch64UnknownLinuxGnu | HostTriple::Aarch64UnknownLinuxMusl => {
+                Ok("linux-arm64")
+            }
             HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => Ok("win64"),
         }
     }
 
     /// Gets the binary path.
-    fn get_lib_path(&self) -> String {
+    pub fn get_lib_path(&self) -> String {
         #[cfg(windows)]
         let llvm_path = format!("{}/esp-clang/bin", self.path.to_str().unwrap());
         #[cfg(unix)]
@@ -47,11 +59,25 @@ impl Llvm {
         llvm_path
     }
 
+    /// Gets the path of the clang executable, which bindgen uses through `CLANG_PATH`.
+    pub fn get_clang_path(&self) -> String {
+        #[cfg(windows)]
+        let clang_path = format!("{}/esp-clang/bin/clang.exe", self.path.to_str().unwrap());
+        #[cfg(unix)]
+        let clang_path = format!("{}/esp-clang/bin/clang", self.path.to_str().unwrap());
+        clang_path
+    }
+
+    /// Whether the release is minified, only containing the libraries but not clang.
+    pub fn is_minified(&self) -> bool {
+        self.file_name.starts_with("libs_")
+    }
+
     /// Installs the LLVM toolchain.
-    pub fn install(&self) -> Result<Vec<String>> {
+    pub fn install(&self, context: &Context) -> Result<Vec<String>> {
         let mut exports: Vec<String> = Vec::new();
 
-        if Path::new(&self.path).exists() {
+        if is_installed(context, &self.path) {
             warn!(
                 "{} Previous installation of LLVM exist in: '{}'. Reusing this installation.",
                 emoji::WARN,
@@ -59,14 +85,25 @@ impl Llvm {
             );
         } else {
             info!("{} Installing Xtensa elf Clang", emoji::WRENCH);
-            download_file(
-                self.repository_url.clone(),
-                "idf_tool_xtensa_elf_clang.tar.xz",
-                self.path.to_str().unwrap(),
-                true,
-            )?;
+            install_component(context, &self.path, || {
+                download_file(
+                    context,
+                    self.repository_url.clone(),
+                    "idf_tool_xtensa_elf_clang.tar.xz",
+                    self.path.to_str().unwrap(),
+                    true,
+                )
+            })?;
         }
-        // Set environment variables.
+        exports.extend(self.get_exports());
+
+        Ok(exports)
+    }
+
+    /// Gets the environment variables to export. Both `LIBCLANG_PATH` and, when installed,
+    /// `CLANG_PATH` are set, so that bindgen does not pick a clang of the system.
+    pub fn get_exports(&self) -> Vec<String> {
+        let mut exports: Vec<String> = Vec::new();
         #[cfg(windows)]
         exports.push(format!(
             "$Env:LIBCLANG_PATH=\"{}/libclang.dll\"",
@@ -76,28 +113,45 @@ impl Llvm {
         exports.push(format!("$Env:PATH+=\";{}\"", self.get_lib_path()));
         #[cfg(unix)]
         exports.push(format!("export LIBCLANG_PATH=\"{}\"", self.get_lib_path()));
-
-        Ok(exports)
+        let clang_path = self.get_clang_path();
+        if Path::new(&clang_path).exists() {
+            #[cfg(windows)]
+            exports.push(format!("$Env:CLANG_PATH=\"{}\"", clang_path));
+            #[cfg(unix)]
+            exports.push(format!("export CLANG_PATH=\"{}\"", clang_path));
+        }
+        exports
     }
 
-    /// Create a new instance with default values and proper toolchain version.
-    pub fn new(version: String, minified: bool, host_triple: &HostTriple) -> Self {
+    /// Create a new instance of the given release. Minified instances only contain
+    /// the libraries, which are enough for `LIBCLANG_PATH`.
+    pub fn new(
+        context: &Context,
+        version: String,
+        minified: bool,
+        host_triple: &HostTriple,
+    ) -> Self {
         let mut file_name = format!(
             "llvm-{}-{}.tar.xz",
-            DEFAULT_LLVM_15_VERSION,
+            version,
             Self::get_arch(host_triple).unwrap()
         );
         if minified {
             file_name = format!("libs_{}", file_name);
         }
-        let repository_url = format!(
-            "{}/{}/{}",
-            DEFAULT_LLVM_REPOSITORY, DEFAULT_LLVM_15_VERSION, file_name,
+        let repository_url = context.artifact_url(
+            "llvm",
+            format!(
+                "{}/{}/{}",
+                context.http.base_url("llvm", DEFAULT_LLVM_REPOSITORY),
+                version,
+                file_name
+            ),
         );
         let path = PathBuf::from(format!(
             "{}/{}-{}",
-            get_tool_path("xtensa-esp32-elf-clang"),
-            DEFAULT_LLVM_15_VERSION,
+            get_tool_path(context, CLANG_NAME),
+            version,
             host_triple
         ));
         Self {
@@ -108,4 +162,53 @@ impl Llvm {
             version,
         }
     }
+
+    /// Parses the LLVM version: either the major version `15`, which resolves to its
+    /// latest known release, or a release of the form `esp-<major>.<minor>.<patch>-<date>`.
+    pub fn parse_version(arg: &str) -> Result<String> {
+        debug!("{} Parsing LLVM version: {}", emoji::DEBUG, arg);
+        if arg == "15" {
+            return Ok(DEFAULT_LLVM_15_VERSION.to_string());
+        }
+        let re = Regex::new(RE_LLVM_VERSION).unwrap();
+        if !re.is_match(arg) {
+            return Err(Error::InvalidLlvmVersion(arg.to_string()).into());
+        }
+        Ok(arg.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        context::Context,
+        host_triple::HostTriple,
+        toolchain::llvm::{Llvm, DEFAULT_LLVM_15_VERSION},
+    };
+
+    #[test]
+    fn test_llvm_parse_version() {
+        assert_eq!(Llvm::parse_version("15").unwrap(), DEFAULT_LLVM_15_VERSION);
+        assert_eq!(
+            Llvm::parse_version("esp-16.0.0-20230516").unwrap(),
+            "esp-16.0.0-20230516"
+        );
+        assert!(Llvm::parse_version("16").is_err());
+        assert!(Llvm::parse_version("esp-15.0.0").is_err());
+        assert!(Llvm::parse_version("llvm-15.0.0-20221201").is_err());
+    }
+
+    #[test]
+    fn test_llvm_new() {
+        let llvm = Llvm::new(
+            &Context::default(),
+            DEFAULT_LLVM_15_VERSION.to_string(),
+            true,
+            &HostTriple::X86_64UnknownLinuxGnu,
+        );
+        assert_eq!(
+            llvm.repository_url,
+            "https://github.com/espressif/llvm-project/releases/download/esp-15.0.0-20221201/libs_llvm-esp-15.0.0-20221201-linux-amd64.tar.xz"
+        );
+    }
 }
@@ -1,28 +1,659 @@
-use crate::{emoji, error::Error};
+use crate::{
+    cache::{self, CachingReader, FileDigest},
+    context::Context,
+    download::Downloader,
+    emoji,
+    error::Error,
+    interrupt::InterruptibleReader,
+    progress::{Event, Phase, ProgressReader},
+    store::{self, HashingReader},
+    targets::Target,
+    toolchain::gcc::get_toolchain_name,
+};
 use dirs::home_dir;
+use embuild::cmd;
 use flate2::bufread::GzDecoder;
-use log::info;
+use log::{debug, info, warn};
 use miette::Result;
+use reqwest::{blocking::Response, header, StatusCode};
+use sha2::{Digest, Sha256};
 use std::{
-    fs::{create_dir_all, File},
-    io::{copy, BufReader},
-    path::Path,
+    collections::{HashMap, VecDeque},
+    fs::{create_dir_all, read_dir, remove_dir_all, remove_file, rename, symlink_metadata, File},
+    io::{self, copy, BufReader, Read, Seek, SeekFrom, Write},
+    panic::resume_unwind,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Instant,
 };
+use strum::IntoEnumIterator;
 use tar::Archive;
 use xz2::read::XzDecoder;
+use zip::result::{ZipError, ZipResult};
 
+pub mod dfu_util;
+pub mod drivers;
 pub mod espidf;
+pub mod frameworks;
 pub mod gcc;
+pub mod gdb;
+pub mod links;
 pub mod llvm;
+pub mod openocd;
+pub mod qemu;
+pub mod reflink;
+pub mod rom_elfs;
 pub mod rust;
+pub mod sccache;
+pub mod seven_zip;
+pub mod signature;
+pub mod tool;
+
+/// Number of installations run at the same time by [`install_concurrently`], enough for the
+/// toolchains of every target while keeping the concurrent downloads bounded.
+pub const INSTALL_WORKERS: usize = 4;
+
+/// Creates the directory of the temporary files and the staging directories of the extracted
+/// archives, set in [`Context::staging_dir`] to use it instead of the temporary directory of
+/// the system and the directory of the tools, e.g. when the former is a small tmpfs or the
+/// latter a network home.
+pub fn create_staging_dir(staging_dir: &Path) -> Result<PathBuf, Error> {
+    create_dir_all(staging_dir)
+        .map_err(|_| Error::FailedToCreateDirectory(staging_dir.display().to_string()))?;
+    Ok(staging_dir.to_path_buf())
+}
+
+/// Local archive of a component, installed instead of the downloaded one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalArtifact {
+    /// Name of the component: `llvm`, `rust`, `rust-src` or the name of a GCC toolchain,
+    /// e.g. `xtensa-esp32-elf`.
+    pub component: String,
+    /// Absolute path of the archive.
+    pub path: PathBuf,
+}
+
+impl LocalArtifact {
+    /// Parses a local artifact of the form `<component>=<path>`.
+    pub fn parse(arg: &str) -> Result<Self, Error> {
+        let (component, path) = arg
+            .split_once('=')
+            .ok_or_else(|| Error::InvalidLocalArtifact(arg.to_string()))?;
+        let components = get_local_artifact_components();
+        if !components.iter().any(|name| name == component) {
+            return Err(Error::UnsupportedLocalArtifact(
+                component.to_string(),
+                components.join(", "),
+            ));
+        }
+        let path = Path::new(path)
+            .canonicalize()
+            .map_err(|_| Error::MissingDownload(path.to_string()))?;
+        Ok(Self {
+            component: component.to_string(),
+            path,
+        })
+    }
+}
+
+/// Gets the names of the components that can be installed from a local archive.
+fn get_local_artifact_components() -> Vec<String> {
+    let mut components: Vec<String> = Target::iter()
+        .map(|target| get_toolchain_name(&target))
+        .collect();
+    components.sort();
+    components.dedup();
+    components.splice(0..0, ["llvm", "rust", "rust-src"].map(str::to_string));
+    components
+}
+
+/// Gets the local archives of [`Context::local_artifacts`], installed instead of the
+/// downloaded ones, by component.
+pub fn get_local_artifacts(artifacts: &[LocalArtifact]) -> HashMap<String, PathBuf> {
+    artifacts
+        .iter()
+        .map(|artifact| (artifact.component.clone(), artifact.path.clone()))
+        .collect()
+}
+
+/// Removes the `com.apple.quarantine` attribute from an extracted tree on macOS, so that
+/// Gatekeeper does not refuse to run the downloaded binaries because "the developer cannot
+/// be verified".
+fn clear_quarantine(context: &Context, path: &Path) {
+    if !cfg!(target_os = "macos") || context.keep_quarantine {
+        return;
+    }
+    debug!(
+        "{} Removing the quarantine attribute from '{}'",
+        emoji::DEBUG,
+        path.display()
+    );
+    // Fails for the files without the attribute, which are fine.
+    let _ = cmd!("xattr", "-r", "-d", "com.apple.quarantine", path)
+        .into_inner()
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
 
 /// Returns the path to the home directory.
 pub fn get_home_dir() -> String {
     home_dir().unwrap().display().to_string()
 }
 
+/// Converts an absolute Windows path into its extended-length form, e.g. `\\?\C:\Users`,
+/// which is not limited to 260 characters. As extended-length paths are not normalized by
+/// Windows, separators are converted to backslashes and `.` and `..` components resolved.
+/// Relative and already extended paths are returned unchanged.
+fn to_extended_length_path(path: &str) -> String {
+    if path.starts_with(r"\\?\") {
+        return path.to_string();
+    }
+    let (prefix, rest) =
+        if let Some(rest) = path.strip_prefix(r"\\").or_else(|| path.strip_prefix("//")) {
+            (r"\\?\UNC\".to_string(), rest)
+        } else if path.len() >= 3
+            && path.as_bytes()[0].is_ascii_alphabetic()
+            && path.as_bytes()[1] == b':'
+            && matches!(path.as_bytes()[2], b'\\' | b'/')
+        {
+            (format!(r"\\?\{}\", &path[..2]), &path[3..])
+        } else {
+            return path.to_string();
+        };
+    let mut components: Vec<&str> = Vec::new();
+    for component in rest.split(['\\', '/']) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    prefix + &components.join("\\")
+}
+
+/// Gets the path to use in file system operations: on Windows, the extended-length form of
+/// absolute paths, so that deep installation directories do not exceed `MAX_PATH`.
+pub fn get_extended_length_path(path: &Path) -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(to_extended_length_path(&path.display().to_string()))
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Returns the disk usage of a directory, in bytes. Symbolic links are not followed.
+pub fn get_dir_size(path: &Path) -> u64 {
+    match symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => read_dir(path)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| get_dir_size(&entry.path()))
+                    .sum()
+            })
+            .unwrap_or(0),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }
+}
+
+/// Queries the GitHub API and returns the parsed JSON response.
+pub fn get_github_json(context: &Context, url: &str) -> Result<serde_json::Value> {
+    let mut headers = header::HeaderMap::new();
+    headers.insert("Accept", "application/vnd.github.v3+json".parse().unwrap());
+    if let Some(token) = context.http.github_token() {
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {token}")
+                .parse()
+                .map_err(|_| Error::InvalidGithubToken)?,
+        );
+    }
+
+    let res = context.http.retry(url, || {
+        let response = context
+            .http
+            .api_client()?
+            .get(url)
+            .headers(headers.clone())
+            .send()?;
+        if is_rate_limited(&response) {
+            return Err(Error::GithubRateLimitExceeded);
+        }
+        Ok(response.error_for_status()?.text()?)
+    })?;
+    let json: serde_json::Value =
+        serde_json::from_str(&res).map_err(|_| Error::FailedToSerializeJson)?;
+    Ok(json)
+}
+
+/// Whether a GitHub API response was rejected due to the rate limit.
+fn is_rate_limited(response: &Response) -> bool {
+    matches!(
+        response.status(),
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+    ) && response
+        .headers()
+        .get("x-ratelimit-remaining")
+        .map_or(false, |remaining| remaining == "0")
+}
+
+/// Result of an installation, with its position in the installations.
+type IndexedResult<T> = (usize, Result<T, Error>);
+
+/// Runs the installations concurrently on a pool of [`INSTALL_WORKERS`] threads, so that
+/// their downloads and extractions overlap and an install takes roughly as long as its
+/// largest artifact. Once an installation fails, the pending ones are not started. Every
+/// started installation is completed before returning their results, in order, or the first
+/// error.
+pub fn install_concurrently<T, F>(installs: Vec<F>) -> Result<Vec<T>, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+{
+    let workers = installs.len().min(INSTALL_WORKERS);
+    let queue = Arc::new(Mutex::new(
+        installs.into_iter().enumerate().collect::<VecDeque<_>>(),
+    ));
+    let failed = Arc::new(AtomicBool::new(false));
+    let handles: Vec<JoinHandle<Vec<IndexedResult<T>>>> = (0..workers)
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            let failed = Arc::clone(&failed);
+            thread::spawn(move || {
+                let mut results = Vec::new();
+                while !failed.load(Ordering::SeqCst) {
+                    let next = queue.lock().unwrap().pop_front();
+                    let (index, install) = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    let result = install();
+                    if result.is_err() {
+                        failed.store(true, Ordering::SeqCst);
+                    }
+                    results.push((index, result));
+                }
+                results
+            })
+        })
+        .collect();
+    let mut results: Vec<IndexedResult<T>> = handles
+        .into_iter()
+        .flat_map(|handle| handle.join().unwrap_or_else(|panic| resume_unwind(panic)))
+        .collect();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Gets the size of a remote file, in bytes, if the server reports it.
+pub fn get_download_size(context: &Context, url: &str) -> Option<u64> {
+    context.downloader().size(url)
+}
+
+/// Opens the download of a URL, reporting its progress.
+fn open_download(
+    context: &Context,
+    downloader: &dyn Downloader,
+    url: &str,
+    file_name: &str,
+) -> Result<impl Read, Error> {
+    let download = downloader.open(url)?;
+    Ok(ProgressReader::new(
+        InterruptibleReader::new(download.reader),
+        &context.progress,
+        file_name,
+        download.size,
+    ))
+}
+
+/// Downloads a URL into a temporary file, retrying on transient failures.
+fn download_to_tempfile(
+    context: &Context,
+    downloader: &dyn Downloader,
+    url: &str,
+    file_name: &str,
+) -> Result<File, Error> {
+    context.http.retry(url, || {
+        let mut response = open_download(context, downloader, url, file_name)?;
+        let mut file = tempfile::tempfile_in(context.temp_dir())?;
+        copy(&mut response, &mut file)?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(file)
+    })
+}
+
+/// Format of a downloaded archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveFormat {
+    Zip,
+    TarGz,
+    TarXz,
+    TarZst,
+    SevenZip,
+}
+
+impl ArchiveFormat {
+    /// Length of the longest magic bytes.
+    const MAGIC_LENGTH: usize = 6;
+
+    /// Detects the format of an archive from its magic bytes.
+    fn from_magic(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+            Some(Self::Zip)
+        } else if bytes.starts_with(b"\x1f\x8b") {
+            Some(Self::TarGz)
+        } else if bytes.starts_with(b"\xfd7zXZ\x00") {
+            Some(Self::TarXz)
+        } else if bytes.starts_with(b"\x28\xb5\x2f\xfd") {
+            Some(Self::TarZst)
+        } else if bytes.starts_with(b"7z\xbc\xaf\x27\x1c") {
+            Some(Self::SevenZip)
+        } else {
+            None
+        }
+    }
+
+    /// Gets the format of an archive from its file extension.
+    fn from_extension(extension: &str) -> Result<Self, Error> {
+        match extension {
+            "zip" => Ok(Self::Zip),
+            "gz" | "tgz" => Ok(Self::TarGz),
+            "xz" => Ok(Self::TarXz),
+            "zst" => Ok(Self::TarZst),
+            "7z" => Ok(Self::SevenZip),
+            _ => Err(Error::UnsuportedFileExtension(extension.to_string())),
+        }
+    }
+
+    /// Detects the format of an archive from its first bytes, as mirrors may serve it under
+    /// another extension, falling back to its extension when they are not recognized.
+    fn detect(bytes: &[u8], extension: &str) -> Result<Self, Error> {
+        match Self::from_magic(bytes) {
+            Some(format) => Ok(format),
+            None => Self::from_extension(extension),
+        }
+    }
+}
+
+/// Reads the first bytes of an archive, enough to detect its format, as the first read of
+/// a download may return fewer of them.
+fn read_magic(archive: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut magic = Vec::with_capacity(ArchiveFormat::MAGIC_LENGTH);
+    archive
+        .take(ArchiveFormat::MAGIC_LENGTH as u64)
+        .read_to_end(&mut magic)?;
+    Ok(magic)
+}
+
+/// Extracts an archive, as it is read, into the output directory.
+fn extract(archive: impl Read, extension: &str, output_directory: &Path) -> Result<(), Error> {
+    let extraction_directory = get_extended_length_path(output_directory);
+    let failed = || Error::FailedToExtract(output_directory.display().to_string());
+    // Reading a download can fail halfway, which is worth retrying, unlike a corrupt archive.
+    let map_err = |e: io::Error| match e.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => failed(),
+        _ => Error::IoError(e),
+    };
+    // The symbolic links are created last, as their target may come later in the archive,
+    // and the directories too, as their permissions may prevent extracting their contents.
+    let unpack = |tarfile: &mut dyn Read| {
+        create_dir_all(&extraction_directory)?;
+        let mut archive = Archive::new(tarfile);
+        let mut directories = Vec::new();
+        let mut symlinks = Vec::new();
+        for entry in archive.entries().map_err(map_err)? {
+            let mut entry = entry.map_err(map_err)?;
+            let entry_type = entry.header().entry_type();
+            if entry_type.is_symlink() {
+                if let (Ok(path), Ok(Some(target))) = (entry.path(), entry.link_name()) {
+                    symlinks.push(links::Symlink {
+                        path: path.into_owned(),
+                        target: target.into_owned(),
+                    });
+                    continue;
+                }
+            }
+            if entry_type.is_dir() {
+                directories.push(entry);
+                continue;
+            }
+            entry.unpack_in(&extraction_directory).map_err(map_err)?;
+        }
+        links::create_symlinks(&extraction_directory, &symlinks)?;
+        for mut directory in directories {
+            directory
+                .unpack_in(&extraction_directory)
+                .map_err(map_err)?;
+        }
+        Ok(())
+    };
+    let mut archive = BufReader::new(archive);
+    let magic = read_magic(&mut archive).map_err(map_err)?;
+    let format = ArchiveFormat::detect(&magic, extension)?;
+    let archive = magic.as_slice().chain(archive);
+    match format {
+        ArchiveFormat::Zip => {
+            extract_zip_stream(archive, &extraction_directory).map_err(|e| match e {
+                ZipError::Io(e) => map_err(e),
+                ZipError::UnsupportedArchive(_) => {
+                    Error::UnstreamableArchive(output_directory.display().to_string())
+                }
+                _ => failed(),
+            })
+        }
+        ArchiveFormat::TarGz => {
+            info!(
+                "{} Uncompressing tar.gz file to '{}'",
+                emoji::WRENCH,
+                output_directory.display()
+            );
+            unpack(&mut GzDecoder::new(archive))
+        }
+        ArchiveFormat::TarXz => {
+            info!(
+                "{} Uncompressing tar.xz file to '{}'",
+                emoji::WRENCH,
+                output_directory.display()
+            );
+            unpack(&mut XzDecoder::new(archive))
+        }
+        ArchiveFormat::TarZst => {
+            info!(
+                "{} Uncompressing tar.zst file to '{}'",
+                emoji::WRENCH,
+                output_directory.display()
+            );
+            unpack(&mut zstd::Decoder::with_buffer(archive).map_err(map_err)?)
+        }
+        ArchiveFormat::SevenZip => Err(Error::UnstreamableArchive(
+            output_directory.display().to_string(),
+        )),
+    }
+}
+
+/// Extracts a zip archive entry by entry, from their local headers. Archives whose entries
+/// are only sized in the central directory, at the end of the archive, are not supported.
+/// The permissions and symbolic links, which the local headers do not hold, are restored
+/// from the central directory once the entries are extracted.
+fn extract_zip_stream(mut archive: impl Read, extraction_directory: &Path) -> ZipResult<()> {
+    while let Some(mut entry) = zip::read::read_zipfile_from_stream(&mut archive)? {
+        let path = match entry.enclosed_name() {
+            Some(path) => extraction_directory.join(path),
+            None => return Err(ZipError::InvalidArchive("Invalid file path")),
+        };
+        if entry.is_dir() {
+            create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        copy(&mut entry, &mut File::create(&path)?)?;
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode & 0o777))?;
+        }
+    }
+    // The permissions and symbolic links are only in the central directory, which follows
+    // the entries, and whose first signature was read.
+    let mut central_directory = links::CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes().to_vec();
+    archive.read_to_end(&mut central_directory)?;
+    links::restore_modes(
+        extraction_directory,
+        &links::parse_zip_modes(&central_directory),
+    )?;
+    Ok(())
+}
+
+/// Extracts a 7z archive, which needs to be seeked, so can not be extracted while it is
+/// downloaded.
+fn extract_7z(archive: File, output_directory: &Path) -> Result<(), Error> {
+    info!(
+        "{} Uncompressing 7z file to '{}'",
+        emoji::WRENCH,
+        output_directory.display()
+    );
+    seven_zip::extract(
+        BufReader::new(archive),
+        &get_extended_length_path(output_directory),
+    )
+    .map_err(|e| match e.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+            Error::FailedToExtract(output_directory.display().to_string())
+        }
+        _ => Error::IoError(e),
+    })
+}
+
+/// Extracts a downloaded archive, when it could not be extracted while downloading it.
+fn extract_downloaded(
+    mut archive: File,
+    extension: &str,
+    output_directory: &Path,
+) -> Result<(), Error> {
+    let magic = read_magic(&mut archive)?;
+    archive.seek(SeekFrom::Start(0))?;
+    match ArchiveFormat::detect(&magic, extension)? {
+        ArchiveFormat::SevenZip => extract_7z(archive, output_directory),
+        ArchiveFormat::Zip => {
+            let extraction_directory = get_extended_length_path(output_directory);
+            let mut zipfile = zip::ZipArchive::new(archive)
+                .and_then(|mut zipfile| zipfile.extract(&extraction_directory).map(|_| zipfile))
+                .map_err(|_| Error::FailedToExtract(output_directory.display().to_string()))?;
+            let modes: Vec<(PathBuf, u32)> = (0..zipfile.len())
+                .filter_map(|index| {
+                    let entry = zipfile.by_index_raw(index).ok()?;
+                    Some((entry.enclosed_name()?.to_path_buf(), entry.unix_mode()?))
+                })
+                .collect();
+            links::restore_modes(&extraction_directory, &modes)?;
+            Ok(())
+        }
+        _ => extract(archive, extension, output_directory),
+    }
+}
+
+/// Empties a directory, creating it if needed.
+fn reset_directory(directory: &Path) -> Result<(), Error> {
+    let extended_length_directory = get_extended_length_path(directory);
+    if extended_length_directory.exists() {
+        remove_dir_all(&extended_length_directory)?;
+    }
+    create_dir_all(&extended_length_directory)
+        .map_err(|_| Error::FailedToCreateDirectory(directory.display().to_string()))
+}
+
+/// Moves the extracted contents of the staging directory into the output directory. The
+/// whole directory is renamed when the output directory does not exist yet, otherwise its
+/// entries are, replacing the existing ones.
+fn move_into_place(staging_directory: &Path, output_directory: &Path) -> Result<(), Error> {
+    let staging_directory = &get_extended_length_path(staging_directory);
+    let output_directory = &get_extended_length_path(output_directory);
+    if !output_directory.exists() {
+        move_path(staging_directory, output_directory)?;
+        return Ok(());
+    }
+    for entry in read_dir(staging_directory)? {
+        let entry = entry?;
+        let destination = output_directory.join(entry.file_name());
+        if destination.is_dir() {
+            remove_dir_all(&destination)?;
+        }
+        move_path(&entry.path(), &destination)?;
+    }
+    remove_dir_all(staging_directory)?;
+    Ok(())
+}
+
+/// Moves a file or directory, copying it when it cannot be renamed, e.g. across
+/// filesystems. The copies are reflinks where the filesystem supports them.
+fn move_path(source: &Path, destination: &Path) -> Result<(), Error> {
+    if rename(source, destination).is_ok() {
+        return Ok(());
+    }
+    debug!(
+        "{} Failed to rename '{}', copying it",
+        emoji::DEBUG,
+        source.display()
+    );
+    if source.symlink_metadata()?.is_dir() {
+        reflink::copy_dir(source, destination)?;
+        remove_dir_all(source)?;
+    } else {
+        reflink::reflink_or_copy(source, destination)?;
+        remove_file(source)?;
+    }
+    Ok(())
+}
+
 /// Downloads a file from a URL and uncompresses it, if necesary, to the output directory.
+/// Archives are extracted while they are downloaded, into a staging directory that is moved
+/// into place once complete, so that a failure never leaves a partially extracted tool
+/// behind.
 pub fn download_file(
+    context: &Context,
+    url: String,
+    file_name: &str,
+    output_directory: &str,
+    uncompress: bool,
+) -> Result<String, Error> {
+    let start = Instant::now();
+    let result = download(
+        context,
+        context.downloader().as_ref(),
+        url,
+        file_name,
+        output_directory,
+        uncompress,
+    );
+    context
+        .summary
+        .record(Path::new(output_directory), start.elapsed());
+    match &result {
+        Ok(_) => context
+            .progress
+            .emit(Event::new(file_name, Phase::Finished)),
+        Err(e) => context
+            .progress
+            .emit(Event::failed(file_name, e, e.exit_code())),
+    }
+    result
+}
+
+/// Downloads the file of [`download_file`], which reports whether it succeeded.
+fn download(
+    context: &Context,
+    downloader: &dyn Downloader,
     url: String,
     file_name: &str,
     output_directory: &str,
@@ -30,65 +661,674 @@ pub fn download_file(
 ) -> Result<String, Error> {
     let file_path = format!("{}/{}", output_directory, file_name);
     if Path::new(&file_path).exists() {
-        info!("{} Using cached file: '{}'", emoji::INFO, file_path);
-        return Ok(file_path);
-    } else if !Path::new(&output_directory).exists() {
+        // Files downloaded as is are checked against the digest recorded along with them,
+        // as an interrupted download would otherwise be reused by every installation.
+        if uncompress || cache::is_intact(Path::new(&file_path)) {
+            info!("{} Using cached file: '{}'", emoji::INFO, file_path);
+            return Ok(file_path);
+        }
+        warn!(
+            "{} Cached file '{}' does not match its recorded size and hash, downloading it again",
+            emoji::WARN,
+            file_path
+        );
+        cache::remove_digested(Path::new(&file_path))
+            .map_err(|_| Error::FailedToRemoveFile(file_path.clone()))?;
+    }
+    context.rollback.track(Path::new(output_directory));
+    // Archives are extracted into a staging directory, created next to the output one.
+    if !uncompress && !Path::new(&output_directory).exists() {
         info!(
             "{} Creating directory: '{}'",
             emoji::WRENCH,
             output_directory
         );
-        if let Err(_e) = create_dir_all(output_directory) {
+        if let Err(_e) = create_dir_all(get_extended_length_path(Path::new(output_directory))) {
             return Err(Error::FailedToCreateDirectory(output_directory.to_string()));
         }
     }
+    // Archives downloaded before, e.g. for another version, are extracted from the cache,
+    // unless their signature has to be verified.
+    let cache = uncompress
+        && !url.starts_with("file://")
+        && cache::is_cached(context, Path::new(output_directory));
+    if cache && !context.verifies_signatures() {
+        if let Some((hash, archive)) = cache::open_archive(context, &url) {
+            info!(
+                "{} Using cached archive {} ({})",
+                emoji::INFO,
+                file_name,
+                hash
+            );
+            let staging_directory = get_staging_directory(context, output_directory);
+            context.rollback.track(&staging_directory);
+            reset_directory(&staging_directory)?;
+            context
+                .progress
+                .emit(Event::new(file_name, Phase::Extracting));
+            extract_downloaded(archive, get_extension(file_name), &staging_directory)?;
+            install_extracted(context, &hash, &staging_directory, output_directory)?;
+            cache::record_archive(context, &hash, &url, file_name, Path::new(output_directory));
+            return Ok(file_path);
+        }
+    }
+    // Downloads failing from a mirror, e.g. once retried or because it serves a corrupt
+    // archive, are downloaded from the next one.
+    let urls = context.http.mirror_urls(&url);
+    let (last_url, failover_urls) = urls.split_last().unwrap();
+    let download_mirror = |mirror_url: &str| {
+        download_from(
+            context,
+            downloader,
+            mirror_url,
+            file_name,
+            output_directory,
+            uncompress,
+            cache,
+        )
+        .map(|hash| {
+            if let Some(hash) = hash.filter(|_| cache) {
+                cache::record_archive(context, &hash, &url, file_name, Path::new(output_directory));
+            }
+            file_path.clone()
+        })
+    };
+    for url in failover_urls {
+        match download_mirror(url) {
+            Err(e) => warn!(
+                "{} Failed to download file {} from {}: {}. Trying the next mirror",
+                emoji::WARN,
+                file_name,
+                url,
+                e
+            ),
+            result => return result,
+        }
+    }
+    download_mirror(last_url)
+}
+
+/// Downloads an archive into the cache without extracting it, unless it is cached already,
+/// and returns its hash. Its signature is verified when enabled, as for an installation.
+pub fn fetch_archive(context: &Context, url: &str) -> Result<String, Error> {
+    let file_name = url.rsplit('/').next().unwrap_or(url);
+    if !context.verifies_signatures() {
+        if let Some((hash, _)) = cache::open_archive(context, url) {
+            info!(
+                "{} Using cached archive {} ({})",
+                emoji::INFO,
+                file_name,
+                hash
+            );
+            return Ok(hash);
+        }
+    }
+    let downloader = context.downloader();
+    let downloader = downloader.as_ref();
+    let fetch_from = |mirror_url: &str| -> Result<String, Error> {
+        info!(
+            "{} Downloading file {} from {}",
+            emoji::DOWNLOAD,
+            file_name,
+            mirror_url
+        );
+        let mut archive = download_to_tempfile(context, downloader, mirror_url, file_name)?;
+        signature::verify_download(context, downloader, mirror_url, &mut archive)?;
+        Ok(CachingReader::new(context, &mut archive, true).finish()?)
+    };
+    // Downloads failing from a mirror, e.g. once retried or because it serves a corrupt
+    // archive, are downloaded from the next one.
+    let urls = context.http.mirror_urls(url);
+    let (last_url, failover_urls) = urls.split_last().unwrap();
+    let mut fetched = None;
+    for mirror_url in failover_urls {
+        match fetch_from(mirror_url) {
+            Err(e) => warn!(
+                "{} Failed to download file {} from {}: {}. Trying the next mirror",
+                emoji::WARN,
+                file_name,
+                mirror_url,
+                e
+            ),
+            result => {
+                fetched = Some(result);
+                break;
+            }
+        }
+    }
+    let hash = fetched.unwrap_or_else(|| fetch_from(last_url))?;
+    cache::record_fetched_archive(context, &hash, url, file_name);
+    Ok(hash)
+}
+
+/// Gets the extension of an archive.
+fn get_extension(file_name: &str) -> &str {
+    Path::new(file_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+}
+
+/// Gets the directory archives are extracted into, next to the output one unless a staging
+/// directory is set.
+fn get_staging_directory(context: &Context, output_directory: &str) -> PathBuf {
+    get_staging_directory_in(context.staging_dir.as_deref(), output_directory)
+}
+
+/// Gets the directory of [`get_staging_directory`] in `staging_dir`, if set. Its name ends
+/// with the hash of the output directory, as the tools share version names.
+fn get_staging_directory_in(staging_dir: Option<&Path>, output_directory: &str) -> PathBuf {
+    let output_directory = output_directory.trim_end_matches('/');
+    match staging_dir {
+        Some(staging_dir) => {
+            let hash = format!("{:x}", Sha256::digest(output_directory.as_bytes()));
+            let name = Path::new(output_directory)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            staging_dir.join(format!("{}-{}.staging", name, &hash[..12]))
+        }
+        None => PathBuf::from(format!("{output_directory}.staging")),
+    }
+}
+
+/// Installs `output_directory` with `install`, which creates it in a staging directory
+/// moved into place once it succeeds, so that a failure never leaves a partial directory.
+pub fn stage(
+    context: &Context,
+    output_directory: &Path,
+    install: impl FnOnce(&Path) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let staging_directory = get_staging_directory(context, &output_directory.display().to_string());
+    context.rollback.track(&staging_directory);
+    reset_directory(&staging_directory)?;
+    install(&staging_directory)?;
+    move_into_place(&staging_directory, output_directory)
+}
+
+/// Moves the directory extracted from the archive with `hash` into place.
+fn install_extracted(
+    context: &Context,
+    hash: &str,
+    staging_directory: &Path,
+    output_directory: &str,
+) -> Result<(), Error> {
+    // Identical archives, e.g. a toolchain pinned by several versions, are stored once.
+    store::link_identical(
+        context,
+        hash,
+        staging_directory,
+        Path::new(output_directory),
+    );
+    move_into_place(staging_directory, Path::new(output_directory))?;
+    store::record(context, hash, Path::new(output_directory));
+    clear_quarantine(context, Path::new(output_directory));
+    Ok(())
+}
+
+/// Reader copying what it reads to a spool file, if any, so that an archive that can not
+/// be extracted while downloading it is extracted from the spool instead of downloaded again.
+struct SpoolingReader<'a, R> {
+    reader: R,
+    spool: Option<&'a mut File>,
+}
+
+impl<R: Read> Read for SpoolingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        if let Some(spool) = &mut self.spool {
+            spool.write_all(&buf[..read])?;
+        }
+        Ok(read)
+    }
+}
+
+/// Archive extracted while downloading it or, when not supported, downloaded to a file.
+enum Streamed {
+    /// The archive was extracted, with its hash.
+    Extracted(String),
+    /// The archive was downloaded to a file, with its hash.
+    Downloaded(String, File),
+}
+
+/// Downloads the file of [`download`] from one of its URLs, returning the hash of the
+/// archive when it is extracted. The archive is stored in the cache when `cache` is set and
+/// the archives are persisted, see [`Context::persist_archives`].
+fn download_from(
+    context: &Context,
+    downloader: &dyn Downloader,
+    url: &str,
+    file_name: &str,
+    output_directory: &str,
+    uncompress: bool,
+    cache: bool,
+) -> Result<Option<String>, Error> {
+    let file_path = format!("{}/{}", output_directory, file_name);
     info!(
         "{} Downloading file {} from {}",
         emoji::DOWNLOAD,
         file_name,
         url
     );
-    let mut resp = reqwest::blocking::get(&url).unwrap();
 
     if uncompress {
-        let extension = Path::new(file_name).extension().unwrap().to_str().unwrap();
-        match extension {
-            "zip" => {
-                let mut tmpfile = tempfile::tempfile().unwrap();
-                resp.copy_to(&mut tmpfile)?;
-                let mut zipfile = zip::ZipArchive::new(tmpfile).unwrap();
-                zipfile.extract(output_directory).unwrap();
-            }
-            "gz" => {
-                info!(
-                    "{} Uncompressing tar.gz file to '{}'",
-                    emoji::WRENCH,
-                    output_directory
+        let extension = get_extension(file_name);
+        let staging_directory = get_staging_directory(context, output_directory);
+        let staging_directory = staging_directory.as_path();
+        context.rollback.track(staging_directory);
+        // Every attempt extracts the archive from scratch. Archives whose signature is
+        // verified are downloaded first, as they are only extracted once verified.
+        let persist = cache && context.persist_archives;
+        let streamed = if context.verifies_signatures() {
+            Err(Error::UnstreamableArchive(file_name.to_string()))
+        } else {
+            context.http.retry(url, || {
+                reset_directory(staging_directory)?;
+                context
+                    .progress
+                    .emit(Event::new(file_name, Phase::Extracting));
+                // Only zip archives whose entries are sized in their central directory, and 7z
+                // ones, can not be streamed, what is downloaded of them is kept.
+                let mut spool = match extension {
+                    "zip" | "7z" => Some(tempfile::tempfile_in(context.temp_dir())?),
+                    _ => None,
+                };
+                let spooled = spool.is_some();
+                let reader = SpoolingReader {
+                    reader: open_download(context, downloader, url, file_name)?,
+                    spool: spool.as_mut(),
+                };
+                let mut archive = CachingReader::new(context, reader, persist);
+                match extract(&mut archive, extension, staging_directory) {
+                    Ok(()) => Ok(Streamed::Extracted(archive.finish()?)),
+                    Err(Error::UnstreamableArchive(_)) if spooled => {
+                        // The rest of the archive is downloaded to the spool.
+                        let hash = archive.finish()?;
+                        let mut spool = spool.take().unwrap();
+                        spool.seek(SeekFrom::Start(0))?;
+                        Ok(Streamed::Downloaded(hash, spool))
+                    }
+                    Err(e) => Err(e),
+                }
+            })
+        };
+        let hash = match streamed {
+            Ok(Streamed::Extracted(hash)) => hash,
+            Ok(Streamed::Downloaded(hash, archive)) => {
+                debug!(
+                    "{} '{}' can not be extracted while downloading it, extracting it once downloaded",
+                    emoji::DEBUG,
+                    file_name
                 );
-                let content_br = BufReader::new(resp);
-                let tarfile = GzDecoder::new(content_br);
-                let mut archive = Archive::new(tarfile);
-                archive.unpack(output_directory).unwrap();
+                reset_directory(staging_directory)?;
+                extract_downloaded(archive, extension, staging_directory)?;
+                hash
             }
-            "xz" => {
-                info!(
-                    "{} Uncompressing tar.xz file to '{}'",
-                    emoji::WRENCH,
-                    output_directory
+            Err(Error::UnstreamableArchive(_)) => {
+                debug!(
+                    "{} '{}' is not extracted while downloading it, downloading it first",
+                    emoji::DEBUG,
+                    file_name
                 );
-                let content_br = BufReader::new(resp);
-                let tarfile = XzDecoder::new(content_br);
-                let mut archive = Archive::new(tarfile);
-                archive.unpack(output_directory).unwrap();
+                reset_directory(staging_directory)?;
+                let mut archive = download_to_tempfile(context, downloader, url, file_name)?;
+                signature::verify_download(context, downloader, url, &mut archive)?;
+                let hash = CachingReader::new(context, &mut archive, persist).finish()?;
+                archive.seek(SeekFrom::Start(0))?;
+                extract_downloaded(archive, extension, staging_directory)?;
+                hash
             }
-            _ => {
-                return Err(Error::UnsuportedFileExtension(extension.to_string()));
-            }
-        }
+            Err(e) => return Err(e),
+        };
+        install_extracted(context, &hash, staging_directory, output_directory)?;
+        Ok(Some(hash))
     } else {
+        let mut resp = download_to_tempfile(context, downloader, url, file_name)?;
+        signature::verify_download(context, downloader, url, &mut resp)?;
         info!("{} Creating file: '{}'", emoji::WRENCH, file_path);
-        let mut out = File::create(file_path)?;
-        copy(&mut resp, &mut out)?;
+        let mut out = File::create(get_extended_length_path(Path::new(&file_path)))?;
+        let mut reader = HashingReader::new(&mut resp);
+        // A partially written file is not left behind, e.g. when interrupted.
+        let size = match copy(&mut reader, &mut out).and_then(|size| {
+            out.sync_all()?;
+            Ok(size)
+        }) {
+            Ok(size) => size,
+            Err(e) => {
+                drop(out);
+                let _ = remove_file(get_extended_length_path(Path::new(&file_path)));
+                return Err(e.into());
+            }
+        };
+        let digest = FileDigest {
+            size,
+            sha256: reader.finish()?,
+        };
+        cache::record_digest(Path::new(&file_path), &digest)?;
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        context::Context,
+        download::MockDownloader,
+        error::Error,
+        toolchain::{
+            download, extract, get_staging_directory_in, install_concurrently, move_into_place,
+            to_extended_length_path, ArchiveFormat, LocalArtifact,
+        },
+    };
+    use flate2::{write::GzEncoder, Compression};
+    use std::{
+        fs::{create_dir_all, read_to_string, write},
+        io::{Cursor, Read, Write},
+        path::Path,
+    };
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_extract() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(3);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "bin/gcc", "gcc".as_bytes())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        builder.append_link(&mut header, "bin/cc", "gcc").unwrap();
+        let tarball = builder.into_inner().unwrap().finish().unwrap();
+        extract(tarball.as_slice(), "gz", temp_dir.path()).unwrap();
+        assert_eq!(
+            read_to_string(temp_dir.path().join("bin").join("gcc")).unwrap(),
+            "gcc"
+        );
+        // Created as a link or, where not supported, as a copy of its target.
+        assert_eq!(
+            read_to_string(temp_dir.path().join("bin").join("cc")).unwrap(),
+            "gcc"
+        );
+
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("bin/openocd.exe", Default::default())
+            .unwrap();
+        writer.write_all(b"openocd").unwrap();
+        writer
+            .start_file(
+                "bin/openocd",
+                zip::write::FileOptions::default().unix_permissions(0o755),
+            )
+            .unwrap();
+        writer.write_all(b"openocd").unwrap();
+        let zipfile = writer.finish().unwrap().into_inner();
+        extract(zipfile.as_slice(), "zip", temp_dir.path()).unwrap();
+        assert_eq!(
+            read_to_string(temp_dir.path().join("bin").join("openocd.exe")).unwrap(),
+            "openocd"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = std::fs::metadata(temp_dir.path().join("bin").join("openocd"));
+            assert_eq!(metadata.unwrap().permissions().mode() & 0o777, 0o755);
+        }
+
+        let mut tarball = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(3);
+        header.set_cksum();
+        tarball
+            .append_data(&mut header, "bin/gdb", "gdb".as_bytes())
+            .unwrap();
+        let tarball = zstd::encode_all(tarball.into_inner().unwrap().as_slice(), 0).unwrap();
+        // Detected from the magic bytes, despite the extension, even when the first read
+        // returns part of them.
+        extract((&tarball[..2]).chain(&tarball[2..]), "gz", temp_dir.path()).unwrap();
+        assert_eq!(
+            read_to_string(temp_dir.path().join("bin").join("gdb")).unwrap(),
+            "gdb"
+        );
+
+        assert!(matches!(
+            extract([7; 1024].as_slice(), "gz", temp_dir.path()),
+            Err(Error::FailedToExtract(_))
+        ));
+    }
+
+    #[test]
+    fn test_archive_format() {
+        assert_eq!(
+            ArchiveFormat::from_magic(b"\x28\xb5\x2f\xfd\x00"),
+            Some(ArchiveFormat::TarZst)
+        );
+        assert_eq!(
+            ArchiveFormat::from_magic(b"\xfd7zXZ\x00\x00"),
+            Some(ArchiveFormat::TarXz)
+        );
+        assert_eq!(ArchiveFormat::from_magic(b"<html>"), None);
+        assert_eq!(
+            ArchiveFormat::from_extension("zst").unwrap(),
+            ArchiveFormat::TarZst
+        );
+        assert_eq!(
+            ArchiveFormat::detect(b"7z\xbc\xaf\x27\x1c\x00", "zip").unwrap(),
+            ArchiveFormat::SevenZip
+        );
+        assert!(ArchiveFormat::from_extension("rar").is_err());
+    }
+
+    #[test]
+    fn test_download() {
+        let temp_dir = TempDir::new().unwrap();
+        let context = Context::default();
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(7);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "bin/openocd", "openocd".as_bytes())
+            .unwrap();
+        let tarball = builder.into_inner().unwrap().finish().unwrap();
+        let downloader = MockDownloader::default()
+            .with_file("https://example.com/openocd.tar.gz", tarball)
+            .with_file("https://example.com/rom.elf", b"elf".to_vec());
+        let output_directory = temp_dir.path().join("openocd").display().to_string();
+
+        download(
+            &context,
+            &downloader,
+            "https://example.com/openocd.tar.gz".to_string(),
+            "openocd.tar.gz",
+            &output_directory,
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            read_to_string(Path::new(&output_directory).join("bin").join("openocd")).unwrap(),
+            "openocd"
+        );
+        assert!(!Path::new(&format!("{output_directory}.staging")).exists());
+
+        let file_path = download(
+            &context,
+            &downloader,
+            "https://example.com/rom.elf".to_string(),
+            "rom.elf",
+            &output_directory,
+            false,
+        )
+        .unwrap();
+        assert_eq!(read_to_string(file_path).unwrap(), "elf");
+        assert!(download(
+            &context,
+            &downloader,
+            "https://example.com/missing.tar.gz".to_string(),
+            "missing.tar.gz",
+            &output_directory,
+            true,
+        )
+        .is_err());
+        assert_eq!(downloader.requests().len(), 3);
+
+        // Files downloaded as is are reused while intact, and downloaded again once not.
+        let file_path = Path::new(&output_directory).join("rom.elf");
+        let download_rom = || {
+            download(
+                &context,
+                &downloader,
+                "https://example.com/rom.elf".to_string(),
+                "rom.elf",
+                &output_directory,
+                false,
+            )
+            .unwrap()
+        };
+        download_rom();
+        assert_eq!(downloader.requests().len(), 3);
+        write(&file_path, "el").unwrap();
+        download_rom();
+        assert_eq!(read_to_string(&file_path).unwrap(), "elf");
+        assert_eq!(downloader.requests().len(), 4);
+
+        // Zip archives whose entries are only sized in the central directory, flagged in
+        // their local header, are extracted once downloaded, without downloading them again.
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("bin/dfu-util", Default::default())
+            .unwrap();
+        writer.write_all(b"dfu-util").unwrap();
+        let mut zipfile = writer.finish().unwrap().into_inner();
+        zipfile[6] |= 0x08;
+        let downloader =
+            MockDownloader::default().with_file("https://example.com/dfu-util.zip", zipfile);
+        let output_directory = temp_dir.path().join("dfu-util").display().to_string();
+        download(
+            &context,
+            &downloader,
+            "https://example.com/dfu-util.zip".to_string(),
+            "dfu-util.zip",
+            &output_directory,
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            read_to_string(Path::new(&output_directory).join("bin").join("dfu-util")).unwrap(),
+            "dfu-util"
+        );
+        assert_eq!(downloader.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_get_staging_directory_in() {
+        assert_eq!(
+            get_staging_directory_in(None, "/esp/tools/openocd-esp32/v0.12.0/"),
+            Path::new("/esp/tools/openocd-esp32/v0.12.0.staging")
+        );
+        let staging_directory = get_staging_directory_in(
+            Some(Path::new("/scratch")),
+            "/esp/tools/openocd-esp32/v0.12.0",
+        );
+        assert!(staging_directory.starts_with("/scratch"));
+        let name = staging_directory.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("v0.12.0-") && name.ends_with(".staging"));
+        assert_ne!(
+            get_staging_directory_in(Some(Path::new("/scratch")), "/esp/tools/qemu/v0.12.0"),
+            staging_directory
+        );
+    }
+
+    #[test]
+    fn test_move_into_place() {
+        let temp_dir = TempDir::new().unwrap();
+        let staging_directory = temp_dir.path().join("tool.staging");
+        let output_directory = temp_dir.path().join("tool");
+        create_dir_all(staging_directory.join("bin")).unwrap();
+        write(staging_directory.join("bin").join("new"), "new").unwrap();
+        create_dir_all(output_directory.join("bin")).unwrap();
+        write(output_directory.join("bin").join("old"), "old").unwrap();
+        write(output_directory.join("keep"), "keep").unwrap();
+
+        move_into_place(&staging_directory, &output_directory).unwrap();
+        assert!(!staging_directory.exists());
+        assert!(!output_directory.join("bin").join("old").exists());
+        assert_eq!(
+            read_to_string(output_directory.join("bin").join("new")).unwrap(),
+            "new"
+        );
+        assert!(output_directory.join("keep").exists());
+    }
+
+    #[test]
+    fn test_install_concurrently() {
+        let installs: Vec<Box<dyn FnOnce() -> Result<usize, Error> + Send>> =
+            vec![Box::new(|| Ok(1)), Box::new(|| Ok(2))];
+        assert_eq!(install_concurrently(installs).unwrap(), vec![1, 2]);
+        let installs: Vec<Box<dyn FnOnce() -> Result<usize, Error> + Send>> = vec![
+            Box::new(|| Ok(1)),
+            Box::new(|| Err(Error::FailedToExtract("gcc".to_string()))),
+        ];
+        assert!(matches!(
+            install_concurrently(installs),
+            Err(Error::FailedToExtract(_))
+        ));
+        let installs: Vec<Box<dyn FnOnce() -> Result<usize, Error> + Send>> = (0..10usize)
+            .map(|i| Box::new(move || Ok(i)) as Box<dyn FnOnce() -> Result<usize, Error> + Send>)
+            .collect();
+        assert_eq!(
+            install_concurrently(installs).unwrap(),
+            (0..10).collect::<Vec<usize>>()
+        );
+    }
+
+    #[test]
+    fn test_local_artifact() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("xtensa-esp32-elf.tar.xz");
+        write(&archive, "gcc").unwrap();
+        let artifact =
+            LocalArtifact::parse(&format!("xtensa-esp32-elf={}", archive.display())).unwrap();
+        assert_eq!(artifact.component, "xtensa-esp32-elf");
+        assert_eq!(artifact.path, archive.canonicalize().unwrap());
+        assert!(LocalArtifact::parse(&format!("llvm={}", archive.display())).is_ok());
+        assert!(matches!(
+            LocalArtifact::parse(&archive.display().to_string()),
+            Err(Error::InvalidLocalArtifact(_))
+        ));
+        assert!(matches!(
+            LocalArtifact::parse(&format!("openocd={}", archive.display())),
+            Err(Error::UnsupportedLocalArtifact(_, _))
+        ));
+        assert!(matches!(
+            LocalArtifact::parse("rust=/missing/rust.tar.xz"),
+            Err(Error::MissingDownload(_))
+        ));
+    }
+
+    #[test]
+    fn test_to_extended_length_path() {
+        assert_eq!(
+            to_extended_length_path(r"C:\Users\esp/.espressif/tools/../dist"),
+            r"\\?\C:\Users\esp\.espressif\dist"
+        );
+        assert_eq!(
+            to_extended_length_path(r"\\server\share\espup"),
+            r"\\?\UNC\server\share\espup"
+        );
+        assert_eq!(
+            to_extended_length_path(r"\\?\C:\Users\esp"),
+            r"\\?\C:\Users\esp"
+        );
+        assert_eq!(to_extended_length_path("tools/dist"), "tools/dist");
+        assert_eq!(
+            to_extended_length_path("/home/esp/.espressif"),
+            "/home/esp/.espressif"
+        );
     }
-    Ok(format!("{}/{}", output_directory, file_name))
 }
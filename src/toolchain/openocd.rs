@@ -0,0 +1,97 @@
+//! OpenOCD source and installation tools
+
+use crate::{
+    context::Context,
+    emoji,
+    error::Error,
+    host_triple::HostTriple,
+    toolchain::tool::{get_host_archive_extension, ReleaseAsset, ReleaseTool},
+};
+use log::info;
+
+pub const DEFAULT_OPENOCD_REPOSITORY: &str =
+    "https://github.com/espressif/openocd-esp32/releases/download";
+const DEFAULT_OPENOCD_VERSION: &str = "0.12.0-esp32-20230921";
+/// Name of the OpenOCD tool directory.
+pub const OPENOCD_NAME: &str = "openocd-esp32";
+
+/// Gets OpenOCD, exporting the location of its scripts.
+pub fn get_openocd(context: &Context, host_triple: &HostTriple) -> ReleaseTool {
+    let asset = format!(
+        "{}-{}-{}.{}",
+        OPENOCD_NAME,
+        get_arch(host_triple),
+        DEFAULT_OPENOCD_VERSION,
+        get_host_archive_extension(host_triple)
+    );
+    let version = format!("v{DEFAULT_OPENOCD_VERSION}");
+    ReleaseTool::new(
+        context,
+        "OpenOCD",
+        ReleaseAsset {
+            name: OPENOCD_NAME,
+            component: "openocd",
+            repository: DEFAULT_OPENOCD_REPOSITORY,
+            tag: &version,
+            asset: &asset,
+            version: &version,
+        },
+    )
+    .with_bin_dir(&format!("{OPENOCD_NAME}/bin"))
+    .with_variable(
+        "OPENOCD_SCRIPTS",
+        &format!("{OPENOCD_NAME}/share/openocd/scripts"),
+    )
+}
+
+/// Gets the path of the udev rules granting access to the debug probes.
+pub fn get_udev_rules_path(openocd: &ReleaseTool) -> String {
+    format!(
+        "{}/{}/share/openocd/contrib/60-openocd.rules",
+        openocd.path.display(),
+        OPENOCD_NAME
+    )
+}
+
+/// Installs OpenOCD, pointing to its udev rules on Linux.
+pub fn install_openocd(context: &Context, openocd: &ReleaseTool) -> Result<Vec<String>, Error> {
+    let exports = openocd.install(context)?;
+    if cfg!(target_os = "linux") {
+        info!(
+            "{} To access the debug probes without root permissions, copy '{}' to '/etc/udev/rules.d/' and reload the udev rules",
+            emoji::INFO,
+            get_udev_rules_path(openocd)
+        );
+    }
+    Ok(exports)
+}
+
+/// Gets the name of the OpenOCD arch based on the host triple.
+fn get_arch(host_triple: &HostTriple) -> &str {
+    match host_triple {
+        HostTriple::Aarch64AppleDarwin => "macos-arm64",
+        HostTriple::X86_64AppleDarwin => "macos",
+        HostTriple::X86_64UnknownLinuxGnu | HostTriple::X86_64UnknownLinuxMusl => "linux-amd64",
+        HostTriple::Aarch64UnknownLinuxGnu | HostTriple::Aarch64UnknownLinuxMusl => "linux-arm64",
+        HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => "win32",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{context::Context, host_triple::HostTriple, toolchain::openocd::get_openocd};
+
+    #[test]
+    fn test_get_openocd() {
+        let openocd = get_openocd(&Context::default(), &HostTriple::Aarch64AppleDarwin);
+        assert_eq!(
+            openocd.repository_url,
+            "https://github.com/espressif/openocd-esp32/releases/download/v0.12.0-esp32-20230921/openocd-esp32-macos-arm64-0.12.0-esp32-20230921.tar.gz"
+        );
+        assert!(openocd
+            .path
+            .ends_with("openocd-esp32/v0.12.0-esp32-20230921"));
+        #[cfg(unix)]
+        assert!(openocd.get_exports()[1].starts_with("export OPENOCD_SCRIPTS="));
+    }
+}
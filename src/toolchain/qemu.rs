@@ -0,0 +1,147 @@
+//! QEMU emulator source and installation tools
+
+use crate::{
+    context::Context,
+    host_triple::HostTriple,
+    targets::Target,
+    toolchain::tool::{ReleaseAsset, ReleaseTool},
+};
+use std::collections::{BTreeSet, HashSet};
+use strum::Display;
+
+pub const DEFAULT_QEMU_REPOSITORY: &str = "https://github.com/espressif/qemu/releases/download";
+const DEFAULT_QEMU_RELEASE: &str = "esp-develop-8.2.0-20240122";
+
+/// Architecture emulated by QEMU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum QemuArch {
+    /// Emulates the ESP32 and ESP32-S3.
+    Xtensa,
+    /// Emulates the ESP32-C3.
+    Riscv32,
+}
+
+impl QemuArch {
+    /// Gets the architecture of the QEMU build emulating a target, if any. Espressif's builds
+    /// only emulate the ESP32, ESP32-S3 and ESP32-C3.
+    pub fn for_target(target: &Target) -> Option<Self> {
+        match target {
+            Target::ESP32 | Target::ESP32S3 => Some(Self::Xtensa),
+            Target::ESP32C3 => Some(Self::Riscv32),
+            _ => None,
+        }
+    }
+}
+
+/// Gets the QEMU build emulating an architecture.
+pub fn get_qemu(context: &Context, arch: QemuArch, host_triple: &HostTriple) -> ReleaseTool {
+    let asset = format!(
+        "qemu-{}-softmmu-{}-{}.tar.xz",
+        arch,
+        DEFAULT_QEMU_RELEASE.replace('-', "_"),
+        get_arch(host_triple)
+    );
+    ReleaseTool::new(
+        context,
+        &format!("QEMU for {arch}"),
+        ReleaseAsset {
+            name: &format!("qemu-{arch}"),
+            component: "qemu",
+            repository: DEFAULT_QEMU_REPOSITORY,
+            tag: DEFAULT_QEMU_RELEASE,
+            asset: &asset,
+            version: DEFAULT_QEMU_RELEASE,
+        },
+    )
+    .with_bin_dir("qemu/bin")
+}
+
+/// Gets the name of the QEMU arch based on the host triple.
+fn get_arch(host_triple: &HostTriple) -> &str {
+    match host_triple {
+        HostTriple::Aarch64AppleDarwin => "aarch64-apple-darwin",
+        HostTriple::X86_64AppleDarwin => "x86_64-apple-darwin",
+        HostTriple::X86_64UnknownLinuxGnu | HostTriple::X86_64UnknownLinuxMusl => {
+            "x86_64-linux-gnu"
+        }
+        HostTriple::Aarch64UnknownLinuxGnu | HostTriple::Aarch64UnknownLinuxMusl => {
+            "aarch64-linux-gnu"
+        }
+        HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => "x86_64-w64-mingw32",
+    }
+}
+
+/// Gets the QEMU builds needed to emulate the selected targets.
+pub fn get_qemu_targets(
+    context: &Context,
+    targets: &HashSet<Target>,
+    host_triple: &HostTriple,
+) -> Vec<ReleaseTool> {
+    targets
+        .iter()
+        .filter_map(QemuArch::for_target)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|arch| get_qemu(context, arch, host_triple))
+        .collect()
+}
+
+/// Gets the selected targets that no QEMU build emulates, sorted.
+pub fn get_unemulated_targets(targets: &HashSet<Target>) -> Vec<Target> {
+    let mut unemulated: Vec<Target> = targets
+        .iter()
+        .filter(|target| QemuArch::for_target(target).is_none())
+        .copied()
+        .collect();
+    unemulated.sort_by_key(|target| target.to_string());
+    unemulated
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        context::Context,
+        host_triple::HostTriple,
+        targets::Target,
+        toolchain::qemu::{get_qemu, get_qemu_targets, get_unemulated_targets, QemuArch},
+    };
+
+    #[test]
+    fn test_get_qemu() {
+        let qemu = get_qemu(
+            &Context::default(),
+            QemuArch::Riscv32,
+            &HostTriple::X86_64UnknownLinuxGnu,
+        );
+        assert_eq!(
+            qemu.repository_url,
+            "https://github.com/espressif/qemu/releases/download/esp-develop-8.2.0-20240122/qemu-riscv32-softmmu-esp_develop_8.2.0_20240122-x86_64-linux-gnu.tar.xz"
+        );
+        assert!(qemu
+            .path
+            .ends_with("qemu-riscv32/esp-develop-8.2.0-20240122"));
+    }
+
+    #[test]
+    fn test_get_qemu_targets() {
+        let context = Context::default();
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let targets = [Target::ESP32C3].into_iter().collect();
+        let qemus = get_qemu_targets(&context, &targets, &host_triple);
+        assert_eq!(qemus.len(), 1);
+        assert_eq!(qemus[0].name, "QEMU for riscv32");
+        let targets = [Target::ESP32, Target::ESP32S3, Target::ESP32C3]
+            .into_iter()
+            .collect();
+        assert_eq!(get_qemu_targets(&context, &targets, &host_triple).len(), 2);
+
+        // The Xtensa build does not emulate the ESP32-S2.
+        let targets = [Target::ESP32S2, Target::ESP32C6].into_iter().collect();
+        assert!(get_qemu_targets(&context, &targets, &host_triple).is_empty());
+        assert_eq!(
+            get_unemulated_targets(&targets),
+            vec![Target::ESP32C6, Target::ESP32S2]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Copy-on-write copies (reflinks) of files, on the filesystems supporting them, e.g. Btrfs
+//! and XFS on Linux or APFS on macOS. A reflink shares the data of the copied file until
+//! either is modified, hence takes no disk space nor time. Other filesystems fall back to
+//! regular copies.
+
+use std::{
+    fs::{self, create_dir_all, read_dir, read_link},
+    io,
+    path::Path,
+};
+
+/// Makes a copy-on-write copy of the file `source` at `destination`, which must not exist.
+#[cfg(target_os = "linux")]
+pub fn reflink(source: &Path, destination: &Path) -> io::Result<()> {
+    use std::{fs::File, os::unix::io::AsRawFd};
+
+    let source_file = File::open(source)?;
+    let destination_file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(destination)?;
+    // SAFETY: both file descriptors are valid for the duration of the call.
+    let result = unsafe {
+        libc::ioctl(
+            destination_file.as_raw_fd(),
+            libc::FICLONE as _,
+            source_file.as_raw_fd(),
+        )
+    };
+    if result != 0 {
+        let error = io::Error::last_os_error();
+        drop(destination_file);
+        let _ = fs::remove_file(destination);
+        return Err(error);
+    }
+    fs::set_permissions(destination, source_file.metadata()?.permissions())
+}
+
+/// Makes a copy-on-write copy of the file `source` at `destination`, which must not exist.
+#[cfg(target_os = "macos")]
+pub fn reflink(source: &Path, destination: &Path) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let to_c_string = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    };
+    let (source, destination) = (to_c_string(source)?, to_c_string(destination)?);
+    // SAFETY: both paths are valid NUL terminated strings.
+    match unsafe { libc::clonefile(source.as_ptr(), destination.as_ptr(), 0) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Makes a copy-on-write copy of the file `source` at `destination`, which must not exist.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn reflink(_source: &Path, _destination: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reflinks are not supported on this platform",
+    ))
+}
+
+/// Copies the file `source` to `destination`, as a reflink when supported.
+pub fn reflink_or_copy(source: &Path, destination: &Path) -> io::Result<()> {
+    if reflink(source, destination).is_ok() {
+        return Ok(());
+    }
+    fs::copy(source, destination).map(|_| ())
+}
+
+/// Creates a symbolic link at `destination` with the same target as `source`.
+fn copy_symlink(source: &Path, destination: &Path) -> io::Result<()> {
+    let target = read_link(source)?;
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(target, destination);
+    #[cfg(windows)]
+    if source.is_dir() {
+        std::os::windows::fs::symlink_dir(target, destination)
+    } else {
+        std::os::windows::fs::symlink_file(target, destination)
+    }
+}
+
+/// Copies the directory `source` to `destination`, the files as reflinks when supported.
+pub fn copy_dir(source: &Path, destination: &Path) -> io::Result<()> {
+    create_dir_all(destination)?;
+    for entry in read_dir(source)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let destination = destination.join(entry.file_name());
+        if file_type.is_symlink() {
+            copy_symlink(&entry.path(), &destination)?;
+        } else if file_type.is_dir() {
+            copy_dir(&entry.path(), &destination)?;
+        } else {
+            reflink_or_copy(&entry.path(), &destination)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::toolchain::reflink::{copy_dir, reflink_or_copy};
+    use std::fs::{create_dir_all, read_to_string, write};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_copy_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source");
+        create_dir_all(source.join("bin")).unwrap();
+        write(source.join("bin").join("gcc"), "gcc").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("gcc", source.join("bin").join("cc")).unwrap();
+
+        let destination = temp_dir.path().join("destination");
+        copy_dir(&source, &destination).unwrap();
+        assert_eq!(
+            read_to_string(destination.join("bin").join("gcc")).unwrap(),
+            "gcc"
+        );
+        #[cfg(unix)]
+        assert!(destination.join("bin").join("cc").is_symlink());
+
+        reflink_or_copy(
+            &source.join("bin").join("gcc"),
+            &temp_dir.path().join("gcc"),
+        )
+        .unwrap();
+        assert_eq!(read_to_string(temp_dir.path().join("gcc")).unwrap(), "gcc");
+    }
+}
@@ -0,0 +1,45 @@
+//! ESP ROM ELFs source and installation tools
+
+use crate::{
+    context::Context,
+    toolchain::tool::{ReleaseAsset, ReleaseTool},
+};
+
+pub const DEFAULT_ROM_ELFS_REPOSITORY: &str =
+    "https://github.com/espressif/esp-rom-elfs/releases/download";
+const DEFAULT_ROM_ELFS_VERSION: &str = "20230320";
+/// Name of the ROM ELFs tool directory.
+pub const ROM_ELFS_NAME: &str = "esp-rom-elfs";
+
+/// Gets the ROM ELFs of every chip, exporting their directory.
+pub fn get_rom_elfs(context: &Context) -> ReleaseTool {
+    ReleaseTool::new(
+        context,
+        "ROM ELFs",
+        ReleaseAsset {
+            name: ROM_ELFS_NAME,
+            component: "rom-elfs",
+            repository: DEFAULT_ROM_ELFS_REPOSITORY,
+            tag: DEFAULT_ROM_ELFS_VERSION,
+            asset: &format!("{ROM_ELFS_NAME}-{DEFAULT_ROM_ELFS_VERSION}.tar.gz"),
+            version: DEFAULT_ROM_ELFS_VERSION,
+        },
+    )
+    .with_variable("ESP_ROM_ELF_DIR", "")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{context::Context, toolchain::rom_elfs::get_rom_elfs};
+
+    #[test]
+    fn test_get_rom_elfs() {
+        let rom_elfs = get_rom_elfs(&Context::default());
+        assert_eq!(
+            rom_elfs.repository_url,
+            "https://github.com/espressif/esp-rom-elfs/releases/download/20230320/esp-rom-elfs-20230320.tar.gz"
+        );
+        assert!(rom_elfs.path.ends_with("esp-rom-elfs/20230320"));
+        assert!(rom_elfs.get_bin_path().is_none());
+    }
+}
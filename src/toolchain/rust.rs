@@ -1,27 +1,84 @@
 //! Xtensa Rust Toolchain source and installation tools
 
 use crate::{
+    context::Context,
     emoji,
     error::Error,
-    host_triple::HostTriple,
-    toolchain::{download_file, espidf::get_dist_path, get_home_dir},
+    host_triple::{get_component_host, log_emulated_component, HostTriple},
+    lock::InstallLock,
+    packages::get_command_output,
+    resume::{install_component, is_installed},
+    targets::Target,
+    toolchain::{download_file, espidf::get_dist_path, get_github_json, get_home_dir},
 };
 use embuild::cmd;
 use log::{debug, info, warn};
-use miette::{IntoDiagnostic, Result};
+use miette::Result;
 use regex::Regex;
-use reqwest::header;
 use serde::{Deserialize, Serialize};
 use std::{collections::HashSet, fmt::Debug};
-use std::{env, fs::remove_dir_all, path::PathBuf, process::Stdio};
+use std::{
+    env,
+    fs::{create_dir_all, read_dir, read_link, remove_dir_all, remove_file, rename},
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
 
 /// Xtensa Rust Toolchain repository
-const DEFAULT_XTENSA_RUST_REPOSITORY: &str =
+pub const DEFAULT_XTENSA_RUST_REPOSITORY: &str =
     "https://github.com/esp-rs/rust-build/releases/download";
 /// Xtensa Rust Toolchain API URL
 const XTENSA_RUST_API_URL: &str = "https://api.github.com/repos/esp-rs/rust-build/releases/latest";
+/// Xtensa Rust Toolchain releases API URL
+const XTENSA_RUST_RELEASES_API_URL: &str =
+    "https://api.github.com/repos/esp-rs/rust-build/releases?per_page=100";
 /// Xtensa Rust Toolchain version regex.
 const RE_TOOLCHAIN_VERSION: &str = r"^(?P<major>0|[1-9]\d*)\.(?P<minor>0|[1-9]\d*)\.(?P<patch>0|[1-9]\d*)\.(?P<subpatch>0|[1-9]\d*)?$";
+/// Requests the latest Xtensa Rust toolchain release.
+pub const LATEST_VERSION: &str = "latest";
+/// Requests the latest Xtensa Rust toolchain release, including pre-releases.
+pub const PRE_RELEASE_VERSION: &str = "pre-release";
+/// Nightly Rust toolchain version regex: `nightly` or a dated nightly.
+const RE_NIGHTLY_VERSION: &str = r"^nightly(-\d{4}-\d{2}-\d{2})?$";
+/// Default name of the Xtensa Rust toolchain in rustup.
+pub const DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME: &str = "esp";
+/// Directory of the tools path where the Xtensa Rust toolchains are installed, by version.
+const XTENSA_RUST_DIR: &str = "rust";
+/// Xtensa Rust Toolchain name regex.
+const RE_TOOLCHAIN_NAME: &str = r"^[A-Za-z0-9][A-Za-z0-9._-]*$";
+/// Names of the official rustup channels, which custom toolchains cannot shadow.
+const RUSTUP_CHANNELS: [&str; 3] = ["stable", "beta", "nightly"];
+
+/// Release of the Xtensa Rust toolchain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Release {
+    /// Version of the release, e.g. `1.64.0.0`.
+    version: String,
+    /// Whether the release is a pre-release.
+    prerelease: bool,
+    /// File names of the release assets.
+    assets: Vec<String>,
+}
+
+impl Release {
+    /// Whether the release provides a toolchain for the host.
+    fn supports(&self, host_triple: &HostTriple) -> bool {
+        let dist_file = format!(
+            "rust-{}-{}.{}",
+            self.version,
+            host_triple,
+            get_artifact_extension(host_triple)
+        );
+        self.assets.contains(&dist_file)
+    }
+
+    /// Gets the host to install the toolchain of the release for: the native one or, when
+    /// the release does not provide it, the emulated one.
+    fn get_host(&self, host_triple: &HostTriple) -> Option<HostTriple> {
+        get_component_host(host_triple, |host_triple| self.supports(host_triple))
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct XtensaRust {
@@ -33,6 +90,9 @@ pub struct XtensaRust {
     pub dist_url: String,
     /// Host triple.
     pub host_triple: String,
+    /// Name of the toolchain in rustup, e.g. `esp`, used as `cargo +esp`.
+    #[serde(default = "get_default_toolchain_name")]
+    pub name: String,
     /// Path to the rustup home directory.
     pub rustup_home: PathBuf,
     #[cfg(unix)]
@@ -48,25 +108,67 @@ pub struct XtensaRust {
 }
 
 impl XtensaRust {
+    /// Get the releases of Xtensa Rust toolchain, newest first.
+    fn get_releases(context: &Context) -> Result<Vec<Release>> {
+        let json = get_github_json(context, XTENSA_RUST_RELEASES_API_URL)?;
+        let releases = json
+            .as_array()
+            .ok_or(Error::FailedToSerializeJson)?
+            .iter()
+            .filter(|release| !release["draft"].as_bool().unwrap_or(false))
+            .filter_map(|release| {
+                let version = release["tag_name"].as_str()?.trim_start_matches('v');
+                Self::parse_version(version).ok()?;
+                Some(Release {
+                    version: version.to_string(),
+                    prerelease: release["prerelease"].as_bool().unwrap_or(false),
+                    assets: release["assets"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|asset| asset["name"].as_str().map(str::to_string))
+                        .collect(),
+                })
+            })
+            .collect();
+        Ok(releases)
+    }
+
+    /// Get the available versions of Xtensa Rust toolchain, newest first.
+    pub fn get_available_versions(context: &Context) -> Result<Vec<String>> {
+        Ok(Self::get_releases(context)?
+            .into_iter()
+            .map(|release| release.version)
+            .collect())
+    }
+
+    /// Resolves the requested version: `latest`, `pre-release` or an exact version, which
+    /// has to provide a toolchain for the host, or for the host emulated by it. Returns the
+    /// version and the host to install its toolchain for.
+    pub fn resolve_version(
+        context: &Context,
+        requested: &str,
+        host_triple: &HostTriple,
+    ) -> Result<(String, HostTriple)> {
+        let (version, toolchain_host) =
+            select_version(&Self::get_releases(context)?, requested, host_triple)?;
+        debug!(
+            "{} Resolved Xtensa Rust version '{}': {}",
+            emoji::DEBUG,
+            requested,
+            version
+        );
+        log_emulated_component(
+            &format!("Xtensa Rust {version}"),
+            host_triple,
+            &toolchain_host,
+        );
+        Ok((version, toolchain_host))
+    }
+
     /// Get the latest version of Xtensa Rust toolchain.
-    pub fn get_latest_version() -> Result<String> {
-        let mut headers = header::HeaderMap::new();
-        headers.insert("Accept", "application/vnd.github.v3+json".parse().unwrap());
-
-        let client = reqwest::blocking::Client::builder()
-            .redirect(reqwest::redirect::Policy::none())
-            .user_agent("espup")
-            .build()
-            .unwrap();
-        let res = client
-            .get(XTENSA_RUST_API_URL)
-            .headers(headers)
-            .send()
-            .into_diagnostic()?
-            .text()
-            .into_diagnostic()?;
-        let json: serde_json::Value =
-            serde_json::from_str(&res).map_err(|_| Error::FailedToSerializeJson)?;
+    pub fn get_latest_version(context: &Context) -> Result<String> {
+        let json = get_github_json(context, XTENSA_RUST_API_URL)?;
         let mut version = json["tag_name"].to_string();
 
         version.retain(|c| c != 'v' && c != '"');
@@ -75,100 +177,275 @@ impl XtensaRust {
         Ok(version)
     }
 
-    /// Installs the Xtensa Rust toolchain.
-    pub fn install(&self) -> Result<(), Error> {
-        #[cfg(unix)]
-        let toolchain_path = self.toolchain_destination.clone();
-        #[cfg(windows)]
-        let toolchain_path = self.toolchain_destination.clone().join("esp");
-        if toolchain_path.exists() {
+    /// Installs the Xtensa Rust toolchain, which is registered in rustup with
+    /// [`XtensaRust::link`].
+    pub fn install(&self, context: &Context) -> Result<(), Error> {
+        let name_path = get_toolchain_path(&self.name);
+        // Toolchains installed elsewhere, e.g. standalone ones, are not registered in rustup.
+        if self
+            .toolchain_destination
+            .starts_with(get_xtensa_rust_path(context))
+            && name_path.exists()
+            && read_link(&name_path).is_err()
+        {
             return Err(Error::XtensaToolchainAlreadyInstalled(
-                toolchain_path.display().to_string(),
+                name_path.display().to_string(),
             ));
         }
+        if is_installed(context, &self.toolchain_destination) {
+            warn!(
+                "{} Previous installation of Xtensa Rust {} exist in: '{}'. Reusing this installation.",
+                emoji::WARN,
+                self.version,
+                self.toolchain_destination.display()
+            );
+            return Ok(());
+        }
         info!(
-            "{} Installing Xtensa Rust {} toolchain",
+            "{} Installing Xtensa Rust {} toolchain as '{}'",
             emoji::WRENCH,
-            self.version
+            self.version,
+            self.name
         );
+        context.rollback.track(&self.toolchain_destination);
+        context
+            .summary
+            .record(&self.toolchain_destination, Duration::ZERO);
+        install_component(context, &self.toolchain_destination, || {
+            self.install_toolchain(context)
+        })
+    }
 
+    /// Installs the components of the toolchain of [`XtensaRust::install`].
+    fn install_toolchain(&self, context: &Context) -> Result<(), Error> {
         #[cfg(unix)]
         if cfg!(unix) {
-            download_file(
-                self.dist_url.clone(),
-                "rust.tar.xz",
-                &get_dist_path("rust"),
-                true,
-            )?;
-
-            info!("{} Installing rust esp toolchain", emoji::WRENCH);
-            let arguments = format!(
-                "{}/rust-nightly-{}/install.sh --destdir={} --prefix='' --without=rust-docs-json-preview,rust-docs",
-                get_dist_path("rust"),
-                &self.host_triple,
-                self.toolchain_destination.display()
-            );
-            cmd!("/bin/bash", "-c", arguments).run()?;
-
-            download_file(
-                self.src_dist_url.clone(),
-                "rust-src.tar.xz",
-                &get_dist_path("rust-src"),
-                true,
-            )?;
-            info!("{} Installing rust-src for esp toolchain", emoji::WRENCH);
-            let arguments = format!(
-                "{}/rust-src-nightly/install.sh --destdir={} --prefix='' --without=rust-docs-json-preview,rust-docs",
-                get_dist_path("rust-src"),
-                self.toolchain_destination.display()
-            );
-            cmd!("/bin/bash", "-c", arguments).run()?;
+            self.install_rust(context)?;
+            if context.install_rust_src {
+                self.install_rust_src(context)?;
+            }
         }
         // Some platfroms like Windows are available in single bundle rust + src, because install
-        // script in dist is not available for the plaform. It's sufficient to extract the toolchain
+        // script in dist is not available for the plaform. It's sufficient to extract the toolchain,
+        // bundled in an `esp` directory, and move it into place under its name.
         #[cfg(windows)]
         if cfg!(windows) {
             download_file(
+                context,
                 self.dist_url.clone(),
                 "rust.zip",
-                &self.toolchain_destination.display().to_string(),
+                &get_dist_path(context, "rust"),
                 true,
             )?;
+            if let Some(toolchains_path) = self.toolchain_destination.parent() {
+                create_dir_all(toolchains_path)?;
+            }
+            rename(
+                PathBuf::from(get_dist_path(context, "rust"))
+                    .join(DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME),
+                &self.toolchain_destination,
+            )?;
         }
 
         Ok(())
     }
 
-    /// Create a new instance.
-    pub fn new(toolchain_version: &str, host_triple: &HostTriple) -> Self {
+    /// Installs the Rust component of the toolchain: the compiler, cargo and clippy.
+    #[cfg(unix)]
+    fn install_rust(&self, context: &Context) -> Result<(), Error> {
+        download_file(
+            context,
+            self.dist_url.clone(),
+            "rust.tar.xz",
+            &get_dist_path(context, "rust"),
+            true,
+        )?;
+
+        info!("{} Installing rust esp toolchain", emoji::WRENCH);
+        let arguments = format!(
+            "{}/rust-nightly-{}/install.sh --destdir={} --prefix='' --without=rust-docs-json-preview,rust-docs",
+            get_dist_path(context, "rust"),
+            &self.host_triple,
+            self.toolchain_destination.display()
+        );
+        cmd!("/bin/bash", "-c", arguments).run()?;
+        Ok(())
+    }
+
+    /// Installs the rust-src component of the toolchain.
+    #[cfg(unix)]
+    fn install_rust_src(&self, context: &Context) -> Result<(), Error> {
+        download_file(
+            context,
+            self.src_dist_url.clone(),
+            "rust-src.tar.xz",
+            &get_dist_path(context, "rust-src"),
+            true,
+        )?;
+        info!("{} Installing rust-src for esp toolchain", emoji::WRENCH);
+        let arguments = format!(
+            "{}/rust-src-nightly/install.sh --destdir={} --prefix='' --without=rust-docs-json-preview,rust-docs",
+            get_dist_path(context, "rust-src"),
+            self.toolchain_destination.display()
+        );
+        cmd!("/bin/bash", "-c", arguments).run()?;
+        Ok(())
+    }
+
+    /// Gets the commit of the compiler of the installed toolchain, if it can be run.
+    #[cfg(unix)]
+    fn get_rustc_commit(&self) -> Option<String> {
+        let rustc = self.toolchain_destination.join("bin").join("rustc");
+        let output = get_command_output(rustc.to_str()?, &["--version"])?;
+        parse_rustc_commit(&output).map(str::to_string)
+    }
+
+    /// Gets the name of the toolchain in rustup that includes its version, e.g.
+    /// `esp-1.64.0.0`, which projects can pin regardless of the toolchain `esp` resolves to.
+    pub fn get_versioned_name(&self) -> String {
+        format!("{}-{}", DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME, self.version)
+    }
+
+    /// Gets the environment variables that select the toolchain without rustup: its `bin`
+    /// directory first in `PATH` and its compiler as `RUSTC`.
+    pub fn get_standalone_exports(&self) -> Vec<String> {
+        let bin_path = self.toolchain_destination.join("bin");
+        let mut exports: Vec<String> = Vec::new();
+        #[cfg(windows)]
+        {
+            exports.push(format!("$Env:PATH=\"{};$Env:PATH\"", bin_path.display()));
+            exports.push(format!(
+                "$Env:RUSTC=\"{}\"",
+                bin_path.join("rustc.exe").display()
+            ));
+        }
+        #[cfg(unix)]
+        {
+            exports.push(format!("export PATH=\"{}:$PATH\"", bin_path.display()));
+            exports.push(format!(
+                "export RUSTC=\"{}\"",
+                bin_path.join("rustc").display()
+            ));
+        }
+        exports
+    }
+
+    /// Registers the toolchain in rustup under its versioned name and under its name,
+    /// replacing the link of the version it resolved to before.
+    pub fn link(&self, context: &Context) -> Result<(), Error> {
+        let versioned_name = self.get_versioned_name();
+        link_toolchain(context, &versioned_name, &self.toolchain_destination)?;
+        if self.name != versioned_name {
+            link_toolchain(context, &self.name, &self.toolchain_destination)?;
+        }
+        Ok(())
+    }
+
+    /// Updates the toolchain `previous` to this one. The release assets bundle the compiler,
+    /// cargo and clippy in a single archive, which is always downloaded, and do not describe
+    /// their components, so only the rust-src component is reused: it is moved from the
+    /// previous toolchain when the compiler is built from the same commit, e.g. between
+    /// releases only rebuilding LLVM. The previous toolchain is removed once the update
+    /// succeeded, a rollback moving its rust-src component back.
+    #[cfg(unix)]
+    pub fn update(&self, context: &Context, previous: &XtensaRust) -> Result<()> {
+        if self.toolchain_destination == previous.toolchain_destination {
+            previous.uninstall()?;
+            return Ok(self.install(context)?);
+        }
+        let previous_commit = previous.get_rustc_commit();
+        let src_path = PathBuf::from("lib").join("rustlib").join("src");
+        let previous_src = previous.toolchain_destination.join(&src_path);
+        info!(
+            "{} Updating Xtensa Rust toolchain '{}' to {}",
+            emoji::WRENCH,
+            self.name,
+            self.version
+        );
+        context.rollback.track(&self.toolchain_destination);
+        context
+            .summary
+            .record(&self.toolchain_destination, Duration::ZERO);
+        install_component(context, &self.toolchain_destination, || -> Result<()> {
+            self.install_rust(context)?;
+            let src = self.toolchain_destination.join(&src_path);
+            if previous_commit.is_some()
+                && self.get_rustc_commit() == previous_commit
+                && previous_src.exists()
+            {
+                info!(
+                    "{} Keeping the rust-src component, the compiler is built from the same commit",
+                    emoji::INFO
+                );
+                if src.exists() {
+                    remove_dir_all(&src).map_err(Error::from)?;
+                }
+                create_dir_all(src.parent().unwrap()).map_err(Error::from)?;
+                context.rollback.track_move(&previous_src, &src);
+                rename(&previous_src, &src).map_err(Error::from)?;
+            } else if context.install_rust_src {
+                self.install_rust_src(context)?;
+            }
+            Ok(())
+        })?;
+        previous.uninstall()
+    }
+
+    /// Updates the toolchain `previous` to this one. Windows toolchains are a single bundle,
+    /// including rust-src, which is installed again.
+    #[cfg(windows)]
+    pub fn update(&self, context: &Context, previous: &XtensaRust) -> Result<()> {
+        previous.uninstall()?;
+        Ok(self.install(context)?)
+    }
+
+    /// Create a new instance, named `name` in rustup.
+    pub fn new(
+        context: &Context,
+        toolchain_version: &str,
+        name: &str,
+        host_triple: &HostTriple,
+    ) -> Self {
         let artifact_extension = get_artifact_extension(host_triple);
         let version = toolchain_version.to_string();
         let dist = format!("rust-{}-{}", version, host_triple);
         let dist_file = format!("{}.{}", dist, artifact_extension);
-        let dist_url = format!(
-            "{}/v{}/{}",
-            DEFAULT_XTENSA_RUST_REPOSITORY, version, dist_file
+        let dist_url = context.artifact_url(
+            "rust",
+            format!(
+                "{}/v{}/{}",
+                context
+                    .http
+                    .base_url("rust", DEFAULT_XTENSA_RUST_REPOSITORY),
+                version,
+                dist_file
+            ),
         );
         #[cfg(unix)]
         let src_dist = format!("rust-src-{}", version);
         #[cfg(unix)]
         let src_dist_file = format!("{}.{}", src_dist, artifact_extension);
         #[cfg(unix)]
-        let src_dist_url = format!(
-            "{}/v{}/{}",
-            DEFAULT_XTENSA_RUST_REPOSITORY, version, src_dist_file
+        let src_dist_url = context.artifact_url(
+            "rust-src",
+            format!(
+                "{}/v{}/{}",
+                context
+                    .http
+                    .base_url("rust", DEFAULT_XTENSA_RUST_REPOSITORY),
+                version,
+                src_dist_file
+            ),
         );
         let cargo_home = get_cargo_home();
         let rustup_home = get_rustup_home();
-        #[cfg(unix)]
-        let toolchain_destination = rustup_home.join("toolchains").join("esp");
-        #[cfg(windows)]
-        let toolchain_destination = rustup_home.join("toolchains");
+        let toolchain_destination = get_xtensa_rust_path(context).join(&version);
         Self {
             cargo_home,
             dist_file,
             dist_url,
             host_triple: host_triple.to_string(),
+            name: name.to_string(),
             rustup_home,
             #[cfg(unix)]
             src_dist_file,
@@ -179,24 +456,64 @@ impl XtensaRust {
         }
     }
 
+    /// Parses the requested version of the Xtensa toolchain: `latest`, `pre-release` or a
+    /// version.
+    pub fn parse_version_request(arg: &str) -> Result<String> {
+        match arg {
+            LATEST_VERSION | PRE_RELEASE_VERSION => Ok(arg.to_string()),
+            _ => Self::parse_version(arg),
+        }
+    }
+
     /// Parses the version of the Xtensa toolchain.
     pub fn parse_version(arg: &str) -> Result<String> {
         debug!("{} Parsing Xtensa Rust version: {}", emoji::DEBUG, arg);
         let re = Regex::new(RE_TOOLCHAIN_VERSION).unwrap();
         if !re.is_match(arg) {
-            return Err(Error::InvalidXtensaToolchanVersion(arg.to_string())).into_diagnostic();
+            return Err(Error::InvalidXtensaToolchanVersion(arg.to_string()).into());
         }
         Ok(arg.to_string())
     }
 
-    /// Removes the Xtensa Rust toolchain.
+    /// Parses the rustup name of the Xtensa toolchain, e.g. `esp-1.64`. Official channel
+    /// names are rejected, as rustup would not use the custom toolchain for them.
+    pub fn parse_toolchain_name(arg: &str) -> Result<String> {
+        debug!(
+            "{} Parsing Xtensa Rust toolchain name: {}",
+            emoji::DEBUG,
+            arg
+        );
+        let re = Regex::new(RE_TOOLCHAIN_NAME).unwrap();
+        if !re.is_match(arg)
+            || RUSTUP_CHANNELS
+                .iter()
+                .any(|channel| arg == *channel || arg.starts_with(&format!("{channel}-")))
+        {
+            return Err(Error::InvalidXtensaToolchainName(arg.to_string()).into());
+        }
+        Ok(arg.to_string())
+    }
+
+    /// Removes the Xtensa Rust toolchain and the links of rustup to it.
     pub fn uninstall(&self) -> Result<()> {
         info!("{} Uninstalling Xtensa Rust toolchain", emoji::WRENCH);
-        remove_dir_all(&self.toolchain_destination)
-            .into_diagnostic()
-            .map_err(|_| {
-                Error::FailedToRemoveDirectory(self.toolchain_destination.display().to_string())
-            })?;
+        let destination = self.toolchain_destination.canonicalize().ok();
+        for entry in read_dir(self.rustup_home.join("toolchains"))
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+        {
+            let link = entry.path();
+            if read_link(&link).is_ok() && link.canonicalize().ok() == destination {
+                // Directory links are directories on Windows.
+                remove_file(&link)
+                    .or_else(|_| std::fs::remove_dir(&link))
+                    .map_err(Error::from)?;
+            }
+        }
+        remove_dir_all(&self.toolchain_destination).map_err(|_| {
+            Error::FailedToRemoveDirectory(self.toolchain_destination.display().to_string())
+        })?;
         Ok(())
     }
 }
@@ -209,20 +526,37 @@ pub struct Crate {
 
 impl Crate {
     /// Installs a crate.
-    pub fn install(&self) -> Result<()> {
+    pub fn install(&self, context: &Context) -> Result<()> {
         #[cfg(unix)]
         let crate_path = format!("{}/bin/{}", get_cargo_home().display(), self.name);
         #[cfg(windows)]
         let crate_path = format!("{}/bin/{}.exe", get_cargo_home().display(), self.name);
         if PathBuf::from(crate_path).exists() {
             warn!("{} {} is already installed", emoji::WARN, self.name);
-            Ok(())
-        } else {
-            cmd!("cargo", "install", &self.name)
+            return Ok(());
+        }
+        context
+            .rollback
+            .track_command(&["cargo", "uninstall", &self.name]);
+        if get_command_output("cargo", &["binstall", "-V"]).is_some() {
+            info!("{} Installing {} prebuilt binary", emoji::WRENCH, self.name);
+            if cmd!("cargo", "binstall", "--no-confirm", &self.name; envs=(context.envs()))
                 .run()
-                .into_diagnostic()?;
-            Ok(())
+                .is_ok()
+            {
+                return Ok(());
+            }
+            warn!(
+                "{} Failed to install {} prebuilt binary, building it from source",
+                emoji::WARN,
+                self.name
+            );
         }
+        info!("{} Installing {} crate", emoji::WRENCH, self.name);
+        cmd!("cargo", "install", &self.name; envs=(context.envs()))
+            .run()
+            .map_err(Error::from)?;
+        Ok(())
     }
 
     /// Create a crate instance.
@@ -234,21 +568,70 @@ impl Crate {
 
     /// Parses the extra crates to be installed.
     pub fn parse_crates(arg: &str) -> Result<HashSet<Crate>> {
-        Ok(arg.split(',').map(Crate::new).collect())
+        Ok(arg
+            .split([',', ' '])
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(Crate::new)
+            .collect())
     }
 }
 
-pub fn install_extra_crates(crates: &HashSet<Crate>) -> Result<()> {
+pub fn install_extra_crates(context: &Context, crates: &HashSet<Crate>) -> Result<()> {
     debug!(
         "{} Installing the following crates: {:#?}",
         emoji::DEBUG,
         crates
     );
     for c in crates {
-        c.install()?;
+        c.install(context)?;
     }
     Ok(())
 }
+/// Selects the requested version among the releases that provide a toolchain for the host.
+fn select_version(
+    releases: &[Release],
+    requested: &str,
+    host_triple: &HostTriple,
+) -> Result<(String, HostTriple), Error> {
+    let mut available = releases.iter().filter_map(|release| {
+        release
+            .get_host(host_triple)
+            .map(|toolchain_host| (release, toolchain_host))
+    });
+    let release = match requested {
+        LATEST_VERSION => available.find(|(release, _)| !release.prerelease),
+        PRE_RELEASE_VERSION => available.next(),
+        _ => available.find(|(release, _)| release.version == requested),
+    };
+    match release {
+        Some((release, toolchain_host)) => Ok((release.version.clone(), toolchain_host)),
+        None => Err(Error::UnavailableXtensaToolchainVersion(
+            requested.to_string(),
+            host_triple.to_string(),
+            releases
+                .iter()
+                .filter(|release| release.get_host(host_triple).is_some())
+                .map(|release| release.version.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        )),
+    }
+}
+
+/// Parses the commit of the compiler from the output of `rustc --version`, e.g.
+/// `rustc 1.65.0-nightly (9d3c9aa3c 2022-11-03)`.
+#[cfg(unix)]
+fn parse_rustc_commit(version: &str) -> Option<&str> {
+    version.split_once('(')?.1.split_whitespace().next()
+}
+
+/// Gets the default rustup name of the Xtensa Rust toolchain, used by configurations that
+/// predate custom names.
+fn get_default_toolchain_name() -> String {
+    DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME.to_string()
+}
+
 /// Gets the artifact extension based on the host architecture.
 fn get_artifact_extension(host_triple: &HostTriple) -> &str {
     match host_triple {
@@ -258,7 +641,7 @@ fn get_artifact_extension(host_triple: &HostTriple) -> &str {
 }
 
 /// Gets the default cargo home path.
-fn get_cargo_home() -> PathBuf {
+pub fn get_cargo_home() -> PathBuf {
     PathBuf::from(env::var("CARGO_HOME").unwrap_or_else(|_e| get_home_dir() + "/.cargo"))
 }
 
@@ -267,29 +650,199 @@ pub fn get_rustup_home() -> PathBuf {
     PathBuf::from(env::var("RUSTUP_HOME").unwrap_or_else(|_e| get_home_dir() + "/.rustup"))
 }
 
+/// Gets the directory of a toolchain installed in rustup.
+pub fn get_toolchain_path(name: &str) -> PathBuf {
+    get_rustup_home().join("toolchains").join(name)
+}
+
+/// Gets the directory where the Xtensa Rust toolchains are installed side by side, each in
+/// the directory of its version.
+pub fn get_xtensa_rust_path(context: &Context) -> PathBuf {
+    context.tools_path.join(XTENSA_RUST_DIR)
+}
+
+/// Removes the Xtensa Rust toolchains installed side by side, and the links of rustup to
+/// them.
+pub fn uninstall_versions(context: &Context) -> Result<()> {
+    let xtensa_rust_path = get_xtensa_rust_path(context);
+    for entry in read_dir(&xtensa_rust_path)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+    {
+        XtensaRust {
+            rustup_home: get_rustup_home(),
+            toolchain_destination: entry.path(),
+            ..Default::default()
+        }
+        .uninstall()?;
+    }
+    if xtensa_rust_path.exists() {
+        remove_dir_all(&xtensa_rust_path)
+            .map_err(|_| Error::FailedToRemoveDirectory(xtensa_rust_path.display().to_string()))?;
+    }
+    Ok(())
+}
+
+/// Registers a toolchain directory in rustup as `name`, replacing a previous link, but not a
+/// toolchain installed under that name.
+fn link_toolchain(context: &Context, name: &str, toolchain_path: &Path) -> Result<(), Error> {
+    check_rustup(context)?;
+    let link = get_toolchain_path(name);
+    if read_link(&link).is_ok() {
+        // Directory links are directories on Windows.
+        remove_file(&link).or_else(|_| std::fs::remove_dir(&link))?;
+    } else if link.exists() {
+        return Err(Error::XtensaToolchainAlreadyInstalled(
+            link.display().to_string(),
+        ));
+    }
+    debug!(
+        "{} Linking '{}' to '{}'",
+        emoji::DEBUG,
+        name,
+        toolchain_path.display()
+    );
+    cmd!("rustup", "toolchain", "link", name, toolchain_path; envs=(context.envs())).run()?;
+    Ok(())
+}
+
+/// Whether a toolchain directory provides the Xtensa targets.
+fn is_xtensa_toolchain(toolchain_path: &Path) -> bool {
+    read_dir(toolchain_path.join("lib").join("rustlib"))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .any(|entry| entry.file_name().to_string_lossy().starts_with("xtensa-"))
+}
+
+/// Makes the `esp` toolchain resolve to the installed Xtensa Rust toolchain `name`, linking
+/// it in rustup like `rustup toolchain link`. An installation named `esp`, rather than a
+/// link, made by previous versions of espup is moved to the directory of its version and
+/// kept as `esp-<version>`, which requires its version from `installed`. Returns the
+/// toolchain of `installed` with its new name and location when it was moved.
+pub fn set_default_toolchain(
+    context: &Context,
+    name: &str,
+    installed: Option<&XtensaRust>,
+) -> Result<Option<XtensaRust>, Error> {
+    let default_path = get_toolchain_path(DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME);
+    let is_link = read_link(&default_path).is_ok();
+    if name == DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME {
+        return if default_path.exists() && !is_link {
+            Ok(None)
+        } else {
+            Err(Error::XtensaToolchainNotInstalled(name.to_string()))
+        };
+    }
+    let toolchain_path = get_toolchain_path(name);
+    if !is_xtensa_toolchain(&toolchain_path) {
+        return Err(Error::XtensaToolchainNotInstalled(name.to_string()));
+    }
+    let toolchain_path = toolchain_path.canonicalize()?;
+
+    let mut moved = None;
+    if !is_link && default_path.exists() {
+        let mut xtensa_rust = match installed
+            .filter(|xtensa_rust| xtensa_rust.toolchain_destination == default_path)
+        {
+            Some(xtensa_rust) => xtensa_rust.clone(),
+            None => {
+                return Err(Error::XtensaToolchainNotLinked(
+                    default_path.display().to_string(),
+                ))
+            }
+        };
+        xtensa_rust.name = xtensa_rust.get_versioned_name();
+        xtensa_rust.toolchain_destination =
+            get_xtensa_rust_path(context).join(&xtensa_rust.version);
+        if xtensa_rust.toolchain_destination.exists() {
+            return Err(Error::XtensaToolchainAlreadyInstalled(
+                xtensa_rust.toolchain_destination.display().to_string(),
+            ));
+        }
+        info!(
+            "{} Keeping the Xtensa Rust {} toolchain as '{}'",
+            emoji::WRENCH,
+            xtensa_rust.version,
+            xtensa_rust.name
+        );
+        create_dir_all(get_xtensa_rust_path(context))?;
+        rename(&default_path, &xtensa_rust.toolchain_destination)?;
+        xtensa_rust.link(context)?;
+        moved = Some(xtensa_rust);
+    }
+
+    link_toolchain(context, DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME, &toolchain_path)?;
+    Ok(moved)
+}
+
+/// Makes the `esp` toolchain resolve to the installed Xtensa Rust toolchain `name`, see
+/// [`set_default_toolchain`], and records the toolchain moved in the configuration file.
+pub fn switch_default_toolchain(
+    context: &mut Context,
+    name: &str,
+    no_wait: bool,
+) -> Result<(), Error> {
+    info!(
+        "{} Switching '{}' to the '{}' toolchain",
+        emoji::DISC,
+        DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME,
+        name
+    );
+    let mut config = context.load_config()?;
+    let context = &*context;
+    let _lock = InstallLock::acquire(context, no_wait)?;
+    if let Some(xtensa_rust) = set_default_toolchain(context, name, config.xtensa_rust.as_ref())? {
+        info!("{} Saving configuration file", emoji::WRENCH);
+        config.xtensa_rust = Some(xtensa_rust);
+        config.save(context.env.as_deref())?;
+    }
+
+    info!(
+        "{} '{}' now resolves to the '{}' toolchain",
+        emoji::CHECK,
+        DEFAULT_XTENSA_RUST_TOOLCHAIN_NAME,
+        name
+    );
+    Ok(())
+}
+
 /// Checks if rustup and the proper nightly version are installed. If rustup is not installed,
 /// it returns an error. If nigthly version is not installed, proceed to install it.
-pub fn check_rust_installation(nightly_version: &str, host_triple: &HostTriple) -> Result<()> {
+pub fn check_rust_installation(
+    context: &Context,
+    nightly_version: &str,
+    host_triple: &HostTriple,
+    install_rustup: bool,
+) -> Result<()> {
     info!("{} Checking existing Rust installation", emoji::WRENCH);
 
-    match cmd!("rustup", "toolchain", "list")
+    match cmd!("rustup", "toolchain", "list"; envs=(context.envs()))
         .into_inner()
         .stdout(Stdio::piped())
         .output()
     {
         Ok(child_output) => {
             let result = String::from_utf8_lossy(&child_output.stdout);
-            if !result.contains("nightly") {
-                warn!("{} Rust nightly toolchain not found", emoji::WARN);
-                install_rust_nightly(nightly_version)?;
+            if !is_toolchain_installed(&result, nightly_version, host_triple) {
+                warn!(
+                    "{} Rust {} toolchain not found",
+                    emoji::WARN,
+                    nightly_version
+                );
+                install_rust_nightly(context, nightly_version)?;
             }
         }
         Err(e) => {
             if let std::io::ErrorKind::NotFound = e.kind() {
+                if !install_rustup {
+                    return Err(Error::MissingRustup.into());
+                }
                 warn!("{} rustup was not found.", emoji::WARN);
-                install_rustup(nightly_version, host_triple)?;
+                install_rustup_init(context, nightly_version, host_triple)?;
             } else {
-                return Err(Error::RustupDetectionError(e.to_string())).into_diagnostic();
+                return Err(Error::RustupDetectionError(e.to_string()).into());
             }
         }
     }
@@ -297,20 +850,55 @@ pub fn check_rust_installation(nightly_version: &str, host_triple: &HostTriple)
     Ok(())
 }
 
-/// Installs rustup
-fn install_rustup(nightly_version: &str, host_triple: &HostTriple) -> Result<(), Error> {
+/// Whether the toolchain, e.g. `nightly-2023-01-01`, is in the output of
+/// `rustup toolchain list`, where toolchains are suffixed with their host triple.
+fn is_toolchain_installed(toolchain_list: &str, toolchain: &str, host_triple: &HostTriple) -> bool {
+    let installed = format!("{toolchain}-{host_triple}");
+    toolchain_list
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .any(|name| name == installed || name == toolchain)
+}
+
+/// Parses the nightly Rust toolchain version: `nightly` or a dated nightly, e.g.
+/// `nightly-2023-01-01`, to reproduce the toolchain of a project exactly.
+pub fn parse_nightly_version(arg: &str) -> Result<String> {
+    let re = Regex::new(RE_NIGHTLY_VERSION).unwrap();
+    if !re.is_match(arg) {
+        return Err(Error::InvalidNightlyVersion(arg.to_string()).into());
+    }
+    Ok(arg.to_string())
+}
+
+/// Fails with [`Error::MissingRustup`] when rustup is not installed.
+fn check_rustup(context: &Context) -> Result<(), Error> {
+    match get_rustup_output(context, &["--version"]) {
+        Some(_) => Ok(()),
+        None => Err(Error::MissingRustup),
+    }
+}
+
+/// Installs rustup, in the rustup and cargo homes of espup, which honor `RUSTUP_HOME` and
+/// `CARGO_HOME`.
+fn install_rustup_init(
+    context: &Context,
+    nightly_version: &str,
+    host_triple: &HostTriple,
+) -> Result<(), Error> {
     #[cfg(windows)]
     let rustup_init_path = download_file(
+        context,
         "https://win.rustup.rs/x86_64".to_string(),
         "rustup-init.exe",
-        &get_dist_path("rustup"),
+        &get_dist_path(context, "rustup"),
         false,
     )?;
     #[cfg(unix)]
     let rustup_init_path = download_file(
+        context,
         "https://sh.rustup.rs".to_string(),
         "rustup-init.sh",
-        &get_dist_path("rustup"),
+        &get_dist_path(context, "rustup"),
         false,
     )?;
     info!(
@@ -320,7 +908,7 @@ fn install_rustup(nightly_version: &str, host_triple: &HostTriple) -> Result<(),
     );
 
     #[cfg(windows)]
-    cmd!(
+    let mut rustup_init = cmd!(
         rustup_init_path,
         "--default-toolchain",
         nightly_version,
@@ -329,10 +917,9 @@ fn install_rustup(nightly_version: &str, host_triple: &HostTriple) -> Result<(),
         "--profile",
         "minimal",
         "-y"
-    )
-    .run()?;
+    );
     #[cfg(not(windows))]
-    cmd!(
+    let mut rustup_init = cmd!(
         "/bin/bash",
         rustup_init_path,
         "--default-toolchain",
@@ -342,23 +929,17 @@ fn install_rustup(nightly_version: &str, host_triple: &HostTriple) -> Result<(),
         "--profile",
         "minimal",
         "-y"
-    )
-    .run()?;
-
-    #[cfg(windows)]
-    let path = format!(
-        "{};{}",
-        std::env::var("PATH").unwrap(),
-        get_cargo_home().join("bin").display()
-    );
-    #[cfg(unix)]
-    let path = format!(
-        "{}:{}",
-        std::env::var("PATH").unwrap(),
-        get_cargo_home().join("bin").display()
     );
+    if !context.modify_path {
+        rustup_init.arg("--no-modify-path");
+    }
+    rustup_init
+        .envs(context.envs())
+        .env("RUSTUP_HOME", get_rustup_home())
+        .env("CARGO_HOME", get_cargo_home());
+    rustup_init.run()?;
 
-    std::env::set_var("PATH", path);
+    // The next commands find rustup and cargo through the `PATH` of `Context::envs`.
     warn!(
         "{} Please restart your terminal after the installation for the changes to take effect.",
         emoji::WARN
@@ -367,52 +948,135 @@ fn install_rustup(nightly_version: &str, host_triple: &HostTriple) -> Result<(),
     Ok(())
 }
 
-/// Installs the RiscV target.
-pub fn install_riscv_target(nightly_version: &str) -> Result<()> {
-    info!("{} Installing Riscv target", emoji::WRENCH);
-    cmd!(
-        "rustup",
-        "component",
-        "add",
-        "rust-src",
-        "--toolchain",
-        nightly_version
-    )
-    .run()
-    .into_diagnostic()?;
+/// Gets the Rust targets of the RISC-V chips among the given targets.
+pub fn get_riscv_targets(targets: &HashSet<Target>) -> Vec<&'static str> {
+    let mut riscv_targets: Vec<&str> = targets
+        .iter()
+        .filter(|target| target.is_riscv())
+        .map(Target::rust_target)
+        .collect();
+    riscv_targets.sort_unstable();
+    riscv_targets.dedup();
+    riscv_targets
+}
+
+/// Installs the RiscV targets.
+pub fn install_riscv_target(
+    context: &Context,
+    nightly_version: &str,
+    targets: &HashSet<Target>,
+) -> Result<()> {
+    info!("{} Installing Riscv targets", emoji::WRENCH);
+    if context.install_rust_src {
+        if !get_installed(context, nightly_version, "component").contains(&"rust-src".to_string()) {
+            context.rollback.track_command(&[
+                "rustup",
+                "component",
+                "remove",
+                "rust-src",
+                "--toolchain",
+                nightly_version,
+            ]);
+        }
+        cmd!(
+            "rustup",
+            "component",
+            "add",
+            "rust-src",
+            "--toolchain",
+            nightly_version;
+            envs=(context.envs())
+        )
+        .run()
+        .map_err(Error::from)?;
+    }
+    let installed = get_installed(context, nightly_version, "target");
+    for target in get_riscv_targets(targets) {
+        if !installed.contains(&target.to_string()) {
+            context.rollback.track_command(&[
+                "rustup",
+                "target",
+                "remove",
+                "--toolchain",
+                nightly_version,
+                target,
+            ]);
+        }
+    }
     cmd!(
         "rustup",
         "target",
         "add",
         "--toolchain",
-        nightly_version,
-        "riscv32imac-unknown-none-elf"
+        nightly_version;
+        args=(get_riscv_targets(targets)),
+        envs=(context.envs())
     )
     .run()
-    .into_diagnostic()?;
+    .map_err(Error::from)?;
     Ok(())
 }
 
+/// Gets the targets or the components, as `kind`, installed for a toolchain.
+fn get_installed(context: &Context, toolchain: &str, kind: &str) -> Vec<String> {
+    get_rustup_output(
+        context,
+        &[kind, "list", "--installed", "--toolchain", toolchain],
+    )
+    .map(|output| output.lines().map(|line| line.trim().to_string()).collect())
+    .unwrap_or_default()
+}
+
+/// Runs rustup, found in the `PATH` of [`Context::envs`], without printing its output and
+/// returns its stdout, if it succeeded.
+fn get_rustup_output(context: &Context, args: &[&str]) -> Option<String> {
+    let output = cmd!("rustup"; args=(args), envs=(context.envs()))
+        .into_inner()
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        None
+    }
+}
+
 /// Installs the desired version of the nightly toolchain.
-fn install_rust_nightly(version: &str) -> Result<()> {
+fn install_rust_nightly(context: &Context, version: &str) -> Result<()> {
     info!("{} Installing {} toolchain", emoji::WRENCH, version);
+    context
+        .rollback
+        .track_command(&["rustup", "toolchain", "uninstall", version]);
     cmd!(
         "rustup",
         "toolchain",
         "install",
         version,
         "--profile",
-        "minimal"
+        "minimal";
+        envs=(context.envs())
     )
     .run()
-    .into_diagnostic()?;
+    .map_err(Error::from)?;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::toolchain::rust::{Crate, XtensaRust};
-    use std::collections::HashSet;
+    #[cfg(unix)]
+    use crate::toolchain::rust::parse_rustc_commit;
+    use crate::{
+        error::Error,
+        host_triple::HostTriple,
+        toolchain::rust::{
+            is_toolchain_installed, is_xtensa_toolchain, parse_nightly_version, select_version,
+            Crate, Release, XtensaRust,
+        },
+    };
+    use std::{collections::HashSet, fs::create_dir_all};
+    use tempfile::TempDir;
 
     #[test]
     fn test_xtensa_rust_parse_version() {
@@ -423,6 +1087,123 @@ mod tests {
         assert!(XtensaRust::parse_version("1.1.1.1.1").is_err());
         assert!(XtensaRust::parse_version("1..1.1").is_err());
         assert!(XtensaRust::parse_version("1._.*.1").is_err());
+        // The error keeps its type, and so its exit code.
+        assert!(matches!(
+            XtensaRust::parse_version("a.1.1.1")
+                .unwrap_err()
+                .downcast_ref::<Error>(),
+            Some(Error::InvalidXtensaToolchanVersion(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_nightly_version() {
+        assert_eq!(parse_nightly_version("nightly").unwrap(), "nightly");
+        assert_eq!(
+            parse_nightly_version("nightly-2023-01-01").unwrap(),
+            "nightly-2023-01-01"
+        );
+        assert!(parse_nightly_version("nightly-2023-1-1").is_err());
+        assert!(parse_nightly_version("stable").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_parse_rustc_commit() {
+        assert_eq!(
+            parse_rustc_commit("rustc 1.65.0-nightly (9d3c9aa3c 2022-11-03)\n"),
+            Some("9d3c9aa3c")
+        );
+        assert_eq!(parse_rustc_commit("rustc 1.65.0-nightly"), None);
+    }
+
+    #[test]
+    fn test_is_toolchain_installed() {
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let toolchain_list = "stable-x86_64-unknown-linux-gnu (default)
+nightly-2023-01-01-x86_64-unknown-linux-gnu
+esp";
+        assert!(is_toolchain_installed(
+            toolchain_list,
+            "nightly-2023-01-01",
+            &host_triple
+        ));
+        assert!(!is_toolchain_installed(
+            toolchain_list,
+            "nightly",
+            &host_triple
+        ));
+        assert!(is_toolchain_installed(toolchain_list, "esp", &host_triple));
+    }
+
+    #[test]
+    fn test_is_xtensa_toolchain() {
+        let temp_dir = TempDir::new().unwrap();
+        let rustlib = temp_dir.path().join("lib").join("rustlib");
+        create_dir_all(rustlib.join("x86_64-unknown-linux-gnu")).unwrap();
+        assert!(!is_xtensa_toolchain(temp_dir.path()));
+        create_dir_all(rustlib.join("xtensa-esp32-none-elf")).unwrap();
+        assert!(is_xtensa_toolchain(temp_dir.path()));
+        assert!(!is_xtensa_toolchain(&temp_dir.path().join("missing")));
+    }
+
+    #[test]
+    fn test_select_version() {
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let release = |version: &str, prerelease: bool, host: &str| Release {
+            version: version.to_string(),
+            prerelease,
+            assets: vec![format!("rust-{version}-{host}.tar.xz")],
+        };
+        let releases = [
+            release("1.66.0.0", true, "x86_64-unknown-linux-gnu"),
+            release("1.65.0.1", false, "aarch64-apple-darwin"),
+            release("1.65.0.0", false, "x86_64-unknown-linux-gnu"),
+        ];
+        assert_eq!(
+            select_version(&releases, "latest", &host_triple).unwrap(),
+            ("1.65.0.0".to_string(), host_triple.clone())
+        );
+        assert_eq!(
+            select_version(&releases, "pre-release", &host_triple).unwrap(),
+            ("1.66.0.0".to_string(), host_triple.clone())
+        );
+        assert_eq!(
+            select_version(&releases, "1.65.0.0", &host_triple).unwrap(),
+            ("1.65.0.0".to_string(), host_triple.clone())
+        );
+        assert!(matches!(
+            select_version(&releases, "1.65.0.1", &host_triple),
+            Err(Error::UnavailableXtensaToolchainVersion(_, _, available)) if available == "1.66.0.0, 1.65.0.0"
+        ));
+
+        // Apple Silicon falls back to the x86_64 toolchain, run under Rosetta 2.
+        let releases = [
+            release("1.65.0.1", false, "aarch64-apple-darwin"),
+            release("1.65.0.0", false, "x86_64-apple-darwin"),
+        ];
+        let host_triple = HostTriple::Aarch64AppleDarwin;
+        assert_eq!(
+            select_version(&releases, "latest", &host_triple).unwrap(),
+            ("1.65.0.1".to_string(), HostTriple::Aarch64AppleDarwin)
+        );
+        assert_eq!(
+            select_version(&releases, "1.65.0.0", &host_triple).unwrap(),
+            ("1.65.0.0".to_string(), HostTriple::X86_64AppleDarwin)
+        );
+    }
+
+    #[test]
+    fn test_xtensa_rust_parse_toolchain_name() {
+        assert_eq!(XtensaRust::parse_toolchain_name("esp").unwrap(), "esp");
+        assert_eq!(
+            XtensaRust::parse_toolchain_name("esp-1.64").unwrap(),
+            "esp-1.64"
+        );
+        assert!(XtensaRust::parse_toolchain_name("nightly").is_err());
+        assert!(XtensaRust::parse_toolchain_name("stable-esp").is_err());
+        assert!(XtensaRust::parse_toolchain_name("esp/1.64").is_err());
+        assert!(XtensaRust::parse_toolchain_name("").is_err());
     }
 
     #[test]
@@ -452,5 +1233,13 @@ mod tests {
             Crate::parse_crates("cargo-binstall,espmonitor"),
             Ok(crates)
         ));
+        let crates: HashSet<Crate> = ["espflash", "ldproxy", "cargo-generate"]
+            .into_iter()
+            .map(Crate::new)
+            .collect();
+        assert_eq!(
+            Crate::parse_crates("espflash, ldproxy  cargo-generate,").unwrap(),
+            crates
+        );
     }
 }
@@ -0,0 +1,172 @@
+//! sccache source and installation tools
+
+use crate::{
+    context::Context,
+    emoji,
+    error::Error,
+    host_triple::HostTriple,
+    resume::{install_component, is_installed},
+    toolchain::{download_file, espidf::get_tool_path},
+};
+use log::{debug, info, warn};
+use std::path::PathBuf;
+
+pub const DEFAULT_SCCACHE_REPOSITORY: &str = "https://github.com/mozilla/sccache/releases/download";
+const DEFAULT_SCCACHE_VERSION: &str = "0.5.4";
+/// Name of the sccache tool directory.
+pub const SCCACHE_NAME: &str = "sccache";
+
+#[derive(Debug, Clone)]
+pub struct Sccache {
+    /// Host triple.
+    pub host_triple: HostTriple,
+    /// sccache installation path.
+    pub path: PathBuf,
+    /// Directory of the cache, see [`get_sccache_dir`].
+    pub cache_dir: PathBuf,
+    /// URL of the sccache artifact.
+    pub repository_url: String,
+}
+
+impl Sccache {
+    /// Gets the binary path.
+    pub fn get_bin_path(&self) -> String {
+        format!(
+            "{}/{}-v{}-{}",
+            self.path.display(),
+            SCCACHE_NAME,
+            DEFAULT_SCCACHE_VERSION,
+            get_arch(&self.host_triple)
+        )
+    }
+
+    /// Gets the path of the sccache executable.
+    fn get_executable(&self) -> String {
+        #[cfg(windows)]
+        return format!("{}/{}.exe", self.get_bin_path(), SCCACHE_NAME);
+        #[cfg(unix)]
+        return format!("{}/{}", self.get_bin_path(), SCCACHE_NAME);
+    }
+
+    /// Gets the environment variables to export: sccache wraps `rustc` in the Cargo builds
+    /// and the compilers in the CMake builds, e.g. the ESP-IDF ones, with a shared cache.
+    pub fn get_exports(&self) -> Vec<String> {
+        let executable = self.get_executable();
+        let variables = [
+            ("RUSTC_WRAPPER", executable.clone()),
+            ("CMAKE_C_COMPILER_LAUNCHER", executable.clone()),
+            ("CMAKE_CXX_COMPILER_LAUNCHER", executable),
+            ("SCCACHE_DIR", self.cache_dir.display().to_string()),
+        ];
+        let mut exports: Vec<String> = Vec::new();
+        #[cfg(windows)]
+        {
+            exports.push(format!("$Env:PATH += \";{}\"", self.get_bin_path()));
+            exports.extend(
+                variables
+                    .iter()
+                    .map(|(key, value)| format!("$Env:{key}=\"{value}\"")),
+            );
+        }
+        #[cfg(unix)]
+        {
+            exports.push(format!("export PATH={}:$PATH", self.get_bin_path()));
+            exports.extend(
+                variables
+                    .iter()
+                    .map(|(key, value)| format!("export {key}=\"{value}\"")),
+            );
+        }
+        exports
+    }
+
+    /// Installs sccache.
+    pub fn install(&self, context: &Context) -> Result<Vec<String>, Error> {
+        debug!("{} sccache path: {}", emoji::DEBUG, self.path.display());
+        if is_installed(context, &self.path) {
+            warn!(
+                "{} Previous installation of sccache exist in: '{}'. Reusing this installation.",
+                emoji::WARN,
+                self.path.display()
+            );
+        } else {
+            info!("{} Installing sccache", emoji::WRENCH);
+            install_component(context, &self.path, || {
+                download_file(
+                    context,
+                    self.repository_url.clone(),
+                    &format!("{SCCACHE_NAME}.tar.gz"),
+                    &self.path.display().to_string(),
+                    true,
+                )
+            })?;
+        }
+        Ok(self.get_exports())
+    }
+
+    /// Create a new instance with default values and proper artifact URL.
+    pub fn new(context: &Context, host_triple: &HostTriple) -> Self {
+        let repository_url = format!(
+            "{}/v{}/{}-v{}-{}.tar.gz",
+            context
+                .http
+                .base_url(SCCACHE_NAME, DEFAULT_SCCACHE_REPOSITORY),
+            DEFAULT_SCCACHE_VERSION,
+            SCCACHE_NAME,
+            DEFAULT_SCCACHE_VERSION,
+            get_arch(host_triple)
+        );
+        let path = PathBuf::from(format!(
+            "{}/v{}",
+            get_tool_path(context, SCCACHE_NAME),
+            DEFAULT_SCCACHE_VERSION
+        ));
+        Self {
+            host_triple: host_triple.clone(),
+            path,
+            cache_dir: get_sccache_dir(context),
+            repository_url,
+        }
+    }
+}
+
+/// Gets the directory of the sccache cache, kept along with the tools and shared by the
+/// projects.
+pub fn get_sccache_dir(context: &Context) -> PathBuf {
+    context.tools_path.join("sccache-cache")
+}
+
+/// Gets the name of the sccache arch based on the host triple. The Linux builds are static.
+fn get_arch(host_triple: &HostTriple) -> &str {
+    match host_triple {
+        HostTriple::Aarch64AppleDarwin => "aarch64-apple-darwin",
+        HostTriple::X86_64AppleDarwin => "x86_64-apple-darwin",
+        HostTriple::X86_64UnknownLinuxGnu | HostTriple::X86_64UnknownLinuxMusl => {
+            "x86_64-unknown-linux-musl"
+        }
+        HostTriple::Aarch64UnknownLinuxGnu | HostTriple::Aarch64UnknownLinuxMusl => {
+            "aarch64-unknown-linux-musl"
+        }
+        HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => {
+            "x86_64-pc-windows-msvc"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{context::Context, host_triple::HostTriple, toolchain::sccache::Sccache};
+
+    #[test]
+    fn test_sccache_new() {
+        let sccache = Sccache::new(&Context::default(), &HostTriple::Aarch64UnknownLinuxGnu);
+        assert_eq!(
+            sccache.repository_url,
+            "https://github.com/mozilla/sccache/releases/download/v0.5.4/sccache-v0.5.4-aarch64-unknown-linux-musl.tar.gz"
+        );
+        assert!(sccache.path.ends_with("sccache/v0.5.4"));
+        assert!(sccache
+            .get_bin_path()
+            .ends_with("sccache-v0.5.4-aarch64-unknown-linux-musl"));
+    }
+}
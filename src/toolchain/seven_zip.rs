@@ -0,0 +1,864 @@
+//! Extraction of 7z archives, whose streams are stored or compressed with LZMA or LZMA2,
+//! the latter possibly behind a branch converter filter. liblzma only decodes these
+//! streams in its own containers, so they are wrapped in a `.lzma` or `.xz` one.
+
+use crate::toolchain::links;
+use std::{
+    fs::{create_dir_all, File},
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+use xz2::{read::XzDecoder, stream::Stream};
+
+/// Magic bytes of a 7z archive.
+const SIGNATURE: &[u8] = b"7z\xbc\xaf\x27\x1c";
+/// Length of the signature header, which locates the header at the end of the archive.
+const SIGNATURE_HEADER_LENGTH: u64 = 32;
+/// Magic bytes of a `.xz` stream.
+const XZ_MAGIC: &[u8] = b"\xfd7zXZ\x00";
+/// Magic bytes of the footer of a `.xz` stream.
+const XZ_FOOTER_MAGIC: &[u8] = b"YZ";
+/// Windows attribute of the directories.
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+/// Attribute set by p7zip and libarchive when the high 16 bits hold a Unix mode.
+const FILE_ATTRIBUTE_UNIX_EXTENSION: u32 = 0x8000;
+
+/// Identifiers of the properties of the header.
+mod property {
+    pub const END: u8 = 0x00;
+    pub const HEADER: u8 = 0x01;
+    pub const ARCHIVE_PROPERTIES: u8 = 0x02;
+    pub const ADDITIONAL_STREAMS_INFO: u8 = 0x03;
+    pub const MAIN_STREAMS_INFO: u8 = 0x04;
+    pub const FILES_INFO: u8 = 0x05;
+    pub const PACK_INFO: u8 = 0x06;
+    pub const UNPACK_INFO: u8 = 0x07;
+    pub const SUBSTREAMS_INFO: u8 = 0x08;
+    pub const SIZE: u8 = 0x09;
+    pub const CRC: u8 = 0x0a;
+    pub const FOLDER: u8 = 0x0b;
+    pub const CODERS_UNPACK_SIZE: u8 = 0x0c;
+    pub const NUM_UNPACK_STREAM: u8 = 0x0d;
+    pub const EMPTY_STREAM: u8 = 0x0e;
+    pub const EMPTY_FILE: u8 = 0x0f;
+    pub const ANTI: u8 = 0x10;
+    pub const NAME: u8 = 0x11;
+    pub const WIN_ATTRIBUTES: u8 = 0x15;
+    pub const ENCODED_HEADER: u8 = 0x17;
+}
+
+/// Identifiers of the supported coders.
+mod method {
+    pub const COPY: &[u8] = &[0x00];
+    pub const LZMA: &[u8] = &[0x03, 0x01, 0x01];
+    pub const LZMA2: &[u8] = &[0x21];
+    /// Branch converter filters, with their `.xz` filter identifier.
+    pub const BRANCH_CONVERTERS: &[(&[u8], u64)] = &[
+        (&[0x03, 0x03, 0x01, 0x03], 0x04),
+        (&[0x03, 0x03, 0x02, 0x05], 0x05),
+        (&[0x03, 0x03, 0x04, 0x01], 0x06),
+        (&[0x03, 0x03, 0x05, 0x01], 0x07),
+        (&[0x03, 0x03, 0x07, 0x01], 0x08),
+        (&[0x03, 0x03, 0x08, 0x05], 0x09),
+    ];
+    /// `.xz` filter identifier of LZMA2.
+    pub const XZ_LZMA2: u64 = 0x21;
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid 7z archive: {message}"),
+    )
+}
+
+/// Reader of the properties of the header.
+struct HeaderReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> HeaderReader<'a> {
+    fn bytes(&mut self, length: u64) -> io::Result<&'a [u8]> {
+        let length = usize::try_from(length)
+            .ok()
+            .filter(|length| *length <= self.bytes.len())
+            .ok_or_else(|| invalid("truncated header"))?;
+        let (bytes, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads a number, whose first byte holds the count of the bytes that follow in its
+    /// leading one bits, and its most significant bits in the others.
+    fn number(&mut self) -> io::Result<u64> {
+        let first = self.byte()?;
+        let mut number = 0;
+        for index in 0..8 {
+            let mask = 0x80 >> index;
+            if first & mask == 0 {
+                let high = u64::from(first & (mask - 1));
+                return Ok(number | high << (8 * index));
+            }
+            number |= u64::from(self.byte()?) << (8 * index);
+        }
+        Ok(number)
+    }
+
+    fn count(&mut self) -> io::Result<usize> {
+        usize::try_from(self.number()?).map_err(|_| invalid("too many items"))
+    }
+
+    fn expect(&mut self, id: u8) -> io::Result<()> {
+        if self.byte()? == id {
+            Ok(())
+        } else {
+            Err(invalid("unexpected property"))
+        }
+    }
+
+    /// Reads a bit field, most significant bit first.
+    fn bits(&mut self, count: usize) -> io::Result<Vec<bool>> {
+        let bytes = self.bytes(((count as u64) + 7) / 8)?;
+        Ok((0..count)
+            .map(|index| bytes[index / 8] & (0x80 >> (index % 8)) != 0)
+            .collect())
+    }
+
+    /// Reads which of the items are defined, all of them unless the first byte is zero.
+    fn defined(&mut self, count: usize) -> io::Result<Vec<bool>> {
+        if self.byte()? == 0 {
+            self.bits(count)
+        } else {
+            Ok(vec![true; count])
+        }
+    }
+
+    fn digests(&mut self, count: usize) -> io::Result<Vec<Option<u32>>> {
+        self.defined(count)?
+            .into_iter()
+            .map(|defined| defined.then(|| self.u32()).transpose())
+            .collect()
+    }
+}
+
+/// Coder of a folder.
+#[derive(Debug)]
+struct Coder {
+    id: Vec<u8>,
+    properties: Vec<u8>,
+}
+
+/// Group of coders decoding a packed stream into one or more files.
+#[derive(Debug, Default)]
+struct Folder {
+    coders: Vec<Coder>,
+    /// Pairs of the input of a coder and the output of the coder feeding it.
+    bind_pairs: Vec<(usize, usize)>,
+    /// Size of the output of each coder.
+    unpack_sizes: Vec<u64>,
+    /// Offset of its packed stream in the archive, and its size.
+    pack_offset: u64,
+    pack_size: u64,
+    /// Size and CRC of each file of the folder.
+    substreams: Vec<(u64, Option<u32>)>,
+}
+
+impl Folder {
+    fn read(reader: &mut HeaderReader) -> io::Result<Self> {
+        let mut folder = Folder::default();
+        for _ in 0..reader.count()? {
+            let flags = reader.byte()?;
+            // Coders with several inputs or outputs, e.g. BCJ2, are not supported.
+            if flags & 0x10 != 0 || flags & 0x80 != 0 {
+                return Err(invalid("unsupported coder"));
+            }
+            let id = reader.bytes(u64::from(flags & 0x0f))?.to_vec();
+            let properties = if flags & 0x20 != 0 {
+                let length = reader.number()?;
+                reader.bytes(length)?.to_vec()
+            } else {
+                Vec::new()
+            };
+            folder.coders.push(Coder { id, properties });
+        }
+        for _ in 1..folder.coders.len() {
+            folder.bind_pairs.push((reader.count()?, reader.count()?));
+        }
+        Ok(folder)
+    }
+
+    /// Gets the coders from the one reading the packed stream to the one writing the files,
+    /// with the size of their output.
+    fn chain(&self) -> io::Result<Vec<(&Coder, u64)>> {
+        let mut index = (0..self.coders.len())
+            .find(|index| !self.bind_pairs.iter().any(|(input, _)| input == index))
+            .ok_or_else(|| invalid("no packed stream"))?;
+        let mut chain = vec![index];
+        while let Some((input, _)) = self.bind_pairs.iter().find(|(_, output)| *output == index) {
+            index = *input;
+            if chain.contains(&index) {
+                return Err(invalid("cyclic coders"));
+            }
+            chain.push(index);
+        }
+        chain
+            .into_iter()
+            .map(
+                |index| match (self.coders.get(index), self.unpack_sizes.get(index)) {
+                    (Some(coder), Some(unpack_size)) => Ok((coder, *unpack_size)),
+                    _ => Err(invalid("unknown coder")),
+                },
+            )
+            .collect()
+    }
+
+    /// Gets the size of the output of the folder, that of its last coder.
+    fn unpack_size(&self) -> u64 {
+        (0..self.coders.len())
+            .find(|index| !self.bind_pairs.iter().any(|(_, output)| output == index))
+            .and_then(|index| self.unpack_sizes.get(index))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Decodes the folder from the archive.
+    fn decode<'a, R: Read + Seek>(&self, archive: &'a mut R) -> io::Result<Box<dyn Read + 'a>> {
+        archive.seek(SeekFrom::Start(self.pack_offset))?;
+        let packed = BufReader::new(archive.take(self.pack_size));
+        let chain = self.chain()?;
+        let decoded: Box<dyn Read + 'a> = match chain.as_slice() {
+            [(coder, _)] if coder.id == method::COPY => Box::new(packed),
+            [(coder, unpack_size)] if coder.id == method::LZMA => {
+                if coder.properties.len() != 5 {
+                    return Err(invalid("invalid LZMA properties"));
+                }
+                // Header of a `.lzma` file: the properties, then the uncompressed size.
+                let mut header = coder.properties.clone();
+                header.extend(unpack_size.to_le_bytes());
+                let stream = Stream::new_lzma_decoder(u64::MAX).map_err(io::Error::from)?;
+                Box::new(XzDecoder::new_stream(
+                    Cursor::new(header).chain(packed),
+                    stream,
+                ))
+            }
+            [(coder, unpack_size), filters @ ..]
+                if coder.id == method::LZMA2 && filters.len() < 4 =>
+            {
+                // The `.xz` filters are listed in the order they are applied when compressing.
+                let mut xz_filters = Vec::new();
+                for (filter, _) in filters.iter().rev() {
+                    let id = method::BRANCH_CONVERTERS
+                        .iter()
+                        .find(|(id, _)| *id == filter.id.as_slice())
+                        .map(|(_, xz_id)| *xz_id)
+                        .ok_or_else(|| invalid("unsupported filter"))?;
+                    xz_filters.push((id, filter.properties.as_slice()));
+                }
+                xz_filters.push((method::XZ_LZMA2, coder.properties.as_slice()));
+                let (header, footer) = get_xz_container(&xz_filters, self.pack_size, *unpack_size);
+                Box::new(XzDecoder::new(
+                    Cursor::new(header).chain(packed).chain(Cursor::new(footer)),
+                ))
+            }
+            _ => return Err(invalid("unsupported compression method")),
+        };
+        Ok(Box::new(decoded.take(self.unpack_size())))
+    }
+}
+
+/// Appends a number of a `.xz` container, in 7 bits little endian groups.
+fn push_xz_number(bytes: &mut Vec<u8>, mut number: u64) {
+    while number >= 0x80 {
+        bytes.push(number as u8 | 0x80);
+        number >>= 7;
+    }
+    bytes.push(number as u8);
+}
+
+/// Pads a part of a `.xz` container to a multiple of four bytes.
+fn pad_xz(bytes: &mut Vec<u8>) {
+    bytes.resize((bytes.len() + 3) / 4 * 4, 0);
+}
+
+/// Gets the bytes before and after a stream compressed with LZMA2 to make it a `.xz`
+/// stream of a single block, without check.
+fn get_xz_container(
+    filters: &[(u64, &[u8])],
+    pack_size: u64,
+    unpack_size: u64,
+) -> (Vec<u8>, Vec<u8>) {
+    let stream_flags = [0, 0];
+    let mut header = XZ_MAGIC.to_vec();
+    header.extend(stream_flags);
+    header.extend(crc32fast::hash(&stream_flags).to_le_bytes());
+    let mut block_header = vec![0, filters.len() as u8 - 1];
+    for (id, properties) in filters {
+        push_xz_number(&mut block_header, *id);
+        push_xz_number(&mut block_header, properties.len() as u64);
+        block_header.extend(*properties);
+    }
+    pad_xz(&mut block_header);
+    // The size of the block header, including its CRC, in multiples of four bytes, minus one.
+    block_header[0] = (block_header.len() / 4) as u8;
+    block_header.extend(crc32fast::hash(&block_header).to_le_bytes());
+    let unpadded_size = block_header.len() as u64 + pack_size;
+    header.extend(block_header);
+
+    let mut footer = vec![0; ((4 - unpadded_size % 4) % 4) as usize];
+    let mut index = vec![0, 1];
+    push_xz_number(&mut index, unpadded_size);
+    push_xz_number(&mut index, unpack_size);
+    pad_xz(&mut index);
+    index.extend(crc32fast::hash(&index).to_le_bytes());
+    let mut stream_footer = ((index.len() / 4 - 1) as u32).to_le_bytes().to_vec();
+    stream_footer.extend(stream_flags);
+    footer.extend(index);
+    footer.extend(crc32fast::hash(&stream_footer).to_le_bytes());
+    footer.extend(stream_footer);
+    footer.extend(XZ_FOOTER_MAGIC);
+    (header, footer)
+}
+
+/// Reads the streams of the archive, locating the packed streams after the signature
+/// header.
+fn read_streams_info(reader: &mut HeaderReader) -> io::Result<Vec<Folder>> {
+    let mut pack_offset = SIGNATURE_HEADER_LENGTH;
+    let mut pack_sizes = Vec::new();
+    let mut folders = Vec::new();
+    loop {
+        match reader.byte()? {
+            property::END => break,
+            property::PACK_INFO => {
+                pack_offset += reader.number()?;
+                let count = reader.count()?;
+                loop {
+                    match reader.byte()? {
+                        property::END => break,
+                        property::SIZE => {
+                            for _ in 0..count {
+                                pack_sizes.push(reader.number()?);
+                            }
+                        }
+                        property::CRC => {
+                            reader.digests(count)?;
+                        }
+                        _ => return Err(invalid("unexpected property")),
+                    }
+                }
+            }
+            property::UNPACK_INFO => {
+                reader.expect(property::FOLDER)?;
+                let count = reader.count()?;
+                if reader.byte()? != 0 {
+                    return Err(invalid("external folders"));
+                }
+                for _ in 0..count {
+                    folders.push(Folder::read(reader)?);
+                }
+                reader.expect(property::CODERS_UNPACK_SIZE)?;
+                for folder in &mut folders {
+                    for _ in 0..folder.coders.len() {
+                        folder.unpack_sizes.push(reader.number()?);
+                    }
+                }
+                loop {
+                    match reader.byte()? {
+                        property::END => break,
+                        property::CRC => {
+                            for (folder, crc) in folders.iter_mut().zip(reader.digests(count)?) {
+                                folder.substreams = vec![(folder.unpack_size(), crc)];
+                            }
+                        }
+                        _ => return Err(invalid("unexpected property")),
+                    }
+                }
+                for folder in &mut folders {
+                    if folder.substreams.is_empty() {
+                        folder.substreams = vec![(folder.unpack_size(), None)];
+                    }
+                }
+            }
+            property::SUBSTREAMS_INFO => read_substreams_info(reader, &mut folders)?,
+            _ => return Err(invalid("unexpected property")),
+        }
+    }
+    if pack_sizes.len() < folders.len() {
+        return Err(invalid("missing packed streams"));
+    }
+    for (folder, pack_size) in folders.iter_mut().zip(pack_sizes) {
+        folder.pack_offset = pack_offset;
+        folder.pack_size = pack_size;
+        pack_offset = pack_offset
+            .checked_add(pack_size)
+            .ok_or_else(|| invalid("invalid packed stream size"))?;
+    }
+    Ok(folders)
+}
+
+/// Reads how the folders are split into files.
+fn read_substreams_info(reader: &mut HeaderReader, folders: &mut [Folder]) -> io::Result<()> {
+    let mut counts = vec![1; folders.len()];
+    let mut property = reader.byte()?;
+    if property == property::NUM_UNPACK_STREAM {
+        for count in &mut counts {
+            *count = reader.count()?;
+        }
+        property = reader.byte()?;
+    }
+    let mut sizes = Vec::new();
+    for (folder, count) in folders.iter().zip(&counts) {
+        let mut remaining = folder.unpack_size();
+        for index in 0..*count {
+            let size = if index + 1 < *count && property == property::SIZE {
+                reader.number()?
+            } else if index + 1 < *count {
+                return Err(invalid("missing file sizes"));
+            } else {
+                remaining
+            };
+            remaining = remaining
+                .checked_sub(size)
+                .ok_or_else(|| invalid("invalid file size"))?;
+            sizes.push(size);
+        }
+    }
+    if property == property::SIZE {
+        property = reader.byte()?;
+    }
+    // Only the CRCs of the files which are not alone in a folder with a CRC are listed.
+    let mut crcs = Vec::new();
+    let mut digests = Vec::new();
+    if property == property::CRC {
+        let unknown = folders
+            .iter()
+            .zip(&counts)
+            .map(|(folder, count)| match folder.substreams.as_slice() {
+                [(_, Some(_))] if *count == 1 => 0,
+                _ => *count,
+            })
+            .sum();
+        digests = reader.digests(unknown)?;
+        property = reader.byte()?;
+    }
+    let mut digests = digests.into_iter();
+    for (folder, count) in folders.iter().zip(&counts) {
+        match folder.substreams.as_slice() {
+            [(_, Some(crc))] if *count == 1 => crcs.push(Some(*crc)),
+            _ => {
+                for _ in 0..*count {
+                    crcs.push(digests.next().flatten());
+                }
+            }
+        }
+    }
+    if property != property::END {
+        return Err(invalid("unexpected property"));
+    }
+    let mut substreams = sizes.into_iter().zip(crcs);
+    for (folder, count) in folders.iter_mut().zip(counts) {
+        folder.substreams = substreams.by_ref().take(count).collect();
+    }
+    Ok(())
+}
+
+/// Entry of the archive.
+#[derive(Debug, Default)]
+struct Entry {
+    path: PathBuf,
+    has_stream: bool,
+    is_dir: bool,
+    is_anti: bool,
+    attributes: Option<u32>,
+}
+
+impl Entry {
+    /// Gets the Unix mode of the entry, when it was archived on Unix.
+    fn unix_mode(&self) -> Option<u32> {
+        self.attributes
+            .filter(|attributes| attributes & FILE_ATTRIBUTE_UNIX_EXTENSION != 0)
+            .map(|attributes| attributes >> 16)
+            .filter(|mode| *mode != 0)
+    }
+}
+
+/// Reads the entries of the archive.
+fn read_files_info(reader: &mut HeaderReader) -> io::Result<Vec<Entry>> {
+    let count = reader.count()?;
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut empty_streams = vec![false; count];
+    let mut empty_files = Vec::new();
+    let mut anti = Vec::new();
+    let mut names = Vec::new();
+    let mut attributes = Vec::new();
+    loop {
+        let property = reader.byte()?;
+        if property == property::END {
+            break;
+        }
+        let length = reader.number()?;
+        let mut data = HeaderReader {
+            bytes: reader.bytes(length)?,
+        };
+        let empty_stream_count = empty_streams.iter().filter(|empty| **empty).count();
+        match property {
+            property::EMPTY_STREAM => empty_streams = data.bits(count)?,
+            property::EMPTY_FILE => empty_files = data.bits(empty_stream_count)?,
+            property::ANTI => anti = data.bits(empty_stream_count)?,
+            property::NAME => {
+                if data.byte()? != 0 {
+                    return Err(invalid("external names"));
+                }
+                let units: Vec<u16> = data
+                    .bytes
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                    .collect();
+                names = units
+                    .split(|unit| *unit == 0)
+                    .take(count)
+                    .map(String::from_utf16_lossy)
+                    .collect();
+            }
+            property::WIN_ATTRIBUTES => {
+                let defined = data.defined(count)?;
+                if data.byte()? != 0 {
+                    return Err(invalid("external attributes"));
+                }
+                for defined in defined {
+                    attributes.push(defined.then(|| data.u32()).transpose()?);
+                }
+            }
+            // Times and padding.
+            _ => {}
+        }
+    }
+    if names.len() != count {
+        return Err(invalid("missing names"));
+    }
+    let mut empty_index = 0;
+    for (index, name) in names.into_iter().enumerate() {
+        let mut entry = Entry {
+            path: get_enclosed_path(&name)?,
+            has_stream: !empty_streams[index],
+            attributes: attributes.get(index).copied().flatten(),
+            ..Entry::default()
+        };
+        if !entry.has_stream {
+            entry.is_dir = !empty_files.get(empty_index).copied().unwrap_or(false);
+            entry.is_anti = anti.get(empty_index).copied().unwrap_or(false);
+            empty_index += 1;
+        }
+        if let Some(attributes) = entry.attributes {
+            entry.is_dir |= attributes & FILE_ATTRIBUTE_DIRECTORY != 0;
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Gets the path of an entry, which must stay inside the extraction directory. The
+/// archives created on Windows separate its components with backslashes.
+fn get_enclosed_path(name: &str) -> io::Result<PathBuf> {
+    name.split(['/', '\\'])
+        .filter(|component| !component.is_empty() && *component != ".")
+        .map(|component| {
+            let path = Path::new(component);
+            if component == ".." || path.has_root() || path.components().count() != 1 {
+                Err(invalid("path outside of the archive"))
+            } else {
+                Ok(path)
+            }
+        })
+        .collect()
+}
+
+/// Writes to a file, computing the CRC of what was written.
+struct CrcWriter {
+    file: File,
+    hasher: crc32fast::Hasher,
+}
+
+impl Write for CrcWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Reads the header of the archive, decoding it when it is compressed.
+fn read_header(archive: &mut (impl Read + Seek)) -> io::Result<(Vec<Folder>, Vec<Entry>)> {
+    let mut signature_header = [0; SIGNATURE_HEADER_LENGTH as usize];
+    archive.seek(SeekFrom::Start(0))?;
+    archive.read_exact(&mut signature_header)?;
+    let mut reader = HeaderReader {
+        bytes: &signature_header,
+    };
+    if reader.bytes(6)? != SIGNATURE || reader.byte()? != 0 {
+        return Err(invalid("unsupported version"));
+    }
+    reader.byte()?;
+    let start_header_crc = reader.u32()?;
+    if crc32fast::hash(reader.bytes) != start_header_crc {
+        return Err(invalid("corrupt signature header"));
+    }
+    let offset = u64::from(reader.u32()?) | u64::from(reader.u32()?) << 32;
+    let size = u64::from(reader.u32()?) | u64::from(reader.u32()?) << 32;
+    let header_crc = reader.u32()?;
+    let header_offset = SIGNATURE_HEADER_LENGTH
+        .checked_add(offset)
+        .ok_or_else(|| invalid("invalid header offset"))?;
+    archive.seek(SeekFrom::Start(header_offset))?;
+    let mut header = Vec::new();
+    archive.take(size).read_to_end(&mut header)?;
+    if header.len() as u64 != size || crc32fast::hash(&header) != header_crc {
+        return Err(invalid("corrupt header"));
+    }
+    loop {
+        let mut reader = HeaderReader { bytes: &header };
+        match reader.byte()? {
+            property::HEADER => return read_main_header(&mut reader),
+            property::ENCODED_HEADER => {
+                let mut decoded = Vec::new();
+                for folder in read_streams_info(&mut reader)? {
+                    folder.decode(archive)?.read_to_end(&mut decoded)?;
+                }
+                header = decoded;
+            }
+            _ => return Err(invalid("unexpected property")),
+        }
+    }
+}
+
+/// Reads the header, once decoded.
+fn read_main_header(reader: &mut HeaderReader) -> io::Result<(Vec<Folder>, Vec<Entry>)> {
+    let mut folders = Vec::new();
+    let mut entries = Vec::new();
+    loop {
+        match reader.byte()? {
+            property::END => return Ok((folders, entries)),
+            property::ARCHIVE_PROPERTIES => loop {
+                if reader.byte()? == property::END {
+                    break;
+                }
+                let length = reader.number()?;
+                reader.bytes(length)?;
+            },
+            property::ADDITIONAL_STREAMS_INFO => {
+                read_streams_info(reader)?;
+            }
+            property::MAIN_STREAMS_INFO => folders = read_streams_info(reader)?,
+            property::FILES_INFO => entries = read_files_info(reader)?,
+            _ => return Err(invalid("unexpected property")),
+        }
+    }
+}
+
+/// Extracts a 7z archive into a directory, then applies the Unix modes of its entries
+/// and creates its symbolic links, which are stored as files holding their target.
+pub fn extract(mut archive: impl Read + Seek, directory: &Path) -> io::Result<()> {
+    let (folders, entries) = read_header(&mut archive)?;
+    create_dir_all(directory)?;
+    for entry in entries
+        .iter()
+        .filter(|entry| !entry.has_stream && !entry.is_anti)
+    {
+        let path = directory.join(&entry.path);
+        if entry.is_dir {
+            create_dir_all(path)?;
+        } else {
+            if let Some(parent) = path.parent() {
+                create_dir_all(parent)?;
+            }
+            File::create(path)?;
+        }
+    }
+    let mut files = entries.iter().filter(|entry| entry.has_stream);
+    for folder in &folders {
+        let mut decoded = folder.decode(&mut archive)?;
+        for (size, crc) in &folder.substreams {
+            let entry = files.next().ok_or_else(|| invalid("missing entries"))?;
+            let path = directory.join(&entry.path);
+            if let Some(parent) = path.parent() {
+                create_dir_all(parent)?;
+            }
+            let mut writer = CrcWriter {
+                file: File::create(&path)?,
+                hasher: crc32fast::Hasher::new(),
+            };
+            if io::copy(&mut decoded.by_ref().take(*size), &mut writer)? != *size {
+                return Err(invalid("truncated stream"));
+            }
+            if crc.map_or(false, |crc| crc != writer.hasher.finalize()) {
+                return Err(invalid("CRC mismatch"));
+            }
+        }
+    }
+    if files.next().is_some() {
+        return Err(invalid("missing streams"));
+    }
+    let modes: Vec<(PathBuf, u32)> = entries
+        .iter()
+        .filter(|entry| !entry.is_dir && !entry.is_anti)
+        .filter_map(|entry| Some((entry.path.clone(), entry.unix_mode()?)))
+        .collect();
+    links::restore_modes(directory, &modes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::toolchain::seven_zip::{extract, get_enclosed_path};
+    use std::{fs::read_to_string, io::Cursor, path::PathBuf};
+    use tempfile::TempDir;
+    use xz2::stream::{Action, LzmaOptions, Stream};
+
+    /// Appends a number of a 7z header, stored in full after a `0xff` byte when it is large.
+    fn push_number(bytes: &mut Vec<u8>, number: u64) {
+        if number < 0x80 {
+            bytes.push(number as u8);
+        } else {
+            bytes.push(0xff);
+            bytes.extend(number.to_le_bytes());
+        }
+    }
+
+    /// Creates a 7z archive whose files, with their Unix modes, are compressed with LZMA in
+    /// a single folder, followed by an empty directory.
+    fn create_archive(files: &[(&str, &[u8], u32)]) -> Vec<u8> {
+        let data: Vec<u8> = files
+            .iter()
+            .flat_map(|(_, data, _)| data.to_vec())
+            .collect();
+        // A `.lzma` file holds the properties, the unknown uncompressed size and the stream.
+        let mut stream = Stream::new_lzma_encoder(&LzmaOptions::new_preset(6).unwrap()).unwrap();
+        let mut lzma = Vec::with_capacity(data.len() + 1024);
+        stream.process_vec(&data, &mut lzma, Action::Run).unwrap();
+        stream.process_vec(&[], &mut lzma, Action::Finish).unwrap();
+        let (properties, packed) = (&lzma[..5], &lzma[13..]);
+
+        let mut header = vec![0x01, 0x04, 0x06, 0x00, 0x01, 0x09];
+        push_number(&mut header, packed.len() as u64);
+        header.extend([
+            0x00, 0x07, 0x0b, 0x01, 0x00, 0x01, 0x23, 0x03, 0x01, 0x01, 0x05,
+        ]);
+        header.extend(properties);
+        header.push(0x0c);
+        push_number(&mut header, data.len() as u64);
+        header.extend([0x00, 0x08, 0x0d, files.len() as u8, 0x09]);
+        for (_, data, _) in &files[..files.len() - 1] {
+            push_number(&mut header, data.len() as u64);
+        }
+        header.extend([0x0a, 0x01]);
+        for (_, data, _) in files {
+            header.extend(crc32fast::hash(data).to_le_bytes());
+        }
+        header.extend([0x00, 0x00, 0x05, files.len() as u8 + 1]);
+        let mut empty_streams = vec![0; (files.len() + 8) / 8];
+        empty_streams[files.len() / 8] = 0x80 >> (files.len() % 8);
+        header.push(0x0e);
+        push_number(&mut header, empty_streams.len() as u64);
+        header.extend(&empty_streams);
+        let mut names = vec![0x00];
+        for name in files.iter().map(|(name, _, _)| *name).chain(["directory"]) {
+            names.extend(name.encode_utf16().chain([0]).flat_map(u16::to_le_bytes));
+        }
+        header.push(0x11);
+        push_number(&mut header, names.len() as u64);
+        header.extend(names);
+        let mut attributes = vec![0x01, 0x00];
+        for (_, _, mode) in files {
+            attributes.extend((mode << 16 | 0x8000).to_le_bytes());
+        }
+        attributes.extend(0x10u32.to_le_bytes());
+        header.push(0x15);
+        push_number(&mut header, attributes.len() as u64);
+        header.extend(attributes);
+        header.extend([0x00, 0x00]);
+
+        let mut start_header = (packed.len() as u64).to_le_bytes().to_vec();
+        start_header.extend((header.len() as u64).to_le_bytes());
+        start_header.extend(crc32fast::hash(&header).to_le_bytes());
+        let mut archive = b"7z\xbc\xaf\x27\x1c\x00\x04".to_vec();
+        archive.extend(crc32fast::hash(&start_header).to_le_bytes());
+        archive.extend(start_header);
+        archive.extend(packed);
+        archive.extend(header);
+        archive
+    }
+
+    #[test]
+    fn test_extract() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = create_archive(&[
+            ("tool/bin/tool", b"#!/bin/sh\n", 0o100755),
+            ("tool/bin/link", b"tool", 0o120777),
+            ("tool/README", &b"README ".repeat(1000), 0o100644),
+            ("tool/empty", b"", 0o100644),
+        ]);
+        extract(Cursor::new(archive), temp_dir.path()).unwrap();
+        let tool = temp_dir.path().join("tool");
+        assert_eq!(
+            read_to_string(tool.join("bin/tool")).unwrap(),
+            "#!/bin/sh\n"
+        );
+        assert_eq!(
+            read_to_string(tool.join("README")).unwrap(),
+            "README ".repeat(1000)
+        );
+        assert_eq!(read_to_string(tool.join("empty")).unwrap(), "");
+        assert!(temp_dir.path().join("directory").is_dir());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = tool
+                .join("bin/tool")
+                .metadata()
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o755);
+            assert_eq!(
+                std::fs::read_link(tool.join("bin/link")).unwrap(),
+                PathBuf::from("tool")
+            );
+        }
+    }
+
+    #[test]
+    fn test_extract_corrupt() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut archive = create_archive(&[("tool", &b"tool ".repeat(1000), 0o100644)]);
+        assert!(extract(Cursor::new(&archive[..20]), temp_dir.path()).is_err());
+        // Corrupting the compressed stream fails its decoding or its CRC.
+        archive[40] ^= 0xff;
+        let error = extract(Cursor::new(archive), temp_dir.path()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_get_enclosed_path() {
+        assert_eq!(
+            get_enclosed_path("tool/bin/tool").unwrap(),
+            PathBuf::from("tool").join("bin").join("tool")
+        );
+        assert_eq!(
+            get_enclosed_path("tool\\bin").unwrap(),
+            PathBuf::from("tool").join("bin")
+        );
+        assert!(get_enclosed_path("../tool").is_err());
+        assert!(get_enclosed_path("tool/../../tool").is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Verification of the signatures published along with some releases: detached OpenPGP
+//! signatures of the downloaded archives, `<url>.asc` or `<url>.sig`, checked with `gpg`
+//! before the archives are extracted, and signed ESP-IDF tags, checked with `git verify-tag`.
+//! The signing keys must be in the keyring of `gpg`.
+
+use crate::{context::Context, download::Downloader, emoji, error::Error};
+use embuild::cmd;
+use log::{debug, info, warn};
+use std::{
+    fs::File,
+    io::{copy, Seek, SeekFrom},
+    path::Path,
+    process::Stdio,
+};
+
+/// Extensions of the detached signatures, appended to the URL of the signed file.
+const SIGNATURE_EXTENSIONS: [&str; 2] = ["asc", "sig"];
+
+/// Fails with `error` if signatures are required, only warning otherwise.
+fn fail_if_required(context: &Context, error: Error) -> Result<(), Error> {
+    if context.require_signatures {
+        return Err(error);
+    }
+    warn!(
+        "{} Skipping the signature verification: {}",
+        emoji::WARN,
+        error
+    );
+    Ok(())
+}
+
+/// Downloads the detached signature of a URL, if one is published.
+fn download_signature(downloader: &dyn Downloader, url: &str) -> Option<tempfile::NamedTempFile> {
+    SIGNATURE_EXTENSIONS.iter().find_map(|extension| {
+        let signature_url = format!("{url}.{extension}");
+        let mut download = downloader.open(&signature_url).ok()?;
+        debug!("{} Found signature '{}'", emoji::DEBUG, signature_url);
+        let mut signature = tempfile::NamedTempFile::new().ok()?;
+        copy(&mut download.reader, &mut signature).ok()?;
+        Some(signature)
+    })
+}
+
+/// Verifies the downloaded `archive` of `url` against its detached signature, before it is
+/// extracted. A signature that does not match always fails, a missing one only when
+/// signatures are required.
+pub fn verify_download(
+    context: &Context,
+    downloader: &dyn Downloader,
+    url: &str,
+    archive: &mut File,
+) -> Result<(), Error> {
+    if !context.verifies_signatures() {
+        return Ok(());
+    }
+    let signature = match download_signature(downloader, url) {
+        Some(signature) => signature,
+        None => return fail_if_required(context, Error::MissingSignature(url.to_string())),
+    };
+    archive.seek(SeekFrom::Start(0))?;
+    // The signed data is read from the standard input.
+    let status = cmd!("gpg", "--batch", "--verify", signature.path(), "-")
+        .into_inner()
+        .stdin(archive.try_clone()?)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    archive.seek(SeekFrom::Start(0))?;
+    match status {
+        Ok(status) if status.success() => {
+            info!("{} Verified the signature of '{}'", emoji::CHECK, url);
+            Ok(())
+        }
+        Ok(_) => Err(Error::InvalidSignature(url.to_string())),
+        Err(_) => fail_if_required(context, Error::MissingGpg(url.to_string())),
+    }
+}
+
+/// Verifies the signature of the `tag` checked out in `repository`. Branches and commits
+/// are not releases, hence have no signature.
+pub fn verify_tag(context: &Context, repository: &Path, tag: Option<&str>) -> Result<(), Error> {
+    if !context.verifies_signatures() {
+        return Ok(());
+    }
+    let repository_name = repository.display().to_string();
+    let tag = match tag {
+        Some(tag) => tag,
+        None => return fail_if_required(context, Error::MissingSignature(repository_name)),
+    };
+    match cmd!("git", "-C", repository, "verify-tag", tag)
+        .into_inner()
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+    {
+        Ok(status) if status.success() => {
+            info!(
+                "{} Verified the signature of the tag '{}'",
+                emoji::CHECK,
+                tag
+            );
+            Ok(())
+        }
+        // Unsigned tags are not told apart from the ones with a bad signature.
+        _ => fail_if_required(
+            context,
+            Error::InvalidSignature(format!("{repository_name} ({tag})")),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        download::MockDownloader,
+        toolchain::signature::{download_signature, SIGNATURE_EXTENSIONS},
+    };
+    use std::fs::read_to_string;
+
+    #[test]
+    fn test_download_signature() {
+        let url = "https://example.com/gcc.tar.xz";
+        let downloader = MockDownloader::default().with_file(
+            &format!("{url}.{}", SIGNATURE_EXTENSIONS[1]),
+            b"signature".to_vec(),
+        );
+        let signature = download_signature(&downloader, url).unwrap();
+        assert_eq!(read_to_string(signature.path()).unwrap(), "signature");
+        assert!(download_signature(&downloader, "https://example.com/gdb.tar.xz").is_none());
+    }
+}
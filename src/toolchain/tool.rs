@@ -0,0 +1,225 @@
+//! Tools installed from an archive attached to one of their releases, e.g. GDB, OpenOCD,
+//! QEMU and the ROM ELFs.
+
+use crate::{
+    context::Context,
+    emoji,
+    error::Error,
+    host_triple::HostTriple,
+    plan::Plan,
+    resume::{install_component, is_installed},
+    toolchain::{download_file, espidf::get_tool_path},
+};
+use log::{debug, info, warn};
+use std::path::PathBuf;
+
+/// Archive of a tool attached to one of its releases.
+#[derive(Debug, Clone, Copy)]
+pub struct ReleaseAsset<'a> {
+    /// Name of the tool directory, e.g. `openocd-esp32`.
+    pub name: &'a str,
+    /// Component whose base URL can be overridden in the `[urls]` table, e.g. `openocd`.
+    pub component: &'a str,
+    /// Default base URL of the release downloads.
+    pub repository: &'a str,
+    /// Tag of the release, e.g. `v0.12.0-esp32-20230921`.
+    pub tag: &'a str,
+    /// Name of the asset, e.g. `openocd-esp32-linux-amd64-0.12.0-esp32-20230921.tar.gz`.
+    pub asset: &'a str,
+    /// Name of the installation directory of the release, e.g. `v0.12.0-esp32-20230921`.
+    pub version: &'a str,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReleaseTool {
+    /// Name of the tool, as logged, e.g. `OpenOCD`.
+    pub name: String,
+    /// Installation path.
+    pub path: PathBuf,
+    /// URL of the artifact.
+    pub repository_url: String,
+    /// Name the artifact is downloaded as, e.g. `openocd-esp32.tar.gz`.
+    file_name: String,
+    /// Directory of the binaries, relative to the installation path, added to `PATH`.
+    bin_dir: Option<String>,
+    /// Variables exported besides `PATH`, along with their value relative to the
+    /// installation path.
+    variables: Vec<(&'static str, String)>,
+}
+
+impl ReleaseTool {
+    /// Creates a tool installed from a release asset, in the `<name>/<version>` directory of
+    /// the tools.
+    pub fn new(context: &Context, name: &str, release: ReleaseAsset) -> Self {
+        let repository_url = format!(
+            "{}/{}/{}",
+            context.http.base_url(release.component, release.repository),
+            release.tag,
+            release.asset
+        );
+        let path = PathBuf::from(format!(
+            "{}/{}",
+            get_tool_path(context, release.name),
+            release.version
+        ));
+        Self {
+            name: name.to_string(),
+            path,
+            repository_url,
+            file_name: format!("{}.{}", release.name, get_archive_extension(release.asset)),
+            bin_dir: None,
+            variables: Vec::new(),
+        }
+    }
+
+    /// Adds the directory of the binaries, relative to the installation path, to `PATH`.
+    pub fn with_bin_dir(mut self, bin_dir: &str) -> Self {
+        self.bin_dir = Some(bin_dir.to_string());
+        self
+    }
+
+    /// Exports a variable set to a path relative to the installation path.
+    pub fn with_variable(mut self, name: &'static str, relative_path: &str) -> Self {
+        self.variables.push((name, relative_path.to_string()));
+        self
+    }
+
+    /// Gets the binary path, if the tool has binaries.
+    pub fn get_bin_path(&self) -> Option<String> {
+        self.bin_dir
+            .as_ref()
+            .map(|bin_dir| format!("{}/{}", self.path.display(), bin_dir))
+    }
+
+    /// Gets the variables exported besides `PATH`, along with their value.
+    fn get_variables(&self) -> impl Iterator<Item = (&str, String)> {
+        self.variables.iter().map(|(name, relative_path)| {
+            (*name, format!("{}/{}", self.path.display(), relative_path))
+        })
+    }
+
+    /// Gets the environment variables to export.
+    pub fn get_exports(&self) -> Vec<String> {
+        let mut exports: Vec<String> = Vec::new();
+        if let Some(bin_path) = self.get_bin_path() {
+            #[cfg(windows)]
+            exports.push(format!("$Env:PATH += \";{bin_path}\""));
+            #[cfg(unix)]
+            exports.push(format!("export PATH={bin_path}:$PATH"));
+        }
+        for (name, value) in self.get_variables() {
+            #[cfg(windows)]
+            exports.push(format!("$Env:{name}=\"{value}\""));
+            #[cfg(unix)]
+            exports.push(format!("export {name}=\"{value}\""));
+        }
+        exports
+    }
+
+    /// Installs the tool.
+    pub fn install(&self, context: &Context) -> Result<Vec<String>, Error> {
+        debug!(
+            "{} {} path: {}",
+            emoji::DEBUG,
+            self.name,
+            self.path.display()
+        );
+        if is_installed(context, &self.path) {
+            warn!(
+                "{} Previous installation of {} exist in: '{}'. Reusing this installation.",
+                emoji::WARN,
+                self.name,
+                self.path.display()
+            );
+        } else {
+            info!("{} Installing {}", emoji::WRENCH, self.name);
+            install_component(context, &self.path, || {
+                download_file(
+                    context,
+                    self.repository_url.clone(),
+                    &self.file_name,
+                    &self.path.display().to_string(),
+                    true,
+                )
+            })?;
+        }
+        Ok(self.get_exports())
+    }
+
+    /// Adds the installation of the tool to the plan, with its estimated disk usage in the
+    /// tools directory.
+    pub fn plan(&self, context: &Context, plan: &mut Plan, disk_usage: u64) {
+        if !is_installed(context, &self.path) {
+            plan.download(context, &self.repository_url, &self.path);
+            plan.create(&self.path);
+            plan.use_disk_space(&context.tools_path, disk_usage);
+        }
+        if let Some(bin_path) = self.get_bin_path() {
+            plan.export("PATH", bin_path);
+        }
+        for (name, value) in self.get_variables() {
+            plan.export(name, value);
+        }
+    }
+}
+
+/// Gets the extension of the archives of the tools published for a host: `zip` on Windows
+/// and `tar.gz` otherwise.
+pub fn get_host_archive_extension(host_triple: &HostTriple) -> &'static str {
+    match host_triple {
+        HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => "zip",
+        _ => "tar.gz",
+    }
+}
+
+/// Gets the extension of an archive, including the compression of tarballs, e.g. `tar.gz`.
+fn get_archive_extension(file_name: &str) -> &str {
+    match file_name.rfind(".tar.") {
+        Some(index) => &file_name[index + 1..],
+        None => file_name.rsplit('.').next().unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        context::Context,
+        toolchain::tool::{get_archive_extension, ReleaseAsset, ReleaseTool},
+    };
+
+    #[test]
+    fn test_release_tool() {
+        assert_eq!(get_archive_extension("tool-1.0-linux.tar.xz"), "tar.xz");
+        assert_eq!(get_archive_extension("tool-1.0.1-win64.zip"), "zip");
+
+        let tool = ReleaseTool::new(
+            &Context::default(),
+            "Tool",
+            ReleaseAsset {
+                name: "esp-tool",
+                component: "tool",
+                repository: "https://github.com/espressif/esp-tool/releases/download",
+                tag: "v1.0",
+                asset: "esp-tool-1.0-linux.tar.gz",
+                version: "1.0",
+            },
+        )
+        .with_bin_dir("esp-tool/bin")
+        .with_variable("ESP_TOOL_DIR", "");
+        assert_eq!(
+            tool.repository_url,
+            "https://github.com/espressif/esp-tool/releases/download/v1.0/esp-tool-1.0-linux.tar.gz"
+        );
+        assert_eq!(tool.file_name, "esp-tool.tar.gz");
+        assert!(tool.path.ends_with("esp-tool/1.0"));
+        assert!(tool
+            .get_bin_path()
+            .unwrap()
+            .ends_with("esp-tool/1.0/esp-tool/bin"));
+        #[cfg(unix)]
+        assert_eq!(
+            tool.get_exports()[1],
+            format!("export ESP_TOOL_DIR=\"{}/\"", tool.path.display())
+        );
+    }
+}
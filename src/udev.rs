@@ -0,0 +1,122 @@
+//! udev rules of the USB devices used to flash and debug the chips on Linux, installed with
+//! `--with-udev-rules` so that they can be opened without root.
+
+use crate::{
+    context::Context, emoji, error::Error, interactive::is_interactive, packages::is_root,
+};
+use embuild::cmd;
+use log::{debug, info};
+use std::{
+    fs::{read_to_string, write},
+    io::Write,
+};
+use tempfile::NamedTempFile;
+
+/// Path of the installed rules, read by udev before its default rules.
+pub const UDEV_RULES_PATH: &str = "/etc/udev/rules.d/60-espup.rules";
+/// Groups owning the serial ports, depending on the distribution.
+const SERIAL_GROUPS: [&str; 2] = ["dialout", "uucp"];
+/// Rules granting access to the USB-JTAG/serial of the chips and to the bridges of the
+/// development boards, as the rules distributed with Espressif's OpenOCD.
+const UDEV_RULES: &str = r#"# Installed by espup, see https://github.com/esp-rs/espup
+# USB-JTAG/serial of the ESP32-S3, ESP32-C3, ESP32-C6, ESP32-H2 and newer chips
+SUBSYSTEMS=="usb", ATTRS{idVendor}=="303a", MODE="660", GROUP="plugdev", TAG+="uaccess"
+# ESP-Prog and the FT2232H based boards
+SUBSYSTEMS=="usb", ATTRS{idVendor}=="0403", ATTRS{idProduct}=="6010", MODE="660", GROUP="plugdev", TAG+="uaccess"
+# Silicon Labs CP210x USB to UART bridges
+SUBSYSTEMS=="usb", ATTRS{idVendor}=="10c4", ATTRS{idProduct}=="ea60", MODE="660", GROUP="plugdev", TAG+="uaccess"
+# WCH CH340 and CH9102 USB to UART bridges
+SUBSYSTEMS=="usb", ATTRS{idVendor}=="1a86", ATTRS{idProduct}=="7523", MODE="660", GROUP="plugdev", TAG+="uaccess"
+SUBSYSTEMS=="usb", ATTRS{idVendor}=="1a86", ATTRS{idProduct}=="55d4", MODE="660", GROUP="plugdev", TAG+="uaccess"
+"#;
+
+/// Gets the command line running `command` as root: as is when already root, otherwise
+/// with `sudo`, which fails instead of asking for a password when nobody can type it.
+fn get_privileged_command(command: &[&str], root: bool, interactive: bool) -> Vec<String> {
+    let mut privileged: Vec<String> = Vec::new();
+    if !root {
+        privileged.push("sudo".to_string());
+        if !interactive {
+            privileged.push("-n".to_string());
+        }
+    }
+    privileged.extend(command.iter().map(|argument| argument.to_string()));
+    privileged
+}
+
+/// Runs `command` as root.
+fn run_privileged(command: &[&str]) -> Result<(), Error> {
+    let command = get_privileged_command(command, is_root(), is_interactive());
+    debug!("{} Running '{}'", emoji::DEBUG, command.join(" "));
+    cmd!(&command[0]; args=(&command[1..])).run()?;
+    Ok(())
+}
+
+/// Installs the udev rules, asking for the sudo password when needed, and reloads them so
+/// that the devices already plugged in are given the new permissions.
+pub fn install_udev_rules(context: &Context) -> Result<(), Error> {
+    if read_to_string(UDEV_RULES_PATH).map_or(false, |rules| rules == UDEV_RULES) {
+        info!(
+            "{} udev rules are already installed in '{}'",
+            emoji::INFO,
+            UDEV_RULES_PATH
+        );
+        return Ok(());
+    }
+    info!(
+        "{} Installing udev rules into '{}'",
+        emoji::WRENCH,
+        UDEV_RULES_PATH
+    );
+    if is_root() {
+        write(UDEV_RULES_PATH, UDEV_RULES)?;
+    } else {
+        let mut rules = NamedTempFile::new_in(context.temp_dir())?;
+        rules.write_all(UDEV_RULES.as_bytes())?;
+        let rules_path = rules.path().display().to_string();
+        run_privileged(&["install", "-m", "644", &rules_path, UDEV_RULES_PATH])?;
+    }
+    info!("{} Reloading udev rules", emoji::WRENCH);
+    run_privileged(&["udevadm", "control", "--reload-rules"])?;
+    run_privileged(&["udevadm", "trigger", "--subsystem-match=usb"])?;
+    run_privileged(&["udevadm", "trigger", "--subsystem-match=tty"])?;
+    Ok(())
+}
+
+/// Gets the group owning the serial ports among the groups of `/etc/group`.
+pub fn get_serial_group(groups: &str) -> Option<&'static str> {
+    let names: Vec<&str> = groups
+        .lines()
+        .filter_map(|line| line.split(':').next())
+        .collect();
+    SERIAL_GROUPS
+        .into_iter()
+        .find(|group| names.contains(group))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::udev::{get_privileged_command, get_serial_group};
+
+    #[test]
+    fn test_udev() {
+        assert_eq!(
+            get_privileged_command(&["udevadm", "trigger"], false, true),
+            ["sudo", "udevadm", "trigger"]
+        );
+        assert_eq!(
+            get_privileged_command(&["udevadm", "trigger"], false, false),
+            ["sudo", "-n", "udevadm", "trigger"]
+        );
+        assert_eq!(
+            get_privileged_command(&["udevadm", "trigger"], true, false),
+            ["udevadm", "trigger"]
+        );
+        assert_eq!(
+            get_serial_group("root:x:0:\ndialout:x:20:user\nplugdev:x:46:user\n"),
+            Some("dialout")
+        );
+        assert_eq!(get_serial_group("root:x:0:\nuucp:x:14:\n"), Some("uucp"));
+        assert_eq!(get_serial_group("root:x:0:\n"), None);
+    }
+}
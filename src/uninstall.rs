@@ -0,0 +1,213 @@
+//! Uninstallation of the Rust for ESP chips environment.
+
+use crate::{
+    config::Config,
+    context::Context,
+    emoji,
+    environment::{
+        get_batch_export_file, get_idf_export_scripts, get_sourcing_profiles, strip_shell_profiles,
+    },
+    error::Error,
+    install::clear_dist_folder,
+    lock::InstallLock,
+    plan::Plan,
+    toolchain::{
+        espidf::{get_dist_path, get_esp_idf_install_dir, get_tool_path},
+        gcc::get_toolchain_name,
+        rust::{get_xtensa_rust_path, uninstall_versions},
+    },
+};
+use embuild::cmd;
+use log::{debug, info};
+use miette::Result;
+use std::{
+    collections::HashSet,
+    fs::{remove_dir_all, remove_file},
+};
+
+/// Uninstalls the environment recorded in the configuration file, saving the configuration
+/// after every removed component, so that an interrupted uninstallation can be resumed. With
+/// `dry_run`, prints the actions instead.
+pub fn uninstall(context: &mut Context, dry_run: bool, no_wait: bool) -> Result<()> {
+    info!("{} Uninstalling esp-rs", emoji::DISC);
+    let mut config = context.load_config()?;
+    let context = &*context;
+    let env = context.env.as_deref();
+
+    debug!(
+        "{} Arguments:
+            - Config: {:#?}",
+        emoji::INFO,
+        config
+    );
+
+    if dry_run {
+        get_uninstall_plan(context, &config)?.print(context);
+        return Ok(());
+    }
+
+    let _lock = InstallLock::acquire(context, no_wait)?;
+
+    if let Some(xtensa_rust) = config.xtensa_rust {
+        info!("{} Deleting Xtensa Rust toolchain", emoji::WRENCH);
+        config.xtensa_rust = None;
+        config.save(env)?;
+        xtensa_rust.uninstall()?;
+    }
+    uninstall_versions(context)?;
+
+    if let Some(llvm_path) = config.llvm_path {
+        info!("{} Deleting Xtensa LLVM", emoji::WRENCH);
+        config.llvm_path = None;
+        config.save(env)?;
+        remove_dir_all(&llvm_path)
+            .map_err(|_| Error::FailedToRemoveDirectory(llvm_path.display().to_string()))?;
+    }
+
+    if let Some(esp_idf_version) = config.esp_idf_version {
+        info!("{} Deleting ESP-IDF {}", emoji::WRENCH, esp_idf_version);
+        let espidf_dir = get_esp_idf_install_dir(
+            context,
+            config.esp_idf_repository.take().as_deref(),
+            &esp_idf_version,
+        );
+        config.esp_idf_version = None;
+        config.save(env)?;
+
+        let repos_dir = espidf_dir.parent().unwrap();
+        remove_dir_all(repos_dir)
+            .map_err(|_| Error::FailedToRemoveDirectory(repos_dir.display().to_string()))?;
+    } else {
+        info!("{} Deleting GCC targets", emoji::WRENCH);
+        for target in &config.targets.clone() {
+            config.targets.remove(target);
+            config.save(env)?;
+            let gcc_path = get_tool_path(context, &get_toolchain_name(target));
+            remove_dir_all(&gcc_path).map_err(|_| Error::FailedToRemoveDirectory(gcc_path))?;
+        }
+    }
+
+    if let Some(tool_paths) = config.tool_paths.take() {
+        info!("{} Deleting tools", emoji::WRENCH);
+        config.save(env)?;
+        for tool_path in tool_paths {
+            if tool_path.exists() {
+                remove_dir_all(&tool_path)
+                    .map_err(|_| Error::FailedToRemoveDirectory(tool_path.display().to_string()))?;
+            }
+        }
+    }
+
+    if config.extra_crates.is_some() {
+        info!("{} Uninstalling extra crates", emoji::WRENCH);
+        let mut updated_extra_crates: HashSet<String> = config.extra_crates.clone().unwrap();
+        for extra_crate in &config.extra_crates.clone().unwrap() {
+            updated_extra_crates.remove(extra_crate);
+            config.extra_crates = Some(updated_extra_crates.clone());
+            config.save(env)?;
+            cmd!("cargo", "uninstall", extra_crate)
+                .run()
+                .map_err(Error::from)?;
+        }
+    }
+
+    if let Some(directory) = config.idf_export_scripts.take() {
+        info!(
+            "{} Deleting ESP-IDF compatible export scripts",
+            emoji::WRENCH
+        );
+        config.save(env)?;
+        for script in get_idf_export_scripts(&directory) {
+            if script.exists() {
+                remove_file(&script)
+                    .map_err(|_| Error::FailedToRemoveFile(script.display().to_string()))?;
+            }
+        }
+    }
+
+    if let Some(export_file) = config.export_file {
+        for profile in strip_shell_profiles(&export_file)? {
+            info!(
+                "{} Removed the sourcing of the export file from '{}'",
+                emoji::WRENCH,
+                profile.display()
+            );
+        }
+        info!("{} Deleting export file", emoji::WRENCH);
+        config.export_file = None;
+        config.save(env)?;
+        remove_file(&export_file)
+            .map_err(|_| Error::FailedToRemoveFile(export_file.display().to_string()))?;
+        let batch_export_file = get_batch_export_file(&export_file);
+        if cfg!(windows) && batch_export_file != export_file && batch_export_file.exists() {
+            remove_file(&batch_export_file)
+                .map_err(|_| Error::FailedToRemoveFile(batch_export_file.display().to_string()))?;
+        }
+    }
+
+    clear_dist_folder(context)?;
+    info!("{} Deleting config file", emoji::WRENCH);
+    let conf_file = Config::get_config_path(env)?;
+    remove_file(&conf_file)
+        .map_err(|_| Error::FailedToRemoveFile(conf_file.display().to_string()))?;
+
+    info!("{} Uninstallation successfully completed!", emoji::CHECK);
+    Ok(())
+}
+
+/// Resolves the actions that an uninstallation would perform.
+pub fn get_uninstall_plan(context: &Context, config: &Config) -> Result<Plan, Error> {
+    let mut plan = Plan::default();
+    let xtensa_rust_path = get_xtensa_rust_path(context);
+    if let Some(xtensa_rust) = &config.xtensa_rust {
+        if !xtensa_rust
+            .toolchain_destination
+            .starts_with(&xtensa_rust_path)
+        {
+            plan.remove(&xtensa_rust.toolchain_destination);
+        }
+    }
+    plan.remove(xtensa_rust_path);
+    if let Some(llvm_path) = &config.llvm_path {
+        plan.remove(llvm_path);
+    }
+    if let Some(esp_idf_version) = &config.esp_idf_version {
+        let espidf_dir = get_esp_idf_install_dir(
+            context,
+            config.esp_idf_repository.as_deref(),
+            esp_idf_version,
+        );
+        plan.remove(espidf_dir.parent().unwrap());
+    } else {
+        for target in &config.targets {
+            plan.remove(get_tool_path(context, &get_toolchain_name(target)));
+        }
+    }
+    if let Some(tool_paths) = &config.tool_paths {
+        for tool_path in tool_paths {
+            plan.remove(tool_path);
+        }
+    }
+    if let Some(extra_crates) = &config.extra_crates {
+        for extra_crate in extra_crates {
+            plan.command(format!("cargo uninstall {extra_crate}"));
+        }
+    }
+    if let Some(directory) = &config.idf_export_scripts {
+        for script in get_idf_export_scripts(directory) {
+            plan.remove(script);
+        }
+    }
+    if let Some(export_file) = &config.export_file {
+        for profile in get_sourcing_profiles(export_file) {
+            plan.modify(profile);
+        }
+        plan.remove(export_file);
+        if cfg!(windows) {
+            plan.remove(get_batch_export_file(export_file));
+        }
+    }
+    plan.remove(get_dist_path(context, ""));
+    plan.remove(Config::get_config_path(context.env.as_deref())?);
+    Ok(plan)
+}
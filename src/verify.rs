@@ -0,0 +1,248 @@
+//! Integrity checks of the installed components.
+
+use crate::{
+    context::Context,
+    emoji,
+    error::Error,
+    list::{get_installed_components, Component},
+    toolchain::{llvm::Llvm, rom_elfs::ROM_ELFS_NAME, sccache::SCCACHE_NAME},
+};
+use log::{error, info, warn};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{read_dir, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Hashes of the installed binaries, recorded once installed.
+pub type Receipt = BTreeMap<PathBuf, String>;
+
+/// Maximum depth at which the `bin` directories of a component are searched for.
+const BIN_DIR_DEPTH: usize = 3;
+
+/// Finds the `bin` directories of a component.
+fn find_bin_dirs(path: &Path, depth: usize) -> Vec<PathBuf> {
+    let mut bin_dirs = Vec::new();
+    for subdirectory in read_dir(path)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+    {
+        if subdirectory.file_name().map_or(false, |name| name == "bin") {
+            bin_dirs.push(subdirectory);
+        } else if depth > 1 {
+            bin_dirs.extend(find_bin_dirs(&subdirectory, depth - 1));
+        }
+    }
+    bin_dirs
+}
+
+/// Gets the binaries of a component: the files in its `bin` directories.
+pub fn get_binaries(path: &Path) -> Vec<PathBuf> {
+    let mut binaries: Vec<PathBuf> = find_bin_dirs(path, BIN_DIR_DEPTH)
+        .iter()
+        .flat_map(|bin_dir| read_dir(bin_dir).into_iter().flatten())
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    binaries.sort();
+    binaries
+}
+
+/// Gets the SHA-256 hash of a file.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Gets the binaries of a component. The `bin` directories of ESP-IDF checkouts are
+/// part of the sources, hence ignored.
+pub fn get_component_binaries(component: &Component) -> Vec<PathBuf> {
+    if component.name.starts_with("ESP-IDF") {
+        Vec::new()
+    } else {
+        get_binaries(&component.path)
+    }
+}
+
+/// Records the hashes of the binaries of the components.
+pub fn create_receipt(components: &[Component]) -> Receipt {
+    components
+        .iter()
+        .flat_map(get_component_binaries)
+        .filter_map(|binary| hash_file(&binary).ok().map(|hash| (binary, hash)))
+        .collect()
+}
+
+/// Whether a file can be executed.
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map_or(false, |metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Whether a file can be executed.
+#[cfg(windows)]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Whether the component is expected to provide binaries.
+fn has_binaries(component: &Component) -> bool {
+    !component.name.starts_with("ESP-IDF")
+        && component.name != "Xtensa LLVM"
+        && component.name != ROM_ELFS_NAME
+        && component.name != SCCACHE_NAME
+}
+
+/// Verifies a component, returning the problems found.
+pub fn verify_component(component: &Component, receipt: Option<&Receipt>) -> Vec<String> {
+    let mut problems = Vec::new();
+    if !component.path.exists() {
+        problems.push(format!("'{}' does not exist", component.path.display()));
+        return problems;
+    }
+
+    if component.name.starts_with("ESP-IDF") {
+        let idf_py = component.path.join("tools").join("idf.py");
+        if !idf_py.exists() {
+            problems.push(format!("'{}' does not exist", idf_py.display()));
+        }
+    } else if component.name == "Xtensa LLVM" {
+        let lib_path = PathBuf::from(
+            Llvm {
+                path: component.path.clone(),
+                ..Default::default()
+            }
+            .get_lib_path(),
+        );
+        let has_libclang = read_dir(&lib_path)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .any(|entry| entry.file_name().to_string_lossy().contains("libclang"));
+        if !has_libclang {
+            problems.push(format!("libclang not found in '{}'", lib_path.display()));
+        }
+    }
+
+    let binaries = get_component_binaries(component);
+    if binaries.is_empty() && has_binaries(component) {
+        problems.push(format!(
+            "No binaries found in '{}'",
+            component.path.display()
+        ));
+    }
+    for binary in &binaries {
+        if !is_executable(binary) {
+            problems.push(format!("'{}' is not executable", binary.display()));
+        }
+    }
+
+    if let Some(receipt) = receipt {
+        for (binary, hash) in receipt
+            .iter()
+            .filter(|(binary, _)| binary.starts_with(&component.path))
+        {
+            match hash_file(binary) {
+                Ok(actual_hash) if &actual_hash == hash => {}
+                Ok(_) => problems.push(format!(
+                    "'{}' was modified since installed",
+                    binary.display()
+                )),
+                Err(_) => problems.push(format!("'{}' is missing", binary.display())),
+            }
+        }
+    }
+    problems
+}
+
+/// Verifies the integrity of the installed components, printing their problems, and fails
+/// when any component is broken.
+pub fn verify(context: &mut Context) -> Result<(), Error> {
+    info!("{} Verifying installed components", emoji::DISC);
+    let config = context.load_config().ok();
+    let receipt = config.as_ref().and_then(|config| config.receipt.as_ref());
+    if receipt.is_none() {
+        warn!(
+            "{} No install receipt found, modified binaries can not be detected",
+            emoji::WARN
+        );
+    }
+    let components = get_installed_components(context, config.as_ref());
+    if components.is_empty() {
+        warn!("{} No installed components found", emoji::WARN);
+        return Ok(());
+    }
+
+    let mut broken = 0;
+    for component in &components {
+        let problems = verify_component(component, receipt);
+        if problems.is_empty() {
+            info!(
+                "{} {} {}: OK",
+                emoji::CHECK,
+                component.name,
+                component.version
+            );
+            continue;
+        }
+        broken += 1;
+        error!(
+            "{} {} {}: broken",
+            emoji::ERROR,
+            component.name,
+            component.version
+        );
+        for problem in problems {
+            error!("   {}", problem);
+        }
+    }
+    if broken > 0 {
+        return Err(Error::VerificationFailed(broken));
+    }
+
+    info!("{} All components are intact!", emoji::CHECK);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        list::Component,
+        verify::{create_receipt, get_binaries, verify_component},
+    };
+    use std::fs::{create_dir_all, write};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_verify_component() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("riscv32-esp-elf").join("bin");
+        create_dir_all(&bin_dir).unwrap();
+        let binary = bin_dir.join("riscv32-esp-elf-gcc");
+        write(&binary, "gcc").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let component = Component {
+            name: "riscv32-esp-elf".to_string(),
+            version: "esp-2021r2-patch5-8.4.0".to_string(),
+            path: temp_dir.path().to_path_buf(),
+        };
+        assert_eq!(get_binaries(&component.path), vec![binary.clone()]);
+
+        let receipt = create_receipt(std::slice::from_ref(&component));
+        assert!(verify_component(&component, Some(&receipt)).is_empty());
+
+        write(&binary, "modified").unwrap();
+        assert_eq!(verify_component(&component, Some(&receipt)).len(), 1);
+    }
+}
@@ -0,0 +1,189 @@
+//! Locations of the installation, printed by `espup which` so that build scripts and CMake
+//! toolchain files can query them instead of hardcoding paths.
+
+use crate::{
+    context::Context,
+    environment::{get_environment, Environment},
+    error::Error,
+    install::get_export_file,
+    toolchain::espidf::get_dist_path,
+};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, EnumString};
+
+/// Location of the installation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumIter, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Location {
+    /// Directory of the tools.
+    Tools,
+    /// Directory of the downloaded archives.
+    Dist,
+    /// Export file of the installation.
+    ExportFile,
+    /// `IDF_PATH` of the active ESP-IDF version.
+    IdfPath,
+    /// `LIBCLANG_PATH` of the Xtensa LLVM.
+    Libclang,
+    /// `bin` directory of the Xtensa GCC toolchain.
+    XtensaGcc,
+}
+
+/// Whether a `PATH` entry is the `bin` directory of an Xtensa GCC toolchain, e.g.
+/// `xtensa-esp32-elf` or `xtensa-esp-elf`.
+fn is_xtensa_gcc(entry: &str) -> bool {
+    let path = Path::new(entry);
+    path.ends_with("bin")
+        && path.components().any(|component| {
+            component.as_os_str().to_str().map_or(false, |name| {
+                name.starts_with("xtensa-esp") && name.ends_with("-elf")
+            })
+        })
+}
+
+impl Location {
+    /// Resolves the location, from the environment of the export file for the components.
+    /// Returns `None` when the component is not installed.
+    pub fn resolve(
+        &self,
+        context: &Context,
+        export_file: &Path,
+        environment: &Environment,
+    ) -> Option<PathBuf> {
+        let get_variable = |key: &str| {
+            environment
+                .variables
+                .iter()
+                .find(|(variable, _)| variable == key)
+                .map(|(_, value)| PathBuf::from(value))
+        };
+        match self {
+            Self::Tools => Some(context.tools_path.clone()),
+            Self::Dist => Some(PathBuf::from(
+                get_dist_path(context, "").trim_end_matches('/'),
+            )),
+            Self::ExportFile => Some(export_file.to_path_buf()),
+            Self::IdfPath => get_variable("IDF_PATH"),
+            Self::Libclang => get_variable("LIBCLANG_PATH"),
+            Self::XtensaGcc => environment
+                .path
+                .iter()
+                .find(|entry| is_xtensa_gcc(entry))
+                .map(PathBuf::from),
+        }
+    }
+}
+
+/// Gets the locations as a JSON object, `null` for the components that are not installed.
+pub fn get_locations(
+    context: &Context,
+    export_file: &Path,
+    environment: &Environment,
+) -> Map<String, Value> {
+    Location::iter()
+        .map(|location| {
+            let path = location
+                .resolve(context, export_file, environment)
+                .map_or(Value::Null, |path| {
+                    Value::String(path.display().to_string())
+                });
+            (location.to_string(), path)
+        })
+        .collect()
+}
+
+/// Prints a location of the installation, or every resolved one when `None`, as text or as a
+/// JSON object with `json`.
+pub fn print_locations(
+    context: &mut Context,
+    location: Option<Location>,
+    json: bool,
+) -> Result<(), Error> {
+    let config = context.load_config().ok();
+    let context = &*context;
+    let export_file = get_export_file(
+        context.env.as_deref(),
+        config.and_then(|config| config.export_file),
+    )?;
+    let environment = get_environment(&export_file).unwrap_or_default();
+    if json {
+        let mut locations = get_locations(context, &export_file, &environment);
+        if let Some(location) = location {
+            locations.retain(|name, _| *name == location.to_string());
+        }
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&locations).map_err(|_| Error::FailedToSerializeJson)?
+        );
+        return Ok(());
+    }
+    match location {
+        Some(location) => {
+            let path = location
+                .resolve(context, &export_file, &environment)
+                .ok_or_else(|| Error::UnresolvedLocation(location.to_string()))?;
+            println!("{}", path.display());
+        }
+        None => {
+            for location in Location::iter() {
+                if let Some(path) = location.resolve(context, &export_file, &environment) {
+                    println!("{}: {}", location, path.display());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        context::Context,
+        environment::Environment,
+        which::{get_locations, Location},
+    };
+    use serde_json::Value;
+    use std::{path::Path, str::FromStr};
+
+    #[test]
+    fn test_resolve_locations() {
+        let context = Context::default();
+        let environment = Environment {
+            variables: vec![
+                ("LIBCLANG_PATH".to_string(), "/esp/llvm/lib".to_string()),
+                ("IDF_PATH".to_string(), "/esp/esp-idf/v5.1".to_string()),
+            ],
+            path: vec![
+                "/esp/tools/riscv32-esp-elf/esp-12.2.0/riscv32-esp-elf/bin".to_string(),
+                "/esp/tools/xtensa-esp-elf/esp-12.2.0/xtensa-esp-elf/bin".to_string(),
+            ],
+        };
+        let export_file = Path::new("/home/esp/export-esp.sh");
+        assert_eq!(
+            Location::from_str("xtensa-gcc")
+                .unwrap()
+                .resolve(&context, export_file, &environment)
+                .unwrap(),
+            Path::new("/esp/tools/xtensa-esp-elf/esp-12.2.0/xtensa-esp-elf/bin")
+        );
+        assert_eq!(
+            Location::IdfPath
+                .resolve(&context, export_file, &environment)
+                .unwrap(),
+            Path::new("/esp/esp-idf/v5.1")
+        );
+        assert!(Location::Libclang
+            .resolve(&context, export_file, &Environment::default())
+            .is_none());
+
+        let locations = get_locations(&context, export_file, &Environment::default());
+        assert_eq!(locations.len(), 6);
+        assert_eq!(
+            locations["export-file"],
+            Value::String("/home/esp/export-esp.sh".to_string())
+        );
+        assert_eq!(locations["libclang"], Value::Null);
+    }
+}
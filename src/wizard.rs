@@ -0,0 +1,585 @@
+//! Installation wizard of `espup install --interactive`, walking first-time users through
+//! the main options and printing the equivalent command line, for scripts.
+
+use crate::{
+    emoji,
+    error::Error,
+    install::{get_export_file, InstallOptions},
+    targets::{parse_targets, Target},
+};
+use console::{Key, Term};
+use std::{
+    collections::HashSet,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+use strum::IntoEnumIterator;
+
+/// Option of a command line, e.g. `--targets`, with the values it was given.
+pub type Argument = (String, Vec<String>);
+
+/// Options of the install subcommand set by the wizard, which replace the given ones in
+/// the equivalent command line.
+const WIZARD_OPTIONS: &[&str] = &[
+    "--targets",
+    "--esp-idf-version",
+    "--with-gdb",
+    "--with-openocd",
+    "--with-qemu",
+    "--with-rom-elfs",
+    "--export-file",
+];
+
+/// Asks the questions of the wizard.
+trait Prompt {
+    /// Asks a question, returning the trimmed answer or `default` when it is empty.
+    fn ask(&mut self, question: &str, default: &str) -> Result<String, Error>;
+
+    /// Asks a yes/no question.
+    fn confirm(&mut self, question: &str, default: bool) -> Result<bool, Error>;
+
+    /// Asks which chips to install.
+    fn pick_targets(&mut self, default: &HashSet<Target>) -> Result<HashSet<Target>, Error>;
+}
+
+/// Asks questions line by line, on any reader and writer, when the output is not a
+/// terminal and in tests.
+struct Prompter<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Prompter<R, W> {
+    /// Prints an error, before asking again.
+    fn error(&mut self, error: &Error) -> Result<(), Error> {
+        writeln!(self.output, "{error}")?;
+        Ok(())
+    }
+}
+
+impl<R: BufRead, W: Write> Prompt for Prompter<R, W> {
+    fn ask(&mut self, question: &str, default: &str) -> Result<String, Error> {
+        if default.is_empty() {
+            write!(self.output, "{}{}: ", emoji::INFO, question)?;
+        } else {
+            write!(self.output, "{}{} [{}]: ", emoji::INFO, question, default)?;
+        }
+        self.output.flush()?;
+        let mut answer = String::new();
+        if self.input.read_line(&mut answer)? == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let answer = answer.trim();
+        Ok(if answer.is_empty() { default } else { answer }.to_string())
+    }
+
+    fn confirm(&mut self, question: &str, default: bool) -> Result<bool, Error> {
+        let answer = self.ask(question, if default { "Y/n" } else { "y/N" })?;
+        Ok(match answer.to_lowercase().as_str() {
+            "y" | "yes" => true,
+            "n" | "no" => false,
+            _ => default,
+        })
+    }
+
+    /// Lists the supported chips and asks which ones to install, until the selection is
+    /// valid.
+    fn pick_targets(&mut self, default: &HashSet<Target>) -> Result<HashSet<Target>, Error> {
+        writeln!(self.output, "  0) all")?;
+        for (number, label) in get_target_labels().iter().enumerate() {
+            writeln!(self.output, "  {}) {label}", number + 1)?;
+        }
+        let last_target = Target::iter().next_back().map(|target| target.to_string());
+        let question = format!(
+            "Targets, numbers or names separated by commas, e.g. 1,{} or {}",
+            Target::iter().count(),
+            last_target.unwrap_or_default()
+        );
+        loop {
+            let selection = self.ask(&question, &format_targets(default))?;
+            match parse_selection(&selection) {
+                Ok(targets) => return Ok(targets),
+                Err(e) => self.error(&e)?,
+            }
+        }
+    }
+}
+
+/// List of items picked with the arrow keys in a terminal, one of them or, when `checked`
+/// is set, several of them.
+struct Menu {
+    items: Vec<String>,
+    /// Whether each item is checked, in the menus picking several items.
+    checked: Option<Vec<bool>>,
+    /// Index of the highlighted item.
+    cursor: usize,
+}
+
+impl Menu {
+    /// Handles a key, returning whether the selection is confirmed. Confirming requires
+    /// at least one checked item.
+    fn handle_key(&mut self, key: &Key) -> bool {
+        let length = self.items.len();
+        match (key, &mut self.checked) {
+            (Key::ArrowUp | Key::Char('k'), _) => self.cursor = (self.cursor + length - 1) % length,
+            (Key::ArrowDown | Key::Char('j') | Key::Tab, _) => {
+                self.cursor = (self.cursor + 1) % length
+            }
+            (Key::Char(' '), Some(checked)) => checked[self.cursor] = !checked[self.cursor],
+            (Key::Char('a'), Some(checked)) => {
+                let all = checked.iter().all(|checked| *checked);
+                checked.iter_mut().for_each(|checked| *checked = !all);
+            }
+            (Key::Enter, Some(checked)) => return checked.contains(&true),
+            (Key::Enter, None) => return true,
+            // The first letter of an item picks it, e.g. `y` for `Yes`.
+            (Key::Char(c), None) => {
+                let index = self.items.iter().position(|item| {
+                    item.chars()
+                        .next()
+                        .map_or(false, |first| first.eq_ignore_ascii_case(c))
+                });
+                if let Some(index) = index {
+                    self.cursor = index;
+                    return true;
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// Renders the items, pointing at the highlighted one.
+    fn render(&self) -> Vec<String> {
+        self.items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let pointer = if index == self.cursor { ">" } else { " " };
+                match &self.checked {
+                    Some(checked) if checked[index] => format!("{pointer} [x] {item}"),
+                    Some(_) => format!("{pointer} [ ] {item}"),
+                    None => format!("{pointer} {item}"),
+                }
+            })
+            .collect()
+    }
+
+    /// Gets the picked items.
+    fn selection(&self) -> Vec<&str> {
+        match &self.checked {
+            Some(checked) => self
+                .items
+                .iter()
+                .zip(checked)
+                .filter(|(_, checked)| **checked)
+                .map(|(item, _)| item.as_str())
+                .collect(),
+            None => vec![self.items[self.cursor].as_str()],
+        }
+    }
+
+    /// Shows the menu until the selection is confirmed, then replaces it with the picked
+    /// items. Escape and Ctrl-C interrupt it.
+    fn run(&mut self, term: &Term, question: &str) -> io::Result<()> {
+        let help = if self.checked.is_some() {
+            "arrows to move, space to check, a to check all, enter to confirm"
+        } else {
+            "arrows to move or first letter, enter to confirm"
+        };
+        term.write_line(&format!("{}{} ({})", emoji::INFO, question, help))?;
+        term.hide_cursor()?;
+        let result = loop {
+            let lines = self.render();
+            for line in &lines {
+                term.write_line(line)?;
+            }
+            let key = term.read_key();
+            term.clear_last_lines(lines.len())?;
+            match key {
+                Ok(Key::Escape) => break Err(io::Error::from(io::ErrorKind::Interrupted)),
+                Ok(key) if self.handle_key(&key) => break Ok(()),
+                Ok(_) => {}
+                Err(e) => break Err(e),
+            }
+        };
+        term.show_cursor()?;
+        result?;
+        term.clear_last_lines(1)?;
+        term.write_line(&format!(
+            "{}{}: {}",
+            emoji::INFO,
+            question,
+            self.selection().join(", ")
+        ))
+    }
+}
+
+/// Asks questions in a terminal, with menus for the choices.
+struct TermPrompter<'a> {
+    term: &'a Term,
+}
+
+impl Prompt for TermPrompter<'_> {
+    fn ask(&mut self, question: &str, default: &str) -> Result<String, Error> {
+        self.term
+            .write_str(&format!("{}{}: ", emoji::INFO, question))?;
+        let answer = self.term.read_line_initial_text(default)?;
+        let answer = answer.trim();
+        Ok(if answer.is_empty() { default } else { answer }.to_string())
+    }
+
+    fn confirm(&mut self, question: &str, default: bool) -> Result<bool, Error> {
+        let mut menu = Menu {
+            items: vec!["Yes".to_string(), "No".to_string()],
+            checked: None,
+            cursor: usize::from(!default),
+        };
+        menu.run(self.term, question)?;
+        Ok(menu.cursor == 0)
+    }
+
+    fn pick_targets(&mut self, default: &HashSet<Target>) -> Result<HashSet<Target>, Error> {
+        let mut menu = Menu {
+            items: get_target_labels(),
+            checked: Some(
+                Target::iter()
+                    .map(|target| default.contains(&target))
+                    .collect(),
+            ),
+            cursor: 0,
+        };
+        menu.run(self.term, "Targets")?;
+        Ok(Target::iter()
+            .zip(menu.checked.unwrap_or_default())
+            .filter(|(_, checked)| *checked)
+            .map(|(target, _)| target)
+            .collect())
+    }
+}
+
+/// Gets the labels of the supported chips, with their architecture.
+fn get_target_labels() -> Vec<String> {
+    Target::iter()
+        .map(|target| {
+            let architecture = if target.is_xtensa() {
+                "Xtensa"
+            } else {
+                "RISC-V"
+            };
+            format!("{target} ({architecture})")
+        })
+        .collect()
+}
+
+/// Formats targets as accepted by `--targets`: `all` or a sorted, comma separated list.
+fn format_targets(targets: &HashSet<Target>) -> String {
+    if Target::iter().all(|target| targets.contains(&target)) {
+        return "all".to_string();
+    }
+    let mut targets: Vec<String> = targets.iter().map(|target| target.to_string()).collect();
+    targets.sort();
+    targets.join(",")
+}
+
+/// Parses the targets selected in the chip picker: numbers of the listed chips, `0` for all
+/// of them, or the names accepted by `--targets`.
+fn parse_selection(selection: &str) -> Result<HashSet<Target>, Error> {
+    let mut targets = HashSet::new();
+    for item in selection.split([',', ' ']).filter(|item| !item.is_empty()) {
+        match item.parse::<usize>() {
+            Ok(0) => targets.extend(Target::iter()),
+            Ok(number) => {
+                let target = Target::iter()
+                    .nth(number - 1)
+                    .ok_or_else(|| Error::UnsupportedTarget(item.to_string()))?;
+                targets.insert(target);
+            }
+            Err(_) => targets.extend(parse_targets(item)?),
+        }
+    }
+    if targets.is_empty() {
+        return Err(Error::UnsupportedTarget(selection.to_string()));
+    }
+    Ok(targets)
+}
+
+/// Asks which chips to install, when `--targets` is omitted in an interactive terminal.
+/// Menus are shown when `term` is a terminal, otherwise the chips are numbered.
+pub fn pick_targets(term: &Term) -> Result<HashSet<Target>, Error> {
+    let default = Target::iter().collect();
+    if term.is_term() {
+        TermPrompter { term }.pick_targets(&default)
+    } else {
+        Prompter {
+            input: io::stdin().lock(),
+            output: term,
+        }
+        .pick_targets(&default)
+    }
+}
+
+/// Quotes a command line argument containing spaces.
+fn quote(argument: &str) -> String {
+    if argument.contains(' ') {
+        format!("\"{argument}\"")
+    } else {
+        argument.to_string()
+    }
+}
+
+/// Formats the options of a command line, repeating the options given several values.
+fn format_arguments(arguments: &[Argument]) -> Vec<String> {
+    let mut formatted = Vec::new();
+    for (option, values) in arguments {
+        if values.is_empty() {
+            formatted.push(option.clone());
+        }
+        for value in values {
+            formatted.push(format!("{option} {}", quote(value)));
+        }
+    }
+    formatted
+}
+
+/// Gets the command line installing the options chosen in the wizard. The other options,
+/// given to espup and to the install subcommand, are kept as they were given.
+pub fn get_command_line(
+    global_arguments: &[Argument],
+    arguments: &[Argument],
+    options: &InstallOptions,
+) -> String {
+    let mut command_line = vec!["espup".to_string()];
+    command_line.extend(format_arguments(global_arguments));
+    command_line.push("install".to_string());
+    command_line.push(format!("--targets {}", format_targets(&options.targets)));
+    if let Some(esp_idf_version) = &options.esp_idf_version {
+        command_line.push(format!("--esp-idf-version {}", quote(esp_idf_version)));
+    }
+    for (flag, enabled) in [
+        ("--with-gdb", options.with_gdb),
+        ("--with-openocd", options.with_openocd),
+        ("--with-qemu", options.with_qemu),
+        ("--with-rom-elfs", options.with_rom_elfs),
+    ] {
+        if enabled {
+            command_line.push(flag.to_string());
+        }
+    }
+    if let Some(export_file) = &options.export_file {
+        command_line.push(format!(
+            "--export-file {}",
+            quote(&export_file.display().to_string())
+        ));
+    }
+    let arguments: Vec<Argument> = arguments
+        .iter()
+        .filter(|(option, _)| !WIZARD_OPTIONS.contains(&option.as_str()))
+        .cloned()
+        .collect();
+    command_line.extend(format_arguments(&arguments));
+    command_line.join(" ")
+}
+
+/// Walks the user through the targets, ESP-IDF, the optional tools and the export file,
+/// starting from the given options. The default export file is the one of the named
+/// environment `env`, if any.
+fn run_wizard(
+    prompt: &mut impl Prompt,
+    env: Option<&str>,
+    options: &mut InstallOptions,
+) -> Result<(), Error> {
+    options.targets = prompt.pick_targets(&options.targets)?;
+
+    if options.idf_path.is_none() && options.target_host.is_none() {
+        let install_esp_idf = prompt.confirm(
+            "Install ESP-IDF? It is needed by the std applications",
+            options.esp_idf_version.is_some(),
+        )?;
+        options.esp_idf_version = if install_esp_idf {
+            let mut esp_idf_version = String::new();
+            while esp_idf_version.is_empty() {
+                esp_idf_version = prompt.ask(
+                    "ESP-IDF version, e.g. v5.0, branch:master or tag:v4.4.4",
+                    options.esp_idf_version.as_deref().unwrap_or_default(),
+                )?;
+            }
+            Some(esp_idf_version)
+        } else {
+            None
+        };
+    }
+
+    options.with_gdb = prompt.confirm("Install GDB, to debug the targets?", options.with_gdb)?;
+    options.with_openocd = prompt.confirm(
+        "Install OpenOCD, to flash and debug the targets through JTAG?",
+        options.with_openocd,
+    )?;
+    options.with_qemu =
+        prompt.confirm("Install QEMU, to emulate the targets?", options.with_qemu)?;
+    options.with_rom_elfs = prompt.confirm(
+        "Install the ROM ELFs, to decode backtraces into ROM code?",
+        options.with_rom_elfs,
+    )?;
+
+    let default_export_file = get_export_file(env, options.export_file.clone())?;
+    let export_file = prompt.ask(
+        "Export file, sourced to set up the environment",
+        &default_export_file.display().to_string(),
+    )?;
+    if Path::new(&export_file) != default_export_file || options.export_file.is_some() {
+        options.export_file = Some(get_export_file(env, Some(PathBuf::from(export_file)))?);
+    }
+    Ok(())
+}
+
+/// Runs the installation wizard, with menus when `term` is a terminal, otherwise line by
+/// line.
+pub fn run_install_wizard(
+    env: Option<&str>,
+    options: &mut InstallOptions,
+    term: &Term,
+) -> Result<(), Error> {
+    if term.is_term() {
+        run_wizard(&mut TermPrompter { term }, env, options)
+    } else {
+        let input = io::stdin().lock();
+        run_wizard(
+            &mut Prompter {
+                input,
+                output: term,
+            },
+            env,
+            options,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        install::InstallOptions,
+        targets::{parse_targets, Target},
+        wizard::{format_targets, get_command_line, parse_selection, run_wizard, Menu, Prompter},
+    };
+    use console::Key;
+    use std::path::PathBuf;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn test_format_targets() {
+        assert_eq!(format_targets(&parse_targets("all").unwrap()), "all");
+        assert_eq!(
+            format_targets(&parse_targets("esp32s3,esp32").unwrap()),
+            "esp32,esp32s3"
+        );
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        assert_eq!(parse_selection("0").unwrap(), parse_targets("all").unwrap());
+        assert_eq!(
+            parse_selection("1, esp32c3").unwrap(),
+            parse_targets("esp32,esp32c3").unwrap()
+        );
+        assert_eq!(
+            parse_selection("riscv 3").unwrap(),
+            parse_targets("riscv,esp32s3").unwrap()
+        );
+        assert!(parse_selection("9").is_err());
+        assert!(parse_selection(" ").is_err());
+    }
+
+    #[test]
+    fn test_menu() {
+        let mut menu = Menu {
+            items: Target::iter().map(|target| target.to_string()).collect(),
+            checked: Some(vec![false; Target::iter().count()]),
+            cursor: 0,
+        };
+        // Nothing is checked yet.
+        assert!(!menu.handle_key(&Key::Enter));
+        assert!(!menu.handle_key(&Key::ArrowUp));
+        assert!(!menu.handle_key(&Key::Char(' ')));
+        assert!(!menu.handle_key(&Key::ArrowDown));
+        assert!(!menu.handle_key(&Key::Char(' ')));
+        let last = Target::iter().next_back().unwrap().to_string();
+        assert_eq!(
+            menu.selection(),
+            vec![Target::ESP32.to_string(), last.clone()]
+        );
+        assert_eq!(menu.render()[0], format!("> [x] {}", Target::ESP32));
+        assert!(menu.handle_key(&Key::Enter));
+        menu.handle_key(&Key::Char('a'));
+        assert_eq!(menu.selection().len(), Target::iter().count());
+
+        let mut menu = Menu {
+            items: vec!["Yes".to_string(), "No".to_string()],
+            checked: None,
+            cursor: 1,
+        };
+        assert_eq!(menu.render(), vec!["  Yes", "> No"]);
+        menu.handle_key(&Key::ArrowDown);
+        assert!(menu.handle_key(&Key::Enter));
+        assert_eq!(menu.selection(), vec!["Yes"]);
+        assert!(menu.handle_key(&Key::Char('n')));
+        assert_eq!(menu.selection(), vec!["No"]);
+    }
+
+    #[test]
+    fn test_run_wizard() {
+        let mut options = InstallOptions::default();
+        let mut output = Vec::new();
+        let answers = "esp33\nesp32c3\ny\n\nv5.0\nyes\n\n\nn\n/tmp/export esp.sh\n";
+        let mut prompter = Prompter {
+            input: answers.as_bytes(),
+            output: &mut output,
+        };
+        run_wizard(&mut prompter, None, &mut options).unwrap();
+        assert_eq!(options.targets, [Target::ESP32C3].into_iter().collect());
+        assert_eq!(options.esp_idf_version.as_deref(), Some("v5.0"));
+        assert!(options.with_gdb && !options.with_openocd && !options.with_rom_elfs);
+        assert_eq!(
+            options.export_file,
+            Some(PathBuf::from("/tmp/export esp.sh"))
+        );
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("esp33"));
+        assert!(output.contains(&format!("e.g. 1,{}", Target::iter().count())));
+    }
+
+    #[test]
+    fn test_get_command_line() {
+        assert_eq!(
+            get_command_line(&[], &[], &InstallOptions::default()),
+            "espup install --targets all"
+        );
+        let options = InstallOptions {
+            targets: [Target::ESP32C3].into_iter().collect(),
+            esp_idf_version: Some("v5.0".to_string()),
+            with_gdb: true,
+            export_file: Some(PathBuf::from("/tmp/export esp.sh")),
+            ..InstallOptions::default()
+        };
+        let global_arguments = [("--tools-path".to_string(), vec!["/opt/esp".to_string()])];
+        let arguments = [
+            ("--targets".to_string(), vec!["esp32".to_string()]),
+            (
+                "--toolchain-version".to_string(),
+                vec!["1.69.0.0".to_string()],
+            ),
+            ("--with-gdb".to_string(), Vec::new()),
+            ("--with-sccache".to_string(), Vec::new()),
+            (
+                "--mirror".to_string(),
+                vec!["espressif".to_string(), "github".to_string()],
+            ),
+        ];
+        assert_eq!(
+            get_command_line(&global_arguments, &arguments, &options),
+            "espup --tools-path /opt/esp install --targets esp32c3 --esp-idf-version v5.0 \
+             --with-gdb --export-file \"/tmp/export esp.sh\" --toolchain-version 1.69.0.0 \
+             --with-sccache --mirror espressif --mirror github"
+        );
+    }
+}
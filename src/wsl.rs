@@ -0,0 +1,116 @@
+//! Detection of the Windows Subsystem for Linux, where the Linux binary needs different
+//! guidance, e.g. to access the USB devices of Windows.
+
+use crate::emoji;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use std::{fs::read_to_string, path::Path};
+use strum_macros::Display;
+
+/// Version of the Windows Subsystem for Linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum Wsl {
+    #[strum(serialize = "WSL1")]
+    Wsl1,
+    #[strum(serialize = "WSL2")]
+    Wsl2,
+}
+
+/// WSL version espup runs under, if any.
+static WSL: Lazy<Option<Wsl>> = Lazy::new(|| {
+    read_to_string("/proc/sys/kernel/osrelease")
+        .ok()
+        .and_then(|release| parse_kernel_release(&release))
+});
+
+/// Parses the kernel release, e.g. `5.15.90.1-microsoft-standard-WSL2` on WSL2 or
+/// `4.4.0-19041-Microsoft` on WSL1.
+pub fn parse_kernel_release(release: &str) -> Option<Wsl> {
+    let release = release.trim().to_lowercase();
+    if !release.contains("microsoft") {
+        None
+    } else if release.contains("microsoft-standard") || release.contains("wsl2") {
+        Some(Wsl::Wsl2)
+    } else {
+        Some(Wsl::Wsl1)
+    }
+}
+
+/// Gets the WSL version espup runs under, if any.
+pub fn detect() -> Option<Wsl> {
+    *WSL
+}
+
+/// Whether a path is in a Windows drive mounted in WSL, e.g. `/mnt/c`.
+pub fn is_windows_path(path: &Path) -> bool {
+    let mut components = path.components().map(|component| component.as_os_str());
+    components.next() == Some("/".as_ref())
+        && components.next() == Some("mnt".as_ref())
+        && components
+            .next()
+            .and_then(|drive| drive.to_str())
+            .map_or(false, |drive| {
+                drive.len() == 1 && drive.chars().all(|c| c.is_ascii_alphabetic())
+            })
+}
+
+/// Warns when installing in a Windows drive, which WSL2 accesses over a slow network
+/// file system.
+pub fn check_install_path(wsl: Wsl, tools_path: &Path) {
+    if wsl == Wsl::Wsl2 && is_windows_path(tools_path) {
+        warn!(
+            "{} '{}' is in a Windows drive, which is slow to access from WSL2. Install in the Linux file system instead, e.g. with '--install-dir ~/.espressif'",
+            emoji::WARN,
+            tools_path.display()
+        );
+    }
+}
+
+/// Prints how to use the installed environment from WSL.
+pub fn print_guidance(wsl: Wsl) {
+    info!(
+        "{} The export file only sets up {} shells: open Windows editors in WSL, e.g. Visual Studio Code with the WSL extension, to use the environment",
+        emoji::INFO,
+        wsl
+    );
+    match wsl {
+        Wsl::Wsl1 => info!(
+            "{} WSL1 maps the COM<N> ports of Windows to /dev/ttyS<N>, use them to flash and monitor",
+            emoji::INFO
+        ),
+        Wsl::Wsl2 => info!(
+            "{} To flash and monitor from WSL2, attach the USB device running 'usbipd attach --wsl --busid <BUSID>' on Windows, see https://learn.microsoft.com/en-us/windows/wsl/connect-usb",
+            emoji::INFO
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wsl::{is_windows_path, parse_kernel_release, Wsl};
+    use std::path::Path;
+
+    #[test]
+    fn test_parse_kernel_release() {
+        assert_eq!(
+            parse_kernel_release("5.15.90.1-microsoft-standard-WSL2\n"),
+            Some(Wsl::Wsl2)
+        );
+        assert_eq!(
+            parse_kernel_release("4.19.104-microsoft-standard"),
+            Some(Wsl::Wsl2)
+        );
+        assert_eq!(
+            parse_kernel_release("4.4.0-19041-Microsoft"),
+            Some(Wsl::Wsl1)
+        );
+        assert_eq!(parse_kernel_release("6.1.0-13-amd64"), None);
+    }
+
+    #[test]
+    fn test_is_windows_path() {
+        assert!(is_windows_path(Path::new("/mnt/c/Users/esp/.espressif")));
+        assert!(!is_windows_path(Path::new("/mnt/data/espup")));
+        assert!(!is_windows_path(Path::new("/home/esp/.espressif")));
+    }
+}
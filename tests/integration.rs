@@ -15,6 +15,162 @@ fn verify_help() {
         .success();
 }
 
+#[test]
+fn verify_cache_clean_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["cache", "clean", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_cache_fetch_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["cache", "fetch", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_cache_list_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["cache", "list", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_cache_size_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["cache", "size", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_check_updates_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["check-updates", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_completions_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["completions", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_completions() {
+    for shell in ["bash", "zsh", "fish", "powershell", "elvish"] {
+        assert_cmd::Command::cargo_bin("espup")
+            .unwrap()
+            .args(["completions", shell])
+            .assert()
+            .success();
+    }
+}
+
+#[test]
+fn verify_default_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["default", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_diff_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["diff", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_doctor_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["doctor", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_env_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["env", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_export_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["export", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_env_overrides() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .env("ESPUP_LOG_LEVEL", "trace")
+        .arg("doctor")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn verify_idf_remove_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["idf", "remove", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_idf_submodules_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["idf", "submodules", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_idf_use_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["idf", "use", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_info_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["info", "--help"])
+        .assert()
+        .success();
+}
+
 #[test]
 fn verify_install_help() {
     assert_cmd::Command::cargo_bin("espup")
@@ -24,6 +180,78 @@ fn verify_install_help() {
         .success();
 }
 
+#[test]
+fn verify_list_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["list", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_migrate_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["migrate", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_mirror_test_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["mirror", "test", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_new_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["new", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_prune_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["prune", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_run_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["run", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_self_update_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["self-update", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_shell_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["shell", "--help"])
+        .assert()
+        .success();
+}
+
 #[test]
 fn verify_update_help() {
     assert_cmd::Command::cargo_bin("espup")
@@ -41,3 +269,21 @@ fn verify_uninstall_help() {
         .assert()
         .success();
 }
+
+#[test]
+fn verify_verify_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["verify", "--help"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn verify_which_help() {
+    assert_cmd::Command::cargo_bin("espup")
+        .unwrap()
+        .args(["which", "--help"])
+        .assert()
+        .success();
+}